@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+// A battle's resonance factor from here up counts as a resonance encounter. Unrelated
+// seeds add next to nothing, so this takes the two transforms' traces all but matching
+const RESONANCE_ENCOUNTER: f32 = 0.5;
+
+// What the achievements are judged on, kept across days
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Progress {
+    pub streak: u32,               // Consecutive days with at least one gesture, up to the last
+    pub best_streak: u32,
+    pub last_day: Option<String>, // YYYY-MM-DD of the latest day with a gesture
+    pub total_interactions: u64,
+    pub frozen: u32,
+    pub battles: u32,
+    pub battles_won: u32,
+    pub resonances: u32, // Battles at or above RESONANCE_ENCOUNTER
+    pub resonance_events: u32, // Moments active together with others (see resonance.rs)
+    pub unlocked: BTreeMap<String, u64>, // Achievement id to when it unlocked, ms since the epoch
+}
+
+pub struct Achievement {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    earned: fn(&Progress) -> bool,
+}
+
+const ACHIEVEMENTS: [Achievement; 13] = [
+    Achievement { id: "first_touch", name: "First Touch", description: "Shape your fractal for the first time", earned: |p| p.total_interactions >= 1 },
+    Achievement { id: "streak_3", name: "Ritual", description: "Visit three days in a row", earned: |p| p.best_streak >= 3 },
+    Achievement { id: "streak_7", name: "Week of Wonder", description: "Visit seven days in a row", earned: |p| p.best_streak >= 7 },
+    Achievement { id: "streak_30", name: "Lunar Cycle", description: "Visit thirty days in a row", earned: |p| p.best_streak >= 30 },
+    Achievement { id: "interactions_100", name: "Sculptor", description: "Make 100 gestures", earned: |p| p.total_interactions >= 100 },
+    Achievement { id: "interactions_1000", name: "Master Sculptor", description: "Make 1000 gestures", earned: |p| p.total_interactions >= 1000 },
+    Achievement { id: "first_freeze", name: "Preserved", description: "Freeze a fractal", earned: |p| p.frozen >= 1 },
+    Achievement { id: "first_battle", name: "Challenger", description: "Battle another fractal", earned: |p| p.battles >= 1 },
+    Achievement { id: "first_battle_win", name: "Victor", description: "Win a battle", earned: |p| p.battles_won >= 1 },
+    Achievement { id: "resonance", name: "Resonance", description: "Meet a fractal that resonates with yours", earned: |p| p.resonances >= 1 },
+    Achievement { id: "resonances_10", name: "Harmonic Soul", description: "Meet ten resonating fractals", earned: |p| p.resonances >= 10 },
+    Achievement { id: "chorus", name: "Chorus", description: "Resonate with friends in the same moment", earned: |p| p.resonance_events >= 1 },
+    Achievement { id: "chorus_10", name: "Choir", description: "Resonate with friends ten times", earned: |p| p.resonance_events >= 10 },
+];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AchievementInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub unlocked: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct AchievementsReport {
+    pub progress: Progress,
+    pub achievements: Vec<AchievementInfo>,
+}
+
+// Streaks and milestones. Each record_* call unlocks whatever the new progress earns; the
+// unlocks wait in take_unlocked() for the caller to announce
+pub struct Achievements {
+    pub progress: Progress,
+    fresh: Vec<AchievementInfo>,
+}
+
+impl Achievements {
+    pub fn new(progress: Progress) -> Self {
+        Achievements { progress, fresh: Vec::new() }
+    }
+
+    // A gesture on `today`, which continues the streak if the last was on `yesterday`
+    pub fn record_gesture(&mut self, today: &str, yesterday: &str, now: u64) {
+        let progress = &mut self.progress;
+        match progress.last_day.as_deref() {
+            Some(day) if day == today => {}
+            Some(day) if day == yesterday => progress.streak += 1,
+            _ => progress.streak = 1,
+        }
+        progress.last_day = Some(today.to_string());
+        progress.best_streak = progress.best_streak.max(progress.streak);
+        progress.total_interactions += 1;
+        self.unlock(now);
+    }
+
+    pub fn record_freeze(&mut self, now: u64) {
+        self.progress.frozen += 1;
+        self.unlock(now);
+    }
+
+    pub fn record_battle(&mut self, won: bool, resonance: f32, now: u64) {
+        self.progress.battles += 1;
+        if won {
+            self.progress.battles_won += 1;
+        }
+        if resonance >= RESONANCE_ENCOUNTER {
+            self.progress.resonances += 1;
+        }
+        self.unlock(now);
+    }
+
+    pub fn record_resonance_event(&mut self, now: u64) {
+        self.progress.resonance_events += 1;
+        self.unlock(now);
+    }
+
+    // Progress saved elsewhere (another tab, or before a migration finished) joins this:
+    // the larger of each count, the later streak, and every unlock
+    pub fn merge(&mut self, other: Progress) {
+        let progress = &mut self.progress;
+        if other.last_day > progress.last_day {
+            progress.last_day = other.last_day;
+            progress.streak = other.streak;
+        }
+        progress.best_streak = progress.best_streak.max(other.best_streak);
+        progress.total_interactions = progress.total_interactions.max(other.total_interactions);
+        progress.frozen = progress.frozen.max(other.frozen);
+        progress.battles = progress.battles.max(other.battles);
+        progress.battles_won = progress.battles_won.max(other.battles_won);
+        progress.resonances = progress.resonances.max(other.resonances);
+        progress.resonance_events = progress.resonance_events.max(other.resonance_events);
+        for (id, when) in other.unlocked {
+            let kept = progress.unlocked.entry(id).or_insert(when);
+            *kept = (*kept).min(when);
+        }
+    }
+
+    // Achievements unlocked since the last call
+    pub fn take_unlocked(&mut self) -> Vec<AchievementInfo> {
+        std::mem::take(&mut self.fresh)
+    }
+
+    pub fn report(&self) -> AchievementsReport {
+        AchievementsReport {
+            progress: self.progress.clone(),
+            achievements: ACHIEVEMENTS.iter().map(|achievement| self.info(achievement)).collect(),
+        }
+    }
+
+    fn info(&self, achievement: &Achievement) -> AchievementInfo {
+        AchievementInfo {
+            id: achievement.id,
+            name: achievement.name,
+            description: achievement.description,
+            unlocked: self.progress.unlocked.get(achievement.id).copied(),
+        }
+    }
+
+    fn unlock(&mut self, now: u64) {
+        for achievement in &ACHIEVEMENTS {
+            if !self.progress.unlocked.contains_key(achievement.id) && (achievement.earned)(&self.progress) {
+                self.progress.unlocked.insert(achievement.id.to_string(), now);
+                self.fresh.push(self.info(achievement));
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioBufferSourceNode, AudioContext, AudioNode, AudioParam, AudioScheduledSourceNode};
+use web_sys::{BiquadFilterType, GainNode, OscillatorNode};
+use serde::Serialize;
+use crate::rng::SeededRng;
+
+// Seconds of pink noise each layer loops; the slow filter sweeps hide the repeat
+const NOISE_LENGTH: f32 = 8.0;
+
+// Seconds at the end of the loop crossfaded into its start, so the seam doesn't click
+const LOOP_FADE: f32 = 0.25;
+
+const SCENE_STREAM: u64 = 0xA4B1_0001;
+const NOISE_STREAM: u64 = 0xA4B1_0002;
+
+// Gain of the whole bed at level 1, well under the oscillator voices
+const BED_GAIN: f32 = 0.06;
+
+pub const DEFAULT_AMBIENCE_LEVEL: f32 = 0.5;
+
+// Seconds a change of level takes
+const LEVEL_RAMP: f64 = 0.5;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerKind {
+    // Low-passed: a dark, wind-like floor
+    Bed,
+    // Band-passed and resonant: a whistling or breathing band above it
+    Texture,
+}
+
+// One noise layer: pink noise through a filter centered on `cutoff` Hz, which a sine LFO
+// at `sweep_rate` Hz swings `sweep_depth` of the way either side
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct AmbientLayer {
+    pub kind: LayerKind,
+    pub cutoff: f32,
+    pub resonance: f32,
+    pub sweep_rate: f32,
+    pub sweep_depth: f32,
+    pub level: f32,
+}
+
+// The day's soundscape: always a bed, and one or two textures over it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AmbientScene {
+    pub seed: u32,
+    pub layers: Vec<AmbientLayer>,
+}
+
+impl AmbientScene {
+    pub fn from_seed(seed: u32) -> Self {
+        let mut rng = SeededRng::new(seed as u64, SCENE_STREAM);
+
+        let mut layers = vec![AmbientLayer {
+            kind: LayerKind::Bed,
+            cutoff: log_range(&mut rng, 180.0, 600.0),
+            resonance: rng.range_f32(0.5, 1.0),
+            sweep_rate: rng.range_f32(0.01, 0.04),
+            sweep_depth: rng.range_f32(0.3, 0.6),
+            level: rng.range_f32(0.6, 0.9),
+        }];
+        let textures = 1 + rng.below(2);
+        for _ in 0..textures {
+            layers.push(AmbientLayer {
+                kind: LayerKind::Texture,
+                cutoff: log_range(&mut rng, 800.0, 4800.0),
+                resonance: rng.range_f32(2.0, 8.0),
+                sweep_rate: rng.range_f32(0.03, 0.15),
+                sweep_depth: rng.range_f32(0.2, 0.5),
+                level: rng.range_f32(0.15, 0.35),
+            });
+        }
+
+        AmbientScene { seed, layers }
+    }
+}
+
+// Procedural noise under the voices, so the drone sits in a space rather than in
+// silence. It follows the seed, crossfading to a new scene when the seed changes
+pub struct Ambience {
+    level: f32,
+    bed: Option<NoiseBed>,
+    noise: Option<AudioBuffer>, // Made on first use, in the context it plays in
+}
+
+impl Ambience {
+    pub fn new() -> Self {
+        Ambience { level: DEFAULT_AMBIENCE_LEVEL, bed: None, noise: None }
+    }
+
+    // Call every frame with the seed the voices play; builds the scene for it, fading in
+    // over `fade` seconds as any other scene fades out
+    pub fn update(&mut self, context: &AudioContext, output: &AudioNode, seed: u32, fade: f32) -> Result<(), JsValue> {
+        if self.level <= 0.0 || self.bed.as_ref().is_some_and(|bed| bed.seed == seed) {
+            return Ok(());
+        }
+
+        let now = context.current_time();
+        let end = now + fade.max(0.0) as f64;
+        let noise = self.noise(context)?;
+        let incoming = NoiseBed::new(context, output, &noise, &AmbientScene::from_seed(seed))?;
+        ramp(&incoming.fader.gain(), self.level * BED_GAIN, now, end)?;
+        if let Some(outgoing) = self.bed.replace(incoming) {
+            outgoing.release(now, end);
+        }
+        Ok(())
+    }
+
+    // 0 silences and stops the bed; it starts again with the next update
+    pub fn set_level(&mut self, context: Option<&AudioContext>, level: f32) {
+        self.level = level.max(0.0).min(1.0);
+        let Some(context) = context else {
+            return;
+        };
+        let now = context.current_time();
+        let end = now + LEVEL_RAMP;
+        if self.level <= 0.0 {
+            if let Some(bed) = self.bed.take() {
+                bed.release(now, end);
+            }
+        } else if let Some(bed) = &self.bed {
+            let _ = ramp(&bed.fader.gain(), self.level * BED_GAIN, now, end);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(bed) = self.bed.take() {
+            bed.stop();
+        }
+    }
+
+    // Stereo pink noise (Paul Kellet's filter over seeded white noise), each channel its
+    // own so the bed is wide. The tail is crossfaded over the head so it loops smoothly
+    fn noise(&mut self, context: &AudioContext) -> Result<AudioBuffer, JsValue> {
+        if let Some(noise) = &self.noise {
+            return Ok(noise.clone());
+        }
+
+        let sample_rate = context.sample_rate();
+        let length = (sample_rate * NOISE_LENGTH) as usize;
+        let fade = (sample_rate * LOOP_FADE) as usize;
+        let buffer = context.create_buffer(2, length as u32, sample_rate)?;
+        let mut rng = SeededRng::new(0, NOISE_STREAM);
+
+        for channel in 0..2 {
+            let mut b = [0.0f32; 7];
+            let pink: Vec<f32> = (0..length + fade).map(|_| {
+                let white = rng.range_f32(-1.0, 1.0);
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let sample = b.iter().sum::<f32>() + white * 0.5362;
+                b[6] = white * 0.115926;
+                sample * 0.11
+            }).collect();
+
+            // The loop wraps from the last sample to the first, so the head fades in from
+            // the samples that would have followed the last
+            let mut samples = pink[..length].to_vec();
+            for (i, sample) in samples.iter_mut().take(fade).enumerate() {
+                let t = i as f32 / fade as f32;
+                *sample = *sample * t.sqrt() + pink[length + i] * (1.0 - t).sqrt();
+            }
+            buffer.copy_to_channel(&samples, channel)?;
+        }
+
+        self.noise = Some(buffer.clone());
+        Ok(buffer)
+    }
+}
+
+// The nodes playing one scene, behind a fader for crossfades
+struct NoiseBed {
+    seed: u32,
+    sources: Vec<AudioBufferSourceNode>,
+    sweeps: Vec<OscillatorNode>,
+    fader: GainNode,
+}
+
+impl NoiseBed {
+    // Starts silent; the caller fades it in
+    fn new(context: &AudioContext, output: &AudioNode, noise: &AudioBuffer, scene: &AmbientScene) -> Result<Self, JsValue> {
+        let fader = context.create_gain()?;
+        fader.gain().set_value(0.0);
+        fader.connect_with_audio_node(output)?;
+
+        let mut offsets = SeededRng::new(scene.seed as u64, NOISE_STREAM + 1);
+        let mut bed = NoiseBed { seed: scene.seed, sources: Vec::new(), sweeps: Vec::new(), fader };
+        let now = context.current_time();
+
+        for layer in &scene.layers {
+            let filter = context.create_biquad_filter()?;
+            filter.set_type(match layer.kind {
+                LayerKind::Bed => BiquadFilterType::Lowpass,
+                LayerKind::Texture => BiquadFilterType::Bandpass,
+            });
+            filter.frequency().set_value(layer.cutoff);
+            filter.q().set_value(layer.resonance);
+
+            let sweep = context.create_oscillator()?;
+            sweep.frequency().set_value(layer.sweep_rate);
+            let depth = context.create_gain()?;
+            depth.gain().set_value(layer.cutoff * layer.sweep_depth);
+            sweep.connect_with_audio_node(&depth)?;
+            depth.connect_with_audio_param(&filter.frequency())?;
+
+            let gain = context.create_gain()?;
+            gain.gain().set_value(layer.level);
+
+            // Each layer starts somewhere else in the loop, so they don't move together
+            let source = context.create_buffer_source()?;
+            source.set_buffer(Some(noise));
+            source.set_loop(true);
+            source.connect_with_audio_node(&filter)?;
+            filter.connect_with_audio_node(&gain)?;
+            gain.connect_with_audio_node(&bed.fader)?;
+
+            source.start_with_when_and_grain_offset(now, offsets.range_f32(0.0, NOISE_LENGTH) as f64)?;
+            sweep.start()?;
+            bed.sources.push(source);
+            bed.sweeps.push(sweep);
+        }
+        Ok(bed)
+    }
+
+    fn release(self, now: f64, end: f64) {
+        let _ = ramp(&self.fader.gain(), 0.0, now, end);
+        for source in &self.sources {
+            let _ = AsRef::<AudioScheduledSourceNode>::as_ref(source).stop_with_when(end);
+        }
+        for sweep in &self.sweeps {
+            let _ = sweep.stop_with_when(end);
+        }
+    }
+
+    fn stop(self) {
+        for source in &self.sources {
+            let _ = AsRef::<AudioScheduledSourceNode>::as_ref(source).stop();
+        }
+        for sweep in &self.sweeps {
+            let _ = sweep.stop();
+        }
+    }
+}
+
+// Spread evenly in pitch rather than in Hz
+fn log_range(rng: &mut SeededRng, low: f32, high: f32) -> f32 {
+    low * (high / low).powf(rng.next_f32())
+}
+
+// From wherever the param is now, even mid-fade
+fn ramp(param: &AudioParam, target: f32, now: f64, end: f64) -> Result<(), JsValue> {
+    let current = param.value();
+    param.cancel_scheduled_values(now)?;
+    param.set_value_at_time(current, now)?;
+    if end <= now {
+        param.set_value_at_time(target, now)?;
+    } else {
+        param.linear_ramp_to_value_at_time(target, end)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+// A pause longer than this, in ms, ends one session and the next gesture starts another
+const SESSION_GAP: f64 = 30.0 * 60.0 * 1000.0;
+
+// Session lengths kept for the average, most recent last
+const MAX_SESSIONS: usize = 30;
+
+// How the user plays, counted on this device only: which gestures, at what hour, for how
+// long at a time. Nothing here is sent anywhere; the "share_usage" preference only
+// records whether the user would allow it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Usage {
+    pub gestures: BTreeMap<String, u32>, // By gesture type
+    pub hours: [u32; 24],                // Gestures in each local hour of the day
+    pub sessions: Vec<f64>,              // The last MAX_SESSIONS sessions' lengths, in seconds
+    pub session_count: u32,
+    pub longest_session: f64, // Seconds
+    session_start: Option<f64>, // ms since the epoch, of the session under way
+    last_activity: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct Insights {
+    pub total_gestures: u32,
+    pub gesture_share: BTreeMap<String, f32>, // 0..1 of all gestures, by type
+    pub favorite_gesture: Option<String>,
+    pub peak_hour: Option<u32>, // Local hour, 0..23
+    pub session_count: u32,
+    pub average_session_minutes: f32,
+    pub longest_session_minutes: f32,
+    pub messages: Vec<String>, // The above in words, e.g. "You interact most at 7am"
+}
+
+impl Usage {
+    // A gesture of `kind` at `now` (ms since the epoch), in local hour `hour`
+    pub fn record_gesture(&mut self, kind: &str, now: f64, hour: u32) {
+        *self.gestures.entry(kind.to_string()).or_insert(0) += 1;
+        self.hours[hour.min(23) as usize] += 1;
+
+        match (self.session_start, self.last_activity) {
+            (Some(_), Some(last)) if now - last <= SESSION_GAP => {}
+            _ => {
+                self.end_session();
+                self.session_start = Some(now);
+                self.session_count += 1;
+            }
+        }
+        self.last_activity = Some(now);
+
+        // The session under way counts toward the longest as it goes
+        if let Some(start) = self.session_start {
+            self.longest_session = self.longest_session.max((now - start) / 1000.0);
+        }
+    }
+
+    fn end_session(&mut self) {
+        let (Some(start), Some(last)) = (self.session_start.take(), self.last_activity.take()) else {
+            return;
+        };
+        self.sessions.push((last - start) / 1000.0);
+        if self.sessions.len() > MAX_SESSIONS {
+            self.sessions.remove(0);
+        }
+    }
+
+    // Usage saved elsewhere (another tab, or before a migration finished) joins this, the
+    // larger of each count, as Achievements::merge does
+    pub fn merge(&mut self, other: Usage) {
+        for (kind, count) in other.gestures {
+            let kept = self.gestures.entry(kind).or_insert(0);
+            *kept = (*kept).max(count);
+        }
+        for (kept, count) in self.hours.iter_mut().zip(other.hours) {
+            *kept = (*kept).max(count);
+        }
+        if other.session_count > self.session_count {
+            self.sessions = other.sessions;
+            self.session_count = other.session_count;
+        }
+        self.longest_session = self.longest_session.max(other.longest_session);
+    }
+
+    pub fn insights(&self) -> Insights {
+        let total_gestures: u32 = self.gestures.values().sum();
+        let gesture_share = self.gestures.iter()
+            .map(|(kind, &count)| (kind.clone(), count as f32 / total_gestures.max(1) as f32))
+            .collect();
+        // Ties go to the first
+        let favorite_gesture = self.gestures.iter()
+            .fold(None, |best: Option<(&String, u32)>, (kind, &count)| match best {
+                Some((_, most)) if most >= count => best,
+                _ => Some((kind, count)),
+            })
+            .map(|(kind, _)| kind.clone());
+        let peak_hour = (0..24u32).filter(|&hour| self.hours[hour as usize] > 0)
+            .fold(None, |best: Option<u32>, hour| match best {
+                Some(peak) if self.hours[peak as usize] >= self.hours[hour as usize] => best,
+                _ => Some(hour),
+            });
+
+        // The session under way counts as it stands
+        let mut sessions = self.sessions.clone();
+        if let (Some(start), Some(last)) = (self.session_start, self.last_activity) {
+            sessions.push((last - start) / 1000.0);
+        }
+        let average_session_minutes = (sessions.iter().sum::<f64>() / sessions.len().max(1) as f64 / 60.0) as f32;
+        let longest_session_minutes = (self.longest_session / 60.0) as f32;
+
+        let mut messages = Vec::new();
+        if let Some(hour) = peak_hour {
+            messages.push(format!("You interact most at {}", hour_name(hour)));
+        }
+        if let Some(kind) = &favorite_gesture {
+            let share = self.gestures[kind] as f32 / total_gestures.max(1) as f32;
+            messages.push(format!("{} is your most used gesture ({:.0}%)", capitalized(kind), share * 100.0));
+        }
+        if !sessions.is_empty() {
+            messages.push(format!("Your sessions last {} on average", minutes_name(average_session_minutes)));
+        }
+
+        Insights {
+            total_gestures,
+            gesture_share,
+            favorite_gesture,
+            peak_hour,
+            session_count: self.session_count,
+            average_session_minutes,
+            longest_session_minutes,
+            messages,
+        }
+    }
+}
+
+// 0 is "12am", 7 "7am", 13 "1pm"
+fn hour_name(hour: u32) -> String {
+    let suffix = if hour < 12 { "am" } else { "pm" };
+    let hour = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    format!("{}{}", hour, suffix)
+}
+
+fn minutes_name(minutes: f32) -> String {
+    match minutes.round() as u32 {
+        0 => "under a minute".to_string(),
+        1 => "a minute".to_string(),
+        minutes => format!("{} minutes", minutes),
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
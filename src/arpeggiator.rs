@@ -0,0 +1,185 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioNode, OscillatorType};
+use serde::{Serialize, Deserialize};
+use crate::clock::Clock;
+use crate::harmony::Chord;
+use crate::rng::SeededRng;
+
+// Gestures older than this, in seconds, no longer count toward the pace; once fewer
+// than two are left the arpeggio stops
+const GESTURE_WINDOW: f64 = 2.0;
+
+// Notes a second, however slow or frantic the gestures
+const MIN_RATE: f32 = 2.0;
+const MAX_RATE: f32 = 16.0;
+
+// As for the rhythm (see Sequencer)
+const LOOKAHEAD: f64 = 0.12;
+
+// Lowest note of the arpeggio, an octave above the reference A so it sits over the voices
+const LOW_NOTE: f32 = 220.0;
+const MAX_OCTAVES: u32 = 4;
+
+// Longest a note rings, in seconds; faster runs get shorter notes
+const MAX_DECAY: f64 = 0.6;
+const SILENCE: f32 = 0.001;
+
+const ARP_STREAM: u64 = 0xA4E9_0001;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArpDirection {
+    Up,
+    Down,
+    // Up then back down, without repeating the top and bottom notes
+    UpDown,
+    Random,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ArpSettings {
+    pub enabled: bool,
+    pub direction: ArpDirection,
+    // 0..1 of a note's length the second of each pair is pushed late; 1/3 swings like
+    // triplets
+    pub swing: f32,
+    pub octaves: u32,
+    // Notes played per gesture, so the pace follows the gestures' own
+    pub notes_per_gesture: f32,
+    pub level: f32,
+}
+
+impl Default for ArpSettings {
+    fn default() -> Self {
+        ArpSettings {
+            enabled: true,
+            direction: ArpDirection::Up,
+            swing: 0.0,
+            octaves: 2,
+            notes_per_gesture: 4.0,
+            level: 0.5,
+        }
+    }
+}
+
+// Turns a run of gestures into an instrument: while they keep coming, the current chord
+// is played a note at a time, as many notes per gesture as the settings ask, so the
+// faster the gestures the faster the run. It slows as they tail off and stops soon after
+pub struct Arpeggiator {
+    pub settings: ArpSettings,
+    gestures: Vec<(f64, f32)>, // Clock time and intensity of the recent gestures
+    step: usize,
+    next_note_time: f64, // Clock time
+    rng: SeededRng,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Arpeggiator {
+            settings: ArpSettings::default(),
+            gestures: Vec::new(),
+            step: 0,
+            next_note_time: 0.0,
+            rng: SeededRng::new(0, ARP_STREAM),
+        }
+    }
+
+    // {"enabled", "direction" (up, down, updown or random), "swing", "octaves",
+    // "notes_per_gesture", "level"}; missing fields take their defaults
+    pub fn configure(&mut self, settings_json: &str) -> Result<(), JsValue> {
+        self.settings = serde_json::from_str(settings_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid arpeggiator settings: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.settings).unwrap()
+    }
+
+    pub fn gesture(&mut self, time: f64, intensity: f32) {
+        self.gestures.push((time, intensity.clamp(0.0, 1.0)));
+    }
+
+    // Notes a second at `time`: the gestures a second since the first recent one, which
+    // falls away as the time since the last grows; None until there are two to pace by
+    fn rate(&self, time: f64) -> Option<f32> {
+        if self.gestures.len() < 2 {
+            return None;
+        }
+        let span = (time - self.gestures[0].0).max(1e-3);
+        let pace = (self.gestures.len() - 1) as f64 / span;
+        Some((pace as f32 * self.settings.notes_per_gesture).clamp(MIN_RATE, MAX_RATE))
+    }
+
+    // Call every frame with the chord to play; queues the notes due within the lookahead,
+    // like the rhythm, so the run keeps time whatever the frame rate
+    pub fn schedule(&mut self, context: &AudioContext, output: &AudioNode, clock: &Clock, chord: &Chord) -> Result<(), JsValue> {
+        let now = clock.now();
+        self.gestures.retain(|&(when, _)| when > now - GESTURE_WINDOW);
+        let rate = match self.rate(now) {
+            Some(rate) if self.settings.enabled => rate,
+            _ => {
+                // The next run starts from the bottom of the pattern
+                self.step = 0;
+                return Ok(());
+            }
+        };
+
+        if self.next_note_time < now {
+            self.next_note_time = now;
+        }
+        let notes = chord.notes(LOW_NOTE, self.settings.octaves.clamp(1, MAX_OCTAVES));
+        let intensity = self.gestures.iter().map(|&(_, intensity)| intensity).sum::<f32>() / self.gestures.len() as f32;
+        let level = 0.12 * self.settings.level.clamp(0.0, 1.0) * (0.5 + 0.5 * intensity);
+        let swing = self.settings.swing.clamp(0.0, 0.9) as f64;
+        let length = 1.0 / rate as f64;
+
+        while self.next_note_time < now + LOOKAHEAD {
+            let Some(when) = clock.to_audio(self.next_note_time) else {
+                return Ok(());
+            };
+            let frequency = notes[self.note_index(notes.len())];
+            Self::pluck(context, output, frequency, level, when, (2.0 * length).min(MAX_DECAY))?;
+
+            // Notes go in pairs, the first held long by the swing and the second short
+            let swung = if self.step.is_multiple_of(2) { 1.0 + swing } else { 1.0 - swing };
+            self.next_note_time += length * swung;
+            self.step += 1;
+        }
+        Ok(())
+    }
+
+    fn note_index(&mut self, count: usize) -> usize {
+        match self.settings.direction {
+            ArpDirection::Up => self.step % count,
+            ArpDirection::Down => count - 1 - self.step % count,
+            ArpDirection::UpDown if count > 1 => {
+                let period = 2 * count - 2;
+                let position = self.step % period;
+                if position < count { position } else { period - position }
+            }
+            ArpDirection::UpDown => 0,
+            ArpDirection::Random => self.rng.below(count as u32) as usize,
+        }
+    }
+
+    // A plucked triangle: a near-instant attack and an exponential decay over `decay`
+    fn pluck(context: &AudioContext, output: &AudioNode, frequency: f32, level: f32, when: f64, decay: f64) -> Result<(), JsValue> {
+        let oscillator = context.create_oscillator()?;
+        let gain = context.create_gain()?;
+        oscillator.set_type(OscillatorType::Triangle);
+        oscillator.frequency().set_value_at_time(frequency, when)?;
+
+        let envelope = gain.gain();
+        envelope.set_value_at_time(0.0, when)?;
+        envelope.linear_ramp_to_value_at_time(level, when + 0.005)?;
+        envelope.exponential_ramp_to_value_at_time(SILENCE, when + decay)?;
+
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(output)?;
+        oscillator.start_with_when(when)?;
+        oscillator.stop_with_when(when + decay)?;
+        Ok(())
+    }
+}
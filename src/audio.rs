@@ -1,144 +1,893 @@
 use wasm_bindgen::prelude::*;
-use web_sys::{AudioContext, AudioNode, OscillatorNode, GainNode, AudioDestinationNode};
+use web_sys::{AudioContext, AudioNode, AudioParam, OscillatorNode, OscillatorType, GainNode, AudioDestinationNode};
+use web_sys::{DistanceModelType, PannerNode, PanningModelType};
+use nalgebra::Vector3;
+use crate::audio_fx::{FxChain, FxSettings};
+use crate::audio_lifecycle::AudioLifecycle;
+use crate::master_bus::{MasterBus, DEFAULT_TARGET_DB};
+use crate::complexity::ComplexityReport;
+use crate::fractals::{create_fractal_from_params, ColoringMode, FractalAudioAnalyzer};
+use crate::math;
+use crate::palette::Palette;
+use crate::user::FrozenFractal;
+use crate::sequencer::{Pattern, Sequencer};
+use crate::gesture_sound::GestureSoundBank;
+use crate::ambience::Ambience;
+use crate::arpeggiator::Arpeggiator;
+use crate::clock::Clock;
+use crate::doppler::Doppler;
+use crate::modulation::{ModTargets, ModulationMatrix};
+use crate::sonify::ViewScan;
+use crate::harmony::{Chord, Progression};
+use crate::voice_allocator::{SlotAction, VoiceAllocator, DEFAULT_MAX_VOICES, MAX_VOICES};
 
-pub struct AudioEngine {
+// Seconds a voice takes to reach a new pitch unless set_glide_time says otherwise
+const DEFAULT_GLIDE_TIME: f32 = 0.15;
+
+// Audible range the oscillators are clamped to
+const MIN_FREQUENCY: f32 = 80.0;
+const MAX_FREQUENCY: f32 = 2000.0;
+
+// Seconds a crossfade between soundscapes takes, e.g. into a new fractal (see transition_to)
+pub const CROSSFADE_TIME: f32 = 4.0;
+
+// Voices reported to the renderer by voice_state; must match the u_audio_state array
+// size in the scene shaders
+pub const AUDIO_STATE_VOICES: usize = 8;
+
+// How quickly a stolen voice fades before its oscillator is retuned for the newcomer
+const STEAL_RELEASE: f64 = 0.03;
+
+// Crossfade into a bank of a new size after set_max_voices
+const VOICE_LIMIT_CROSSFADE: f32 = 0.5;
+
+// Time constant for voices following their sample points around, in seconds
+const POSITION_SMOOTHING: f64 = 0.05;
+
+// Time constant for the Doppler bends and the whoosh, a little slower than the positions
+// so frame-to-frame jitter in the motion isn't heard
+const MOTION_SMOOTHING: f64 = 0.1;
+
+// Level the voices drop to while a sonification sweep plays over them, and the seconds
+// they take to get there and back
+const SONIFY_DUCK: f32 = 0.25;
+const SONIFY_DUCK_TIME: f64 = 0.3;
+
+// A resonance's shimmer: the first few voices again at these multiples of their pitch (an
+// octave, and a fifth above that), at this share of their level
+const RESONANCE_RATIOS: [f32; 2] = [2.0, 3.0];
+const RESONANCE_VOICES: usize = 4;
+const RESONANCE_LEVEL: f32 = 0.5;
+
+// Level of the master bus until set_master_volume changes it
+pub const DEFAULT_MASTER_VOLUME: f32 = 0.1;
+
+// Offline bounces: the fractal is re-analysed OFFLINE_CONTROL_RATE times a second, like
+// the live render loop, and ramps are evaluated every RAMP_BLOCK samples
+pub const OFFLINE_SAMPLE_RATE: u32 = 44_100;
+const OFFLINE_CONTROL_RATE: u32 = 60;
+const RAMP_BLOCK: usize = 32;
+const MAX_OFFLINE_SECONDS: f32 = 60.0;
+
+// The nodes every voice plays through, built once there is a context to build them in
+struct Graph {
     context: AudioContext,
-    oscillators: Vec<OscillatorNode>,
-    gain_nodes: Vec<GainNode>,
     master_gain: GainNode,
+    voice_bus: GainNode, // Where the voice banks mix, for the modulation to move together
+    pitch_bus: GainNode, // Pitch modulation in cents, fanned out to every voice's detune
+    fx: FxChain,
+    master_bus: MasterBus,
+}
+
+pub struct AudioEngine {
+    lifecycle: AudioLifecycle,
+    graph: Option<Graph>,
+    voices: Option<VoiceBank>,
+    // Settings made before the graph exists are applied when it's built
+    fx_settings: FxSettings,
+    target_loudness: f32,
+    master_volume: f32,
+    sequencer: Sequencer,
+    gesture_sounds: GestureSoundBank,
+    arpeggiator: Arpeggiator,
+    ambience: Ambience,
+    modulation: ModulationMatrix,
+    doppler: Doppler,
+    clock: Clock,
     current_frequencies: Vec<f32>,
+    glide_time: f32,
+    max_voices: usize,
+    resonance: Vec<OscillatorNode>, // The shimmer's, until they stop by themselves
     is_playing: bool,
 }
 
 impl AudioEngine {
+    // Silent until the first user gesture on the page starts the AudioContext (see
+    // AudioLifecycle); frequencies and settings given before then are kept
     pub fn new() -> Result<Self, JsValue> {
-        // Create audio context
-        let context = AudioContext::new()?;
-
-        // Create master gain node
-        let master_gain = context.create_gain()?;
-        master_gain.connect_with_audio_node(&context.destination())?;
-        master_gain.gain().set_value(0.1); // Start quiet
-
         Ok(AudioEngine {
-            context,
-            oscillators: Vec::new(),
-            gain_nodes: Vec::new(),
-            master_gain,
+            lifecycle: AudioLifecycle::new()?,
+            graph: None,
+            voices: None,
+            fx_settings: FxSettings::default(),
+            target_loudness: DEFAULT_TARGET_DB,
+            master_volume: DEFAULT_MASTER_VOLUME,
+            sequencer: Sequencer::new(),
+            gesture_sounds: GestureSoundBank::new(),
+            arpeggiator: Arpeggiator::new(),
+            ambience: Ambience::new(),
+            modulation: ModulationMatrix::new(),
+            doppler: Doppler::new(),
+            clock: Clock::new(),
             current_frequencies: Vec::new(),
+            glide_time: DEFAULT_GLIDE_TIME,
+            max_voices: DEFAULT_MAX_VOICES,
+            resonance: Vec::new(),
             is_playing: false,
         })
     }
 
+    // Builds the graph once a gesture has created the context, and brings in the voices
+    // that were waiting for it
+    fn connect(&mut self) {
+        if self.graph.is_some() {
+            return;
+        }
+        let Some(context) = self.lifecycle.context() else {
+            return;
+        };
+
+        match self.build_graph(context) {
+            Ok(graph) => {
+                self.clock.attach(graph.context.clone());
+                self.graph = Some(graph);
+                self.glide_oscillators();
+            }
+            Err(e) => web_sys::console::warn_2(&JsValue::from_str("Audio graph failed:"), &e),
+        }
+    }
+
+    fn build_graph(&self, context: AudioContext) -> Result<Graph, JsValue> {
+        // Create master gain node, feeding the effects chain and then the limiter
+        let mut master_bus = MasterBus::new(&context)?;
+        master_bus.set_target_loudness(self.target_loudness);
+        let fx = FxChain::new(&context, master_bus.input(), &self.fx_settings)?;
+        let master_gain = context.create_gain()?;
+        master_gain.connect_with_audio_node(fx.input())?;
+        master_gain.gain().set_value(self.master_volume);
+        let voice_bus = context.create_gain()?;
+        voice_bus.connect_with_audio_node(&master_gain)?;
+        let pitch_bus = context.create_gain()?;
+
+        Ok(Graph { context, master_gain, voice_bus, pitch_bus, fx, master_bus })
+    }
+
+    // Holds the audio suspended, page visibility notwithstanding, until resume()
+    pub fn suspend(&self) {
+        self.lifecycle.suspend();
+    }
+
+    // Starts or resumes the audio; call from a user gesture handler, since browsers ignore
+    // it otherwise until the page has had one
+    pub fn resume(&mut self) {
+        self.lifecycle.resume();
+        self.connect();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.lifecycle.is_running()
+    }
+
+    // Keeps the audio going while the page is hidden instead of suspending it
+    pub fn set_background_playback(&self, enabled: bool) {
+        self.lifecycle.set_plays_hidden(enabled);
+    }
+
     pub fn update_frequencies(&mut self, frequencies: &[f32]) {
+        self.connect();
+
         // Only update if frequencies have changed significantly
-        if self.frequencies_changed(frequencies) {
+        if frequencies_changed(&self.current_frequencies, frequencies) {
             self.current_frequencies = frequencies.to_vec();
-            self.restart_oscillators();
+            self.glide_oscillators();
         }
     }
 
-    fn frequencies_changed(&self, new_frequencies: &[f32]) -> bool {
-        if self.current_frequencies.len() != new_frequencies.len() {
-            return true;
+    // Seconds each voice takes to glide to a new frequency; 0 jumps straight there
+    pub fn set_glide_time(&mut self, seconds: f32) {
+        self.glide_time = seconds.max(0.0).min(5.0);
+    }
+
+    // Ramps the existing voices to the current frequencies instead of restarting them,
+    // which would click; new voices fade in and unused ones fade out
+    fn glide_oscillators(&mut self) {
+        let Some(graph) = &self.graph else {
+            return;
+        };
+        if self.voices.is_none() {
+            match VoiceBank::new(graph, 1.0, self.max_voices) {
+                Ok(bank) => self.voices = Some(bank),
+                Err(_) => return,
+            }
         }
+        let Some(voices) = &mut self.voices else {
+            return;
+        };
 
-        self.current_frequencies.iter()
-            .zip(new_frequencies.iter())
-            .any(|(old, new)| (old - new).abs() > 5.0) // 5Hz threshold
+        let now = graph.context.current_time();
+        voices.glide(&graph.context, &self.current_frequencies, now, now + self.glide_time as f64);
+        self.is_playing = !voices.oscillators.is_empty();
     }
 
-    fn restart_oscillators(&mut self) {
-        // Stop existing oscillators
-        self.stop_all();
+    // Blends into `frequencies` over `seconds` instead of gliding there: a fresh bank of
+    // voices starts at the new pitches and fades in while the current bank fades out, for
+    // changes too large to glide through, such as a new day's fractal
+    pub fn transition_to(&mut self, frequencies: &[f32], seconds: f32) {
+        self.connect();
+        self.current_frequencies = frequencies.to_vec();
+        let Some(graph) = &self.graph else {
+            return;
+        };
+
+        let mut incoming = match VoiceBank::new(graph, 0.0, self.max_voices) {
+            Ok(bank) => bank,
+            Err(_) => return self.glide_oscillators(),
+        };
+        let now = graph.context.current_time();
+        let end = now + seconds.max(0.0) as f64;
+        incoming.glide(&graph.context, &self.current_frequencies, now, now);
+        let _ = Self::ramp(&incoming.fader.gain(), 1.0, now, end, false);
+        if let Some(outgoing) = self.voices.replace(incoming) {
+            outgoing.release(now, end);
+        }
+        self.is_playing = !self.current_frequencies.is_empty();
+    }
+
+    // The resonance mode's sound: a shimmer over the drone (see RESONANCE_RATIOS), louder
+    // for a stronger resonance, swelling in over `fade` seconds, holding, and fading out by
+    // `seconds`, when it stops by itself. Scheduled whole on the audio clock, so it needs
+    // nothing per frame; a later one replaces it
+    pub fn resonate(&mut self, seconds: f32, fade: f32, strength: f32) -> Result<(), JsValue> {
+        self.connect();
+        for oscillator in self.resonance.drain(..) {
+            let _ = oscillator.stop();
+        }
+        let Some(graph) = &self.graph else {
+            return Ok(());
+        };
 
-        // Clear vectors
-        self.oscillators.clear();
-        self.gain_nodes.clear();
+        let now = graph.context.current_time();
+        let end = now + seconds.max(2.0 * fade) as f64;
+        let level = RESONANCE_LEVEL * strength.max(0.0).min(2.0).sqrt();
+        let shimmer = graph.context.create_gain()?;
+        shimmer.gain().set_value_at_time(0.0, now)?;
+        shimmer.gain().linear_ramp_to_value_at_time(level, now + fade as f64)?;
+        shimmer.gain().set_value_at_time(level, end - fade as f64)?;
+        shimmer.gain().linear_ramp_to_value_at_time(0.0, end)?;
+        shimmer.connect_with_audio_node(&graph.voice_bus)?;
 
-        // Create new oscillators for current frequencies
-        for (i, &frequency) in self.current_frequencies.iter().enumerate() {
-            if let Ok((osc, gain)) = self.create_oscillator(frequency, i) {
-                self.oscillators.push(osc);
-                self.gain_nodes.push(gain);
+        for &frequency in self.current_frequencies.iter().take(RESONANCE_VOICES) {
+            let frequency = frequency.max(MIN_FREQUENCY).min(MAX_FREQUENCY);
+            for ratio in RESONANCE_RATIOS {
+                let oscillator = graph.context.create_oscillator()?;
+                let gain = graph.context.create_gain()?;
+                oscillator.set_type(OscillatorType::Sine);
+                oscillator.frequency().set_value(frequency * ratio);
+                gain.gain().set_value(Self::voice_gain(frequency) / ratio);
+                oscillator.connect_with_audio_node(&gain)?;
+                gain.connect_with_audio_node(&shimmer)?;
+                oscillator.start_with_when(now)?;
+                oscillator.stop_with_when(end)?;
+                self.resonance.push(oscillator);
             }
         }
+        Ok(())
+    }
 
-        self.is_playing = true;
+    // Caps how many voices sound at once, keeping the loudest; older devices crackle with
+    // too many oscillators. The change crossfades into a bank of the new size
+    pub fn set_max_voices(&mut self, count: usize) {
+        self.max_voices = count.clamp(1, MAX_VOICES);
+        if self.voices.is_some() {
+            let frequencies = self.current_frequencies.clone();
+            self.transition_to(&frequencies, VOICE_LIMIT_CROSSFADE);
+        }
     }
 
-    fn create_oscillator(&self, frequency: f32, index: usize) -> Result<(OscillatorNode, GainNode), JsValue> {
-        let oscillator = self.context.create_oscillator()?;
-        let gain = self.context.create_gain()?;
+    // Pins the param where it is now, so the ramp starts from the value actually playing
+    // even mid-glide, then ramps to `target` by `end`. Pitch glides exponentially, which
+    // sounds even, but that can't reach or leave zero
+    fn ramp(param: &AudioParam, target: f32, now: f64, end: f64, exponential: bool) -> Result<(), JsValue> {
+        let current = param.value();
+        param.cancel_scheduled_values(now)?;
+        param.set_value_at_time(current, now)?;
 
-        // Set frequency (clamp to audible range)
-        let clamped_freq = frequency.max(80.0).min(2000.0);
-        oscillator.frequency().set_value(clamped_freq);
+        if end <= now {
+            param.set_value_at_time(target, now)?;
+        } else if exponential && current > 0.0 && target > 0.0 {
+            param.exponential_ramp_to_value_at_time(target, end)?;
+        } else {
+            param.linear_ramp_to_value_at_time(target, end)?;
+        }
+        Ok(())
+    }
 
-        // Use default sine wave for now
-        // TODO: Fix waveform setting when Web API is updated
+    // Lower frequencies louder
+    fn voice_gain(frequency: f32) -> f32 {
+        (1.0 / (1.0 + frequency / 400.0)) * 0.1
+    }
 
-        // Set gain based on frequency (lower frequencies louder)
-        let gain_value = (1.0 / (1.0 + frequency / 400.0)) * 0.1;
-        gain.gain().set_value(gain_value);
+    // Moves each voice to the listener-space position of its sample point (x right, y up,
+    // -z ahead, as Web Audio's default listener hears it); voices without one stay put.
+    // How fast they move bends and brightens them (see Doppler)
+    pub fn set_voice_positions(&mut self, positions: &[Vector3<f32>]) {
+        let (Some(graph), Some(voices)) = (&self.graph, &self.voices) else {
+            return;
+        };
+        let now = graph.context.current_time();
 
-        // Connect oscillator -> gain -> master gain
-        oscillator.connect_with_audio_node(&gain)?;
-        gain.connect_with_audio_node(&self.master_gain)?;
+        let slots: Vec<_> = voices.allocator.slots().iter()
+            .map(|voice| Some(((*voice)?, *positions.get((*voice)?)?)))
+            .collect();
+        if let Some(motion) = self.doppler.update(self.clock.now(), &slots) {
+            for (oscillator, cents) in voices.oscillators.iter().zip(&motion.shifts) {
+                let _ = oscillator.detune().set_target_at_time(*cents, now, MOTION_SMOOTHING);
+            }
+            let _ = graph.fx.cutoff_detune().set_target_at_time(motion.whoosh, now, MOTION_SMOOTHING);
+        }
 
-        // Start oscillator
-        oscillator.start()?;
+        let placed = voices.allocator.slots().iter()
+            .zip(&voices.panners)
+            .filter_map(|(voice, panner)| Some((panner, positions.get((*voice)?)?)));
+        for (panner, position) in placed {
+            let _ = panner.position_x().set_target_at_time(position.x, now, POSITION_SMOOTHING);
+            let _ = panner.position_y().set_target_at_time(position.y, now, POSITION_SMOOTHING);
+            let _ = panner.position_z().set_target_at_time(position.z, now, POSITION_SMOOTHING);
+        }
+    }
+
+    // Mono bounce of a frozen fractal's drone: `seconds` of samples at OFFLINE_SAMPLE_RATE,
+    // replaying the frequency changes the live engine would make from time 0, with the
+    // same glides and levels. The voices are synthesized here rather than through an
+    // OfflineAudioContext, whose rendering is asynchronous and differs in the last bits
+    // between browser engines; with the `deterministic` feature this is bit-identical on
+    // every device. Panning, effects, modulation and the ambience are left out.
+    pub fn render_offline(frozen_json: &str, seconds: f32) -> Result<Vec<f32>, JsValue> {
+        let frozen: FrozenFractal = serde_json::from_str(frozen_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let params = frozen.fractal_params();
+        let palette = Palette::from_seed(frozen.seed);
+        let chord = *Progression::from_seed(frozen.seed).chord_at(frozen.interaction_count);
+
+        let total = (seconds.max(0.0).min(MAX_OFFLINE_SECONDS) * OFFLINE_SAMPLE_RATE as f32) as usize;
+        let control_step = (OFFLINE_SAMPLE_RATE / OFFLINE_CONTROL_RATE) as usize;
+        let sample_rate = OFFLINE_SAMPLE_RATE as f64;
+
+        let mut samples = Vec::with_capacity(total);
+        let mut voices: Vec<OfflineVoice> = Vec::new();
+        let mut allocator = VoiceAllocator::new(DEFAULT_MAX_VOICES);
+        let mut current_frequencies: Vec<f32> = Vec::new();
+
+        for control_start in (0..total).step_by(control_step) {
+            let now = control_start as f64 / sample_rate;
+            let time = now as f32;
+            let fractal = create_fractal_from_params(&params, time, ColoringMode::Standard, palette.clone());
+            let frequencies: Vec<f32> = FractalAudioAnalyzer::extract_frequencies(&*fractal, &FractalAudioAnalyzer::sample_points(time))
+                .into_iter()
+                .map(|frequency| chord.constrain(frequency))
+                .collect();
+
+            // The same decisions as update_frequencies and VoiceBank::glide
+            if frequencies_changed(&current_frequencies, &frequencies) {
+                let end = now + DEFAULT_GLIDE_TIME as f64;
+                let amplitudes: Vec<f32> = frequencies.iter().map(|&f| Self::voice_gain(f)).collect();
+                for action in allocator.allocate(&amplitudes) {
+                    match action {
+                        SlotAction::Keep { slot, voice } => voices[slot].glide(frequencies[voice], now, end),
+                        SlotAction::Assign { slot, voice } if slot == voices.len() => {
+                            voices.push(OfflineVoice::new(frequencies[voice].clamp(MIN_FREQUENCY, MAX_FREQUENCY)));
+                            voices[slot].glide(frequencies[voice], now, end);
+                        }
+                        SlotAction::Assign { slot, voice } => voices[slot].steal(frequencies[voice], now, end),
+                        SlotAction::Release { slot } => voices[slot].release(now, end),
+                    }
+                }
+                current_frequencies = frequencies;
+            }
+
+            let control_end = (control_start + control_step).min(total);
+            for block_start in (control_start..control_end).step_by(RAMP_BLOCK) {
+                let block_time = block_start as f64 / sample_rate;
+                for voice in voices.iter_mut() {
+                    voice.settle(block_time);
+                }
+                let levels: Vec<(f64, f32)> = voices.iter()
+                    .map(|voice| (voice.frequency.at(block_time) as f64 / sample_rate, voice.gain.at(block_time)))
+                    .collect();
 
-        Ok((oscillator, gain))
+                for _ in block_start..(block_start + RAMP_BLOCK).min(control_end) {
+                    let mut mix = 0.0;
+                    for (voice, &(step, gain)) in voices.iter_mut().zip(&levels) {
+                        mix += gain * math::sin((voice.phase * std::f64::consts::TAU) as f32);
+                        voice.phase = (voice.phase + step).fract();
+                    }
+                    samples.push(mix * DEFAULT_MASTER_VOLUME);
+                }
+            }
+        }
+
+        Ok(samples)
     }
 
-    pub fn play_gesture_feedback(&self, gesture_type: &str, intensity: f32) -> Result<(), JsValue> {
-        // Create a short feedback sound based on gesture
-        let oscillator = self.context.create_oscillator()?;
-        let gain = self.context.create_gain()?;
+    // The gesture's sound from the bank, louder and bent further the harder the gesture
+    pub fn play_gesture_feedback(&mut self, gesture_type: &str, intensity: f32) -> Result<(), JsValue> {
+        // Gestures start the audio, so this may be the call that builds the graph
+        self.connect();
+        let Some(graph) = &self.graph else {
+            return Ok(());
+        };
+        self.arpeggiator.gesture(self.clock.now(), intensity);
+        self.gesture_sounds.play(&graph.context, &graph.master_gain, gesture_type, intensity)
+    }
 
-        let frequency = match gesture_type {
-            "swipe" => 440.0 + intensity * 200.0,
-            "pinch" => 880.0 - intensity * 400.0,
-            "tilt" => 330.0 + intensity * 100.0,
-            "smile" => 550.0 + intensity * 300.0,
-            _ => 440.0,
+    pub fn set_gesture_sounds(&mut self, sounds_json: &str) -> Result<(), JsValue> {
+        self.gesture_sounds.configure(sounds_json)
+    }
+
+    pub fn get_gesture_sounds(&self) -> String {
+        self.gesture_sounds.to_json()
+    }
+
+    pub fn set_arpeggiator(&mut self, settings_json: &str) -> Result<(), JsValue> {
+        self.arpeggiator.configure(settings_json)
+    }
+
+    pub fn get_arpeggiator(&self) -> String {
+        self.arpeggiator.to_json()
+    }
+
+    // Call every frame with the chord the voices are on; queues the arpeggio's upcoming
+    // notes while gestures keep coming (see Arpeggiator)
+    pub fn schedule_arpeggio(&mut self, chord: &Chord) -> Result<(), JsValue> {
+        match &self.graph {
+            Some(graph) => self.arpeggiator.schedule(&graph.context, &graph.master_gain, &self.clock, chord),
+            None => Ok(()),
+        }
+    }
+
+    // Call every frame with the seed the voices come from; a new seed crossfades to its
+    // own soundscape along with the voices
+    pub fn update_ambience(&mut self, seed: u32) {
+        self.connect();
+        if let Some(graph) = &self.graph {
+            let _ = self.ambience.update(&graph.context, &graph.master_gain, seed, CROSSFADE_TIME);
+        }
+    }
+
+    // Plays a sweep of the view (see ViewScan::play) over `seconds`, with the voices
+    // ducked beneath it; call from a gesture handler, as it may start the audio
+    pub fn sonify(&mut self, scan: &ViewScan, seconds: f32) -> Result<(), JsValue> {
+        self.context()?;
+        let Some(graph) = &self.graph else {
+            return Ok(());
         };
+        let now = graph.context.current_time();
+        let start = now + SONIFY_DUCK_TIME;
+        let end = start + seconds.max(0.5) as f64;
 
-        oscillator.frequency().set_value(frequency);
-        // Using default sine wave
+        let level = graph.voice_bus.gain();
+        level.cancel_scheduled_values(now)?;
+        level.set_value_at_time(level.value(), now)?;
+        level.linear_ramp_to_value_at_time(SONIFY_DUCK, start)?;
+        level.set_value_at_time(SONIFY_DUCK, end)?;
+        level.linear_ramp_to_value_at_time(1.0, end + SONIFY_DUCK_TIME)?;
 
-        // Quick envelope: attack -> decay
-        let now = self.context.current_time();
-        gain.gain().set_value(0.0);
-        gain.gain().linear_ramp_to_value_at_time(intensity * 0.2, now + 0.05)?;
-        gain.gain().linear_ramp_to_value_at_time(0.0, now + 0.3)?;
+        scan.play(&graph.context, &graph.master_gain, start, end - start)
+    }
 
-        oscillator.connect_with_audio_node(&gain)?;
-        gain.connect_with_audio_node(&self.master_gain)?;
+    // Call every frame with the seed the voices come from; plays its modulation patch
+    // (unless set_modulation has replaced it) in time with the rhythm
+    pub fn update_modulation(&mut self, seed: u32) {
+        self.connect();
+        let Some(graph) = &self.graph else {
+            return;
+        };
+        let targets = ModTargets {
+            pitch: &graph.pitch_bus,
+            cutoff: graph.fx.cutoff_detune(),
+            gain: graph.voice_bus.gain(),
+        };
+        let tempo = self.sequencer.pattern().map(|pattern| pattern.tempo);
+        let pulse = self.sequencer.pulse(self.clock.now());
+        let voices = self.voice_state().chunks(2).map(|voice| voice[1]).sum::<f32>().min(1.0);
+        let _ = self.modulation.update(&graph.context, &targets, seed, tempo, pulse, voices);
+    }
 
-        oscillator.start()?;
-        oscillator.stop_with_when(now + 0.3)?;
+    // Routes as JSON in place of the seed's patch (see Resonant::set_modulation); null
+    // goes back to the seed's
+    pub fn set_modulation(&mut self, routes_json: &str) -> Result<(), JsValue> {
+        self.modulation.set_routes(routes_json)
+    }
 
-        Ok(())
+    pub fn get_modulation(&self, seed: u32) -> String {
+        self.modulation.to_json(seed)
+    }
+
+    // How strongly the voices' motion across the view bends and brightens them, 0..1
+    pub fn set_doppler_depth(&mut self, depth: f32) {
+        self.doppler.depth = depth.max(0.0).min(1.0);
+    }
+
+    // 0..1 of the ambience's full level, which sits well under the voices; 0 turns it off
+    pub fn set_ambience_level(&mut self, level: f32) {
+        self.ambience.set_level(self.graph.as_ref().map(|graph| &graph.context), level);
+    }
+
+    // RMS loudness in dBFS the auto-gain steers toward (-60 to -6)
+    pub fn set_target_loudness(&mut self, db: f32) {
+        self.target_loudness = db;
+        if let Some(graph) = &mut self.graph {
+            graph.master_bus.set_target_loudness(db);
+        }
+    }
+
+    // Call every frame; keeps the auto-gain following the mix
+    pub fn update_levels(&mut self) {
+        if let Some(graph) = &mut self.graph {
+            graph.master_bus.update(&graph.context);
+        }
+    }
+
+    // The context for other audio nodes to join, starting it if no gesture has yet; call
+    // from a gesture handler for the same reason as resume()
+    pub fn context(&mut self) -> Result<AudioContext, JsValue> {
+        let context = self.lifecycle.start().ok_or("Audio unavailable")?;
+        self.connect();
+        Ok(context)
+    }
+
+    pub fn set_effect(&mut self, name: &str, params_json: &str) -> Result<(), JsValue> {
+        self.fx_settings.set_effect(name, params_json)?;
+        self.apply_effects()
+    }
+
+    pub fn get_effects(&self) -> String {
+        self.fx_settings.to_json()
+    }
+
+    pub fn follows_fractal(&self) -> bool {
+        self.fx_settings.mix.follow_fractal
+    }
+
+    pub fn follow_fractal(&mut self, geometry: &ComplexityReport) -> Result<(), JsValue> {
+        self.fx_settings.follow_fractal(geometry);
+        self.apply_effects()
+    }
+
+    fn apply_effects(&mut self) -> Result<(), JsValue> {
+        match &mut self.graph {
+            Some(graph) => graph.fx.apply(&graph.context, &self.fx_settings),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_rhythm(&mut self, pattern: Pattern) {
+        self.sequencer.set_pattern(pattern);
+    }
+
+    pub fn get_rhythm(&self) -> Option<&Pattern> {
+        self.sequencer.pattern()
+    }
+
+    pub fn set_rhythm_enabled(&mut self, enabled: bool) {
+        self.sequencer.enabled = enabled;
+    }
+
+    // Call every frame; queues the rhythm's upcoming hits on the audio clock
+    pub fn schedule_rhythm(&mut self) -> Result<(), JsValue> {
+        match &self.graph {
+            Some(graph) => self.sequencer.schedule(&graph.context, &graph.master_gain, &self.clock),
+            None => Ok(()),
+        }
+    }
+
+    // Call once per frame, before anything reads the time; returns the seconds elapsed
+    // on the shared clock (see Clock) for the animation to advance by
+    pub fn tick_clock(&mut self) -> f64 {
+        self.clock.tick()
+    }
+
+    // The rhythm's pulse now (see Sequencer::pulse), for the visuals to beat along with
+    pub fn rhythm_pulse(&self) -> f32 {
+        self.sequencer.pulse(self.clock.now())
+    }
+
+    // The loudest AUDIO_STATE_VOICES voices as (pitch, level) pairs, read from the audio
+    // params so glides and crossfades show as they happen. Pitch runs 0..1 logarithmically
+    // over the voices' range and level 0..1; all zero while nothing can be heard
+    pub fn voice_state(&self) -> [f32; AUDIO_STATE_VOICES * 2] {
+        let mut state = [0.0; AUDIO_STATE_VOICES * 2];
+        let Some(voices) = self.voices.as_ref().filter(|_| self.is_running()) else {
+            return state;
+        };
+
+        let fader = voices.fader.gain().value();
+        let full_scale = Self::voice_gain(MIN_FREQUENCY);
+        let range = (MAX_FREQUENCY / MIN_FREQUENCY).ln();
+        let mut sounding: Vec<(f32, f32)> = voices.oscillators.iter().zip(&voices.gain_nodes)
+            .map(|(oscillator, gain)| {
+                let pitch = (oscillator.frequency().value() / MIN_FREQUENCY).ln() / range;
+                (pitch.max(0.0).min(1.0), (gain.gain().value() * fader / full_scale).min(1.0))
+            })
+            .collect();
+        sounding.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        for (slot, (pitch, level)) in state.chunks_mut(2).zip(sounding) {
+            slot[0] = pitch;
+            slot[1] = level;
+        }
+        state
     }
 
     pub fn get_current_frequencies(&self) -> Vec<f32> {
         self.current_frequencies.clone()
     }
 
-    pub fn set_master_volume(&self, volume: f32) {
-        self.master_gain.gain().set_value(volume.max(0.0).min(1.0));
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.clamp(0.0, 1.0);
+        if let Some(graph) = &self.graph {
+            graph.master_gain.gain().set_value(self.master_volume);
+        }
     }
 
     pub fn stop_all(&mut self) {
-        for oscillator in &self.oscillators {
+        // Stopped oscillators can't be restarted, so the bank starts over
+        if let Some(voices) = self.voices.take() {
+            for oscillator in &voices.oscillators {
+                let _ = oscillator.stop();
+            }
+        }
+        for oscillator in self.resonance.drain(..) {
             let _ = oscillator.stop();
         }
+        self.ambience.stop();
+        self.modulation.stop();
+        self.current_frequencies.clear();
         self.is_playing = false;
     }
 }
 
+// Persistent set of oscillator slots behind one fader, at most the allocator's cap:
+// slots are only ever added, and ones without a voice are faded to silence rather than
+// stopped, ready for the next newcomer
+struct VoiceBank {
+    allocator: VoiceAllocator,
+    oscillators: Vec<OscillatorNode>,
+    gain_nodes: Vec<GainNode>,
+    panners: Vec<PannerNode>, // Places each voice where its sample point sits in the view
+    fader: GainNode,
+    pitch_bus: GainNode,
+}
+
+impl VoiceBank {
+    fn new(graph: &Graph, level: f32, max_voices: usize) -> Result<Self, JsValue> {
+        let fader = graph.context.create_gain()?;
+        fader.gain().set_value(level);
+        fader.connect_with_audio_node(&graph.voice_bus)?;
+        Ok(VoiceBank {
+            allocator: VoiceAllocator::new(max_voices),
+            oscillators: Vec::new(),
+            gain_nodes: Vec::new(),
+            panners: Vec::new(),
+            fader,
+            pitch_bus: graph.pitch_bus.clone(),
+        })
+    }
+
+    // Takes the voices the allocator lets in to their frequencies and levels by `end`
+    fn glide(&mut self, context: &AudioContext, frequencies: &[f32], now: f64, end: f64) {
+        let amplitudes: Vec<f32> = frequencies.iter().map(|&f| AudioEngine::voice_gain(f)).collect();
+
+        for action in self.allocator.allocate(&amplitudes) {
+            let _ = match action {
+                SlotAction::Keep { slot, voice } => self.retarget(slot, frequencies[voice], now, end),
+                SlotAction::Assign { slot, voice } if slot == self.oscillators.len() => {
+                    match self.create_oscillator(context, frequencies[voice]) {
+                        Ok((osc, gain, panner)) => {
+                            self.oscillators.push(osc);
+                            self.gain_nodes.push(gain);
+                            self.panners.push(panner);
+                            self.retarget(slot, frequencies[voice], now, end)
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+                SlotAction::Assign { slot, voice } => self.steal(slot, frequencies[voice], now, end),
+                SlotAction::Release { slot } => match self.gain_nodes.get(slot) {
+                    Some(gain) => AudioEngine::ramp(&gain.gain(), 0.0, now, end, false),
+                    None => Ok(()),
+                },
+            };
+        }
+    }
+
+    fn retarget(&self, slot: usize, frequency: f32, now: f64, end: f64) -> Result<(), JsValue> {
+        let (Some(oscillator), Some(gain)) = (self.oscillators.get(slot), self.gain_nodes.get(slot)) else {
+            return Ok(());
+        };
+        let target = frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+        AudioEngine::ramp(&oscillator.frequency(), target, now, end, true)?;
+        AudioEngine::ramp(&gain.gain(), AudioEngine::voice_gain(frequency), now, end, false)
+    }
+
+    // Hands a slot to a new voice: whatever it played fades out over STEAL_RELEASE, then
+    // the oscillator jumps to the new pitch while silent and fades back in
+    fn steal(&self, slot: usize, frequency: f32, now: f64, end: f64) -> Result<(), JsValue> {
+        let (Some(oscillator), Some(gain)) = (self.oscillators.get(slot), self.gain_nodes.get(slot)) else {
+            return Ok(());
+        };
+        let released = now + STEAL_RELEASE;
+
+        let level = gain.gain();
+        AudioEngine::ramp(&level, 0.0, now, released, false)?;
+        level.linear_ramp_to_value_at_time(AudioEngine::voice_gain(frequency), released + (end - now).max(STEAL_RELEASE))?;
+
+        let pitch = oscillator.frequency();
+        pitch.cancel_scheduled_values(now)?;
+        pitch.set_value_at_time(pitch.value(), now)?;
+        pitch.set_value_at_time(frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY), released)?;
+        Ok(())
+    }
+
+    // Fades the whole bank out by `end` and stops it there; the nodes go once stopped
+    fn release(self, now: f64, end: f64) {
+        let _ = AudioEngine::ramp(&self.fader.gain(), 0.0, now, end, false);
+        for oscillator in &self.oscillators {
+            let _ = oscillator.stop_with_when(end);
+        }
+    }
+
+    // Starts silent at its frequency, straight ahead; glide fades it in
+    fn create_oscillator(&self, context: &AudioContext, frequency: f32) -> Result<(OscillatorNode, GainNode, PannerNode), JsValue> {
+        let oscillator = context.create_oscillator()?;
+        let gain = context.create_gain()?;
+        let panner = context.create_panner()?;
+
+        // HRTF for headphone listeners; the gentle rolloff keeps far voices audible, since
+        // the whole fractal sits only a few units from the camera
+        panner.set_panning_model(PanningModelType::Hrtf);
+        panner.set_distance_model(DistanceModelType::Inverse);
+        panner.set_ref_distance(1.0);
+        panner.set_rolloff_factor(0.3);
+        panner.position_z().set_value(-1.0);
+
+        oscillator.frequency().set_value(frequency.max(MIN_FREQUENCY).min(MAX_FREQUENCY));
+
+        // Use default sine wave for now
+        // TODO: Fix waveform setting when Web API is updated
+
+        gain.gain().set_value(0.0);
+
+        // Connect oscillator -> gain -> panner -> bank fader
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&panner)?;
+        panner.connect_with_audio_node(&self.fader)?;
+        self.pitch_bus.connect_with_audio_param(&oscillator.detune())?;
+
+        // Start oscillator
+        oscillator.start()?;
+
+        Ok((oscillator, gain, panner))
+    }
+}
+
+fn frequencies_changed(old_frequencies: &[f32], new_frequencies: &[f32]) -> bool {
+    if old_frequencies.len() != new_frequencies.len() {
+        return true;
+    }
+
+    old_frequencies.iter()
+        .zip(new_frequencies.iter())
+        .any(|(old, new)| (old - new).abs() > 5.0) // 5Hz threshold
+}
+
+// An AudioParam's automation as AudioEngine::ramp schedules it: hold the value reached
+// at `start`, then move to `to` by `end`
+#[derive(Clone, Copy)]
+struct Ramp {
+    from: f32,
+    to: f32,
+    start: f64,
+    end: f64,
+    exponential: bool,
+}
+
+impl Ramp {
+    fn at(&self, time: f64) -> f32 {
+        if time >= self.end {
+            return self.to;
+        }
+        let progress = ((time - self.start) / (self.end - self.start)).max(0.0) as f32;
+        if self.exponential && self.from > 0.0 && self.to > 0.0 {
+            self.from * math::powf(self.to / self.from, progress)
+        } else {
+            self.from + (self.to - self.from) * progress
+        }
+    }
+
+    fn retarget(&mut self, to: f32, now: f64, end: f64) {
+        self.from = self.at(now);
+        self.to = to;
+        self.start = now;
+        self.end = end;
+    }
+}
+
+// The second half of a steal: at `at` the pitch jumps, then (unless released meanwhile)
+// the level fades up to `gain` by `end`
+#[derive(Clone, Copy)]
+struct Retune {
+    at: f64,
+    frequency: f32,
+    fade_in: Option<(f32, f64)>,
+}
+
+// One oscillator slot of render_offline's bank; like a live voice it starts silent
+struct OfflineVoice {
+    phase: f64, // In cycles
+    frequency: Ramp,
+    gain: Ramp,
+    retune: Option<Retune>,
+}
+
+impl OfflineVoice {
+    fn new(frequency: f32) -> Self {
+        OfflineVoice {
+            phase: 0.0,
+            frequency: Ramp { from: frequency, to: frequency, start: 0.0, end: 0.0, exponential: true },
+            gain: Ramp { from: 0.0, to: 0.0, start: 0.0, end: 0.0, exponential: false },
+            retune: None,
+        }
+    }
+
+    // As VoiceBank::retarget; a glide cancels a pending retune, as it does live
+    fn glide(&mut self, frequency: f32, now: f64, end: f64) {
+        self.retune = None;
+        self.frequency.retarget(frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY), now, end);
+        self.gain.retarget(AudioEngine::voice_gain(frequency), now, end);
+    }
+
+    // As VoiceBank::steal
+    fn steal(&mut self, frequency: f32, now: f64, end: f64) {
+        let released = now + STEAL_RELEASE;
+        self.gain.retarget(0.0, now, released);
+        self.retune = Some(Retune {
+            at: released,
+            frequency: frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY),
+            fade_in: Some((AudioEngine::voice_gain(frequency), released + (end - now).max(STEAL_RELEASE))),
+        });
+    }
+
+    // Only the level is cancelled live, so a pending pitch jump still happens
+    fn release(&mut self, now: f64, end: f64) {
+        if let Some(retune) = &mut self.retune {
+            retune.fade_in = None;
+        }
+        self.gain.retarget(0.0, now, end);
+    }
+
+    fn settle(&mut self, time: f64) {
+        let Some(retune) = self.retune.filter(|retune| time >= retune.at) else {
+            return;
+        };
+        self.retune = None;
+        self.frequency = Ramp { from: retune.frequency, to: retune.frequency, start: retune.at, end: retune.at, exponential: true };
+        if let Some((gain, end)) = retune.fade_in {
+            self.gain.retarget(gain, retune.at, end);
+        }
+    }
+}
+
 impl Drop for AudioEngine {
     fn drop(&mut self) {
         self.stop_all();
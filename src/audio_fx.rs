@@ -0,0 +1,236 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioNode, AudioParam, BiquadFilterNode, BiquadFilterType, ConvolverNode, DelayNode, GainNode};
+use serde::{Serialize, Deserialize};
+use crate::complexity::ComplexityReport;
+use crate::rng::SeededRng;
+
+// DelayNode allocates its buffer up front, so this caps DelaySettings::time
+const MAX_DELAY_TIME: f32 = 2.0;
+
+// Longest reverb tail; the impulse response is this many seconds of stereo samples at most
+const MAX_REVERB_SIZE: f32 = 6.0;
+
+// Time constant for parameter changes, short enough to feel immediate without zipper noise
+const PARAM_SMOOTHING: f64 = 0.05;
+
+// Fixed noise for the impulse response, so a given size and decay always sound the same
+const IMPULSE_STREAM: u64 = 0x1F00_0001;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ReverbSettings {
+    pub size: f32,  // Tail length in seconds
+    pub decay: f32, // Exponent of the tail's fade; higher dies away sooner
+    pub wet: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        ReverbSettings { size: 2.0, decay: 3.0, wet: 0.25 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct DelaySettings {
+    pub time: f32,     // Seconds between echoes
+    pub feedback: f32, // Share of each echo fed back into the line
+    pub wet: f32,
+}
+
+impl Default for DelaySettings {
+    fn default() -> Self {
+        DelaySettings { time: 0.375, feedback: 0.35, wet: 0.0 }
+    }
+}
+
+// Low-pass over everything, ahead of the reverb and delay
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub cutoff: f32,    // Hz
+    pub resonance: f32, // Q
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        FilterSettings { cutoff: 8000.0, resonance: 0.7 }
+    }
+}
+
+// Level of the unprocessed signal, and whether the fractal drives the reverb size and
+// filter cutoff (see FxChain::follow_fractal)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct MixSettings {
+    pub dry: f32,
+    pub follow_fractal: bool,
+}
+
+impl Default for MixSettings {
+    fn default() -> Self {
+        MixSettings { dry: 1.0, follow_fractal: true }
+    }
+}
+
+// Everything set_effect controls, kept apart from the nodes so it can be set before the
+// audio graph exists
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct FxSettings {
+    pub reverb: ReverbSettings,
+    pub delay: DelaySettings,
+    pub filter: FilterSettings,
+    pub mix: MixSettings,
+}
+
+impl FxSettings {
+    // "reverb", "delay", "filter" or "mix" with that effect's settings as JSON; missing
+    // fields take their defaults
+    pub fn set_effect(&mut self, name: &str, params_json: &str) -> Result<(), JsValue> {
+        let invalid = |e: serde_json::Error| JsValue::from_str(&format!("Invalid {} settings: {}", name, e));
+        match name {
+            "reverb" => self.reverb = serde_json::from_str(params_json).map_err(invalid)?,
+            "delay" => self.delay = serde_json::from_str(params_json).map_err(invalid)?,
+            "filter" => self.filter = serde_json::from_str(params_json).map_err(invalid)?,
+            "mix" => self.mix = serde_json::from_str(params_json).map_err(invalid)?,
+            _ => return Err(JsValue::from_str(&format!("Unknown audio effect: {}", name))),
+        }
+        Ok(())
+    }
+
+    // Rougher fractals get a bigger room, and more surface a brighter filter
+    pub fn follow_fractal(&mut self, geometry: &ComplexityReport) {
+        if !self.mix.follow_fractal {
+            return;
+        }
+
+        let roughness = (geometry.box_dimension - 2.0).clamp(0.0, 1.0);
+        let brightness = ((1.0 + geometry.surface_area).ln() / 5.0).min(1.0);
+        self.reverb.size = 0.8 + roughness * 3.7;
+        self.filter.cutoff = 1500.0 + brightness * 8500.0;
+    }
+
+    pub fn to_json(self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
+
+// input -> low-pass -> dry ----------------------> output
+//                   -> delay (with feedback) -> wet -> output
+//                   -> convolver -> wet --------------> output
+pub struct FxChain {
+    input: GainNode,
+    filter: BiquadFilterNode,
+    dry: GainNode,
+    delay: DelayNode,
+    feedback: GainNode,
+    delay_wet: GainNode,
+    convolver: ConvolverNode,
+    reverb_wet: GainNode,
+    impulse: Option<(f32, f32)>, // Size and decay the loaded impulse response was built for
+}
+
+impl FxChain {
+    pub fn new(context: &AudioContext, output: &AudioNode, settings: &FxSettings) -> Result<Self, JsValue> {
+        let input = context.create_gain()?;
+        let filter = context.create_biquad_filter()?;
+        let dry = context.create_gain()?;
+        let delay = context.create_delay_with_max_delay_time(MAX_DELAY_TIME as f64)?;
+        let feedback = context.create_gain()?;
+        let delay_wet = context.create_gain()?;
+        let convolver = context.create_convolver()?;
+        let reverb_wet = context.create_gain()?;
+
+        filter.set_type(BiquadFilterType::Lowpass);
+        input.connect_with_audio_node(&filter)?;
+
+        filter.connect_with_audio_node(&dry)?;
+        dry.connect_with_audio_node(output)?;
+
+        filter.connect_with_audio_node(&delay)?;
+        delay.connect_with_audio_node(&feedback)?;
+        feedback.connect_with_audio_node(&delay)?;
+        delay.connect_with_audio_node(&delay_wet)?;
+        delay_wet.connect_with_audio_node(output)?;
+
+        filter.connect_with_audio_node(&convolver)?;
+        convolver.connect_with_audio_node(&reverb_wet)?;
+        reverb_wet.connect_with_audio_node(output)?;
+
+        let mut chain = FxChain {
+            input,
+            filter,
+            dry,
+            delay,
+            feedback,
+            delay_wet,
+            convolver,
+            reverb_wet,
+            impulse: None,
+        };
+        chain.apply(context, settings)?;
+        Ok(chain)
+    }
+
+    // Where sources connect
+    pub fn input(&self) -> &GainNode {
+        &self.input
+    }
+
+    // Shifts the filter's cutoff in cents, on top of its settings; for modulation
+    pub fn cutoff_detune(&self) -> AudioParam {
+        self.filter.detune()
+    }
+
+    // Moves the nodes to `settings`, smoothly except for a new reverb tail
+    pub fn apply(&mut self, context: &AudioContext, settings: &FxSettings) -> Result<(), JsValue> {
+        let now = context.current_time();
+        let smooth = |param: AudioParam, value: f32| param.set_target_at_time(value, now, PARAM_SMOOTHING).map(|_| ());
+
+        let nyquist = context.sample_rate() * 0.5;
+        smooth(self.filter.frequency(), settings.filter.cutoff.max(20.0).min(nyquist))?;
+        smooth(self.filter.q(), settings.filter.resonance.clamp(0.0001, 30.0))?;
+
+        smooth(self.delay.delay_time(), settings.delay.time.clamp(0.0, MAX_DELAY_TIME))?;
+        // Below 1 so the echoes always die out
+        smooth(self.feedback.gain(), settings.delay.feedback.clamp(0.0, 0.95))?;
+        smooth(self.delay_wet.gain(), settings.delay.wet.clamp(0.0, 1.0))?;
+
+        smooth(self.reverb_wet.gain(), settings.reverb.wet.clamp(0.0, 1.0))?;
+        smooth(self.dry.gain(), settings.mix.dry.clamp(0.0, 1.0))?;
+
+        // Swapping the convolver's buffer can click, so only rebuild it for an audible change
+        let size = settings.reverb.size.clamp(0.1, MAX_REVERB_SIZE);
+        let decay = settings.reverb.decay.clamp(0.5, 10.0);
+        let stale = match self.impulse {
+            Some((built_size, built_decay)) => (built_size - size).abs() > 0.1 || (built_decay - decay).abs() > 0.1,
+            None => true,
+        };
+        if stale {
+            self.load_impulse(context, size, decay)?;
+        }
+        Ok(())
+    }
+
+    // Decaying stereo noise, the usual stand-in for a recorded room
+    fn load_impulse(&mut self, context: &AudioContext, size: f32, decay: f32) -> Result<(), JsValue> {
+        let sample_rate = context.sample_rate();
+        let length = (sample_rate * size) as u32;
+        let buffer = context.create_buffer(2, length, sample_rate)?;
+        let mut rng = SeededRng::new(0, IMPULSE_STREAM);
+
+        for channel in 0..2 {
+            let samples: Vec<f32> = (0..length)
+                .map(|i| {
+                    let fade = 1.0 - i as f32 / length as f32;
+                    rng.range_f32(-1.0, 1.0) * fade.powf(decay)
+                })
+                .collect();
+            buffer.copy_to_channel(&samples, channel)?;
+        }
+
+        self.convolver.set_buffer(Some(&buffer));
+        self.impulse = Some((size, decay));
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{AnalyserNode, AudioContext, MediaStream, MediaStreamAudioSourceNode, MediaStreamConstraints, MediaStreamTrack};
+use nalgebra::{Matrix4, Vector3};
+
+// Must match the u_audio_bands array size in the scene shaders
+pub const AUDIO_BANDS: usize = 8;
+
+// FFT window; 1024 samples at 48kHz gives ~47Hz bins, fine enough for the lowest band
+const FFT_SIZE: u32 = 1024;
+
+// Band edges run logarithmically from the lowest kick drum to the top of speech sibilance
+const LOWEST_FREQUENCY: f32 = 40.0;
+const HIGHEST_FREQUENCY: f32 = 12_000.0;
+
+// Seconds for a band to rise toward a louder level, fall back from it, and for the
+// noise floor to creep up to a steady background
+const ATTACK_TIME: f32 = 0.03;
+const RELEASE_TIME: f32 = 0.25;
+const FLOOR_RISE_TIME: f32 = 3.0;
+
+// Level above the noise floor that reads as a full-strength band
+const FULL_SCALE: f32 = 0.25;
+
+// The open microphone; the analyser is deliberately not connected to the speakers
+struct Capture {
+    stream: MediaStream,
+    source: MediaStreamAudioSourceNode,
+    analyser: AnalyserNode,
+    sample_rate: f32,
+}
+
+// Releases the microphone, which also turns off the browser's recording indicator
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let _ = self.source.disconnect();
+        for track in self.stream.get_tracks().iter() {
+            if let Ok(track) = track.dyn_into::<MediaStreamTrack>() {
+                track.stop();
+            }
+        }
+    }
+}
+
+// Microphone analysis for audio-reactive rendering. Band energies are 0..1 above an
+// adaptive noise floor, so a humming fridge settles to zero while voices and beats pulse
+pub struct AudioInput {
+    // Filled in once the user grants access; the request resolves asynchronously
+    capture: Rc<RefCell<Option<Capture>>>,
+    bins: Vec<u8>,
+    floor: [f32; AUDIO_BANDS],
+    bands: [f32; AUDIO_BANDS],
+    // Whether the bands also nudge the view transform, not just the shading
+    pub perturb_transform: bool,
+}
+
+impl AudioInput {
+    // Asks for the microphone; until the user answers (or if they refuse) every band is 0
+    pub fn open(context: &AudioContext, perturb_transform: bool) -> Result<Self, JsValue> {
+        let window = web_sys::window().ok_or("No window available")?;
+        let devices = window.navigator().media_devices()?;
+
+        let constraints = MediaStreamConstraints::new();
+        constraints.set_audio(&JsValue::TRUE);
+        let request = devices.get_user_media_with_constraints(&constraints)?;
+
+        let capture = Rc::new(RefCell::new(None));
+        let slot = capture.clone();
+        let context = context.clone();
+
+        let on_granted: Closure<dyn FnMut(JsValue)> = Closure::once(move |stream: JsValue| {
+            let Ok(stream) = stream.dyn_into::<MediaStream>() else {
+                return;
+            };
+            match Self::connect(&context, stream) {
+                Ok(opened) => *slot.borrow_mut() = Some(opened),
+                Err(e) => web_sys::console::warn_2(&JsValue::from_str("Microphone unavailable:"), &e),
+            }
+        });
+        let on_refused: Closure<dyn FnMut(JsValue)> = Closure::once(move |error: JsValue| {
+            web_sys::console::warn_2(&JsValue::from_str("Microphone access refused:"), &error);
+        });
+        let _ = request.then2(&on_granted, &on_refused);
+        // The request may settle after this AudioInput is gone, so the callbacks must
+        // outlive it; they are small and there is one pair per open()
+        on_granted.forget();
+        on_refused.forget();
+
+        Ok(AudioInput {
+            capture,
+            bins: Vec::new(),
+            floor: [0.0; AUDIO_BANDS],
+            bands: [0.0; AUDIO_BANDS],
+            perturb_transform,
+        })
+    }
+
+    fn connect(context: &AudioContext, stream: MediaStream) -> Result<Capture, JsValue> {
+        let source = context.create_media_stream_source(&stream)?;
+        let analyser = context.create_analyser()?;
+        analyser.set_fft_size(FFT_SIZE);
+        // Smoothing happens per band in update(), with separate attack and release
+        analyser.set_smoothing_time_constant(0.0);
+        source.connect_with_audio_node(&analyser)?;
+        Ok(Capture { stream, source, analyser, sample_rate: context.sample_rate() })
+    }
+
+    pub fn bands(&self) -> [f32; AUDIO_BANDS] {
+        self.bands
+    }
+
+    // Reads the latest spectrum; call once per frame with the frame's length in seconds
+    pub fn update(&mut self, delta_seconds: f32) {
+        let capture = self.capture.borrow();
+        let Some(capture) = capture.as_ref() else {
+            return;
+        };
+
+        self.bins.resize(capture.analyser.frequency_bin_count() as usize, 0);
+        capture.analyser.get_byte_frequency_data(&mut self.bins);
+        let bin_width = capture.sample_rate / FFT_SIZE as f32;
+
+        let attack = 1.0 - (-delta_seconds / ATTACK_TIME).exp();
+        let release = 1.0 - (-delta_seconds / RELEASE_TIME).exp();
+        let floor_rise = 1.0 - (-delta_seconds / FLOOR_RISE_TIME).exp();
+
+        for band in 0..AUDIO_BANDS {
+            let level = Self::band_level(&self.bins, bin_width, band);
+
+            // The floor drops straight to quieter levels but only creeps up to louder ones
+            let floor = &mut self.floor[band];
+            *floor = if level < *floor { level } else { *floor + (level - *floor) * floor_rise };
+
+            let target = ((level - *floor) / FULL_SCALE).min(1.0);
+            let current = &mut self.bands[band];
+            *current += (target - *current) * if target > *current { attack } else { release };
+        }
+    }
+
+    // Mean bin of one logarithmic band, 0..1
+    fn band_level(bins: &[u8], bin_width: f32, band: usize) -> f32 {
+        let ratio = HIGHEST_FREQUENCY / LOWEST_FREQUENCY;
+        let edge = |i: usize| LOWEST_FREQUENCY * ratio.powf(i as f32 / AUDIO_BANDS as f32);
+        let first = (edge(band) / bin_width) as usize;
+        let last = ((edge(band + 1) / bin_width) as usize).max(first + 1).min(bins.len());
+        if first >= last {
+            return 0.0;
+        }
+
+        let sum: u32 = bins[first..last].iter().map(|&b| b as u32).sum();
+        sum as f32 / ((last - first) as f32 * 255.0)
+    }
+
+    // Bass swells the fractal and the mids sway it, applied after the user's own transform
+    pub fn perturbation(&self) -> Matrix4<f32> {
+        if !self.perturb_transform {
+            return Matrix4::identity();
+        }
+
+        let bass = 0.5 * (self.bands[0] + self.bands[1]);
+        let mids = (self.bands[3] + self.bands[4] + self.bands[5]) / 3.0;
+        Matrix4::new_nonuniform_scaling(&Vector3::repeat(1.0 + 0.08 * bass)) * Matrix4::from_scaled_axis(Vector3::y() * 0.05 * mids)
+    }
+}
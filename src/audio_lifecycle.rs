@@ -0,0 +1,132 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{AudioContext, AudioContextState, Document, Event};
+
+// Events browsers count as a user gesture for the autoplay policy. iOS Safari only
+// honours touchend, Chrome also pointerdown from a mouse and keydown
+const GESTURE_EVENTS: [&str; 4] = ["pointerdown", "touchend", "keydown", "click"];
+
+type ListenerClosure = Closure<dyn FnMut(Event)>;
+
+// Owns the AudioContext's lifetime. Browsers only let audio start from a user gesture,
+// and creating the context earlier logs a warning (and on iOS Safari leaves it silently
+// suspended), so it isn't created until the first gesture on the page. The context is
+// suspended while the page is hidden, unless set_plays_hidden says otherwise, and
+// suspend() holds it suspended until resume()
+pub struct AudioLifecycle {
+    // Filled in by the first gesture, or by start() if that comes first
+    context: Rc<RefCell<Option<AudioContext>>>,
+    // Set by suspend(); showing the page again doesn't override it
+    held: Rc<Cell<bool>>,
+    // Keeps playing while the page is hidden, for hosts that are only ever heard
+    plays_hidden: Rc<Cell<bool>>,
+    document: Document,
+    listeners: Vec<(&'static str, ListenerClosure)>,
+}
+
+impl AudioLifecycle {
+    pub fn new() -> Result<Self, JsValue> {
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or("No document available")?;
+
+        let context = Rc::new(RefCell::new(None));
+        let held = Rc::new(Cell::new(false));
+        let plays_hidden = Rc::new(Cell::new(false));
+        let mut listeners = Vec::new();
+
+        // Stays installed after the first gesture: iOS Safari also suspends a running
+        // context when a call or another app takes the audio, and only a gesture resumes it
+        for &event in GESTURE_EVENTS.iter() {
+            let (slot, held) = (context.clone(), held.clone());
+            let on_gesture: ListenerClosure = Closure::wrap(Box::new(move |_: Event| {
+                Self::start_in(&slot, &held);
+            }) as Box<dyn FnMut(Event)>);
+            listeners.push((event, on_gesture));
+        }
+
+        let (slot, held_by_user, keep_playing) = (context.clone(), held.clone(), plays_hidden.clone());
+        let visible = document.clone();
+        let on_visibility: ListenerClosure = Closure::wrap(Box::new(move |_: Event| {
+            let Some(context) = slot.borrow().clone() else {
+                return;
+            };
+            if keep_playing.get() {
+                return;
+            }
+            if visible.hidden() {
+                let _ = context.suspend();
+            } else if !held_by_user.get() {
+                let _ = context.resume();
+            }
+        }) as Box<dyn FnMut(Event)>);
+        listeners.push(("visibilitychange", on_visibility));
+
+        for (event, listener) in &listeners {
+            document.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+        }
+
+        Ok(AudioLifecycle { context, held, plays_hidden, document, listeners })
+    }
+
+    // The context, once a gesture has created it
+    pub fn context(&self) -> Option<AudioContext> {
+        self.context.borrow().clone()
+    }
+
+    // Creates the context now if no gesture has yet and resumes it unless held. Call from
+    // inside a gesture handler, or the browser may keep it suspended
+    pub fn start(&self) -> Option<AudioContext> {
+        Self::start_in(&self.context, &self.held)
+    }
+
+    fn start_in(slot: &RefCell<Option<AudioContext>>, held: &Cell<bool>) -> Option<AudioContext> {
+        if slot.borrow().is_none() {
+            match AudioContext::new() {
+                Ok(context) => *slot.borrow_mut() = Some(context),
+                Err(e) => {
+                    web_sys::console::warn_2(&JsValue::from_str("Audio unavailable:"), &e);
+                    return None;
+                }
+            }
+        }
+
+        let context = slot.borrow().clone()?;
+        if !held.get() && context.state() != AudioContextState::Running {
+            let _ = context.resume();
+        }
+        Some(context)
+    }
+
+    pub fn suspend(&self) {
+        self.held.set(true);
+        if let Some(context) = self.context() {
+            let _ = context.suspend();
+        }
+    }
+
+    pub fn resume(&self) {
+        self.held.set(false);
+        if !self.document.hidden() || self.plays_hidden.get() {
+            self.start();
+        }
+    }
+
+    pub fn set_plays_hidden(&self, enabled: bool) {
+        self.plays_hidden.set(enabled);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.context().is_some_and(|context| context.state() == AudioContextState::Running)
+    }
+}
+
+impl Drop for AudioLifecycle {
+    fn drop(&mut self) {
+        for (event, listener) in &self.listeners {
+            let _ = self.document.remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use nalgebra::Matrix4;
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::{complexity, effort, lineage, math};
+use crate::user::FrozenFractal;
+
+// Challenges still unanswered after this long, in ms, expire
+const CHALLENGE_TIMEOUT: u64 = 24 * 60 * 60 * 1000;
+
+// Challenges kept; past it the oldest settled ones go first
+const MAX_CHALLENGES: usize = 50;
+
+// How far apart the two ends' scores may be and still agree. They work the same f32 sums
+// from the same fractals, so in practice they match exactly
+const SCORE_TOLERANCE: f32 = 1e-4;
+
+// A battle's outcome as the shared algorithm scores it, from the challenger's side
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct BattleScores {
+    pub challenger: f32,
+    pub opponent: f32,
+    pub resonance: f32, // Between the two, the same from either side
+}
+
+impl BattleScores {
+    fn agrees(&self, other: &BattleScores) -> bool {
+        (self.challenger - other.challenger).abs() <= SCORE_TOLERANCE
+            && (self.opponent - other.opponent).abs() <= SCORE_TOLERANCE
+            && (self.resonance - other.resonance).abs() <= SCORE_TOLERANCE
+    }
+}
+
+// The battle algorithm: each side's complexity, the resonance between the two, and a
+// little for a bred line. Both ends of a networked battle work it out, and compare. None
+// if either side's fractal doesn't back up what it claims (see verified_complexity)
+pub fn scores(challenger: &FrozenFractal, opponent: &FrozenFractal) -> Option<BattleScores> {
+    let resonance = resonance_between(challenger, opponent);
+    Some(BattleScores {
+        challenger: verified_complexity(challenger)? + resonance,
+        opponent: verified_complexity(opponent)? + resonance,
+        resonance,
+    })
+}
+
+// What a side brings to a battle, worked out here rather than taken from the fractal: its
+// geometry analyzed again from its params, what its score claims on top for the day's
+// gestures (no more than a whole day at the limit earns) and its lineage bonus. None if
+// any of it doesn't check out, or it was scored with other math than this build's, so its
+// geometry can't be reproduced here
+fn verified_complexity(fractal: &FrozenFractal) -> Option<f32> {
+    if fractal.math_version != math::MATH_VERSION {
+        return None;
+    }
+    let geometry = complexity::analyze(&fractal.fractal_params()).score;
+    let engagement = fractal.complexity_score - geometry;
+    if !(-SCORE_TOLERANCE..=effort::max_bonus() + SCORE_TOLERANCE).contains(&engagement) {
+        return None;
+    }
+    Some(geometry + engagement.max(0.0) + lineage::battle_bonus(fractal)?)
+}
+
+fn resonance_between(fractal_a: &FrozenFractal, fractal_b: &FrozenFractal) -> f32 {
+    // Trace similarity of the two transforms (a simplified resonance measure); one that
+    // isn't a whole matrix counts as the identity
+    let trace = |fractal: &FrozenFractal| match fractal.transform_matrix.len() {
+        16 => Matrix4::from_row_slice(&fractal.transform_matrix).trace(),
+        _ => 4.0,
+    };
+    let trace_similarity = 1.0 / (1.0 + (trace(fractal_a) - trace(fractal_b)).abs());
+
+    // Seed harmony (how well seeds work together)
+    let seed_diff = (fractal_a.seed as f32 - fractal_b.seed as f32).abs();
+    let seed_harmony = 1.0 / (1.0 + seed_diff / 1000.0);
+
+    (trace_similarity + seed_harmony) * 0.5
+}
+
+// A step of a challenge, carried by a Battle message. The message's fractal is the
+// challenger's, but for an accept, which carries the opponent's
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "stage", rename_all = "lowercase")]
+pub enum BattleStage {
+    Challenge { rating: f32 },
+    Accept { rating: f32, scores: BattleScores }, // As the opponent worked them out
+    Decline,
+    Result { scores: BattleScores },              // As the challenger did
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BattleSignal {
+    pub challenge_id: String,
+    #[serde(flatten)]
+    pub stage: BattleStage,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeState {
+    Sent,     // Waiting for the opponent to answer
+    Received, // Waiting for the user to
+    Accepted, // Fought here, waiting for the challenger's result to check
+    Declined,
+    Verified, // Both ends scored it the same; recorded
+    Disputed, // They didn't, or a fractal didn't check out; not recorded
+    Expired,
+}
+
+// One challenge, sent or received, as this end knows it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Challenge {
+    pub id: String,          // The challenger's user id and a number of theirs
+    pub opponent_id: String, // Whoever is at the other end, challenger or not
+    pub challenger: bool,    // Whether the user sent it
+    pub state: ChallengeState,
+    pub own: Option<FrozenFractal>,    // The user's fractal as fought
+    pub theirs: Option<FrozenFractal>, // The other end's
+    pub their_rating: f32,
+    pub scores: Option<BattleScores>, // As worked out here
+    pub created_at: u64,              // ms since the epoch
+    pub updated_at: u64,
+}
+
+impl Challenge {
+    fn settled(&self) -> bool {
+        !matches!(self.state, ChallengeState::Sent | ChallengeState::Received | ChallengeState::Accepted)
+    }
+
+    // The user's score and the other end's, once scored
+    pub fn own_scores(&self) -> Option<(f32, f32, f32)> {
+        let scores = self.scores?;
+        Some(match self.challenger {
+            true => (scores.challenger, scores.opponent, scores.resonance),
+            false => (scores.opponent, scores.challenger, scores.resonance),
+        })
+    }
+}
+
+// Battles fought over the network rather than with pasted JSON. The challenger sends
+// their fractal and rating; the opponent accepts with theirs, scoring the battle as they
+// do, or declines. The challenger scores it in turn, checks the two agree, and sends
+// their result for the opponent to check likewise. Each end records the battle only once
+// the scores agree
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Challenges {
+    pub list: Vec<Challenge>, // Oldest first
+}
+
+impl Challenges {
+    pub fn challenge(&mut self, id: String, opponent_id: &str, own: FrozenFractal, now: u64) -> &Challenge {
+        self.push(Challenge {
+            id,
+            opponent_id: opponent_id.to_string(),
+            challenger: true,
+            state: ChallengeState::Sent,
+            own: Some(own),
+            theirs: None,
+            their_rating: 0.0,
+            scores: None,
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    // Fights a received challenge with `own`, returning it with the scores to send back;
+    // one whose fractal doesn't check out is disputed, with none
+    pub fn accept(&mut self, id: &str, own: FrozenFractal, now: u64) -> Result<&Challenge, JsValue> {
+        let challenge = self.received(id)?;
+        let theirs = challenge.theirs.as_ref().ok_or("The challenge carried no fractal")?;
+        challenge.scores = scores(theirs, &own);
+        challenge.own = Some(own);
+        challenge.state = match challenge.scores {
+            Some(_) => ChallengeState::Accepted,
+            None => ChallengeState::Disputed,
+        };
+        challenge.updated_at = now;
+        Ok(challenge)
+    }
+
+    pub fn decline(&mut self, id: &str, now: u64) -> Result<&Challenge, JsValue> {
+        let challenge = self.received(id)?;
+        challenge.state = ChallengeState::Declined;
+        challenge.updated_at = now;
+        Ok(challenge)
+    }
+
+    fn received(&mut self, id: &str) -> Result<&mut Challenge, JsValue> {
+        self.list.iter_mut()
+            .find(|challenge| challenge.id == id && challenge.state == ChallengeState::Received)
+            .ok_or_else(|| JsValue::from_str("No challenge waiting with that id"))
+    }
+
+    // Takes a signal from `sender`, returning the challenge it moved on; one that fits no
+    // challenge in the state to take it (a repeat, or from someone else) is dropped
+    pub fn receive(&mut self, sender: &str, signal: BattleSignal, fractal: FrozenFractal, now: u64) -> Option<&Challenge> {
+        let index = self.list.iter().position(|challenge| challenge.id == signal.challenge_id && challenge.opponent_id == sender);
+        let (index, state) = match (index, signal.stage) {
+            (None, BattleStage::Challenge { rating }) => {
+                // Ids start with their challenger's, so no two challengers' collide
+                if !signal.challenge_id.starts_with(&format!("{}:", sender)) {
+                    return None;
+                }
+                self.push(Challenge {
+                    id: signal.challenge_id,
+                    opponent_id: sender.to_string(),
+                    challenger: false,
+                    state: ChallengeState::Received,
+                    own: None,
+                    theirs: Some(fractal),
+                    their_rating: rating,
+                    scores: None,
+                    created_at: now,
+                    updated_at: now,
+                });
+                return self.list.last();
+            }
+            (Some(index), BattleStage::Accept { rating, scores: theirs }) if self.list[index].state == ChallengeState::Sent => {
+                let challenge = &mut self.list[index];
+                let own = challenge.own.as_ref()?;
+                let ours = scores(own, &fractal);
+                challenge.scores = ours;
+                challenge.theirs = Some(fractal);
+                challenge.their_rating = rating;
+                let agrees = ours.is_some_and(|ours| ours.agrees(&theirs));
+                (index, if agrees { ChallengeState::Verified } else { ChallengeState::Disputed })
+            }
+            (Some(index), BattleStage::Decline) if self.list[index].state == ChallengeState::Sent => {
+                (index, ChallengeState::Declined)
+            }
+            (Some(index), BattleStage::Result { scores: theirs }) if self.list[index].state == ChallengeState::Accepted => {
+                let agrees = self.list[index].scores.is_some_and(|ours| ours.agrees(&theirs));
+                (index, if agrees { ChallengeState::Verified } else { ChallengeState::Disputed })
+            }
+            _ => return None,
+        };
+        let challenge = &mut self.list[index];
+        challenge.state = state;
+        challenge.updated_at = now;
+        Some(challenge)
+    }
+
+    // Expires challenges left unanswered past CHALLENGE_TIMEOUT, returning them
+    pub fn expire(&mut self, now: u64) -> Vec<Challenge> {
+        self.list.iter_mut()
+            .filter(|challenge| !challenge.settled() && now.saturating_sub(challenge.created_at) > CHALLENGE_TIMEOUT)
+            .map(|challenge| {
+                challenge.state = ChallengeState::Expired;
+                challenge.updated_at = now;
+                challenge.clone()
+            })
+            .collect()
+    }
+
+    fn push(&mut self, challenge: Challenge) -> &Challenge {
+        self.list.push(challenge);
+        let mut excess = self.list.len().saturating_sub(MAX_CHALLENGES);
+        self.list.retain(|challenge| {
+            let dropped = excess > 0 && challenge.settled();
+            excess -= dropped as usize;
+            !dropped
+        });
+        let excess = self.list.len().saturating_sub(MAX_CHALLENGES);
+        self.list.drain(..excess);
+        self.list.last().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractals::{ColoringMode, FractalParams};
+    use crate::lineage::{Lineage, ParentRef};
+    use crate::user::Curation;
+
+    // A day's fractal as battle_snapshot takes it, `gestures` worth of bonus on its geometry.
+    // The seeds used are a Mandelbulb and a Julia4D, quick to analyze
+    fn snapshot(seed: u32, gestures: u32) -> FrozenFractal {
+        let params = FractalParams::from_seed(seed);
+        FrozenFractal {
+            version: 1,
+            seed,
+            fractal_type: Some(params.fractal_type()),
+            transform_matrix: vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            complexity_score: complexity::analyze(&params).score + effort::bonus(gestures),
+            timestamp: 1_718_064_000_000,
+            interaction_count: gestures,
+            coloring_mode: ColoringMode::Standard,
+            palette: None,
+            params: Some(params),
+            derivation: 1,
+            math_version: math::MATH_VERSION,
+            lineage: Lineage::default(),
+            curation: Curation::default(),
+        }
+    }
+
+    #[test]
+    fn honest_sides_score_alike_from_either_end() {
+        let (a, b) = (snapshot(20240613, 120), snapshot(20240617, 0));
+        let ours = scores(&a, &b).expect("honest fractals check out");
+        let theirs = scores(&b, &a).expect("honest fractals check out");
+        assert_eq!(ours.challenger, theirs.opponent);
+        assert_eq!(ours.opponent, theirs.challenger);
+        assert_eq!(ours.resonance, theirs.resonance);
+    }
+
+    // The doctored side goes first in each, so the honest one is never analyzed
+    #[test]
+    fn claimed_complexity_must_match_the_geometry() {
+        let (honest, other) = (snapshot(20240613, 0), snapshot(20240617, 0));
+        let mut inflated = other.clone();
+        inflated.complexity_score += effort::max_bonus() + 1.0;
+        assert_eq!(scores(&inflated, &honest), None);
+
+        let mut deflated = other.clone();
+        deflated.complexity_score -= 1.0;
+        assert_eq!(scores(&deflated, &honest), None);
+
+        let mut other_math = other;
+        other_math.math_version = math::MATH_VERSION + 1;
+        assert_eq!(scores(&other_math, &honest), None);
+    }
+
+    #[test]
+    fn lineage_bonus_needs_a_lineage_that_adds_up() {
+        let (honest, unbred) = (snapshot(20240613, 0), snapshot(20240617, 0));
+        let mut claimed = unbred.clone();
+        claimed.lineage = Lineage { generation: 6, parents: Vec::new() };
+        assert_eq!(scores(&claimed, &honest), None);
+
+        let mut bred = unbred.clone();
+        bred.lineage = Lineage { generation: 2, parents: vec![ParentRef { seed: 7, timestamp: 1, generation: 1 }] };
+        assert!(scores(&bred, &honest).unwrap().challenger > scores(&unbred, &honest).unwrap().challenger);
+    }
+
+    // An accept whose fractal claims more than it has is disputed, whatever scores it sends
+    #[test]
+    fn inflated_accept_is_disputed() {
+        let mut challenges = Challenges::default();
+        challenges.challenge("me:1".to_string(), "them", snapshot(20240613, 0), 0);
+        let mut inflated = snapshot(20240617, 0);
+        inflated.complexity_score += effort::max_bonus() + 1.0;
+        let forged = BattleScores { challenger: 0.0, opponent: 100.0, resonance: 0.5 };
+        let signal = BattleSignal {
+            challenge_id: "me:1".to_string(),
+            stage: BattleStage::Accept { rating: 1200.0, scores: forged },
+        };
+        let challenge = challenges.receive("them", signal, inflated, 1).expect("the accept fits the challenge");
+        assert_eq!(challenge.state, ChallengeState::Disputed);
+        assert!(challenge.own_scores().is_none());
+    }
+}
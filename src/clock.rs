@@ -0,0 +1,92 @@
+use web_sys::{AudioContext, AudioContextState};
+
+// Share of the gap between the estimate and the audio clock closed each tick. The audio
+// clock only moves once per 128-sample render quantum (or coarser, in some browsers), so
+// read raw it stutters; smoothed against the wall clock it runs evenly
+const SMOOTHING: f64 = 0.05;
+
+// A gap wider than this is not jitter but the audio clock having stood still, suspended
+// or starved; the estimate snaps to it instead of drifting over
+const RESYNC: f64 = 0.1;
+
+// Longest step tick() reports, so a backgrounded tab doesn't jump the animation
+const MAX_STEP: f64 = 0.25;
+
+// The one timeline both the render loop and the sequencer run on, in seconds. While the
+// audio plays it follows AudioContext.currentTime, which is what the hardware actually
+// plays by; before the first gesture, and while suspended, it runs on the wall clock so
+// the visuals keep moving. It never jumps when it changes source, and never runs back
+pub struct Clock {
+    context: Option<AudioContext>,
+    // Audio time minus wall time, while the audio clock is running
+    audio_offset: Option<f64>,
+    // Clock time minus source time; set afresh when the source changes
+    shift: Option<f64>,
+    time: f64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock { context: None, audio_offset: None, shift: None, time: 0.0 }
+    }
+
+    // Follows this context from the next tick, once it's running
+    pub fn attach(&mut self, context: AudioContext) {
+        self.context = Some(context);
+    }
+
+    // Call once per frame; moves the clock to now and returns the seconds since the last
+    // tick
+    pub fn tick(&mut self) -> f64 {
+        let wall = wall_seconds();
+        let audio = self.context.as_ref()
+            .filter(|context| context.state() == AudioContextState::Running)
+            .map(|context| context.current_time());
+
+        let source = match audio {
+            Some(audio) => {
+                let observed = audio - wall;
+                let offset = match self.audio_offset {
+                    Some(offset) if (observed - offset).abs() < RESYNC => offset + (observed - offset) * SMOOTHING,
+                    _ => {
+                        self.shift = None;
+                        observed
+                    }
+                };
+                self.audio_offset = Some(offset);
+                wall + offset
+            }
+            None => {
+                if self.audio_offset.take().is_some() {
+                    self.shift = None;
+                }
+                wall
+            }
+        };
+
+        let shift = *self.shift.get_or_insert(self.time - source);
+        let time = (source + shift).max(self.time);
+        let step = time - self.time;
+        self.time = time;
+        step.min(MAX_STEP)
+    }
+
+    // The time at the last tick
+    pub fn now(&self) -> f64 {
+        self.time
+    }
+
+    // When a clock time falls on the AudioContext's own timeline, for scheduling; None
+    // while the audio clock isn't running
+    pub fn to_audio(&self, time: f64) -> Option<f64> {
+        self.audio_offset?;
+        Some(time - self.shift?)
+    }
+}
+
+fn wall_seconds() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now() * 0.001)
+        .unwrap_or_else(|| js_sys::Date::now() * 0.001)
+}
@@ -0,0 +1,418 @@
+// Payloads for share links and tokens: URL-safe base64 (RFC 4648 §5, unpadded) of a
+// format byte and the JSON, deflated (RFC 1951) when that comes out shorter. Both halves
+// are here rather than in a crate, since the links only need a few hundred bytes of each
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// First byte of the decoded payload
+const FORMAT_PLAIN: u8 = 0;
+const FORMAT_DEFLATE: u8 = 1;
+
+// Largest payload inflate will produce; links are far smaller, and anything bigger is
+// someone's idea of a joke
+const MAX_INFLATED: usize = 64 * 1024;
+
+// Encodes `text` for a URL, deflated if that's shorter
+pub fn encode(text: &str) -> String {
+    let deflated = deflate(text.as_bytes());
+    let (format, body) = if deflated.len() < text.len() {
+        (FORMAT_DEFLATE, deflated)
+    } else {
+        (FORMAT_PLAIN, text.as_bytes().to_vec())
+    };
+    let mut payload = Vec::with_capacity(body.len() + 1);
+    payload.push(format);
+    payload.extend(body);
+    base64_encode(&payload)
+}
+
+pub fn decode(encoded: &str) -> Result<String, ()> {
+    let payload = base64_decode(encoded)?;
+    let (&format, body) = payload.split_first().ok_or(())?;
+    let bytes = match format {
+        FORMAT_PLAIN => body.to_vec(),
+        FORMAT_DEFLATE => inflate(body)?,
+        _ => return Err(()),
+    };
+    String::from_utf8(bytes).map_err(|_| ())
+}
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(group >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+// Padding, if any, is ignored
+pub fn base64_decode(encoded: &str) -> Result<Vec<u8>, ()> {
+    let digits = encoded.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        // A lone trailing digit can't hold a byte
+        if chunk.len() == 1 {
+            return Err(());
+        }
+        let mut group = 0u32;
+        for (i, &digit) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|&c| c == digit).ok_or(())? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// Length and distance codes: the smallest value each stands for, and the extra bits
+// that follow it
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+                                35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+                                3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+                                  257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+                                  8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+                                  7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Order the code length code lengths come in, in a dynamic block's header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 12;
+const MAX_CHAIN: usize = 64; // Earlier matches tried per position; plenty for JSON
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    // `value`'s low `count` bits, least significant first, as DEFLATE packs everything
+    // but Huffman codes
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+// The fixed literal/length code (RFC 1951 §3.2.6)
+fn fixed_code(symbol: u16) -> (u32, u32) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    }
+}
+
+// One block with the fixed codes, matches found through hash chains of three-byte
+// prefixes. Not the smallest output possible, but links are short and this is simple
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: Vec::new(), buffer: 0, count: 0 };
+    writer.bits(1, 1); // The final block
+    writer.bits(1, 2); // Fixed Huffman codes
+
+    // Each hash's latest position, and each position's previous one with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(data, i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, i)];
+            let mut tries = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && tries < MAX_CHAIN {
+                let longest = (data.len() - i).min(MAX_MATCH);
+                let length = (0..longest).take_while(|&k| data[candidate + k] == data[i + k]).count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                }
+                candidate = prev[candidate];
+                tries += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= best_length).unwrap();
+            let (huffman, bits) = fixed_code(257 + code as u16);
+            writer.code(huffman, bits);
+            writer.bits((best_length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+            let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= best_distance).unwrap();
+            writer.code(code as u32, 5);
+            writer.bits((best_distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+            for k in i..i + best_length {
+                insert(k, &mut head, &mut prev);
+            }
+            i += best_length;
+        } else {
+            let (huffman, bits) = fixed_code(data[i] as u16);
+            writer.code(huffman, bits);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+
+    let (huffman, bits) = fixed_code(256); // End of block
+    writer.code(huffman, bits);
+    writer.finish()
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    ((data[i] as usize) << 8 ^ (data[i + 1] as usize) << 4 ^ data[i + 2] as usize) & ((1 << HASH_BITS) - 1)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // In bits
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, ()> {
+        let byte = *self.data.get(self.position / 8).ok_or(())?;
+        let bit = (byte >> (self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, ()> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    // A canonical Huffman code, read a bit at a time and compared against the first
+    // code of each length
+    fn symbol(&mut self, huffman: &Huffman) -> Result<u16, ()> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=15 {
+            code |= self.bit()? as i32;
+            let count = huffman.counts[length] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(())
+    }
+}
+
+struct Huffman {
+    counts: [u16; 16],  // Codes of each length
+    symbols: Vec<u16>, // Ordered by code
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn fixed() -> (Self, Self) {
+        let lengths: Vec<u8> = (0..288).map(|symbol| fixed_code(symbol).1 as u8).collect();
+        (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+    }
+}
+
+// Any DEFLATE stream: stored, fixed and dynamic blocks
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, ()> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.position = reader.position.div_ceil(8) * 8;
+                let length = reader.bits(16)?;
+                if reader.bits(16)? != !length & 0xFFFF {
+                    return Err(());
+                }
+                let start = reader.position / 8;
+                let stored = data.get(start..start + length as usize).ok_or(())?;
+                out.extend_from_slice(stored);
+                reader.position += 8 * length as usize;
+            }
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(()),
+        }
+        if out.len() > MAX_INFLATED {
+            return Err(());
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic(reader: &mut BitReader) -> Result<(Huffman, Huffman), ()> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match reader.symbol(&code_lengths)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or(())?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return Err(()),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(());
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), ()> {
+    loop {
+        let symbol = reader.symbol(literals)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let code = symbol - 257;
+                let length = *LENGTH_BASE.get(code).ok_or(())? as usize + reader.bits(LENGTH_EXTRA[code] as u32)? as usize;
+                let code = reader.symbol(distances)? as usize;
+                let distance = *DISTANCE_BASE.get(code).ok_or(())? as usize + reader.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+                if distance > out.len() || out.len() + length > MAX_INFLATED {
+                    return Err(());
+                }
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Raw DEFLATE streams zlib wrote, one of each block type, and what they hold
+    const STORED: &[u8] = &[0x01, 0x08, 0x00, 0xf7, 0xff, 0x52, 0x65, 0x73, 0x6f, 0x6e, 0x61, 0x6e, 0x74];
+    const FIXED: &[u8] = &[0xab, 0x56, 0x2a, 0x4e, 0x4d, 0x4d, 0x51, 0xb2, 0x32, 0x31, 0xd2, 0x81, 0xb3, 0x6a, 0x01];
+    const DYNAMIC: &[u8] = &[
+        0x3d, 0x8b, 0xc1, 0x11, 0x00, 0x40, 0x0c, 0x01, 0x6b, 0x5d, 0xfa, 0xef, 0xe1,
+        0x38, 0x33, 0x79, 0x64, 0xc4, 0x02, 0x01, 0x92, 0x5c, 0xf9, 0xa7, 0x3d, 0xe0,
+        0xc8, 0x70, 0xf2, 0xfa, 0x15, 0x7c, 0xd4, 0x9b, 0x6e, 0x53, 0x18, 0xf2, 0x00,
+    ];
+    const SAMPLES: [(&str, &[u8], &str); 3] = [
+        ("stored", STORED, "Resonant"),
+        ("fixed", FIXED, r#"{"seed":42,"seed":42}"#),
+        ("dynamic", DYNAMIC, "abaaabbbcaaabaaababaabaaaaacbaacaaababbbaaacabaaabcaacaaababcaaabbbababaaacaabca"),
+    ];
+
+    fn stored_block(last: bool, bytes: &[u8]) -> Vec<u8> {
+        let length = bytes.len() as u16;
+        let mut block = vec![last as u8];
+        block.extend(length.to_le_bytes());
+        block.extend((!length).to_le_bytes());
+        block.extend_from_slice(bytes);
+        block
+    }
+
+    #[test]
+    fn inflates_what_zlib_deflated() {
+        for (name, stream, text) in SAMPLES {
+            assert_eq!(inflate(stream).as_deref(), Ok(text.as_bytes()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn truncated_streams_are_refused() {
+        for (name, stream, _) in SAMPLES {
+            if let Some(length) = (0..stream.len()).find(|&length| inflate(&stream[..length]).is_ok()) {
+                panic!("{}: inflated when cut to {} of {} bytes", name, length, stream.len());
+            }
+        }
+    }
+
+    #[test]
+    fn stored_lengths_must_agree() {
+        let mut stream = STORED.to_vec();
+        stream[3] ^= 1;
+        assert_eq!(inflate(&stream), Err(()));
+    }
+
+    #[test]
+    fn output_past_the_limit_is_refused() {
+        let half = vec![0; MAX_INFLATED / 2 + 1];
+        assert_eq!(inflate(&stored_block(true, &half)).map(|out| out.len()), Ok(half.len()));
+        let mut stream = stored_block(false, &half);
+        stream.extend(stored_block(true, &half));
+        assert_eq!(inflate(&stream), Err(()));
+    }
+}
@@ -0,0 +1,133 @@
+use nalgebra::{Vector3, Vector4};
+use serde::Serialize;
+use crate::fractals::*;
+use crate::math;
+use crate::palette::Palette;
+
+// Half-size of the cube the analysis covers; big enough to hold the widest Mandelbox
+const ANALYSIS_EXTENT: f32 = 4.0;
+
+// Octree depth; the finest boxes are 2 * ANALYSIS_EXTENT / 2^MAX_LEVEL across
+const MAX_LEVEL: u32 = 6;
+
+// Stop refining once a level has this many surface boxes; dust-like formulas fill
+// whole levels and would otherwise cost hundreds of milliseconds
+const MAX_BOXES: usize = 16_384;
+
+// Levels below this are too coarse for the box count to follow a power law
+const MIN_FIT_LEVEL: u32 = 2;
+
+// A point counts as inside the set below this distance, as for a raymarch hit. The
+// formulas' estimates only approach zero inside rather than going negative, so boxes
+// buried in the solid would otherwise count as surface.
+const INSIDE_EPSILON: f32 = 0.002;
+
+// Nudges the grid off the origin so box centers and corners miss the lattice points of
+// the periodic formulas (Apollonian), where the estimate degenerates to zero
+const GRID_OFFSET: f32 = 0.37;
+
+const SQRT_3: f32 = 1.732_050_8;
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct ComplexityReport {
+    pub box_dimension: f32, // Box-counting estimate: ~2 for smooth surfaces, toward 3 for dust-like detail
+    pub surface_area: f32,  // Finest-level surface boxes times their face area
+    pub surface_boxes: u32,
+    pub score: f32,         // What battles compare; see score()
+}
+
+// Measures a fractal's geometric richness from its distance field at time 0. Boxes are
+// refined only where the surface passes through them (|DE| within the box's half-diagonal
+// and not wholly inside), and the dimension is the slope of log(box count) against
+// log(1 / box size).
+pub fn analyze(params: &FractalParams) -> ComplexityReport {
+    let fractal = create_fractal_from_params(params, 0.0, ColoringMode::Standard, Palette::from_seed(0));
+
+    // The plane fractals ignore z, so only the z = 0 layer carries information
+    let planar = params.is_planar();
+
+    let mut boxes = vec![Vector3::new(GRID_OFFSET, GRID_OFFSET, if planar { 0.0 } else { GRID_OFFSET })];
+    let mut half = ANALYSIS_EXTENT;
+    let mut counts = Vec::with_capacity(MAX_LEVEL as usize);
+
+    for _ in 1..=MAX_LEVEL {
+        half *= 0.5;
+        boxes = boxes.iter()
+            .flat_map(|center| children(center, half, planar))
+            .filter(|center| crosses_surface(&*fractal, center, half, planar))
+            .collect();
+        counts.push(boxes.len() as u32);
+
+        if boxes.is_empty() || boxes.len() > MAX_BOXES {
+            break;
+        }
+    }
+
+    let surface_boxes = counts.last().copied().unwrap_or(0);
+    let face = 2.0 * half;
+    let surface_area = surface_boxes as f32 * face * face;
+    let box_dimension = fit_dimension(&counts);
+
+    ComplexityReport {
+        box_dimension,
+        surface_area,
+        surface_boxes,
+        score: score(box_dimension, surface_area),
+    }
+}
+
+// Dimension dominates; surface area breaks ties between similarly rough shapes
+fn score(box_dimension: f32, surface_area: f32) -> f32 {
+    box_dimension + 0.5 * math::ln(1.0 + surface_area)
+}
+
+fn children(center: &Vector3<f32>, half: f32, planar: bool) -> Vec<Vector3<f32>> {
+    let mut out = Vec::with_capacity(8);
+    for corner in 0..8 {
+        if planar && corner & 4 != 0 {
+            continue;
+        }
+        let sign = |bit: i32| if corner & bit == 0 { -half } else { half };
+        let z = if planar { 0.0 } else { sign(4) };
+        out.push(center + Vector3::new(sign(1), sign(2), z));
+    }
+    out
+}
+
+fn crosses_surface(fractal: &dyn FractalGenerator, center: &Vector3<f32>, half: f32, planar: bool) -> bool {
+    let distance = |p: &Vector3<f32>| fractal.distance_estimator(&Vector4::new(p.x, p.y, p.z, 0.0));
+    let reach = if planar { half * std::f32::consts::SQRT_2 } else { half * SQRT_3 };
+
+    let center_distance = distance(center);
+    if center_distance.abs() > reach {
+        return false;
+    }
+    if center_distance > INSIDE_EPSILON {
+        return true;
+    }
+
+    // Center is inside: only a surface box if some corner pokes out
+    children(center, half, planar).iter().any(|corner| distance(corner) > INSIDE_EPSILON)
+}
+
+// Least-squares slope of ln(count) over level * ln(2), skipping the coarse levels
+fn fit_dimension(counts: &[u32]) -> f32 {
+    let points: Vec<(f32, f32)> = counts.iter()
+        .enumerate()
+        .map(|(i, &count)| (i as u32 + 1, count))
+        .filter(|&(level, count)| level >= MIN_FIT_LEVEL && count > 0)
+        .map(|(level, count)| (level as f32 * std::f32::consts::LN_2, math::ln(count as f32)))
+        .collect();
+
+    if points.len() < 2 {
+        return 0.0;
+    }
+
+    let n = points.len() as f32;
+    let mean_x = points.iter().map(|p| p.0).sum::<f32>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f32>() / n;
+    let covariance: f32 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let variance: f32 = points.iter().map(|p| (p.0 - mean_x) * (p.0 - mean_x)).sum();
+
+    (covariance / variance).max(0.0)
+}
@@ -0,0 +1,363 @@
+use nalgebra::{Vector2, Vector3, Vector4};
+use crate::fractals::*;
+use crate::palette::Palette;
+use crate::registry::FractalType;
+
+// Relative disagreement allowed between the two paths; both are f32, but the shader
+// orbits are written with vector ops that round slightly differently
+const TOLERANCE: f32 = 1e-3;
+
+// Sampling box around the origin; every formula lives inside it
+const SAMPLE_EXTENT: f32 = 1.5;
+
+// Points compared per formula
+const SAMPLES: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct ConformanceReport {
+    pub fractal_type: FractalType,
+    pub params: FractalParams,
+    pub max_error: f32, // Relative, see TOLERANCE
+    pub worst_point: [f32; 3],
+    pub passed: bool,
+}
+
+// Compares FractalGenerator::distance_estimator with shader_distance() at `samples`
+// quasi-random points (w = 0, as in the shader)
+pub fn check(params: &FractalParams, time: f32, samples: usize) -> ConformanceReport {
+    let fractal = create_fractal_from_params(params, time, ColoringMode::Standard, Palette::from_seed(0));
+    let uniforms = params.shader_params();
+
+    let mut max_error = 0.0f32;
+    let mut worst_point = [0.0; 3];
+
+    for i in 0..samples {
+        let p = halton_point(i as u32 + 1) * SAMPLE_EXTENT;
+        let cpu = fractal.distance_estimator(&Vector4::new(p.x, p.y, p.z, 0.0));
+        let gpu = shader_distance(params.shader_id(), &uniforms, &p, time);
+
+        let error = if !cpu.is_finite() || !gpu.is_finite() {
+            // Both paths blow up at the same singular points (e.g. r = 0)
+            if cpu.is_nan() == gpu.is_nan() && cpu.is_infinite() == gpu.is_infinite() { 0.0 } else { f32::MAX }
+        } else {
+            (cpu - gpu).abs() / cpu.abs().max(1.0)
+        };
+
+        if error > max_error {
+            max_error = error;
+            worst_point = [p.x, p.y, p.z];
+        }
+    }
+
+    ConformanceReport {
+        fractal_type: params.fractal_type(),
+        params: *params,
+        max_error,
+        worst_point,
+        passed: max_error <= TOLERANCE,
+    }
+}
+
+// Every formula, at a spread of seeds and times; a failure means fractals.rs and
+// FRAGMENT_SHADER have drifted apart
+const SEEDS: [u32; 4] = [1, 7, 20_240_611, 987_654_321];
+
+fn assert_conforms(params: &FractalParams, time: f32) {
+    let report = check(params, time, SAMPLES);
+    assert!(report.passed, "{:?} ({:?}) off by {} at {:?}",
+            report.fractal_type, report.params, report.max_error, report.worst_point);
+}
+
+#[test]
+fn daily_formulas_match_the_shader() {
+    for seed in SEEDS {
+        for offset in 0..7 {
+            let params = FractalParams::from_seed(seed.wrapping_add(offset));
+            if !params.is_line_geometry() {
+                assert_conforms(&params, (seed % 97) as f32);
+            }
+        }
+    }
+}
+
+#[test]
+fn hybrids_match_the_shader() {
+    for seed in SEEDS {
+        assert_conforms(&FractalParams::Hybrid(HybridSpec::from_seed(seed)), 0.0);
+    }
+}
+
+#[test]
+fn plane_orbits_match_the_shader() {
+    for seed in SEEDS {
+        assert_conforms(&FractalParams::plane_from_seed(seed), 0.0);
+    }
+}
+
+// 3D Halton sequence in [-1, 1]^3
+fn halton_point(index: u32) -> Vector3<f32> {
+    let halton = |mut i: u32, base: u32| {
+        let mut f = 1.0f32;
+        let mut r = 0.0f32;
+        while i > 0 {
+            f /= base as f32;
+            r += f * (i % base) as f32;
+            i /= base;
+        }
+        r * 2.0 - 1.0
+    };
+
+    Vector3::new(halton(index, 2), halton(index, 3), halton(index, 5))
+}
+
+// Line-for-line port of the to_glsl() formulas and their formula_distance() dispatch, without zoom,
+// slice or orbit traps. `u` is u_fractal_params flattened.
+pub fn shader_distance(fractal_type: i32, u: &[f32; 8], pos: &Vector3<f32>, time: f32) -> f32 {
+    match fractal_type {
+        0 => mandelbulb(u, pos, time),
+        1 => julia4d(u, pos, time),
+        2 => kaleido_ifs(u, pos, time),
+        3 => mandelbox(u, pos, time),
+        4 => menger(u, pos),
+        5 => apollonian(u, pos),
+        7 => plane_orbit(u, pos),
+        _ => hybrid(u, pos),
+    }
+}
+
+fn mandelbulb(u: &[f32; 8], pos: &Vector3<f32>, time: f32) -> f32 {
+    let mut z = *pos;
+    let mut dr = 1.0f32;
+    let mut r = 0.0f32;
+    let power = u[0] + (time * 0.1).sin() * 2.0;
+    let iterations = u[2] as i32;
+
+    for i in 0..16 {
+        if i >= iterations { break; }
+
+        r = z.norm();
+        if r > 2.0 { break; }
+
+        let theta = (z.z / r).acos() + time * 0.05;
+        let phi = z.y.atan2(z.x) + time * 0.03;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        let zr = r.powf(power);
+        z = zr * Vector3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos()
+        ) + pos;
+    }
+
+    0.5 * r.ln() * r / dr
+}
+
+fn julia4d(u: &[f32; 8], pos: &Vector3<f32>, time: f32) -> f32 {
+    let mut z = Vector4::new(pos.x, pos.y, pos.z, 0.0);
+    let mut dz = Vector4::new(1.0, 0.0, 0.0, 0.0);
+    let c = Vector4::new(u[0], u[1], u[2], u[3]) + Vector4::new(
+        (time * 0.1).sin() * 0.3,
+        (time * 0.13).cos() * 0.2,
+        (time * 0.07).sin() * 0.25,
+        (time * 0.11).cos() * 0.15
+    );
+    let iterations = u[4] as i32;
+
+    for i in 0..16 {
+        if i >= iterations { break; }
+        if z.norm() > 4.0 { break; }
+
+        dz = 2.0 * Vector4::new(
+            z.x * dz.x - z.y * dz.y - z.z * dz.z - z.w * dz.w,
+            z.x * dz.y + z.y * dz.x,
+            z.x * dz.z + z.z * dz.x,
+            z.x * dz.w + z.w * dz.x
+        ) + Vector4::new(1.0, 0.0, 0.0, 0.0);
+        z = Vector4::new(
+            z.x * z.x - z.y * z.y - z.z * z.z - z.w * z.w,
+            2.0 * z.x * z.y,
+            2.0 * z.x * z.z,
+            2.0 * z.x * z.w
+        ) + c;
+    }
+
+    let r = z.norm();
+    0.5 * r.ln() * r / dz.norm()
+}
+
+fn kaleido_ifs(u: &[f32; 8], pos: &Vector3<f32>, time: f32) -> f32 {
+    let mut p = *pos;
+    let mut scale = 1.0f32;
+    let folds = u[2] as i32;
+
+    for i in 0..16 {
+        if i >= folds { break; }
+
+        let angle = time * 0.1 + i as f32 * 0.5;
+        let n = Vector3::new(angle.cos(), angle.sin(), (angle * 1.3).sin()).normalize();
+
+        let d = p.dot(&n);
+        if d < 0.0 { p -= 2.0 * d * n; }
+
+        p = p.map(|v| v.max(-1.0).min(1.0) * 2.0 - v);
+
+        let r2 = p.norm_squared();
+        if r2 < 0.25 {
+            p *= 4.0;
+            scale *= 4.0;
+        } else if r2 < 1.0 {
+            p /= r2;
+            scale /= r2;
+        }
+
+        let s = u[0] + (time * 0.05 + i as f32 * 0.1).sin() * 0.5;
+        p = p * s + Vector3::new((time * 0.07).sin() * 0.1, (time * 0.11).cos() * 0.1, 0.0);
+        scale *= s;
+    }
+
+    (p.norm() - 0.5) / scale.abs()
+}
+
+fn mandelbox(u: &[f32; 8], pos: &Vector3<f32>, time: f32) -> f32 {
+    let mut z = *pos;
+    let mut dr = 1.0f32;
+    let scale = u[0] + (time * 0.05).sin() * 0.1;
+    let min_r2 = u[1] * u[1];
+    let iterations = u[2] as i32;
+
+    for i in 0..16 {
+        if i >= iterations { break; }
+
+        z = z.map(|v| v.max(-1.0).min(1.0) * 2.0 - v);
+
+        let r2 = z.norm_squared();
+        if r2 < min_r2 {
+            z /= min_r2;
+            dr /= min_r2;
+        } else if r2 < 1.0 {
+            z /= r2;
+            dr /= r2;
+        }
+
+        z = z * scale + pos;
+        dr = dr * scale.abs() + 1.0;
+    }
+
+    z.norm() / dr.abs()
+}
+
+fn menger(u: &[f32; 8], pos: &Vector3<f32>) -> f32 {
+    let mut z = *pos;
+    let scale = u[0];
+    let shift = scale - 1.0;
+    let folds = u[2] as i32;
+    let mut shrink = 1.0f32;
+
+    for i in 0..8 {
+        if i >= folds { break; }
+
+        z = z.abs();
+        if z.x < z.y { z.swap_rows(0, 1); }
+        if z.x < z.z { z.swap_rows(0, 2); }
+        if z.y < z.z { z.swap_rows(1, 2); }
+
+        z = z * scale - Vector3::repeat(shift);
+        if z.z < -0.5 * shift { z.z += shift; }
+
+        shrink /= scale;
+    }
+
+    (z.abs() - Vector3::repeat(1.0)).map(|v| v.max(0.0)).norm() * shrink
+}
+
+fn apollonian(u: &[f32; 8], pos: &Vector3<f32>) -> f32 {
+    let mut p = *pos;
+    let mut scale = 1.0f32;
+    let iterations = u[2] as i32;
+
+    for i in 0..12 {
+        if i >= iterations { break; }
+
+        p = p.map(|v| {
+            let half = 0.5 * v + 0.5;
+            -1.0 + 2.0 * (half - half.floor())
+        });
+        let k = u[0] / p.norm_squared().max(0.000001);
+        p *= k;
+        scale *= k;
+    }
+
+    0.25 * p.y.abs() / scale
+}
+
+// plane_orbit() in FRAGMENT_SHADER_2D, distance part only
+fn plane_orbit(u: &[f32; 8], pos: &Vector3<f32>) -> f32 {
+    let p = Vector2::new(pos.x, pos.y);
+    let julia = u[3] > 0.5;
+    let c = if julia { Vector2::new(u[0], u[1]) } else { p };
+    let mut z = if julia { p } else { Vector2::zeros() };
+    let mut dz = if julia { Vector2::new(1.0, 0.0) } else { Vector2::zeros() };
+    let dz_offset = if julia { Vector2::zeros() } else { Vector2::new(1.0, 0.0) };
+    let iterations = u[2] as i32;
+
+    for i in 0..256 {
+        if i >= iterations { break; }
+        if z.dot(&z) > PLANE_BAILOUT * PLANE_BAILOUT { break; }
+
+        dz = 2.0 * Vector2::new(z.x * dz.x - z.y * dz.y, z.x * dz.y + z.y * dz.x) + dz_offset;
+        z = Vector2::new(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+    }
+
+    let r = z.norm();
+    if r <= PLANE_BAILOUT {
+        return 0.0;
+    }
+    0.5 * r * r.ln() / dz.norm()
+}
+
+fn hybrid_step(formula: &[f32], z: &mut Vector3<f32>, dr: &mut f32, c: &Vector3<f32>) {
+    if formula[0] < 0.5 {
+        let power = formula[1];
+        let r = z.norm().max(0.000001);
+        let theta = (z.z / r).acos();
+        let phi = z.y.atan2(z.x);
+        *dr = r.powf(power - 1.0) * power * *dr + 1.0;
+        *z = r.powf(power) * Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) + c;
+    } else {
+        let (scale, min_radius) = (formula[1], formula[2]);
+        *z = z.map(|v| v.max(-1.0).min(1.0) * 2.0 - v);
+
+        let r2 = z.norm_squared();
+        let min_r2 = min_radius * min_radius;
+        if r2 < min_r2 {
+            *z /= min_r2;
+            *dr /= min_r2;
+        } else if r2 < 1.0 {
+            *z /= r2;
+            *dr /= r2;
+        }
+
+        *z = *z * scale + c;
+        *dr = *dr * scale.abs() + 1.0;
+    }
+}
+
+fn hybrid(u: &[f32; 8], pos: &Vector3<f32>) -> f32 {
+    let mut z = *pos;
+    let mut dr = 1.0f32;
+    let first_steps = u[3].max(1.0);
+    let cycle = first_steps + u[7].max(1.0);
+
+    for i in 0..HYBRID_ITERATIONS {
+        if z.norm_squared() > 16.0 { break; }
+
+        if (i as f32) % cycle < first_steps {
+            hybrid_step(&u[0..4], &mut z, &mut dr, pos);
+        } else {
+            hybrid_step(&u[4..8], &mut z, &mut dr, pos);
+        }
+    }
+
+    0.5 * z.norm() / dr.abs()
+}
@@ -0,0 +1,153 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::crypto;
+use crate::network;
+
+// Most friends kept; adding past it is refused rather than dropping anyone
+const MAX_CONTACTS: usize = 500;
+
+// Longest display name, in characters
+const MAX_NAME_CHARS: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Contact {
+    pub id: String,   // Their user id, which their messages are sent as
+    pub name: String, // As the user knows them; the start of the id until they're named
+    #[serde(default)]
+    pub share_key: Option<String>, // Their public key, once a share link has carried one
+    pub added_at: u64,  // ms since the epoch
+    #[serde(default)]
+    pub last_seen: u64, // When a message from them last arrived; 0 for not yet
+    #[serde(default)]
+    pub updated_at: u64, // When the name or key last changed, to merge by
+}
+
+// The user's friends, whom messages can be addressed to rather than sent to everyone
+// connected; kept with the rest of the state, and synced and exported with it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Contacts {
+    pub friends: Vec<Contact>,
+}
+
+impl Contacts {
+    pub fn get(&self, id: &str) -> Option<&Contact> {
+        self.friends.iter().find(|friend| friend.id == id)
+    }
+
+    // Adds a friend, or for one already here takes the name and key if given
+    pub fn add(&mut self, id: &str, name: Option<&str>, share_key: Option<String>, now: u64) -> Result<&Contact, JsValue> {
+        let name = name.map(clean_name).transpose()?;
+        if id.is_empty() || id.len() > 64 || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(JsValue::from_str("Not a user id"));
+        }
+
+        let index = match self.friends.iter().position(|friend| friend.id == id) {
+            Some(index) => {
+                let friend = &mut self.friends[index];
+                if let Some(name) = name {
+                    friend.name = name;
+                    friend.updated_at = now;
+                }
+                if share_key.is_some() {
+                    friend.share_key = share_key;
+                    friend.updated_at = now;
+                }
+                index
+            }
+            None => {
+                if self.friends.len() >= MAX_CONTACTS {
+                    return Err(JsValue::from_str(&format!("Already {} friends", MAX_CONTACTS)));
+                }
+                self.friends.push(Contact {
+                    id: id.to_string(),
+                    name: name.unwrap_or_else(|| id.chars().take(8).collect()),
+                    share_key,
+                    added_at: now,
+                    last_seen: 0,
+                    updated_at: now,
+                });
+                self.friends.len() - 1
+            }
+        };
+        Ok(&self.friends[index])
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.friends.len();
+        self.friends.retain(|friend| friend.id != id);
+        self.friends.len() != before
+    }
+
+    pub fn rename(&mut self, id: &str, name: &str, now: u64) -> Result<(), JsValue> {
+        let name = clean_name(name)?;
+        let friend = self.friends.iter_mut().find(|friend| friend.id == id)
+            .ok_or_else(|| JsValue::from_str("Not a friend"))?;
+        friend.name = name;
+        friend.updated_at = now;
+        Ok(())
+    }
+
+    // Notes a message from `id` at `at`; false if they aren't a friend
+    pub fn seen(&mut self, id: &str, at: u64) -> bool {
+        match self.friends.iter_mut().find(|friend| friend.id == id) {
+            Some(friend) => {
+                friend.last_seen = friend.last_seen.max(at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Every friend either side has; for one on both, the name and key changed last and
+    // the latest sighting. A friend removed on one side comes back from the other
+    pub fn merge(&mut self, other: Contacts) {
+        for theirs in other.friends {
+            let count = self.friends.len();
+            match self.friends.iter_mut().find(|friend| friend.id == theirs.id) {
+                Some(ours) => {
+                    let last_seen = ours.last_seen.max(theirs.last_seen);
+                    let added_at = ours.added_at.min(theirs.added_at);
+                    if theirs.updated_at > ours.updated_at {
+                        *ours = theirs;
+                    }
+                    ours.last_seen = last_seen;
+                    ours.added_at = added_at;
+                }
+                None if count < MAX_CONTACTS => self.friends.push(theirs),
+                None => {}
+            }
+        }
+    }
+}
+
+fn clean_name(name: &str) -> Result<String, JsValue> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(JsValue::from_str("A friend needs a name"));
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(JsValue::from_str(&format!("Names are at most {} characters", MAX_NAME_CHARS)));
+    }
+    Ok(name.to_string())
+}
+
+// The sender of a share link, as get_share_url (`user`) or NetworkManager::create_share_url
+// (`from`) names them, and their public key if it carries one (`key`), which messages to
+// them are sealed with
+pub fn from_share_url(url: &str) -> Result<(String, Option<String>), JsValue> {
+    let mut id = None;
+    let mut key = None;
+    for (name, value) in network::query_params(url)? {
+        match name.as_str() {
+            "user" | "from" if !value.is_empty() => id = Some(value),
+            "key" if !value.is_empty() => {
+                crypto::parse_public_key(&value).ok_or_else(|| JsValue::from_str("The link's key isn't a public key"))?;
+                key = Some(value);
+            }
+            _ => {}
+        }
+    }
+    let id = id.ok_or_else(|| JsValue::from_str("The link doesn't say who shared it"))?;
+    Ok((id, key))
+}
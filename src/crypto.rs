@@ -0,0 +1,245 @@
+// End-to-end encryption of messages between friends, so the relay passes them on without
+// reading them. Each user has an X25519 key pair, kept with their user id; the public half
+// travels in share links (`key`) and is kept with the friend. A message is sealed with a
+// fresh key under XChaCha20-Poly1305, and that key sealed in turn for each recipient under
+// one derived (HKDF-SHA256) from the sender's and recipient's keys, so only the two of them
+// can open it. Each recipient's sealed key is bound to the rest of the envelope (see
+// Envelope::binding): every recipient learns the message key, so without that one of them
+// could seal a message of their own under it and pass it to the others as the sender's.
+// With it, a recipient knows the message came from whoever holds the sender's key
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use x25519_dalek::StaticSecret;
+pub use x25519_dalek::PublicKey;
+use crate::codec;
+
+// Bound into every derived key, so keys derived for anything else never collide with these
+const KEY_INFO: &[u8] = b"resonant message key 2"; // 1 sealed keys unbound
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+
+// A sealed message as the relay sees it: who it's from and for, and nothing more
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Envelope {
+    pub sender_id: String,
+    pub sender_key: [u8; KEY_LENGTH], // The sender's public key
+    pub recipients: Vec<String>,      // The relay routes by these, as by a message's
+    pub keys: Vec<Vec<u8>>,           // The message's key sealed for each recipient in turn: nonce, then ciphertext
+    pub nonce: [u8; NONCE_LENGTH],
+    pub ciphertext: Vec<u8>, // The message, as wire::encode_message gives it
+}
+
+impl Envelope {
+    // What each sealed key is bound to: everything in the envelope but the sealed keys,
+    // each field prefixed with its length so no two envelopes run together alike
+    fn binding(&self) -> Vec<u8> {
+        let mut hash = Sha256::new();
+        let mut field = |bytes: &[u8]| {
+            hash.update((bytes.len() as u64).to_le_bytes());
+            hash.update(bytes);
+        };
+        field(self.sender_id.as_bytes());
+        field(&self.sender_key);
+        field(&(self.recipients.len() as u64).to_le_bytes());
+        self.recipients.iter().for_each(|recipient| field(recipient.as_bytes()));
+        field(&self.nonce);
+        field(&self.ciphertext);
+        hash.finalize().to_vec()
+    }
+}
+
+// The user's key pair
+#[derive(Clone)]
+pub struct Identity {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl Identity {
+    pub fn generate() -> Result<Self, JsValue> {
+        let mut secret = [0u8; KEY_LENGTH];
+        fill_random(&mut secret)?;
+        Ok(Self::from_secret(secret))
+    }
+
+    fn from_secret(secret: [u8; KEY_LENGTH]) -> Self {
+        let secret = StaticSecret::from(secret);
+        let public = PublicKey::from(&secret);
+        Identity { secret, public }
+    }
+
+    // The secret key, as it's saved; keep it to the user's own storage and exports
+    pub fn encode(&self) -> String {
+        codec::base64_encode(self.secret.as_bytes())
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self, JsValue> {
+        let secret = codec::base64_decode(encoded).ok()
+            .and_then(|bytes| <[u8; KEY_LENGTH]>::try_from(bytes).ok())
+            .ok_or_else(|| JsValue::from_str("Not a secret key"))?;
+        Ok(Self::from_secret(secret))
+    }
+
+    // The public key, as share links carry it: 43 characters of URL-safe base64
+    pub fn public_key(&self) -> String {
+        codec::base64_encode(self.public.as_bytes())
+    }
+
+    // Seals `message` (wire::encode_message's bytes) from `sender_id` for each recipient,
+    // with their public key
+    pub fn seal(&self, sender_id: &str, recipients: &[(String, PublicKey)], message: &[u8]) -> Result<Envelope, JsValue> {
+        let mut random = vec![0u8; KEY_LENGTH + NONCE_LENGTH * (recipients.len() + 1)];
+        fill_random(&mut random)?;
+        let (message_key, nonces) = random.split_at(KEY_LENGTH);
+        let nonces: Vec<[u8; NONCE_LENGTH]> = nonces.chunks(NONCE_LENGTH).map(|nonce| nonce.try_into().unwrap()).collect();
+        Ok(self.seal_with(sender_id, recipients, message, message_key.try_into().unwrap(), &nonces))
+    }
+
+    // seal, with the message key and nonces (the message's, then one per recipient) given
+    fn seal_with(&self, sender_id: &str, recipients: &[(String, PublicKey)], message: &[u8],
+                 message_key: [u8; KEY_LENGTH], nonces: &[[u8; NONCE_LENGTH]]) -> Envelope {
+        let cipher = XChaCha20Poly1305::new(&message_key.into());
+        let ciphertext = cipher.encrypt(XNonce::from_slice(&nonces[0]), message).unwrap();
+        let mut envelope = Envelope {
+            sender_id: sender_id.to_string(),
+            sender_key: self.public.to_bytes(),
+            recipients: recipients.iter().map(|(id, _)| id.clone()).collect(),
+            keys: Vec::new(),
+            nonce: nonces[0],
+            ciphertext,
+        };
+        let binding = envelope.binding();
+        envelope.keys = recipients.iter().zip(&nonces[1..]).map(|((_, key), nonce)| {
+            let payload = Payload { msg: message_key.as_slice(), aad: &binding };
+            let sealed = self.key_cipher(&self.public, key).encrypt(XNonce::from_slice(nonce), payload).unwrap();
+            [nonce.as_slice(), &sealed].concat()
+        }).collect();
+        envelope
+    }
+
+    // The message sealed in `envelope` for `own_id`, if it was sealed with `sender`'s key
+    pub fn open(&self, own_id: &str, envelope: &Envelope, sender: &PublicKey) -> Result<Vec<u8>, String> {
+        if envelope.sender_key != sender.to_bytes() {
+            return Err("Sealed with a key other than the sender's".to_string());
+        }
+        let index = envelope.recipients.iter().position(|recipient| recipient == own_id)
+            .ok_or("Not sealed for this user")?;
+        let sealed = envelope.keys.get(index).filter(|sealed| sealed.len() > NONCE_LENGTH).ok_or("No key sealed for this user")?;
+        let (nonce, sealed) = sealed.split_at(NONCE_LENGTH);
+        let payload = Payload { msg: sealed, aad: &envelope.binding() };
+        let message_key = self.key_cipher(sender, &self.public).decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| "The message's key doesn't open".to_string())?;
+        let message_key = <[u8; KEY_LENGTH]>::try_from(message_key).map_err(|_| "The message's key is the wrong length")?;
+        XChaCha20Poly1305::new(&message_key.into())
+            .decrypt(XNonce::from_slice(&envelope.nonce), envelope.ciphertext.as_slice())
+            .map_err(|_| "The message doesn't open".to_string())
+    }
+
+    // The cipher sealing message keys from `sender` to `recipient`, one of them this
+    // identity; the same at either end
+    fn key_cipher(&self, sender: &PublicKey, recipient: &PublicKey) -> XChaCha20Poly1305 {
+        let other = if sender == &self.public { recipient } else { sender };
+        let shared = self.secret.diffie_hellman(other);
+        let info = [KEY_INFO, sender.as_bytes(), recipient.as_bytes()].concat();
+        let mut key = [0u8; KEY_LENGTH];
+        Hkdf::<Sha256>::new(None, shared.as_bytes()).expand(&info, &mut key).unwrap();
+        XChaCha20Poly1305::new(&key.into())
+    }
+}
+
+// A public key as share links carry it; None for anything else, or a key of low order,
+// which would make every shared secret with it the same
+pub fn parse_public_key(encoded: &str) -> Option<PublicKey> {
+    let bytes = <[u8; KEY_LENGTH]>::try_from(codec::base64_decode(encoded).ok()?).ok()?;
+    let key = PublicKey::from(bytes);
+    StaticSecret::from([1u8; KEY_LENGTH]).diffie_hellman(&key).was_contributory().then_some(key)
+}
+
+// From crypto.getRandomValues; unlike user ids, keys never fall back to Math.random
+pub(crate) fn fill_random(bytes: &mut [u8]) -> Result<(), JsValue> {
+    let crypto = web_sys::window().ok_or("No window available")?.crypto()?;
+    crypto.get_random_values_with_u8_array(bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"a message for friends";
+
+    // A sender and two friends with fixed keys, and what the sender sealed for the friends
+    fn sealed() -> (Identity, [Identity; 2], Envelope) {
+        let sender = Identity::from_secret([7; KEY_LENGTH]);
+        let friends = [Identity::from_secret([11; KEY_LENGTH]), Identity::from_secret([13; KEY_LENGTH])];
+        let envelope = sender.seal_with("sender", &recipients(&friends), MESSAGE, [3; KEY_LENGTH], &nonces(3));
+        (sender, friends, envelope)
+    }
+
+    fn recipients(friends: &[Identity]) -> Vec<(String, PublicKey)> {
+        friends.iter().enumerate().map(|(i, friend)| (format!("friend_{}", i), friend.public)).collect()
+    }
+
+    fn nonces(count: u8) -> Vec<[u8; NONCE_LENGTH]> {
+        (0..count).map(|i| [i; NONCE_LENGTH]).collect()
+    }
+
+    #[test]
+    fn opens_for_each_recipient() {
+        let (sender, friends, envelope) = sealed();
+        for (i, friend) in friends.iter().enumerate() {
+            assert_eq!(friend.open(&format!("friend_{}", i), &envelope, &sender.public).as_deref(), Ok(MESSAGE));
+        }
+        assert!(!envelope.ciphertext.windows(MESSAGE.len()).any(|window| window == MESSAGE));
+    }
+
+    #[test]
+    fn refused_altered_or_misaddressed() {
+        let (sender, friends, envelope) = sealed();
+        let stranger = Identity::from_secret([17; KEY_LENGTH]);
+        let mut altered = envelope.clone();
+        *altered.ciphertext.last_mut().unwrap() ^= 1;
+        let mut reordered = envelope.clone();
+        reordered.recipients.reverse();
+        let mut renamed = envelope.clone();
+        renamed.sender_id = "someone_else".to_string();
+
+        assert!(friends[0].open("friend_0", &altered, &sender.public).is_err());
+        assert!(friends[0].open("friend_0", &reordered, &sender.public).is_err());
+        assert!(friends[0].open("friend_0", &renamed, &sender.public).is_err());
+        assert!(friends[0].open("friend_0", &envelope, &stranger.public).is_err());
+        assert!(stranger.open("friend_0", &envelope, &sender.public).is_err());
+    }
+
+    // A recipient knows the message key, and so can seal anything under it; the other
+    // recipient's sealed key, bound to the envelope it came in, doesn't open with it
+    #[test]
+    fn co_recipient_cannot_forge_the_sender() {
+        let (sender, friends, envelope) = sealed();
+        let sealed_key = &envelope.keys[1];
+        let (nonce, sealed_key) = sealed_key.split_at(NONCE_LENGTH);
+        let payload = Payload { msg: sealed_key, aad: &envelope.binding() };
+        let message_key = friends[1].key_cipher(&sender.public, &friends[1].public)
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .unwrap();
+        let forged_message = b"a message the sender never sent".as_slice();
+        let mut forged = envelope.clone();
+        forged.ciphertext = XChaCha20Poly1305::new_from_slice(&message_key).unwrap()
+            .encrypt(XNonce::from_slice(&forged.nonce), forged_message)
+            .unwrap();
+        assert!(friends[0].open("friend_0", &forged, &sender.public).is_err());
+    }
+
+    #[test]
+    fn keys_decode_as_encoded() {
+        let (sender, _, _) = sealed();
+        assert!(Identity::decode(&sender.encode()).is_ok_and(|decoded| decoded.public == sender.public));
+        assert_eq!(parse_public_key(&sender.public_key()), Some(sender.public));
+        assert_eq!(parse_public_key(&codec::base64_encode(&[0; KEY_LENGTH])), None);
+    }
+}
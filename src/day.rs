@@ -0,0 +1,139 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+
+// How often, in ms, check_rollover actually looks at the clock
+pub const ROLLOVER_CHECK_INTERVAL: f64 = 1000.0;
+
+// Where one day ends and the next begins. Local midnight follows the user across
+// timezones (and DST, which moves the clock but never the date); UTC midnight gives
+// everyone the same day at the same moment, wherever they are
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DayBoundary {
+    #[default]
+    Local,
+    Utc,
+}
+
+impl DayBoundary {
+    pub fn parse(name: &str) -> Result<Self, JsValue> {
+        match name {
+            "local" => Ok(DayBoundary::Local),
+            "utc" => Ok(DayBoundary::Utc),
+            _ => Err(JsValue::from_str(&format!("Unknown day boundary: {}", name))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DayBoundary::Local => "local",
+            DayBoundary::Utc => "utc",
+        }
+    }
+
+    // The date now, YYYY-MM-DD. The browser reads the timezone afresh each time, so a
+    // device that has travelled gets its new local date
+    pub fn today(self) -> String {
+        let now = js_sys::Date::new_0();
+        let (year, month, day) = match self {
+            DayBoundary::Local => (now.get_full_year(), now.get_month(), now.get_date()),
+            DayBoundary::Utc => (now.get_utc_full_year(), now.get_utc_month(), now.get_utc_date()),
+        };
+        format!("{}-{:02}-{:02}", year, month + 1, day)
+    }
+
+    // Minutes since the day began by this boundary's clock, with the seconds as a fraction
+    pub fn minute_of_day(self) -> f64 {
+        let now = js_sys::Date::new_0();
+        let (hours, minutes, seconds) = match self {
+            DayBoundary::Local => (now.get_hours(), now.get_minutes(), now.get_seconds()),
+            DayBoundary::Utc => (now.get_utc_hours(), now.get_utc_minutes(), now.get_utc_seconds()),
+        };
+        hours as f64 * 60.0 + minutes as f64 + seconds as f64 / 60.0
+    }
+}
+
+fn parse(date: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = date.splitn(3, '-').map(|part| part.parse().ok());
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+// The number a day's seed is hashed from. The month counts from 0, as Date.getMonth()
+// does, which every seed so far was made with
+pub fn day_code(date: &str) -> u32 {
+    let (year, month, day) = parse(date).unwrap_or((0, 1, 0));
+    year * 10000 + month.saturating_sub(1) * 100 + day
+}
+
+// The date before `date`, both YYYY-MM-DD; in whole calendar days, so the hour lost or
+// gained to DST can't skip or repeat one. Empty, as for a date that isn't one, before
+// the first day of year 0
+pub fn previous(date: &str) -> String {
+    let Some((mut year, mut month, mut day)) = parse(date) else {
+        return String::new();
+    };
+    if day > 1 {
+        day -= 1;
+    } else {
+        if month > 1 {
+            month -= 1;
+        } else {
+            let Some(before) = year.checked_sub(1) else {
+                return String::new();
+            };
+            year = before;
+            month = 12;
+        }
+        day = days_in_month(year, month);
+    }
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+// The date after `date`, both YYYY-MM-DD
+pub fn next(date: &str) -> String {
+    let Some((mut year, mut month, mut day)) = parse(date) else {
+        return String::new();
+    };
+    if day < days_in_month(year, month) {
+        day += 1;
+    } else {
+        day = 1;
+        if month < 12 {
+            month += 1;
+        } else {
+            year += 1;
+            month = 1;
+        }
+    }
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn previous_and_next_cross_months_and_years() {
+        assert_eq!(previous("2024-03-01"), "2024-02-29");
+        assert_eq!(previous("2023-03-01"), "2023-02-28");
+        assert_eq!(previous("2024-01-01"), "2023-12-31");
+        assert_eq!(next("2024-02-29"), "2024-03-01");
+        assert_eq!(next("2023-12-31"), "2024-01-01");
+    }
+
+    #[test]
+    fn previous_stops_at_year_zero() {
+        assert_eq!(previous("0-01-01"), "");
+        assert_eq!(previous("0-01-02"), "0-01-01");
+        assert_eq!(previous("not a date"), "");
+    }
+}
@@ -0,0 +1,81 @@
+use nalgebra::Vector3;
+
+// Speed of sound in view units a second, far below the real thing so that spinning the
+// fractal by hand bends the pitch enough to hear
+const SOUND_SPEED: f32 = 30.0;
+
+// Furthest a voice is bent, either way, at depth 1
+const MAX_SHIFT_CENTS: f32 = 50.0;
+
+// The low-pass opens by up to this many octaves as the voices rush past, half of it at
+// WHOOSH_SPEED view units a second
+const MAX_WHOOSH_OCTAVES: f32 = 1.5;
+const WHOOSH_SPEED: f32 = 10.0;
+
+// A gap between updates longer than this, in seconds, is a stall rather than motion
+const MAX_GAP: f64 = 0.25;
+
+pub const DEFAULT_DOPPLER_DEPTH: f32 = 1.0;
+
+// How the voices are moving at one update: each slot's pitch shift and the filter's
+// opening, both in cents
+pub struct Motion {
+    pub shifts: Vec<f32>,
+    pub whoosh: f32,
+}
+
+// Simulated Doppler: each voice's sample point is followed across the view from one
+// update to the next, a voice drawing nearer the listener is bent sharp and one drawing
+// away flat, and the faster they move at all the brighter they sound, so spinning or
+// zooming the fractal whooshes
+pub struct Doppler {
+    pub depth: f32, // 0 turns it off
+    last: Vec<Option<(usize, Vector3<f32>)>>, // Each slot's voice and position at the last update
+    last_time: Option<f64>,
+}
+
+impl Doppler {
+    pub fn new() -> Self {
+        Doppler { depth: DEFAULT_DOPPLER_DEPTH, last: Vec::new(), last_time: None }
+    }
+
+    // `placed` is each voice slot's voice and listener-space position, None for a slot
+    // with nothing in it; None when no time has passed since the last update
+    pub fn update(&mut self, time: f64, placed: &[Option<(usize, Vector3<f32>)>]) -> Option<Motion> {
+        let elapsed = time - self.last_time.unwrap_or(time);
+        if self.last_time.is_some() && elapsed <= 0.0 {
+            return None;
+        }
+        self.last_time = Some(time);
+        let last = std::mem::replace(&mut self.last, placed.to_vec());
+        let depth = self.depth.max(0.0).min(1.0);
+
+        // A slot that changed hands, or a stall, moves nothing
+        let velocities: Vec<Option<Vector3<f32>>> = placed.iter().enumerate()
+            .map(|(slot, now)| {
+                let (voice, position) = (*now)?;
+                let (was_voice, was) = (*last.get(slot)?)?;
+                (voice == was_voice && elapsed > 0.0 && elapsed <= MAX_GAP)
+                    .then(|| (position - was) / elapsed as f32)
+            })
+            .collect();
+
+        // The listener sits at the origin, so the radial speed is along the position
+        let shifts = placed.iter().zip(&velocities)
+            .map(|(now, velocity)| match (now, velocity) {
+                (Some((_, position)), Some(velocity)) => {
+                    let receding = velocity.dot(&position.try_normalize(1e-6).unwrap_or_else(Vector3::zeros)).max(-0.9 * SOUND_SPEED);
+                    let cents = -1200.0 * (1.0 + receding / SOUND_SPEED).log2();
+                    depth * cents.max(-MAX_SHIFT_CENTS).min(MAX_SHIFT_CENTS)
+                }
+                _ => 0.0,
+            })
+            .collect();
+
+        let moving: Vec<f32> = velocities.iter().flatten().map(|velocity| velocity.norm()).collect();
+        let speed = moving.iter().sum::<f32>() / moving.len().max(1) as f32;
+        let whoosh = depth * 1200.0 * MAX_WHOOSH_OCTAVES * speed / (speed + WHOOSH_SPEED);
+
+        Some(Motion { shifts, whoosh })
+    }
+}
@@ -0,0 +1,303 @@
+// Live echoes: the user's transform streamed to friends as it moves, for co-viewing, in a
+// few bytes a step rather than a whole message with its sixteen floats. A stream is a run
+// of numbered steps: now and then a keyframe, the pose packed whole (Pose::to_packed), and
+// between them deltas, each the pose's quantized increments from the latest keyframe, not
+// from the step before. A lost delta is then made up by the next, and rounding never adds
+// up; a lost keyframe leaves its deltas unreadable until the next one, which comes within
+// KEY_INTERVAL. Steps are numbered so one arriving late, or twice, is passed over
+use std::collections::BTreeMap;
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Serialize, Deserialize};
+use crate::pose::{self, Pose};
+
+// Steps go out at most this often, in ms; the view's moves between gather into the next
+const SEND_INTERVAL: f64 = 50.0;
+
+// A keyframe goes out this often, in ms, while the pose moves, and IDLE_KEY_INTERVAL while
+// it's still, so a stream that went quiet is still known to be there; one not heard from
+// in STREAM_TIMEOUT has ended
+const KEY_INTERVAL: f64 = 1000.0;
+const IDLE_KEY_INTERVAL: f64 = 5.0 * 1000.0;
+const STREAM_TIMEOUT: u64 = 15 * 1000;
+
+// Steps a delta's parts are counted in: radians of rotation about each axis, the log of
+// each scale, and units of translation. Within about 6e-5 of the pose, a delta's parts
+// still take only a byte or two each
+const DELTA_STEPS: f32 = 8192.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum EchoDelta {
+    Key { pose: [u8; pose::PACKED_LEN] },
+    // From the keyframe numbered `base`: the rotation after it as a scaled axis, then the
+    // change in the log of each scale and in the translation, each in DELTA_STEPS
+    Delta { base: u32, rotation: [i32; 3], scale: [i32; 3], translation: [i32; 3] },
+    End, // The stream has stopped
+}
+
+// A step of someone's echo stream, routed by its recipients as a message is. Not sealed,
+// and not acknowledged or kept: one lost is made up by the next. Nor authenticated; the
+// sender is whoever `sender_id` says
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EchoStep {
+    pub sender_id: String,
+    pub stream: u64, // A new stream's steps start again from 0
+    pub sequence: u32,
+    pub recipients: Vec<String>, // Empty for everyone connected
+    pub delta: EchoDelta,
+}
+
+// The pose `delta` gives from the keyframe's
+fn apply(key: &Pose, rotation: [i32; 3], scale: [i32; 3], translation: [i32; 3]) -> Pose {
+    let steps = |parts: [i32; 3]| Vector3::from(parts.map(|part| part as f32 / DELTA_STEPS));
+    let mut pose = Pose {
+        rotation: key.rotation * UnitQuaternion::from_scaled_axis(steps(rotation)),
+        scale: key.scale.component_mul(&steps(scale).map(f32::exp)),
+        translation: key.translation + steps(translation),
+    };
+    pose.clamp();
+    pose
+}
+
+fn delta(key: &Pose, pose: &Pose) -> ([i32; 3], [i32; 3], [i32; 3]) {
+    let steps = |parts: Vector3<f32>| -> [i32; 3] { parts.map(|part| (part * DELTA_STEPS).round() as i32).into() };
+    let rotation = (key.rotation.inverse() * pose.rotation).scaled_axis();
+    let scale = pose.scale.zip_map(&key.scale, |s, k| s.ln() - k.ln());
+    (steps(rotation), steps(scale), steps(pose.translation - key.translation))
+}
+
+// The user's end of a stream
+pub struct EchoSender {
+    pub stream: u64,
+    recipients: Vec<String>,
+    sequence: u32, // Of the next step
+    key: Option<(u32, Pose)>, // The latest keyframe's number, and its pose as it unpacks
+    key_at: f64, // ms since the epoch
+    sent: Option<Pose>, // As the latest step had it
+    sent_at: f64,
+}
+
+impl EchoSender {
+    pub fn new(stream: u64, recipients: Vec<String>) -> Self {
+        EchoSender { stream, recipients, sequence: 0, key: None, key_at: 0.0, sent: None, sent_at: 0.0 }
+    }
+
+    // The step to send for `pose` now, if one is due: a keyframe first and every
+    // KEY_INTERVAL, and otherwise a delta whenever the pose has moved
+    pub fn next(&mut self, user_id: &str, pose: &Pose, now: f64) -> Option<EchoStep> {
+        let moved = self.sent != Some(*pose);
+        if moved && now < self.sent_at + SEND_INTERVAL {
+            return None;
+        }
+        let key_due = now >= self.key_at + if moved { KEY_INTERVAL } else { IDLE_KEY_INTERVAL };
+        let delta = match self.key {
+            Some((base, key)) if !key_due => {
+                if !moved {
+                    return None;
+                }
+                let (rotation, scale, translation) = delta(&key, pose);
+                EchoDelta::Delta { base, rotation, scale, translation }
+            }
+            _ => {
+                let packed = pose.to_packed();
+                self.key = Some((self.sequence, Pose::from_packed(&packed).unwrap()));
+                self.key_at = now;
+                EchoDelta::Key { pose: packed }
+            }
+        };
+        self.sent = Some(*pose);
+        self.sent_at = now;
+        Some(self.step(user_id, delta))
+    }
+
+    // After the connection drops: those who hear the next one start from a keyframe
+    pub fn restart(&mut self) {
+        self.key = None;
+        self.sent = None;
+    }
+
+    pub fn end(&mut self, user_id: &str) -> EchoStep {
+        self.step(user_id, EchoDelta::End)
+    }
+
+    fn step(&mut self, user_id: &str, delta: EchoDelta) -> EchoStep {
+        let step = EchoStep {
+            sender_id: user_id.to_string(),
+            stream: self.stream,
+            sequence: self.sequence,
+            recipients: self.recipients.clone(),
+            delta,
+        };
+        self.sequence += 1;
+        step
+    }
+}
+
+// Someone else's stream, as reassembled so far
+#[derive(Clone, Debug)]
+pub struct EchoStream {
+    pub stream: u64,
+    key: Option<(u32, Pose)>,
+    latest: Option<u32>, // The latest step taken
+    pub pose: Option<Pose>, // None until the first keyframe
+    pub updated_at: u64,    // When the pose last moved, ms since the epoch by this device's clock
+    heard_at: u64,
+    changed: bool,
+}
+
+impl EchoStream {
+    fn new(stream: u64, now: u64) -> Self {
+        EchoStream { stream, key: None, latest: None, pose: None, updated_at: now, heard_at: now, changed: false }
+    }
+}
+
+// Every stream coming in, by sender
+#[derive(Default)]
+pub struct EchoStreams {
+    streams: BTreeMap<String, EchoStream>,
+}
+
+impl EchoStreams {
+    pub fn receive(&mut self, step: EchoStep, now: u64) {
+        if step.delta == EchoDelta::End {
+            if self.streams.get(&step.sender_id).is_some_and(|stream| stream.stream <= step.stream) {
+                self.streams.remove(&step.sender_id);
+            }
+            return;
+        }
+        let stream = self.streams.entry(step.sender_id).or_insert_with(|| EchoStream::new(step.stream, now));
+        // Streams are numbered as they start, so a lower number is a stopped one's late step
+        if step.stream < stream.stream || (step.stream == stream.stream && stream.latest.is_some_and(|latest| step.sequence <= latest)) {
+            return;
+        }
+        if step.stream > stream.stream {
+            *stream = EchoStream::new(step.stream, now);
+        }
+        stream.heard_at = now;
+        let pose = match step.delta {
+            EchoDelta::Key { pose } => {
+                let Some(pose) = Pose::from_packed(&pose) else {
+                    return;
+                };
+                stream.key = Some((step.sequence, pose));
+                pose
+            }
+            // A delta from a keyframe that was lost, or overtaken, waits for the next
+            EchoDelta::Delta { base, rotation, scale, translation } => match stream.key {
+                Some((key_sequence, key)) if key_sequence == base => apply(&key, rotation, scale, translation),
+                _ => return,
+            },
+            EchoDelta::End => return,
+        };
+        stream.latest = Some(step.sequence);
+        if stream.pose != Some(pose) {
+            stream.pose = Some(pose);
+            stream.updated_at = now;
+            stream.changed = true;
+        }
+    }
+
+    // Streams not heard from in STREAM_TIMEOUT have ended
+    pub fn expire(&mut self, now: u64) {
+        self.streams.retain(|_, stream| now.saturating_sub(stream.heard_at) <= STREAM_TIMEOUT);
+    }
+
+    // Senders whose pose has moved since the last call, with it
+    pub fn take_changed(&mut self) -> Vec<(&str, Pose)> {
+        let mut changed = Vec::new();
+        for (sender, stream) in &mut self.streams {
+            if std::mem::take(&mut stream.changed) {
+                changed.extend(stream.pose.map(|pose| (sender.as_str(), pose)));
+            }
+        }
+        changed
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (&String, &EchoStream)> {
+        self.streams.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.streams.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Frame;
+
+    // A stream of poses sent step by step, with each step and the pose it was sent for
+    fn sent() -> (EchoSender, Vec<(EchoStep, Pose)>) {
+        let mut sender = EchoSender::new(1, vec!["friend".to_string()]);
+        let steps = (0..60).filter_map(|i| {
+            let t = i as f32 / 10.0;
+            let pose = Pose {
+                rotation: UnitQuaternion::from_euler_angles(t * 0.7, -t * 0.3, t * 1.1),
+                scale: Vector3::new(1.0 + t * 0.2, 1.0 + t * 0.1, 1.0 + t * 0.15),
+                translation: Vector3::new(t * 0.3, -t * 0.2, (t * 2.0).sin()),
+            };
+            sender.next("user_abc", &pose, i as f64 * 100.0).map(|step| (step, pose))
+        }).collect();
+        (sender, steps)
+    }
+
+    fn assert_close(shown: Option<Pose>, sent: &Pose) {
+        let shown = shown.expect("a pose is shown");
+        assert!(shown.rotation.angle_to(&sent.rotation) < 1e-3
+                    && (shown.scale - sent.scale).amax() < 1e-3
+                    && (shown.translation - sent.translation).amax() < 1e-3,
+                "showed {:?} for {:?}", shown, sent);
+    }
+
+    // Some steps lost, some late and one repeated: each pose shown is close to the one
+    // sent, deltas from a lost keyframe are passed over, and the receiver ends where the
+    // sender did, the stream gone once ended
+    #[test]
+    fn stream_survives_lost_and_late_steps() {
+        let (mut sender, steps) = sent();
+        let mut receiver = EchoStreams::default();
+        let (mut lost_key, mut passed_over) = (None, 0);
+        for (i, (step, pose)) in steps.iter().enumerate() {
+            // Every seventh step lost; every fifth held back, and delivered after the next
+            let lost = i % 7 == 3;
+            if lost && matches!(step.delta, EchoDelta::Key { .. }) {
+                lost_key = Some(step.sequence);
+            }
+            if lost || i % 5 == 4 {
+                continue;
+            }
+            receiver.receive(step.clone(), i as u64);
+            if i.is_multiple_of(5) && i > 0 {
+                receiver.receive(steps[i - 1].0.clone(), i as u64);
+            }
+            let stream = &receiver.streams["user_abc"];
+            if stream.latest == Some(step.sequence) {
+                assert_close(stream.pose, pose);
+            } else {
+                assert!(matches!(step.delta, EchoDelta::Delta { base, .. } if Some(base) == lost_key),
+                        "step {} passed over", step.sequence);
+                passed_over += 1;
+            }
+        }
+        assert!(lost_key.is_some() && passed_over > 0, "no delta from a lost keyframe");
+
+        let (last, pose) = steps.last().unwrap();
+        receiver.receive(last.clone(), 1000);
+        assert_close(receiver.streams["user_abc"].pose, pose);
+        receiver.receive(sender.end("user_abc"), 1000);
+        assert!(receiver.streams.is_empty());
+    }
+
+    #[test]
+    fn deltas_are_smaller_than_a_matrix() {
+        let deltas: Vec<usize> = sent().1.into_iter()
+            .filter(|(step, _)| matches!(step.delta, EchoDelta::Delta { .. }))
+            .map(|(step, _)| crate::wire::encode(&Frame::Echo { step }).len())
+            .collect();
+        assert!(!deltas.is_empty());
+        for bytes in deltas {
+            assert!(bytes < 16 * 4, "a delta took {} bytes", bytes);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Serialize, Deserialize};
+use crate::math;
+use crate::golden::GOLDEN_MULTIPLIER;
+
+// The day's gestures add BONUS_SCALE * ln(1 + gestures / BONUS_KNEE) to its complexity:
+// close to linear for the first couple of dozen, after which each doubling adds about the
+// same, so a few minutes' play holds its own against hours of mashing
+pub const BONUS_SCALE: f32 = 0.4;
+pub const BONUS_KNEE: f32 = 20.0;
+
+// Gestures counted toward the bonus in any one minute; past it they still move the
+// fractal, but earn nothing
+pub const MAX_COUNTED_PER_MINUTE: u32 = 30;
+
+// `gestures` already weighted, golden-hour ones counting several times
+pub fn bonus(gestures: u32) -> f32 {
+    BONUS_SCALE * math::ln(1.0 + gestures as f32 / BONUS_KNEE)
+}
+
+// The most a day's gestures can earn: every minute of it at the limit, all of them golden
+pub fn max_bonus() -> f32 {
+    bonus(MAX_COUNTED_PER_MINUTE * 24 * 60 * GOLDEN_MULTIPLIER)
+}
+
+// Counts gestures per minute of the clock, admitting MAX_COUNTED_PER_MINUTE in each. Saved
+// with the user's state, so reloading the page doesn't start the minute's count again
+#[derive(Serialize, Deserialize, Default)]
+pub struct RateLimiter {
+    minute: u64,
+    counted: u32,
+}
+
+impl RateLimiter {
+    // Whether a gesture at `now` (ms since the epoch) counts
+    pub fn admit(&mut self, now: f64) -> bool {
+        let minute = (now / 60_000.0) as u64;
+        if minute != self.minute {
+            self.minute = minute;
+            self.counted = 0;
+        }
+        if self.counted >= MAX_COUNTED_PER_MINUTE {
+            return false;
+        }
+        self.counted += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The most the bonus may come to after a waking day at the rate limit; about the
+    // spread of geometry scores between seeds, so the seed still decides most battles
+    const MAX_GRIND_BONUS: f32 = 3.0;
+
+    // Gestures in a calm morning's session, and the least they should earn
+    const MINDFUL_GESTURES: u32 = 50;
+    const MIN_MINDFUL_BONUS: f32 = 0.4;
+
+    const GROUND_DAY: u32 = MAX_COUNTED_PER_MINUTE * 16 * 60; // A waking day at the limit
+
+    #[test]
+    fn starts_at_zero() {
+        assert_eq!(bonus(0), 0.0);
+    }
+
+    // Every gesture worth something, but less than the one before
+    #[test]
+    fn rises_ever_more_slowly() {
+        let mut last_gain = f32::MAX;
+        for gestures in 0..GROUND_DAY {
+            let gain = bonus(gestures + 1) - bonus(gestures);
+            assert!(gain >= 0.0, "bonus falls after {} gestures", gestures);
+            // Allowing for f32 rounding once the steps get small
+            assert!(gain <= last_gain + 1e-6, "gesture {} earns more than the one before", gestures + 1);
+            last_gain = gain;
+        }
+    }
+
+    #[test]
+    fn a_short_session_counts() {
+        assert!(bonus(MINDFUL_GESTURES) >= MIN_MINDFUL_BONUS, "bonus({}) = {}", MINDFUL_GESTURES, bonus(MINDFUL_GESTURES));
+    }
+
+    #[test]
+    fn grinding_is_capped() {
+        assert!(bonus(GROUND_DAY) <= MAX_GRIND_BONUS, "bonus({}) = {}", GROUND_DAY, bonus(GROUND_DAY));
+    }
+
+    #[test]
+    fn rate_limited_per_minute() {
+        let mut limiter = RateLimiter::default();
+        let start = 1_718_064_000_000.0;
+        let counted = (0..MAX_COUNTED_PER_MINUTE * 2).filter(|&i| limiter.admit(start + i as f64)).count() as u32;
+        assert_eq!(counted, MAX_COUNTED_PER_MINUTE);
+        assert!(limiter.admit(start + 60_000.0));
+    }
+
+    // As the page saves and reloads it, mid-minute
+    #[test]
+    fn rate_limit_survives_a_reload() {
+        let mut limiter = RateLimiter::default();
+        let start = 1_718_064_000_000.0;
+        (0..MAX_COUNTED_PER_MINUTE).for_each(|i| assert!(limiter.admit(start + i as f64)));
+        let mut reloaded: RateLimiter = serde_json::from_str(&serde_json::to_string(&limiter).unwrap()).unwrap();
+        assert!(!reloaded.admit(start + 1000.0));
+    }
+}
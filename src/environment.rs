@@ -0,0 +1,137 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram};
+use serde::{Serialize, Deserialize};
+
+// Must match the array sizes in FRAGMENT_SHADER
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GradientStop {
+    pub position: f32,   // 0.0..1.0 along the gradient
+    pub color: [f32; 3], // Components above 1.0 are allowed and tone-mapped in the shader
+}
+
+// What surrounds the fractal: drawn where rays miss and reflected on the surface
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Environment {
+    Gradient {
+        horizon: [f32; 3],
+        zenith: [f32; 3],
+        #[serde(default = "default_reflectivity")]
+        reflectivity: f32,
+    },
+    Starfield {
+        base: [f32; 3],
+        density: f32,
+        #[serde(default = "default_reflectivity")]
+        reflectivity: f32,
+    },
+    Custom {
+        stops: Vec<GradientStop>, // Position 0.0 is straight down, 1.0 straight up
+        #[serde(default = "default_reflectivity")]
+        reflectivity: f32,
+    },
+}
+
+fn default_reflectivity() -> f32 {
+    0.25
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        // Close to the original near-black blue backdrop
+        Environment::Gradient {
+            horizon: [0.01, 0.02, 0.04],
+            zenith: [0.0, 0.0, 0.02],
+            reflectivity: default_reflectivity(),
+        }
+    }
+}
+
+impl Environment {
+    pub fn from_json(json: &str) -> Result<Self, JsValue> {
+        let environment: Environment = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid environment: {}", e)))?;
+
+        if let Environment::Custom { stops, .. } = &environment {
+            if stops.is_empty() || stops.len() > MAX_GRADIENT_STOPS {
+                return Err(JsValue::from_str(&format!(
+                    "Environment needs between 1 and {} gradient stops", MAX_GRADIENT_STOPS
+                )));
+            }
+        }
+
+        Ok(environment)
+    }
+
+    fn shader_mode(&self) -> i32 {
+        match self {
+            Environment::Gradient { .. } => 0,
+            Environment::Starfield { .. } => 1,
+            Environment::Custom { .. } => 2,
+        }
+    }
+
+    fn reflectivity(&self) -> f32 {
+        match self {
+            Environment::Gradient { reflectivity, .. }
+            | Environment::Starfield { reflectivity, .. }
+            | Environment::Custom { reflectivity, .. } => reflectivity.max(0.0).min(1.0),
+        }
+    }
+
+    // Every mode is expressed as sorted gradient stops so the shader has a single lookup
+    fn gradient_stops(&self) -> Vec<GradientStop> {
+        match self {
+            Environment::Gradient { horizon, zenith, .. } => vec![
+                GradientStop { position: 0.0, color: *zenith },
+                GradientStop { position: 0.5, color: *horizon },
+                GradientStop { position: 1.0, color: *zenith },
+            ],
+            Environment::Starfield { base, .. } => vec![
+                GradientStop { position: 0.0, color: *base },
+            ],
+            Environment::Custom { stops, .. } => {
+                let mut stops: Vec<GradientStop> = stops.iter().take(MAX_GRADIENT_STOPS).cloned().collect();
+                stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap_or(std::cmp::Ordering::Equal));
+                stops
+            }
+        }
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram) {
+        let stops = self.gradient_stops();
+
+        let mut colors = [0.0f32; MAX_GRADIENT_STOPS * 3];
+        let mut positions = [0.0f32; MAX_GRADIENT_STOPS];
+        for (i, stop) in stops.iter().enumerate() {
+            colors[i * 3..i * 3 + 3].copy_from_slice(&stop.color);
+            positions[i] = stop.position;
+        }
+
+        let density = match self {
+            Environment::Starfield { density, .. } => density.max(0.0).min(1.0),
+            _ => 0.0,
+        };
+
+        if let Some(loc) = gl.get_uniform_location(program, "u_env_mode") {
+            gl.uniform1i(Some(&loc), self.shader_mode());
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_env_stop_count") {
+            gl.uniform1i(Some(&loc), stops.len() as i32);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_env_colors") {
+            gl.uniform3fv_with_f32_array(Some(&loc), &colors);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_env_positions") {
+            gl.uniform1fv_with_f32_array(Some(&loc), &positions);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_env_density") {
+            gl.uniform1f(Some(&loc), density);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_env_reflectivity") {
+            gl.uniform1f(Some(&loc), self.reflectivity());
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use serde::Serialize;
+use crate::fractals::FractalParams;
+use crate::rng::SeededRng;
+
+// SeededRng stream for the curve, apart from the params derivation
+const EVOLUTION_STREAM: u64 = 0xE70_0001;
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+// Intensity thresholds between phases
+const CALM_BELOW: f32 = 0.2;
+const PEAK_ABOVE: f32 = 0.8;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EvolutionPhase {
+    Calm,
+    Building,
+    Peak,
+    Settling,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct EvolutionState {
+    pub phase: EvolutionPhase,
+    pub intensity: f32, // 0 at the calmest point of the day, up to `strength` at the peak
+    pub hour: f32,
+}
+
+// How far the daily fractal has drifted over the day: calm around the seed's morning hour,
+// most complex around its evening hour, eased in between. Each type's
+// FractalTypeInfo::evolve decides what "more complex" means for its settings.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct EvolutionCurve {
+    pub calm_hour: f32,
+    pub peak_hour: f32,
+    pub strength: f32, // Scales the whole drift, 0..1
+}
+
+impl EvolutionCurve {
+    pub fn from_seed(seed: u32) -> Self {
+        let mut rng = SeededRng::new(seed as u64, EVOLUTION_STREAM);
+        EvolutionCurve {
+            calm_hour: rng.range_f32(5.0, 8.0),
+            peak_hour: rng.range_f32(18.0, 22.0),
+            strength: rng.range_f32(0.6, 1.0),
+        }
+    }
+
+    pub fn state(&self, hour: f32) -> EvolutionState {
+        let hour = hour.rem_euclid(HOURS_PER_DAY);
+        let rising_hours = (self.peak_hour - self.calm_hour).rem_euclid(HOURS_PER_DAY);
+        let since_calm = (hour - self.calm_hour).rem_euclid(HOURS_PER_DAY);
+
+        // Cosine ease up from calm to peak, then back down over the night
+        let (progress, rising) = if since_calm < rising_hours {
+            (since_calm / rising_hours, true)
+        } else {
+            (1.0 - (since_calm - rising_hours) / (HOURS_PER_DAY - rising_hours), false)
+        };
+        let eased = 0.5 - 0.5 * (progress * std::f32::consts::PI).cos();
+
+        let phase = if eased < CALM_BELOW {
+            EvolutionPhase::Calm
+        } else if eased > PEAK_ABOVE {
+            EvolutionPhase::Peak
+        } else if rising {
+            EvolutionPhase::Building
+        } else {
+            EvolutionPhase::Settling
+        };
+
+        EvolutionState { phase, intensity: eased * self.strength, hour }
+    }
+
+    // `params` drifted to how they look at `hour`; types without an evolve rule stay put
+    pub fn apply(&self, params: &FractalParams, hour: f32) -> FractalParams {
+        let intensity = self.state(hour).intensity;
+        match params.info().evolve {
+            Some(evolve) => evolve(params, intensity),
+            None => *params,
+        }
+    }
+}
+
+// Local time of day in fractional hours
+pub fn local_hour() -> f32 {
+    let now = js_sys::Date::new_0();
+    now.get_hours() as f32 + now.get_minutes() as f32 / 60.0
+}
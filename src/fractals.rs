@@ -1,11 +1,178 @@
-use nalgebra::{Vector3, Vector4, Matrix4};
+use nalgebra::{Vector2, Vector3, Vector4, Matrix4};
 use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::palette::Palette;
+use crate::user::{FrozenFractal, Curation, FROZEN_VERSION};
+use crate::registry::{self, FractalType, FractalTypeInfo};
+use crate::rng::SeededRng;
+use crate::glsl::{self, GlslSnippet};
+use crate::math;
+use crate::complexity;
+use crate::lineage::Lineage;
 
 // Trait for all fractal types - thinking ahead for extensibility
 pub trait FractalGenerator {
     fn distance_estimator(&self, pos: &Vector4<f32>) -> f32;
-    fn get_color(&self, iterations: i32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32>;
-    fn get_name(&self) -> &'static str;
+    // `iterations` is the smooth iteration count (OrbitStats::smooth_iteration) at `pos`
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32>;
+    fn get_coloring_mode(&self) -> ColoringMode;
+    fn get_palette(&self) -> &Palette;
+    // The distance estimator for FRAGMENT_SHADER; None for types drawn by another program
+    fn to_glsl(&self) -> Option<GlslSnippet> { None }
+    // Level of detail: the same fractal with another iteration (or fold) budget, see
+    // FractalParams::with_iterations
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator>;
+    // Closest approach of the orbit to the active trap shape
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32;
+    // Exact settings, for freezing and sharing
+    fn get_params(&self) -> FractalParams;
+    // How the orbit of `pos` behaves over at most max_iter iterations
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats;
+}
+
+// Orbit points whose magnitudes OrbitStats keeps, starting point included
+pub const ORBIT_TRACE_LEN: usize = 32;
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct OrbitStats {
+    pub escape_iteration: i32, // Iterations run before bailing out, or all of them
+    pub escaped: bool,         // False for bounded orbits and the IFS formulas, which never bail out
+    pub min_distance: f32,     // Closest approach of the orbit to the origin
+    pub average_magnitude: f32,
+    pub smooth_iteration: f32, // escape_iteration with the overshoot past the bailout folded in, so it varies continuously
+    #[serde(skip)]
+    pub trace: [f32; ORBIT_TRACE_LEN], // Magnitude at each step, see orbit()
+}
+
+impl OrbitStats {
+    // The traced magnitudes, from the starting point on
+    pub fn orbit(&self) -> &[f32] {
+        let len = (self.escape_iteration.max(0) as usize + 1).min(ORBIT_TRACE_LEN);
+        &self.trace[..len]
+    }
+}
+
+// Continuous iteration count for an orbit that reached `magnitude` after `iterations` steps
+// of a formula growing like |z|^power, with `bailout` the escape radius. Mirrored by
+// smooth_iteration() in glsl::HELPERS.
+pub fn smooth_iteration(iterations: i32, magnitude: f32, bailout: f32, power: f32) -> f32 {
+    (iterations as f32 + 1.0 - math::ln(math::ln(magnitude) / math::ln(bailout)) / math::ln(power)).max(0.0)
+}
+
+// Accumulates OrbitStats as an orbit runs; the starting point counts toward the
+// distances but not the iterations
+struct OrbitTracker {
+    iterations: i32,
+    escaped: bool,
+    min_distance: f32,
+    magnitude_sum: f32,
+    smooth_iteration: Option<f32>,
+    trace: [f32; ORBIT_TRACE_LEN],
+}
+
+impl OrbitTracker {
+    fn new(start_magnitude: f32) -> Self {
+        let mut trace = [0.0; ORBIT_TRACE_LEN];
+        trace[0] = start_magnitude;
+        OrbitTracker {
+            iterations: 0,
+            escaped: false,
+            min_distance: start_magnitude,
+            magnitude_sum: start_magnitude,
+            smooth_iteration: None,
+            trace,
+        }
+    }
+
+    fn record(&mut self, magnitude: f32) {
+        self.iterations += 1;
+        self.min_distance = self.min_distance.min(magnitude);
+        self.magnitude_sum += magnitude;
+        if let Some(slot) = self.trace.get_mut(self.iterations as usize) {
+            *slot = magnitude;
+        }
+    }
+
+    fn escape(&mut self, magnitude: f32, bailout: f32, power: f32) {
+        self.escaped = true;
+        self.smooth_iteration = Some(smooth_iteration(self.iterations, magnitude, bailout, power));
+    }
+
+    fn finish(&self) -> OrbitStats {
+        OrbitStats {
+            escape_iteration: self.iterations,
+            escaped: self.escaped,
+            min_distance: self.min_distance,
+            average_magnitude: self.magnitude_sum / (self.iterations + 1) as f32,
+            smooth_iteration: self.smooth_iteration.unwrap_or(self.iterations as f32),
+            trace: self.trace,
+        }
+    }
+}
+
+// How surface color is derived; the shader ids must match u_coloring_mode in FRAGMENT_SHADER
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum ColoringMode {
+    #[default]
+    Standard,
+    PointTrap,  // Distance to the origin
+    LineTrap,   // Distance to the y axis
+    PlaneTrap,  // Distance to the y = 0 plane
+    Equalized,  // Smooth iteration count through a histogram-equalized lookup (GPU only, see histogram.rs)
+}
+
+impl ColoringMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(ColoringMode::Standard),
+            "point" => Some(ColoringMode::PointTrap),
+            "line" => Some(ColoringMode::LineTrap),
+            "plane" => Some(ColoringMode::PlaneTrap),
+            "equalized" => Some(ColoringMode::Equalized),
+            _ => None,
+        }
+    }
+
+    pub fn shader_id(&self) -> i32 {
+        match self {
+            ColoringMode::Standard => 0,
+            ColoringMode::PointTrap => 1,
+            ColoringMode::LineTrap => 2,
+            ColoringMode::PlaneTrap => 3,
+            ColoringMode::Equalized => 4,
+        }
+    }
+
+    pub fn from_shader_id(id: i32) -> Option<Self> {
+        [ColoringMode::Standard, ColoringMode::PointTrap, ColoringMode::LineTrap, ColoringMode::PlaneTrap, ColoringMode::Equalized]
+            .into_iter()
+            .find(|mode| mode.shader_id() == id)
+    }
+
+    pub fn trap_distance(&self, p: &Vector3<f32>) -> f32 {
+        match self {
+            ColoringMode::Standard | ColoringMode::Equalized => f32::MAX,
+            ColoringMode::PointTrap => p.norm(),
+            ColoringMode::LineTrap => (p.x * p.x + p.z * p.z).sqrt(),
+            ColoringMode::PlaneTrap => p.y.abs(),
+        }
+    }
+}
+
+// Shared trap coloring, mirrored by trap_color() in glsl::HELPERS
+pub fn trap_color(trap: f32, palette: &Palette) -> Vector3<f32> {
+    let trap = trap.min(4.0);
+    let t = (0.6 + trap * 1.5).fract();
+    let value = (1.0 - trap).max(0.15).min(1.0);
+    palette.shade(t, 0.75, value)
+}
+
+pub(crate) fn trap_coloring<F: FractalGenerator + ?Sized>(fractal: &F, pos: &Vector4<f32>) -> Option<Vector3<f32>> {
+    match fractal.get_coloring_mode() {
+        // The CPU colorers have no lookup, so equalized falls back to their standard hues
+        ColoringMode::Standard | ColoringMode::Equalized => None,
+        _ => Some(trap_color(fractal.orbit_trap(pos), fractal.get_palette())),
+    }
 }
 
 // Enhanced Mandelbulb with time evolution
@@ -13,41 +180,22 @@ pub struct Mandelbulb {
     pub power: f32,
     pub iterations: i32,
     pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
 }
 
 impl FractalGenerator for Mandelbulb {
     fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
-        let mut z = Vector3::new(pos.x, pos.y, pos.z);
-        let mut dr = 1.0f32;
-        let mut r = 0.0f32;
-
-        // Time-evolving power
-        let dynamic_power = self.power + (self.time * 0.1).sin() * 2.0;
-
-        for _ in 0..self.iterations {
-            r = z.norm();
-            if r > 2.0 { break; }
-
-            // Spherical coordinates with 4D influence
-            let theta = (z.z / r).acos() + pos.w * 0.1 + self.time * 0.05;
-            let phi = z.y.atan2(z.x) + self.time * 0.03;
-
-            dr = r.powf(dynamic_power - 1.0) * dynamic_power * dr + 1.0;
+        self.orbit(pos, i32::MAX).0
+    }
 
-            let zr = r.powf(dynamic_power);
-            z = Vector3::new(
-                zr * theta.sin() * phi.cos(),
-                zr * theta.sin() * phi.sin(),
-                zr * theta.cos()
-            ) + Vector3::new(pos.x, pos.y, pos.z);
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
         }
 
-        0.5 * r.ln() * r / dr
-    }
-
-    fn get_color(&self, iterations: i32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
         // Much more vibrant and dynamic coloring
-        let iteration_factor = iterations as f32 / self.iterations as f32;
+        let iteration_factor = iterations / self.iterations as f32;
 
         // Multi-layered hue calculation for rich colors
         let base_hue = (iteration_factor * 6.0 + self.time * 0.5).sin() * 0.5 + 0.5;
@@ -63,10 +211,69 @@ impl FractalGenerator for Mandelbulb {
         let pulse = (self.time * 2.0 + pos.x * 0.5).sin() * 0.3 + 0.7;
         let value = (1.0 - (distance * 4.0).min(0.9)) * pulse;
 
-        hsv_to_rgb(hue, saturation, value)
+        self.palette.shade(hue, saturation, value)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "mandelbulb", source: glsl::MANDELBULB }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::Mandelbulb { power: self.power, iterations: self.iterations }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
     }
+}
+
+impl Mandelbulb {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
+        let mut z = Vector3::new(pos.x, pos.y, pos.z);
+        let mut dr = 1.0f32;
+        let mut r = 0.0f32;
+        let mut trap = f32::MAX;
+
+        // Time-evolving power
+        let dynamic_power = self.power + math::sin(self.time * 0.1) * 2.0;
+        let mut stats = OrbitTracker::new(z.norm());
+
+        for _ in 0..self.iterations.min(max_iter) {
+            r = z.norm();
+            if r > 2.0 {
+                stats.escape(r, 2.0, dynamic_power);
+                break;
+            }
+
+            // Spherical coordinates with 4D influence
+            let theta = math::acos(z.z / r) + pos.w * 0.1 + self.time * 0.05;
+            let phi = math::atan2(z.y, z.x) + self.time * 0.03;
+
+            dr = math::powf(r, dynamic_power - 1.0) * dynamic_power * dr + 1.0;
 
-    fn get_name(&self) -> &'static str { "Mandelbulb" }
+            let zr = math::powf(r, dynamic_power);
+            z = Vector3::new(
+                zr * math::sin(theta) * math::cos(phi),
+                zr * math::sin(theta) * math::sin(phi),
+                zr * math::cos(theta)
+            ) + Vector3::new(pos.x, pos.y, pos.z);
+            trap = trap.min(self.coloring.trap_distance(&z));
+            stats.record(z.norm());
+        }
+
+        (0.5 * math::ln(r) * r / dr, trap, stats.finish())
+    }
 }
 
 // Julia4D set - 4D Julia fractals
@@ -74,49 +281,88 @@ pub struct Julia4D {
     pub c: Vector4<f32>,
     pub iterations: i32,
     pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
 }
 
 impl FractalGenerator for Julia4D {
     fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
+
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        let angle = (pos.x.atan2(pos.y) + self.time * 0.1) / (2.0 * std::f32::consts::PI);
+        let depth = (pos.z + pos.w) * 0.1 + self.time * 0.05;
+
+        let hue = (angle + depth).fract();
+        let saturation = (1.0 - distance * 0.5).max(0.2);
+        let value = (iterations / self.iterations as f32).powf(0.7);
+
+        self.palette.shade(hue, saturation, value)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "julia4d", source: glsl::JULIA4D }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::Julia4D { c: self.c.into(), iterations: self.iterations }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
+}
+
+impl Julia4D {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
         let mut z = *pos;
+        let mut trap = f32::MAX;
         let mut dz = Vector4::new(1.0, 0.0, 0.0, 0.0);
 
         // Time-evolving Julia constant
         let dynamic_c = Vector4::new(
-            self.c.x + (self.time * 0.1).sin() * 0.3,
-            self.c.y + (self.time * 0.13).cos() * 0.2,
-            self.c.z + (self.time * 0.07).sin() * 0.25,
-            self.c.w + (self.time * 0.11).cos() * 0.15
+            self.c.x + math::sin(self.time * 0.1) * 0.3,
+            self.c.y + math::cos(self.time * 0.13) * 0.2,
+            self.c.z + math::sin(self.time * 0.07) * 0.25,
+            self.c.w + math::cos(self.time * 0.11) * 0.15
         );
 
-        for _ in 0..self.iterations {
+        let mut stats = OrbitTracker::new(z.norm());
+
+        for _ in 0..self.iterations.min(max_iter) {
             let r = z.norm();
-            if r > 4.0 { break; }
+            if r > 4.0 {
+                stats.escape(r, 4.0, 2.0);
+                break;
+            }
 
             // 4D quaternion-like multiplication
             dz = self.quat_mult_derivative(&z, &dz) + Vector4::new(1.0, 0.0, 0.0, 0.0);
             z = self.quat_square(&z) + dynamic_c;
+            trap = trap.min(self.coloring.trap_distance(&z.xyz()));
+            stats.record(z.norm());
         }
 
         let r = z.norm();
-        0.5 * r.ln() * r / dz.norm()
+        (0.5 * math::ln(r) * r / dz.norm(), trap, stats.finish())
     }
 
-    fn get_color(&self, iterations: i32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
-        let angle = (pos.x.atan2(pos.y) + self.time * 0.1) / (2.0 * std::f32::consts::PI);
-        let depth = (pos.z + pos.w) * 0.1 + self.time * 0.05;
-
-        let hue = (angle + depth).fract();
-        let saturation = (1.0 - distance * 0.5).max(0.2);
-        let value = (iterations as f32 / self.iterations as f32).powf(0.7);
-
-        hsv_to_rgb(hue, saturation, value)
-    }
-
-    fn get_name(&self) -> &'static str { "Julia4D" }
-}
-
-impl Julia4D {
     fn quat_square(&self, q: &Vector4<f32>) -> Vector4<f32> {
         Vector4::new(
             q.x * q.x - q.y * q.y - q.z * q.z - q.w * q.w,
@@ -141,17 +387,64 @@ pub struct KaleidoIFS {
     pub fold_count: i32,
     pub scale: f32,
     pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
 }
 
 impl FractalGenerator for KaleidoIFS {
     fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
+
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        let complexity = (iterations + distance * 10.0) * 0.1;
+        let hue = (complexity + self.time * 0.3 + pos.w).fract();
+        let saturation = (1.0 - distance * 0.3).max(0.4);
+        let value = (0.8 + (complexity * 3.0).sin() * 0.2).max(0.1);
+
+        self.palette.shade(hue, saturation, value)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "kaleidoIFS", source: glsl::KALEIDO_IFS }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::KaleidoIFS { fold_count: self.fold_count, scale: self.scale }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
+}
+
+impl KaleidoIFS {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
         let mut p = Vector3::new(pos.x, pos.y, pos.z);
         let mut scale = 1.0f32;
+        let mut trap = f32::MAX;
+
+        let mut stats = OrbitTracker::new(p.norm());
 
-        for i in 0..self.fold_count {
+        for i in 0..self.fold_count.min(max_iter) {
             // Time-based folding planes
             let angle = self.time * 0.1 + i as f32 * 0.5;
-            let fold_normal = Vector3::new(angle.cos(), angle.sin(), (angle * 1.3).sin());
+            let fold_normal = Vector3::new(math::cos(angle), math::sin(angle), math::sin(angle * 1.3)).normalize();
 
             // Kaleidoscopic folding
             let dot = p.dot(&fold_normal);
@@ -173,107 +466,1459 @@ impl FractalGenerator for KaleidoIFS {
             }
 
             // Scale and translate
-            let dynamic_scale = self.scale + (self.time * 0.05 + i as f32 * 0.1).sin() * 0.5;
+            let dynamic_scale = self.scale + math::sin(self.time * 0.05 + i as f32 * 0.1) * 0.5;
             p = p * dynamic_scale + Vector3::new(
-                (self.time * 0.07).sin() * 0.1,
-                (self.time * 0.11).cos() * 0.1,
+                math::sin(self.time * 0.07) * 0.1,
+                math::cos(self.time * 0.11) * 0.1,
                 pos.w * 0.2
             );
             scale *= dynamic_scale;
+            trap = trap.min(self.coloring.trap_distance(&p));
+            stats.record(p.norm());
         }
 
-        (p.norm() - 0.5) / scale.abs()
+        ((p.norm() - 0.5) / scale.abs(), trap, stats.finish())
     }
+}
 
-    fn get_color(&self, iterations: i32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
-        let complexity = (iterations as f32 + distance * 10.0) * 0.1;
-        let hue = (complexity + self.time * 0.3 + pos.w).fract();
-        let saturation = (1.0 - distance * 0.3).max(0.4);
-        let value = (0.8 + (complexity * 3.0).sin() * 0.2).max(0.1);
+// Mandelbox - box fold + sphere fold, with seed-derived scale and minimum radius
+pub struct Mandelbox {
+    pub scale: f32,
+    pub min_radius: f32,
+    pub iterations: i32,
+    pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
+}
+
+impl FractalGenerator for Mandelbox {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
+
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        let iteration_factor = iterations / self.iterations as f32;
+        let hue = (iteration_factor * 1.5 + self.time * 0.05 + pos.xyz().norm() * 0.2).fract();
+        let saturation = (0.75 - distance * 0.3).max(0.3);
+        let value = 0.85 * (1.0 - (distance * 2.0).min(0.7));
 
-        hsv_to_rgb(hue, saturation, value)
+        self.palette.shade(hue, saturation, value)
     }
 
-    fn get_name(&self) -> &'static str { "KaleidoIFS" }
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "mandelbox", source: glsl::MANDELBOX }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::Mandelbox {
+            scale: self.scale,
+            min_radius: self.min_radius,
+            iterations: self.iterations,
+        }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
 }
 
-// Fractal selector based on seed
-pub fn create_fractal_from_seed(seed: u32, time: f32) -> Box<dyn FractalGenerator> {
-    let fractal_type = seed % 3;
+impl Mandelbox {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
+        let offset = Vector3::new(pos.x, pos.y, pos.z);
+        let mut z = offset;
+        let mut dr = 1.0f32;
+        let mut trap = f32::MAX;
+
+        // Gentle breathing of the scale keeps the box alive over the day
+        let scale = self.scale + math::sin(self.time * 0.05) * 0.1;
+        let min_r2 = self.min_radius * self.min_radius;
+        let mut stats = OrbitTracker::new(z.norm());
 
-    match fractal_type {
-        0 => Box::new(Mandelbulb {
-            power: 6.0 + ((seed / 3) % 8) as f32,
-            iterations: 8 + ((seed / 24) % 4) as i32,
-            time,
-        }),
-        1 => {
-            let c_seed = seed / 100;
-            Box::new(Julia4D {
-                c: Vector4::new(
-                    ((c_seed % 1000) as f32 / 1000.0 - 0.5) * 2.0,
-                    (((c_seed / 1000) % 1000) as f32 / 1000.0 - 0.5) * 2.0,
-                    (((c_seed / 1000000) % 1000) as f32 / 1000.0 - 0.5) * 2.0,
-                    (((c_seed / 1000000000) % 1000) as f32 / 1000.0 - 0.5) * 2.0,
-                ),
-                iterations: 8 + ((seed / 13) % 6) as i32,
-                time,
-            })
-        },
-        _ => Box::new(KaleidoIFS {
-            fold_count: 4 + ((seed / 7) % 8) as i32,
-            scale: 1.5 + ((seed / 17) % 10) as f32 * 0.3,
-            time,
-        }),
+        for _ in 0..self.iterations.min(max_iter) {
+            // Box fold
+            z = z.map(|v| v.max(-1.0).min(1.0) * 2.0 - v);
+
+            // Sphere fold (fixed radius 1)
+            let r2 = z.norm_squared();
+            if r2 < min_r2 {
+                z /= min_r2;
+                dr /= min_r2;
+            } else if r2 < 1.0 {
+                z /= r2;
+                dr /= r2;
+            }
+
+            z = z * scale + offset;
+            dr = dr * scale.abs() + 1.0;
+            trap = trap.min(self.coloring.trap_distance(&z));
+            stats.record(z.norm());
+        }
+
+        (z.norm() / dr.abs(), trap, stats.finish())
     }
 }
 
-// HSV to RGB conversion for beautiful colors
-fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Vector3<f32> {
-    let c = v * s;
-    let h_prime = (h * 6.0) % 6.0;
-    let x = c * (1.0 - ((h_prime % 2.0) - 1.0).abs());
-    let m = v - c;
+// Menger sponge IFS - sort-and-scale folding with seed-controlled fold count and hole scale
+pub struct MengerSponge {
+    pub fold_count: i32,
+    pub hole_scale: f32, // IFS scale factor; 3.0 is the classic sponge, larger opens bigger holes
+    pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
+}
 
-    let (r, g, b) = if h_prime < 1.0 {
-        (c, x, 0.0)
-    } else if h_prime < 2.0 {
-        (x, c, 0.0)
-    } else if h_prime < 3.0 {
-        (0.0, c, x)
-    } else if h_prime < 4.0 {
-        (0.0, x, c)
-    } else if h_prime < 5.0 {
-        (x, 0.0, c)
-    } else {
-        (c, 0.0, x)
-    };
+impl FractalGenerator for MengerSponge {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
 
-    Vector3::new(r + m, g + m, b + m)
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        // Faces of the sponge pick up alternating bands along each axis
+        let bands = (pos.x * 2.0).sin() + (pos.y * 2.0).sin() + (pos.z * 2.0).sin();
+        let hue = (bands * 0.1 + iterations * 0.07 + self.time * 0.04).fract();
+        let saturation = (0.7 - distance * 0.2).max(0.3);
+        let value = 0.9 * (1.0 - (distance * 3.0).min(0.8));
+
+        self.palette.shade(hue, saturation, value)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "menger", source: glsl::MENGER_SPONGE }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::MengerSponge { fold_count: self.fold_count, hole_scale: self.hole_scale }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
 }
 
-// Audio synthesis from fractal geometry - thinking ahead
-pub struct FractalAudioAnalyzer;
+impl MengerSponge {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
+        let mut z = Vector3::new(pos.x, pos.y, pos.z);
+        let scale = self.hole_scale;
+        let shift = scale - 1.0;
+        let mut trap = f32::MAX;
+        let folds = self.fold_count.min(max_iter);
+        let mut stats = OrbitTracker::new(z.norm());
 
-impl FractalAudioAnalyzer {
-    pub fn extract_frequencies(fractal: &dyn FractalGenerator, sample_points: &[Vector4<f32>]) -> Vec<f32> {
-        let mut frequencies = Vec::with_capacity(32);
+        for _ in 0..folds {
+            z = z.abs();
 
-        // Sample fractal at different points and convert to frequencies
-        for (i, point) in sample_points.iter().enumerate() {
-            let distance = fractal.distance_estimator(point);
-            let color = fractal.get_color(8, distance, point);
+            // Sort components descending so every octant folds onto the same wedge
+            if z.x < z.y { z.swap_rows(0, 1); }
+            if z.x < z.z { z.swap_rows(0, 2); }
+            if z.y < z.z { z.swap_rows(1, 2); }
 
-            // Convert color and distance to musical frequencies
-            let base_freq = 220.0; // A3
-            let frequency = base_freq * (1.0 + distance * 0.5) * (1.0 + color.x * 0.3);
-            frequencies.push(frequency);
+            z = z * scale - Vector3::repeat(shift);
+            if z.z < -0.5 * shift {
+                z.z += shift;
+            }
+            trap = trap.min(self.coloring.trap_distance(&z));
+            stats.record(z.norm());
         }
 
-        frequencies
+        // Distance to the unit box, scaled back down through every fold
+        let outside = (z.abs() - Vector3::repeat(1.0)).map(|v| v.max(0.0));
+        (outside.norm() * scale.powi(-folds), trap, stats.finish())
     }
+}
 
-    pub fn create_harmonic_series(fundamental: f32, harmonics: usize) -> Vec<f32> {
-        (1..=harmonics).map(|h| fundamental * h as f32).collect()
+// Apollonian gasket - repeated sphere inversion packing with seed-controlled inversion strength
+pub struct Apollonian {
+    pub inversion: f32, // k in p *= k / |p|^2; 1.0 is the classic packing, higher inflates the spheres
+    pub iterations: i32,
+    pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
+}
+
+impl FractalGenerator for Apollonian {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
+
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        let hue = (pos.xyz().norm() * 0.3 + iterations * 0.05 + self.time * 0.03).fract();
+        let saturation = (0.65 - distance * 0.2).max(0.25);
+        let value = 0.9 * (1.0 - (distance * 3.0).min(0.8));
+
+        self.palette.shade(hue, saturation, value)
     }
-}
\ No newline at end of file
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "apollonian", source: glsl::APOLLONIAN }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::Apollonian { inversion: self.inversion, iterations: self.iterations }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
+}
+
+impl Apollonian {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
+        let mut p = Vector3::new(pos.x, pos.y, pos.z);
+        let mut scale = 1.0f32;
+        let mut trap = f32::MAX;
+
+        let mut stats = OrbitTracker::new(p.norm());
+
+        for _ in 0..self.iterations.min(max_iter) {
+            // Fold into the [-1, 1] cell, then invert in the unit sphere
+            p = p.map(|v| {
+                let half = 0.5 * v + 0.5;
+                -1.0 + 2.0 * (half - half.floor())
+            });
+
+            let r2 = p.norm_squared().max(1e-6);
+            let k = self.inversion / r2;
+            p *= k;
+            scale *= k;
+            trap = trap.min(self.coloring.trap_distance(&p));
+            stats.record(p.norm());
+        }
+
+        (0.25 * p.y.abs() / scale, trap, stats.finish())
+    }
+}
+
+// Escape radius of the plane orbit (BAILOUT_2D in FRAGMENT_SHADER_2D); large so the
+// smooth iteration count and distance estimate are accurate
+pub const PLANE_BAILOUT: f32 = 256.0;
+
+// Escape-time Mandelbrot set, or a Julia set when `julia` holds the constant, on the
+// z = 0 plane. The cheap fallback for devices that can't afford raymarching; the
+// distance estimate ignores z, so it reads as the set extruded along the view axis.
+pub struct Mandelbrot2D {
+    pub julia: Option<[f32; 2]>,
+    pub iterations: i32,
+    pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
+}
+
+impl FractalGenerator for Mandelbrot2D {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
+
+    fn get_color(&self, _iterations: f32, _distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        // Mirrors main() in FRAGMENT_SHADER_2D, minus the screen-space edge darkening
+        let (distance, _, stats) = self.orbit(pos, i32::MAX);
+        if distance <= 0.0 {
+            return Vector3::repeat(0.02);
+        }
+
+        let hue = (stats.smooth_iteration * 0.02 + self.time * 0.02).fract();
+        self.palette.shade(hue, 0.8, 0.9)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::Mandelbrot2D { julia: self.julia, iterations: self.iterations }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
+}
+
+impl Mandelbrot2D {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter
+    // iterations; the distance is 0 for points that never escape
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
+        let p = Vector2::new(pos.x, pos.y);
+        let (c, mut z, mut dz) = match self.julia {
+            Some(c) => (Vector2::from(c), p, Vector2::new(1.0, 0.0)),
+            None => (p, Vector2::zeros(), Vector2::zeros()),
+        };
+        let dz_offset = if self.julia.is_some() { Vector2::zeros() } else { Vector2::new(1.0, 0.0) };
+
+        let mut trap = f32::MAX;
+        let mut stats = OrbitTracker::new(z.norm());
+
+        for _ in 0..self.iterations.min(max_iter) {
+            if z.norm_squared() > PLANE_BAILOUT * PLANE_BAILOUT {
+                break;
+            }
+
+            dz = 2.0 * Vector2::new(z.x * dz.x - z.y * dz.y, z.x * dz.y + z.y * dz.x) + dz_offset;
+            z = Vector2::new(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+            trap = trap.min(self.coloring.trap_distance(&Vector3::new(z.x, z.y, 0.0)));
+            stats.record(z.norm());
+        }
+
+        // Counts as escaped even when the last iteration is the one that crosses the bailout
+        let r = z.norm();
+        if r <= PLANE_BAILOUT {
+            return (0.0, trap, stats.finish());
+        }
+
+        stats.escape(r, PLANE_BAILOUT, 2.0);
+        (0.5 * r * math::ln(r) / dz.norm(), trap, stats.finish())
+    }
+}
+
+// Iterations of a hybrid orbit (HYBRID_ITERATIONS in FRAGMENT_SHADER)
+pub const HYBRID_ITERATIONS: i32 = 12;
+
+// One step of a base formula, as used inside a hybrid orbit
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "formula")]
+pub enum HybridFormula {
+    Mandelbulb { power: f32 },
+    Mandelbox { scale: f32, min_radius: f32 },
+}
+
+impl HybridFormula {
+    fn step(&self, z: &mut Vector3<f32>, dr: &mut f32, c: &Vector3<f32>) {
+        match *self {
+            HybridFormula::Mandelbulb { power } => {
+                let r = z.norm().max(1e-6);
+                let theta = math::acos(z.z / r);
+                let phi = math::atan2(z.y, z.x);
+                *dr = math::powf(r, power - 1.0) * power * *dr + 1.0;
+
+                let zr = math::powf(r, power);
+                *z = Vector3::new(
+                    zr * math::sin(theta) * math::cos(phi),
+                    zr * math::sin(theta) * math::sin(phi),
+                    zr * math::cos(theta)
+                ) + c;
+            }
+            HybridFormula::Mandelbox { scale, min_radius } => {
+                *z = z.map(|v| v.max(-1.0).min(1.0) * 2.0 - v);
+
+                let r2 = z.norm_squared();
+                let min_r2 = min_radius * min_radius;
+                if r2 < min_r2 {
+                    *z /= min_r2;
+                    *dr /= min_r2;
+                } else if r2 < 1.0 {
+                    *z /= r2;
+                    *dr /= r2;
+                }
+
+                *z = *z * scale + c;
+                *dr = *dr * scale.abs() + 1.0;
+            }
+        }
+    }
+
+    // (kind, p1, p2) as read by hybrid_step() in FRAGMENT_SHADER
+    fn shader_params(&self) -> [f32; 3] {
+        match *self {
+            HybridFormula::Mandelbulb { power } => [0.0, power, 0.0],
+            HybridFormula::Mandelbox { scale, min_radius } => [1.0, scale, min_radius],
+        }
+    }
+
+    // See morph(); a change of formula switches at the midpoint
+    fn morph(&self, other: &HybridFormula, t: f32) -> HybridFormula {
+        match (*self, *other) {
+            (HybridFormula::Mandelbulb { power: pa }, HybridFormula::Mandelbulb { power: pb }) =>
+                HybridFormula::Mandelbulb { power: lerp(pa, pb, t) },
+            (HybridFormula::Mandelbox { scale: sa, min_radius: ra }, HybridFormula::Mandelbox { scale: sb, min_radius: rb }) =>
+                HybridFormula::Mandelbox {
+                    scale: lerp(sa.abs(), sb.abs(), t).copysign(halfway(sa, sb, t)),
+                    min_radius: lerp(ra, rb, t),
+                },
+            _ => halfway(*self, *other, t),
+        }
+    }
+
+    // How fast one step grows |z| far out, for the smooth iteration count
+    fn growth(&self) -> f32 {
+        match *self {
+            HybridFormula::Mandelbulb { power } => power,
+            HybridFormula::Mandelbox { scale, .. } => scale.abs(),
+        }
+    }
+}
+
+// Two formulas interleaved along one orbit: `first_steps` of the first, then
+// `second_steps` of the second, repeating
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct HybridSpec {
+    pub first: HybridFormula,
+    pub second: HybridFormula,
+    pub first_steps: u32,
+    pub second_steps: u32,
+}
+
+impl HybridSpec {
+    // Seed-driven Mandelbulb/Mandelbox pairing with a varying step ratio
+    pub fn from_seed(seed: u32) -> Self {
+        HybridSpec {
+            first: HybridFormula::Mandelbulb { power: 6.0 + ((seed / 7) % 4) as f32 },
+            second: HybridFormula::Mandelbox {
+                scale: -1.8 - ((seed / 11) % 5) as f32 * 0.1,
+                min_radius: 0.4 + ((seed / 55) % 3) as f32 * 0.1,
+            },
+            first_steps: 1 + seed % 2,
+            second_steps: 1 + (seed / 2) % 3,
+        }
+    }
+}
+
+pub struct HybridFractal {
+    pub spec: HybridSpec,
+    pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
+}
+
+impl FractalGenerator for HybridFractal {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).0
+    }
+
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        let iteration_factor = iterations / HYBRID_ITERATIONS as f32;
+        let hue = (iteration_factor * 2.0 + pos.xyz().norm() * 0.15 + self.time * 0.05).fract();
+        let saturation = (0.8 - distance * 0.3).max(0.3);
+        let value = 0.9 * (1.0 - (distance * 3.0).min(0.8));
+
+        self.palette.shade(hue, saturation, value)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+    fn to_glsl(&self) -> Option<GlslSnippet> { Some(GlslSnippet { function: "hybrid", source: glsl::HYBRID }) }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::Hybrid(self.spec)
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.orbit(pos, i32::MAX).1
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.orbit(pos, max_iter).2
+    }
+}
+
+impl HybridFractal {
+    // Returns (distance estimate, orbit trap minimum, orbit statistics) for up to max_iter iterations
+    fn orbit(&self, pos: &Vector4<f32>, max_iter: i32) -> (f32, f32, OrbitStats) {
+        let c = Vector3::new(pos.x, pos.y, pos.z);
+        let mut z = c;
+        let mut dr = 1.0f32;
+        let mut trap = f32::MAX;
+        // Clamped as validate would, so an unchecked spec can't overflow the cycle
+        let limit = HYBRID_ITERATIONS as u32;
+        let (first_steps, second_steps) = (self.spec.first_steps.clamp(1, limit), self.spec.second_steps.clamp(1, limit));
+        let cycle = first_steps + second_steps;
+        let formula_at = |i: u32| {
+            if i % cycle < first_steps { self.spec.first } else { self.spec.second }
+        };
+
+        let mut stats = OrbitTracker::new(z.norm());
+
+        for i in 0..HYBRID_ITERATIONS.min(max_iter) as u32 {
+            if z.norm_squared() > 16.0 {
+                // The step that just ran decides how fast the orbit was growing
+                stats.escape(z.norm(), 4.0, formula_at(i.saturating_sub(1)).growth());
+                break;
+            }
+
+            formula_at(i).step(&mut z, &mut dr, &c);
+            trap = trap.min(self.coloring.trap_distance(&z));
+            stats.record(z.norm());
+        }
+
+        (0.5 * z.norm() / dr.abs(), trap, stats.finish())
+    }
+}
+
+// Seed-to-params derivation used for new fractals. Saved fractals record the version they
+// were made with (FrozenFractal::derivation), so bumping this never changes old ones.
+pub const CURRENT_DERIVATION: u32 = 1;
+
+// SeededRng stream of derivation 1
+const DERIVATION_V1_STREAM: u64 = 0x5EED_0001;
+
+// Everything that shapes a fractal besides time and styling, so a fractal can be
+// rebuilt exactly after freezing, sharing or battling
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum FractalParams {
+    Mandelbulb { power: f32, iterations: i32 },
+    Julia4D { c: [f32; 4], iterations: i32 },
+    KaleidoIFS { fold_count: i32, scale: f32 },
+    Mandelbox { scale: f32, min_radius: f32, iterations: i32 },
+    MengerSponge { fold_count: i32, hole_scale: f32 },
+    Apollonian { inversion: f32, iterations: i32 },
+    Hybrid(HybridSpec),
+    Mandelbrot2D { julia: Option<[f32; 2]>, iterations: i32 },
+    LSystem { rule_seed: u32, iterations: i32, angle: f32 },
+}
+
+impl FractalParams {
+    // The settings today's derivation picks for a seed
+    pub fn from_seed(seed: u32) -> Self {
+        Self::from_seed_with(seed, CURRENT_DERIVATION)
+    }
+
+    // A specific derivation version, for rebuilding fractals saved under an older one;
+    // unknown versions fall back to the current derivation
+    pub fn from_seed_with(seed: u32, derivation: u32) -> Self {
+        match derivation {
+            0 => Self::params_v0(seed),
+            _ => Self::params_v1(seed),
+        }
+    }
+
+    // Legacy modulo mapping, see FractalRegistry::daily_v0
+    pub fn params_v0(seed: u32) -> Self {
+        registry::daily_v0(seed)
+            .and_then(|entry| entry.from_seed_v0)
+            .map(|from_seed| from_seed(seed))
+            .expect("the built-in registry has seeded types")
+    }
+
+    // Type and settings drawn from a SeededRng on its own stream
+    pub fn params_v1(seed: u32) -> Self {
+        let mut rng = SeededRng::new(seed as u64, DERIVATION_V1_STREAM);
+        registry::daily_v1(&mut rng)
+            .and_then(|entry| entry.from_rng)
+            .map(|from_rng| from_rng(&mut rng))
+            .expect("the built-in registry has seeded types")
+    }
+
+    // The plane fractal shown instead of the daily one in 2D mode: a Julia set near the
+    // Mandelbrot boundary for most seeds, the Mandelbrot set itself for the rest
+    pub fn plane_from_seed(seed: u32) -> Self {
+        let iterations = 96 + ((seed / 5) % 4) as i32 * 32;
+        if seed.is_multiple_of(5) {
+            return FractalParams::Mandelbrot2D { julia: None, iterations };
+        }
+
+        let angle = (seed % 360) as f32 / 360.0 * std::f32::consts::TAU;
+        let radius = 0.7 + ((seed / 360) % 8) as f32 * 0.01;
+        FractalParams::Mandelbrot2D {
+            julia: Some([radius * angle.cos(), radius * angle.sin()]),
+            iterations,
+        }
+    }
+
+    // Drawn by FRAGMENT_SHADER_2D rather than the raymarcher
+    pub fn is_planar(&self) -> bool {
+        matches!(self, FractalParams::Mandelbrot2D { .. })
+    }
+
+    // The budget level of detail scales: iterations, fold count or L-system generations.
+    // None for Hybrid, whose orbit length is fixed by HYBRID_ITERATIONS.
+    pub fn iterations(&self) -> Option<i32> {
+        match *self {
+            FractalParams::Mandelbulb { iterations, .. }
+            | FractalParams::Julia4D { iterations, .. }
+            | FractalParams::Mandelbox { iterations, .. }
+            | FractalParams::Apollonian { iterations, .. }
+            | FractalParams::Mandelbrot2D { iterations, .. }
+            | FractalParams::LSystem { iterations, .. } => Some(iterations),
+            FractalParams::KaleidoIFS { fold_count, .. }
+            | FractalParams::MengerSponge { fold_count, .. } => Some(fold_count),
+            FractalParams::Hybrid(_) => None,
+        }
+    }
+
+    // The shader loop bound on iterations(), and so the most any params may ask for
+    fn iteration_limit(&self) -> i32 {
+        match self {
+            FractalParams::Mandelbulb { .. }
+            | FractalParams::Julia4D { .. }
+            | FractalParams::Mandelbox { .. }
+            | FractalParams::KaleidoIFS { .. } => 16,
+            FractalParams::Apollonian { .. } => 12,
+            FractalParams::Mandelbrot2D { .. } => 256,
+            FractalParams::LSystem { .. } => 5,
+            FractalParams::MengerSponge { .. } => 8,
+            FractalParams::Hybrid(_) => HYBRID_ITERATIONS,
+        }
+    }
+
+    // The same settings with iterations() replaced, clamped to 1 and the shader loop bound
+    pub fn with_iterations(&self, count: i32) -> Self {
+        let count = count.max(1).min(self.iteration_limit());
+        let mut params = *self;
+        match &mut params {
+            FractalParams::Mandelbulb { iterations, .. }
+            | FractalParams::Julia4D { iterations, .. }
+            | FractalParams::Mandelbox { iterations, .. }
+            | FractalParams::Apollonian { iterations, .. }
+            | FractalParams::Mandelbrot2D { iterations, .. }
+            | FractalParams::LSystem { iterations, .. } => *iterations = count,
+            FractalParams::KaleidoIFS { fold_count, .. }
+            | FractalParams::MengerSponge { fold_count, .. } => *fold_count = count,
+            FractalParams::Hybrid(_) => {}
+        }
+        params
+    }
+
+    // Params from anywhere not built here (links, imports, synced state, peers) are only
+    // used within these: counts from 1 to the shader loop bound, and floats finite and
+    // well around what derivation, evolution and breeding produce. Past them the CPU
+    // estimators could run for billions of steps, or give NaN everywhere
+    pub fn validate(&self) -> Result<(), String> {
+        let within = |name: &str, value: f32, min: f32, max: f32| {
+            if value.is_finite() && value >= min && value <= max {
+                Ok(())
+            } else {
+                Err(format!("{} {} is outside {} to {}", name, value, min, max))
+            }
+        };
+        let formula = |formula: HybridFormula| match formula {
+            HybridFormula::Mandelbulb { power } => within("Power", power, 1.0, 32.0),
+            HybridFormula::Mandelbox { scale, min_radius } => {
+                within("Scale", scale, -8.0, 8.0)?;
+                within("Minimum radius", min_radius, 0.01, 2.0)
+            }
+        };
+        if let Some(count) = self.iterations() {
+            if count < 1 || count > self.iteration_limit() {
+                return Err(format!("{} iterations, expected 1 to {}", count, self.iteration_limit()));
+            }
+        }
+        match *self {
+            FractalParams::Mandelbulb { power, .. } => formula(HybridFormula::Mandelbulb { power }),
+            FractalParams::Julia4D { c, .. } => c.iter().try_for_each(|&part| within("Julia constant", part, -4.0, 4.0)),
+            FractalParams::KaleidoIFS { scale, .. } => within("Scale", scale, -8.0, 8.0),
+            FractalParams::Mandelbox { scale, min_radius, .. } => formula(HybridFormula::Mandelbox { scale, min_radius }),
+            FractalParams::MengerSponge { hole_scale, .. } => within("Hole scale", hole_scale, 1.0, 8.0),
+            FractalParams::Apollonian { inversion, .. } => within("Inversion", inversion, 0.1, 4.0),
+            FractalParams::Hybrid(spec) => {
+                let limit = HYBRID_ITERATIONS as u32;
+                if !(1..=limit).contains(&spec.first_steps) || !(1..=limit).contains(&spec.second_steps) {
+                    return Err(format!("Hybrid steps {} and {}, expected 1 to {}", spec.first_steps, spec.second_steps, limit));
+                }
+                formula(spec.first)?;
+                formula(spec.second)
+            }
+            FractalParams::Mandelbrot2D { julia, .. } =>
+                julia.unwrap_or_default().iter().try_for_each(|&part| within("Julia constant", part, -4.0, 4.0)),
+            FractalParams::LSystem { angle, .. } => within("Angle", angle, -360.0, 360.0),
+        }
+    }
+
+    // Drawn as lines by LineRenderer; there is no shader distance estimator for these
+    pub fn is_line_geometry(&self) -> bool {
+        matches!(self, FractalParams::LSystem { .. })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, JsValue> {
+        let params: Self = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid fractal params: {}", e)))?;
+        params.validate().map_err(|e| JsValue::from_str(&format!("Invalid fractal params: {}", e)))?;
+        Ok(params)
+    }
+
+    // u_fractal_type in FRAGMENT_SHADER
+    pub fn shader_id(&self) -> i32 {
+        self.fractal_type().shader_id()
+    }
+
+    // This variant's registry entry
+    pub fn info(&self) -> FractalTypeInfo {
+        self.fractal_type().info()
+    }
+
+    // Layout of u_fractal_params[2]; each formula in FRAGMENT_SHADER documents what it reads
+    pub fn shader_params(&self) -> [f32; 8] {
+        match *self {
+            FractalParams::Mandelbulb { power, iterations } =>
+                [power, 0.0, iterations as f32, 0.0, 0.0, 0.0, 0.0, 0.0],
+            FractalParams::Julia4D { c, iterations } =>
+                [c[0], c[1], c[2], c[3], iterations as f32, 0.0, 0.0, 0.0],
+            FractalParams::KaleidoIFS { fold_count, scale } =>
+                [scale, 0.0, fold_count as f32, 0.0, 0.0, 0.0, 0.0, 0.0],
+            FractalParams::Mandelbox { scale, min_radius, iterations } =>
+                [scale, min_radius, iterations as f32, 0.0, 0.0, 0.0, 0.0, 0.0],
+            FractalParams::MengerSponge { fold_count, hole_scale } =>
+                [hole_scale, 0.0, fold_count as f32, 0.0, 0.0, 0.0, 0.0, 0.0],
+            FractalParams::Apollonian { inversion, iterations } =>
+                [inversion, 0.0, iterations as f32, 0.0, 0.0, 0.0, 0.0, 0.0],
+            FractalParams::Hybrid(spec) => {
+                let a = spec.first.shader_params();
+                let b = spec.second.shader_params();
+                [
+                    a[0], a[1], a[2], spec.first_steps as f32,
+                    b[0], b[1], b[2], spec.second_steps as f32,
+                ]
+            },
+            FractalParams::Mandelbrot2D { julia, iterations } => {
+                let c = julia.unwrap_or([0.0, 0.0]);
+                [c[0], c[1], iterations as f32, julia.is_some() as i32 as f32, 0.0, 0.0, 0.0, 0.0]
+            },
+            FractalParams::LSystem { .. } => [0.0; 8],
+        }
+    }
+
+    // The type of the fractal these params build
+    pub fn fractal_type(&self) -> FractalType {
+        match self {
+            FractalParams::Mandelbulb { .. } => FractalType::Mandelbulb,
+            FractalParams::Julia4D { .. } => FractalType::Julia4D,
+            FractalParams::KaleidoIFS { .. } => FractalType::KaleidoIFS,
+            FractalParams::Mandelbox { .. } => FractalType::Mandelbox,
+            FractalParams::MengerSponge { .. } => FractalType::MengerSponge,
+            FractalParams::Apollonian { .. } => FractalType::Apollonian,
+            FractalParams::Hybrid(_) => FractalType::Hybrid,
+            FractalParams::Mandelbrot2D { .. } => FractalType::Mandelbrot2D,
+            FractalParams::LSystem { .. } => FractalType::LSystem,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.fractal_type().name()
+    }
+}
+
+impl FractalParams {
+    fn hybrid_formula(&self) -> Option<HybridFormula> {
+        match *self {
+            FractalParams::Mandelbulb { power, .. } => Some(HybridFormula::Mandelbulb { power }),
+            FractalParams::Mandelbox { scale, min_radius, .. } => Some(HybridFormula::Mandelbox { scale, min_radius }),
+            _ => None,
+        }
+    }
+
+    // Same formula: blend every setting. Bulb x box: a hybrid of the two. Otherwise one parent wins.
+    fn crossover(a: &FractalParams, b: &FractalParams) -> FractalParams {
+        use FractalParams::*;
+
+        let t = random();
+        match (*a, *b) {
+            (Mandelbulb { power: pa, iterations: ia }, Mandelbulb { power: pb, iterations: ib }) =>
+                Mandelbulb { power: lerp(pa, pb, t), iterations: pick(ia, ib) },
+            (Julia4D { c: ca, iterations: ia }, Julia4D { c: cb, iterations: ib }) => {
+                let mut c = ca;
+                for (ci, cb) in c.iter_mut().zip(cb.iter()) {
+                    *ci = lerp(*ci, *cb, t);
+                }
+                Julia4D { c, iterations: pick(ia, ib) }
+            },
+            (KaleidoIFS { fold_count: fa, scale: sa }, KaleidoIFS { fold_count: fb, scale: sb }) =>
+                KaleidoIFS { fold_count: pick(fa, fb), scale: lerp(sa, sb, t) },
+            (Mandelbox { scale: sa, min_radius: ra, iterations: ia },
+             Mandelbox { scale: sb, min_radius: rb, iterations: ib }) =>
+                Mandelbox { scale: lerp(sa, sb, t), min_radius: lerp(ra, rb, t), iterations: pick(ia, ib) },
+            (MengerSponge { fold_count: fa, hole_scale: ha }, MengerSponge { fold_count: fb, hole_scale: hb }) =>
+                MengerSponge { fold_count: pick(fa, fb), hole_scale: lerp(ha, hb, t) },
+            (Apollonian { inversion: va, iterations: ia }, Apollonian { inversion: vb, iterations: ib }) =>
+                Apollonian { inversion: lerp(va, vb, t), iterations: pick(ia, ib) },
+            (Hybrid(ha), Hybrid(hb)) => Hybrid(HybridSpec {
+                first: pick(ha.first, hb.first),
+                second: pick(ha.second, hb.second),
+                first_steps: pick(ha.first_steps, hb.first_steps),
+                second_steps: pick(ha.second_steps, hb.second_steps),
+            }),
+            _ => match (a.hybrid_formula(), b.hybrid_formula()) {
+                (Some(first), Some(second)) => Hybrid(HybridSpec {
+                    first,
+                    second,
+                    first_steps: 1 + (random() * 2.0) as u32,
+                    second_steps: 1 + (random() * 3.0) as u32,
+                }),
+                _ => pick(*a, *b),
+            },
+        }
+    }
+
+    // Nudges every setting by up to `rate` of its typical range, staying inside the ranges
+    // the seed rotation produces
+    fn mutate(&self, rate: f32) -> FractalParams {
+        use FractalParams::*;
+
+        match *self {
+            Mandelbulb { power, iterations } => Mandelbulb {
+                power: jitter(power, rate, 2.0, 16.0),
+                iterations: jitter_count(iterations, rate, 4, 14),
+            },
+            Julia4D { c, iterations } => Julia4D {
+                c: c.map(|v| jitter(v, rate, -1.0, 1.0)),
+                iterations: jitter_count(iterations, rate, 4, 16),
+            },
+            KaleidoIFS { fold_count, scale } => KaleidoIFS {
+                fold_count: jitter_count(fold_count, rate, 2, 14),
+                scale: jitter(scale, rate, 1.2, 4.5),
+            },
+            Mandelbox { scale, min_radius, iterations } => Mandelbox {
+                // Keep the sign; crossing zero collapses the box
+                scale: scale.signum() * jitter(scale.abs(), rate, 1.5, 3.0),
+                min_radius: jitter(min_radius, rate, 0.1, 0.6),
+                iterations: jitter_count(iterations, rate, 6, 16),
+            },
+            MengerSponge { fold_count, hole_scale } => MengerSponge {
+                fold_count: jitter_count(fold_count, rate, 2, 6),
+                hole_scale: jitter(hole_scale, rate, 2.5, 3.6),
+            },
+            Apollonian { inversion, iterations } => Apollonian {
+                inversion: jitter(inversion, rate, 0.8, 1.6),
+                iterations: jitter_count(iterations, rate, 4, 12),
+            },
+            Hybrid(spec) => {
+                let formula = |f: HybridFormula| match f {
+                    HybridFormula::Mandelbulb { power } =>
+                        HybridFormula::Mandelbulb { power: jitter(power, rate, 2.0, 16.0) },
+                    HybridFormula::Mandelbox { scale, min_radius } => HybridFormula::Mandelbox {
+                        scale: scale.signum() * jitter(scale.abs(), rate, 1.5, 3.0),
+                        min_radius: jitter(min_radius, rate, 0.1, 0.6),
+                    },
+                };
+                Hybrid(HybridSpec {
+                    first: formula(spec.first),
+                    second: formula(spec.second),
+                    first_steps: jitter_count(spec.first_steps as i32, rate, 1, 3) as u32,
+                    second_steps: jitter_count(spec.second_steps as i32, rate, 1, 4) as u32,
+                })
+            },
+            Mandelbrot2D { julia, iterations } => Mandelbrot2D {
+                julia: julia.map(|c| c.map(|v| jitter(v, rate, -1.0, 1.0))),
+                iterations: jitter_count(iterations, rate, 32, 256),
+            },
+            // The grammar itself is not mutated; a different rule_seed is a different plant
+            LSystem { rule_seed, iterations, angle } => LSystem {
+                rule_seed,
+                iterations: jitter_count(iterations, rate, 2, 5),
+                angle: jitter(angle, rate, 15.0, 40.0),
+            },
+        }
+    }
+}
+
+// Child of two frozen fractals: mixed seed, blended transform and crossed-over params,
+// then mutated by up to `mutation_rate` (0..1) of each setting's range. It is a
+// generation on from the later of its parents, scored on its own geometry
+pub fn breed(a: &FrozenFractal, b: &FrozenFractal, mutation_rate: f32) -> FrozenFractal {
+    let rate = mutation_rate.max(0.0).min(1.0);
+
+    let mut seed = a.seed ^ b.seed.rotate_left(16);
+    seed = seed.wrapping_mul(0x9E37_79B1) ^ (seed >> 15);
+
+    let transform_matrix = if a.transform_matrix.len() == 16 && b.transform_matrix.len() == 16 {
+        let t = random();
+        a.transform_matrix.iter().zip(b.transform_matrix.iter())
+            .map(|(x, y)| lerp(*x, *y, t) + (random() - 0.5) * 0.2 * rate)
+            .collect()
+    } else {
+        Matrix4::<f32>::identity().as_slice().to_vec()
+    };
+
+    let params = FractalParams::crossover(&a.fractal_params(), &b.fractal_params()).mutate(rate);
+
+    FrozenFractal {
+        version: FROZEN_VERSION,
+        seed,
+        fractal_type: Some(params.fractal_type()),
+        transform_matrix,
+        complexity_score: complexity::analyze(&params).score,
+        timestamp: js_sys::Date::now() as u64,
+        interaction_count: 0,
+        coloring_mode: pick(a.coloring_mode, b.coloring_mode),
+        palette: pick(a.palette.clone(), b.palette.clone()),
+        params: Some(params),
+        derivation: CURRENT_DERIVATION,
+        math_version: math::MATH_VERSION,
+        lineage: Lineage::child_of(&[a, b]),
+        curation: Curation::default(),
+    }
+}
+
+// Someone else's frozen fractal answered with `transform_matrix`, row by row as it's saved
+// (see UserState::matrix_data): the same fractal in a new pose, a generation on from the
+// original
+pub fn echo(original: &FrozenFractal, transform_matrix: Vec<f32>) -> FrozenFractal {
+    FrozenFractal {
+        transform_matrix,
+        timestamp: js_sys::Date::now() as u64,
+        interaction_count: 0,
+        lineage: Lineage::child_of(&[original]),
+        curation: Curation::default(),
+        ..original.clone()
+    }
+}
+
+fn random() -> f32 {
+    js_sys::Math::random() as f32
+}
+
+fn pick<T>(a: T, b: T) -> T {
+    if random() < 0.5 { a } else { b }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn jitter(value: f32, rate: f32, min: f32, max: f32) -> f32 {
+    (value + (random() * 2.0 - 1.0) * rate * (max - min)).max(min).min(max)
+}
+
+fn jitter_count(value: i32, rate: f32, min: i32, max: i32) -> i32 {
+    jitter(value as f32, rate, min as f32, max as f32).round() as i32
+}
+
+// Settings part way (t in 0..1) from `a` to `b`. Continuous settings interpolate (the
+// Julia constant along the arc between the two, so the set doesn't shrink mid-way),
+// counts step to the nearest whole value, and whatever can't blend, including a change
+// of type, switches at the midpoint.
+pub fn morph(a: &FractalParams, b: &FractalParams, t: f32) -> FractalParams {
+    use FractalParams::*;
+
+    let t = t.max(0.0).min(1.0);
+    let count = |x: i32, y: i32| lerp(x as f32, y as f32, t).round() as i32;
+
+    match (*a, *b) {
+        (Mandelbulb { power: pa, iterations: ia }, Mandelbulb { power: pb, iterations: ib }) =>
+            Mandelbulb { power: lerp(pa, pb, t), iterations: count(ia, ib) },
+        (Julia4D { c: ca, iterations: ia }, Julia4D { c: cb, iterations: ib }) =>
+            Julia4D { c: slerp(ca, cb, t), iterations: count(ia, ib) },
+        (KaleidoIFS { fold_count: fa, scale: sa }, KaleidoIFS { fold_count: fb, scale: sb }) =>
+            KaleidoIFS { fold_count: count(fa, fb), scale: lerp(sa, sb, t) },
+        // |scale| blends and the sign (the spiky variant) flips at the midpoint, rather
+        // than passing through the degenerate scale 0
+        (Mandelbox { scale: sa, min_radius: ra, iterations: ia },
+         Mandelbox { scale: sb, min_radius: rb, iterations: ib }) => Mandelbox {
+            scale: lerp(sa.abs(), sb.abs(), t).copysign(halfway(sa, sb, t)),
+            min_radius: lerp(ra, rb, t),
+            iterations: count(ia, ib),
+        },
+        (MengerSponge { fold_count: fa, hole_scale: ha }, MengerSponge { fold_count: fb, hole_scale: hb }) =>
+            MengerSponge { fold_count: count(fa, fb), hole_scale: lerp(ha, hb, t) },
+        (Apollonian { inversion: va, iterations: ia }, Apollonian { inversion: vb, iterations: ib }) =>
+            Apollonian { inversion: lerp(va, vb, t), iterations: count(ia, ib) },
+        (Hybrid(ha), Hybrid(hb)) => Hybrid(HybridSpec {
+            first: ha.first.morph(&hb.first, t),
+            second: ha.second.morph(&hb.second, t),
+            first_steps: count(ha.first_steps as i32, hb.first_steps as i32) as u32,
+            second_steps: count(ha.second_steps as i32, hb.second_steps as i32) as u32,
+        }),
+        (Mandelbrot2D { julia: ja, iterations: ia }, Mandelbrot2D { julia: jb, iterations: ib }) => Mandelbrot2D {
+            julia: match (ja, jb) {
+                (Some(ca), Some(cb)) => Some([lerp(ca[0], cb[0], t), lerp(ca[1], cb[1], t)]),
+                _ => halfway(ja, jb, t),
+            },
+            iterations: count(ia, ib),
+        },
+        (LSystem { rule_seed: ra, iterations: ia, angle: aa }, LSystem { rule_seed: rb, iterations: ib, angle: ab }) =>
+            LSystem { rule_seed: halfway(ra, rb, t), iterations: count(ia, ib), angle: lerp(aa, ab, t) },
+        _ => halfway(*a, *b, t),
+    }
+}
+
+fn halfway<T>(a: T, b: T, t: f32) -> T {
+    if t < 0.5 { a } else { b }
+}
+
+// Direction along the great circle, length interpolated separately; falls back to a
+// straight lerp when either end is at the origin or the two point opposite ways
+fn slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let (va, vb) = (Vector4::from(a), Vector4::from(b));
+    let (la, lb) = (va.norm(), vb.norm());
+    let angle = if la > 1e-6 && lb > 1e-6 {
+        (va.dot(&vb) / (la * lb)).max(-1.0).min(1.0).acos()
+    } else {
+        0.0
+    };
+
+    if angle < 1e-4 || angle.sin() < 1e-4 {
+        return va.lerp(&vb, t).into();
+    }
+
+    let direction = (va / la * ((1.0 - t) * angle).sin() + vb / lb * (t * angle).sin()) / angle.sin();
+    (direction * lerp(la, lb, t)).into()
+}
+
+// A timed morph from one fractal into another, eased in and out
+#[derive(Clone, Copy, Debug)]
+pub struct Morph {
+    pub from: FractalParams,
+    pub to: FractalParams,
+    elapsed: f32,
+    duration: f32, // Seconds
+}
+
+impl Morph {
+    pub fn new(from: FractalParams, to: FractalParams, duration: f32) -> Self {
+        Morph { from, to, elapsed: 0.0, duration: duration.max(0.0) }
+    }
+
+    pub fn advance(&mut self, seconds: f32) {
+        self.elapsed = (self.elapsed + seconds).min(self.duration);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn params(&self) -> FractalParams {
+        if self.is_finished() {
+            return self.to;
+        }
+        let t = self.elapsed / self.duration;
+        morph(&self.from, &self.to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+pub fn create_fractal_from_params(params: &FractalParams, time: f32, coloring: ColoringMode,
+                                  palette: Palette) -> Box<dyn FractalGenerator> {
+    (params.info().build)(params, time, coloring, palette)
+}
+
+// `fractal` at `detail` (0..1) of its full iteration budget, keeping at least one iteration
+pub fn with_detail(fractal: Box<dyn FractalGenerator>, detail: f32) -> Box<dyn FractalGenerator> {
+    match fractal.get_params().iterations() {
+        Some(full) if detail < 1.0 => fractal.with_iterations((full as f32 * detail).round() as i32),
+        _ => fractal,
+    }
+}
+
+// Camera and march budget of main() in FRAGMENT_SHADER
+const CPU_MAX_STEPS: i32 = 80;
+const CPU_HIT_EPSILON: f32 = 0.002;
+const CPU_MAX_DISTANCE: f32 = 15.0;
+
+// Software raymarch of a fractal at time 0 with the classic spectrum colors, for when no
+// WebGL context is around. Returns width x height RGBA bytes, top row first.
+pub fn render_cpu(params: &FractalParams, width: u32, height: u32) -> Vec<u8> {
+    let palette = Palette::named("spectrum").unwrap();
+    let fractal = create_fractal_from_params(params, 0.0, ColoringMode::Standard, palette);
+    render_fractal_cpu(&*fractal, width, height)
+}
+
+pub fn render_fractal_cpu(fractal: &dyn FractalGenerator, width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    let min_side = width.min(height).max(1) as f32;
+    let light = Vector3::new(-0.5, 0.8, -0.6).normalize();
+
+    for y in 0..height {
+        for x in 0..width {
+            let uv_x = (x as f32 + 0.5 - 0.5 * width as f32) / min_side;
+            let uv_y = (0.5 * height as f32 - y as f32 - 0.5) / min_side;
+
+            let origin = Vector3::new(uv_x * 2.5, uv_y * 2.5, -4.0);
+            let dir = Vector3::new(uv_x * 0.6, uv_y * 0.6, 1.0).normalize();
+
+            let color = match march_cpu(fractal, &origin, &dir) {
+                Some((pos, steps, dist)) => {
+                    let p = Vector4::new(pos.x, pos.y, pos.z, 0.0);
+                    let normal = estimate_normal_cpu(fractal, &pos);
+                    let diffuse = normal.dot(&light).max(0.0);
+                    let occlusion = 1.0 - steps as f32 / CPU_MAX_STEPS as f32 * 0.5;
+                    let iterations = fractal.orbit_stats(&p, i32::MAX).smooth_iteration;
+                    fractal.get_color(iterations, dist, &p) * (0.3 + 0.7 * diffuse) * occlusion
+                }
+                None => Vector3::new(0.0, 0.0, 0.02),
+            };
+
+            for channel in color.iter() {
+                pixels.push((channel.max(0.0).min(1.0) * 255.0) as u8);
+            }
+            pixels.push(255);
+        }
+    }
+
+    pixels
+}
+
+// Returns (hit position, steps taken, distance at the hit)
+pub(crate) fn march_cpu(fractal: &dyn FractalGenerator, origin: &Vector3<f32>, dir: &Vector3<f32>) -> Option<(Vector3<f32>, i32, f32)> {
+    let mut t = 0.0f32;
+
+    for step in 0..CPU_MAX_STEPS {
+        let pos = origin + dir * t;
+        let dist = fractal.distance_estimator(&Vector4::new(pos.x, pos.y, pos.z, 0.0));
+
+        if dist < CPU_HIT_EPSILON {
+            return Some((pos, step, dist));
+        }
+
+        t += dist * 0.9;
+        if !t.is_finite() || t > CPU_MAX_DISTANCE {
+            break;
+        }
+    }
+
+    None
+}
+
+fn estimate_normal_cpu(fractal: &dyn FractalGenerator, pos: &Vector3<f32>) -> Vector3<f32> {
+    let de = |offset: Vector3<f32>| {
+        let p = pos + offset;
+        fractal.distance_estimator(&Vector4::new(p.x, p.y, p.z, 0.0))
+    };
+
+    let e = 0.002;
+    let normal = Vector3::new(
+        de(Vector3::new(e, 0.0, 0.0)) - de(Vector3::new(-e, 0.0, 0.0)),
+        de(Vector3::new(0.0, e, 0.0)) - de(Vector3::new(0.0, -e, 0.0)),
+        de(Vector3::new(0.0, 0.0, e)) - de(Vector3::new(0.0, 0.0, -e)),
+    );
+    normal.try_normalize(1e-9).unwrap_or(Vector3::new(0.0, 0.0, -1.0))
+}
+
+// Half-size of the cube searched for the surface before voxelizing; holds the widest Mandelbox
+const VOXEL_SEARCH_EXTENT: f32 = 4.0;
+const VOXEL_SEARCH_SAMPLES: usize = 32;
+
+// Distance estimates sampled on a cubic lattice, for mesh export
+pub struct VoxelGrid {
+    pub resolution: usize, // Samples per axis
+    pub origin: Vector3<f32>,
+    pub spacing: f32,
+    pub values: Vec<f32>,  // x fastest, then y, then z
+}
+
+impl VoxelGrid {
+    pub fn value(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[x + self.resolution * (y + self.resolution * z)]
+    }
+
+    pub fn position(&self, x: usize, y: usize, z: usize) -> Vector3<f32> {
+        self.origin + Vector3::new(x as f32, y as f32, z as f32) * self.spacing
+    }
+}
+
+// Samples a fractal's distance field at time 0 on a resolution^3 lattice fitted around
+// its surface, found first with a coarse pass over the search cube
+pub fn voxelize(params: &FractalParams, resolution: usize) -> VoxelGrid {
+    let fractal = create_fractal_from_params(params, 0.0, ColoringMode::Standard, Palette::from_seed(0));
+    let distance = |p: &Vector3<f32>| fractal.distance_estimator(&Vector4::new(p.x, p.y, p.z, 0.0));
+
+    let coarse = 2.0 * VOXEL_SEARCH_EXTENT / (VOXEL_SEARCH_SAMPLES - 1) as f32;
+    let mut min = Vector3::repeat(f32::MAX);
+    let mut max = Vector3::repeat(f32::MIN);
+    for z in 0..VOXEL_SEARCH_SAMPLES {
+        for y in 0..VOXEL_SEARCH_SAMPLES {
+            for x in 0..VOXEL_SEARCH_SAMPLES {
+                let p = Vector3::new(x as f32, y as f32, z as f32) * coarse - Vector3::repeat(VOXEL_SEARCH_EXTENT);
+                if distance(&p) < coarse {
+                    min = min.inf(&p);
+                    max = max.sup(&p);
+                }
+            }
+        }
+    }
+    if min.x > max.x {
+        min = Vector3::repeat(-1.0);
+        max = Vector3::repeat(1.0);
+    }
+
+    // Pad by a coarse cell so the surface never touches the lattice boundary
+    let center = (min + max) * 0.5;
+    let half = 0.5 * (max - min).max() + coarse;
+    let resolution = resolution.max(2);
+    let spacing = 2.0 * half / (resolution - 1) as f32;
+    let origin = center - Vector3::repeat(half);
+
+    let mut values = Vec::with_capacity(resolution * resolution * resolution);
+    for z in 0..resolution {
+        for y in 0..resolution {
+            for x in 0..resolution {
+                values.push(distance(&(origin + Vector3::new(x as f32, y as f32, z as f32) * spacing)));
+            }
+        }
+    }
+
+    VoxelGrid { resolution, origin, spacing, values }
+}
+
+// HSV to RGB conversion for beautiful colors
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Vector3<f32> {
+    let c = v * s;
+    let h_prime = (h * 6.0) % 6.0;
+    let x = c * (1.0 - ((h_prime % 2.0) - 1.0).abs());
+    let m = v - c;
+
+    let (r, g, b) = if h_prime < 1.0 {
+        (c, x, 0.0)
+    } else if h_prime < 2.0 {
+        (x, c, 0.0)
+    } else if h_prime < 3.0 {
+        (0.0, c, x)
+    } else if h_prime < 4.0 {
+        (0.0, x, c)
+    } else if h_prime < 5.0 {
+        (x, 0.0, c)
+    } else {
+        (c, 0.0, x)
+    };
+
+    Vector3::new(r + m, g + m, b + m)
+}
+
+// Audio synthesis from fractal geometry - thinking ahead
+// Orbit iterations used for analysis; enough to tell escaping orbits from bounded ones
+const ANALYSIS_ITERATIONS: i32 = 16;
+
+// Short hex signature of how orbits behave on a fixed probe cube: one digit for the
+// escape iteration and one for the closest approach at each corner
+pub fn orbit_fingerprint(fractal: &dyn FractalGenerator) -> String {
+    let mut fingerprint = String::with_capacity(16);
+
+    for corner in 0..8 {
+        let sign = |bit: i32| if corner & bit == 0 { -0.8 } else { 0.8 };
+        let probe = Vector4::new(sign(1), sign(2), sign(4), 0.0);
+        let stats = fractal.orbit_stats(&probe, ANALYSIS_ITERATIONS);
+
+        let escape_digit = stats.escape_iteration.max(0).min(15) as u32;
+        let distance_digit = (stats.min_distance * 8.0).max(0.0).min(15.0) as u32;
+        fingerprint.push(std::char::from_digit(escape_digit, 16).unwrap());
+        fingerprint.push(std::char::from_digit(distance_digit, 16).unwrap());
+    }
+
+    fingerprint
+}
+
+// Iterations traced per sample point for its spectrum
+const SPECTRAL_ITERATIONS: i32 = ORBIT_TRACE_LEN as i32 - 1;
+
+// Fundamentals are A2 plus one of these steps (semitones): the major pentatonic over two
+// octaves, so every point lands in the same key and no two notes clash
+const ROOT_FREQUENCY: f32 = 110.0;
+const PENTATONIC_STEPS: [i32; 10] = [0, 2, 4, 7, 9, 12, 14, 16, 19, 21];
+
+// Strongest orbit periodicities voiced as overtones of each fundamental
+const SPECTRAL_PEAKS: usize = 2;
+
+pub struct FractalAudioAnalyzer;
+
+impl FractalAudioAnalyzer {
+    // Where the live engine listens at `time` seconds: three axis points drifting through
+    // the fourth dimension and one circling the origin
+    pub fn sample_points(time: f32) -> Vec<Vector4<f32>> {
+        vec![
+            Vector4::new(1.0, 0.0, 0.0, time * 0.1),
+            Vector4::new(0.0, 1.0, 0.0, time * 0.13),
+            Vector4::new(0.0, 0.0, 1.0, time * 0.17),
+            Vector4::new(math::sin(time * 0.1), math::cos(time * 0.1), 0.0, 0.5),
+        ]
+    }
+
+    pub fn extract_frequencies(fractal: &dyn FractalGenerator, sample_points: &[Vector4<f32>]) -> Vec<f32> {
+        Self::extract_voices(fractal, sample_points).into_iter().map(|(frequency, _)| frequency).collect()
+    }
+
+    // Per sample point, a fundamental set by how the orbit escapes, then overtones from the
+    // strongest periodicities in its magnitudes; each paired with the index of its point
+    pub fn extract_voices(fractal: &dyn FractalGenerator, sample_points: &[Vector4<f32>]) -> Vec<(f32, usize)> {
+        let mut voices = Vec::with_capacity(sample_points.len() * (1 + SPECTRAL_PEAKS));
+
+        for (index, point) in sample_points.iter().enumerate() {
+            let stats = fractal.orbit_stats(point, SPECTRAL_ITERATIONS);
+            let fundamental = Self::fundamental(&stats);
+            voices.push((fundamental, index));
+
+            // A component repeating every 1/cycles iterations becomes harmonic 2 (slow
+            // drift) up to 5 (period two), which keeps the top note under AudioEngine's 2 kHz
+            for (cycles, _) in orbit_spectrum(stats.orbit()).into_iter().take(SPECTRAL_PEAKS) {
+                voices.push((fundamental * (2.0 + (cycles * 6.0).round()), index));
+            }
+        }
+
+        voices
+    }
+
+    // Quick escapes sit high in the upper octave and late ones low in it; bounded orbits
+    // take the lower octave, by how close to the origin they come
+    fn fundamental(stats: &OrbitStats) -> f32 {
+        let position = if stats.escaped {
+            0.5 + 0.5 * (1.0 - stats.smooth_iteration / SPECTRAL_ITERATIONS as f32).max(0.0).min(1.0)
+        } else {
+            0.5 * stats.min_distance.max(0.0).min(1.0)
+        };
+        let degree = (position * (PENTATONIC_STEPS.len() - 1) as f32).round() as usize;
+        ROOT_FREQUENCY * 2f32.powf(PENTATONIC_STEPS[degree] as f32 / 12.0)
+    }
+
+    pub fn create_harmonic_series(fundamental: f32, harmonics: usize) -> Vec<f32> {
+        (1..=harmonics).map(|h| fundamental * h as f32).collect()
+    }
+}
+
+// Power spectrum of an orbit's magnitudes as (cycles per iteration in 0..0.5, power),
+// strongest first. Magnitudes are log-compressed so an escaping orbit's last step doesn't
+// drown out everything before it; its steady climb still shows up in the lowest bins.
+pub fn orbit_spectrum(magnitudes: &[f32]) -> Vec<(f32, f32)> {
+    let n = magnitudes.len();
+    if n < 4 {
+        return Vec::new();
+    }
+
+    let samples: Vec<f32> = magnitudes.iter().map(|m| m.max(0.0).ln_1p()).collect();
+    let mean = samples.iter().sum::<f32>() / n as f32;
+
+    let mut spectrum: Vec<(f32, f32)> = (1..=n / 2).map(|bin| {
+        let (re, im) = samples.iter().enumerate().fold((0.0f32, 0.0f32), |(re, im), (k, x)| {
+            let angle = std::f32::consts::TAU * (bin * k) as f32 / n as f32;
+            (re + (x - mean) * angle.cos(), im - (x - mean) * angle.sin())
+        });
+        (bin as f32 / n as f32, (re * re + im * im) / n as f32)
+    }).filter(|(_, power)| *power > 1e-6).collect();
+
+    spectrum.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    spectrum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_evolved_and_preset_params_validate() {
+        let derived = (0..2000).flat_map(|seed| {
+            let params = [FractalParams::params_v0(seed), FractalParams::params_v1(seed), FractalParams::plane_from_seed(seed)];
+            let evolved = params.map(|params| params.info().evolve.map_or(params, |evolve| evolve(&params, 1.0)));
+            params.into_iter().chain(evolved).chain([FractalParams::Hybrid(HybridSpec::from_seed(seed))])
+        });
+        for params in derived.chain(crate::presets::PRESETS.iter().map(|preset| preset.params)) {
+            assert_eq!(params.validate(), Ok(()), "{:?}", params);
+        }
+    }
+
+    // Each derivation's params for a seed of every type it draws, as first released; a
+    // change here changes historical fractals, and wants a new derivation version instead
+    #[test]
+    fn derivations_are_pinned() {
+        let v0 = [
+            FractalParams::Mandelbulb { power: 6.0, iterations: 8 },
+            FractalParams::Julia4D { c: [-1.0, -1.0, -1.0, -1.0], iterations: 8 },
+            FractalParams::KaleidoIFS { fold_count: 4, scale: 1.5 },
+            FractalParams::Mandelbox { scale: 2.0, min_radius: 0.25, iterations: 10 },
+            FractalParams::MengerSponge { fold_count: 3, hole_scale: 2.8 },
+            FractalParams::Apollonian { inversion: 1.0, iterations: 6 },
+            FractalParams::LSystem { rule_seed: 0, iterations: 3, angle: 18.0 },
+        ];
+        for (seed, expected) in v0.into_iter().enumerate() {
+            assert_eq!(FractalParams::params_v0(seed as u32), expected, "version 0, seed {}", seed);
+        }
+
+        let v1 = [
+            (0, FractalParams::LSystem { rule_seed: 4087829721, iterations: 3, angle: 24.145172 }),
+            (1, FractalParams::MengerSponge { fold_count: 4, hole_scale: 3.0148263 }),
+            (2, FractalParams::Julia4D { c: [0.0067589283, -0.19319725, -0.7699808, -0.9742247], iterations: 13 }),
+            (4, FractalParams::Apollonian { inversion: 1.0927482, iterations: 9 }),
+            (6, FractalParams::KaleidoIFS { fold_count: 4, scale: 2.8144655 }),
+            (7, FractalParams::Mandelbox { scale: 2.0525331, min_radius: 0.29345706, iterations: 13 }),
+            (9, FractalParams::Mandelbulb { power: 8.364107, iterations: 11 }),
+        ];
+        for (seed, expected) in v1 {
+            assert_eq!(FractalParams::params_v1(seed), expected, "version 1, seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn params_past_the_bounds_rejected() {
+        let spec = HybridSpec::from_seed(7);
+        let crafted = [
+            FractalParams::MengerSponge { fold_count: i32::MAX, hole_scale: 3.0 },
+            FractalParams::KaleidoIFS { fold_count: 1_000_000_000, scale: 2.0 },
+            FractalParams::Mandelbulb { power: f32::NAN, iterations: 10 },
+            FractalParams::Julia4D { c: [0.0, f32::INFINITY, 0.0, 0.0], iterations: 10 },
+            FractalParams::Mandelbox { scale: 2.0, min_radius: 0.5, iterations: 0 },
+            FractalParams::Apollonian { inversion: 1.2, iterations: -1 },
+            FractalParams::Mandelbrot2D { julia: None, iterations: 100_000 },
+            FractalParams::LSystem { rule_seed: 0, iterations: 40, angle: 22.5 },
+            FractalParams::Hybrid(HybridSpec { first_steps: 0, ..spec }),
+            FractalParams::Hybrid(HybridSpec { second: HybridFormula::Mandelbox { scale: 2.0, min_radius: 0.0 }, ..spec }),
+        ];
+        for params in crafted {
+            assert!(params.validate().is_err(), "{:?} passed", params);
+        }
+    }
+
+    #[test]
+    fn unchecked_hybrid_steps_still_orbit() {
+        let spec = HybridSpec { first_steps: u32::MAX, second_steps: u32::MAX, ..HybridSpec::from_seed(7) };
+        let fractal = HybridFractal { spec, time: 0.0, coloring: ColoringMode::Standard, palette: Palette::from_seed(7) };
+        assert!(fractal.distance_estimator(&Vector4::new(0.3, 0.2, 0.1, 0.0)).is_finite());
+    }
+
+    #[test]
+    fn with_iterations_stays_valid() {
+        for params in crate::presets::PRESETS.iter().map(|preset| preset.params) {
+            for count in [i32::MIN, 0, 1, 1000, i32::MAX] {
+                assert_eq!(params.with_iterations(count).validate(), Ok(()), "{:?} at {}", params, count);
+            }
+        }
+    }
+}
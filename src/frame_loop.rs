@@ -0,0 +1,93 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+// Longest step handed to the frame callback, so a backgrounded tab doesn't jump the animation
+const MAX_DELTA_MS: f64 = 250.0;
+
+type FrameClosure = Closure<dyn FnMut(f64)>;
+
+// requestAnimationFrame loop with delta-time tracking and an optional FPS cap
+pub struct FrameLoop {
+    running: Rc<Cell<bool>>,
+    request_id: Rc<Cell<Option<i32>>>,
+    // The closure reschedules itself, so it lives in a slot it also holds; clearing the
+    // slot breaks that cycle
+    closure: Rc<RefCell<Option<FrameClosure>>>,
+}
+
+impl FrameLoop {
+    pub fn new() -> Self {
+        FrameLoop {
+            running: Rc::new(Cell::new(false)),
+            request_id: Rc::new(Cell::new(None)),
+            closure: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.get()
+    }
+
+    // Calls `on_frame` with the elapsed milliseconds since the last rendered frame.
+    // A cap of 0 renders on every animation frame.
+    pub fn start<F: FnMut(f32) + 'static>(&mut self, frame_cap_fps: f32, mut on_frame: F) -> Result<(), JsValue> {
+        self.stop();
+
+        let min_interval = if frame_cap_fps > 0.0 { 1000.0 / frame_cap_fps as f64 } else { 0.0 };
+        let mut last_frame: Option<f64> = None;
+
+        let running = self.running.clone();
+        let request_id = self.request_id.clone();
+        let slot = self.closure.clone();
+
+        let closure = Closure::wrap(Box::new(move |timestamp: f64| {
+            if !running.get() {
+                return;
+            }
+
+            let elapsed = last_frame.map(|last| timestamp - last).unwrap_or(0.0);
+
+            // Leave a little slack so a 60Hz display isn't rounded down to 30fps by jitter
+            if last_frame.is_none() || elapsed >= min_interval - 1.0 {
+                last_frame = Some(timestamp);
+                on_frame(elapsed.min(MAX_DELTA_MS) as f32);
+            }
+
+            // on_frame may have stopped the loop
+            if running.get() {
+                if let Some(closure) = slot.borrow().as_ref() {
+                    request_id.set(request_frame(closure).ok());
+                }
+            }
+        }) as Box<dyn FnMut(f64)>);
+
+        self.request_id.set(Some(request_frame(&closure)?));
+        *self.closure.borrow_mut() = Some(closure);
+        self.running.set(true);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.running.set(false);
+
+        if let Some(id) = self.request_id.take() {
+            if let Some(window) = web_sys::window() {
+                let _ = window.cancel_animation_frame(id);
+            }
+        }
+    }
+}
+
+impl Drop for FrameLoop {
+    fn drop(&mut self) {
+        self.stop();
+        self.closure.borrow_mut().take();
+    }
+}
+
+fn request_frame(closure: &FrameClosure) -> Result<i32, JsValue> {
+    let window = web_sys::window().ok_or("No window available")?;
+    window.request_animation_frame(closure.as_ref().unchecked_ref())
+}
@@ -0,0 +1,140 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::codec;
+use crate::sync::{self, Reply};
+use crate::user::FrozenFractal;
+
+// Entries asked for per page of the gallery
+pub const PAGE_SIZE: u32 = 24;
+
+// A fractal someone published, as the gallery lists it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GalleryEntry {
+    pub id: String,
+    #[serde(default)]
+    pub author: Option<String>, // As they chose to be credited; None for anonymous
+    #[serde(default)]
+    pub published_at: u64, // ms since the epoch
+    #[serde(default)]
+    pub thumbnail_url: Option<String>, // Where the server keeps the thumbnail, if it does
+    pub fractal: FrozenFractal,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GalleryPage {
+    pub page: u32, // From 0
+    pub items: Vec<GalleryEntry>,
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+// How a request to the gallery finished, for the host
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum GalleryEvent {
+    Published { id: String },
+    Page(GalleryPage),
+    Opened(Box<GalleryEntry>), // Boxed, as an entry carries a whole fractal
+    Failed { request: &'static str, error: String },
+}
+
+enum Pending {
+    Publish(Reply<String>),
+    List(Reply<GalleryPage>),
+    Open(Reply<GalleryEntry>),
+}
+
+#[derive(Deserialize)]
+struct Published {
+    id: String,
+}
+
+// Publishes frozen fractals to a public gallery the host runs, and browses it. The
+// endpoint takes a POST of {"fractal", "author", "thumbnail": {"size", "rgba"}} (the
+// thumbnail square, raw RGBA in URL-safe base64) answering {"id"}; a GET with `page` and
+// `per_page` answering a GalleryPage; and a GET of /{id} answering a GalleryEntry. The
+// token, if any, goes in a Bearer Authorization header. Requests answer through poll
+pub struct GalleryClient {
+    endpoint: String,
+    token: Option<String>,
+    pending: Vec<Pending>,
+}
+
+impl GalleryClient {
+    pub fn new(endpoint: &str, token: Option<String>) -> Result<Self, JsValue> {
+        if endpoint.is_empty() {
+            return Err(JsValue::from_str("No gallery endpoint"));
+        }
+        Ok(GalleryClient {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            token: token.filter(|token| !token.is_empty()),
+            pending: Vec::new(),
+        })
+    }
+
+    // `thumbnail` is size x size raw RGBA, as render_thumbnail gives it
+    pub fn publish(&mut self, fractal: &FrozenFractal, author: Option<&str>, thumbnail: &[u8]) -> Result<(), JsValue> {
+        let size = (thumbnail.len() as f64 / 4.0).sqrt() as usize;
+        if size == 0 || size * size * 4 != thumbnail.len() {
+            return Err(JsValue::from_str("The thumbnail isn't square RGBA"));
+        }
+        let body = serde_json::json!({
+            "fractal": fractal,
+            "author": author,
+            "thumbnail": { "size": size, "rgba": codec::base64_encode(thumbnail) },
+        }).to_string();
+        let request = sync::request(&self.endpoint, "POST", self.token.as_deref(), Some(&body), &[]);
+        self.pending.push(Pending::Publish(sync::send(request, |status, text| {
+            parse::<Published>(status, &text).map(|published| published.id)
+        })));
+        Ok(())
+    }
+
+    pub fn list(&mut self, page: u32) {
+        let url = format!("{}?page={}&per_page={}", self.endpoint, page, PAGE_SIZE);
+        let request = sync::request(&url, "GET", self.token.as_deref(), None, &[]);
+        self.pending.push(Pending::List(sync::send(request, |status, text| parse(status, &text))));
+    }
+
+    pub fn open(&mut self, id: &str) {
+        let id = js_sys::encode_uri_component(id).as_string().unwrap_or_default();
+        let url = format!("{}/{}", self.endpoint, id);
+        let request = sync::request(&url, "GET", self.token.as_deref(), None, &[]);
+        self.pending.push(Pending::Open(sync::send(request, |status, text| parse(status, &text))));
+    }
+
+    // The requests that have finished since the last poll, in the order they were made
+    pub fn poll(&mut self) -> Vec<GalleryEvent> {
+        let mut events = Vec::new();
+        self.pending.retain(|pending| {
+            let event = match pending {
+                Pending::Publish(reply) => finish(reply, "publish", |id| GalleryEvent::Published { id }),
+                Pending::List(reply) => finish(reply, "list", GalleryEvent::Page),
+                Pending::Open(reply) => finish(reply, "open", |entry| GalleryEvent::Opened(Box::new(entry))),
+            };
+            let finished = event.is_some();
+            events.extend(event);
+            !finished
+        });
+        events
+    }
+}
+
+// The event for a finished request; None while it's under way
+fn finish<T>(reply: &Reply<T>, request: &'static str, event: impl FnOnce(T) -> GalleryEvent) -> Option<GalleryEvent> {
+    let result = reply.borrow_mut().take()?;
+    Some(match result {
+        Ok(value) => event(value),
+        Err(e) => GalleryEvent::Failed {
+            request,
+            error: e.as_string().unwrap_or_else(|| format!("{:?}", e)),
+        },
+    })
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(status: u16, text: &str) -> Result<T, JsValue> {
+    if !(200..=299).contains(&status) {
+        return Err(JsValue::from_str(&format!("Gallery request failed ({}): {}", status, text)));
+    }
+    serde_json::from_str(text).map_err(|e| JsValue::from_str(&format!("Invalid gallery response: {}", e)))
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioBuffer, AudioContext, AudioNode, AudioScheduledSourceNode, BiquadFilterType, OscillatorType};
+use serde::{Serialize, Deserialize};
+use crate::rng::SeededRng;
+
+// Seconds of noise behind every noise layer; bursts start at a random point in it
+const NOISE_LENGTH: f32 = 1.0;
+
+// Fixed noise, so a gesture sounds the same every time it's made
+const NOISE_STREAM: u64 = 0x6E57_0001;
+
+// Envelopes end here rather than at zero, which an exponential ramp can't reach
+const SILENCE: f32 = 0.001;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerSource {
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+    // White noise through a band-pass filter; the frequencies move the filter's center
+    Noise,
+}
+
+// One voice of a gesture's sound: a tone or noise burst gliding from `start_frequency`
+// to `end_frequency` over `glide` seconds, under an attack/decay envelope that begins
+// `delay` seconds after the gesture
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Layer {
+    pub source: LayerSource,
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    pub glide: f32,
+    pub delay: f32,
+    pub attack: f32,
+    pub decay: f32,
+    pub level: f32,
+    pub resonance: f32, // Band-pass Q for noise layers; ignored by tones
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer {
+            source: LayerSource::Sine,
+            start_frequency: 440.0,
+            end_frequency: 440.0,
+            glide: 0.0,
+            delay: 0.0,
+            attack: 0.05,
+            decay: 0.25,
+            level: 0.2,
+            resonance: 1.0,
+        }
+    }
+}
+
+// Layers played together for one gesture type. Intensity (0..1) scales each layer's
+// level by intensity^loudness_curve and raises every frequency by up to `pitch_rise`
+// octaves, so a hard swipe is louder and brighter than a light one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct GestureSound {
+    pub layers: Vec<Layer>,
+    pub loudness_curve: f32,
+    pub pitch_rise: f32,
+}
+
+impl Default for GestureSound {
+    fn default() -> Self {
+        GestureSound { layers: vec![Layer::default()], loudness_curve: 1.0, pitch_rise: 0.0 }
+    }
+}
+
+impl GestureSound {
+    fn new(layers: Vec<Layer>, loudness_curve: f32, pitch_rise: f32) -> Self {
+        GestureSound { layers, loudness_curve, pitch_rise }
+    }
+}
+
+// The sound for each gesture type, with "default" covering types it has no entry for
+pub struct GestureSoundBank {
+    sounds: BTreeMap<String, GestureSound>,
+    noise: Option<AudioBuffer>, // Made on first use, in the context it plays in
+    noise_offset: SeededRng,
+}
+
+impl GestureSoundBank {
+    pub fn new() -> Self {
+        let tone = |source, start_frequency, end_frequency, glide, attack, decay, level| Layer {
+            source, start_frequency, end_frequency, glide, attack, decay, level, ..Layer::default()
+        };
+        let noise = |start_frequency, end_frequency, glide, attack, decay, level, resonance| Layer {
+            source: LayerSource::Noise, start_frequency, end_frequency, glide, attack, decay, level, resonance, ..Layer::default()
+        };
+        let chime = |frequency, delay| Layer { delay, ..tone(LayerSource::Sine, frequency, frequency, 0.0, 0.005, 0.6, 0.1) };
+
+        let mut sounds = BTreeMap::new();
+        // A rising whoosh of air with a faint tone riding it
+        sounds.insert("swipe".to_string(), GestureSound::new(vec![
+            noise(600.0, 2400.0, 0.25, 0.03, 0.3, 0.35, 2.0),
+            tone(LayerSource::Triangle, 330.0, 495.0, 0.2, 0.02, 0.25, 0.08),
+        ], 1.0, 0.5));
+        // A click, then a falling pluck
+        sounds.insert("pinch".to_string(), GestureSound::new(vec![
+            noise(4000.0, 4000.0, 0.0, 0.001, 0.03, 0.2, 0.7),
+            tone(LayerSource::Sine, 880.0, 440.0, 0.15, 0.005, 0.3, 0.2),
+        ], 0.8, -0.5));
+        // A slow, beating swell of two detuned triangles
+        sounds.insert("tilt".to_string(), GestureSound::new(vec![
+            tone(LayerSource::Triangle, 330.0, 330.0, 0.0, 0.12, 0.5, 0.12),
+            tone(LayerSource::Triangle, 333.0, 336.0, 0.5, 0.12, 0.5, 0.12),
+        ], 1.5, 0.25));
+        // A bright three-note chime arpeggiated upward
+        sounds.insert("smile".to_string(), GestureSound::new(vec![
+            chime(523.3, 0.0),
+            chime(659.3, 0.06),
+            chime(784.0, 0.12),
+        ], 0.7, 0.3));
+        // The original single beep
+        sounds.insert("default".to_string(), GestureSound::default());
+
+        GestureSoundBank {
+            sounds,
+            noise: None,
+            noise_offset: SeededRng::new(0, NOISE_STREAM + 1),
+        }
+    }
+
+    // A JSON object of gesture type to sound; the types given replace their current
+    // sounds and the rest are kept
+    pub fn configure(&mut self, sounds_json: &str) -> Result<(), JsValue> {
+        let sounds: BTreeMap<String, GestureSound> = serde_json::from_str(sounds_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid gesture sounds: {}", e)))?;
+        self.sounds.extend(sounds);
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.sounds).unwrap()
+    }
+
+    pub fn play(&mut self, context: &AudioContext, output: &AudioNode, gesture_type: &str, intensity: f32) -> Result<(), JsValue> {
+        let Some(sound) = self.sounds.get(gesture_type).or_else(|| self.sounds.get("default")) else {
+            return Ok(());
+        };
+        let sound = sound.clone();
+
+        let intensity = intensity.max(0.0).min(1.0);
+        let loudness = intensity.powf(sound.loudness_curve.max(0.0));
+        let pitch = 2f32.powf(sound.pitch_rise * intensity);
+        let now = context.current_time();
+
+        for layer in &sound.layers {
+            self.play_layer(context, output, layer, now + layer.delay.max(0.0) as f64, loudness, pitch)?;
+        }
+        Ok(())
+    }
+
+    fn play_layer(&mut self, context: &AudioContext, output: &AudioNode, layer: &Layer, start: f64, loudness: f32, pitch: f32) -> Result<(), JsValue> {
+        let nyquist = context.sample_rate() * 0.5;
+        let from = (layer.start_frequency * pitch).max(20.0).min(nyquist);
+        let to = (layer.end_frequency * pitch).max(20.0).min(nyquist);
+        let attack = layer.attack.max(0.001) as f64;
+        let end = start + attack + layer.decay.max(0.01) as f64;
+
+        let gain = context.create_gain()?;
+        let envelope = gain.gain();
+        envelope.set_value_at_time(0.0, start)?;
+        envelope.linear_ramp_to_value_at_time((layer.level * loudness).max(SILENCE), start + attack)?;
+        envelope.exponential_ramp_to_value_at_time(SILENCE, end)?;
+        gain.connect_with_audio_node(output)?;
+
+        // Both sources glide the same way, a tone its pitch and noise its band
+        let glide = |frequency: web_sys::AudioParam| -> Result<(), JsValue> {
+            frequency.set_value_at_time(from, start)?;
+            frequency.exponential_ramp_to_value_at_time(to, start + layer.glide.max(0.001) as f64)?;
+            Ok(())
+        };
+
+        if layer.source == LayerSource::Noise {
+            let source = context.create_buffer_source()?;
+            source.set_buffer(Some(&self.noise(context)?));
+            let filter = context.create_biquad_filter()?;
+            filter.set_type(BiquadFilterType::Bandpass);
+            filter.q().set_value(layer.resonance.max(0.0001).min(30.0));
+            glide(filter.frequency())?;
+
+            source.connect_with_audio_node(&filter)?;
+            filter.connect_with_audio_node(&gain)?;
+            let offset = self.noise_offset.range_f32(0.0, NOISE_LENGTH * 0.5) as f64;
+            source.start_with_when_and_grain_offset(start, offset)?;
+            AsRef::<AudioScheduledSourceNode>::as_ref(&source).stop_with_when(end)?;
+        } else {
+            let oscillator = context.create_oscillator()?;
+            oscillator.set_type(match layer.source {
+                LayerSource::Triangle => OscillatorType::Triangle,
+                LayerSource::Square => OscillatorType::Square,
+                LayerSource::Sawtooth => OscillatorType::Sawtooth,
+                _ => OscillatorType::Sine,
+            });
+            glide(oscillator.frequency())?;
+
+            oscillator.connect_with_audio_node(&gain)?;
+            oscillator.start_with_when(start)?;
+            oscillator.stop_with_when(end)?;
+        }
+        Ok(())
+    }
+
+    fn noise(&mut self, context: &AudioContext) -> Result<AudioBuffer, JsValue> {
+        if let Some(noise) = &self.noise {
+            return Ok(noise.clone());
+        }
+
+        let sample_rate = context.sample_rate();
+        let length = (sample_rate * NOISE_LENGTH) as u32;
+        let buffer = context.create_buffer(1, length, sample_rate)?;
+        let mut rng = SeededRng::new(0, NOISE_STREAM);
+        let samples: Vec<f32> = (0..length).map(|_| rng.range_f32(-1.0, 1.0)).collect();
+        buffer.copy_to_channel(&samples, 0)?;
+
+        self.noise = Some(buffer.clone());
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,429 @@
+use crate::fractals::ColoringMode;
+use crate::palette::Palette;
+use crate::registry;
+
+// A formula's distance estimator in GLSL: `source` defines `float <function>(vec3 pos, float time)`
+// plus any helpers it needs, reading its settings from u_fractal_params as laid out by
+// FractalParams::shader_params
+#[derive(Clone, Copy, Debug)]
+pub struct GlslSnippet {
+    pub function: &'static str,
+    pub source: &'static str,
+}
+
+// Line in FRAGMENT_SHADER replaced by the formulas and their dispatch
+const FORMULAS_MARKER: &str = "// {formulas}";
+
+// Line in FRAGMENT_SHADER and FRAGMENT_SHADER_2D replaced by HELPERS
+const HELPERS_MARKER: &str = "// {helpers}";
+
+// Fills a fragment shader template with HELPERS
+pub fn with_helpers(template: &str) -> String {
+    template.replacen(HELPERS_MARKER, HELPERS, 1)
+}
+
+// Fills the scene shader template with HELPERS, every registered type's to_glsl() snippet
+// and a formula_distance() that picks one by u_fractal_type. Types without a snippet
+// (plane and line fractals) have their own programs.
+pub fn fragment_shader(template: &str) -> String {
+    let mut formulas = String::new();
+    let mut dispatch = String::from("float formula_distance(vec3 pos, float time) {\n");
+
+    for entry in registry::all() {
+        let fractal = (entry.build)(&entry.example, 0.0, ColoringMode::Standard, Palette::from_seed(0));
+        let Some(snippet) = fractal.to_glsl() else { continue };
+
+        formulas.push_str(snippet.source);
+        formulas.push('\n');
+        dispatch.push_str(&format!(
+            "    if(u_fractal_type == {}) return {}(pos, time);\n", entry.shader_id, snippet.function
+        ));
+    }
+    dispatch.push_str("    return 1e10;\n}\n");
+
+    with_helpers(template).replacen(FORMULAS_MARKER, &(formulas + &dispatch), 1)
+}
+
+// The trap, iteration, palette and voice helpers of both the scene and the plane shader.
+// They read u_coloring_mode, u_audio_state, the u_palette_* arrays and u_equalize_*, which
+// the template declares ahead of the marker.
+pub const HELPERS: &str = r#"
+// Orbit trap minimum for the current evaluation, reset before every one
+float g_trap;
+
+// Smooth iteration count; mirrors smooth_iteration() in fractals.rs
+float smooth_iteration(float n, float r, float bailout, float power) {
+    return max(n + 1.0 - log(log(r) / log(bailout)) / log(power), 0.0);
+}
+
+void trap_orbit(vec3 z) {
+    if(u_coloring_mode == 1) {
+        g_trap = min(g_trap, length(z));
+    } else if(u_coloring_mode == 2) {
+        g_trap = min(g_trap, length(z.xz));
+    } else if(u_coloring_mode == 3) {
+        g_trap = min(g_trap, abs(z.y));
+    }
+}
+
+vec3 palette_sample(float t) {
+    t = fract(t);
+    vec3 color = u_palette_colors[0];
+    for(int i = 1; i < 8; i++) {
+        if(i >= u_palette_count) break;
+        float p0 = u_palette_positions[i - 1];
+        float p1 = u_palette_positions[i];
+        if(t >= p0) {
+            color = mix(u_palette_colors[i - 1], u_palette_colors[i], clamp((t - p0) / max(p1 - p0, 0.0001), 0.0, 1.0));
+        }
+    }
+    return color;
+}
+
+// Mirrors Palette::shade
+vec3 palette_shade(float t, float saturation, float value) {
+    vec3 color = palette_sample(t);
+    float luminance = dot(color, vec3(0.299, 0.587, 0.114));
+    return mix(vec3(luminance), color, clamp(saturation, 0.0, 1.0)) * value;
+}
+
+// Mirrors fractals::trap_color
+vec3 trap_color(float trap) {
+    trap = min(trap, 4.0);
+    float t = fract(0.6 + trap * 1.5);
+    return palette_shade(t, 0.75, clamp(1.0 - trap, 0.15, 1.0));
+}
+
+// Palette position of a smooth iteration count after histogram equalization
+float equalize(float iterations) {
+    return texture2D(u_equalize_lut, vec2(clamp(iterations / u_equalize_range, 0.0, 1.0), 0.5)).r;
+}
+
+// Level-weighted pitch (x) and total level (y) of the voices now sounding
+vec2 voice_mix() {
+    float level = 0.0;
+    float pitch = 0.0;
+    for(int i = 0; i < 8; i++) {
+        level += u_audio_state[i].y;
+        pitch += u_audio_state[i].x * u_audio_state[i].y;
+    }
+    return vec2(pitch / max(level, 0.001), min(level, 1.0));
+}
+"#;
+
+pub const MANDELBULB: &str = r#"
+// u_fractal_params[0] = (power, unused, iterations, unused)
+float mandelbulb(vec3 pos, float time) {
+    vec3 z = pos;
+    float dr = 1.0;
+    float r = 0.0;
+    float power = u_fractal_params[0].x + sin(time * 0.1) * 2.0;
+    int iterations = int(u_fractal_params[0].z);
+
+    for(int i = 0; i < 16; i++) {
+        if(i >= iterations) break;
+
+        r = length(z);
+        if(r > 2.0) {
+            g_iterations = smooth_iteration(float(i), r, 2.0, power);
+            break;
+        }
+
+        float theta = acos(z.z / r) + time * 0.05;
+        float phi = atan(z.y, z.x) + time * 0.03;
+        dr = pow(r, power - 1.0) * power * dr + 1.0;
+
+        float zr = pow(r, power);
+        z = zr * vec3(
+            sin(theta) * cos(phi),
+            sin(theta) * sin(phi),
+            cos(theta)
+        ) + pos;
+        trap_orbit(z);
+        g_iterations = float(i + 1);
+    }
+
+    return 0.5 * log(r) * r / dr;
+}
+"#;
+
+// The dual-float variant backs scene()'s deep-zoom path
+pub const JULIA4D: &str = r#"
+// u_fractal_params[0] = Julia constant, u_fractal_params[1].x = iterations
+vec4 julia_constant(float time) {
+    return u_fractal_params[0] + vec4(
+        sin(time * 0.1) * 0.3,
+        cos(time * 0.13) * 0.2,
+        sin(time * 0.07) * 0.25,
+        cos(time * 0.11) * 0.15
+    );
+}
+
+// Derivative of z -> z^2 + c in the quaternion-like algebra used by julia4d()
+vec4 julia_derivative(vec4 z, vec4 dz) {
+    return 2.0 * vec4(
+        z.x * dz.x - z.y * dz.y - z.z * dz.z - z.w * dz.w,
+        z.x * dz.y + z.y * dz.x,
+        z.x * dz.z + z.z * dz.x,
+        z.x * dz.w + z.w * dz.x
+    ) + vec4(1.0, 0.0, 0.0, 0.0);
+}
+
+float julia4d(vec3 pos, float time) {
+    vec4 z = vec4(pos, 0.0);
+    vec4 dz = vec4(1.0, 0.0, 0.0, 0.0);
+    vec4 c = julia_constant(time);
+    int iterations = int(u_fractal_params[1].x);
+
+    for(int i = 0; i < 16; i++) {
+        if(i >= iterations) break;
+        if(length(z) > 4.0) {
+            g_iterations = smooth_iteration(float(i), length(z), 4.0, 2.0);
+            break;
+        }
+
+        dz = julia_derivative(z, dz);
+        z = vec4(
+            z.x * z.x - z.y * z.y - z.z * z.z - z.w * z.w,
+            2.0 * z.x * z.y,
+            2.0 * z.x * z.z,
+            2.0 * z.x * z.w
+        ) + c;
+        trap_orbit(z.xyz);
+        g_iterations = float(i + 1);
+    }
+
+    float r = length(z);
+    return 0.5 * log(r) * r / length(dz);
+}
+
+// julia4d() with the orbit carried in dual-float so deep zooms keep resolving; the
+// derivative only scales the estimate, so it stays in plain floats
+float julia4d_df(vec2 px, vec2 py, vec2 pz, float time) {
+    vec2 zx = px;
+    vec2 zy = py;
+    vec2 zz = pz;
+    vec2 zw = vec2(0.0);
+    vec4 c = julia_constant(time);
+    vec2 cx = vec2(c.x, 0.0);
+    vec2 cy = vec2(c.y, 0.0);
+    vec2 cz = vec2(c.z, 0.0);
+    vec2 cw = vec2(c.w, 0.0);
+    vec4 dz = vec4(1.0, 0.0, 0.0, 0.0);
+    int iterations = int(u_fractal_params[1].x);
+    vec2 two = vec2(2.0, 0.0);
+
+    for(int i = 0; i < 16; i++) {
+        if(i >= iterations) break;
+
+        vec4 z = vec4(zx.x, zy.x, zz.x, zw.x);
+        if(length(z) > 4.0) {
+            g_iterations = smooth_iteration(float(i), length(z), 4.0, 2.0);
+            break;
+        }
+
+        dz = julia_derivative(z, dz);
+        vec2 x = df_add(df_add(df_mul(zx, zx), -df_mul(zy, zy)), df_add(-df_mul(zz, zz), -df_mul(zw, zw)));
+        vec2 two_x = df_mul(two, zx);
+        zy = df_add(df_mul(two_x, zy), cy);
+        zz = df_add(df_mul(two_x, zz), cz);
+        zw = df_add(df_mul(two_x, zw), cw);
+        zx = df_add(x, cx);
+        trap_orbit(vec3(zx.x, zy.x, zz.x));
+        g_iterations = float(i + 1);
+    }
+
+    float r = length(vec4(zx.x, zy.x, zz.x, zw.x));
+    return 0.5 * log(r) * r / length(dz);
+}
+"#;
+
+pub const KALEIDO_IFS: &str = r#"
+// u_fractal_params[0] = (scale, unused, fold count, unused)
+float kaleidoIFS(vec3 pos, float time) {
+    vec3 p = pos;
+    float scale = 1.0;
+    int folds = int(u_fractal_params[0].z);
+
+    for(int i = 0; i < 16; i++) {
+        if(i >= folds) break;
+
+        float angle = time * 0.1 + float(i) * 0.5;
+        vec3 n = normalize(vec3(cos(angle), sin(angle), sin(angle * 1.3)));
+
+        float d = dot(p, n);
+        if(d < 0.0) p -= 2.0 * d * n;
+
+        p = clamp(p, -1.0, 1.0) * 2.0 - p;
+
+        float r2 = dot(p, p);
+        if(r2 < 0.25) {
+            p *= 4.0;
+            scale *= 4.0;
+        } else if(r2 < 1.0) {
+            p /= r2;
+            scale /= r2;
+        }
+
+        float s = u_fractal_params[0].x + sin(time * 0.05 + float(i) * 0.1) * 0.5;
+        p = p * s + vec3(sin(time * 0.07) * 0.1, cos(time * 0.11) * 0.1, 0.0);
+        scale *= s;
+        trap_orbit(p);
+        g_iterations = float(i + 1);
+    }
+
+    return (length(p) - 0.5) / abs(scale);
+}
+"#;
+
+pub const MANDELBOX: &str = r#"
+// u_fractal_params[0] = (scale, min_radius, iterations, unused)
+float mandelbox(vec3 pos, float time) {
+    vec3 z = pos;
+    float dr = 1.0;
+    float scale = u_fractal_params[0].x + sin(time * 0.05) * 0.1;
+    float min_r2 = u_fractal_params[0].y * u_fractal_params[0].y;
+    int iterations = int(u_fractal_params[0].z);
+
+    for(int i = 0; i < 16; i++) {
+        if(i >= iterations) break;
+
+        z = clamp(z, -1.0, 1.0) * 2.0 - z;
+
+        float r2 = dot(z, z);
+        if(r2 < min_r2) {
+            z /= min_r2;
+            dr /= min_r2;
+        } else if(r2 < 1.0) {
+            z /= r2;
+            dr /= r2;
+        }
+
+        z = z * scale + pos;
+        dr = dr * abs(scale) + 1.0;
+        trap_orbit(z);
+        g_iterations = float(i + 1);
+    }
+
+    return length(z) / abs(dr);
+}
+"#;
+
+pub const MENGER_SPONGE: &str = r#"
+// u_fractal_params[0] = (hole scale, unused, fold count, unused)
+float menger(vec3 pos, float time) {
+    vec3 z = pos;
+    float scale = u_fractal_params[0].x;
+    float shift = scale - 1.0;
+    int folds = int(u_fractal_params[0].z);
+    float shrink = 1.0;
+
+    for(int i = 0; i < 8; i++) {
+        if(i >= folds) break;
+
+        z = abs(z);
+        if(z.x < z.y) z.xy = z.yx;
+        if(z.x < z.z) z.xz = z.zx;
+        if(z.y < z.z) z.yz = z.zy;
+
+        z = z * scale - vec3(shift);
+        if(z.z < -0.5 * shift) z.z += shift;
+
+        shrink /= scale;
+        trap_orbit(z);
+        g_iterations = float(i + 1);
+    }
+
+    return length(max(abs(z) - vec3(1.0), 0.0)) * shrink;
+}
+"#;
+
+pub const APOLLONIAN: &str = r#"
+// u_fractal_params[0] = (inversion strength, unused, iterations, unused)
+float apollonian(vec3 pos, float time) {
+    vec3 p = pos;
+    float scale = 1.0;
+    int iterations = int(u_fractal_params[0].z);
+
+    for(int i = 0; i < 12; i++) {
+        if(i >= iterations) break;
+
+        p = -1.0 + 2.0 * fract(0.5 * p + 0.5);
+        float k = u_fractal_params[0].x / max(dot(p, p), 0.000001);
+        p *= k;
+        scale *= k;
+        trap_orbit(p);
+        g_iterations = float(i + 1);
+    }
+
+    return 0.25 * abs(p.y) / scale;
+}
+"#;
+
+pub const HYBRID: &str = r#"
+// Hybrid orbits (see HybridFractal): u_fractal_params[n] = (kind, p1, p2, steps) per formula,
+// kind 0 = Mandelbulb (p1 = power), kind 1 = Mandelbox (p1 = scale, p2 = min radius)
+const int HYBRID_ITERATIONS = 12;
+
+void bulb_step(inout vec3 z, inout float dr, vec3 c, float power) {
+    float r = max(length(z), 0.000001);
+    float theta = acos(z.z / r);
+    float phi = atan(z.y, z.x);
+    dr = pow(r, power - 1.0) * power * dr + 1.0;
+    z = pow(r, power) * vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta)) + c;
+}
+
+void box_step(inout vec3 z, inout float dr, vec3 c, float scale, float min_radius) {
+    z = clamp(z, -1.0, 1.0) * 2.0 - z;
+
+    float r2 = dot(z, z);
+    float min_r2 = min_radius * min_radius;
+    if(r2 < min_r2) {
+        z /= min_r2;
+        dr /= min_r2;
+    } else if(r2 < 1.0) {
+        z /= r2;
+        dr /= r2;
+    }
+
+    z = z * scale + c;
+    dr = dr * abs(scale) + 1.0;
+}
+
+void hybrid_step(vec4 formula, inout vec3 z, inout float dr, vec3 c) {
+    if(formula.x < 0.5) {
+        bulb_step(z, dr, c, formula.y);
+    } else {
+        box_step(z, dr, c, formula.y, formula.z);
+    }
+}
+
+float hybrid(vec3 pos, float time) {
+    vec3 z = pos;
+    float dr = 1.0;
+    vec4 first = u_fractal_params[0];
+    vec4 second = u_fractal_params[1];
+    float first_steps = max(first.w, 1.0);
+    float cycle = first_steps + max(second.w, 1.0);
+
+    for(int i = 0; i < HYBRID_ITERATIONS; i++) {
+        if(dot(z, z) > 16.0) {
+            // The step that just ran decides how fast the orbit was growing
+            vec4 last = mod(max(float(i) - 1.0, 0.0), cycle) < first_steps ? first : second;
+            float growth = last.x < 0.5 ? last.y : abs(last.y);
+            g_iterations = smooth_iteration(float(i), length(z), 4.0, growth);
+            break;
+        }
+
+        if(mod(float(i), cycle) < first_steps) {
+            hybrid_step(first, z, dr, pos);
+        } else {
+            hybrid_step(second, z, dr, pos);
+        }
+        trap_orbit(z);
+        g_iterations = float(i + 1);
+    }
+
+    return 0.5 * length(z) / abs(dr);
+}
+"#;
@@ -0,0 +1,121 @@
+use serde::{Serialize, Deserialize};
+use crate::rng::SeededRng;
+
+// Stream of the seed's numbers the windows are drawn from, apart from the fractal's own
+const GOLDEN_STREAM: u64 = 0x601D_0001;
+
+// Each gesture in a window counts this many times toward the day's complexity bonus
+pub const GOLDEN_MULTIPLIER: u32 = 3;
+
+const WINDOW_MINUTES: u32 = 60;
+
+// Windows fall between 7am and 9pm, by the day boundary's clock
+const EARLIEST_START: u32 = 7 * 60;
+const LATEST_END: u32 = 21 * 60;
+
+// Minutes the aura takes to swell as a window opens and to fade as it closes
+const AURA_FADE: f64 = 5.0;
+
+// A stretch of the day, in minutes after it began, when gestures count for more
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct GoldenWindow {
+    pub start: u32,
+    pub minutes: u32,
+}
+
+impl GoldenWindow {
+    pub fn end(&self) -> u32 {
+        self.start + self.minutes
+    }
+
+    fn contains(&self, minute: f64) -> bool {
+        minute >= self.start as f64 && minute < self.end() as f64
+    }
+}
+
+// One or two a day, drawn from the seed so everyone sharing a seed shares them; two
+// split the day, one in the morning and one in the afternoon, so they never overlap
+pub fn windows(seed: u32) -> Vec<GoldenWindow> {
+    let mut rng = SeededRng::new(seed as u64, GOLDEN_STREAM);
+    let count = 1 + rng.below(2);
+    let part = (LATEST_END - EARLIEST_START) / count;
+    (0..count)
+        .map(|i| GoldenWindow {
+            start: EARLIEST_START + i * part + rng.below(part - WINDOW_MINUTES + 1),
+            minutes: WINDOW_MINUTES,
+        })
+        .collect()
+}
+
+// The window open at `minute` of the day, if any
+pub fn active(windows: &[GoldenWindow], minute: f64) -> Option<GoldenWindow> {
+    windows.iter().copied().find(|window| window.contains(minute))
+}
+
+// How strongly the aura shows at `minute`, 0..1: full inside a window, easing in and out
+// at its edges
+pub fn aura(windows: &[GoldenWindow], minute: f64) -> f32 {
+    let Some(window) = active(windows, minute) else {
+        return 0.0;
+    };
+    let since_open = minute - window.start as f64;
+    let until_close = window.end() as f64 - minute;
+    let edge = (since_open.min(until_close) / AURA_FADE).min(1.0);
+    (edge * edge * (3.0 - 2.0 * edge)) as f32
+}
+
+// Golden-hour counts, kept with the rest of the user's state
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GoldenStats {
+    pub windows_hit: u32,  // Windows with a gesture in them, all time
+    pub interactions: u32, // Gestures made in a window, all time
+    pub day: String,       // The day the next two are for, YYYY-MM-DD
+    pub day_interactions: u32,
+    pub day_windows: Vec<u32>, // Start of each of the day's windows hit so far
+}
+
+impl GoldenStats {
+    // A gesture on `day` in the window starting at `window_start`
+    pub fn record(&mut self, day: &str, window_start: u32) {
+        if self.day != day {
+            self.day = day.to_string();
+            self.day_interactions = 0;
+            self.day_windows.clear();
+        }
+        if !self.day_windows.contains(&window_start) {
+            self.day_windows.push(window_start);
+            self.windows_hit += 1;
+        }
+        self.day_interactions += 1;
+        self.interactions += 1;
+    }
+
+    // Gestures in a window on `day`
+    pub fn interactions_on(&self, day: &str) -> u32 {
+        if self.day == day {
+            self.day_interactions
+        } else {
+            0
+        }
+    }
+
+    // Counts saved elsewhere (another tab, or before a migration finished) join these,
+    // the larger of each, as Achievements::merge does
+    pub fn merge(&mut self, other: GoldenStats) {
+        self.windows_hit = self.windows_hit.max(other.windows_hit);
+        self.interactions = self.interactions.max(other.interactions);
+        if other.day > self.day {
+            self.day = other.day;
+            self.day_interactions = other.day_interactions;
+            self.day_windows = other.day_windows;
+        } else if other.day == self.day {
+            self.day_interactions = self.day_interactions.max(other.day_interactions);
+            for start in other.day_windows {
+                if !self.day_windows.contains(&start) {
+                    self.day_windows.push(start);
+                }
+            }
+        }
+    }
+}
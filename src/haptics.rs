@@ -0,0 +1,93 @@
+use wasm_bindgen::prelude::*;
+use web_sys::Navigator;
+use serde::{Serialize, Deserialize};
+
+// Gap between the pulses of a multi-pulse pattern, in ms
+const PULSE_GAP: f32 = 40.0;
+
+// How a gesture's intensity (0..1) becomes a pulse length: min_pulse ms for the lightest
+// touch up to max_pulse ms, eased by intensity^curve. Vibration motors have no amplitude
+// control on the web, so length is the only way to feel stronger
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct HapticMapping {
+    pub min_pulse: f32,
+    pub max_pulse: f32,
+    pub curve: f32,
+}
+
+impl Default for HapticMapping {
+    fn default() -> Self {
+        HapticMapping { min_pulse: 8.0, max_pulse: 60.0, curve: 1.5 }
+    }
+}
+
+// Vibration patterns (navigator.vibrate) to go with the audio feedback. Browsers without
+// the Vibration API, iOS Safari among them, get nothing rather than an error
+pub struct Haptics {
+    navigator: Option<Navigator>,
+    pub mapping: HapticMapping,
+}
+
+impl Haptics {
+    pub fn new() -> Self {
+        let navigator = web_sys::window()
+            .map(|window| window.navigator())
+            .filter(|navigator| js_sys::Reflect::has(navigator, &JsValue::from_str("vibrate")).unwrap_or(false));
+        Haptics { navigator, mapping: HapticMapping::default() }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.navigator.is_some()
+    }
+
+    pub fn set_mapping(&mut self, mapping_json: &str) -> Result<(), JsValue> {
+        self.mapping = serde_json::from_str(mapping_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid haptic mapping: {}", e)))?;
+        Ok(())
+    }
+
+    fn pulse_length(&self, intensity: f32) -> f32 {
+        let eased = intensity.max(0.0).min(1.0).powf(self.mapping.curve.max(0.0));
+        self.mapping.min_pulse + (self.mapping.max_pulse - self.mapping.min_pulse) * eased
+    }
+
+    // Each gesture has its own rhythm, echoing its sound in the GestureSoundBank: a swipe
+    // is one sweep, a pinch a double click, a tilt a soft flutter and a smile a skip
+    pub fn gesture(&self, gesture_type: &str, intensity: f32) {
+        let pulse = self.pulse_length(intensity);
+        let pattern = match gesture_type {
+            "swipe" => vec![pulse],
+            "pinch" => vec![pulse * 0.5, PULSE_GAP, pulse * 0.5],
+            "tilt" => vec![pulse * 0.3, PULSE_GAP, pulse * 0.3, PULSE_GAP, pulse * 0.3],
+            "smile" => vec![pulse, PULSE_GAP * 1.5, pulse * 0.5],
+            _ => vec![pulse],
+        };
+        self.vibrate(&pattern);
+    }
+
+    // A battle's outcome: a rising triple for a win, harder the more the two fractals
+    // resonated, and a single dull knock for a loss
+    pub fn battle(&self, won: bool, resonance: f32) {
+        if won {
+            let pulse = self.pulse_length(resonance);
+            self.vibrate(&[pulse * 0.5, PULSE_GAP, pulse * 0.75, PULSE_GAP, pulse]);
+        } else {
+            self.vibrate(&[self.pulse_length(0.3)]);
+        }
+    }
+
+    // A short tick, as a fractal is frozen into the collection
+    pub fn tick(&self) {
+        self.vibrate(&[self.mapping.min_pulse]);
+    }
+
+    // Alternating on and off times in ms
+    fn vibrate(&self, pattern: &[f32]) {
+        let Some(navigator) = &self.navigator else {
+            return;
+        };
+        let pattern: js_sys::Array = pattern.iter().map(|&ms| JsValue::from_f64(ms.max(0.0).round() as f64)).collect();
+        navigator.vibrate_with_pattern(&pattern);
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Serialize;
+use crate::rng::SeededRng;
+
+// Chords in a day's progression; it starts over after the last
+const PROGRESSION_LENGTH: usize = 8;
+
+// Gestures it takes to move on to the next chord
+const INTERACTIONS_PER_CHORD: u32 = 5;
+
+const HARMONY_STREAM: u64 = 0x4A12_0001;
+
+// Pitch class 0; the same A the voices' pentatonic scale is built on
+const REFERENCE_FREQUENCY: f32 = 110.0;
+
+const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+const NOTE_NAMES: [&str; 12] = ["A", "A#", "B", "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#"];
+const DEGREE_NAMES: [&str; 7] = ["I", "ii", "iii", "IV", "V", "vi", "vii°"];
+
+// A triad on one degree of the key, as pitch classes relative to A
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chord {
+    pub degree: usize,
+    pub tones: [i32; 3],
+}
+
+impl Chord {
+    // Stacked thirds within the major scale, so each degree gets its own quality
+    fn on(key: i32, degree: usize) -> Self {
+        let tone = |step: usize| (key + MAJOR_SCALE[(degree + step) % 7]) % 12;
+        Chord { degree, tones: [tone(0), tone(2), tone(4)] }
+    }
+
+    // The nearest chord tone to `frequency`, in whichever octave is closest
+    pub fn constrain(&self, frequency: f32) -> f32 {
+        if frequency.is_nan() || frequency <= 0.0 {
+            return frequency;
+        }
+
+        let semitones = 12.0 * (frequency / REFERENCE_FREQUENCY).log2();
+        let octave = (semitones / 12.0).floor() as i32;
+        let nearest = (octave - 1..=octave + 1)
+            .flat_map(|o| self.tones.iter().map(move |&tone| (o * 12 + tone) as f32))
+            .min_by(|a, b| (a - semitones).abs().partial_cmp(&(b - semitones).abs()).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or(semitones);
+        REFERENCE_FREQUENCY * 2f32.powf(nearest / 12.0)
+    }
+
+    // The chord's tones over `octaves` octaves from `low` Hz up, lowest first
+    pub fn notes(&self, low: f32, octaves: u32) -> Vec<f32> {
+        let start = (12.0 * (low / REFERENCE_FREQUENCY).log2()).ceil() as i32;
+        (start..start + 12 * octaves as i32)
+            .filter(|semitone| self.tones.contains(&semitone.rem_euclid(12)))
+            .map(|semitone| REFERENCE_FREQUENCY * 2f32.powf(semitone as f32 / 12.0))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct HarmonyInfo {
+    pub key: &'static str,
+    pub progression: Vec<&'static str>,
+    pub current: usize,
+}
+
+// The day's chords: a key and a walk around the circle of fifths, mostly falling by
+// fifths toward the tonic as cadences do, now and then rising a fifth or stepping down a
+// third. Each chord holds until enough gestures have been made to move on
+pub struct Progression {
+    pub key: i32,
+    pub chords: Vec<Chord>,
+}
+
+impl Progression {
+    pub fn from_seed(seed: u32) -> Self {
+        let mut rng = SeededRng::new(seed as u64, HARMONY_STREAM);
+        let key = rng.below(12) as i32;
+
+        let mut degree = 0;
+        let mut chords = Vec::with_capacity(PROGRESSION_LENGTH);
+        for _ in 0..PROGRESSION_LENGTH {
+            chords.push(Chord::on(key, degree));
+            degree = match rng.below(10) {
+                0..=5 => (degree + 3) % 7,
+                6..=7 => (degree + 4) % 7,
+                _ => (degree + 5) % 7,
+            };
+            // The diminished chord stands in for the dominant it belongs to
+            if degree == 6 {
+                degree = 4;
+            }
+        }
+
+        Progression { key, chords }
+    }
+
+    pub fn index_at(&self, interaction_count: u32) -> usize {
+        (interaction_count / INTERACTIONS_PER_CHORD) as usize % self.chords.len()
+    }
+
+    pub fn chord_at(&self, interaction_count: u32) -> &Chord {
+        &self.chords[self.index_at(interaction_count)]
+    }
+
+    pub fn info(&self, interaction_count: u32) -> HarmonyInfo {
+        HarmonyInfo {
+            key: NOTE_NAMES[self.key as usize],
+            progression: self.chords.iter().map(|chord| DEGREE_NAMES[chord.degree]).collect(),
+            current: self.index_at(interaction_count),
+        }
+    }
+}
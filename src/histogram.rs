@@ -0,0 +1,134 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram, WebGlTexture};
+use nalgebra::{Vector3, Vector4};
+use crate::fractals::*;
+
+// Entries in the equalization lookup texture (u_equalize_lut)
+pub const LUT_SIZE: usize = 256;
+
+// Rays (or plane points) per side sampled for the histogram
+const SAMPLE_GRID: u32 = 32;
+
+// Texture unit for the lookup; unit 0 belongs to the depth-of-field pass
+const LUT_TEXTURE_UNIT: u32 = 1;
+
+// Distribution of smooth iteration counts over what the camera sees
+pub struct EscapeHistogram {
+    pub bins: Vec<u32>,
+    pub range: f32, // Smooth iteration count mapped to the last bin
+}
+
+impl EscapeHistogram {
+    pub fn from_samples(samples: &[f32]) -> Self {
+        let range = samples.iter().copied().fold(1.0f32, f32::max);
+        let mut bins = vec![0u32; LUT_SIZE];
+        for sample in samples {
+            let bin = (sample / range * (LUT_SIZE - 1) as f32) as usize;
+            bins[bin.min(LUT_SIZE - 1)] += 1;
+        }
+        EscapeHistogram { bins, range }
+    }
+
+    // Smooth iteration counts at the surface points of a coarse CPU raymarch, or at the
+    // escaping points of the view for plane fractals
+    pub fn sample(fractal: &dyn FractalGenerator) -> Self {
+        let planar = fractal.get_params().is_planar();
+        let mut samples = Vec::with_capacity((SAMPLE_GRID * SAMPLE_GRID) as usize);
+
+        for y in 0..SAMPLE_GRID {
+            for x in 0..SAMPLE_GRID {
+                let u = (x as f32 + 0.5) / SAMPLE_GRID as f32 - 0.5;
+                let v = (y as f32 + 0.5) / SAMPLE_GRID as f32 - 0.5;
+
+                let point = if planar {
+                    Some(Vector4::new(u * 2.5, v * 2.5, 0.0, 0.0))
+                } else {
+                    let origin = Vector3::new(u * 2.5, v * 2.5, -4.0);
+                    let dir = Vector3::new(u * 0.6, v * 0.6, 1.0).normalize();
+                    march_cpu(fractal, &origin, &dir).map(|(pos, _, _)| Vector4::new(pos.x, pos.y, pos.z, 0.0))
+                };
+
+                if let Some(point) = point {
+                    let stats = fractal.orbit_stats(&point, i32::MAX);
+                    if !planar || stats.escaped {
+                        samples.push(stats.smooth_iteration);
+                    }
+                }
+            }
+        }
+
+        Self::from_samples(&samples)
+    }
+
+    // Cumulative distribution scaled to bytes: equally common counts get equally wide
+    // slices of the palette, so a narrow band of counts no longer collapses to one hue
+    pub fn lut(&self) -> [u8; LUT_SIZE] {
+        let total: u32 = self.bins.iter().sum();
+        let mut lut = [0u8; LUT_SIZE];
+        if total == 0 {
+            for (i, entry) in lut.iter_mut().enumerate() {
+                *entry = (i * 255 / (LUT_SIZE - 1)) as u8;
+            }
+            return lut;
+        }
+
+        let mut running = 0u32;
+        for (entry, count) in lut.iter_mut().zip(&self.bins) {
+            running += count;
+            *entry = (running as f32 / total as f32 * 255.0).round() as u8;
+        }
+        lut
+    }
+}
+
+// GPU side of ColoringMode::Equalized: the lookup texture for the current fractal, rebuilt
+// only when its settings change
+pub struct Equalizer {
+    texture: WebGlTexture,
+    range: f32,
+    cached: Option<FractalParams>,
+}
+
+impl Equalizer {
+    pub fn new(gl: &GL) -> Result<Self, JsValue> {
+        let texture = gl.create_texture().ok_or("Failed to create texture")?;
+        Ok(Equalizer { texture, range: 1.0, cached: None })
+    }
+
+    pub fn prepare(&mut self, gl: &GL, fractal: &dyn FractalGenerator) -> Result<(), JsValue> {
+        let params = fractal.get_params();
+        if self.cached == Some(params) {
+            return Ok(());
+        }
+
+        let histogram = EscapeHistogram::sample(fractal);
+        gl.active_texture(GL::TEXTURE0 + LUT_TEXTURE_UNIT);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D, 0, GL::LUMINANCE as i32, LUT_SIZE as i32, 1, 0,
+            GL::LUMINANCE, GL::UNSIGNED_BYTE, Some(&histogram.lut()),
+        )?;
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+        gl.active_texture(GL::TEXTURE0);
+
+        self.range = histogram.range;
+        self.cached = Some(params);
+        Ok(())
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram) {
+        gl.active_texture(GL::TEXTURE0 + LUT_TEXTURE_UNIT);
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.texture));
+        gl.active_texture(GL::TEXTURE0);
+
+        if let Some(loc) = gl.get_uniform_location(program, "u_equalize_lut") {
+            gl.uniform1i(Some(&loc), LUT_TEXTURE_UNIT as i32);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_equalize_range") {
+            gl.uniform1f(Some(&loc), self.range);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Serialize, Deserialize};
+
+// Where every player starts, and where an opponent who doesn't say is assumed to be
+pub const DEFAULT_RATING: f32 = 1200.0;
+
+// Most a single battle can move the rating
+const K_FACTOR: f32 = 32.0;
+
+// A rating gap of this much makes the higher rated ten times as likely to win
+const RATING_SCALE: f32 = 400.0;
+
+// Battles kept in the history, most recent last
+const MAX_HISTORY: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Battle {
+    pub opponent_id: String,
+    pub opponent_seed: u32,
+    pub opponent_rating: f32,
+    pub score_self: f32,
+    pub score_opponent: f32,
+    pub resonance: f32,
+    pub won: bool,
+    pub rating_change: f32,
+    pub timestamp: u64,
+}
+
+// The user's standing in battles: an Elo rating that rises with wins over stronger
+// fractals and falls with losses to weaker ones, and the battles that got it there
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Ladder {
+    pub rating: f32,
+    pub peak_rating: f32,
+    pub wins: u32,
+    pub losses: u32,
+    pub history: Vec<Battle>,
+}
+
+impl Default for Ladder {
+    fn default() -> Self {
+        Ladder { rating: DEFAULT_RATING, peak_rating: DEFAULT_RATING, wins: 0, losses: 0, history: Vec::new() }
+    }
+}
+
+impl Ladder {
+    // Chance of beating an opponent rated `opponent_rating`, 0..1
+    pub fn expected_score(&self, opponent_rating: f32) -> f32 {
+        1.0 / (1.0 + 10f32.powf((opponent_rating - self.rating) / RATING_SCALE))
+    }
+
+    // Rates a battle and adds it to the history; fill in everything but rating_change,
+    // which comes back set
+    pub fn record(&mut self, mut battle: Battle) -> Battle {
+        let actual = if battle.won { 1.0 } else { 0.0 };
+        battle.rating_change = K_FACTOR * (actual - self.expected_score(battle.opponent_rating));
+        self.rating += battle.rating_change;
+        self.peak_rating = self.peak_rating.max(self.rating);
+        if battle.won {
+            self.wins += 1;
+        } else {
+            self.losses += 1;
+        }
+
+        self.history.push(battle.clone());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        battle
+    }
+
+    // A ladder saved elsewhere (another tab, or before a migration finished) replaces
+    // this one if it has seen more battles; ratings can't be added up
+    pub fn merge(&mut self, other: Ladder) {
+        if other.wins + other.losses > self.wins + self.losses {
+            *self = other;
+        }
+    }
+}
@@ -1,347 +1,3239 @@
 mod fractals;
 mod audio;
+mod audio_fx;
+mod audio_input;
+mod audio_lifecycle;
+mod sequencer;
+mod clock;
+mod doppler;
+mod synth;
+mod master_bus;
+mod gesture_sound;
+mod ambience;
+mod arpeggiator;
+mod modulation;
+mod sonify;
+mod haptics;
+mod midi_out;
+mod harmony;
+mod voice_allocator;
 mod user;
+mod achievements;
+mod ladder;
+mod replay;
+mod golden;
+mod wake;
+mod analytics;
+mod day;
+mod preferences;
+mod pose;
+mod store;
 mod network;
-
+mod codec;
+mod wire;
+mod contacts;
+mod crypto;
+mod room;
+mod echo;
+mod time_sync;
+mod gallery;
+mod resonance;
+mod battle;
+mod sync;
+mod postfx;
+mod environment;
+mod palette;
+mod settings;
+mod zoom;
+mod slice;
+mod frame_loop;
+mod stats;
+#[cfg(test)]
+mod conformance;
+mod effort;
+#[cfg(test)]
+mod schema;
+mod lsystem;
+mod complexity;
+mod registry;
+mod rng;
+mod evolution;
+mod lineage;
+mod presets;
+mod mesh;
+mod glsl;
+mod weather;
+mod quality;
+mod noise;
+mod histogram;
+mod math;
+mod seed_quality;
+
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use web_sys::{WebGlRenderingContext as GL, WebGlProgram, WebGlShader};
 use nalgebra::Vector4;
 use fractals::*;
-use audio::AudioEngine;
-use user::UserState;
+use audio::{AudioEngine, AUDIO_STATE_VOICES, CROSSFADE_TIME, DEFAULT_MASTER_VOLUME};
+use user::{UserState, FrozenFractal, FrozenFilter, CurationChange, BattleResult};
+use replay::Replay;
+use sync::{CloudSync, RestBackend};
+use gallery::{GalleryClient, GalleryEvent};
+use resonance::{Resonance, ResonanceSettings};
+use battle::{Challenge, ChallengeState};
+use network::{FractalMessage, LinkContent, NetworkManager, SharedLink};
+use pose::Pose;
+use day::DayBoundary;
+use postfx::{DepthOfField, RenderTarget};
+use environment::Environment;
+use palette::Palette;
+use settings::{RenderSettings, RenderMode};
+use zoom::ZoomState;
+use slice::SlicePlane;
+use frame_loop::FrameLoop;
+use stats::{RenderStats, StatsOverlay};
+use lsystem::LineRenderer;
+use evolution::EvolutionCurve;
+use weather::{Modifier, WeatherSource};
+use quality::QualityController;
+use histogram::Equalizer;
+use registry::FractalType;
+use audio_input::{AudioInput, AUDIO_BANDS};
+use midi_out::MidiOut;
+use haptics::Haptics;
+use harmony::Progression;
+use ambience::AmbientScene;
+use sonify::ViewScan;
+use sequencer::Pattern;
+
+// Everything besides the fractal itself that one draw of the scene shader depends on
+struct SceneView {
+    seed: u32,
+    time: f32,
+    transform: nalgebra::Matrix4<f32>,
+    width: i32,
+    height: i32,
+    // Pixel offset of the drawn region within width x height, then its size; everything
+    // but tiled exports draws the whole frame
+    tile: [f32; 4],
+    audio_bands: [f32; AUDIO_BANDS], // Microphone band energies; silent outside live rendering
+    audio_state: [f32; AUDIO_STATE_VOICES * 2], // Voices as pitch, level pairs; likewise silent
+    pulse: f32, // The rhythm's beat, 0..1; likewise silent
+    golden: f32, // The golden-hour aura, 0..1; only while one is open in live rendering
+    resonance: f32, // The resonance mode, 0..1; likewise only live
+    write_depth: bool,
+    zoom: ZoomState,
+}
+
+// Gallery thumbnails are capped so a bad request can't allocate a huge framebuffer
+const MAX_THUMBNAIL_SIZE: u32 = 512;
+
+// Side of the thumbnail publish_to_gallery renders when the host doesn't give one
+const GALLERY_THUMBNAIL_SIZE: u32 = 256;
+
+// Seconds a share link's fractal takes to morph in from whatever was showing
+const LINK_MORPH_DURATION: f32 = 3.0;
+
+// Share of the full iteration budget thumbnails render with; fine detail is lost at
+// their size anyway
+const THUMBNAIL_DETAIL: f32 = 0.75;
+
+// High-resolution exports: 8K on a side at most, drawn in tiles small enough that one
+// draw never trips the GPU watchdog even at full detail
+const MAX_EXPORT_SIZE: u32 = 8192;
+const MAX_EXPORT_SAMPLES: u32 = 64;
+const EXPORT_TILE_SIZE: i32 = 256;
+
+// Samples per axis for export_mesh; the top end is already ~7M distance estimates
+const MIN_MESH_RESOLUTION: u32 = 16;
+const MAX_MESH_RESOLUTION: u32 = 192;
+
+// Enough drift that children differ visibly from both parents
+const BREED_MUTATION_RATE: f32 = 0.1;
+
+// Length of sonify_view's sweep, top of the view to the bottom
+const SONIFY_SECONDS: f32 = 4.0;
+
+// Gesture strength under reduced motion, a quarter of the usual doubling
+const REDUCED_MOTION_GAIN: f32 = 0.5;
+
+// Sub-pixel offset of supersample `index`, within half a pixel of the center. The R2
+// sequence spreads any number of samples evenly, and sample 0 is the center itself
+fn sample_jitter(index: u32) -> (f32, f32) {
+    let n = index as f32;
+    ((0.5 + n * 0.754_877_7).fract() - 0.5, (0.5 + n * 0.569_840_3).fract() - 0.5)
+}
+
+// Headless render of FractalParams JSON through the CPU raymarcher; slow, but needs no
+// canvas or WebGL context. Returns width x height RGBA bytes, top row first.
+#[wasm_bindgen]
+pub fn render_fractal_params_cpu(params_json: &str, width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+    let params = FractalParams::from_json(params_json)?;
+    Ok(render_cpu(&params, width.max(1).min(MAX_THUMBNAIL_SIZE), height.max(1).min(MAX_THUMBNAIL_SIZE)))
+}
+
+// Mono f32 samples at 44.1kHz of a frozen fractal's drone over its first `seconds`
+// (at most 60), identical on every device built with the `deterministic` feature
+#[wasm_bindgen]
+pub fn render_frozen_audio(frozen_json: &str, seconds: f32) -> Result<Vec<f32>, JsValue> {
+    AudioEngine::render_offline(frozen_json, seconds)
+}
 
+// What the host holds. The app lives in a RefCell the frame loop shares, so a host
+// callback that calls back in mid-frame gets an exception rather than a second &mut, and
+// one that frees it leaves the frame to finish first
 #[wasm_bindgen]
 pub struct Resonant {
-    gl: GL,
-    program: WebGlProgram,
-    user_state: UserState,
-    audio_engine: AudioEngine,
-    time: f32,
-    fractal_type: String,
-    last_wake_time: f64,
+    app: Rc<RefCell<App>>,
 }
 
 #[wasm_bindgen]
 impl Resonant {
     #[wasm_bindgen(constructor)]
     pub fn new(canvas_id: &str) -> Result<Resonant, JsValue> {
-        // Setup panic hook for better debugging
-        console_error_panic_hook::set_once();
+        Ok(Resonant { app: Rc::new(RefCell::new(App::new(canvas_id)?)) })
+    }
 
-        let document = web_sys::window().unwrap().document().unwrap();
-        let canvas = document.get_element_by_id(canvas_id).unwrap();
-        let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into()?;
+    // Drive render() from requestAnimationFrame inside the crate; 0 means uncapped
+    pub fn start(&self, frame_cap_fps: f32) -> Result<(), JsValue> {
+        // Weak, as the loop lives in the app: once the host frees it, frames stop
+        let app = Rc::downgrade(&self.app);
+        self.app().frame_loop.start(frame_cap_fps, move |delta_time| {
+            if let Some(app) = app.upgrade() {
+                app.borrow_mut().render(delta_time);
+            }
+        })
+    }
 
-        // Setup WebGL with better error handling
-        let gl = canvas
-            .get_context("webgl")?
-            .ok_or("WebGL not supported")?
-            .dyn_into::<GL>()?;
+    pub fn stop(&self) {
+        self.app().stop()
+    }
 
-        // Enable depth testing for better 3D rendering
-        gl.enable(GL::DEPTH_TEST);
-        gl.depth_func(GL::LEQUAL);
+    pub fn is_running(&self) -> bool {
+        self.app().is_running()
+    }
 
-        let program = Self::create_shader_program(&gl)?;
+    pub fn render(&self, delta_time: f32) {
+        self.app().render(delta_time)
+    }
 
-        // Initialize user state with persistence
-        let user_state = UserState::new()?;
+    pub fn set_target_loudness(&self, db: f32) {
+        self.app().set_target_loudness(db)
+    }
 
-        // Initialize audio engine
-        let audio_engine = AudioEngine::new()?;
+    pub fn set_gesture_sounds(&self, sounds_json: &str) -> Result<(), JsValue> {
+        self.app().set_gesture_sounds(sounds_json)
+    }
 
-        // Detect if this is a wake-up or just app open
-        let last_wake_time = Self::detect_wake_time();
+    pub fn get_gesture_sounds(&self) -> String {
+        self.app().get_gesture_sounds()
+    }
 
-        Ok(Resonant {
-            gl,
-            program,
-            user_state,
-            audio_engine,
-            time: 0.0,
-            fractal_type: "Unknown".to_string(),
-            last_wake_time,
-        })
+    pub fn is_haptics_supported(&self) -> bool {
+        self.app().is_haptics_supported()
     }
 
-    pub fn render(&mut self, delta_time: f32) {
-        self.time += delta_time * 0.001;
+    pub fn set_preference(&self, key: &str, value: &str) -> Result<(), JsValue> {
+        self.app().set_preference(key, value)
+    }
 
-        // Get today's fractal based on user ID + date + wake time
-        let current_fractal = self.user_state.get_current_fractal(self.time);
-        self.fractal_type = current_fractal.get_name().to_string();
+    pub fn get_insights(&self) -> String {
+        self.app().get_insights()
+    }
 
-        let gl = &self.gl;
-        gl.clear_color(0.0, 0.0, 0.02, 1.0);
-        gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
-        gl.use_program(Some(&self.program));
+    pub fn is_morning_session(&self) -> bool {
+        self.app().is_morning_session()
+    }
 
-        // Setup uniforms
-        self.setup_uniforms(&*current_fractal);
+    pub fn get_wake_time(&self) -> f64 {
+        self.app().get_wake_time()
+    }
 
-        // Draw fullscreen quad with vertices
-        self.draw_quad();
+    pub fn get_preferences(&self) -> String {
+        self.app().get_preferences()
+    }
 
-        // Update audio based on fractal state
-        self.update_audio(&*current_fractal);
+    pub fn set_haptics_enabled(&self, enabled: bool) -> Result<(), JsValue> {
+        self.app().set_haptics_enabled(enabled)
     }
 
-    fn setup_uniforms(&self, fractal: &dyn FractalGenerator) {
-        let gl = &self.gl;
+    pub fn get_haptics_enabled(&self) -> bool {
+        self.app().get_haptics_enabled()
+    }
 
-        // Time uniform
-        if let Some(loc) = gl.get_uniform_location(&self.program, "u_time") {
-            gl.uniform1f(Some(&loc), self.time);
-        }
+    pub fn set_haptic_mapping(&self, mapping_json: &str) -> Result<(), JsValue> {
+        self.app().set_haptic_mapping(mapping_json)
+    }
 
-        // Seed uniform
-        if let Some(loc) = gl.get_uniform_location(&self.program, "u_seed") {
-            gl.uniform1i(Some(&loc), self.user_state.get_seed() as i32);
-        }
+    pub fn set_arpeggiator(&self, settings_json: &str) -> Result<(), JsValue> {
+        self.app().set_arpeggiator(settings_json)
+    }
 
-        // Fractal type uniform
-        if let Some(loc) = gl.get_uniform_location(&self.program, "u_fractal_type") {
-            let fractal_id = match fractal.get_name() {
-                "Mandelbulb" => 0,
-                "Julia4D" => 1,
-                "KaleidoIFS" => 2,
-                _ => 0,
-            };
-            gl.uniform1i(Some(&loc), fractal_id);
-        }
+    pub fn get_arpeggiator(&self) -> String {
+        self.app().get_arpeggiator()
+    }
 
-        // Transform matrix from user interactions
-        if let Some(loc) = gl.get_uniform_location(&self.program, "u_transform") {
-            let transform = self.user_state.get_current_transform();
-            let matrix_array: [f32; 16] = transform.as_slice().try_into().unwrap_or([
-                1.0, 0.0, 0.0, 0.0,
-                0.0, 1.0, 0.0, 0.0,
-                0.0, 0.0, 1.0, 0.0,
-                0.0, 0.0, 0.0, 1.0,
-            ]);
-            gl.uniform_matrix4fv_with_f32_array(Some(&loc), false, &matrix_array);
-        }
+    pub fn suspend_audio(&self) {
+        self.app().suspend_audio()
     }
 
-    fn draw_quad(&self) {
-        // For now, use the built-in triangle strip
-        // Later we'll add proper vertex buffers for mobile optimization
-        self.gl.draw_arrays(GL::TRIANGLE_STRIP, 0, 4);
+    pub fn resume_audio(&self) {
+        self.app().resume_audio()
     }
 
-    fn update_audio(&mut self, fractal: &dyn FractalGenerator) {
-        // Sample fractal at key points for audio generation
-        let sample_points = vec![
-            Vector4::new(1.0, 0.0, 0.0, self.time * 0.1),
-            Vector4::new(0.0, 1.0, 0.0, self.time * 0.13),
-            Vector4::new(0.0, 0.0, 1.0, self.time * 0.17),
-            Vector4::new((self.time * 0.1).sin(), (self.time * 0.1).cos(), 0.0, 0.5),
-        ];
+    pub fn is_audio_running(&self) -> bool {
+        self.app().is_audio_running()
+    }
 
-        let frequencies = FractalAudioAnalyzer::extract_frequencies(fractal, &sample_points);
-        self.audio_engine.update_frequencies(&frequencies);
+    pub fn set_rhythm_enabled(&self, enabled: bool) {
+        self.app().set_rhythm_enabled(enabled)
     }
 
-    pub fn apply_gesture(&mut self, gesture_type: &str, intensity: f32, direction: f32) -> Result<(), JsValue> {
-        // Make gesture effects MUCH more dramatic and immediate
-        let dramatic_intensity = intensity * 2.0; // Double the effect
+    pub fn get_rhythm(&self) -> String {
+        self.app().get_rhythm()
+    }
 
-        // Convert gesture to mathematical transform with enhanced effects
-        let transform = match gesture_type {
-            "swipe" => {
-                // Create massive rotation with multiple axes
-                let primary = self.create_rotation_transform(direction, dramatic_intensity);
-                let secondary = self.create_rotation_transform(direction + std::f32::consts::PI/3.0, dramatic_intensity * 0.7);
-                primary * secondary
-            },
-            "pinch" => {
-                // Dramatic scaling with non-uniform effects
-                let scale_factor = 1.0 + dramatic_intensity * 3.0;
-                self.create_complex_scale_transform(scale_factor, direction)
-            },
-            "tilt" => {
-                // Complex 4D transformation
-                let tilt = self.create_tilt_transform(direction, dramatic_intensity);
-                let twist = self.create_4d_twist_transform(direction, dramatic_intensity * 0.5);
-                tilt * twist
-            },
-            "smile" => {
-                // Brightness + color shift + fractal parameter change
-                let brightness = self.create_brightness_transform(dramatic_intensity);
-                let color_shift = self.create_color_shift_transform(dramatic_intensity);
-                let params = self.create_parameter_shift_transform(dramatic_intensity);
-                brightness * color_shift * params
-            },
-            _ => return Ok(()),
-        };
+    pub fn set_doppler_depth(&self, depth: f32) {
+        self.app().set_doppler_depth(depth)
+    }
 
-        // Apply transform with animation interpolation
-        self.user_state.apply_transform_animated(transform, dramatic_intensity)?;
+    pub fn set_harmony_enabled(&self, enabled: bool) {
+        self.app().set_harmony_enabled(enabled)
+    }
 
-        // Trigger audio feedback
-        self.audio_engine.play_gesture_feedback(gesture_type, intensity)?;
+    pub fn get_harmony(&self) -> String {
+        self.app().get_harmony()
+    }
 
-        Ok(())
+    pub fn set_modulation(&self, routes_json: &str) -> Result<(), JsValue> {
+        self.app().set_modulation(routes_json)
     }
 
-    fn create_rotation_transform(&self, direction: f32, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::{Matrix4, Vector3};
+    pub fn get_modulation(&self) -> String {
+        self.app().get_modulation()
+    }
+
+    pub fn sonify_view(&self) -> Result<(), JsValue> {
+        self.app().sonify_view()
+    }
+
+    pub fn set_ambience_level(&self, level: f32) {
+        self.app().set_ambience_level(level)
+    }
+
+    pub fn get_ambience(&self) -> String {
+        self.app().get_ambience()
+    }
+
+    pub fn set_max_voices(&self, count: u32) {
+        self.app().set_max_voices(count)
+    }
+
+    pub fn set_audio_glide(&self, seconds: f32) {
+        self.app().set_audio_glide(seconds)
+    }
+
+    pub fn set_audio_effect(&self, name: &str, params_json: &str) -> Result<(), JsValue> {
+        self.app().set_audio_effect(name, params_json)
+    }
+
+    pub fn get_audio_effects(&self) -> String {
+        self.app().get_audio_effects()
+    }
+
+    pub fn enable_audio_input(&self, perturb_transform: bool) -> Result<(), JsValue> {
+        self.app().enable_audio_input(perturb_transform)
+    }
+
+    pub fn disable_audio_input(&self) {
+        self.app().disable_audio_input()
+    }
+
+    pub fn get_audio_bands(&self) -> Vec<f32> {
+        self.app().get_audio_bands()
+    }
+
+    pub fn enable_midi_output(&self) -> Result<(), JsValue> {
+        self.app().enable_midi_output()
+    }
+
+    pub fn disable_midi_output(&self) {
+        self.app().disable_midi_output()
+    }
+
+    pub fn get_midi_outputs(&self) -> String {
+        self.app().get_midi_outputs()
+    }
+
+    pub fn set_midi_output(&self, output_id: &str) -> Result<(), JsValue> {
+        self.app().set_midi_output(output_id)
+    }
+
+    pub fn apply_gesture(&self, gesture_type: &str, intensity: f32, direction: f32) -> Result<(), JsValue> {
+        self.app().apply_gesture(gesture_type, intensity, direction)
+    }
+
+    pub fn undo(&self) -> bool {
+        self.app().undo()
+    }
+
+    pub fn redo(&self) -> bool {
+        self.app().redo()
+    }
+
+    pub fn reset_transform(&self) {
+        self.app().reset_transform()
+    }
+
+    pub fn replay_today(&self, speed: f32) -> bool {
+        self.app().replay_today(speed)
+    }
+
+    pub fn stop_replay(&self) {
+        self.app().stop_replay()
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.app().is_replaying()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.app().can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.app().can_redo()
+    }
+
+    pub fn set_focus_distance(&self, distance: f32) {
+        self.app().set_focus_distance(distance)
+    }
+
+    pub fn set_aperture(&self, aperture: f32) {
+        self.app().set_aperture(aperture)
+    }
+
+    pub fn set_coloring_mode(&self, mode: &str) -> Result<(), JsValue> {
+        self.app().set_coloring_mode(mode)
+    }
+
+    pub fn set_environment(&self, environment_json: &str) -> Result<(), JsValue> {
+        self.app().set_environment(environment_json)
+    }
+
+    pub fn set_palette(&self, name_or_json: &str) -> Result<(), JsValue> {
+        self.app().set_palette(name_or_json)
+    }
+
+    pub fn render_thumbnail(&self, frozen_json: &str, size: u32) -> Result<Vec<u8>, JsValue> {
+        self.app().render_thumbnail(frozen_json, size)
+    }
+
+    pub fn render_frozen_hq(&self, frozen_json: &str, width: u32, height: u32, samples: u32) -> Result<Vec<u8>, JsValue> {
+        self.app().render_frozen_hq(frozen_json, width, height, samples)
+    }
+
+    pub fn set_slice(&self, normal: Vec<f32>, offset: f32, enabled: bool) -> Result<(), JsValue> {
+        self.app().set_slice(normal, offset, enabled)
+    }
+
+    pub fn animate_slice(&self, speed: f32, amplitude: f32) {
+        self.app().animate_slice(speed, amplitude)
+    }
+
+    pub fn get_stats(&self) -> String {
+        self.app().get_stats()
+    }
+
+    pub fn set_stats_overlay(&self, enabled: bool) -> Result<(), JsValue> {
+        self.app().set_stats_overlay(enabled)
+    }
+
+    pub fn set_render_settings(&self, settings_json: &str) -> Result<(), JsValue> {
+        self.app().set_render_settings(settings_json)
+    }
+
+    pub fn get_render_settings(&self) -> String {
+        self.app().get_render_settings()
+    }
+
+    pub fn zoom_at(&self, pixel_x: f32, pixel_y: f32, factor: f32) {
+        self.app().zoom_at(pixel_x, pixel_y, factor)
+    }
+
+    pub fn pan(&self, delta_x: f32, delta_y: f32) {
+        self.app().pan(delta_x, delta_y)
+    }
+
+    pub fn reset_zoom(&self) {
+        self.app().reset_zoom()
+    }
+
+    pub fn get_zoom(&self) -> f64 {
+        self.app().get_zoom()
+    }
+
+    pub fn set_hybrid(&self, spec: &str) -> Result<(), JsValue> {
+        self.app().set_hybrid(spec)
+    }
+
+    pub fn set_fractal_params(&self, params: &str) -> Result<(), JsValue> {
+        self.app().set_fractal_params(params)
+    }
+
+    pub fn set_weather(&self, source: &str) -> Result<(), JsValue> {
+        self.app().set_weather(source)
+    }
+
+    pub fn set_adaptive_quality(&self, target_fps: f32) {
+        self.app().set_adaptive_quality(target_fps)
+    }
+
+    pub fn load_preset(&self, name: &str) -> Result<(), JsValue> {
+        self.app().load_preset(name)
+    }
+
+    pub fn morph_to(&self, frozen_json: &str, duration: f32) -> Result<(), JsValue> {
+        self.app().morph_to(frozen_json, duration)
+    }
+
+    pub fn export_mesh(&self, format: &str, resolution: u32) -> Result<Vec<u8>, JsValue> {
+        self.app().export_mesh(format, resolution)
+    }
+
+    pub fn get_preset_names(&self) -> String {
+        self.app().get_preset_names()
+    }
+
+    pub fn get_fractal_params(&self) -> String {
+        self.app().get_fractal_params()
+    }
+
+    pub fn get_share_url(&self) -> String {
+        self.app().get_share_url()
+    }
+
+    pub fn load_from_url(&self, search_params: &str) -> Result<String, JsValue> {
+        self.app().load_from_url(search_params)
+    }
+
+    pub fn get_fractal_info(&self) -> String {
+        self.app().get_fractal_info()
+    }
+
+    pub fn is_storage_ready(&self) -> bool {
+        self.app().is_storage_ready()
+    }
+
+    pub fn export_state(&self) -> String {
+        self.app().export_state()
+    }
+
+    pub fn import_state(&self, state_json: &str) -> Result<(), JsValue> {
+        self.app().import_state(state_json)
+    }
+
+    pub fn get_history(&self, from: &str, to: &str) -> String {
+        self.app().get_history(from, to)
+    }
+
+    pub fn set_day_note(&self, text: &str) -> Result<(), JsValue> {
+        self.app().set_day_note(text)
+    }
+
+    pub fn get_day_note(&self) -> Option<String> {
+        self.app().get_day_note()
+    }
+
+    pub fn load_past_day(&self, date: &str) -> Result<String, JsValue> {
+        self.app().load_past_day(date)
+    }
+
+    pub fn regenerate_identity(&self) -> Result<(), JsValue> {
+        self.app().regenerate_identity()
+    }
+
+    pub fn freeze_fractal(&self) -> Result<String, JsValue> {
+        self.app().freeze_fractal()
+    }
+
+    pub fn list_frozen(&self, filter_json: &str) -> Result<String, JsValue> {
+        self.app().list_frozen(filter_json)
+    }
+
+    pub fn get_collections(&self) -> String {
+        self.app().get_collections()
+    }
+
+    pub fn update_frozen(&self, seed: u32, timestamp: f64, changes_json: &str) -> Result<String, JsValue> {
+        self.app().update_frozen(seed, timestamp, changes_json)
+    }
+
+    pub fn keep_frozen(&self, frozen_json: &str) -> Result<String, JsValue> {
+        self.app().keep_frozen(frozen_json)
+    }
+
+    pub fn get_lineage(&self, seed: u32, timestamp: f64) -> Result<String, JsValue> {
+        self.app().get_lineage(seed, timestamp)
+    }
+
+    pub fn delete_frozen(&self, seed: u32, timestamp: f64) -> Result<bool, JsValue> {
+        self.app().delete_frozen(seed, timestamp)
+    }
+
+    pub fn set_frozen_capacity(&self, capacity: u32) -> Result<(), JsValue> {
+        self.app().set_frozen_capacity(capacity)
+    }
+
+    pub fn get_frozen_capacity(&self) -> u32 {
+        self.app().get_frozen_capacity()
+    }
+
+    pub fn breed_with(&self, opponent_json: &str) -> Result<String, JsValue> {
+        self.app().breed_with(opponent_json)
+    }
+
+    pub fn echo_fractal(&self, original_json: &str) -> Result<String, JsValue> {
+        self.app().echo_fractal(original_json)
+    }
+
+    pub fn battle_fractals(&self, opponent_data: &str) -> Result<String, JsValue> {
+        self.app().battle_fractals(opponent_data)
+    }
+
+    pub fn send_battle_challenge(&self, friend_id: &str) -> Result<String, JsValue> {
+        self.app().send_battle_challenge(friend_id)
+    }
+
+    pub fn accept_battle_challenge(&self, challenge_id: &str) -> Result<(), JsValue> {
+        self.app().accept_battle_challenge(challenge_id)
+    }
+
+    pub fn decline_battle_challenge(&self, challenge_id: &str) -> Result<(), JsValue> {
+        self.app().decline_battle_challenge(challenge_id)
+    }
+
+    pub fn get_battle_challenges(&self) -> String {
+        self.app().get_battle_challenges()
+    }
+
+    pub fn set_battle_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_battle_callback(callback)
+    }
+
+    pub fn get_battle_record(&self) -> String {
+        self.app().get_battle_record()
+    }
+
+    pub fn get_achievements(&self) -> String {
+        self.app().get_achievements()
+    }
+
+    pub fn set_achievement_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_achievement_callback(callback)
+    }
+
+    pub fn set_rollover_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_rollover_callback(callback)
+    }
+
+    pub fn set_storage_error_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_storage_error_callback(callback)
+    }
+
+    pub fn erase_all_data(&self) -> Result<String, JsValue> {
+        self.app().erase_all_data()
+    }
+
+    pub fn enter_sandbox(&self, seed: u32) -> Result<(), JsValue> {
+        self.app().enter_sandbox(seed)
+    }
+
+    pub fn exit_sandbox(&self) -> bool {
+        self.app().exit_sandbox()
+    }
+
+    pub fn is_in_sandbox(&self) -> bool {
+        self.app().is_in_sandbox()
+    }
+
+    pub fn get_next_golden_hour(&self) -> String {
+        self.app().get_next_golden_hour()
+    }
+
+    pub fn is_golden_hour(&self) -> bool {
+        self.app().is_golden_hour()
+    }
+
+    pub fn get_golden_stats(&self) -> String {
+        self.app().get_golden_stats()
+    }
+
+    pub fn set_resonance_settings(&self, settings_json: &str) -> Result<(), JsValue> {
+        self.app().set_resonance_settings(settings_json)
+    }
+
+    pub fn get_resonance_settings(&self) -> String {
+        self.app().get_resonance_settings()
+    }
+
+    pub fn set_resonance_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_resonance_callback(callback)
+    }
+
+    pub fn get_resonance_history(&self) -> String {
+        self.app().get_resonance_history()
+    }
+
+    pub fn get_resonance_level(&self) -> f32 {
+        self.app().get_resonance_level()
+    }
+
+    pub fn configure_sync(&self, endpoint: &str, token: Option<String>) -> Result<(), JsValue> {
+        self.app().configure_sync(endpoint, token)
+    }
+
+    pub fn stop_sync(&self) {
+        self.app().stop_sync()
+    }
+
+    pub fn sync_now(&self) -> bool {
+        self.app().sync_now()
+    }
+
+    pub fn set_sync_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_sync_callback(callback)
+    }
+
+    pub fn configure_gallery(&self, endpoint: &str, token: Option<String>) -> Result<(), JsValue> {
+        self.app().configure_gallery(endpoint, token)
+    }
+
+    pub fn publish_to_gallery(&self, frozen_json: Option<String>, author: Option<String>, thumbnail: Option<Vec<u8>>) -> Result<(), JsValue> {
+        self.app().publish_to_gallery(frozen_json, author, thumbnail)
+    }
+
+    pub fn list_gallery(&self, page: u32) -> Result<(), JsValue> {
+        self.app().list_gallery(page)
+    }
+
+    pub fn open_gallery_fractal(&self, id: &str) -> Result<(), JsValue> {
+        self.app().open_gallery_fractal(id)
+    }
+
+    pub fn set_gallery_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_gallery_callback(callback)
+    }
+
+    pub fn connect_network(&self, url: &str) -> Result<(), JsValue> {
+        self.app().connect_network(url)
+    }
+
+    pub fn disconnect_network(&self) {
+        self.app().disconnect_network()
+    }
+
+    pub fn is_network_connected(&self) -> bool {
+        self.app().is_network_connected()
+    }
+
+    pub fn share_morning_fractal(&self, friend_ids: Option<String>) -> Result<(), JsValue> {
+        self.app().share_morning_fractal(friend_ids)
+    }
+
+    pub fn build_morning_notification(&self) -> Result<String, JsValue> {
+        self.app().build_morning_notification()
+    }
+
+    pub fn send_echo(&self, original_json: &str, friend_ids: Option<String>) -> Result<(), JsValue> {
+        self.app().send_echo(original_json, friend_ids)
+    }
+
+    pub fn start_echo_stream(&self, friend_ids: Option<String>) -> Result<(), JsValue> {
+        self.app().start_echo_stream(friend_ids)
+    }
+
+    pub fn stop_echo_stream(&self) -> bool {
+        self.app().stop_echo_stream()
+    }
+
+    pub fn get_echo_streams(&self) -> String {
+        self.app().get_echo_streams()
+    }
+
+    pub fn set_echo_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_echo_callback(callback)
+    }
+
+    pub fn add_friend_from_share_url(&self, url: &str, name: Option<String>) -> Result<String, JsValue> {
+        self.app().add_friend_from_share_url(url, name)
+    }
+
+    pub fn list_friends(&self) -> String {
+        self.app().list_friends()
+    }
+
+    pub fn get_active_friends(&self) -> String {
+        self.app().get_active_friends()
+    }
+
+    pub fn rename_friend(&self, id: &str, name: &str) -> Result<(), JsValue> {
+        self.app().rename_friend(id, name)
+    }
+
+    pub fn remove_friend(&self, id: &str) -> bool {
+        self.app().remove_friend(id)
+    }
+
+    pub fn join_room(&self, room_id: &str, seed: Option<u32>) -> Result<(), JsValue> {
+        self.app().join_room(room_id, seed)
+    }
+
+    pub fn leave_room(&self) -> bool {
+        self.app().leave_room()
+    }
+
+    pub fn get_room(&self) -> String {
+        self.app().get_room()
+    }
+
+    pub fn set_room_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_room_callback(callback)
+    }
+
+    pub fn set_network_callback(&self, callback: Option<js_sys::Function>) {
+        self.app().set_network_callback(callback)
+    }
+
+    pub fn set_shared_epoch(&self, epoch: f64) {
+        self.app().set_shared_epoch(epoch)
+    }
+
+    pub fn get_clock_sync(&self) -> String {
+        self.app().get_clock_sync()
+    }
+
+    pub fn poll_inbox(&self) -> String {
+        self.app().poll_inbox()
+    }
+
+    pub fn get_storage_usage(&self) -> String {
+        self.app().get_storage_usage()
+    }
+
+    pub fn set_day_boundary(&self, boundary: &str) -> Result<(), JsValue> {
+        self.app().set_day_boundary(boundary)
+    }
+
+    pub fn get_day_boundary(&self) -> String {
+        self.app().get_day_boundary()
+    }
+}
+
+impl Resonant {
+    // The app for the length of one call
+    fn app(&self) -> RefMut<'_, App> {
+        self.app.try_borrow_mut()
+            .unwrap_or_else(|_| wasm_bindgen::throw_str("Resonant can't be called from its own callbacks"))
+    }
+}
+
+struct App {
+    canvas: web_sys::HtmlCanvasElement,
+    gl: GL,
+    program: WebGlProgram,
+    program_2d: WebGlProgram,
+    line_renderer: LineRenderer,
+    user_state: UserState,
+    audio_engine: AudioEngine,
+    audio_input: Option<AudioInput>, // Microphone, while audio-reactive rendering is on
+    midi_out: Option<MidiOut>,       // While the voices are also sent to a MIDI output
+    haptics: Haptics,
+    achievement_callback: Option<js_sys::Function>,
+    rollover_callback: Option<js_sys::Function>,
+    storage_error_callback: Option<js_sys::Function>,
+    cloud_sync: Option<CloudSync>, // Once the host has configured a backend
+    sync_callback: Option<js_sys::Function>,
+    network: NetworkManager, // Sharing with friends, once the host has connected it to a server
+    network_callback: Option<js_sys::Function>,
+    gallery: Option<GalleryClient>, // Once the host has configured one
+    gallery_callback: Option<js_sys::Function>,
+    resonance: Resonance,
+    resonance_callback: Option<js_sys::Function>,
+    battle_callback: Option<js_sys::Function>,
+    room_callback: Option<js_sys::Function>,
+    echo_callback: Option<js_sys::Function>,
+    pending_link: Option<SharedLink>, // Waiting for the saved state to load, see load_from_url
+    harmony_enabled: bool,           // Whether voices are held to the day's chord progression
+    audio_seed: u32,                 // Seed the voices last played, to crossfade when it changes
+    audio_transition: Option<f32>,   // Seconds of crossfade the next audio update should make
+    time: f32,
+    fractal_type: FractalType,
+    dof: DepthOfField,
+    coloring_mode: ColoringMode,
+    environment: Environment,
+    palette: Palette,
+    fractal_params: Option<FractalParams>, // Overrides the daily seed's fractal when set
+    preset: Option<&'static str>,          // Preset fractal_params came from, shared by name
+    morph: Option<Morph>,                  // Transition into a received fractal, see morph_to
+    replay: Option<Replay>,                // The day's gestures playing back, see replay_today
+    fx_params: Option<FractalParams>,      // Fractal the audio effects were last fitted to
+    rhythm_params: Option<FractalParams>,  // Fractal the sequencer's pattern came from
+    weather: WeatherSource,
+    quality: QualityController,
+    equalizer: Equalizer,
+    thumbnail_target: RenderTarget,
+    render_settings: RenderSettings,
+    zoom: ZoomState,
+    slice: SlicePlane,
+    frame_loop: FrameLoop,
+    stats: RenderStats,
+    stats_overlay: Option<StatsOverlay>,
+}
+
+impl App {
+    pub fn new(canvas_id: &str) -> Result<App, JsValue> {
+        // Setup panic hook for better debugging
+        console_error_panic_hook::set_once();
+
+        let document = web_sys::window().unwrap().document().unwrap();
+        let canvas = document.get_element_by_id(canvas_id).unwrap();
+        let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into()?;
+
+        // Setup WebGL with better error handling
+        let gl = canvas
+            .get_context("webgl")?
+            .ok_or("WebGL not supported")?
+            .dyn_into::<GL>()?;
+
+        // Enable depth testing for better 3D rendering
+        gl.enable(GL::DEPTH_TEST);
+        gl.depth_func(GL::LEQUAL);
+
+        let program = Self::create_shader_program(&gl)?;
+        let program_2d = Self::link_program(&gl, VERTEX_SHADER, &glsl::with_helpers(FRAGMENT_SHADER_2D))?;
+        let line_program = Self::link_program(&gl, lsystem::LINE_VERTEX_SHADER, lsystem::LINE_FRAGMENT_SHADER)?;
+        let line_renderer = LineRenderer::new(&gl, line_program)?;
+
+        // Depth-of-field resolve pass, idle until an aperture is set
+        let dof_program = Self::link_program(&gl, VERTEX_SHADER, postfx::DOF_FRAGMENT_SHADER)?;
+        let dof = DepthOfField::new(&gl, dof_program)?;
+        let thumbnail_target = RenderTarget::new(&gl)?;
+        let equalizer = Equalizer::new(&gl)?;
+
+        // Initialize user state with persistence
+        let user_state = UserState::new()?;
+
+        // Initialize audio engine
+        let audio_engine = AudioEngine::new()?;
+
+        // Until the user picks one, colors come from the daily seed
+        let seed = user_state.get_seed();
+        let palette = Palette::from_seed(seed);
+        let fractal_type = FractalParams::from_seed(seed).fractal_type();
+        let network = NetworkManager::new(user_state.get_user_id().to_string());
+
+        let mut resonant = App {
+            canvas,
+            gl,
+            program,
+            program_2d,
+            line_renderer,
+            user_state,
+            audio_engine,
+            audio_input: None,
+            midi_out: None,
+            haptics: Haptics::new(),
+            achievement_callback: None,
+            rollover_callback: None,
+            storage_error_callback: None,
+            cloud_sync: None,
+            sync_callback: None,
+            network,
+            network_callback: None,
+            gallery: None,
+            gallery_callback: None,
+            resonance: Resonance::default(),
+            resonance_callback: None,
+            battle_callback: None,
+            room_callback: None,
+            echo_callback: None,
+            pending_link: None,
+            harmony_enabled: true,
+            audio_seed: seed,
+            audio_transition: None,
+            time: 0.0,
+            fractal_type,
+            dof,
+            coloring_mode: ColoringMode::Standard,
+            environment: Environment::default(),
+            palette,
+            fractal_params: None,
+            preset: None,
+            morph: None,
+            replay: None,
+            fx_params: None,
+            rhythm_params: None,
+            weather: WeatherSource::Seed,
+            quality: QualityController::default(),
+            equalizer,
+            thumbnail_target,
+            render_settings: RenderSettings::default(),
+            zoom: ZoomState::default(),
+            slice: SlicePlane::default(),
+            frame_loop: FrameLoop::new(),
+            stats: RenderStats::default(),
+            stats_overlay: None,
+        };
+        for key in ["volume", "quality", "palette"] {
+            resonant.apply_preference(key)?;
+        }
+
+        // Opened from a share link, the page shows what it carries
+        let search = web_sys::window().and_then(|window| window.location().search().ok()).unwrap_or_default();
+        match resonant.network.parse_share_link(&search) {
+            Ok(Some(link)) => resonant.pending_link = Some(link),
+            Ok(None) => {}
+            Err(e) => web_sys::console::warn_2(&JsValue::from_str("Share link not opened:"), &e),
+        }
+        Ok(resonant)
+    }
+
+    pub fn stop(&mut self) {
+        self.frame_loop.stop();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.frame_loop.is_running()
+    }
+
+    // `delta_time` is the frame's interval in ms, for the stats and quality controller;
+    // the animation itself advances by the shared clock the audio is scheduled on, so what
+    // is seen and what is heard can't drift apart however uneven the frames
+    pub fn render(&mut self, delta_time: f32) {
+        self.user_state.poll_store();
+        self.user_state.note_activity();
+        match self.user_state.check_rollover() {
+            Ok(Some(old_seed)) => self.roll_over(old_seed),
+            Ok(None) => {}
+            Err(e) => web_sys::console::warn_2(&JsValue::from_str("Day rollover failed:"), &e),
+        }
+        self.report_storage_errors();
+        self.poll_link();
+        self.poll_sync();
+        self.poll_network();
+        self.poll_room();
+        self.poll_echo();
+        self.poll_battles();
+        self.poll_resonance();
+        self.poll_gallery();
+        let elapsed = self.audio_engine.tick_clock() as f32;
+        self.time += elapsed;
+        self.advance_morph(elapsed);
+        self.advance_replay(elapsed as f64);
+        if let Some(input) = &mut self.audio_input {
+            input.update(elapsed);
+        }
+        let (audio_bands, audio_perturbation) = match &self.audio_input {
+            Some(input) => (input.bands(), input.perturbation()),
+            None => ([0.0; AUDIO_BANDS], nalgebra::Matrix4::identity()),
+        };
+
+        // Get today's fractal based on user ID + date + wake time
+        let params = self.current_params();
+        let modifier = self.weather_modifier(&params);
+        self.quality.record_frame(delta_time);
+        let current_fractal = with_detail(modifier.wrap(
+            self.user_state.get_current_fractal(self.time, self.coloring_mode, &self.palette, &params)
+        ), self.quality.detail);
+        self.fractal_type = params.fractal_type();
+
+        // Route the scene through the offscreen target when depth of field is on; the
+        // plane shader and the line pipeline write no depth, so they never get the pass
+        let dof_active = !params.is_planar() && !params.is_line_geometry() && self.dof.is_enabled() && self.dof.begin(&self.gl).is_ok();
+
+        let gl = &self.gl;
+        gl.clear_color(0.0, 0.0, 0.02, 1.0);
+        gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        gl.use_program(Some(self.scene_program(&params)));
+
+        // Setup uniforms
+        let view = SceneView {
+            seed: self.user_state.get_seed(),
+            time: self.time,
+            transform: self.view_transform() * audio_perturbation,
+            width: gl.drawing_buffer_width(),
+            height: gl.drawing_buffer_height(),
+            tile: [0.0, 0.0, gl.drawing_buffer_width() as f32, gl.drawing_buffer_height() as f32],
+            audio_bands,
+            audio_state: self.audio_engine.voice_state(),
+            pulse: self.audio_engine.rhythm_pulse(),
+            golden: self.user_state.golden_aura(),
+            resonance: self.resonance.level(js_sys::Date::now() as u64),
+            write_depth: dof_active,
+            zoom: self.zoom,
+        };
+        // A failed lookup upload keeps the previous one; only the colors suffer
+        let _ = self.prepare_equalizer(&*current_fractal);
+        self.setup_uniforms(&*current_fractal, &view);
+        modifier.apply_uniforms(&self.gl, self.scene_program(&params));
+        self.draw_scene(&current_fractal.get_params(), current_fractal.get_palette());
+
+        if dof_active {
+            self.dof.end(&self.gl);
+        }
+
+        let draw_calls = 1 + dof_active as u32;
+        let resolution_scale = self.resolution_scale();
+        self.stats.record_frame(
+            delta_time, view.width, view.height, draw_calls,
+            self.render_settings.foveation, resolution_scale,
+        );
+        if let Some(overlay) = &self.stats_overlay {
+            overlay.update(&self.stats);
+        }
+
+        // Update audio based on fractal state
+        self.update_audio(&*current_fractal);
+    }
+
+    // The equalization lookup is built from CPU-sampled orbits, so skip it unless needed
+    fn prepare_equalizer(&mut self, fractal: &dyn FractalGenerator) -> Result<(), JsValue> {
+        if fractal.get_coloring_mode() != ColoringMode::Equalized {
+            return Ok(());
+        }
+        self.equalizer.prepare(&self.gl, fractal)
+    }
+
+    // Only the raymarcher applies the weather and roughness layer
+    fn weather_modifier(&self, params: &FractalParams) -> Modifier {
+        if params.is_planar() || params.is_line_geometry() {
+            return Modifier::default();
+        }
+        self.weather.modifier(self.user_state.get_seed())
+            .with_interactions(self.user_state.get_interaction_count())
+    }
+
+    // The plane fractals have their own cheap shader and the plants are drawn as lines;
+    // everything else is raymarched
+    fn scene_program(&self, params: &FractalParams) -> &WebGlProgram {
+        if params.is_line_geometry() {
+            self.line_renderer.program()
+        } else if params.is_planar() {
+            &self.program_2d
+        } else {
+            &self.program
+        }
+    }
+
+    // Expects scene_program(params) in use with its uniforms set
+    fn draw_scene(&mut self, params: &FractalParams, palette: &Palette) {
+        if params.is_line_geometry() {
+            self.line_renderer.prepare(&self.gl, params, palette);
+            self.line_renderer.draw(&self.gl);
+        } else {
+            self.draw_quad();
+        }
+    }
+
+    fn setup_uniforms(&self, fractal: &dyn FractalGenerator, view: &SceneView) {
+        let gl = &self.gl;
+        let params = fractal.get_params();
+        let program = self.scene_program(&params);
+
+        // Time uniform
+        if let Some(loc) = gl.get_uniform_location(program, "u_time") {
+            gl.uniform1f(Some(&loc), view.time);
+        }
+
+        // Seed uniform
+        if let Some(loc) = gl.get_uniform_location(program, "u_seed") {
+            gl.uniform1i(Some(&loc), view.seed as i32);
+        }
+
+        // Output size in pixels
+        if let Some(loc) = gl.get_uniform_location(program, "u_resolution") {
+            gl.uniform2f(Some(&loc), view.width as f32, view.height as f32);
+        }
+
+        // Region of the output this draw covers
+        if let Some(loc) = gl.get_uniform_location(program, "u_tile") {
+            gl.uniform4fv_with_f32_array(Some(&loc), &view.tile);
+        }
+
+        // Microphone band energies, lowest first
+        if let Some(loc) = gl.get_uniform_location(program, "u_audio_bands") {
+            gl.uniform1fv_with_f32_array(Some(&loc), &view.audio_bands);
+        }
+
+        // The voices sounding, loudest first
+        if let Some(loc) = gl.get_uniform_location(program, "u_audio_state") {
+            gl.uniform2fv_with_f32_array(Some(&loc), &view.audio_state);
+        }
+
+        // The heartbeat, landing with the hits the sequencer scheduled
+        if let Some(loc) = gl.get_uniform_location(program, "u_pulse") {
+            gl.uniform1f(Some(&loc), view.pulse);
+        }
+
+        // The golden-hour aura
+        if let Some(loc) = gl.get_uniform_location(program, "u_golden") {
+            gl.uniform1f(Some(&loc), view.golden);
+        }
+
+        // The resonance mode's shimmer
+        if let Some(loc) = gl.get_uniform_location(program, "u_resonance") {
+            gl.uniform1f(Some(&loc), view.resonance);
+        }
+
+        // Fractal type uniform
+        if let Some(loc) = gl.get_uniform_location(program, "u_fractal_type") {
+            gl.uniform1i(Some(&loc), params.shader_id());
+        }
+
+        // Formula settings, so the shader orbit matches the Rust one
+        if let Some(loc) = gl.get_uniform_location(program, "u_fractal_params") {
+            gl.uniform4fv_with_f32_array(Some(&loc), &params.shader_params());
+        }
+
+        // Coloring mode uniform
+        if let Some(loc) = gl.get_uniform_location(program, "u_coloring_mode") {
+            gl.uniform1i(Some(&loc), fractal.get_coloring_mode().shader_id());
+        }
+
+        // Transform matrix from user interactions
+        if let Some(loc) = gl.get_uniform_location(program, "u_transform") {
+            let transform = view.transform;
+            let matrix_array: [f32; 16] = transform.as_slice().try_into().unwrap_or([
+                1.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            ]);
+            gl.uniform_matrix4fv_with_f32_array(Some(&loc), false, &matrix_array);
+        }
+
+        // Surface palette
+        fractal.get_palette().apply_uniforms(gl, program);
+
+        // Background and reflection environment
+        self.environment.apply_uniforms(gl, program);
+
+        // Quality knobs such as foveation
+        self.render_settings.apply_uniforms(gl, program);
+
+        // Camera zoom, split into hi/lo parts for the dual-float path
+        view.zoom.apply_uniforms(gl, program, self.render_settings.deep_zoom);
+
+        // Cross-section plane
+        self.slice.apply_uniforms(gl, program, view.time);
+
+        // Equalization lookup; bound even when unused so the sampler never points at
+        // the depth-of-field target being rendered into
+        self.equalizer.apply_uniforms(gl, program);
+
+        // Depth goes into alpha only when the depth-of-field pass will consume it
+        if let Some(loc) = gl.get_uniform_location(program, "u_write_depth") {
+            gl.uniform1i(Some(&loc), view.write_depth as i32);
+        }
+    }
+
+    // Drawing buffer pixels per physical screen pixel
+    fn resolution_scale(&self) -> f32 {
+        let device_pixel_ratio = web_sys::window().map(|w| w.device_pixel_ratio()).unwrap_or(1.0);
+        let physical_width = self.canvas.client_width() as f64 * device_pixel_ratio;
+        if physical_width <= 0.0 {
+            return 1.0;
+        }
+        (self.gl.drawing_buffer_width() as f64 / physical_width) as f32
+    }
+
+    fn draw_quad(&self) {
+        // For now, use the built-in triangle strip
+        // Later we'll add proper vertex buffers for mobile optimization
+        self.gl.draw_arrays(GL::TRIANGLE_STRIP, 0, 4);
+    }
+
+    fn update_audio(&mut self, fractal: &dyn FractalGenerator) {
+        // Through a morph the voices already play the destination, having crossfaded to it
+        // when the morph began
+        let destination = self.morph.as_ref().map(|morph| with_detail(self.weather_modifier(&morph.to).wrap(
+            create_fractal_from_params(&morph.to, self.time, self.coloring_mode, self.palette.clone())
+        ), self.quality.detail));
+        let fractal = destination.as_deref().unwrap_or(fractal);
+
+        // Sample fractal at key points for audio generation
+        let sample_points = FractalAudioAnalyzer::sample_points(self.time);
+
+        let voices = FractalAudioAnalyzer::extract_voices(fractal, &sample_points);
+        let mut frequencies: Vec<f32> = voices.iter().map(|&(frequency, _)| frequency).collect();
+
+        // Snapped onto the current chord of the day's progression, which moves on as the
+        // user keeps interacting
+        let progression = Progression::from_seed(self.user_state.get_seed());
+        let chord = progression.chord_at(self.user_state.get_interaction_count());
+        if self.harmony_enabled {
+            for frequency in &mut frequencies {
+                *frequency = chord.constrain(*frequency);
+            }
+        }
+        // A new day's seed is a different piece altogether, so blend rather than glide
+        if self.user_state.get_seed() != self.audio_seed {
+            self.audio_seed = self.user_state.get_seed();
+            self.audio_transition = Some(CROSSFADE_TIME);
+        }
+        match self.audio_transition.take() {
+            Some(seconds) => self.audio_engine.transition_to(&frequencies, seconds),
+            None => self.audio_engine.update_frequencies(&frequencies),
+        }
+        self.audio_engine.update_ambience(self.audio_seed);
+        self.audio_engine.update_modulation(self.audio_seed);
+        if let Some(midi) = &mut self.midi_out {
+            midi.update(&self.audio_engine.get_current_frequencies());
+        }
+
+        // Each voice sounds from where its sample point appears on screen
+        let positions: Vec<_> = voices.iter().map(|&(_, index)| self.listener_position(&sample_points[index])).collect();
+        self.audio_engine.set_voice_positions(&positions);
+
+        // The complexity analysis is too slow for every frame, so the effects only follow
+        // settled fractals, once each
+        let params = fractal.get_params();
+        if self.audio_engine.follows_fractal() && self.morph.is_none() && self.fx_params != Some(params) {
+            self.fx_params = Some(params);
+            let _ = self.audio_engine.follow_fractal(&complexity::analyze(&params));
+        }
+
+        // The heartbeat likewise keeps its pattern through a morph and picks up the new
+        // fractal's once it settles
+        if self.morph.is_none() && self.rhythm_params != Some(params) {
+            self.rhythm_params = Some(params);
+            self.audio_engine.set_rhythm(Pattern::from_fractal(fractal));
+        }
+        let _ = self.audio_engine.schedule_rhythm();
+        let _ = self.audio_engine.schedule_arpeggio(chord);
+        self.audio_engine.update_levels();
+    }
+
+    // RMS loudness in dBFS the audio is normalized toward (-60 to -6, default -30); a
+    // limiter on the master bus catches whatever peaks still get through
+    pub fn set_target_loudness(&mut self, db: f32) {
+        self.audio_engine.set_target_loudness(db);
+    }
+
+    // Replaces the sounds of the gesture types in `sounds_json`, an object of gesture type
+    // ("swipe", "pinch", "tilt", "smile", or "default" for any other) to
+    // {"layers", "loudness_curve", "pitch_rise"}. Each layer is {"source" (sine, triangle,
+    // square, sawtooth or noise), "start_frequency", "end_frequency", "glide", "delay",
+    // "attack", "decay", "level", "resonance"}; missing fields take their defaults
+    pub fn set_gesture_sounds(&mut self, sounds_json: &str) -> Result<(), JsValue> {
+        self.audio_engine.set_gesture_sounds(sounds_json)
+    }
+
+    pub fn get_gesture_sounds(&self) -> String {
+        self.audio_engine.get_gesture_sounds()
+    }
+
+    // Whether this browser can vibrate at all; iOS Safari can't
+    pub fn is_haptics_supported(&self) -> bool {
+        self.haptics.is_supported()
+    }
+
+    // Sets and remembers one preference by name, taking effect at once: "volume" (0..1),
+    // "quality" (full, adaptive or saver), "palette" (as set_palette, "seed" by default),
+    // "haptics_enabled", "reduced_motion", "share_usage", "share_presence", "day_boundary"
+    // or "frozen_capacity"
+    pub fn set_preference(&mut self, key: &str, value: &str) -> Result<(), JsValue> {
+        self.user_state.set_preference(key, value)?;
+        self.apply_preference(key)
+    }
+
+    // What this device has seen of the user's habits, as JSON: the share of each gesture,
+    // the busiest hour, session lengths, and "messages" putting them in words ("You
+    // interact most at 7am"). Kept locally and never sent
+    pub fn get_insights(&self) -> String {
+        serde_json::to_string(&self.user_state.get_insights()).unwrap()
+    }
+
+    // Whether this session is the first after four hours or more away: the morning
+    // ritual, whatever the clock says
+    pub fn is_morning_session(&self) -> bool {
+        self.user_state.is_wake_session()
+    }
+
+    // When the user's waking day began, ms since the epoch: the start of the first
+    // session after four hours or more away
+    pub fn get_wake_time(&self) -> f64 {
+        self.user_state.get_wake_time()
+    }
+
+    // Every preference as JSON
+    pub fn get_preferences(&self) -> String {
+        serde_json::to_string(self.user_state.get_preferences()).unwrap()
+    }
+
+    // Hands a changed preference to what it controls; the rest are read where they're used
+    fn apply_preference(&mut self, key: &str) -> Result<(), JsValue> {
+        let preferences = self.user_state.get_preferences();
+        match key {
+            "volume" => self.audio_engine.set_master_volume(preferences.volume * DEFAULT_MASTER_VOLUME),
+            "quality" => self.quality.set_target_fps(preferences.quality.target_fps()),
+            "palette" => {
+                let palette = preferences.palette.clone();
+                self.set_palette(&palette)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // The user's preference, remembered across visits
+    pub fn set_haptics_enabled(&mut self, enabled: bool) -> Result<(), JsValue> {
+        self.user_state.set_haptics_enabled(enabled)
+    }
+
+    pub fn get_haptics_enabled(&self) -> bool {
+        self.user_state.get_haptics_enabled()
+    }
+
+    // How gesture intensity becomes vibration length: {"min_pulse", "max_pulse"} in ms
+    // and "curve", the exponent intensity is raised to; missing fields keep their defaults
+    pub fn set_haptic_mapping(&mut self, mapping_json: &str) -> Result<(), JsValue> {
+        self.haptics.set_mapping(mapping_json)
+    }
+
+    // Repeated gestures arpeggiate the current chord, faster the faster they come:
+    // {"enabled", "direction" (up, down, updown or random), "swing" (0..1; 1/3 for a
+    // triplet feel), "octaves", "notes_per_gesture", "level"}; missing fields take their
+    // defaults
+    pub fn set_arpeggiator(&mut self, settings_json: &str) -> Result<(), JsValue> {
+        self.audio_engine.set_arpeggiator(settings_json)
+    }
+
+    pub fn get_arpeggiator(&self) -> String {
+        self.audio_engine.get_arpeggiator()
+    }
+
+    // Silences all audio, and keeps it silent when the page is hidden and shown again,
+    // until resume_audio
+    pub fn suspend_audio(&self) {
+        self.audio_engine.suspend();
+    }
+
+    // Starts the audio, or resumes it after suspend_audio. Audio also starts by itself on
+    // the first touch, click or key press, which browsers require; call this from one of
+    // those handlers too
+    pub fn resume_audio(&mut self) {
+        self.audio_engine.resume();
+    }
+
+    // False until the first gesture, while suspended, and while the page is hidden
+    pub fn is_audio_running(&self) -> bool {
+        self.audio_engine.is_running()
+    }
+
+    // Turns the orbit-derived heartbeat (see get_rhythm) on or off
+    pub fn set_rhythm_enabled(&mut self, enabled: bool) {
+        self.audio_engine.set_rhythm_enabled(enabled);
+    }
+
+    // The current pattern as JSON: tempo plus 16 steps of trigger, accent and pitch
+    pub fn get_rhythm(&self) -> String {
+        serde_json::to_string(&self.audio_engine.get_rhythm()).unwrap()
+    }
+
+    // A fractal-space point as Web Audio's listener hears it: projected the way
+    // LINE_VERTEX_SHADER places geometry (transform, then zoom, camera 4 units back),
+    // with z flipped because the camera looks along +z and the listener along -z. Deep
+    // zooms fling the points far off screen, so distances are capped to keep them audible
+    fn listener_position(&self, point: &Vector4<f32>) -> nalgebra::Vector3<f32> {
+        let transformed = self.view_transform() * Vector4::new(point.x, point.y, point.z, 1.0);
+        let view = (transformed.xyz() - self.zoom.center.cast::<f32>()) * self.zoom.zoom as f32;
+        let position = nalgebra::Vector3::new(view.x, view.y, -(view.z + 4.0));
+        position.cap_magnitude(postfx::MAX_RAY_DISTANCE)
+    }
+
+    // How much spinning or zooming the fractal is heard, 0..1 (default 1; 0 turns it off):
+    // voices drawing nearer bend sharp and those drawing away flat, as by the Doppler
+    // effect, and the faster they move the more the filter opens
+    pub fn set_doppler_depth(&mut self, depth: f32) {
+        self.audio_engine.set_doppler_depth(depth);
+    }
+
+    // Whether the voices follow the day's chord progression (see get_harmony) or play the
+    // fractal's raw pentatonic notes
+    pub fn set_harmony_enabled(&mut self, enabled: bool) {
+        self.harmony_enabled = enabled;
+    }
+
+    // The day's key and progression as JSON, with the index of the chord now sounding
+    pub fn get_harmony(&self) -> String {
+        let progression = Progression::from_seed(self.user_state.get_seed());
+        serde_json::to_string(&progression.info(self.user_state.get_interaction_count())).unwrap()
+    }
+
+    // Replaces the day's modulation patch with a JSON array of routes, each {"source"
+    // ("lfo", "pulse" following the rhythm's hits, or "voices" following their loudness),
+    // "destination" ("pitch" in semitones, "cutoff" in octaves, or "gain"), "depth", and
+    // for LFOs "shape" (sine, triangle, square or sawtooth) and "beats" per cycle at the
+    // rhythm's tempo}; null restores the day's patch
+    pub fn set_modulation(&mut self, routes_json: &str) -> Result<(), JsValue> {
+        self.audio_engine.set_modulation(routes_json)
+    }
+
+    // The routes playing, as set_modulation takes them
+    pub fn get_modulation(&self) -> String {
+        self.audio_engine.get_modulation(self.audio_seed)
+    }
+
+    // Plays the view as sound, for listeners who can't see it: a sweep from the top row
+    // to the bottom, each tone panned to its place across the screen and higher the
+    // nearer the fractal is there, with the voices ducked beneath. Call from a gesture
+    // handler, as it may start the audio
+    pub fn sonify_view(&mut self) -> Result<(), JsValue> {
+        let params = self.current_params();
+        let fractal = self.weather_modifier(&params).wrap(
+            self.user_state.get_current_fractal(self.time, self.coloring_mode, &self.palette, &params)
+        );
+        let scan = ViewScan::read(&*fractal, &self.zoom, &self.view_transform(),
+            self.canvas.width() as i32, self.canvas.height() as i32);
+        self.audio_engine.sonify(&scan, SONIFY_SECONDS)
+    }
+
+    // Loudness of the noise bed and textures under the voices, 0..1 (default 0.5); 0
+    // turns them off
+    pub fn set_ambience_level(&mut self, level: f32) {
+        self.audio_engine.set_ambience_level(level);
+    }
+
+    // The day's soundscape as JSON: its noise layers, each a "bed" or "texture" with its
+    // filter's cutoff, resonance and sweep, and its level
+    pub fn get_ambience(&self) -> String {
+        serde_json::to_string(&AmbientScene::from_seed(self.user_state.get_seed())).unwrap()
+    }
+
+    // Most voices sounding at once (1-32, default 8); the quietest give way, fading out
+    // as louder ones take their oscillators
+    pub fn set_max_voices(&mut self, count: u32) {
+        self.audio_engine.set_max_voices(count as usize);
+    }
+
+    // Seconds the fractal's voices take to glide to new frequencies
+    pub fn set_audio_glide(&mut self, seconds: f32) {
+        self.audio_engine.set_glide_time(seconds);
+    }
+
+    // "reverb" {"size","decay","wet"}, "delay" {"time","feedback","wet"}, "filter"
+    // {"cutoff","resonance"} or "mix" {"dry","follow_fractal"}; missing fields take their
+    // defaults. While following the fractal, its complexity sets the reverb size and cutoff
+    pub fn set_audio_effect(&mut self, name: &str, params_json: &str) -> Result<(), JsValue> {
+        self.audio_engine.set_effect(name, params_json)?;
+        // Refit on the next frame, over the new settings
+        self.fx_params = None;
+        Ok(())
+    }
+
+    pub fn get_audio_effects(&self) -> String {
+        self.audio_engine.get_effects()
+    }
+
+    // Pulses the fractal to the microphone: the browser asks for permission, and nothing
+    // changes until it is granted. `perturb_transform` also lets bass swell and mids sway
+    // the view, on top of the shading
+    pub fn enable_audio_input(&mut self, perturb_transform: bool) -> Result<(), JsValue> {
+        match &mut self.audio_input {
+            Some(input) => input.perturb_transform = perturb_transform,
+            None => self.audio_input = Some(AudioInput::open(&self.audio_engine.context()?, perturb_transform)?),
+        }
+        Ok(())
+    }
+
+    // Releases the microphone
+    pub fn disable_audio_input(&mut self) {
+        self.audio_input = None;
+    }
+
+    // Current band energies (0..1, lowest band first); all zero without a microphone
+    pub fn get_audio_bands(&self) -> Vec<f32> {
+        self.audio_input.as_ref().map(|input| input.bands().to_vec()).unwrap_or_else(|| vec![0.0; AUDIO_BANDS])
+    }
+
+    // Asks for Web MIDI access so the fractal's voices can drive an external synth; pick
+    // the output with set_midi_output once get_midi_outputs lists it
+    pub fn enable_midi_output(&mut self) -> Result<(), JsValue> {
+        if self.midi_out.is_none() {
+            self.midi_out = Some(MidiOut::open()?);
+        }
+        Ok(())
+    }
+
+    // Releases any sounding notes and closes MIDI
+    pub fn disable_midi_output(&mut self) {
+        self.midi_out = None;
+    }
+
+    // Connected outputs as JSON [{"id","name","manufacturer"}]; empty until access is granted
+    pub fn get_midi_outputs(&self) -> String {
+        let outputs = self.midi_out.as_ref().map(|midi| midi.outputs()).unwrap_or_default();
+        serde_json::to_string(&outputs).unwrap()
+    }
+
+    // Sends each voice to the output with this id (one channel per voice from channel 2,
+    // nearest note plus pitch bend), or to none for an empty id
+    pub fn set_midi_output(&mut self, output_id: &str) -> Result<(), JsValue> {
+        let midi = self.midi_out.as_mut().ok_or("Call enable_midi_output first")?;
+        midi.select(output_id);
+        Ok(())
+    }
+
+    pub fn apply_gesture(&mut self, gesture_type: &str, intensity: f32, direction: f32) -> Result<(), JsValue> {
+        self.replay = None;
+
+        // Make gesture effects MUCH more dramatic and immediate, unless the user has asked
+        // for reduced motion
+        let dramatic_intensity = if self.user_state.get_preferences().reduced_motion {
+            intensity * REDUCED_MOTION_GAIN
+        } else {
+            intensity * 2.0 // Double the effect
+        };
+
+        // Convert gesture to mathematical transform with enhanced effects
+        let transform = match gesture_type {
+            "swipe" => {
+                // Create massive rotation with multiple axes
+                let primary = self.create_rotation_transform(direction, dramatic_intensity);
+                let secondary = self.create_rotation_transform(direction + std::f32::consts::PI/3.0, dramatic_intensity * 0.7);
+                primary * secondary
+            },
+            "pinch" => {
+                // Dramatic scaling with non-uniform effects
+                let scale_factor = 1.0 + dramatic_intensity * 3.0;
+                self.create_complex_scale_transform(scale_factor, direction)
+            },
+            "tilt" => {
+                // Complex 4D transformation
+                let tilt = self.create_tilt_transform(direction, dramatic_intensity);
+                let twist = self.create_4d_twist_transform(direction, dramatic_intensity * 0.5);
+                tilt * twist
+            },
+            "smile" => {
+                // Brightness + color shift + fractal parameter change
+                let brightness = self.create_brightness_transform(dramatic_intensity);
+                let color_shift = self.create_color_shift_transform(dramatic_intensity);
+                let params = self.create_parameter_shift_transform(dramatic_intensity);
+                brightness * color_shift * params
+            },
+            _ => return Ok(()),
+        };
+
+        // Apply transform with animation interpolation
+        self.user_state.apply_transform_animated(transform, dramatic_intensity)?;
+        self.user_state.record_usage(gesture_type);
+        self.user_state.record_gesture(gesture_type, intensity, direction);
+
+        // Trigger audio feedback, and a vibration to go with it
+        self.audio_engine.play_gesture_feedback(gesture_type, intensity)?;
+        if self.user_state.get_haptics_enabled() {
+            self.haptics.gesture(gesture_type, intensity);
+        }
+        self.announce_achievements();
+
+        Ok(())
+    }
+
+    // Takes back the last gesture (or redo or reset); false if there is nothing to undo.
+    // The day's history survives reloads, up to its last 50 steps
+    pub fn undo(&mut self) -> bool {
+        self.replay = None;
+        self.user_state.undo()
+    }
+
+    // Reapplies what undo took back; false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        self.replay = None;
+        self.user_state.redo()
+    }
+
+    // Returns to the day's fractal as it was before any gesture; undo brings it back
+    pub fn reset_transform(&mut self) {
+        self.replay = None;
+        self.user_state.reset_transform();
+    }
+
+    // Plays the day back from the untouched fractal, each gesture (and undo, redo or
+    // reset) in turn, `speed` times as fast as they came and with no pause over two
+    // seconds; false if there is nothing yet to replay. Any gesture stops it
+    pub fn replay_today(&mut self, speed: f32) -> bool {
+        let steps = self.user_state.get_today_gestures().to_vec();
+        if steps.is_empty() || self.user_state.in_sandbox() {
+            return false;
+        }
+        self.replay = Some(Replay::new(steps, speed as f64));
+        true
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    fn advance_replay(&mut self, seconds: f64) {
+        let Some(replay) = self.replay.as_mut() else { return };
+        replay.advance(seconds);
+        if replay.is_finished() {
+            self.replay = None;
+        }
+    }
+
+    // The day's transform, or where a replay has got to
+    fn view_transform(&self) -> nalgebra::Matrix4<f32> {
+        match &self.replay {
+            Some(replay) => replay.transform(),
+            None => self.user_state.get_current_transform(),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.user_state.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.user_state.can_redo()
+    }
+
+    fn create_rotation_transform(&self, direction: f32, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::{Matrix4, Vector3};
+
+        // Much more dramatic rotation with time-based evolution
+        let base_angle = direction * intensity * 0.5; // 5x stronger
+        let time_modifier = (self.time * 0.1).sin() * 0.2;
+        let angle = base_angle + time_modifier;
+
+        // Rotate around multiple axes for complex motion
+        let rotation_x = Matrix4::from_axis_angle(&Vector3::x_axis(), angle * 0.3);
+        let rotation_y = Matrix4::from_axis_angle(&Vector3::y_axis(), angle * 0.7);
+        let rotation_z = Matrix4::from_axis_angle(&Vector3::z_axis(), angle);
+
+        rotation_z * rotation_y * rotation_x
+    }
+
+    fn create_scale_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::Matrix4;
+        let scale = 1.0 + intensity * 1.5; // Much more dramatic scaling
+        Matrix4::new_scaling(scale)
+    }
+
+    fn create_complex_scale_transform(&self, scale_factor: f32, direction: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::Matrix4;
+
+        // Non-uniform scaling based on direction
+        let scale_x = scale_factor * (1.0 + direction.cos() * 0.5);
+        let scale_y = scale_factor * (1.0 + direction.sin() * 0.5);
+        let scale_z = scale_factor * (1.0 + (direction * 2.0).cos() * 0.3);
+
+        Matrix4::new_nonuniform_scaling(&nalgebra::Vector3::new(scale_x, scale_y, scale_z))
+    }
+
+    fn create_tilt_transform(&self, direction: f32, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::{Matrix4, Vector3};
+
+        // More dramatic tilting with perspective distortion
+        let angle_x = direction.cos() * intensity * 0.3;
+        let angle_y = direction.sin() * intensity * 0.3;
+        let angle_z = (direction * 0.5).sin() * intensity * 0.2;
+
+        let tilt_x = Matrix4::from_axis_angle(&Vector3::x_axis(), angle_x);
+        let tilt_y = Matrix4::from_axis_angle(&Vector3::y_axis(), angle_y);
+        let tilt_z = Matrix4::from_axis_angle(&Vector3::z_axis(), angle_z);
+
+        tilt_z * tilt_y * tilt_x
+    }
+
+    fn create_4d_twist_transform(&self, direction: f32, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::Matrix4;
+
+        // Complex 4D rotation that creates mesmerizing effects
+        let twist_factor = intensity * 0.4;
+        let time_twist = (self.time * 0.05 + direction).sin() * twist_factor;
+
+        // Create a complex transformation matrix
+        let mut transform = Matrix4::identity();
+
+        // Apply time-based twisted rotation
+        let c = time_twist.cos();
+        let s = time_twist.sin();
+
+        transform[(0, 0)] = c;
+        transform[(0, 1)] = -s;
+        transform[(1, 0)] = s;
+        transform[(1, 1)] = c;
+
+        // Add some 4D perspective effects
+        transform[(2, 3)] = intensity * 0.1;
+
+        transform
+    }
+
+    fn create_color_shift_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::Matrix4;
+
+        // Color transformation matrix that shifts hues dramatically
+        let hue_shift = intensity * std::f32::consts::PI;
+        let cos_h = hue_shift.cos();
+        let sin_h = hue_shift.sin();
+
+        let mut color_matrix = Matrix4::identity();
+
+        // RGB rotation matrix for hue shifting
+        color_matrix[(0, 0)] = cos_h + (1.0 - cos_h) * 0.299;
+        color_matrix[(0, 1)] = (1.0 - cos_h) * 0.587 - sin_h * 0.114;
+        color_matrix[(0, 2)] = (1.0 - cos_h) * 0.114 + sin_h * 0.587;
+
+        color_matrix[(1, 0)] = (1.0 - cos_h) * 0.299 + sin_h * 0.701;
+        color_matrix[(1, 1)] = cos_h + (1.0 - cos_h) * 0.587;
+        color_matrix[(1, 2)] = (1.0 - cos_h) * 0.114 - sin_h * 0.299;
+
+        color_matrix[(2, 0)] = (1.0 - cos_h) * 0.299 - sin_h * 0.587;
+        color_matrix[(2, 1)] = (1.0 - cos_h) * 0.587 + sin_h * 0.299;
+        color_matrix[(2, 2)] = cos_h + (1.0 - cos_h) * 0.114;
+
+        color_matrix
+    }
+
+    fn create_parameter_shift_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::Matrix4;
+
+        // Transform that affects fractal parameters dynamically
+        let param_shift = intensity * 0.2;
+        let mut param_matrix = Matrix4::identity();
+
+        // Encode parameter changes in transformation matrix
+        param_matrix[(3, 0)] = param_shift * (self.time * 0.1).sin();
+        param_matrix[(3, 1)] = param_shift * (self.time * 0.13).cos();
+        param_matrix[(3, 2)] = param_shift * (self.time * 0.07).sin();
+
+        param_matrix
+    }
+
+    fn create_brightness_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
+        use nalgebra::Matrix4;
+        // Brightness affects the 4th dimension
+        Matrix4::new_translation(&nalgebra::Vector3::new(0.0, 0.0, intensity * 0.1))
+    }
+
+    pub fn set_focus_distance(&mut self, distance: f32) {
+        self.dof.focus_distance = distance.max(0.1).min(postfx::MAX_RAY_DISTANCE);
+    }
+
+    // 0.0 disables depth of field entirely, larger values give a shallower focus
+    pub fn set_aperture(&mut self, aperture: f32) {
+        self.dof.aperture = aperture.max(0.0).min(1.0);
+    }
+
+    // "standard", "point", "line", "plane" or "equalized"
+    pub fn set_coloring_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        self.coloring_mode = ColoringMode::from_name(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown coloring mode: {}", mode)))?;
+        Ok(())
+    }
+
+    // JSON such as {"kind":"gradient","horizon":[..],"zenith":[..]},
+    // {"kind":"starfield","base":[..],"density":0.02} or {"kind":"custom","stops":[..]}
+    pub fn set_environment(&mut self, environment_json: &str) -> Result<(), JsValue> {
+        self.environment = Environment::from_json(environment_json)?;
+        Ok(())
+    }
+
+    // Built-in name ("spectrum", "fire", "ocean", "aurora", "mono", "seed") or palette JSON
+    pub fn set_palette(&mut self, name_or_json: &str) -> Result<(), JsValue> {
+        self.palette = match name_or_json {
+            "seed" => Palette::from_seed(self.user_state.get_seed()),
+            _ => Palette::parse(name_or_json)?,
+        };
+        Ok(())
+    }
+
+    // Renders a frozen fractal offscreen and returns size x size raw RGBA bytes (top row first),
+    // leaving the live view untouched
+    pub fn render_thumbnail(&mut self, frozen_json: &str, size: u32) -> Result<Vec<u8>, JsValue> {
+        let frozen: FrozenFractal = serde_json::from_str(frozen_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        let size = size.max(1).min(MAX_THUMBNAIL_SIZE) as i32;
+        let fractal = with_detail(Self::frozen_generator(&frozen), THUMBNAIL_DETAIL);
+
+        self.prepare_equalizer(&*fractal)?;
+        self.thumbnail_target.bind(&self.gl, size, size)?;
+
+        let view = Self::frozen_view(&frozen, size, size, [0.0, 0.0, size as f32, size as f32]);
+        self.draw_still(&*fractal, &view);
+
+        let gl = &self.gl;
+        let pixels = self.thumbnail_target.read_rgba(gl);
+
+        // Hand the canvas back to the live render loop
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        gl.viewport(0, 0, gl.drawing_buffer_width(), gl.drawing_buffer_height());
+
+        pixels
+    }
+
+    // Wallpaper export: width x height raw RGBA bytes (top row first) of a frozen fractal at
+    // full detail, each pixel averaging `samples` jittered rays. The image is drawn tile by
+    // tile, each sample its own draw and readback, so the GPU never sees one long draw;
+    // the live view is left untouched
+    pub fn render_frozen_hq(&mut self, frozen_json: &str, width: u32, height: u32, samples: u32) -> Result<Vec<u8>, JsValue> {
+        let frozen: FrozenFractal = serde_json::from_str(frozen_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        let width = width.max(1).min(MAX_EXPORT_SIZE) as i32;
+        let height = height.max(1).min(MAX_EXPORT_SIZE) as i32;
+        let samples = samples.max(1).min(MAX_EXPORT_SAMPLES);
+        let fractal = Self::frozen_generator(&frozen);
+        self.prepare_equalizer(&*fractal)?;
+
+        let mut image = vec![0u8; width as usize * height as usize * 4];
+        let mut sums: Vec<u32> = Vec::new();
+
+        for top in (0..height).step_by(EXPORT_TILE_SIZE as usize) {
+            for left in (0..width).step_by(EXPORT_TILE_SIZE as usize) {
+                let tile_width = EXPORT_TILE_SIZE.min(width - left);
+                let tile_height = EXPORT_TILE_SIZE.min(height - top);
+                self.thumbnail_target.bind(&self.gl, tile_width, tile_height)?;
+
+                sums.clear();
+                sums.resize(tile_width as usize * tile_height as usize * 4, 0);
+
+                for sample in 0..samples {
+                    // gl_FragCoord counts rows from the bottom
+                    let (jitter_x, jitter_y) = sample_jitter(sample);
+                    let tile = [
+                        left as f32 + jitter_x,
+                        (height - top - tile_height) as f32 + jitter_y,
+                        tile_width as f32,
+                        tile_height as f32,
+                    ];
+                    let view = Self::frozen_view(&frozen, width, height, tile);
+                    self.draw_still(&*fractal, &view);
+
+                    let pixels = self.thumbnail_target.read_rgba(&self.gl)?;
+                    for (sum, &value) in sums.iter_mut().zip(&pixels) {
+                        *sum += value as u32;
+                    }
+                }
+
+                let row_len = tile_width as usize * 4;
+                for (row, chunk) in sums.chunks(row_len).enumerate() {
+                    let start = ((top as usize + row) * width as usize + left as usize) * 4;
+                    for (out, &sum) in image[start..start + row_len].iter_mut().zip(chunk) {
+                        *out = ((sum + samples / 2) / samples) as u8;
+                    }
+                }
+            }
+        }
+
+        // Hand the canvas back to the live render loop
+        let gl = &self.gl;
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        gl.viewport(0, 0, gl.drawing_buffer_width(), gl.drawing_buffer_height());
+
+        Ok(image)
+    }
+
+    fn frozen_generator(frozen: &FrozenFractal) -> Box<dyn FractalGenerator> {
+        let palette = frozen.palette.clone().unwrap_or_else(|| Palette::from_seed(frozen.seed));
+        create_fractal_from_params(&frozen.fractal_params(), 0.0, frozen.coloring_mode, palette)
+    }
+
+    // Frozen fractals are a still frame, so time is pinned to the start of the animation
+    fn frozen_view(frozen: &FrozenFractal, width: i32, height: i32, tile: [f32; 4]) -> SceneView {
+        let transform = if frozen.transform_matrix.len() == 16 {
+            nalgebra::Matrix4::from_row_slice(&frozen.transform_matrix)
+        } else {
+            nalgebra::Matrix4::identity()
+        };
+
+        SceneView {
+            seed: frozen.seed,
+            time: 0.0,
+            transform,
+            width,
+            height,
+            tile,
+            audio_bands: [0.0; AUDIO_BANDS],
+            audio_state: [0.0; AUDIO_STATE_VOICES * 2],
+            pulse: 0.0,
+            golden: 0.0,
+            resonance: 0.0,
+            write_depth: false,
+            zoom: ZoomState::default(),
+        }
+    }
+
+    // Draws into whatever framebuffer is bound
+    fn draw_still(&mut self, fractal: &dyn FractalGenerator, view: &SceneView) {
+        let params = fractal.get_params();
+        let gl = &self.gl;
+        gl.clear_color(0.0, 0.0, 0.02, 1.0);
+        gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        gl.use_program(Some(self.scene_program(&params)));
+
+        self.setup_uniforms(fractal, view);
+        // Frozen fractals keep the shape they were frozen with, whatever today's weather
+        Modifier::default().apply_uniforms(&self.gl, self.scene_program(&params));
+        self.draw_scene(&params, fractal.get_palette());
+    }
+
+    // Cut away everything on the `normal` side of the plane dot(p, normal) = offset
+    pub fn set_slice(&mut self, normal: Vec<f32>, offset: f32, enabled: bool) -> Result<(), JsValue> {
+        self.slice.set(&normal, offset, enabled)
+    }
+
+    // Sweep the slice offset by +/- amplitude at `speed` radians per second; 0 stops it
+    pub fn animate_slice(&mut self, speed: f32, amplitude: f32) {
+        self.slice.set_sweep(speed, amplitude);
+    }
+
+    pub fn get_stats(&self) -> String {
+        self.stats.to_json()
+    }
+
+    pub fn set_stats_overlay(&mut self, enabled: bool) -> Result<(), JsValue> {
+        self.stats_overlay = if enabled {
+            Some(StatsOverlay::new(&self.canvas)?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    // Partial JSON is fine, e.g. {"foveation":0.6}
+    pub fn set_render_settings(&mut self, settings_json: &str) -> Result<(), JsValue> {
+        self.render_settings = RenderSettings::from_json(settings_json)?;
+        Ok(())
+    }
+
+    pub fn get_render_settings(&self) -> String {
+        self.render_settings.to_json()
+    }
+
+    // Zoom by `factor` around a canvas pixel (origin top-left)
+    pub fn zoom_at(&mut self, pixel_x: f32, pixel_y: f32, factor: f32) {
+        let (uv_x, uv_y) = self.pixel_to_uv(pixel_x, pixel_y);
+        self.zoom.zoom_at(uv_x, uv_y, factor as f64);
+    }
+
+    pub fn pan(&mut self, delta_x: f32, delta_y: f32) {
+        let min_side = self.gl.drawing_buffer_width().min(self.gl.drawing_buffer_height()).max(1) as f64;
+        self.zoom.pan(delta_x as f64 / min_side, -delta_y as f64 / min_side);
+    }
+
+    pub fn reset_zoom(&mut self) {
+        self.zoom = ZoomState::default();
+    }
+
+    pub fn get_zoom(&self) -> f64 {
+        self.zoom.zoom
+    }
+
+    fn pixel_to_uv(&self, pixel_x: f32, pixel_y: f32) -> (f64, f64) {
+        let width = self.gl.drawing_buffer_width() as f64;
+        let height = self.gl.drawing_buffer_height() as f64;
+        let min_side = width.min(height).max(1.0);
+
+        // gl_FragCoord has its origin bottom-left
+        ((pixel_x as f64 - 0.5 * width) / min_side, (0.5 * height - pixel_y as f64) / min_side)
+    }
+
+    // "off" returns to the daily fractal, "seed" derives a hybrid from today's seed,
+    // anything else is parsed as HybridSpec JSON
+    pub fn set_hybrid(&mut self, spec: &str) -> Result<(), JsValue> {
+        self.preset = None;
+        self.morph = None;
+        self.fractal_params = match spec {
+            "off" => None,
+            "seed" => Some(FractalParams::Hybrid(HybridSpec::from_seed(self.user_state.get_seed()))),
+            json => {
+                let params = FractalParams::Hybrid(serde_json::from_str(json)
+                    .map_err(|e| JsValue::from_str(&format!("Invalid hybrid spec: {}", e)))?);
+                params.validate().map_err(|e| JsValue::from_str(&format!("Invalid hybrid spec: {}", e)))?;
+                Some(params)
+            }
+        };
+        self.audio_transition = Some(CROSSFADE_TIME);
+        Ok(())
+    }
+
+    // FractalParams JSON (e.g. from a frozen fractal), a share link's params as
+    // get_share_url encodes them, or "seed" for the daily fractal
+    pub fn set_fractal_params(&mut self, params: &str) -> Result<(), JsValue> {
+        self.preset = None;
+        self.morph = None;
+        self.fractal_params = match params {
+            "seed" => None,
+            json if json.starts_with('{') => Some(FractalParams::from_json(json)?),
+            encoded => {
+                let json = codec::decode(encoded).map_err(|_| JsValue::from_str("Invalid fractal params"))?;
+                Some(FractalParams::from_json(&json)?)
+            }
+        };
+        self.audio_transition = Some(CROSSFADE_TIME);
+        Ok(())
+    }
+
+    // "seed" (default) derives turbulence, erosion and bloom from the seed and date, "off"
+    // disables them, and WeatherConditions JSON such as {"wind_speed":8,"humidity":0.7}
+    // drives them from real conditions
+    pub fn set_weather(&mut self, source: &str) -> Result<(), JsValue> {
+        self.weather = WeatherSource::parse(source)?;
+        Ok(())
+    }
+
+    // Lowers the fractal's iteration budget while frames run slower than `target_fps`;
+    // 0 turns it off. Frozen and shared fractals always keep their full settings.
+    pub fn set_adaptive_quality(&mut self, target_fps: f32) {
+        self.quality.set_target_fps(target_fps);
+    }
+
+    // Shows a curated fractal (see get_preset_names) in the palette it was picked for
+    pub fn load_preset(&mut self, name: &str) -> Result<(), JsValue> {
+        let preset = presets::by_name(name)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown preset: {}", name)))?;
+
+        self.fractal_params = Some(preset.params);
+        self.palette = Palette::parse(preset.palette)?;
+        self.preset = Some(preset.name);
+        self.morph = None;
+        self.audio_transition = Some(CROSSFADE_TIME);
+        Ok(())
+    }
+
+    // Smoothly turns the live view into a FrozenFractal (e.g. a friend's) over `duration`
+    // seconds; once there, it stays as if set with set_fractal_params
+    pub fn morph_to(&mut self, frozen_json: &str, duration: f32) -> Result<(), JsValue> {
+        let frozen: FrozenFractal = serde_json::from_str(frozen_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        self.morph = Some(Morph::new(self.current_params(), frozen.fractal_params(), duration));
+        self.preset = None;
+        // The sound blends over the whole morph, but never faster than a normal crossfade
+        self.audio_transition = Some(duration.max(CROSSFADE_TIME));
+        Ok(())
+    }
+
+    fn advance_morph(&mut self, seconds: f32) {
+        let Some(morph) = self.morph.as_mut() else { return };
+        morph.advance(seconds);
+        if morph.is_finished() {
+            self.fractal_params = Some(morph.to);
+            self.morph = None;
+        }
+    }
+
+    // Meshes the current fractal for 3D printing: "obj" returns OBJ text as UTF-8 bytes,
+    // "stl" binary STL. `resolution` is the voxel samples per axis.
+    pub fn export_mesh(&self, format: &str, resolution: u32) -> Result<Vec<u8>, JsValue> {
+        let params = self.current_params();
+        if params.is_planar() {
+            return Err(JsValue::from_str("Plane fractals have no volume to export"));
+        }
+
+        let resolution = resolution.max(MIN_MESH_RESOLUTION).min(MAX_MESH_RESOLUTION);
+        let mesh = mesh::Mesh::from_voxels(&voxelize(&params, resolution as usize));
+        match format {
+            "obj" => Ok(mesh.to_obj(params.name()).into_bytes()),
+            "stl" => Ok(mesh.to_stl()),
+            _ => Err(JsValue::from_str(&format!("Unknown mesh format: {}", format))),
+        }
+    }
+
+    // JSON array of preset names
+    pub fn get_preset_names(&self) -> String {
+        serde_json::to_string(&presets::names()).unwrap()
+    }
+
+    pub fn get_fractal_params(&self) -> String {
+        serde_json::to_string(&self.current_params()).unwrap()
+    }
+
+    // 2D mode keeps explicit plane params but otherwise swaps in the seed's plane fractal
+    // Explicit params are shown as given; seed-derived ones drift with the time of day
+    fn current_params(&self) -> FractalParams {
+        if let Some(morph) = &self.morph {
+            return morph.params();
+        }
+        let seed = self.user_state.get_seed();
+        let curve = EvolutionCurve::from_seed(seed);
+        match (self.render_settings.mode, self.fractal_params) {
+            (RenderMode::Plane, Some(params)) if params.is_planar() => params,
+            (RenderMode::Plane, _) => curve.apply(&FractalParams::plane_from_seed(seed), evolution::local_hour()),
+            (RenderMode::Raymarched, Some(params)) => params,
+            (RenderMode::Raymarched, None) => curve.apply(&FractalParams::from_seed(seed), evolution::local_hour()),
+        }
+    }
+
+    pub fn get_share_url(&self) -> String {
+        let mut url = format!("{}?seed={}&user={}&key={}&time={}",
+            network::SHARE_DOMAIN,
+            self.user_state.get_seed(),
+            self.user_state.get_user_id(),
+            self.user_state.get_identity().public_key(),
+            self.user_state.get_wake_time() as u64
+        );
+
+        // The seed alone reproduces the daily fractal and a preset name its curated
+        // settings; other overrides and the sender's time-of-day evolution travel explicitly
+        if let Some(name) = self.preset {
+            url.push_str(&format!("&preset={}", name));
+            return url;
+        }
+
+        let seed = self.user_state.get_seed();
+        let explicit = match (self.fractal_params, self.render_settings.mode) {
+            (Some(params), _) => Some(params),
+            (None, RenderMode::Raymarched) => Some(self.current_params()).filter(|params| *params != FractalParams::from_seed(seed)),
+            (None, RenderMode::Plane) => None,
+        };
+        if let Some(params) = explicit {
+            let json = serde_json::to_string(&params).unwrap();
+            url.push_str(&format!("&params={}", codec::encode(&json)));
+        }
+        url
+    }
+
+    // Opens a share link, whole or just its query ("?f=..."): create_share_url's `f`, a
+    // frozen fractal, in the sandbox as it was shared; a temporary share `token`'s seed in
+    // the sandbox; or get_share_url's `seed` in the sandbox, with its `preset` or `params`.
+    // Shared params morph in from the current view. Everything is checked before anything
+    // changes; the page's own link is opened this way as the crate starts. Returns {"kind"
+    // ("fractal", "token" or "seed"), "seed", "from", "key"}, the last two naming the
+    // sharer for add_friend_from_share_url where the link does. Until the saved state has
+    // loaded the link waits, and opens on the first frame after
+    pub fn load_from_url(&mut self, search_params: &str) -> Result<String, JsValue> {
+        let link = self.network.parse_share_link(search_params)?
+            .ok_or_else(|| JsValue::from_str("The link carries no fractal"))?;
+        let (kind, seed) = match &link.content {
+            LinkContent::Fractal(fractal) => ("fractal", fractal.seed),
+            LinkContent::Token(seed) => ("token", *seed),
+            LinkContent::Seed { seed, .. } => ("seed", *seed),
+        };
+        let summary = serde_json::json!({ "kind": kind, "seed": seed, "from": link.from, "key": link.key }).to_string();
+        if self.user_state.is_store_ready() {
+            self.open_link(link)?;
+        } else {
+            self.pending_link = Some(link);
+        }
+        Ok(summary)
+    }
+
+    pub fn get_fractal_info(&self) -> String {
+        let params = self.current_params();
+        let modifier = self.weather_modifier(&params);
+        let fractal = modifier.wrap(create_fractal_from_params(&params, self.time, self.coloring_mode, self.palette.clone()));
+        let geometry = complexity::analyze(&params);
+        let evolution = EvolutionCurve::from_seed(self.user_state.get_seed()).state(evolution::local_hour());
+
+        serde_json::json!({
+            "type": self.fractal_type,
+            "fingerprint": orbit_fingerprint(&*fractal),
+            "seed": self.user_state.get_seed(),
+            "seed_quality": self.user_state.get_seed_quality(),
+            "complexity": self.user_state.complexity_score(&geometry),
+            "geometry": geometry,
+            "evolution": evolution,
+            "weather": modifier,
+            "interactions_today": self.user_state.get_interaction_count(),
+            "audio_frequencies": self.audio_engine.get_current_frequencies()
+        }).to_string()
+    }
+
+    // Whether the saved state has finished loading from IndexedDB; until then the frozen
+    // fractals and today's transform may still be on their way
+    pub fn is_storage_ready(&self) -> bool {
+        self.user_state.is_store_ready()
+    }
+
+    // The user's identity and collection as one versioned JSON blob: user id, frozen
+    // fractals, every day's transform, gesture count and note (unless the export_notes
+    // preference is off), achievement progress and preferences, for import_state on
+    // another device
+    pub fn export_state(&self) -> String {
+        self.user_state.export_state()
+    }
+
+    // Becomes the user in an export_state blob, days and seeds included; frozen fractals
+    // already here are kept alongside theirs
+    pub fn import_state(&mut self, state_json: &str) -> Result<(), JsValue> {
+        let seed_palette = self.palette == Palette::from_seed(self.user_state.get_seed());
+        self.user_state.import_state(state_json)?;
+        // Colors that came from the old seed follow the new one
+        if seed_palette {
+            self.palette = Palette::from_seed(self.user_state.get_seed());
+        }
+        Ok(())
+    }
+
+    // The recorded days from `from` to `to` (YYYY-MM-DD, both included; "" for no limit)
+    // as JSON, oldest first: each day's seed, complexity, gesture count, thumbnail hash
+    // and note
+    pub fn get_history(&self, from: &str, to: &str) -> String {
+        serde_json::to_string(&self.user_state.get_history(from, to)).unwrap()
+    }
+
+    // A mood or a line of journal for today's fractal, up to 280 characters, emoji
+    // welcome; "" removes it. Kept in the day's history record
+    pub fn set_day_note(&mut self, text: &str) -> Result<(), JsValue> {
+        self.user_state.set_day_note(text)
+    }
+
+    pub fn get_day_note(&self) -> Option<String> {
+        self.user_state.get_day_note()
+    }
+
+    // Shows the fractal of a past day (YYYY-MM-DD) as it was left, in the sandbox so
+    // gestures leave today's alone, and returns it as FrozenFractal JSON for
+    // render_thumbnail or render_frozen_hq; exit_sandbox returns to today's
+    pub fn load_past_day(&mut self, date: &str) -> Result<String, JsValue> {
+        let day = self.user_state.past_day(date)
+            .ok_or_else(|| JsValue::from_str(&format!("No record of {}", date)))?;
+
+        self.open_in_sandbox(&day)?;
+        Ok(serde_json::to_string(&day).unwrap())
+    }
+
+    // Replaces the user id (which share URLs carry) with a new random one, and with it
+    // today's seed and every one after; frozen fractals and achievements are kept
+    pub fn regenerate_identity(&mut self) -> Result<(), JsValue> {
+        let seed_palette = self.palette == Palette::from_seed(self.user_state.get_seed());
+        self.user_state.regenerate_identity()?;
+        if seed_palette {
+            self.palette = Palette::from_seed(self.user_state.get_seed());
+        }
+        self.audio_transition = Some(CROSSFADE_TIME);
+        Ok(())
+    }
+
+    pub fn freeze_fractal(&mut self) -> Result<String, JsValue> {
+        let frozen = self.user_state.freeze_current_fractal(
+            self.coloring_mode, self.palette.clone(), self.current_params()
+        )?;
+        if self.user_state.get_haptics_enabled() {
+            self.haptics.tick();
+        }
+        self.announce_achievements();
+        Ok(serde_json::to_string(&frozen).unwrap())
+    }
+
+    // The frozen fractals as a JSON array, the most complex first. `filter_json` narrows
+    // them by {"collection", "tags" (all of them), "favorite"}; "" or "{}" lists them all
+    pub fn list_frozen(&self, filter_json: &str) -> Result<String, JsValue> {
+        let filter: FrozenFilter = match filter_json {
+            "" => FrozenFilter::default(),
+            json => serde_json::from_str(json)
+                .map_err(|e| JsValue::from_str(&format!("Invalid frozen filter: {}", e)))?,
+        };
+        Ok(serde_json::to_string(&self.user_state.list_frozen(&filter)).unwrap())
+    }
+
+    // The names of the collections in use, as a JSON array
+    pub fn get_collections(&self) -> String {
+        serde_json::to_string(&self.user_state.get_collections()).unwrap()
+    }
+
+    // Files the frozen fractal with this seed and timestamp: `changes_json` is any of
+    // {"collection" ("" for none), "tags", "favorite"}. Returns it as updated
+    pub fn update_frozen(&mut self, seed: u32, timestamp: f64, changes_json: &str) -> Result<String, JsValue> {
+        let change: CurationChange = serde_json::from_str(changes_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid frozen fractal changes: {}", e)))?;
+        let frozen = self.user_state.update_frozen(seed, timestamp as u64, change)?;
+        Ok(serde_json::to_string(&frozen).unwrap())
+    }
+
+    // Adds FrozenFractal JSON from elsewhere (breed_with's child, an echo) to the frozen
+    // fractals, keeping its lineage; returns it as kept
+    pub fn keep_frozen(&mut self, frozen_json: &str) -> Result<String, JsValue> {
+        let frozen: FrozenFractal = serde_json::from_str(frozen_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid frozen fractal: {}", e)))?;
+        let kept = self.user_state.keep_frozen(frozen);
+        Ok(serde_json::to_string(&kept).unwrap())
+    }
+
+    // The family tree of the frozen fractal with this seed and timestamp as JSON: its
+    // seed, timestamp, generation and parents, each the same down to the day's own
+    // fractals or to ones no longer in the collection ("known": false)
+    pub fn get_lineage(&self, seed: u32, timestamp: f64) -> Result<String, JsValue> {
+        let tree = self.user_state.get_lineage(seed, timestamp as u64)?;
+        Ok(serde_json::to_string(&tree).unwrap())
+    }
+
+    // False if there was no frozen fractal with this seed and timestamp
+    pub fn delete_frozen(&mut self, seed: u32, timestamp: f64) -> Result<bool, JsValue> {
+        self.user_state.delete_frozen(seed, timestamp as u64)
+    }
+
+    // Frozen fractals kept before the least complex are dropped (10 unless set; favorites
+    // are always kept)
+    pub fn set_frozen_capacity(&mut self, capacity: u32) -> Result<(), JsValue> {
+        self.user_state.set_frozen_capacity(capacity as usize)
+    }
+
+    pub fn get_frozen_capacity(&self) -> u32 {
+        self.user_state.get_preferences().frozen_capacity as u32
+    }
+
+    // Returns the child of today's fractal and a frozen opponent as FrozenFractal JSON;
+    // pass its params to set_fractal_params to view it, and it to keep_frozen to keep it
+    pub fn breed_with(&self, opponent_json: &str) -> Result<String, JsValue> {
+        let opponent: FrozenFractal = serde_json::from_str(opponent_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let current = self.user_state.snapshot_current_fractal(
+            self.coloring_mode, self.palette.clone(), self.current_params()
+        );
+
+        let child = breed(&current, &opponent, BREED_MUTATION_RATE);
+        Ok(serde_json::to_string(&child).unwrap())
+    }
+
+    // Answers a frozen fractal (FrozenFractal JSON, e.g. a friend's) with today's transform
+    // and returns the echo as FrozenFractal JSON, a generation on from it; keep_frozen
+    // keeps it
+    pub fn echo_fractal(&self, original_json: &str) -> Result<String, JsValue> {
+        let original: FrozenFractal = serde_json::from_str(original_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let transform = UserState::matrix_data(&self.user_state.get_current_transform());
+        Ok(serde_json::to_string(&echo(&original, transform)).unwrap())
+    }
+
+    pub fn battle_fractals(&mut self, opponent_data: &str) -> Result<String, JsValue> {
+        let result = self.user_state.battle_against_fractal(opponent_data, self.current_params())?;
+        if self.user_state.get_haptics_enabled() {
+            self.haptics.battle(result.score_self > result.score_opponent, result.resonance_factor);
+        }
+        self.announce_achievements();
+        Ok(serde_json::to_string(&result).unwrap())
+    }
+
+    // Challenges a friend to battle today's fractal against theirs over the network, and
+    // returns the challenge's id. Both ends score the battle alike and check each other's
+    // scores before it's recorded; the battle callback hears how it goes
+    pub fn send_battle_challenge(&mut self, friend_id: &str) -> Result<String, JsValue> {
+        if self.user_state.get_contacts().get(friend_id).is_none() {
+            return Err(JsValue::from_str(&format!("{} is not a friend", friend_id)));
+        }
+        self.sealable(&[friend_id])?;
+        let fractal = self.user_state.battle_snapshot(self.current_params())?;
+        Ok(self.network.send_battle_challenge(&fractal, friend_id, self.user_state.get_rating()))
+    }
+
+    // Fights a challenge received from a friend with today's fractal
+    pub fn accept_battle_challenge(&mut self, challenge_id: &str) -> Result<(), JsValue> {
+        if let Some(challenge) = self.network.get_challenges().list.iter().find(|challenge| challenge.id == challenge_id) {
+            self.sealable(&[&challenge.opponent_id])?;
+        }
+        let fractal = self.user_state.battle_snapshot(self.current_params())?;
+        let challenge = self.network.accept_battle_challenge(challenge_id, &fractal, self.user_state.get_rating())?;
+        self.announce_challenge(&challenge, None);
+        Ok(())
+    }
+
+    pub fn decline_battle_challenge(&mut self, challenge_id: &str) -> Result<(), JsValue> {
+        let challenge = self.network.decline_battle_challenge(challenge_id)?;
+        self.announce_challenge(&challenge, None);
+        Ok(())
+    }
+
+    // The challenges sent and received, oldest first, as a JSON array of {"id",
+    // "opponent_id", "challenger", "state", "own", "theirs", "their_rating", "scores",
+    // "created_at", "updated_at"}. The state is "sent", "received", "accepted" (and
+    // waiting for the challenger's result), "declined", "verified", "disputed" or
+    // "expired", after a day unanswered
+    pub fn get_battle_challenges(&self) -> String {
+        serde_json::to_string(&self.network.get_challenges().list).unwrap()
+    }
+
+    // Called with {"challenge", "result"} as each challenge moves on: received, answered,
+    // and settled. The result, as battle_fractals returns it, comes once the battle is
+    // verified and recorded; null stops the calls
+    pub fn set_battle_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.battle_callback = callback;
+    }
+
+    // The user's battle rating (Elo, from 1200), peak, wins and losses, and the last 100
+    // battles with each opponent, the scores and resonance, as JSON
+    pub fn get_battle_record(&self) -> String {
+        self.user_state.get_battle_record()
+    }
+
+    // Streaks, milestones and which achievements are unlocked (and when), as JSON
+    pub fn get_achievements(&self) -> String {
+        self.user_state.get_achievements()
+    }
+
+    // Called with an achievement's JSON ({"id", "name", "description", "unlocked"}) each
+    // time one unlocks; null stops the calls
+    pub fn set_achievement_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.achievement_callback = callback;
+    }
+
+    // Called with {"date", "seed"} when the day changes during the session and the
+    // fractal moves on to the new day's seed; null stops the calls
+    pub fn set_rollover_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.rollover_callback = callback;
+    }
+
+    // Called with {"kind", "key", "message"} for each write that fails, kind "quota" when
+    // the browser is out of room (the oldest days are evicted to make some) or "write";
+    // null stops the calls
+    pub fn set_storage_error_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.storage_error_callback = callback;
+    }
+
+    // Deletes everything saved on this device and reports it as {"removed": [keys],
+    // "counts": {kind: n}}; syncing stops, so nothing is pulled back. Reload afterwards:
+    // until then the session carries on as a new, unsaved user
+    pub fn erase_all_data(&mut self) -> Result<String, JsValue> {
+        let seed_palette = self.palette == Palette::from_seed(self.user_state.get_seed());
+        self.cloud_sync = None;
+        let report = self.user_state.erase_all_data()?;
+        self.network.reset();
+        self.follow_seed(seed_palette);
+        Ok(serde_json::to_string(&report).unwrap())
+    }
+
+    // Explores any seed away from the day's own: a transform of its own, nothing saved
+    // and no gestures counted, and no battles until exit_sandbox brings back the day's
+    // fractal as it was. Called again, moves to another seed
+    pub fn enter_sandbox(&mut self, seed: u32) -> Result<(), JsValue> {
+        let seed_palette = self.palette == Palette::from_seed(self.user_state.get_seed());
+        self.user_state.enter_sandbox(seed)?;
+        self.follow_seed(seed_palette);
+        Ok(())
+    }
+
+    // False if not in the sandbox
+    pub fn exit_sandbox(&mut self) -> bool {
+        let seed_palette = self.palette == Palette::from_seed(self.user_state.get_seed());
+        if !self.user_state.exit_sandbox() {
+            return false;
+        }
+        self.follow_seed(seed_palette);
+        true
+    }
+
+    pub fn is_in_sandbox(&self) -> bool {
+        self.user_state.in_sandbox()
+    }
+
+    // After the seed changes: colors that came from the old seed follow the new one, the
+    // voices crossfade, and any replay of the old one stops
+    fn follow_seed(&mut self, seed_palette: bool) {
+        if seed_palette {
+            self.palette = Palette::from_seed(self.user_state.get_seed());
+        }
+        self.audio_transition = Some(CROSSFADE_TIME);
+        self.replay = None;
+    }
+
+    // The golden hour under way, or else the next (today's, or tomorrow's first), as
+    // {"start", "end"} in ms since the epoch, for the host to schedule a notification;
+    // start is in the past while one is open
+    pub fn get_next_golden_hour(&self) -> String {
+        match self.user_state.next_golden_hour() {
+            Some((start, end)) => serde_json::json!({ "start": start, "end": end }).to_string(),
+            None => "null".to_string(),
+        }
+    }
+
+    // Gestures made now count three times toward the day's complexity
+    pub fn is_golden_hour(&self) -> bool {
+        self.user_state.is_golden_hour()
+    }
+
+    // {"windows_hit", "interactions", "today_interactions", "today_windows_hit",
+    // "windows"}: golden-hour counts, all time and today, and today's windows in minutes
+    // after the day began
+    pub fn get_golden_stats(&self) -> String {
+        self.user_state.get_golden_stats()
+    }
+
+    // When a resonance happens, as JSON {"window", "participants", "duration"}: the user
+    // and at least `participants - 1` others active (a gesture, or a message received)
+    // within `window` seconds, after which the resonance mode lasts `duration` seconds.
+    // Missing fields take their defaults, 300, 3 and 30
+    pub fn set_resonance_settings(&mut self, settings_json: &str) -> Result<(), JsValue> {
+        self.resonance.settings = ResonanceSettings::parse(settings_json)?;
+        Ok(())
+    }
+
+    pub fn get_resonance_settings(&self) -> String {
+        serde_json::to_string(&self.resonance.settings).unwrap()
+    }
+
+    // Called with {"started_at", "participants", "seed", "strength", "duration"} as each
+    // resonance starts; null stops the calls
+    pub fn set_resonance_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.resonance_callback = callback;
+    }
+
+    // Every resonance the user has been part of, oldest first, as a JSON array of the
+    // events the callback hears
+    pub fn get_resonance_history(&self) -> String {
+        serde_json::to_string(&self.user_state.get_resonances().events).unwrap()
+    }
+
+    // How strongly the resonance mode shows now, 0..1; 0 outside one
+    pub fn get_resonance_level(&self) -> f32 {
+        self.resonance.level(js_sys::Date::now() as u64)
+    }
+
+    // Keeps the state in step with a REST endpoint (see RestBackend), so it survives
+    // cleared browser storage and follows the user between devices: synced at once,
+    // then every five minutes. `token` is sent as a Bearer token; it isn't saved, so
+    // configure again on each load
+    pub fn configure_sync(&mut self, endpoint: &str, token: Option<String>) -> Result<(), JsValue> {
+        let backend = RestBackend::new(endpoint, token)?;
+        self.cloud_sync = Some(CloudSync::new(Box::new(backend)));
+        Ok(())
+    }
+
+    pub fn stop_sync(&mut self) {
+        self.cloud_sync = None;
+    }
+
+    // Syncs on the next frame rather than waiting out the interval; false if no backend
+    // is configured
+    pub fn sync_now(&mut self) -> bool {
+        let Some(sync) = &mut self.cloud_sync else {
+            return false;
+        };
+        sync.sync_now();
+        true
+    }
+
+    // Called with {"synced", "revision", "error"} after each sync, error null unless it
+    // failed; null stops the calls
+    pub fn set_sync_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.sync_callback = callback;
+    }
+
+    // Publishes to and browses a public gallery at an HTTP endpoint (see GalleryClient);
+    // `token` is sent as a Bearer token and, as for sync, isn't saved
+    pub fn configure_gallery(&mut self, endpoint: &str, token: Option<String>) -> Result<(), JsValue> {
+        self.gallery = Some(GalleryClient::new(endpoint, token)?);
+        Ok(())
+    }
+
+    // Publishes a frozen fractal (FrozenFractal JSON), or without one the fractal as it
+    // stands, credited to `author` if given. `thumbnail` is square raw RGBA, as
+    // render_thumbnail gives; without one, one is rendered. The gallery callback hears
+    // {"kind": "published", "id"} once it's up
+    pub fn publish_to_gallery(&mut self, frozen_json: Option<String>, author: Option<String>,
+                              thumbnail: Option<Vec<u8>>) -> Result<(), JsValue> {
+        if self.gallery.is_none() {
+            return Err(JsValue::from_str("No gallery configured"));
+        }
+        let fractal = match frozen_json {
+            Some(json) => serde_json::from_str(&json).map_err(|e| JsValue::from_str(&e.to_string()))?,
+            None => self.user_state.snapshot_current_fractal(self.coloring_mode, self.palette.clone(), self.current_params()),
+        };
+        let thumbnail = match thumbnail {
+            Some(thumbnail) => thumbnail,
+            None => self.render_thumbnail(&serde_json::to_string(&fractal).unwrap(), GALLERY_THUMBNAIL_SIZE)?,
+        };
+        let gallery = self.gallery.as_mut().ok_or("No gallery configured")?;
+        gallery.publish(&fractal, author.as_deref(), &thumbnail)
+    }
+
+    // Asks for a page of the gallery, from 0; the gallery callback hears {"kind": "page",
+    // "page", "items": [{"id", "author", "published_at", "thumbnail_url", "fractal"}],
+    // "has_more"}
+    pub fn list_gallery(&mut self, page: u32) -> Result<(), JsValue> {
+        self.gallery.as_mut().ok_or("No gallery configured")?.list(page);
+        Ok(())
+    }
+
+    // Fetches a gallery fractal by id and opens it in the sandbox, posed and styled as
+    // published; the gallery callback hears {"kind": "opened", ...the entry}
+    pub fn open_gallery_fractal(&mut self, id: &str) -> Result<(), JsValue> {
+        self.gallery.as_mut().ok_or("No gallery configured")?.open(id);
+        Ok(())
+    }
+
+    // Called as each gallery request finishes, as the requests describe; a failed one
+    // gives {"kind": "failed", "request", "error"}. null stops the calls
+    pub fn set_gallery_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.gallery_callback = callback;
+    }
+
+    // Connects to a WebSocket server (ws:// or wss://) for sharing fractals with friends,
+    // reconnecting with backoff whenever the connection drops. Each message is a binary
+    // frame (see wire.rs) acknowledged by id, and the client opens with a hello carrying
+    // the user id; a server still speaking JSON text frames is understood too
+    pub fn connect_network(&mut self, url: &str) -> Result<(), JsValue> {
+        self.network.connect(url)
+    }
+
+    // Messages not yet acknowledged wait for the next connect_network
+    pub fn disconnect_network(&mut self) {
+        self.network.disconnect();
+    }
+
+    pub fn is_network_connected(&self) -> bool {
+        self.network.is_connected()
+    }
+
+    // Sends today's fractal as soon as the connection is up: sealed to the friends in
+    // `friend_ids` (a JSON array of user ids), each of whom needs a key a share link
+    // carried, or unsealed to everyone connected without it
+    pub fn share_morning_fractal(&mut self, friend_ids: Option<String>) -> Result<(), JsValue> {
+        if self.user_state.in_sandbox() {
+            return Err(JsValue::from_str("Only the day's fractal is shared; leave the sandbox first"));
+        }
+        let recipients = self.sealed_recipients(friend_ids.as_deref())?;
+        let fractal = self.user_state.snapshot_current_fractal(self.coloring_mode, self.palette.clone(), self.current_params());
+        self.network.broadcast_morning_fractal(&fractal, recipients)
+    }
+
+    // A Web Push payload for today's fractal, as JSON: {"title", "body", "url", "tag",
+    // "thumbnail": {"seed", "timestamp", "size"}, "timestamp"}. The url opens it; the
+    // thumbnail says which fractal to draw, and how large, for the host to render_thumbnail
+    pub fn build_morning_notification(&self) -> Result<String, JsValue> {
+        if self.user_state.in_sandbox() {
+            return Err(JsValue::from_str("Only the day's fractal is notified of; leave the sandbox first"));
+        }
+        let fractal = self.user_state.snapshot_current_fractal(self.coloring_mode, self.palette.clone(), self.current_params());
+        Ok(self.network.build_morning_notification(&fractal))
+    }
+
+    // Answers a friend's fractal (FrozenFractal JSON) with today's transform, to the
+    // friends in `friend_ids` as share_morning_fractal sends
+    pub fn send_echo(&mut self, original_json: &str, friend_ids: Option<String>) -> Result<(), JsValue> {
+        let original: FrozenFractal = serde_json::from_str(original_json)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let recipients = self.sealed_recipients(friend_ids.as_deref())?;
+        let transform = self.user_state.get_current_transform();
+        self.network.send_echo_response(&original, &UserState::matrix_data(&transform), recipients)
+    }
+
+    // Streams the view's transform live to the friends in `friend_ids`, as
+    // share_morning_fractal sends, step by step as it moves: a few bytes a step rather than
+    // send_echo's whole message, for watching one another sculpt (see echo.rs). Replaces
+    // any stream already going
+    pub fn start_echo_stream(&mut self, friend_ids: Option<String>) -> Result<(), JsValue> {
+        let recipients = self.recipients(friend_ids.as_deref())?;
+        self.network.start_echo_stream(recipients);
+        Ok(())
+    }
+
+    // False if no stream was going
+    pub fn stop_echo_stream(&mut self) -> bool {
+        self.network.stop_echo_stream()
+    }
+
+    // Friends' live echoes, as JSON: [{"sender_id", "name", "transform", "updated_at"}],
+    // the transform as a transform_echo's 16 floats, null until a keyframe has arrived,
+    // and updated_at when it last moved, in ms since the epoch by this device's clock
+    pub fn get_echo_streams(&self) -> String {
+        let contacts = self.user_state.get_contacts();
+        let streams: Vec<serde_json::Value> = self.network.get_echo_streams().list()
+            .filter_map(|(sender, stream)| {
+                let contact = contacts.get(sender)?;
+                Some(serde_json::json!({
+                    "sender_id": sender,
+                    "name": contact.name,
+                    "transform": stream.pose.map(|pose| UserState::matrix_data(&pose.to_matrix())),
+                    "updated_at": stream.updated_at,
+                }))
+            })
+            .collect();
+        serde_json::to_string(&streams).unwrap()
+    }
+
+    // Called as a friend's live echo moves, as JSON: {"sender_id", "transform"}, as
+    // get_echo_streams has them; null stops the calls
+    pub fn set_echo_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.echo_callback = callback;
+    }
+
+    // Befriends whoever shared `url` (a share link's URL, such as get_share_url gives), by
+    // `name` if given; returns the friend as JSON: {"id", "name", "share_key", "added_at",
+    // "last_seen", "updated_at"}
+    pub fn add_friend_from_share_url(&mut self, url: &str, name: Option<String>) -> Result<String, JsValue> {
+        let friend = self.user_state.add_friend_from_share_url(url, name.as_deref())?;
+        Ok(serde_json::to_string(&friend).unwrap())
+    }
+
+    // Friends, as add_friend_from_share_url returns each, most recently seen first
+    pub fn list_friends(&self) -> String {
+        let mut friends = self.user_state.get_contacts().friends.clone();
+        friends.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then_with(|| a.name.cmp(&b.name)));
+        serde_json::to_string(&friends).unwrap()
+    }
+
+    // Friends connected now, as the presence they send says, the most recently active
+    // first, as JSON: [{"id", "name", "joined_at", "last_active", "heard_at"}], times in
+    // ms since the epoch by this device's clock, and last_active null before a gesture
+    // today. The user's own presence goes to friends unless "share_presence" is off
+    pub fn get_active_friends(&self) -> String {
+        let contacts = self.user_state.get_contacts();
+        let active: Vec<serde_json::Value> = self.network.get_active_friends().into_iter()
+            .filter_map(|friend| {
+                let contact = contacts.get(&friend.id)?;
+                Some(serde_json::json!({
+                    "id": friend.id,
+                    "name": contact.name,
+                    "joined_at": friend.joined_at,
+                    "last_active": friend.last_active,
+                    "heard_at": friend.heard_at,
+                }))
+            })
+            .collect();
+        serde_json::to_string(&active).unwrap()
+    }
+
+    pub fn rename_friend(&mut self, id: &str, name: &str) -> Result<(), JsValue> {
+        self.user_state.rename_friend(id, name)
+    }
 
-        // Much more dramatic rotation with time-based evolution
-        let base_angle = direction * intensity * 0.5; // 5x stronger
-        let time_modifier = (self.time * 0.1).sin() * 0.2;
-        let angle = base_angle + time_modifier;
+    // False if they weren't a friend
+    pub fn remove_friend(&mut self, id: &str) -> bool {
+        self.user_state.remove_friend(id)
+    }
 
-        // Rotate around multiple axes for complex motion
-        let rotation_x = Matrix4::from_axis_angle(&Vector3::x_axis(), angle * 0.3);
-        let rotation_y = Matrix4::from_axis_angle(&Vector3::y_axis(), angle * 0.7);
-        let rotation_z = Matrix4::from_axis_angle(&Vector3::z_axis(), angle);
+    // Joins the room `room_id` (1 to 64 bytes), to sculpt one fractal with whoever else
+    // is in it: its seed opens in the sandbox, and every gesture anyone there makes moves
+    // it for all, as does entering the sandbox on another seed (see room.rs). With `seed`
+    // the room starts afresh on it, untouched; without, the fractal in view shows until
+    // the room's arrives. Only the seed's own fractal is shared, colors staying each
+    // user's own. Leaving the sandbox leaves the room
+    pub fn join_room(&mut self, room_id: &str, seed: Option<u32>) -> Result<(), JsValue> {
+        let claim = seed.is_some();
+        let fresh = claim || !self.user_state.in_sandbox();
+        let seed = seed.unwrap_or_else(|| self.user_state.get_seed());
+        let pose = if fresh { Pose::default() } else { self.user_state.get_pose() };
+        self.network.join_room(room_id, seed, &pose, claim)?;
+        if fresh {
+            if let Err(e) = self.enter_sandbox(seed) {
+                self.network.leave_room();
+                return Err(e);
+            }
+        }
+        self.fractal_params = None;
+        self.preset = None;
+        self.morph = None;
+        Ok(())
+    }
 
-        rotation_z * rotation_y * rotation_x
+    // Stays in the sandbox, with the room's fractal as it was left; false if not in a room
+    pub fn leave_room(&mut self) -> bool {
+        let left = self.network.leave_room();
+        if left {
+            self.announce_room();
+        }
+        left
     }
 
-    fn create_scale_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::Matrix4;
-        let scale = 1.0 + intensity * 1.5; // Much more dramatic scaling
-        Matrix4::new_scaling(scale)
+    // The room the user is in, as JSON: {"id", "seed", "members": [{"id", "name"}]}, the
+    // members being the others there, named if they're friends; null outside one
+    pub fn get_room(&self) -> String {
+        let Some(room) = self.network.get_room() else {
+            return "null".to_string();
+        };
+        let contacts = self.user_state.get_contacts();
+        let members: Vec<serde_json::Value> = room.members()
+            .map(|id| serde_json::json!({ "id": id, "name": contacts.get(id).map(|friend| &friend.name) }))
+            .collect();
+        serde_json::json!({ "id": room.id, "seed": room.seed(), "members": members }).to_string()
     }
 
-    fn create_complex_scale_transform(&self, scale_factor: f32, direction: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::Matrix4;
+    // Called with get_room's JSON whenever someone joins or leaves the room, and with null
+    // once the user has left it; null stops the calls
+    pub fn set_room_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.room_callback = callback;
+    }
 
-        // Non-uniform scaling based on direction
-        let scale_x = scale_factor * (1.0 + direction.cos() * 0.5);
-        let scale_y = scale_factor * (1.0 + direction.sin() * 0.5);
-        let scale_z = scale_factor * (1.0 + (direction * 2.0).cos() * 0.3);
+    // Called with each message friends send, as JSON: {"sender_id", "fractal_data",
+    // "transform_echo", "timestamp", "message_type", "recipients" if it was addressed, and
+    // "epoch" for set_shared_epoch if it carries one}; null stops the calls
+    pub fn set_network_callback(&mut self, callback: Option<js_sys::Function>) {
+        self.network_callback = callback;
+    }
 
-        Matrix4::new_nonuniform_scaling(&nalgebra::Vector3::new(scale_x, scale_y, scale_z))
+    // Animates in phase with whoever sent a message carrying `epoch` (when their animation
+    // started, in ms on the shared clock): the animation jumps to where theirs is now, so a
+    // fractal they shared moves the same on both screens. As exact as the two clocks'
+    // estimates of the relay's, which get_clock_sync gives
+    pub fn set_shared_epoch(&mut self, epoch: f64) {
+        let shared_now = self.network.get_clock().to_shared(js_sys::Date::now());
+        self.time = ((shared_now - epoch) / 1000.0).max(0.0) as f32;
     }
 
-    fn create_tilt_transform(&self, direction: f32, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::{Matrix4, Vector3};
+    // The shared clock as estimated from the relay's, as JSON: {"offset", "uncertainty"},
+    // the relay's lead over this device's clock and how far off that may be, in ms; both
+    // null until the relay has answered a probe, when the device's own clock stands in
+    pub fn get_clock_sync(&self) -> String {
+        let clock = self.network.get_clock();
+        serde_json::json!({ "offset": clock.offset(), "uncertainty": clock.uncertainty() }).to_string()
+    }
 
-        // More dramatic tilting with perspective distortion
-        let angle_x = direction.cos() * intensity * 0.3;
-        let angle_y = direction.sin() * intensity * 0.3;
-        let angle_z = (direction * 0.5).sin() * intensity * 0.2;
+    // Messages that have arrived since the last call (or callback), as a JSON array of
+    // what set_network_callback is called with; each comes once, even across reloads
+    pub fn poll_inbox(&mut self) -> String {
+        serde_json::to_string(&self.take_inbox()).unwrap()
+    }
 
-        let tilt_x = Matrix4::from_axis_angle(&Vector3::x_axis(), angle_x);
-        let tilt_y = Matrix4::from_axis_angle(&Vector3::y_axis(), angle_y);
-        let tilt_z = Matrix4::from_axis_angle(&Vector3::z_axis(), angle_z);
+    // {"used", "budget"}: estimated bytes of saved state, and how many there may be before
+    // the oldest days are evicted
+    pub fn get_storage_usage(&self) -> String {
+        let (used, budget) = self.user_state.get_storage_usage();
+        serde_json::json!({ "used": used, "budget": budget }).to_string()
+    }
 
-        tilt_z * tilt_y * tilt_x
+    // "local" (default) starts each day at local midnight, wherever the device is;
+    // "utc" at UTC midnight, the same moment everywhere. Changing it can change today's
+    // seed
+    pub fn set_day_boundary(&mut self, boundary: &str) -> Result<(), JsValue> {
+        if let Some(old_seed) = self.user_state.set_day_boundary(DayBoundary::parse(boundary)?)? {
+            self.roll_over(old_seed);
+        }
+        Ok(())
     }
 
-    fn create_4d_twist_transform(&self, direction: f32, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::Matrix4;
+    pub fn get_day_boundary(&self) -> String {
+        self.user_state.get_preferences().day_boundary.name().to_string()
+    }
 
-        // Complex 4D rotation that creates mesmerizing effects
-        let twist_factor = intensity * 0.4;
-        let time_twist = (self.time * 0.05 + direction).sin() * twist_factor;
+    fn roll_over(&mut self, old_seed: u32) {
+        // Colors that came from the old seed follow the new one
+        if self.palette == Palette::from_seed(old_seed) {
+            self.palette = Palette::from_seed(self.user_state.get_seed());
+        }
+        self.audio_transition = Some(CROSSFADE_TIME);
 
-        // Create a complex transformation matrix
-        let mut transform = Matrix4::identity();
+        let Some(callback) = &self.rollover_callback else {
+            return;
+        };
+        let json = serde_json::json!({
+            "date": self.user_state.get_day(),
+            "seed": self.user_state.get_seed(),
+        });
+        if let Err(e) = callback.call1(&JsValue::NULL, &JsValue::from_str(&json.to_string())) {
+            web_sys::console::warn_2(&JsValue::from_str("Rollover callback failed:"), &e);
+        }
+    }
 
-        // Apply time-based twisted rotation
-        let c = time_twist.cos();
-        let s = time_twist.sin();
+    fn poll_sync(&mut self) {
+        let Some(sync) = &mut self.cloud_sync else {
+            return;
+        };
+        let seed = self.user_state.get_seed();
+        let seed_palette = self.palette == Palette::from_seed(seed);
+        let Some(status) = sync.poll(&mut self.user_state) else {
+            return;
+        };
 
-        transform[(0, 0)] = c;
-        transform[(0, 1)] = -s;
-        transform[(1, 0)] = s;
-        transform[(1, 1)] = c;
+        // Taking on the synced identity changes today's seed
+        if self.user_state.get_seed() != seed {
+            if seed_palette {
+                self.palette = Palette::from_seed(self.user_state.get_seed());
+            }
+            self.audio_transition = Some(CROSSFADE_TIME);
+        }
+        let Some(callback) = &self.sync_callback else {
+            return;
+        };
+        let json = JsValue::from_str(&serde_json::to_string(&status).unwrap());
+        if let Err(e) = callback.call1(&JsValue::NULL, &json) {
+            web_sys::console::warn_2(&JsValue::from_str("Sync callback failed:"), &e);
+        }
+    }
 
-        // Add some 4D perspective effects
-        transform[(2, 3)] = intensity * 0.1;
+    // Shows what the others in the room have done, and sends what the user has
+    fn poll_room(&mut self) {
+        if self.network.get_room().is_none() {
+            return;
+        }
+        if !self.user_state.in_sandbox() {
+            self.network.leave_room();
+            self.announce_room();
+            return;
+        }
+        let Some(poll) = self.network.poll_room(self.user_state.get_seed(), &self.user_state.get_pose()) else {
+            return;
+        };
+        if let Some((seed, pose)) = poll.shared {
+            if let Err(e) = self.show_room(seed, pose) {
+                web_sys::console::warn_2(&JsValue::from_str("Room's fractal not shown:"), &e);
+            }
+        }
+        if poll.members_changed {
+            self.announce_room();
+        }
+    }
 
-        transform
+    fn show_room(&mut self, seed: u32, pose: Pose) -> Result<(), JsValue> {
+        if seed != self.user_state.get_seed() {
+            self.enter_sandbox(seed)?;
+            self.fractal_params = None;
+            self.preset = None;
+            self.morph = None;
+        }
+        self.user_state.set_sandbox_pose(pose)
     }
 
-    fn create_color_shift_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::Matrix4;
+    fn poll_echo(&mut self) {
+        let contacts = self.user_state.get_contacts();
+        let moved = self.network.poll_echo(&self.user_state.get_pose());
+        let Some(callback) = &self.echo_callback else {
+            return;
+        };
+        for (sender, pose) in moved.into_iter().filter(|(sender, _)| contacts.get(sender).is_some()) {
+            let json = serde_json::json!({ "sender_id": sender, "transform": UserState::matrix_data(&pose.to_matrix()) }).to_string();
+            if let Err(e) = callback.call1(&JsValue::NULL, &JsValue::from_str(&json)) {
+                web_sys::console::warn_2(&JsValue::from_str("Echo callback failed:"), &e);
+            }
+        }
+    }
 
-        // Color transformation matrix that shifts hues dramatically
-        let hue_shift = intensity * std::f32::consts::PI;
-        let cos_h = hue_shift.cos();
-        let sin_h = hue_shift.sin();
+    fn announce_room(&self) {
+        let Some(callback) = &self.room_callback else {
+            return;
+        };
+        if let Err(e) = callback.call1(&JsValue::NULL, &JsValue::from_str(&self.get_room())) {
+            web_sys::console::warn_2(&JsValue::from_str("Room callback failed:"), &e);
+        }
+    }
 
-        let mut color_matrix = Matrix4::identity();
+    fn poll_battles(&mut self) {
+        let contacts = self.user_state.get_contacts();
+        let moved = self.network.poll_battles(|id| contacts.get(id).is_some());
+        for challenge in moved {
+            let result = match challenge.state {
+                ChallengeState::Verified => self.user_state.record_challenge(&challenge),
+                _ => None,
+            };
+            if let Some(result) = &result {
+                if self.user_state.get_haptics_enabled() {
+                    self.haptics.battle(result.score_self > result.score_opponent, result.resonance_factor);
+                }
+                self.announce_achievements();
+            }
+            self.announce_challenge(&challenge, result.as_ref());
+        }
+    }
 
-        // RGB rotation matrix for hue shifting
-        color_matrix[(0, 0)] = cos_h + (1.0 - cos_h) * 0.299;
-        color_matrix[(0, 1)] = (1.0 - cos_h) * 0.587 - sin_h * 0.114;
-        color_matrix[(0, 2)] = (1.0 - cos_h) * 0.114 + sin_h * 0.587;
+    fn announce_challenge(&self, challenge: &Challenge, result: Option<&BattleResult>) {
+        let Some(callback) = &self.battle_callback else {
+            return;
+        };
+        let json = serde_json::json!({ "challenge": challenge, "result": result }).to_string();
+        if let Err(e) = callback.call1(&JsValue::NULL, &JsValue::from_str(&json)) {
+            web_sys::console::warn_2(&JsValue::from_str("Battle callback failed:"), &e);
+        }
+    }
 
-        color_matrix[(1, 0)] = (1.0 - cos_h) * 0.299 + sin_h * 0.701;
-        color_matrix[(1, 1)] = cos_h + (1.0 - cos_h) * 0.587;
-        color_matrix[(1, 2)] = (1.0 - cos_h) * 0.114 - sin_h * 0.299;
+    // Starts the resonance mode when the user and enough others are active together: the
+    // shimmer in the shaders follows Resonance::level, and the audio's is scheduled whole
+    fn poll_resonance(&mut self) {
+        let now = js_sys::Date::now() as u64;
+        let since = now.saturating_sub(self.resonance.settings.window as u64 * 1000);
+        let user_state = &self.user_state;
+        let contacts = user_state.get_contacts();
+        let activity = self.network.recent_activity(since, |id| contacts.get(id).is_some());
+        let event = self.resonance.detect(
+            now, user_state.get_user_id(), user_state.last_gesture_at(), &activity,
+            user_state.get_resonances().last_at(), user_state.get_seed(),
+        );
+        let Some(event) = event else {
+            return;
+        };
 
-        color_matrix[(2, 0)] = (1.0 - cos_h) * 0.299 - sin_h * 0.587;
-        color_matrix[(2, 1)] = (1.0 - cos_h) * 0.587 + sin_h * 0.299;
-        color_matrix[(2, 2)] = cos_h + (1.0 - cos_h) * 0.114;
+        if let Err(e) = self.audio_engine.resonate(event.duration, resonance::MODE_FADE, event.strength) {
+            web_sys::console::warn_2(&JsValue::from_str("Resonance sound failed:"), &e);
+        }
+        self.user_state.record_resonance(event.clone());
+        self.announce_achievements();
+        if let Some(callback) = &self.resonance_callback {
+            let json = JsValue::from_str(&serde_json::to_string(&event).unwrap());
+            if let Err(e) = callback.call1(&JsValue::NULL, &json) {
+                web_sys::console::warn_2(&JsValue::from_str("Resonance callback failed:"), &e);
+            }
+        }
+    }
 
-        color_matrix
+    fn poll_gallery(&mut self) {
+        let Some(gallery) = &mut self.gallery else {
+            return;
+        };
+        for event in gallery.poll() {
+            let event = match event {
+                GalleryEvent::Opened(entry) => match self.open_in_sandbox(&entry.fractal) {
+                    Ok(()) => GalleryEvent::Opened(entry),
+                    Err(e) => GalleryEvent::Failed {
+                        request: "open",
+                        error: e.as_string().unwrap_or_else(|| format!("{:?}", e)),
+                    },
+                },
+                event => event,
+            };
+            let Some(callback) = &self.gallery_callback else {
+                continue;
+            };
+            let json = JsValue::from_str(&serde_json::to_string(&event).unwrap());
+            if let Err(e) = callback.call1(&JsValue::NULL, &json) {
+                web_sys::console::warn_2(&JsValue::from_str("Gallery callback failed:"), &e);
+            }
+        }
     }
 
-    fn create_parameter_shift_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::Matrix4;
+    fn poll_link(&mut self) {
+        if !self.user_state.is_store_ready() {
+            return;
+        }
+        let Some(link) = self.pending_link.take() else {
+            return;
+        };
+        if let Err(e) = self.open_link(link) {
+            web_sys::console::warn_2(&JsValue::from_str("Share link not opened:"), &e);
+        }
+    }
 
-        // Transform that affects fractal parameters dynamically
-        let param_shift = intensity * 0.2;
-        let mut param_matrix = Matrix4::identity();
+    fn open_link(&mut self, link: SharedLink) -> Result<(), JsValue> {
+        let from = self.current_params();
+        let to = match link.content {
+            LinkContent::Fractal(fractal) => self.open_in_sandbox(&fractal).map(|_| Some(fractal.fractal_params())),
+            LinkContent::Token(seed) => self.enter_sandbox(seed).map(|_| None),
+            LinkContent::Seed { seed, preset, params } => self.enter_sandbox(seed).and_then(|_| match preset {
+                Some(name) => self.load_preset(name).map(|_| presets::by_name(name).map(|preset| preset.params)),
+                None => {
+                    self.fractal_params = params;
+                    Ok(params)
+                }
+            }),
+        };
+        if let Some(to) = to? {
+            self.morph = Some(Morph::new(from, to, LINK_MORPH_DURATION));
+        }
+        Ok(())
+    }
 
-        // Encode parameter changes in transformation matrix
-        param_matrix[(3, 0)] = param_shift * (self.time * 0.1).sin();
-        param_matrix[(3, 1)] = param_shift * (self.time * 0.13).cos();
-        param_matrix[(3, 2)] = param_shift * (self.time * 0.07).sin();
+    // Explores someone's frozen fractal in the sandbox: its seed, params, pose and styling
+    fn open_in_sandbox(&mut self, frozen: &FrozenFractal) -> Result<(), JsValue> {
+        self.enter_sandbox(frozen.seed)?;
+        self.user_state.set_sandbox_transform(&UserState::from_matrix_data(&frozen.transform_matrix))?;
+        self.preset = None;
+        self.morph = None;
+        self.fractal_params = frozen.params;
+        self.coloring_mode = frozen.coloring_mode;
+        self.palette = frozen.palette.clone().unwrap_or_else(|| Palette::from_seed(frozen.seed));
+        Ok(())
+    }
 
-        param_matrix
+    // The inbox's messages from friends; a stranger's are dropped
+    fn take_inbox(&mut self) -> Vec<FractalMessage> {
+        let mut messages = self.network.poll_inbox();
+        messages.retain(|message| self.user_state.get_contacts().get(&message.sender_id).is_some());
+        for message in &messages {
+            self.user_state.friend_seen(&message.sender_id, message.timestamp);
+        }
+        messages
     }
 
-    fn create_brightness_transform(&self, intensity: f32) -> nalgebra::Matrix4<f32> {
-        use nalgebra::Matrix4;
-        // Brightness affects the 4th dimension
-        Matrix4::new_translation(&nalgebra::Vector3::new(0.0, 0.0, intensity * 0.1))
+    // A JSON array of friends' ids, each checked to be a friend; none for everyone
+    fn recipients(&self, friend_ids: Option<&str>) -> Result<Vec<String>, JsValue> {
+        let Some(json) = friend_ids else {
+            return Ok(Vec::new());
+        };
+        let ids: Vec<String> = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid friend ids: {}", e)))?;
+        if ids.is_empty() {
+            return Err(JsValue::from_str("No friends to send to"));
+        }
+        match ids.iter().find(|id| self.user_state.get_contacts().get(id).is_none()) {
+            Some(id) => Err(JsValue::from_str(&format!("{} is not a friend", id))),
+            None => Ok(ids),
+        }
     }
 
-    pub fn get_share_url(&self) -> String {
-        format!("{}?seed={}&user={}&time={}",
-            "https://resonant.app",
-            self.user_state.get_seed(),
-            self.user_state.get_user_id(),
-            self.last_wake_time as u64
-        )
+    // recipients, each of whom must also have a key to seal for
+    fn sealed_recipients(&self, friend_ids: Option<&str>) -> Result<Vec<String>, JsValue> {
+        let ids = self.recipients(friend_ids)?;
+        self.sealable(&ids)?;
+        Ok(ids)
     }
 
-    pub fn get_fractal_info(&self) -> String {
-        serde_json::json!({
-            "type": self.fractal_type,
-            "seed": self.user_state.get_seed(),
-            "complexity": self.user_state.get_complexity_score(),
-            "interactions_today": self.user_state.get_interaction_count(),
-            "audio_frequencies": self.audio_engine.get_current_frequencies()
-        }).to_string()
+    // Addressed messages go sealed or not at all, so each friend in `ids` needs a key a
+    // share link carried
+    fn sealable(&self, ids: &[impl AsRef<str>]) -> Result<(), JsValue> {
+        let contacts = self.user_state.get_contacts();
+        let unsealable = ids.iter().map(AsRef::as_ref).find(|id| {
+            contacts.get(id).and_then(|friend| friend.share_key.as_deref()).and_then(crypto::parse_public_key).is_none()
+        });
+        match unsealable {
+            Some(id) => Err(JsValue::from_str(&format!("No key to seal for {}; befriend them again from a link with one", id))),
+            None => Ok(()),
+        }
     }
 
-    pub fn freeze_fractal(&mut self) -> Result<String, JsValue> {
-        let frozen = self.user_state.freeze_current_fractal(self.fractal_type.clone())?;
-        Ok(serde_json::to_string(&frozen).unwrap())
+    fn poll_network(&mut self) {
+        self.network.set_user_id(self.user_state.get_user_id());
+        self.network.set_animation_epoch(js_sys::Date::now() - self.time as f64 * 1000.0);
+        let contacts = self.user_state.get_contacts();
+        self.network.poll(self.user_state.get_identity(), |id| {
+            contacts.get(id)?.share_key.as_deref().and_then(crypto::parse_public_key)
+        }, |id| contacts.get(id).is_some());
+        self.network.poll_presence(self.user_state.get_preferences().share_presence, self.user_state.last_gesture_at(), || {
+            contacts.friends.iter().map(|friend| friend.id.clone()).collect()
+        });
+        // Without a callback, messages wait in the inbox for one or for poll_inbox
+        let Some(callback) = self.network_callback.clone() else {
+            return;
+        };
+        for message in self.take_inbox() {
+            let json = JsValue::from_str(&serde_json::to_string(&message).unwrap());
+            if let Err(e) = callback.call1(&JsValue::NULL, &json) {
+                web_sys::console::warn_2(&JsValue::from_str("Network callback failed:"), &e);
+            }
+        }
     }
 
-    pub fn battle_fractals(&self, opponent_data: &str) -> Result<String, JsValue> {
-        let result = self.user_state.battle_against_fractal(opponent_data)?;
-        Ok(serde_json::to_string(&result).unwrap())
+    fn report_storage_errors(&mut self) {
+        let errors = self.user_state.take_storage_errors();
+        let Some(callback) = &self.storage_error_callback else {
+            return;
+        };
+        for error in errors {
+            let json = JsValue::from_str(&serde_json::to_string(&error).unwrap());
+            if let Err(e) = callback.call1(&JsValue::NULL, &json) {
+                web_sys::console::warn_2(&JsValue::from_str("Storage error callback failed:"), &e);
+            }
+        }
     }
 
-    fn detect_wake_time() -> f64 {
-        // Simple heuristic: if it's been more than 4 hours since last activity,
-        // this is probably a wake-up
-        js_sys::Date::now()
+    fn announce_achievements(&mut self) {
+        let unlocked = self.user_state.take_unlocked_achievements();
+        let Some(callback) = &self.achievement_callback else {
+            return;
+        };
+        for achievement in unlocked {
+            let json = JsValue::from_str(&serde_json::to_string(&achievement).unwrap());
+            if let Err(e) = callback.call1(&JsValue::NULL, &json) {
+                web_sys::console::warn_2(&JsValue::from_str("Achievement callback failed:"), &e);
+            }
+        }
     }
 
     fn create_shader_program(gl: &GL) -> Result<WebGlProgram, JsValue> {
-        let vert_shader = Self::compile_shader(gl, GL::VERTEX_SHADER, VERTEX_SHADER)?;
-        let frag_shader = Self::compile_shader(gl, GL::FRAGMENT_SHADER, FRAGMENT_SHADER)?;
+        Self::link_program(gl, VERTEX_SHADER, &glsl::fragment_shader(FRAGMENT_SHADER))
+    }
+
+    fn link_program(gl: &GL, vertex_source: &str, fragment_source: &str) -> Result<WebGlProgram, JsValue> {
+        let vert_shader = Self::compile_shader(gl, GL::VERTEX_SHADER, vertex_source)?;
+        let frag_shader = Self::compile_shader(gl, GL::FRAGMENT_SHADER, fragment_source)?;
 
         let program = gl.create_program().ok_or("Failed to create program")?;
         gl.attach_shader(&program, &vert_shader);
@@ -383,12 +3275,81 @@ void main() {
 "#;
 
 const FRAGMENT_SHADER: &str = r#"
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
 precision mediump float;
+#endif
 
 uniform float u_time;
 uniform int u_seed;
+uniform vec2 u_resolution;
+uniform vec4 u_tile; // xy: pixel offset of this draw within u_resolution
 uniform int u_fractal_type;
+uniform vec4 u_fractal_params[2];
 uniform mat4 u_transform;
+uniform bool u_write_depth;
+uniform int u_coloring_mode;
+
+// Environment (see environment.rs, arrays sized by MAX_GRADIENT_STOPS)
+uniform int u_env_mode;
+uniform int u_env_stop_count;
+uniform vec3 u_env_colors[8];
+uniform float u_env_positions[8];
+uniform float u_env_density;
+uniform float u_env_reflectivity;
+
+// 0.0 = full quality everywhere, 1.0 = strongest falloff toward the edges
+uniform float u_foveation;
+
+// Microphone band energies (see audio_input.rs, sized by AUDIO_BANDS), lowest first
+uniform float u_audio_bands[8];
+
+// What the audio engine is playing (see AudioEngine::voice_state), loudest voice first:
+// x is its pitch (0 at 80Hz to 1 at 2kHz, logarithmic), y its level 0..1
+uniform vec2 u_audio_state[8];
+
+// The rhythm's beat (see Sequencer::pulse): jumps to the hit's accent and decays
+uniform float u_pulse;
+
+// The golden-hour aura (see golden.rs), 0..1, and its color
+uniform float u_golden;
+const vec3 GOLDEN_AURA = vec3(1.0, 0.78, 0.36);
+
+// The resonance mode (see resonance.rs), 0..1: the palette shimmers through the surface
+uniform float u_resonance;
+
+// Camera zoom (see zoom.rs); the center arrives as hi + lo f32 pairs
+uniform float u_zoom;
+uniform vec3 u_center_hi;
+uniform vec3 u_center_lo;
+uniform bool u_dual_float;
+uniform float u_df_one;
+
+// Weather modifier (see weather.rs): (turbulence, erosion, bloom) and wave phases
+uniform vec3 u_weather;
+uniform vec3 u_weather_phase;
+uniform float u_roughness;
+
+// Cross-section plane (see slice.rs)
+uniform bool u_slice_enabled;
+uniform vec3 u_slice_normal;
+uniform float u_slice_offset;
+
+// Surface palette (see palette.rs, arrays sized by MAX_PALETTE_STOPS)
+uniform int u_palette_count;
+uniform vec3 u_palette_colors[8];
+uniform float u_palette_positions[8];
+
+// Histogram equalization lookup for coloring mode 4 (see histogram.rs)
+uniform sampler2D u_equalize_lut;
+uniform float u_equalize_range;
+
+// Smooth iteration count of the current DE evaluation (OrbitStats::smooth_iteration)
+float g_iterations;
+
+// The trap, iteration, palette and voice helpers, shared with FRAGMENT_SHADER_2D; see glsl.rs
+// {helpers}
 
 vec3 hsv2rgb(vec3 c) {
     vec4 K = vec4(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
@@ -396,86 +3357,162 @@ vec3 hsv2rgb(vec3 c) {
     return c.z * mix(K.xxx, clamp(p - K.xxx, 0.0, 1.0), c.y);
 }
 
-float mandelbulb(vec3 pos, float time, float seed) {
-    vec3 z = pos;
-    float dr = 1.0;
-    float r = 0.0;
-    float power = 6.0 + sin(time * 0.1 + seed * 0.001) * 3.0;
+// Dual-float (double-single) arithmetic: a value is vec2(hi, lo) with hi + lo the full
+// number. u_df_one keeps compilers from simplifying the error terms to zero.
+vec2 quick_two_sum(float a, float b) {
+    float s = a + b;
+    return vec2(s, b - (s - a) * u_df_one);
+}
 
-    for(int i = 0; i < 10; i++) {
-        r = length(z);
-        if(r > 2.0) break;
+vec2 two_sum(float a, float b) {
+    float s = a + b;
+    float v = (s - a) * u_df_one;
+    return vec2(s, (a - (s - v)) + (b - v));
+}
 
-        float theta = acos(z.z/r) + time * 0.05;
-        float phi = atan(z.y, z.x) + seed * 0.001;
-        dr = pow(r, power - 1.0) * power * dr + 1.0;
+vec2 df_split(float a) {
+    float t = a * 4097.0;
+    float hi = t - (t - a) * u_df_one;
+    return vec2(hi, a - hi);
+}
 
-        float zr = pow(r, power);
-        z = zr * vec3(
-            sin(theta) * cos(phi),
-            sin(theta) * sin(phi),
-            cos(theta)
-        ) + pos;
-    }
+vec2 two_prod(float a, float b) {
+    float p = a * b;
+    vec2 sa = df_split(a);
+    vec2 sb = df_split(b);
+    float err = ((sa.x * sb.x - p) + sa.x * sb.y + sa.y * sb.x) + sa.y * sb.y;
+    return vec2(p, err);
+}
 
-    return 0.5 * log(r) * r / dr;
+vec2 df_add(vec2 a, vec2 b) {
+    vec2 s = two_sum(a.x, b.x);
+    return quick_two_sum(s.x, s.y + a.y + b.y);
 }
 
-float julia4d(vec3 pos, float time, float seed) {
-    vec4 z = vec4(pos, sin(time * 0.1) * 0.5);
-    vec4 c = vec4(
-        sin(seed * 0.001) * 0.7,
-        cos(seed * 0.0013) * 0.5,
-        sin(time * 0.1 + seed * 0.002) * 0.3,
-        cos(time * 0.07 + seed * 0.0017) * 0.4
-    );
+vec2 df_mul(vec2 a, vec2 b) {
+    vec2 p = two_prod(a.x, b.x);
+    return quick_two_sum(p.x, p.y + a.x * b.y + a.y * b.x);
+}
 
-    for(int i = 0; i < 8; i++) {
-        if(dot(z, z) > 4.0) break;
+// The formulas mirror the FractalGenerator orbits in fractals.rs (with w = 0). Each comes
+// from its generator's to_glsl() and is spliced in here along with formula_distance(),
+// see glsl.rs; conformance.rs holds a Rust port of them to check the two stay in step.
+// {formulas}
 
-        float x = z.x * z.x - z.y * z.y - z.z * z.z - z.w * z.w + c.x;
-        float y = 2.0 * z.x * z.y + c.y;
-        float zz = 2.0 * z.x * z.z + c.z;
-        float w = 2.0 * z.x * z.w + c.w;
-        z = vec4(x, y, zz, w);
-    }
+// Mirrors Modifier::warp and Modifier::surface
+vec3 weather_warp(vec3 p) {
+    return p + sin(3.0 * p.yzx + u_weather_phase) * u_weather.x * 0.08;
+}
+
+// Gradient noise; mirrors noise.rs
+vec3 hash33(vec3 p) {
+    vec3 q = fract(p * vec3(0.1031, 0.1030, 0.0973));
+    q += dot(q, q.yxz + 33.33);
+    return fract((q.xxy + q.yxx) * q.zyx);
+}
+
+float noise_corner(vec3 cell, vec3 f, vec3 offset) {
+    return dot(hash33(cell + offset) * 2.0 - 1.0, f - offset);
+}
+
+float gradient_noise(vec3 p) {
+    vec3 cell = floor(p);
+    vec3 f = p - cell;
+    vec3 u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    return mix(
+        mix(mix(noise_corner(cell, f, vec3(0.0, 0.0, 0.0)), noise_corner(cell, f, vec3(1.0, 0.0, 0.0)), u.x),
+            mix(noise_corner(cell, f, vec3(0.0, 1.0, 0.0)), noise_corner(cell, f, vec3(1.0, 1.0, 0.0)), u.x), u.y),
+        mix(mix(noise_corner(cell, f, vec3(0.0, 0.0, 1.0)), noise_corner(cell, f, vec3(1.0, 0.0, 1.0)), u.x),
+            mix(noise_corner(cell, f, vec3(0.0, 1.0, 1.0)), noise_corner(cell, f, vec3(1.0, 1.0, 1.0)), u.x), u.y),
+        u.z
+    );
+}
 
-    return length(z.xyz) - 1.0;
+// Mirrors Modifier::roughen (frequency 9, noise slope 3)
+float roughen(float dist, vec3 p) {
+    if(u_roughness <= 0.0) return dist;
+    return (dist + u_roughness * gradient_noise(p * 9.0)) / (1.0 + u_roughness * 9.0 * 3.0);
 }
 
-float kaleidoIFS(vec3 pos, float time, float seed) {
-    vec3 p = pos;
-    float scale = 1.0;
+float weather_surface(float dist, vec3 p) {
+    vec3 wave = sin(17.0 * p + u_weather_phase.zxy);
+    float pits = 0.5 + 0.5 * wave.x * wave.y * wave.z;
+    dist = dist / (1.0 + u_weather.x * 0.08 * 3.0) + u_weather.y * 0.02 * pits - u_weather.z * 0.03;
+    return roughen(dist, p);
+}
+
+// Distance in view units for a view-space position; the camera sits around u_center_*
+// and everything is scaled by u_zoom
+float scene(vec3 local) {
+    g_trap = 1e10;
+    g_iterations = 0.0;
+
+    vec3 offset = local / u_zoom;
+    vec3 pos = u_center_hi + (u_center_lo + offset);
+    float dist = 0.0;
+
+    // Only the Julia orbit is pure add/multiply, so it is the one formula with a
+    // dual-float path; the others keep f32 around the recentered camera
+    if(u_dual_float && u_fractal_type == 1) {
+        dist = julia4d_df(
+            df_add(vec2(u_center_hi.x, u_center_lo.x), vec2(offset.x, 0.0)),
+            df_add(vec2(u_center_hi.y, u_center_lo.y), vec2(offset.y, 0.0)),
+            df_add(vec2(u_center_hi.z, u_center_lo.z), vec2(offset.z, 0.0)),
+            u_time
+        );
+    } else {
+        dist = formula_distance(weather_warp(pos), u_time);
+    }
+    dist = weather_surface(dist, pos);
+
+    // Intersect with the half-space behind the slice plane
+    if(u_slice_enabled) {
+        dist = max(dist, dot(pos, u_slice_normal) - u_slice_offset);
+    }
 
-    for(int i = 0; i < 5; i++) {
-        float angle = time * 0.1 + float(i) * 0.5 + seed * 0.01;
-        vec3 n = normalize(vec3(cos(angle), sin(angle), sin(angle * 1.3)));
+    return dist * u_zoom;
+}
 
-        float d = dot(p, n);
-        if(d < 0.0) p -= 2.0 * d * n;
+vec3 estimate_normal(vec3 pos) {
+    vec2 e = vec2(0.002, 0.0);
+    return normalize(vec3(
+        scene(pos + e.xyy) - scene(pos - e.xyy),
+        scene(pos + e.yxy) - scene(pos - e.yxy),
+        scene(pos + e.yyx) - scene(pos - e.yyx)
+    ));
+}
 
-        p = clamp(p, -1.0, 1.0) * 2.0 - p;
+float star_hash(vec3 p) {
+    return fract(sin(dot(p, vec3(12.9898, 78.233, 45.164))) * 43758.5453);
+}
 
-        float r2 = dot(p, p);
-        if(r2 < 0.25) {
-            p *= 4.0;
-            scale *= 4.0;
-        } else if(r2 < 1.0) {
-            p /= r2;
-            scale /= r2;
+vec3 environment(vec3 dir) {
+    float h = dir.y * 0.5 + 0.5;
+
+    // Piecewise-linear walk over the sorted gradient stops
+    vec3 env = u_env_colors[0];
+    for(int i = 1; i < 8; i++) {
+        if(i >= u_env_stop_count) break;
+        float p0 = u_env_positions[i - 1];
+        float p1 = u_env_positions[i];
+        if(h >= p0) {
+            env = mix(u_env_colors[i - 1], u_env_colors[i], clamp((h - p0) / max(p1 - p0, 0.0001), 0.0, 1.0));
         }
+    }
 
-        float s = 1.6 + sin(time * 0.05 + float(i) * 0.1) * 0.2;
-        p = p * s;
-        scale *= s;
+    if(u_env_mode == 1) {
+        vec3 cell = floor(dir * 200.0);
+        float star = step(1.0 - u_env_density, star_hash(cell));
+        float twinkle = 0.6 + 0.4 * sin(u_time * 2.0 + star_hash(cell + 1.0) * 6.28);
+        env += vec3(star * twinkle);
     }
 
-    return (length(p) - 0.5) / abs(scale);
+    // HDR stops are tone-mapped back into displayable range
+    return env / (1.0 + env);
 }
 
 void main() {
-    vec2 resolution = vec2(640.0, 480.0);
-    vec2 uv = (gl_FragCoord.xy - 0.5 * resolution) / min(resolution.x, resolution.y);
+    vec2 uv = (gl_FragCoord.xy + u_tile.xy - 0.5 * u_resolution) / min(u_resolution.x, u_resolution.y);
 
     vec3 ray_origin = vec3(uv * 2.5, -4.0);
     vec3 ray_dir = normalize(vec3(uv * 0.6, 1.0));
@@ -484,36 +3521,83 @@ void main() {
     vec3 color = vec3(0.0);
     float seed = float(u_seed);
     int steps = 0;
+    bool hit = false;
+
+    // Foveation: fewer steps and a looser hit threshold away from screen center
+    float eccentricity = clamp(length(uv) / 0.7, 0.0, 1.0);
+    float quality = 1.0 - u_foveation * eccentricity * eccentricity;
+    int max_steps = int(80.0 * mix(0.35, 1.0, quality));
+    float hit_epsilon = 0.002 / max(quality, 0.25);
 
     for(int i = 0; i < 80; i++) {
-        vec3 pos = ray_origin + ray_dir * t;
-        float dist = 0.0;
+        if(i >= max_steps) break;
 
-        if(u_fractal_type == 0) {
-            dist = mandelbulb(pos, u_time, seed);
-        } else if(u_fractal_type == 1) {
-            dist = julia4d(pos, u_time, seed);
-        } else {
-            dist = kaleidoIFS(pos, u_time, seed);
-        }
+        vec3 pos = ray_origin + ray_dir * t;
+        float dist = scene(pos);
 
-        if(dist < 0.002) {
+        if(dist < hit_epsilon) {
+            // Hue follows the smooth orbit count of this hit; the march step count only
+            // shades, so neither bands
             float glow = float(steps) / 80.0;
+            float orbit = g_iterations / 16.0;
 
             vec3 baseColor = vec3(0.5);
             if(u_fractal_type == 0) {
-                float hue = sin(glow * 3.14 + u_time * 0.2 + seed * 0.01) * 0.3 + 0.1;
-                baseColor = hsv2rgb(vec3(hue, 0.8, 0.9));
+                float hue = sin(orbit * 3.14 + u_time * 0.2 + seed * 0.01) * 0.3 + 0.1;
+                baseColor = palette_shade(hue, 0.8, 0.9);
             } else if(u_fractal_type == 1) {
-                float hue = cos(glow * 2.0 + u_time * 0.3) * 0.3 + 0.6;
-                baseColor = hsv2rgb(vec3(hue, 0.7, 0.8));
+                float hue = cos(orbit * 2.0 + u_time * 0.3) * 0.3 + 0.6;
+                baseColor = palette_shade(hue, 0.7, 0.8);
+            } else if(u_fractal_type == 2) {
+                float hue = fract(orbit * 2.0 + u_time * 0.1 + length(pos) * 0.1);
+                baseColor = palette_shade(hue, 0.9, 0.9);
+            } else if(u_fractal_type == 3) {
+                float hue = fract(orbit * 1.5 + u_time * 0.05 + length(pos) * 0.2);
+                baseColor = palette_shade(hue, 0.75, 0.85);
+            } else if(u_fractal_type == 4) {
+                float bands = sin(pos.x * 2.0) + sin(pos.y * 2.0) + sin(pos.z * 2.0);
+                float hue = fract(bands * 0.1 + orbit * 0.5 + u_time * 0.04);
+                baseColor = palette_shade(hue, 0.7, 0.9);
+            } else if(u_fractal_type == 5) {
+                float hue = fract(length(pos) * 0.3 + orbit * 0.4 + u_time * 0.03);
+                baseColor = palette_shade(hue, 0.65, 0.9);
             } else {
-                float hue = fract(glow * 2.0 + u_time * 0.1 + length(pos) * 0.1);
-                baseColor = hsv2rgb(vec3(hue, 0.9, 0.9));
+                float hue = fract(orbit * 2.0 + length(pos) * 0.15 + u_time * 0.05);
+                baseColor = palette_shade(hue, 0.8, 0.9);
+            }
+
+            if(u_coloring_mode == 4) {
+                baseColor = palette_shade(equalize(g_iterations), 0.8, 0.9);
+            } else if(u_coloring_mode != 0) {
+                baseColor = trap_color(g_trap);
             }
 
             float lighting = 0.7 + 0.3 * sin(glow * 10.0);
             color = baseColor * lighting * (1.0 - glow * 0.5);
+
+            // Bass brightens the surface, highs light up the glow near the orbit traps
+            float bass = 0.5 * (u_audio_bands[0] + u_audio_bands[1]);
+            color *= 1.0 + 0.5 * bass;
+            color += baseColor * glow * u_audio_bands[6];
+
+            // The glow also sings along: louder voices brighten it, tinted by their pitch
+            vec2 voices = voice_mix();
+            color += palette_shade(voices.x, 0.6, 1.0) * glow * voices.y * 0.5;
+
+            // And throbs with the heartbeat
+            color += baseColor * glow * u_pulse * 0.4;
+
+            // In a golden hour the glow turns warm and the surface catches it
+            color += GOLDEN_AURA * (glow * 0.6 + 0.1) * u_golden;
+
+            // While resonating with others the palette ripples out across the surface
+            float ripple = 0.5 + 0.5 * sin(length(pos) * 12.0 - u_time * 3.0);
+            color += palette_shade(fract(glow + u_time * 0.1), 1.0, 1.0) * ripple * (glow + 0.2) * 0.6 * u_resonance;
+
+            // Environment-tinted reflection
+            vec3 reflected = environment(reflect(ray_dir, estimate_normal(pos)));
+            color = mix(color, color * 0.5 + reflected, u_env_reflectivity);
+            hit = true;
             break;
         }
 
@@ -522,9 +3606,141 @@ void main() {
         if(t > 15.0) break;
     }
 
-    if(length(color) < 0.01) {
-        float bg = length(uv) * 0.1;
-        color = vec3(bg * 0.05, bg * 0.1, bg * 0.2);
+    if(!hit) {
+        color = environment(ray_dir);
+        // With a warm halo around the fractal in a golden hour
+        color += GOLDEN_AURA * u_golden * 0.25 * exp(-4.0 * length(ray_dir.xy));
+        // And in a palette-colored one while resonating
+        color += palette_shade(fract(u_time * 0.05), 1.0, 1.0) * u_resonance * 0.3 * exp(-3.0 * length(ray_dir.xy));
+    }
+
+    // Normalized hit distance for the depth-of-field pass
+    float depth = u_write_depth ? clamp(t / 15.0, 0.0, 1.0) : 1.0;
+    gl_FragColor = vec4(color, depth);
+}
+"#;
+
+// Escape-time plane renderer for RenderMode::Plane: one orbit per pixel, no raymarching
+const FRAGMENT_SHADER_2D: &str = r#"
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+
+uniform float u_time;
+uniform vec2 u_resolution;
+uniform vec4 u_tile; // xy: pixel offset of this draw within u_resolution
+uniform vec4 u_fractal_params[2];
+uniform mat4 u_transform;
+uniform int u_coloring_mode;
+
+// Microphone band energies (see audio_input.rs), lowest first
+uniform float u_audio_bands[8];
+
+// What the audio engine is playing (see AudioEngine::voice_state), loudest voice first:
+// x is its pitch (0 at 80Hz to 1 at 2kHz, logarithmic), y its level 0..1
+uniform vec2 u_audio_state[8];
+
+// The rhythm's beat (see Sequencer::pulse): jumps to the hit's accent and decays
+uniform float u_pulse;
+
+// The golden-hour aura (see golden.rs), 0..1, and its color
+uniform float u_golden;
+const vec3 GOLDEN_AURA = vec3(1.0, 0.78, 0.36);
+
+// The resonance mode (see resonance.rs), 0..1: the palette shimmers through the surface
+uniform float u_resonance;
+
+// Camera zoom (see zoom.rs); only x and y of the center are used
+uniform float u_zoom;
+uniform vec3 u_center_hi;
+uniform vec3 u_center_lo;
+
+// Surface palette (see palette.rs, arrays sized by MAX_PALETTE_STOPS)
+uniform int u_palette_count;
+uniform vec3 u_palette_colors[8];
+uniform float u_palette_positions[8];
+
+// Histogram equalization lookup for coloring mode 4 (see histogram.rs)
+uniform sampler2D u_equalize_lut;
+uniform float u_equalize_range;
+
+const int MAX_ITERATIONS_2D = 256;
+const float BAILOUT_2D = 256.0;
+
+// The trap, iteration, palette and voice helpers, shared with FRAGMENT_SHADER; see glsl.rs
+// {helpers}
+
+// u_fractal_params[0] = (Julia c.x, Julia c.y, iterations, 1 for Julia / 0 for Mandelbrot).
+// Returns (distance estimate, smooth iteration count); mirrors Mandelbrot2D::orbit
+vec2 plane_orbit(vec2 p) {
+    bool julia = u_fractal_params[0].w > 0.5;
+    vec2 c = julia ? u_fractal_params[0].xy : p;
+    vec2 z = julia ? p : vec2(0.0);
+    vec2 dz = julia ? vec2(1.0, 0.0) : vec2(0.0);
+    vec2 dz_offset = julia ? vec2(0.0) : vec2(1.0, 0.0);
+    int iterations = int(u_fractal_params[0].z);
+    float n = 0.0;
+
+    for(int i = 0; i < MAX_ITERATIONS_2D; i++) {
+        if(i >= iterations) break;
+        if(dot(z, z) > BAILOUT_2D * BAILOUT_2D) break;
+
+        dz = 2.0 * vec2(z.x * dz.x - z.y * dz.y, z.x * dz.y + z.y * dz.x) + dz_offset;
+        z = vec2(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y) + c;
+        trap_orbit(vec3(z, 0.0));
+        n += 1.0;
+    }
+
+    float r = length(z);
+    if(r <= BAILOUT_2D) {
+        return vec2(0.0, n);
+    }
+    return vec2(0.5 * r * log(r) / length(dz), smooth_iteration(n, r, BAILOUT_2D, 2.0));
+}
+
+void main() {
+    float min_side = min(u_resolution.x, u_resolution.y);
+    vec2 uv = (gl_FragCoord.xy + u_tile.xy - 0.5 * u_resolution) / min_side;
+
+    // Same view scale as the raymarched camera (VIEW_SCALE in zoom.rs), so pan and
+    // zoom behave identically in both modes
+    vec2 p = u_center_hi.xy + (u_center_lo.xy + uv * 2.5 / u_zoom);
+    p = (u_transform * vec4(p, 0.0, 1.0)).xy;
+
+    g_trap = 1e10;
+    vec2 orbit = plane_orbit(p);
+
+    vec3 color = vec3(0.02);
+    if(orbit.x > 0.0) {
+        float hue = u_coloring_mode == 4 ? equalize(orbit.y) : fract(orbit.y * 0.02 + u_time * 0.02);
+        color = palette_shade(hue, 0.8, 0.9);
+
+        // Darken within a couple of pixels of the set so filaments stay visible
+        float pixel = 2.5 / (u_zoom * min_side);
+        color *= mix(0.35, 1.0, smoothstep(0.0, 2.0 * pixel, orbit.x));
+
+        // Bass brightens the bands around the set
+        color *= 1.0 + 0.5 * (u_audio_bands[0] + u_audio_bands[1]) * 0.5;
+
+        // And the voices being played tint the bands toward their pitch
+        vec2 voices = voice_mix();
+        color = mix(color, palette_shade(voices.x, 0.8, 1.0), 0.2 * voices.y);
+
+        // And the bands brighten on each beat of the heartbeat
+        color *= 1.0 + 0.25 * u_pulse;
+
+        // And warm to gold in a golden hour
+        color = mix(color, color * GOLDEN_AURA * 1.4, 0.4 * u_golden);
+
+        // And ripple through the palette while resonating with others
+        float ripple = 0.5 + 0.5 * sin(orbit.y * 0.5 - u_time * 3.0);
+        color = mix(color, palette_shade(fract(hue + 0.5), 1.0, 1.2), 0.45 * ripple * u_resonance);
+    }
+
+    if(u_coloring_mode != 0 && u_coloring_mode != 4) {
+        color = trap_color(g_trap);
     }
 
     gl_FragColor = vec4(color, 1.0);
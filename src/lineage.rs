@@ -0,0 +1,92 @@
+use serde::{Serialize, Deserialize};
+use crate::user::FrozenFractal;
+
+// Each generation from a day's own fractal adds this to a battle score, up to
+// MAX_BONUS_GENERATIONS of them; a nod to the work of breeding, small beside complexity
+const BONUS_PER_GENERATION: f32 = 0.05;
+const MAX_BONUS_GENERATIONS: u32 = 6;
+
+// Ancestors get_lineage follows back before it stops
+const MAX_TREE_DEPTH: usize = 32;
+
+// Where a frozen fractal came from: the fractals it was bred or echoed from, and how many
+// generations it is from a day's own fractal
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Lineage {
+    pub generation: u32, // 0 for a day's own fractal, and everything saved before lineage
+    pub parents: Vec<ParentRef>,
+}
+
+// A parent, told apart as frozen fractals are (FrozenFractal::is); it may have been
+// dropped from the collection since, or never been in it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParentRef {
+    pub seed: u32,
+    pub timestamp: u64,
+    pub generation: u32,
+}
+
+impl Lineage {
+    // A generation on from the latest of `parents`
+    pub fn child_of(parents: &[&FrozenFractal]) -> Self {
+        Lineage {
+            generation: parents.iter().map(|parent| parent.lineage.generation + 1).max().unwrap_or(0),
+            parents: parents.iter()
+                .map(|parent| ParentRef { seed: parent.seed, timestamp: parent.timestamp, generation: parent.lineage.generation })
+                .collect(),
+        }
+    }
+
+    // Whether the generation follows from the parents named, as child_of would have it
+    pub fn adds_up(&self) -> bool {
+        let expected = self.parents.iter().map(|parent| parent.generation.saturating_add(1)).max().unwrap_or(0);
+        self.generation == expected
+    }
+}
+
+// None for a lineage that doesn't add up, as a fractal edited to claim generations it
+// never bred would have
+pub fn battle_bonus(fractal: &FrozenFractal) -> Option<f32> {
+    let lineage = &fractal.lineage;
+    lineage.adds_up().then(|| lineage.generation.min(MAX_BONUS_GENERATIONS) as f32 * BONUS_PER_GENERATION)
+}
+
+// A fractal and its ancestors, as get_lineage returns them
+#[derive(Serialize, Debug)]
+pub struct LineageNode {
+    pub seed: u32,
+    pub timestamp: u64,
+    pub generation: u32,
+    pub known: bool, // Whether it is in the collection; ancestors of one that isn't are unknown
+    pub parents: Vec<LineageNode>,
+}
+
+// `fractal`'s family tree, its parents looked up in `collection`, theirs in turn
+pub fn tree(fractal: &FrozenFractal, collection: &[FrozenFractal]) -> LineageNode {
+    node(fractal, collection, 0)
+}
+
+fn node(fractal: &FrozenFractal, collection: &[FrozenFractal], depth: usize) -> LineageNode {
+    let parents = fractal.lineage.parents.iter()
+        .map(|parent| match collection.iter().find(|kept| kept.is(parent.seed, parent.timestamp)) {
+            // Generations only grow down the tree, but one edited by hand could loop
+            Some(kept) if depth < MAX_TREE_DEPTH => node(kept, collection, depth + 1),
+            kept => LineageNode {
+                seed: parent.seed,
+                timestamp: parent.timestamp,
+                generation: parent.generation,
+                known: kept.is_some(),
+                parents: Vec::new(),
+            },
+        })
+        .collect();
+
+    LineageNode {
+        seed: fractal.seed,
+        timestamp: fractal.timestamp,
+        generation: fractal.lineage.generation,
+        known: true,
+        parents,
+    }
+}
@@ -0,0 +1,336 @@
+use nalgebra::{Rotation3, Vector3, Vector4};
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlBuffer, WebGlProgram};
+use crate::fractals::{create_fractal_from_params, trap_coloring, ColoringMode, FractalGenerator, FractalParams, OrbitStats};
+use crate::palette::Palette;
+
+// Expansion stops growing once a generation would pass this many symbols
+const MAX_SYMBOLS: usize = 60_000;
+
+// Radius the finished plant is scaled to, so it frames like the raymarched fractals
+const FIT_RADIUS: f32 = 1.4;
+
+// Each branch is a little shorter than its parent
+const BRANCH_SHRINK: f32 = 0.85;
+
+// Plant grammars: (axiom, rules). F draws forward, X only drives growth, + - turn,
+// & ^ pitch, \ / roll, [ ] push and pop the turtle
+const GRAMMARS: [(&str, &[(char, &str)]); 4] = [
+    ("F", &[('F', "F[+F][-F][&F][^F]F")]),
+    ("X", &[('X', "F[&+X][&-X][^/X]F\\X"), ('F', "FF")]),
+    ("F", &[('F', "FF-[-F+F+F]+[+F-F-F]/[&F^F]")]),
+    ("X", &[('X', "F[+X]F[-X]&[^X]+X"), ('F', "FF")]),
+];
+
+pub struct Segment {
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+    pub depth: f32, // Branch nesting, 0 at the trunk to 1 at the deepest twig
+}
+
+// Seed-chosen plant grown from a 3D turtle walk. Rendered as lines by LineRenderer;
+// the distance estimate treats every segment as a thin capsule.
+pub struct LSystem {
+    pub rule_seed: u32,
+    pub iterations: i32,
+    pub angle: f32, // Degrees per turn
+    pub time: f32,
+    pub coloring: ColoringMode,
+    pub palette: Palette,
+    segments: Vec<Segment>,
+}
+
+impl LSystem {
+    pub fn new(rule_seed: u32, iterations: i32, angle: f32, time: f32, coloring: ColoringMode, palette: Palette) -> Self {
+        let segments = grow(rule_seed, iterations, angle);
+        LSystem { rule_seed, iterations, angle, time, coloring, palette, segments }
+    }
+
+    // Thickness of the capsules the distance estimate wraps around each segment
+    fn radius(&self) -> f32 {
+        0.01
+    }
+
+    // Returns (closest point, its segment, distance to the segment's axis)
+    fn nearest(&self, p: &Vector3<f32>) -> Option<(Vector3<f32>, &Segment, f32)> {
+        self.segments.iter()
+            .map(|segment| {
+                let point = closest_point(segment, p);
+                (point, segment, (p - point).norm())
+            })
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+    }
+}
+
+impl FractalGenerator for LSystem {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        match self.nearest(&pos.xyz()) {
+            Some((_, _, distance)) => distance - self.radius(),
+            None => pos.xyz().norm(),
+        }
+    }
+
+    fn get_color(&self, _iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        if let Some(color) = trap_coloring(self, pos) {
+            return color;
+        }
+
+        let depth = self.nearest(&pos.xyz()).map(|(_, segment, _)| segment.depth).unwrap_or(0.0);
+        let hue = (depth * 0.6 + self.time * 0.02).fract();
+        let value = 0.9 * (1.0 - (distance * 3.0).min(0.8));
+        self.palette.shade(hue, 0.7, value)
+    }
+
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(&self.get_params().with_iterations(iterations), self.time, self.coloring, self.palette.clone())
+    }
+
+    fn get_params(&self) -> FractalParams {
+        FractalParams::LSystem { rule_seed: self.rule_seed, iterations: self.iterations, angle: self.angle }
+    }
+
+    fn get_coloring_mode(&self) -> ColoringMode { self.coloring }
+
+    fn get_palette(&self) -> &Palette { &self.palette }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.nearest(&pos.xyz())
+            .map(|(point, _, _)| self.coloring.trap_distance(&point))
+            .unwrap_or(f32::MAX)
+    }
+
+    // No orbit here: the "iteration" is how deep in the branching the nearest segment sits
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        let (depth, distance) = self.nearest(&pos.xyz())
+            .map(|(_, segment, distance)| (segment.depth, distance))
+            .unwrap_or((0.0, f32::MAX));
+
+        let smooth_iteration = (depth * self.iterations as f32).min(max_iter as f32);
+        OrbitStats {
+            escape_iteration: smooth_iteration.round() as i32,
+            escaped: false,
+            min_distance: distance,
+            average_magnitude: pos.xyz().norm(),
+            smooth_iteration,
+            ..OrbitStats::default()
+        }
+    }
+}
+
+fn closest_point(segment: &Segment, p: &Vector3<f32>) -> Vector3<f32> {
+    let axis = segment.end - segment.start;
+    let t = ((p - segment.start).dot(&axis) / axis.norm_squared().max(1e-12)).max(0.0).min(1.0);
+    segment.start + axis * t
+}
+
+fn expand(rule_seed: u32, iterations: i32) -> String {
+    let (axiom, rules) = GRAMMARS[(rule_seed % GRAMMARS.len() as u32) as usize];
+    let mut current = axiom.to_string();
+
+    for _ in 0..iterations.max(0) {
+        let mut next = String::with_capacity(current.len() * 4);
+        for symbol in current.chars() {
+            match rules.iter().find(|(from, _)| *from == symbol) {
+                Some((_, to)) => next.push_str(to),
+                None => next.push(symbol),
+            }
+        }
+
+        if next.len() > MAX_SYMBOLS {
+            break;
+        }
+        current = next;
+    }
+
+    current
+}
+
+// Walks the expanded string with a 3D turtle (growing along +y) and fits the result
+// into FIT_RADIUS around the origin
+fn grow(rule_seed: u32, iterations: i32, angle: f32) -> Vec<Segment> {
+    let turn = angle.to_radians();
+    let mut position = Vector3::zeros();
+    let mut orientation = Rotation3::identity();
+    let mut step = 1.0f32;
+    let mut depth = 0usize;
+    let mut stack = Vec::new();
+    let mut segments = Vec::new();
+    let mut max_depth = 1usize;
+
+    for symbol in expand(rule_seed, iterations).chars() {
+        match symbol {
+            'F' => {
+                let end = position + orientation * Vector3::new(0.0, step, 0.0);
+                segments.push(Segment { start: position, end, depth: depth as f32 });
+                position = end;
+            }
+            '+' => orientation *= Rotation3::from_axis_angle(&Vector3::z_axis(), turn),
+            '-' => orientation *= Rotation3::from_axis_angle(&Vector3::z_axis(), -turn),
+            '&' => orientation *= Rotation3::from_axis_angle(&Vector3::x_axis(), turn),
+            '^' => orientation *= Rotation3::from_axis_angle(&Vector3::x_axis(), -turn),
+            '\\' => orientation *= Rotation3::from_axis_angle(&Vector3::y_axis(), turn),
+            '/' => orientation *= Rotation3::from_axis_angle(&Vector3::y_axis(), -turn),
+            '[' => {
+                stack.push((position, orientation, step, depth));
+                step *= BRANCH_SHRINK;
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            ']' => {
+                if let Some(state) = stack.pop() {
+                    (position, orientation, step, depth) = state;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fit(&mut segments, max_depth as f32);
+    segments
+}
+
+fn fit(segments: &mut [Segment], max_depth: f32) {
+    if segments.is_empty() {
+        return;
+    }
+
+    let mut min = Vector3::repeat(f32::MAX);
+    let mut max = Vector3::repeat(f32::MIN);
+    for segment in segments.iter() {
+        min = min.inf(&segment.start).inf(&segment.end);
+        max = max.sup(&segment.start).sup(&segment.end);
+    }
+
+    let center = (min + max) * 0.5;
+    let radius = segments.iter()
+        .map(|s| (s.start - center).norm().max((s.end - center).norm()))
+        .fold(0.0f32, f32::max)
+        .max(1e-6);
+    let scale = FIT_RADIUS / radius;
+
+    for segment in segments.iter_mut() {
+        segment.start = (segment.start - center) * scale;
+        segment.end = (segment.end - center) * scale;
+        segment.depth /= max_depth;
+    }
+}
+
+// Draws an LSystem's segments as GL lines with the same camera as FRAGMENT_SHADER.
+// Geometry and palette colors are baked into one vertex buffer, rebuilt only when the
+// plant or palette changes.
+pub struct LineRenderer {
+    program: WebGlProgram,
+    buffer: WebGlBuffer,
+    vertex_count: i32,
+    cached: Option<(FractalParams, Palette)>,
+}
+
+impl LineRenderer {
+    pub fn new(gl: &GL, program: WebGlProgram) -> Result<Self, JsValue> {
+        let buffer = gl.create_buffer().ok_or("Failed to create line buffer")?;
+        Ok(LineRenderer { program, buffer, vertex_count: 0, cached: None })
+    }
+
+    pub fn program(&self) -> &WebGlProgram {
+        &self.program
+    }
+
+    // Grows and uploads the plant unless it is already in the buffer
+    pub fn prepare(&mut self, gl: &GL, params: &FractalParams, palette: &Palette) {
+        let segments = match (*params, &self.cached) {
+            (_, Some((cached_params, cached_palette))) if cached_params == params && cached_palette == palette => return,
+            (FractalParams::LSystem { rule_seed, iterations, angle }, _) => grow(rule_seed, iterations, angle),
+            _ => Vec::new(),
+        };
+
+        // x, y, z, r, g, b per vertex
+        let mut vertices = Vec::with_capacity(segments.len() * 12);
+        for segment in &segments {
+            let color = palette.sample(segment.depth * 0.6);
+            for point in [segment.start, segment.end] {
+                vertices.extend_from_slice(&[point.x, point.y, point.z, color.x, color.y, color.z]);
+            }
+        }
+
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buffer));
+        let data = js_sys::Float32Array::from(vertices.as_slice());
+        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &data, GL::STATIC_DRAW);
+
+        self.vertex_count = (vertices.len() / 6) as i32;
+        self.cached = Some((*params, palette.clone()));
+    }
+
+    // Expects the program in use with its uniforms set
+    pub fn draw(&self, gl: &GL) {
+        let stride = 6 * 4;
+        let position = gl.get_attrib_location(&self.program, "a_position");
+        let color = gl.get_attrib_location(&self.program, "a_color");
+        if position < 0 || color < 0 {
+            return;
+        }
+
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buffer));
+        gl.enable_vertex_attrib_array(position as u32);
+        gl.vertex_attrib_pointer_with_i32(position as u32, 3, GL::FLOAT, false, stride, 0);
+        gl.enable_vertex_attrib_array(color as u32);
+        gl.vertex_attrib_pointer_with_i32(color as u32, 3, GL::FLOAT, false, stride, 3 * 4);
+
+        gl.draw_arrays(GL::LINES, 0, self.vertex_count);
+
+        // Leave attribute state as the fullscreen passes expect it
+        gl.disable_vertex_attrib_array(position as u32);
+        gl.disable_vertex_attrib_array(color as u32);
+        gl.bind_buffer(GL::ARRAY_BUFFER, None);
+    }
+}
+
+// Projects like the raymarcher's camera: a point p is seen at uv = p.xy / (2.5 + 0.6 * (p.z + 4))
+pub const LINE_VERTEX_SHADER: &str = r#"
+attribute vec3 a_position;
+attribute vec3 a_color;
+
+uniform float u_time;
+uniform vec2 u_resolution;
+uniform vec4 u_tile; // Pixel offset and size of this draw within u_resolution
+uniform mat4 u_transform;
+uniform float u_zoom;
+uniform vec3 u_center_hi;
+uniform vec3 u_center_lo;
+
+varying vec3 v_color;
+varying float v_fog;
+
+void main() {
+    // Slow turntable so the plant reads as 3D
+    float angle = u_time * 0.1;
+    vec3 p = vec3(
+        a_position.x * cos(angle) + a_position.z * sin(angle),
+        a_position.y,
+        -a_position.x * sin(angle) + a_position.z * cos(angle)
+    );
+    p = (u_transform * vec4(p, 1.0)).xyz;
+    p = (p - (u_center_hi + u_center_lo)) * u_zoom;
+
+    float depth = p.z + 4.0;
+    vec2 uv = p.xy / (2.5 + 0.6 * depth);
+    float min_side = min(u_resolution.x, u_resolution.y);
+
+    // Pixel position in the whole output, then clip space of the tile being drawn
+    vec2 pixel = uv * min_side + 0.5 * u_resolution - u_tile.xy;
+    gl_Position = vec4(pixel / u_tile.zw * 2.0 - 1.0, clamp(depth / 15.0, 0.0, 1.0) * 2.0 - 1.0, 1.0);
+    v_color = a_color;
+    v_fog = clamp(depth / 8.0, 0.0, 1.0);
+}
+"#;
+
+pub const LINE_FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+
+varying vec3 v_color;
+varying float v_fog;
+
+void main() {
+    gl_FragColor = vec4(mix(v_color, vec3(0.0, 0.0, 0.02), v_fog * 0.6), 1.0);
+}
+"#;
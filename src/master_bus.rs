@@ -0,0 +1,92 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AnalyserNode, AudioContext, AudioNode, GainNode};
+
+// Loudness the auto-gain steers the mix toward until set_target_loudness changes it, as
+// RMS in dB below full scale
+pub const DEFAULT_TARGET_DB: f32 = -30.0;
+
+// How far the auto-gain may cut or boost; the boost stays modest so a near-silent
+// fractal isn't dragged up into its noise
+const MIN_AUTO_GAIN_DB: f32 = -24.0;
+const MAX_AUTO_GAIN_DB: f32 = 12.0;
+
+// Below this the mix counts as silence and the auto-gain holds still
+const SILENCE_DB: f32 = -70.0;
+
+// Seconds for the auto-gain to close most of the gap to the target; slow enough that it
+// reads as a level, not as pumping
+const AUTO_GAIN_TIME: f32 = 1.5;
+
+// Samples per RMS measurement, about 45ms at 44.1kHz
+const MEASURE_SIZE: u32 = 2048;
+
+// input -> auto-gain -> limiter -> destination, with the level measured after the
+// auto-gain. The limiter is a hard-kneed, fast DynamicsCompressorNode catching the
+// spikes when voices stack up; the auto-gain evens out fractal-to-fractal loudness
+pub struct MasterBus {
+    auto_gain: GainNode,
+    meter: AnalyserNode,
+    samples: Vec<f32>,
+    target_db: f32,
+    gain_db: f32,
+    last_update: f64,
+}
+
+impl MasterBus {
+    pub fn new(context: &AudioContext) -> Result<Self, JsValue> {
+        let auto_gain = context.create_gain()?;
+        let limiter = context.create_dynamics_compressor()?;
+        let meter = context.create_analyser()?;
+
+        limiter.threshold().set_value(-3.0);
+        limiter.knee().set_value(0.0);
+        limiter.ratio().set_value(20.0);
+        limiter.attack().set_value(0.003);
+        limiter.release().set_value(0.25);
+
+        meter.set_fft_size(MEASURE_SIZE);
+        auto_gain.connect_with_audio_node(&limiter)?;
+        auto_gain.connect_with_audio_node(&meter)?;
+        limiter.connect_with_audio_node(&context.destination())?;
+
+        Ok(MasterBus {
+            auto_gain,
+            meter,
+            samples: vec![0.0; MEASURE_SIZE as usize],
+            target_db: DEFAULT_TARGET_DB,
+            gain_db: 0.0,
+            last_update: context.current_time(),
+        })
+    }
+
+    // Where the mix connects
+    pub fn input(&self) -> &AudioNode {
+        &self.auto_gain
+    }
+
+    pub fn set_target_loudness(&mut self, db: f32) {
+        self.target_db = db.max(-60.0).min(-6.0);
+    }
+
+    // Measures the mix and moves the auto-gain toward the target; call once per frame
+    pub fn update(&mut self, context: &AudioContext) {
+        let now = context.current_time();
+        let elapsed = (now - self.last_update).max(0.0) as f32;
+        self.last_update = now;
+
+        self.meter.get_float_time_domain_data(&mut self.samples);
+        let mean_square = self.samples.iter().map(|s| s * s).sum::<f32>() / self.samples.len() as f32;
+        let measured_db = 10.0 * mean_square.max(1e-12).log10();
+        if measured_db < SILENCE_DB {
+            return;
+        }
+
+        // The meter sits after the auto-gain, so the measurement already includes it
+        let error = self.target_db - measured_db;
+        let step = 1.0 - (-elapsed / AUTO_GAIN_TIME).exp();
+        self.gain_db = (self.gain_db + error * step).max(MIN_AUTO_GAIN_DB).min(MAX_AUTO_GAIN_DB);
+
+        let linear = 10f32.powf(self.gain_db / 20.0);
+        let _ = self.auto_gain.gain().set_target_at_time(linear, now, 0.05);
+    }
+}
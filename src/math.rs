@@ -0,0 +1,207 @@
+// Transcendental functions for the distance estimators. By default they are std's, which
+// on wasm is the libm compiled into the module but on native builds is the platform's, and
+// those differ in the last bits between systems. With the `deterministic` feature every
+// call goes through the software versions below instead: they use only +, -, *, / and
+// sqrt, which IEEE 754 rounds identically everywhere, so a fractal's orbits, distances and
+// complexity score come out bit-identical on any device.
+
+// Recorded in FrozenFractal::math_version; bump it whenever a software version changes,
+// since old fractals' scores were computed with the previous one
+pub const MATH_VERSION: u32 = if cfg!(feature = "deterministic") { 1 } else { 0 };
+
+pub fn sin(x: f32) -> f32 {
+    if cfg!(feature = "deterministic") { soft::sin(x) } else { x.sin() }
+}
+
+pub fn cos(x: f32) -> f32 {
+    if cfg!(feature = "deterministic") { soft::cos(x) } else { x.cos() }
+}
+
+pub fn acos(x: f32) -> f32 {
+    if cfg!(feature = "deterministic") { soft::acos(x) } else { x.acos() }
+}
+
+pub fn atan2(y: f32, x: f32) -> f32 {
+    if cfg!(feature = "deterministic") { soft::atan2(y, x) } else { y.atan2(x) }
+}
+
+pub fn ln(x: f32) -> f32 {
+    if cfg!(feature = "deterministic") { soft::ln(x) } else { x.ln() }
+}
+
+pub fn powf(x: f32, y: f32) -> f32 {
+    if cfg!(feature = "deterministic") { soft::powf(x, y) } else { x.powf(y) }
+}
+
+// Evaluated in f64 and rounded once at the end, so the f32 results are within an ulp of
+// the true values for the arguments the formulas see
+pub mod soft {
+    use std::f64::consts::{FRAC_PI_2, LN_2, PI};
+
+    // The low bits of pi/2 that FRAC_PI_2 drops, for the range reduction
+    const FRAC_PI_2_LOW: f64 = 6.123233995736766e-17;
+
+    // Quarter turns in `x` and what is left over, in -pi/4..pi/4
+    fn quadrant(x: f64) -> (i64, f64) {
+        let k = (x / FRAC_PI_2).round();
+        (k as i64, (x - k * FRAC_PI_2) - k * FRAC_PI_2_LOW)
+    }
+
+    // Taylor series, ample on -pi/4..pi/4
+    fn sin_series(r: f64) -> f64 {
+        let r2 = r * r;
+        r * (1.0 + r2 * (-1.0 / 6.0 + r2 * (1.0 / 120.0 + r2 * (-1.0 / 5040.0
+            + r2 * (1.0 / 362880.0 + r2 * (-1.0 / 39916800.0 + r2 / 6227020800.0))))))
+    }
+
+    fn cos_series(r: f64) -> f64 {
+        let r2 = r * r;
+        1.0 + r2 * (-0.5 + r2 * (1.0 / 24.0 + r2 * (-1.0 / 720.0 + r2 * (1.0 / 40320.0
+            + r2 * (-1.0 / 3628800.0 + r2 * (1.0 / 479001600.0 - r2 / 87178291200.0))))))
+    }
+
+    pub fn sin(x: f32) -> f32 {
+        if !x.is_finite() {
+            return f32::NAN;
+        }
+        let (k, r) = quadrant(x as f64);
+        (match k.rem_euclid(4) {
+            0 => sin_series(r),
+            1 => cos_series(r),
+            2 => -sin_series(r),
+            _ => -cos_series(r),
+        }) as f32
+    }
+
+    pub fn cos(x: f32) -> f32 {
+        if !x.is_finite() {
+            return f32::NAN;
+        }
+        let (k, r) = quadrant(x as f64);
+        (match k.rem_euclid(4) {
+            0 => cos_series(r),
+            1 => -sin_series(r),
+            2 => -cos_series(r),
+            _ => sin_series(r),
+        }) as f32
+    }
+
+    // Two argument halvings bring |t| <= 1 under tan(pi/16), where the series is short
+    fn atan(t: f64) -> f64 {
+        if t.abs() > 1.0 {
+            return FRAC_PI_2.copysign(t) - atan(1.0 / t);
+        }
+        let mut t = t;
+        for _ in 0..2 {
+            t /= 1.0 + (1.0 + t * t).sqrt();
+        }
+        let t2 = t * t;
+        let mut sum = 0.0;
+        for n in (0..12).rev() {
+            let sign = if n % 2 == 0 { 1.0 } else { -1.0 };
+            sum = sign / (2 * n + 1) as f64 + t2 * sum;
+        }
+        4.0 * t * sum
+    }
+
+    fn atan2_f64(y: f64, x: f64) -> f64 {
+        if x > 0.0 {
+            atan(y / x)
+        } else if x < 0.0 {
+            atan(y / x) + PI.copysign(y)
+        } else if y != 0.0 {
+            FRAC_PI_2.copysign(y)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn atan2(y: f32, x: f32) -> f32 {
+        if y.is_nan() || x.is_nan() {
+            return f32::NAN;
+        }
+        atan2_f64(y as f64, x as f64) as f32
+    }
+
+    pub fn acos(x: f32) -> f32 {
+        if !(-1.0..=1.0).contains(&x) {
+            return f32::NAN;
+        }
+        let x = x as f64;
+        atan2_f64((1.0 - x * x).sqrt(), x) as f32
+    }
+
+    // Natural log of a positive finite f64: exponent from the bits, mantissa by the
+    // atanh series of (m - 1) / (m + 1) with m in sqrt(1/2)..sqrt(2)
+    fn ln_f64(x: f64) -> f64 {
+        let bits = x.to_bits();
+        let mut exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+        let mut m = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | 0x3ff0_0000_0000_0000);
+        if m > std::f64::consts::SQRT_2 {
+            m *= 0.5;
+            exponent += 1;
+        }
+
+        let s = (m - 1.0) / (m + 1.0);
+        let s2 = s * s;
+        let mut sum = 0.0;
+        for n in (0..9).rev() {
+            sum = 1.0 / (2 * n + 1) as f64 + s2 * sum;
+        }
+        exponent as f64 * LN_2 + 2.0 * s * sum
+    }
+
+    pub fn ln(x: f32) -> f32 {
+        if x.is_nan() || x < 0.0 {
+            f32::NAN
+        } else if x == 0.0 {
+            f32::NEG_INFINITY
+        } else if x.is_infinite() {
+            f32::INFINITY
+        } else {
+            // f32 subnormals are normal as f64, so the bit split above holds for them too
+            ln_f64(x as f64) as f32
+        }
+    }
+
+    // e^x for an x whose result fits in f32 (or under/overflows it cleanly)
+    fn exp_f64(x: f64) -> f64 {
+        if x > 90.0 {
+            return f64::INFINITY;
+        }
+        if x < -110.0 {
+            return 0.0;
+        }
+        let k = (x / LN_2).round();
+        let r = x - k * LN_2;
+        let mut sum = 1.0;
+        for n in (1..=14).rev() {
+            sum = 1.0 + r * sum / n as f64;
+        }
+        sum * f64::from_bits(((k as i64 + 1023) as u64) << 52)
+    }
+
+    pub fn powf(x: f32, y: f32) -> f32 {
+        if y == 0.0 || x == 1.0 {
+            return 1.0;
+        }
+        if x.is_nan() || y.is_nan() {
+            return f32::NAN;
+        }
+        if x == 0.0 {
+            return if y > 0.0 { 0.0 } else { f32::INFINITY };
+        }
+        if x < 0.0 {
+            // Only integer powers of negative numbers are real
+            if y.fract() != 0.0 {
+                return f32::NAN;
+            }
+            let magnitude = powf(-x, y);
+            return if (y * 0.5).fract() != 0.0 { -magnitude } else { magnitude };
+        }
+        if x.is_infinite() {
+            return if y > 0.0 { f32::INFINITY } else { 0.0 };
+        }
+        exp_f64(y as f64 * ln_f64(x as f64)) as f32
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use nalgebra::Vector3;
+use crate::fractals::VoxelGrid;
+
+// The distance estimates only approach zero inside the set rather than going negative,
+// so the exported surface sits this many voxels out from it
+const SURFACE_OFFSET: f32 = 0.5;
+
+// Each lattice cube split into six tetrahedra around its 0-7 diagonal. Corners are
+// numbered x + 2y + 4z; every cube uses the same split, so neighbours share face
+// diagonals and the surface closes without cracks or ambiguous cases.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 3, 2, 7],
+    [0, 2, 6, 7],
+    [0, 6, 4, 7],
+    [0, 4, 5, 7],
+    [0, 5, 1, 7],
+];
+
+// Indexed triangle mesh, triangles wound counter-clockwise seen from outside
+pub struct Mesh {
+    pub vertices: Vec<Vector3<f32>>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    // Cube marching over the grid's cells. Lattice boundary samples count as outside,
+    // so the mesh is always closed and printable.
+    pub fn from_voxels(grid: &VoxelGrid) -> Self {
+        let level = grid.spacing * SURFACE_OFFSET;
+        let n = grid.resolution;
+        let sample = |x: usize, y: usize, z: usize| {
+            let border = x == 0 || y == 0 || z == 0 || x == n - 1 || y == n - 1 || z == n - 1;
+            let value = grid.value(x, y, z) - level;
+            if border { value.max(grid.spacing) } else { value }
+        };
+
+        let mut mesh = Mesh { vertices: Vec::new(), triangles: Vec::new() };
+        let mut edge_vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+        for z in 0..n - 1 {
+            for y in 0..n - 1 {
+                for x in 0..n - 1 {
+                    let corners: [(usize, usize, usize); 8] = std::array::from_fn(|c| {
+                        (x + (c & 1), y + ((c >> 1) & 1), z + ((c >> 2) & 1))
+                    });
+                    let values = corners.map(|(cx, cy, cz)| sample(cx, cy, cz));
+                    if values.iter().all(|v| *v > 0.0) || values.iter().all(|v| *v <= 0.0) {
+                        continue;
+                    }
+
+                    for tetrahedron in TETRAHEDRA {
+                        let mut vertex = |a: usize, b: usize| {
+                            let (ia, ib) = (lattice_index(corners[a], n), lattice_index(corners[b], n));
+                            let key = (ia.min(ib), ia.max(ib));
+                            *edge_vertices.entry(key).or_insert_with(|| {
+                                let t = values[a] / (values[a] - values[b]);
+                                let pa = grid.position(corners[a].0, corners[a].1, corners[a].2);
+                                let pb = grid.position(corners[b].0, corners[b].1, corners[b].2);
+                                mesh.vertices.push(pa + (pb - pa) * t);
+                                (mesh.vertices.len() - 1) as u32
+                            })
+                        };
+
+                        let (inside, outside): (Vec<usize>, Vec<usize>) =
+                            tetrahedron.iter().partition(|&&c| values[c] <= 0.0);
+                        let faces: Vec<[u32; 3]> = match (inside.len(), outside.len()) {
+                            (1, 3) => vec![[
+                                vertex(inside[0], outside[0]),
+                                vertex(inside[0], outside[1]),
+                                vertex(inside[0], outside[2]),
+                            ]],
+                            (3, 1) => vec![[
+                                vertex(inside[0], outside[0]),
+                                vertex(inside[1], outside[0]),
+                                vertex(inside[2], outside[0]),
+                            ]],
+                            (2, 2) => {
+                                let (ac, ad) = (vertex(inside[0], outside[0]), vertex(inside[0], outside[1]));
+                                let (bc, bd) = (vertex(inside[1], outside[0]), vertex(inside[1], outside[1]));
+                                vec![[ac, ad, bd], [ac, bd, bc]]
+                            }
+                            _ => Vec::new(),
+                        };
+
+                        // Wind each face so its normal points from the inside corners to the outside ones
+                        let centroid = |cs: &[usize]| {
+                            cs.iter().map(|&c| grid.position(corners[c].0, corners[c].1, corners[c].2)).sum::<Vector3<f32>>()
+                                / cs.len() as f32
+                        };
+                        let outward = centroid(&outside) - centroid(&inside);
+                        for [a, b, c] in faces {
+                            mesh.push_oriented([a, b, c], &outward);
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    fn push_oriented(&mut self, [a, b, c]: [u32; 3], outward: &Vector3<f32>) {
+        if a == b || b == c || a == c {
+            return;
+        }
+        let normal = self.face_normal(&[a, b, c]);
+        self.triangles.push(if normal.dot(outward) < 0.0 { [a, c, b] } else { [a, b, c] });
+    }
+
+    fn face_normal(&self, &[a, b, c]: &[u32; 3]) -> Vector3<f32> {
+        let (pa, pb, pc) = (self.vertices[a as usize], self.vertices[b as usize], self.vertices[c as usize]);
+        (pb - pa).cross(&(pc - pa))
+    }
+
+    // Wavefront OBJ text
+    pub fn to_obj(&self, name: &str) -> String {
+        let mut obj = format!("# Resonant fractal mesh\no {}\n", name);
+        for v in &self.vertices {
+            obj.push_str(&format!("v {} {} {}\n", v.x, v.y, v.z));
+        }
+        for [a, b, c] in &self.triangles {
+            obj.push_str(&format!("f {} {} {}\n", a + 1, b + 1, c + 1));
+        }
+        obj
+    }
+
+    // Binary STL: 80-byte header, triangle count, then normal, three corners and an
+    // empty attribute word per triangle, all little-endian
+    pub fn to_stl(&self) -> Vec<u8> {
+        let mut stl = Vec::with_capacity(84 + self.triangles.len() * 50);
+        let mut header = [0u8; 80];
+        let title = b"Resonant fractal mesh";
+        header[..title.len()].copy_from_slice(title);
+        stl.extend_from_slice(&header);
+        stl.extend_from_slice(&(self.triangles.len() as u32).to_le_bytes());
+
+        for triangle in &self.triangles {
+            let normal = self.face_normal(triangle).try_normalize(1e-12).unwrap_or_else(Vector3::zeros);
+            let corners = triangle.iter().map(|&i| self.vertices[i as usize]);
+            for v in std::iter::once(normal).chain(corners) {
+                for component in v.iter() {
+                    stl.extend_from_slice(&component.to_le_bytes());
+                }
+            }
+            stl.extend_from_slice(&0u16.to_le_bytes());
+        }
+        stl
+    }
+}
+
+fn lattice_index((x, y, z): (usize, usize, usize), n: usize) -> usize {
+    x + n * (y + n * z)
+}
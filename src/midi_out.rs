@@ -0,0 +1,208 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{MidiAccess, MidiOutput};
+use serde::Serialize;
+
+// Each voice plays on its own channel so it can bend independently, as MPE does: channel
+// 1 (index 0) is left free and voices take channels 2 to 16
+const FIRST_VOICE_CHANNEL: u8 = 1;
+const MAX_VOICES: usize = 15;
+
+// Semitones either way a full pitch bend reaches; set on every voice channel when an
+// output is selected, since synths disagree on the default
+const BEND_RANGE: f32 = 2.0;
+
+const VELOCITY: u8 = 90;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PITCH_BEND: u8 = 0xE0;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MidiPortInfo {
+    pub id: String,
+    pub name: String,
+    pub manufacturer: String,
+}
+
+// What one voice channel is currently playing
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sounding {
+    note: u8,
+    bend: u16, // 14-bit, 8192 is centered
+}
+
+// Sends the fractal's voices to a Web MIDI output: each frequency becomes the nearest
+// note plus a pitch bend for the rest, re-struck only when it drifts to another note
+pub struct MidiOut {
+    // Filled in once the user grants access; the request resolves asynchronously
+    access: Rc<RefCell<Option<MidiAccess>>>,
+    // Selected by id, and looked up again once access arrives if it was chosen before
+    output_id: Option<String>,
+    output: Option<MidiOutput>,
+    voices: Vec<Option<Sounding>>,
+}
+
+impl MidiOut {
+    // Asks for MIDI access; no outputs are listed until the user allows it
+    pub fn open() -> Result<Self, JsValue> {
+        let window = web_sys::window().ok_or("No window available")?;
+        let request = window.navigator().request_midi_access()?;
+
+        let access = Rc::new(RefCell::new(None));
+        let slot = access.clone();
+
+        let on_granted: Closure<dyn FnMut(JsValue)> = Closure::once(move |granted: JsValue| {
+            if let Ok(granted) = granted.dyn_into::<MidiAccess>() {
+                *slot.borrow_mut() = Some(granted);
+            }
+        });
+        let on_refused: Closure<dyn FnMut(JsValue)> = Closure::once(move |error: JsValue| {
+            web_sys::console::warn_2(&JsValue::from_str("MIDI access refused:"), &error);
+        });
+        let _ = request.then2(&on_granted, &on_refused);
+        // As for the microphone request, the callbacks may outlive this MidiOut
+        on_granted.forget();
+        on_refused.forget();
+
+        Ok(MidiOut {
+            access,
+            output_id: None,
+            output: None,
+            voices: Vec::new(),
+        })
+    }
+
+    // Outputs currently connected; empty until access is granted
+    pub fn outputs(&self) -> Vec<MidiPortInfo> {
+        let access = self.access.borrow();
+        let Some(access) = access.as_ref() else {
+            return Vec::new();
+        };
+
+        access.outputs().values().into_iter()
+            .filter_map(|port| port.ok()?.dyn_into::<MidiOutput>().ok())
+            .map(|port| MidiPortInfo {
+                id: port.id(),
+                name: port.name().unwrap_or_default(),
+                manufacturer: port.manufacturer().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    // Switches to the output with this id, or to none for an empty id. The notes playing
+    // on the old output are released first
+    pub fn select(&mut self, output_id: &str) {
+        self.release_all();
+        self.output = None;
+        self.output_id = if output_id.is_empty() { None } else { Some(output_id.to_string()) };
+    }
+
+    // Brings the output in line with `frequencies`; call once per frame
+    pub fn update(&mut self, frequencies: &[f32]) {
+        if self.output.is_none() && !self.connect() {
+            return;
+        }
+
+        let count = frequencies.len().min(MAX_VOICES);
+        if self.voices.len() < count {
+            self.voices.resize(count, None);
+        }
+
+        // Voices past the frequencies given fall silent
+        let targets = frequencies[..count].iter().map(|&frequency| Self::sounding(frequency))
+            .chain(std::iter::repeat(None));
+        for (i, target) in targets.take(self.voices.len()).enumerate() {
+            let current = self.voices[i];
+            if current == target {
+                continue;
+            }
+
+            let channel = FIRST_VOICE_CHANNEL + i as u8;
+            match (current, target) {
+                // Same note, drifted within it: only the bend moves
+                (Some(playing), Some(next)) if playing.note == next.note => {
+                    self.send_bend(channel, next.bend);
+                }
+                (_, next) => {
+                    if let Some(playing) = current {
+                        self.send(&[NOTE_OFF | channel, playing.note, 0]);
+                    }
+                    if let Some(next) = next {
+                        // Bend before striking, so the note doesn't start off pitch
+                        self.send_bend(channel, next.bend);
+                        self.send(&[NOTE_ON | channel, next.note, VELOCITY]);
+                    }
+                }
+            }
+            self.voices[i] = target;
+        }
+    }
+
+    // Nearest MIDI note and the bend making up the difference; None outside the MIDI range
+    fn sounding(frequency: f32) -> Option<Sounding> {
+        if frequency.is_nan() || frequency <= 0.0 {
+            return None;
+        }
+        let pitch = 69.0 + 12.0 * (frequency / 440.0).log2();
+        let note = pitch.round();
+        if !(0.0..=127.0).contains(&note) {
+            return None;
+        }
+        let bend = 8192.0 + (pitch - note) / BEND_RANGE * 8192.0;
+        Some(Sounding { note: note as u8, bend: bend.max(0.0).min(16383.0) as u16 })
+    }
+
+    // Finds the selected output once access has arrived, and sets its bend range
+    fn connect(&mut self) -> bool {
+        let Some(id) = &self.output_id else {
+            return false;
+        };
+        let output = self.access.borrow().as_ref().and_then(|access| access.outputs().get(id));
+        let Some(output) = output else {
+            return false;
+        };
+        self.output = Some(output);
+
+        for i in 0..MAX_VOICES as u8 {
+            let channel = FIRST_VOICE_CHANNEL + i;
+            // RPN 0 (pitch bend sensitivity), then the null RPN so stray data entry
+            // messages can't change it
+            self.send(&[CONTROL_CHANGE | channel, 101, 0]);
+            self.send(&[CONTROL_CHANGE | channel, 100, 0]);
+            self.send(&[CONTROL_CHANGE | channel, 6, BEND_RANGE as u8]);
+            self.send(&[CONTROL_CHANGE | channel, 38, 0]);
+            self.send(&[CONTROL_CHANGE | channel, 101, 127]);
+            self.send(&[CONTROL_CHANGE | channel, 100, 127]);
+        }
+        true
+    }
+
+    fn send_bend(&self, channel: u8, bend: u16) {
+        self.send(&[PITCH_BEND | channel, (bend & 0x7F) as u8, (bend >> 7) as u8]);
+    }
+
+    fn send(&self, message: &[u8]) {
+        if let Some(output) = &self.output {
+            let _ = output.send(&js_sys::Uint8Array::from(message));
+        }
+    }
+
+    fn release_all(&mut self) {
+        for i in 0..self.voices.len() {
+            if let Some(playing) = self.voices[i].take() {
+                self.send(&[NOTE_OFF | (FIRST_VOICE_CHANNEL + i as u8), playing.note, 0]);
+            }
+        }
+    }
+}
+
+// Hanging notes would keep droning on the synth after the fractal has gone quiet
+impl Drop for MidiOut {
+    fn drop(&mut self) {
+        self.release_all();
+    }
+}
@@ -0,0 +1,303 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioNode, AudioParam, ConstantSourceNode, GainNode, OscillatorNode, OscillatorType};
+use serde::{Serialize, Deserialize};
+use crate::rng::SeededRng;
+
+const MODULATION_STREAM: u64 = 0x30D0_0001;
+
+// Tempo the LFOs count beats in until the sequencer has a rhythm
+const DEFAULT_TEMPO: f32 = 72.0;
+
+// Seconds a new patch's routes take to fade in, and an old one's to fade out, so swapping
+// patches doesn't jump the pitch or the filter mid-sweep
+const ROUTE_FADE: f64 = 1.0;
+
+// Smoothing time constant, in seconds, of the followers' per-frame readings and of LFO
+// rate changes when the tempo moves
+const FOLLOWER_SMOOTHING: f64 = 0.03;
+const TEMPO_SMOOTHING: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModSource {
+    // A low-frequency oscillator, one cycle every `beats` beats of the rhythm
+    Lfo,
+    // Follows the rhythm's hits as the visuals see them (see Sequencer::pulse)
+    Pulse,
+    // Follows how loud the voices are playing, 0..1
+    Voices,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModDestination {
+    // Every voice's detune; depth in semitones
+    Pitch,
+    // The effect chain's low-pass filter; depth in octaves
+    Cutoff,
+    // The voices' level together; depth as a fraction of full level
+    Gain,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+}
+
+// One source driving one destination. An LFO swings `depth` either way; the followers
+// run 0..1, so they push by up to `depth` in one direction (negative to duck)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct Route {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub depth: f32,
+    pub shape: LfoShape, // LFOs only
+    pub beats: f32,      // LFOs only
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Route {
+            source: ModSource::Lfo,
+            destination: ModDestination::Cutoff,
+            depth: 1.0,
+            shape: LfoShape::Sine,
+            beats: 8.0,
+        }
+    }
+}
+
+impl Route {
+    // From the destination's unit into what its AudioParam takes
+    fn scale(&self) -> f32 {
+        match self.destination {
+            ModDestination::Pitch => self.depth * 100.0,
+            ModDestination::Cutoff => self.depth * 1200.0,
+            ModDestination::Gain => self.depth,
+        }
+    }
+
+    // The day's patch: a slow filter sweep always, and a coin toss each for a tremolo, a
+    // pitch drift and a filter kick on every hit of the rhythm
+    pub fn patch_from_seed(seed: u32) -> Vec<Route> {
+        let mut rng = SeededRng::new(seed as u64, MODULATION_STREAM);
+        let mut pick = |choices: &[f32]| choices[rng.below(choices.len() as u32) as usize];
+        let sweep_beats = pick(&[8.0, 16.0, 32.0]);
+        let tremolo_beats = pick(&[1.0, 2.0, 4.0]);
+        let drift_beats = pick(&[4.0, 8.0, 16.0]);
+
+        let mut routes = vec![Route {
+            source: ModSource::Lfo,
+            destination: ModDestination::Cutoff,
+            depth: rng.range_f32(0.5, 1.5),
+            shape: if rng.chance(0.5) { LfoShape::Sine } else { LfoShape::Triangle },
+            beats: sweep_beats,
+        }];
+        if rng.chance(0.5) {
+            routes.push(Route {
+                source: ModSource::Lfo,
+                destination: ModDestination::Gain,
+                depth: rng.range_f32(0.1, 0.3),
+                shape: LfoShape::Triangle,
+                beats: tremolo_beats,
+            });
+        }
+        if rng.chance(0.5) {
+            routes.push(Route {
+                source: ModSource::Lfo,
+                destination: ModDestination::Pitch,
+                depth: rng.range_f32(0.05, 0.2),
+                shape: LfoShape::Sine,
+                beats: drift_beats,
+            });
+        }
+        if rng.chance(0.6) {
+            routes.push(Route {
+                source: ModSource::Pulse,
+                destination: ModDestination::Cutoff,
+                depth: rng.range_f32(0.5, 1.0),
+                ..Route::default()
+            });
+        }
+        routes
+    }
+}
+
+// Where the routes connect: `pitch` is a node feeding every voice's detune, in cents
+pub struct ModTargets<'a> {
+    pub pitch: &'a AudioNode,
+    pub cutoff: AudioParam,
+    pub gain: AudioParam,
+}
+
+// The modulation routes, the day's seed's unless set_routes has replaced them
+pub struct ModulationMatrix {
+    custom: Option<Vec<Route>>,
+    playing: Option<PlayingPatch>,
+    stale: bool, // The routes changed since the playing patch was built
+}
+
+impl ModulationMatrix {
+    pub fn new() -> Self {
+        ModulationMatrix { custom: None, playing: None, stale: false }
+    }
+
+    // A JSON array of routes to use in place of the seed's, or null to go back to them
+    pub fn set_routes(&mut self, routes_json: &str) -> Result<(), JsValue> {
+        self.custom = serde_json::from_str(routes_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid modulation: {}", e)))?;
+        self.stale = true;
+        Ok(())
+    }
+
+    pub fn routes(&self, seed: u32) -> Vec<Route> {
+        self.custom.clone().unwrap_or_else(|| Route::patch_from_seed(seed))
+    }
+
+    pub fn to_json(&self, seed: u32) -> String {
+        serde_json::to_string(&self.routes(seed)).unwrap()
+    }
+
+    // Call every frame: builds the patch for `seed` if it isn't playing, keeps the LFOs
+    // on the rhythm's `tempo`, and feeds the followers their readings
+    pub fn update(&mut self, context: &AudioContext, targets: &ModTargets, seed: u32, tempo: Option<f32>, pulse: f32, voices: f32) -> Result<(), JsValue> {
+        let now = context.current_time();
+        let tempo = tempo.unwrap_or(DEFAULT_TEMPO);
+        let key = if self.custom.is_some() { None } else { Some(seed) };
+
+        if self.stale || self.playing.as_ref().is_none_or(|playing| playing.seed != key) {
+            if let Some(outgoing) = self.playing.take() {
+                outgoing.release(now);
+            }
+            self.stale = false;
+            self.playing = Some(PlayingPatch::new(context, targets, &self.routes(seed), key, tempo)?);
+        }
+
+        let Some(playing) = &mut self.playing else {
+            return Ok(());
+        };
+        for node in &playing.nodes {
+            match node {
+                RouteNode::Lfo { oscillator, beats, .. } if tempo != playing.tempo => {
+                    oscillator.frequency().set_target_at_time(lfo_rate(tempo, *beats), now, TEMPO_SMOOTHING)?;
+                }
+                RouteNode::Follower { source, follows, scale } => {
+                    let reading = if *follows == ModSource::Voices { voices } else { pulse };
+                    source.offset().set_target_at_time(reading * scale, now, FOLLOWER_SMOOTHING)?;
+                }
+                _ => {}
+            }
+        }
+        playing.tempo = tempo;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(playing) = self.playing.take() {
+            for node in &playing.nodes {
+                node.stop();
+            }
+        }
+    }
+}
+
+fn lfo_rate(tempo: f32, beats: f32) -> f32 {
+    tempo / 60.0 / beats.max(0.0625)
+}
+
+enum RouteNode {
+    Lfo { oscillator: OscillatorNode, depth: GainNode, beats: f32 },
+    Follower { source: ConstantSourceNode, follows: ModSource, scale: f32 },
+}
+
+impl RouteNode {
+    fn output(&self) -> &AudioNode {
+        match self {
+            RouteNode::Lfo { depth, .. } => depth,
+            RouteNode::Follower { source, .. } => source,
+        }
+    }
+
+    fn stop(&self) {
+        let _ = match self {
+            RouteNode::Lfo { oscillator, .. } => oscillator.stop(),
+            RouteNode::Follower { source, .. } => source.stop(),
+        };
+    }
+}
+
+// One patch's nodes
+struct PlayingPatch {
+    seed: Option<u32>, // None for custom routes
+    nodes: Vec<RouteNode>,
+    tempo: f32,
+}
+
+impl PlayingPatch {
+    fn new(context: &AudioContext, targets: &ModTargets, routes: &[Route], seed: Option<u32>, tempo: f32) -> Result<Self, JsValue> {
+        let now = context.current_time();
+        let mut nodes = Vec::with_capacity(routes.len());
+
+        for route in routes {
+            let node = match route.source {
+                ModSource::Lfo => {
+                    let oscillator = context.create_oscillator()?;
+                    oscillator.set_type(match route.shape {
+                        LfoShape::Sine => OscillatorType::Sine,
+                        LfoShape::Triangle => OscillatorType::Triangle,
+                        LfoShape::Square => OscillatorType::Square,
+                        LfoShape::Sawtooth => OscillatorType::Sawtooth,
+                    });
+                    oscillator.frequency().set_value(lfo_rate(tempo, route.beats));
+                    let depth = context.create_gain()?;
+                    depth.gain().set_value_at_time(0.0, now)?;
+                    depth.gain().linear_ramp_to_value_at_time(route.scale(), now + ROUTE_FADE)?;
+                    oscillator.connect_with_audio_node(&depth)?;
+                    oscillator.start()?;
+                    RouteNode::Lfo { oscillator, depth, beats: route.beats }
+                }
+                follows => {
+                    let source = context.create_constant_source()?;
+                    source.offset().set_value(0.0);
+                    source.start()?;
+                    RouteNode::Follower { source, follows, scale: route.scale() }
+                }
+            };
+
+            let output = node.output();
+            match route.destination {
+                ModDestination::Pitch => output.connect_with_audio_node(targets.pitch).map(|_| ())?,
+                ModDestination::Cutoff => output.connect_with_audio_param(&targets.cutoff)?,
+                ModDestination::Gain => output.connect_with_audio_param(&targets.gain)?,
+            }
+            nodes.push(node);
+        }
+
+        Ok(PlayingPatch { seed, nodes, tempo })
+    }
+
+    // Fades every route out over ROUTE_FADE and stops it there
+    fn release(self, now: f64) {
+        let end = now + ROUTE_FADE;
+        for node in &self.nodes {
+            let _ = match node {
+                RouteNode::Lfo { oscillator, depth, .. } => {
+                    let _ = depth.gain().cancel_scheduled_values(now);
+                    let _ = depth.gain().set_value_at_time(depth.gain().value(), now);
+                    let _ = depth.gain().linear_ramp_to_value_at_time(0.0, end);
+                    oscillator.stop_with_when(end)
+                }
+                RouteNode::Follower { source, .. } => {
+                    let _ = source.offset().cancel_scheduled_values(now);
+                    let _ = source.offset().set_target_at_time(0.0, now, ROUTE_FADE / 4.0);
+                    source.stop_with_when(end)
+                }
+            };
+        }
+    }
+}
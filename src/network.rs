@@ -1,14 +1,93 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
-use serde::{Serialize, Deserialize};
-use crate::user::FrozenFractal;
+use wasm_bindgen::JsCast;
+use web_sys::{BinaryType, Event, MessageEvent, Storage, WebSocket};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use crate::user::{UserState, FrozenFractal, Curation, FROZEN_VERSION};
+use crate::fractals::FractalParams;
+use crate::registry::{self, FractalType};
+use crate::lineage::Lineage;
+use crate::codec;
+use crate::wire;
+use crate::pose::Pose;
+use crate::battle::{BattleSignal, BattleStage, Challenge, ChallengeState, Challenges};
+use crate::crypto::{self, Envelope, Identity, PublicKey};
+use crate::room::{Room, RoomPoll, RoomStatus, RoomUpdate};
+use crate::echo::{EchoSender, EchoStep, EchoStreams};
+use crate::time_sync::SharedClock;
+use crate::presets;
+
+// The wait, in ms, before reconnecting after a dropped or failed connection; it doubles
+// with each failure in a row, up to MAX_RECONNECT_DELAY
+const RECONNECT_DELAY: f64 = 1000.0;
+const MAX_RECONNECT_DELAY: f64 = 60.0 * 1000.0;
+
+// Ids of messages received lately, to drop one the server sends again because our ack
+// was lost
+const MAX_RECENT_IDS: usize = 256;
+
+// The outbox and inbox, kept so neither is lost to a reload while offline
+const QUEUES_KEY: &str = "resonant_network_queues";
+
+// Messages the inbox keeps; past it the oldest already handed over go first, then the
+// oldest of the rest
+const MAX_INBOX: usize = 200;
+
+// How often, in ms, presence is sent to friends while connected; a friend not heard from
+// in PRESENCE_TIMEOUT has gone, whether or not they said so
+const PRESENCE_INTERVAL: f64 = 60.0 * 1000.0;
+const PRESENCE_TIMEOUT: u64 = 150 * 1000;
+
+// Where share links point
+pub const SHARE_DOMAIN: &str = "https://resonant.app";
+
+// Push services take about 4 KB, less what encrypting it adds; a notification whose share
+// link would take it past this links by seed instead
+const MAX_NOTIFICATION_BYTES: usize = 3000;
+
+// The size, in pixels, the host renders a notification's thumbnail at
+const NOTIFICATION_THUMBNAIL_SIZE: u32 = 192;
+
+// A Web Push payload for the morning's fractal: what a service worker passes to
+// showNotification, the link opening it, and which fractal to render the image from
+#[derive(Serialize)]
+pub struct MorningNotification {
+    pub title: String,
+    pub body: String,
+    pub url: String,
+    pub tag: String, // One per day, so a later push replaces rather than stacks
+    pub thumbnail: NotificationThumbnail,
+    pub timestamp: u64,
+}
+
+// The host renders it with render_thumbnail, or its server with the same seed; images
+// don't fit in a push
+#[derive(Serialize)]
+pub struct NotificationThumbnail {
+    pub seed: u32,
+    pub timestamp: u64,
+    pub size: u32,
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FractalMessage {
     pub sender_id: String,
     pub fractal_data: FrozenFractal,
-    pub transform_echo: Option<Vec<f32>>, // 4x4 matrix if this is a response
+    pub transform_echo: Option<Vec<f32>>, // 4x4 matrix row by row, as transform_matrix, if this is a response
     pub timestamp: u64,
     pub message_type: MessageType,
+    // The friends (user ids) the relay should pass it to; empty for everyone connected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    // A step of a battle challenge, for a Battle message that is one (see battle.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battle: Option<BattleSignal>,
+    // When the sender's animation started, in ms on the shared clock (see time_sync.rs),
+    // for the fractal to animate in phase on both screens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -19,140 +98,774 @@ pub enum MessageType {
     Resonance,   // Special resonance moment
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    Joined, // Just connected
+    Active, // Still here
+    Left,
+}
+
+// That someone is here, sent to their friends every PRESENCE_INTERVAL while they're
+// connected. It isn't acknowledged or kept: one lost is made up by the next
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Presence {
+    pub user_id: String,
+    pub status: PresenceStatus,
+    // Seconds since their last gesture, or None for none today. Not a time, as their
+    // clock may be anything
+    pub idle: Option<u32>,
+    pub recipients: Vec<String>,
+}
+
+// A friend connected now, as their presence says, in this device's time (ms since the epoch)
+#[derive(Clone, Debug)]
+pub struct FriendPresence {
+    pub id: String,
+    pub joined_at: u64,           // When they were first heard from this time
+    pub last_active: Option<u64>, // Their last gesture
+    pub heard_at: u64,
+}
+
+// What a share link opens
+pub enum LinkContent {
+    Fractal(FrozenFractal), // create_share_url's `f`
+    Seed { seed: u32, preset: Option<&'static str>, params: Option<FractalParams> }, // get_share_url's
+    Token(u32), // A temporary share token's seed
+}
+
+// A share link as read, with who shared it and their public key where it says
+pub struct SharedLink {
+    pub content: LinkContent,
+    pub from: Option<String>,
+    pub key: Option<String>,
+}
+
+// A message on its way out, kept until the server acknowledges it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Outgoing {
+    pub id: u64,
+    pub message: FractalMessage,
+    #[serde(skip)]
+    sent: bool, // On the current connection; everything unacknowledged goes again on the next
+}
+
+// A message from someone else, as the server numbered it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Incoming {
+    pub id: u64,
+    pub message: FractalMessage,
+    pub delivered: bool, // Whether poll_inbox has handed it over
+    #[serde(default)]
+    pub received_at: u64, // ms since the epoch, by this device's clock
+}
+
+// The queues, saved whenever they change and read back on the next load
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct NetworkState {
+    #[serde(skip)]
     pub connected_peers: Vec<String>,
-    pub pending_messages: Vec<FractalMessage>,
-    pub last_sync: u64,
+    pub outbox: Vec<Outgoing>,  // Unacknowledged, oldest first
+    pub inbox: Vec<Incoming>,   // From others, oldest first
+    recent_ids: VecDeque<u64>,  // Of the latest received, delivered or not
+    pub last_sync: u64,         // When the server was last heard from, ms since the epoch
+    pub challenges: Challenges, // Battles sent and received
+    battles: Vec<FractalMessage>, // Challenge steps received, for poll_battles
+}
+
+// What goes over the socket, one binary frame apiece (wire.rs); JSON text frames, as the
+// first relays spoke, are still read. The client says hello on connecting, and each
+// message either way is acknowledged by its id; the server relays others' messages with
+// ids of its own, and may say who else is connected. A message for friends whose keys are
+// known goes sealed (see crypto.rs), the relay seeing only its sender and recipients.
+// Room updates go to whoever has joined the room (see room.rs), and the steps of a live
+// echo stream by their recipients (see echo.rs). The client probes the relay's clock with
+// time frames, which the relay answers at once with its own times (see time_sync.rs)
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Frame {
+    Hello { user_id: String },
+    Message { id: u64, message: FractalMessage },
+    Ack { id: u64 },
+    Peers { peers: Vec<String> },
+    Sealed { id: u64, envelope: Envelope },
+    Presence { presence: Presence },
+    Room { update: RoomUpdate },
+    Echo { step: EchoStep },
+    // A probe has neither of the relay's times, and its answer both, in ms since the epoch
+    Time { sent: u64, received: Option<u64>, replied: Option<u64> },
+}
+
+type EventClosure = Closure<dyn FnMut(Event)>;
+
+// What the socket's handlers have seen since the last poll
+#[derive(Default)]
+struct SocketEvents {
+    opened: bool,
+    closed: bool,
+    frames: Vec<Result<Frame, String>>, // Or why one couldn't be read
+}
+
+// An open or opening socket, and the handlers feeding its events to poll
+struct Connection {
+    socket: WebSocket,
+    events: Rc<RefCell<SocketEvents>>,
+    _on_open: EventClosure,
+    _on_close: EventClosure,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl Connection {
+    fn open(url: &str) -> Result<Self, JsValue> {
+        let socket = WebSocket::new(url)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+        let events = Rc::new(RefCell::new(SocketEvents::default()));
+
+        let opened = events.clone();
+        let on_open: EventClosure = Closure::new(move |_: Event| opened.borrow_mut().opened = true);
+        // An error is always followed by a close, which is where reconnecting starts
+        let closed = events.clone();
+        let on_close: EventClosure = Closure::new(move |_: Event| closed.borrow_mut().closed = true);
+        let received = events.clone();
+        let on_message: Closure<dyn FnMut(MessageEvent)> = Closure::new(move |event: MessageEvent| {
+            let data = event.data();
+            let frame = match data.as_string() {
+                Some(text) => serde_json::from_str(&text).map_err(|e| e.to_string()),
+                None => wire::decode(&js_sys::Uint8Array::new(&data).to_vec()),
+            };
+            received.borrow_mut().frames.push(frame);
+        });
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        Ok(Connection { socket, events, _on_open: on_open, _on_close: on_close, _on_message: on_message })
+    }
+
+    fn send(&self, frame: &Frame) -> bool {
+        self.socket.send_with_u8_array(&wire::encode(frame)).is_ok()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+        self.socket.set_onclose(None);
+        self.socket.set_onmessage(None);
+        let _ = self.socket.close();
+    }
 }
 
 pub struct NetworkManager {
     user_id: String,
     connection_state: NetworkState,
+    storage: Option<Storage>, // None where there's no localStorage; the queues then last the session
+    url: Option<String>, // Of the server, once connect has been called
+    connection: Option<Connection>,
+    open: bool,
+    online: bool, // As the browser last said, while there's no connection
+    failures: u32, // Connections in a row that failed or dropped
+    next_connect: f64, // ms since the epoch
+    next_id: u64,
+    presence: BTreeMap<String, FriendPresence>, // Friends heard from on this connection
+    next_presence: f64, // ms since the epoch; 0 to say the user joined
+    reported_active: Option<u64>, // The last gesture presence went out with
+    room: Option<Room>, // The room the user is in, see join_room
+    echo_out: Option<EchoSender>, // The user's live echo, see start_echo_stream
+    echoes: EchoStreams, // Others' live echoes, heard on this connection
+    clock: SharedClock, // The relay's, as estimated
+    animation_epoch: Option<f64>, // When the view's animation started, ms by this device's clock
 }
 
 impl NetworkManager {
+    // Picks up the queues the last session left
     pub fn new(user_id: String) -> Self {
+        let now = js_sys::Date::now();
+        let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+        let connection_state: NetworkState = storage.as_ref()
+            .and_then(|storage| storage.get_item(QUEUES_KEY).ok().flatten())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        // Ids carry on from the clock, so they don't repeat across reloads, and past any
+        // still in the outbox
+        let next_id = connection_state.outbox.iter().map(|pending| pending.id).fold(now as u64, u64::max);
         NetworkManager {
             user_id,
-            connection_state: NetworkState {
-                connected_peers: Vec::new(),
-                pending_messages: Vec::new(),
-                last_sync: js_sys::Date::now() as u64,
-            },
+            connection_state,
+            storage,
+            url: None,
+            connection: None,
+            open: false,
+            online: true,
+            failures: 0,
+            next_connect: 0.0,
+            next_id,
+            presence: BTreeMap::new(),
+            next_presence: 0.0,
+            reported_active: None,
+            room: None,
+            echo_out: None,
+            echoes: EchoStreams::default(),
+            clock: SharedClock::default(),
+            animation_epoch: None,
+        }
+    }
+
+    // Connects to a WebSocket server (ws:// or wss://), and keeps reconnecting, backing
+    // off, whenever the connection fails or drops; messages wait until it's up
+    pub fn connect(&mut self, url: &str) -> Result<(), JsValue> {
+        if !url.starts_with("ws://") && !url.starts_with("wss://") {
+            return Err(JsValue::from_str("Not a WebSocket URL"));
+        }
+        self.disconnect();
+        self.clock.reset();
+        self.url = Some(url.to_string());
+        Ok(())
+    }
+
+    // Unsent messages are kept for the next connect, and the room to join again. Friends
+    // told the user was here, and the room, are told they've left
+    pub fn disconnect(&mut self) {
+        if let (true, Some(connection)) = (self.open, &self.connection) {
+            if self.next_presence > 0.0 {
+                connection.send(&Frame::Presence { presence: self.presence(PresenceStatus::Left, None, Vec::new()) });
+            }
+            self.send_leave(connection);
+            if let Some(echo) = &mut self.echo_out {
+                connection.send(&Frame::Echo { step: echo.end(&self.user_id) });
+            }
+        }
+        self.url = None;
+        self.close();
+        self.failures = 0;
+        self.next_connect = 0.0;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.open
+    }
+
+    // Forgets the user, after erase_all_data: the inbox, outbox, battles and ids seen go,
+    // the room is left and the echo stream ended, and friends heard from are forgotten.
+    // Nothing is saved, the queues' key having gone with the rest; the connection stays,
+    // saying hello as whoever the user is next
+    pub fn reset(&mut self) {
+        self.leave_room();
+        self.stop_echo_stream();
+        self.connection_state = NetworkState::default();
+        self.presence.clear();
+        self.echoes.clear();
+    }
+
+    // Messages are sent as this user from here on; an open connection says hello again
+    pub fn set_user_id(&mut self, user_id: &str) {
+        if self.user_id == user_id {
+            return;
+        }
+        self.user_id = user_id.to_string();
+        if let (true, Some(connection)) = (self.open, &self.connection) {
+            connection.send(&Frame::Hello { user_id: self.user_id.clone() });
+        }
+    }
+
+    // Moves the connection along: connects when it's time, acknowledges the messages
+    // that have arrived and puts them in the inbox, drops acknowledged ones from the
+    // outbox and sends the rest. Messages are sealed and opened with `identity`, and
+    // `key_of` gives a friend's public key where it's known. Live echoes are kept only
+    // from senders `is_friend` accepts. Call once per frame
+    pub fn poll(&mut self, identity: &Identity, key_of: impl Fn(&str) -> Option<PublicKey>, is_friend: impl Fn(&str) -> bool) {
+        let now = js_sys::Date::now();
+        let Some(url) = self.url.clone() else {
+            return;
+        };
+        let Some(connection) = &self.connection else {
+            // Offline, attempts can only fail; back online, the outbox can't go soon enough
+            let online = web_sys::window().is_none_or(|window| window.navigator().on_line());
+            if online && !self.online {
+                self.failures = 0;
+                self.next_connect = 0.0;
+            }
+            self.online = online;
+            if online && now >= self.next_connect {
+                match Connection::open(&url) {
+                    Ok(connection) => self.connection = Some(connection),
+                    Err(e) => {
+                        web_sys::console::warn_2(&JsValue::from_str("WebSocket connection failed:"), &e);
+                        self.back_off(now);
+                    }
+                }
+            }
+            return;
+        };
+
+        let events = std::mem::take(&mut *connection.events.borrow_mut());
+        if events.opened {
+            self.open = true;
+            self.failures = 0;
+            connection.send(&Frame::Hello { user_id: self.user_id.clone() });
+        }
+
+        let mut changed = false;
+        let state = &mut self.connection_state;
+        for frame in events.frames {
+            state.last_sync = now as u64;
+            match frame {
+                Ok(Frame::Message { id, message }) => {
+                    // Saved before it's acknowledged, so the server only forgets it once
+                    // it can't be lost here
+                    changed |= state.receive(id, message, now as u64);
+                    connection.send(&Frame::Ack { id });
+                }
+                Ok(Frame::Sealed { id, envelope }) => {
+                    // One that won't open never will, so it's acknowledged all the same
+                    match open(identity, &self.user_id, &envelope, &key_of) {
+                        Ok(message) => changed |= state.receive(id, message, now as u64),
+                        Err(e) => web_sys::console::warn_2(&JsValue::from_str("Sealed message dropped:"),
+                                                           &JsValue::from_str(&e)),
+                    }
+                    connection.send(&Frame::Ack { id });
+                }
+                Ok(Frame::Ack { id }) => {
+                    state.outbox.retain(|pending| pending.id != id);
+                    changed = true;
+                }
+                Ok(Frame::Peers { peers }) => state.connected_peers = peers,
+                Ok(Frame::Presence { presence }) => receive_presence(&mut self.presence, presence, now as u64, &is_friend),
+                Ok(Frame::Room { update }) => {
+                    if let Some(room) = &mut self.room {
+                        room.receive(update, now as u64);
+                    }
+                }
+                // Echoes aren't sealed, and the relay doesn't say who sent a frame, so a
+                // step's sender is taken at its word; strangers' are dropped all the same
+                Ok(Frame::Echo { step }) if is_friend(&step.sender_id) => self.echoes.receive(step, now as u64),
+                Ok(Frame::Echo { .. }) => {}
+                Ok(Frame::Time { sent, received: Some(received), replied: Some(replied) }) => {
+                    self.clock.receive(sent, received, replied, now as u64);
+                }
+                Ok(Frame::Time { .. }) => {}
+                Ok(Frame::Hello { .. }) => {}
+                Err(e) => web_sys::console::warn_2(&JsValue::from_str("Unreadable network frame:"),
+                                                   &JsValue::from_str(&e)),
+            }
+        }
+
+        if changed {
+            state.trim_inbox();
+            save_queues(&self.storage, state);
+        }
+        if events.closed {
+            self.close();
+            self.back_off(now);
+        } else if self.open {
+            let mut unsealed = Vec::new();
+            for pending in self.connection_state.outbox.iter_mut().filter(|pending| !pending.sent) {
+                match seal(identity, pending, &key_of) {
+                    Ok(frame) => pending.sent = connection.send(&frame),
+                    // Sending checks for keys, so this is a friend whose key went since;
+                    // the message is dropped rather than sent in the clear
+                    Err(e) => {
+                        web_sys::console::warn_2(&JsValue::from_str("Message not sealed, and dropped:"), &e);
+                        unsealed.push(pending.id);
+                    }
+                }
+            }
+            if !unsealed.is_empty() {
+                self.connection_state.outbox.retain(|pending| !unsealed.contains(&pending.id));
+                save_queues(&self.storage, &self.connection_state);
+            }
+            if let Some(sent) = self.clock.probe(now) {
+                connection.send(&Frame::Time { sent, received: None, replied: None });
+            }
+        }
+    }
+
+    // Messages that have arrived since the last call, oldest first; each is handed over
+    // once, however many reloads it waits through first
+    pub fn poll_inbox(&mut self) -> Vec<FractalMessage> {
+        let arrived: Vec<FractalMessage> = self.connection_state.inbox.iter_mut()
+            .filter(|incoming| !incoming.delivered)
+            .map(|incoming| {
+                incoming.delivered = true;
+                incoming.message.clone()
+            })
+            .collect();
+        if !arrived.is_empty() {
+            self.save();
+        }
+        arrived
+    }
+
+    // Tells `friends` the user is here, while connected and `sharing`: on joining, every
+    // PRESENCE_INTERVAL after, and at once when they gesture after a quiet spell, with
+    // how long ago their last gesture (`last_active`, ms since the epoch) was. Turning
+    // sharing off says they've left. Call once per frame, after poll
+    pub fn poll_presence(&mut self, sharing: bool, last_active: Option<u64>, friends: impl FnOnce() -> Vec<String>) {
+        let Some(connection) = self.connection.as_ref().filter(|_| self.open) else {
+            return;
+        };
+        let now = js_sys::Date::now();
+        let announced = self.next_presence > 0.0;
+        if !sharing {
+            if announced {
+                connection.send(&Frame::Presence { presence: self.presence(PresenceStatus::Left, None, friends()) });
+                self.next_presence = 0.0;
+                self.reported_active = None;
+            }
+            return;
+        }
+        let woke = last_active.is_some_and(|at| self.reported_active.is_none_or(|reported| at > reported + PRESENCE_INTERVAL as u64));
+        if announced && now < self.next_presence && !woke {
+            return;
+        }
+        let friends = friends();
+        if friends.is_empty() {
+            return;
+        }
+        let status = if announced { PresenceStatus::Active } else { PresenceStatus::Joined };
+        let idle = last_active.map(|at| ((now as u64).saturating_sub(at) / 1000).min(u32::MAX as u64) as u32);
+        connection.send(&Frame::Presence { presence: self.presence(status, idle, friends) });
+        self.next_presence = now + PRESENCE_INTERVAL;
+        self.reported_active = last_active;
+    }
+
+    fn presence(&self, status: PresenceStatus, idle: Option<u32>, recipients: Vec<String>) -> Presence {
+        Presence { user_id: self.user_id.clone(), status, idle, recipients }
+    }
+
+    // Friends connected now, as their presence says: heard from within PRESENCE_TIMEOUT
+    // and not gone, the most recently active first
+    pub fn get_active_friends(&self) -> Vec<&FriendPresence> {
+        let now = js_sys::Date::now() as u64;
+        let mut active: Vec<&FriendPresence> = self.presence.values()
+            .filter(|friend| now.saturating_sub(friend.heard_at) <= PRESENCE_TIMEOUT)
+            .collect();
+        active.sort_by_key(|friend| std::cmp::Reverse(friend.last_active));
+        active
+    }
+
+    // Joins `room_id`, leaving any room the user is in, with `seed` posed as `pose` in
+    // view; claiming the room makes that its fractal (see Room::new). The room hears of it
+    // once the connection is up
+    pub fn join_room(&mut self, room_id: &str, seed: u32, pose: &Pose, claim: bool) -> Result<(), JsValue> {
+        let room = Room::new(room_id, &self.user_id, seed, pose, claim).map_err(|e| JsValue::from_str(&e))?;
+        self.leave_room();
+        self.room = Some(room);
+        Ok(())
+    }
+
+    // False if not in a room
+    pub fn leave_room(&mut self) -> bool {
+        if let (true, Some(connection)) = (self.open, &self.connection) {
+            self.send_leave(connection);
+        }
+        self.room.take().is_some()
+    }
+
+    fn send_leave(&self, connection: &Connection) {
+        if let Some(room) = self.room.as_ref().filter(|room| room.is_announced()) {
+            connection.send(&Frame::Room { update: room.message(&self.user_id, RoomStatus::Left) });
+        }
+    }
+
+    pub fn get_room(&self) -> Option<&Room> {
+        self.room.as_ref()
+    }
+
+    // Keeps the room and the view in step: the user's gestures since the last call (the
+    // view's `seed` and `pose` against what it showed) go out to the room, and what the
+    // others did comes back to show. None outside a room. Call once per frame, after poll
+    pub fn poll_room(&mut self, seed: u32, pose: &Pose) -> Option<RoomPoll> {
+        let room = self.room.as_mut()?;
+        let now = js_sys::Date::now();
+        room.expire(now as u64);
+        let poll = room.update(&self.user_id, seed, pose);
+        if let Some(connection) = self.connection.as_ref().filter(|_| self.open) {
+            if let Some(update) = room.outgoing(&self.user_id, now) {
+                connection.send(&Frame::Room { update });
+            }
+        }
+        Some(poll)
+    }
+
+    // Streams the view's transform to `recipients` (everyone connected if empty) live,
+    // step by step as it moves (see echo.rs), in place of any stream already going
+    pub fn start_echo_stream(&mut self, recipients: Vec<String>) {
+        self.stop_echo_stream();
+        self.next_id += 1;
+        self.echo_out = Some(EchoSender::new(self.next_id, recipients));
+    }
+
+    // False if no stream was going
+    pub fn stop_echo_stream(&mut self) -> bool {
+        let Some(mut echo) = self.echo_out.take() else {
+            return false;
+        };
+        if let (true, Some(connection)) = (self.open, &self.connection) {
+            connection.send(&Frame::Echo { step: echo.end(&self.user_id) });
+        }
+        true
+    }
+
+    // Sends the next step of the user's live echo, for the view's `pose`, when one is due,
+    // and returns the senders whose echoes have moved since the last call, with where to.
+    // Call once per frame, after poll
+    pub fn poll_echo(&mut self, pose: &Pose) -> Vec<(&str, Pose)> {
+        let now = js_sys::Date::now();
+        if let (Some(echo), Some(connection)) = (&mut self.echo_out, self.connection.as_ref().filter(|_| self.open)) {
+            if let Some(step) = echo.next(&self.user_id, pose, now) {
+                connection.send(&Frame::Echo { step });
+            }
+        }
+        self.echoes.expire(now as u64);
+        self.echoes.take_changed()
+    }
+
+    pub fn get_echo_streams(&self) -> &EchoStreams {
+        &self.echoes
+    }
+
+    // When the view's animation started (its time 0), in ms by this device's clock; the
+    // messages sent after carry it on the shared clock
+    pub fn set_animation_epoch(&mut self, local: f64) {
+        self.animation_epoch = Some(local);
+    }
+
+    fn shared_epoch(&self) -> Option<u64> {
+        self.animation_epoch.map(|local| self.clock.to_shared(local).max(0.0) as u64)
+    }
+
+    pub fn get_clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    fn close(&mut self) {
+        self.connection = None;
+        self.open = false;
+        self.echoes.clear();
+        if let Some(echo) = &mut self.echo_out {
+            echo.restart();
+        }
+        if let Some(room) = &mut self.room {
+            room.disconnected();
+        }
+        self.presence.clear();
+        self.next_presence = 0.0;
+        self.reported_active = None;
+        for pending in &mut self.connection_state.outbox {
+            pending.sent = false;
         }
     }
 
+    fn save(&self) {
+        save_queues(&self.storage, &self.connection_state);
+    }
+
+    // Waits before the next attempt, doubling with each failure in a row; jittered, so a
+    // server coming back isn't met by every client at once
+    fn back_off(&mut self, now: f64) {
+        let delay = (RECONNECT_DELAY * 2f64.powi(self.failures.min(16) as i32)).min(MAX_RECONNECT_DELAY);
+        self.next_connect = now + delay * (0.5 + 0.5 * js_sys::Math::random());
+        self.failures += 1;
+    }
+
+    // Queued to go out with the next poll that finds the connection open, and saved
+    // until the server acknowledges it
+    fn queue(&mut self, message: FractalMessage) {
+        self.next_id += 1;
+        self.connection_state.outbox.push(Outgoing { id: self.next_id, message, sent: false });
+        self.save();
+    }
+
     // Generate shareable URL with embedded fractal data
     pub fn create_share_url(&self, fractal: &FrozenFractal, domain: &str) -> String {
         let encoded_data = self.encode_fractal_for_url(fractal);
         format!("{}?f={}&from={}", domain, encoded_data, self.user_id)
     }
 
+    // The morning notification for `fractal`, as compact JSON: its stats in the body, and
+    // a share link that opens it in the sandbox, or by seed if that would be too long
+    pub fn build_morning_notification(&self, fractal: &FrozenFractal) -> String {
+        let fractal_type = fractal.fractal_type();
+        let mut stats = vec![fractal_type.name().to_string(), format!("complexity {:.1}", fractal.complexity_score)];
+        match fractal.interaction_count {
+            0 => {}
+            1 => stats.push("1 gesture".to_string()),
+            n => stats.push(format!("{} gestures", n)),
+        }
+        if fractal.lineage.generation > 0 {
+            stats.push(format!("generation {}", fractal.lineage.generation));
+        }
+        let day = fractal.timestamp / (24 * 60 * 60 * 1000);
+        let mut notification = MorningNotification {
+            title: "Your morning fractal is ready".to_string(),
+            body: stats.join(" · "),
+            url: self.create_share_url(fractal, SHARE_DOMAIN),
+            tag: format!("morning-{}", day),
+            thumbnail: NotificationThumbnail {
+                seed: fractal.seed,
+                timestamp: fractal.timestamp,
+                size: NOTIFICATION_THUMBNAIL_SIZE,
+            },
+            timestamp: fractal.timestamp,
+        };
+        let json = serde_json::to_string(&notification).unwrap();
+        if json.len() <= MAX_NOTIFICATION_BYTES {
+            return json;
+        }
+        notification.url = format!("{}?seed={}&user={}", SHARE_DOMAIN, fractal.seed, self.user_id);
+        serde_json::to_string(&notification).unwrap()
+    }
+
     // Decode fractal from URL parameter
     pub fn decode_share_url(&self, url_param: &str) -> Result<FrozenFractal, JsValue> {
         let decoded = self.decode_fractal_from_url(url_param)?;
         Ok(decoded)
     }
 
-    // Simplified URL encoding for fractal data
+    // The compact JSON, as URL-safe base64 and deflated if that's shorter
     fn encode_fractal_for_url(&self, fractal: &FrozenFractal) -> String {
-        // Create compact representation
-        let compact = CompactFractal {
-            seed: fractal.seed,
-            fractal_type: match fractal.fractal_type.as_str() {
-                "Mandelbulb" => 0,
-                "Julia4D" => 1,
-                "KaleidoIFS" => 2,
-                _ => 0,
-            },
-            complexity: (fractal.complexity_score * 100.0) as u16,
-            interactions: fractal.interaction_count.min(255) as u8,
-        };
-
-        // Convert to base64
-        let json = serde_json::to_string(&compact).unwrap();
-        base64_encode(&json)
+        codec::encode(&encode_compact(fractal))
     }
 
     fn decode_fractal_from_url(&self, encoded: &str) -> Result<FrozenFractal, JsValue> {
-        let json = base64_decode(encoded)
+        let json = payload_decode(encoded)
             .map_err(|_| JsValue::from_str("Invalid fractal data"))?;
 
-        let compact: CompactFractal = serde_json::from_str(&json)
-            .map_err(|_| JsValue::from_str("Invalid fractal format"))?;
-
-        Ok(FrozenFractal {
-            seed: compact.seed,
-            fractal_type: match compact.fractal_type {
-                0 => "Mandelbulb".to_string(),
-                1 => "Julia4D".to_string(),
-                2 => "KaleidoIFS".to_string(),
-                _ => "Mandelbulb".to_string(),
-            },
-            transform_matrix: vec![1.0; 16], // Default identity matrix
-            complexity_score: compact.complexity as f32 / 100.0,
-            timestamp: js_sys::Date::now() as u64,
-            interaction_count: compact.interactions as u32,
-        })
+        decode_compact(&json, js_sys::Date::now() as u64)
+            .map_err(|e| JsValue::from_str(&format!("Invalid fractal format: {}", e)))
     }
 
-    // Send fractal to friends (placeholder for future P2P)
-    pub fn broadcast_morning_fractal(&mut self, fractal: &FrozenFractal) -> Result<(), JsValue> {
+    // Sends the day's fractal to `recipients`, or everyone connected if there are none
+    pub fn broadcast_morning_fractal(&mut self, fractal: &FrozenFractal, recipients: Vec<String>) -> Result<(), JsValue> {
         let message = FractalMessage {
             sender_id: self.user_id.clone(),
             fractal_data: fractal.clone(),
             transform_echo: None,
             timestamp: js_sys::Date::now() as u64,
             message_type: MessageType::Morning,
+            recipients,
+            battle: None,
+            epoch: self.shared_epoch(),
         };
 
-        // For now, just store in pending messages
-        // In future: send via WebRTC or WebSocket
-        self.connection_state.pending_messages.push(message);
-
-        // Log to console for debugging
-        web_sys::console::log_1(&JsValue::from_str(&format!(
-            "Broadcasting morning fractal: seed={}", fractal.seed
-        )));
-
+        self.queue(message);
         Ok(())
     }
 
-    // Respond to received fractal with transform
+    // Respond to received fractal with transform, to `recipients` as for a morning fractal
     pub fn send_echo_response(&mut self, original_fractal: &FrozenFractal,
-                             transform_matrix: &[f32]) -> Result<(), JsValue> {
+                             transform_matrix: &[f32], recipients: Vec<String>) -> Result<(), JsValue> {
         let message = FractalMessage {
             sender_id: self.user_id.clone(),
             fractal_data: original_fractal.clone(),
             transform_echo: Some(transform_matrix.to_vec()),
             timestamp: js_sys::Date::now() as u64,
             message_type: MessageType::Echo,
+            recipients,
+            battle: None,
+            epoch: self.shared_epoch(),
         };
 
-        self.connection_state.pending_messages.push(message);
+        self.queue(message);
+        Ok(())
+    }
 
-        web_sys::console::log_1(&JsValue::from_str(&format!(
-            "Sending echo response to fractal: seed={}", original_fractal.seed
-        )));
+    // Challenges `opponent` to a battle with `fractal`, the user's at `rating`; returns the
+    // challenge's id
+    pub fn send_battle_challenge(&mut self, fractal: &FrozenFractal, opponent: &str, rating: f32) -> String {
+        self.next_id += 1;
+        let id = format!("{}:{}", self.user_id, self.next_id);
+        self.connection_state.challenges.challenge(id.clone(), opponent, fractal.clone(), js_sys::Date::now() as u64);
+        self.send_battle(&id, BattleStage::Challenge { rating }, fractal, opponent);
+        id
+    }
 
-        Ok(())
+    // Fights a received challenge with `fractal`, the user's at `rating`, and sends it
+    // back with the scores as worked out here; one whose fractal doesn't check out is
+    // declined instead, so the challenger isn't left waiting
+    pub fn accept_battle_challenge(&mut self, id: &str, fractal: &FrozenFractal, rating: f32) -> Result<Challenge, JsValue> {
+        let challenge = self.connection_state.challenges.accept(id, fractal.clone(), js_sys::Date::now() as u64)?.clone();
+        let stage = match challenge.scores {
+            Some(scores) => BattleStage::Accept { rating, scores },
+            None => BattleStage::Decline,
+        };
+        self.send_battle(id, stage, fractal, &challenge.opponent_id);
+        Ok(challenge)
     }
 
-    // Check for resonance moments (when multiple people are active)
-    pub fn check_resonance_window(&self) -> bool {
+    pub fn decline_battle_challenge(&mut self, id: &str) -> Result<Challenge, JsValue> {
+        let challenge = self.connection_state.challenges.decline(id, js_sys::Date::now() as u64)?.clone();
+        if let Some(theirs) = &challenge.theirs {
+            self.send_battle(id, BattleStage::Decline, theirs, &challenge.opponent_id);
+        }
+        Ok(challenge)
+    }
+
+    // Moves the challenges on with the steps received since the last call, and expires
+    // stale ones, returning each that moved. A challenge from anyone `is_friend` turns
+    // down is dropped. Call once per frame, after poll
+    pub fn poll_battles(&mut self, is_friend: impl Fn(&str) -> bool) -> Vec<Challenge> {
         let now = js_sys::Date::now() as u64;
-        let time_window = 300_000; // 5 minutes in milliseconds
-
-        // Check if there are recent messages from multiple users
-        let recent_senders: std::collections::HashSet<String> = self.connection_state
-            .pending_messages
-            .iter()
-            .filter(|msg| now - msg.timestamp < time_window)
-            .map(|msg| msg.sender_id.clone())
-            .collect();
+        let received = std::mem::take(&mut self.connection_state.battles);
+        let mut moved = Vec::new();
+        for message in received {
+            let Some(signal) = message.battle else {
+                continue;
+            };
+            if matches!(signal.stage, BattleStage::Challenge { .. }) && !is_friend(&message.sender_id) {
+                continue;
+            }
+            let challenges = &mut self.connection_state.challenges;
+            let Some(challenge) = challenges.receive(&message.sender_id, signal, message.fractal_data, now).cloned() else {
+                continue;
+            };
+            // The challenger answers an accept with its own result, agreeing or not, for
+            // the opponent to check in turn
+            let answered = matches!(challenge.state, ChallengeState::Verified | ChallengeState::Disputed);
+            if challenge.challenger && answered {
+                if let (Some(scores), Some(own)) = (challenge.scores, &challenge.own) {
+                    self.send_battle(&challenge.id, BattleStage::Result { scores }, own, &challenge.opponent_id);
+                }
+            }
+            moved.push(challenge);
+        }
+        moved.extend(self.connection_state.challenges.expire(now));
+        if !moved.is_empty() {
+            self.save();
+        }
+        moved
+    }
+
+    pub fn get_challenges(&self) -> &Challenges {
+        &self.connection_state.challenges
+    }
+
+    fn send_battle(&mut self, id: &str, stage: BattleStage, fractal: &FrozenFractal, opponent: &str) {
+        self.queue(FractalMessage {
+            sender_id: self.user_id.clone(),
+            fractal_data: fractal.clone(),
+            transform_echo: None,
+            timestamp: js_sys::Date::now() as u64,
+            message_type: MessageType::Battle,
+            recipients: vec![opponent.to_string()],
+            battle: Some(BattleSignal { challenge_id: id.to_string(), stage }),
+            epoch: None,
+        });
+    }
 
-        recent_senders.len() >= 2
+    // Which friends (as `is_friend` says) have sent something since `since` and when it
+    // arrived, for Resonance::detect; arrival rather than the sender's timestamp, as their
+    // clock may be anything. Friends present count from their last gesture. Nobody else's
+    // activity counts, so strangers, or a relay client making up ids, can't set one off
+    pub fn recent_activity(&self, since: u64, is_friend: impl Fn(&str) -> bool) -> Vec<(&str, u64)> {
+        let present = self.get_active_friends().into_iter()
+            .filter(|friend| is_friend(&friend.id))
+            .filter_map(|friend| Some((friend.id.as_str(), friend.last_active.filter(|&at| at >= since)?)));
+        let mut activity = self.connection_state.recent_messages(since, &is_friend);
+        activity.extend(present);
+        activity
     }
 
     // Generate time-limited share token
@@ -167,12 +880,12 @@ impl NetworkManager {
         };
 
         let json = serde_json::to_string(&token_data).unwrap();
-        base64_encode(&json)
+        codec::encode(&json)
     }
 
     // Validate and use share token
     pub fn validate_share_token(&self, token: &str) -> Result<u32, JsValue> {
-        let json = base64_decode(token)
+        let json = payload_decode(token)
             .map_err(|_| JsValue::from_str("Invalid token"))?;
 
         let token_data: ShareToken = serde_json::from_str(&json)
@@ -186,22 +899,253 @@ impl NetworkManager {
         Ok(token_data.fractal_seed)
     }
 
-    pub fn get_pending_messages(&self) -> &[FractalMessage] {
-        &self.connection_state.pending_messages
+    // Reads a share link, or its query alone (`?f=...`), checking everything it carries;
+    // None if it carries no fractal at all. A link with several takes `f`, then `token`,
+    // then `seed`
+    pub fn parse_share_link(&self, url: &str) -> Result<Option<SharedLink>, JsValue> {
+        let params = query_params(url)?;
+        let get = |name: &str| params.iter().find(|(key, value)| key == name && !value.is_empty()).map(|(_, value)| value.as_str());
+        let content = if let Some(encoded) = get("f") {
+            LinkContent::Fractal(self.decode_share_url(encoded)?)
+        } else if let Some(token) = get("token") {
+            LinkContent::Token(self.validate_share_token(token)?)
+        } else if let Some(seed) = get("seed") {
+            let seed = seed.parse().map_err(|_| JsValue::from_str(&format!("Invalid seed: {}", seed)))?;
+            let preset = get("preset").map(|name| {
+                presets::by_name(name).map(|preset| preset.name)
+                    .ok_or_else(|| JsValue::from_str(&format!("Unknown preset: {}", name)))
+            }).transpose()?;
+            let params = get("params").map(|encoded| {
+                let json = codec::decode(encoded).map_err(|_| JsValue::from_str("Invalid fractal params"))?;
+                FractalParams::from_json(&json)
+            }).transpose()?;
+            LinkContent::Seed { seed, preset, params }
+        } else {
+            return Ok(None);
+        };
+        if get("key").is_some_and(|key| crypto::parse_public_key(key).is_none()) {
+            return Err(JsValue::from_str("The link's key isn't a public key"));
+        }
+        Ok(Some(SharedLink {
+            content,
+            from: get("from").or(get("user")).map(str::to_string),
+            key: get("key").map(str::to_string),
+        }))
     }
 
     pub fn clear_old_messages(&mut self, max_age_hours: u32) {
         let cutoff = js_sys::Date::now() as u64 - (max_age_hours as u64 * 3600 * 1000);
-        self.connection_state.pending_messages.retain(|msg| msg.timestamp > cutoff);
+        self.connection_state.outbox.retain(|pending| pending.message.timestamp > cutoff);
+        self.connection_state.inbox.retain(|incoming| incoming.message.timestamp > cutoff);
+        self.save();
+    }
+}
+
+// Takes a presence into `friends`, heard at `now`. Like echoes, presence isn't sealed and
+// its sender is taken at its word, so only friends' is kept: a stranger's, or one naming
+// an id that isn't a friend's, is dropped rather than listed or let remove anyone
+fn receive_presence(friends: &mut BTreeMap<String, FriendPresence>, presence: Presence, now: u64,
+                    is_friend: &impl Fn(&str) -> bool) {
+    if !is_friend(&presence.user_id) {
+        return;
+    }
+    if presence.status == PresenceStatus::Left {
+        friends.remove(&presence.user_id);
+        return;
+    }
+    let last_active = presence.idle.map(|idle| now.saturating_sub(idle as u64 * 1000));
+    let friend = friends.entry(presence.user_id.clone()).or_insert(FriendPresence {
+        id: presence.user_id,
+        joined_at: now,
+        last_active,
+        heard_at: now,
+    });
+    friend.last_active = last_active;
+    friend.heard_at = now;
+}
+
+// The frame `pending` goes in: sealed when it's addressed, and as it is, to everyone
+// connected, when it isn't. One addressed to a friend whose key isn't known is refused
+// rather than sent in the clear
+fn seal(identity: &Identity, pending: &Outgoing, key_of: &impl Fn(&str) -> Option<PublicKey>) -> Result<Frame, JsValue> {
+    let message = &pending.message;
+    let keys = recipient_keys(message, key_of).map_err(|e| JsValue::from_str(&e))?;
+    if keys.is_empty() {
+        return Ok(Frame::Message { id: pending.id, message: message.clone() });
     }
+    let envelope = identity.seal(&message.sender_id, &keys, &wire::encode_message(message))?;
+    Ok(Frame::Sealed { id: pending.id, envelope })
+}
+
+// Each recipient of `message` with the key to seal for them; an error naming the first
+// whose key isn't known
+fn recipient_keys(message: &FractalMessage, key_of: &impl Fn(&str) -> Option<PublicKey>) -> Result<Vec<(String, PublicKey)>, String> {
+    message.recipients.iter()
+        .map(|recipient| {
+            let key = key_of(recipient).ok_or_else(|| format!("No key to seal for {}; befriend them again from a link with one", recipient))?;
+            Ok((recipient.clone(), key))
+        })
+        .collect()
 }
 
+// The message in a sealed frame for `user_id`. A friend's is only opened with the key
+// kept for them; anyone else's with the key it came with, which says no more of who sent
+// it than an unsealed message would
+fn open(identity: &Identity, user_id: &str, envelope: &Envelope,
+        key_of: &impl Fn(&str) -> Option<PublicKey>) -> Result<FractalMessage, String> {
+    let sender = key_of(&envelope.sender_id).unwrap_or_else(|| PublicKey::from(envelope.sender_key));
+    let message = wire::decode_message(&identity.open(user_id, envelope, &sender)?)?;
+    if message.sender_id != envelope.sender_id {
+        return Err(format!("Sealed by {} as from {}", envelope.sender_id, message.sender_id));
+    }
+    Ok(message)
+}
+
+impl NetworkState {
+    // Takes a message the server numbered `id`, arriving at `now`; false for one already
+    // received, which the server sent again because our ack was lost
+    fn receive(&mut self, id: u64, message: FractalMessage, now: u64) -> bool {
+        if self.recent_ids.contains(&id) {
+            return false;
+        }
+        if self.recent_ids.len() >= MAX_RECENT_IDS {
+            self.recent_ids.pop_front();
+        }
+        self.recent_ids.push_back(id);
+        // Challenge steps are the protocol's, not the host's
+        if message.battle.is_some() {
+            self.battles.push(message);
+        } else {
+            self.inbox.push(Incoming { id, message, delivered: false, received_at: now });
+        }
+        true
+    }
+
+    // Which friends sent the messages that arrived since `since`, and when each did
+    fn recent_messages(&self, since: u64, is_friend: &impl Fn(&str) -> bool) -> Vec<(&str, u64)> {
+        self.inbox.iter()
+            .filter(|incoming| incoming.received_at >= since && is_friend(&incoming.message.sender_id))
+            .map(|incoming| (incoming.message.sender_id.as_str(), incoming.received_at))
+            .collect()
+    }
+
+    // Drops the oldest delivered messages past MAX_INBOX, then if need be the oldest
+    // undelivered ones
+    fn trim_inbox(&mut self) {
+        let mut excess = self.inbox.len().saturating_sub(MAX_INBOX);
+        self.inbox.retain(|incoming| {
+            let dropped = excess > 0 && incoming.delivered;
+            excess -= dropped as usize;
+            !dropped
+        });
+        let excess = self.inbox.len().saturating_sub(MAX_INBOX);
+        self.inbox.drain(..excess);
+    }
+}
+
+// A URL's query parameters, or a bare query's, each decoded; the fragment is left out
+pub fn query_params(url: &str) -> Result<Vec<(String, String)>, JsValue> {
+    let query = url.split_once('?').map_or(url, |(_, query)| query);
+    let query = query.split_once('#').map_or(query, |(query, _)| query);
+    query.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = js_sys::decode_uri_component(value)?.as_string().unwrap_or_default();
+        Ok((name.to_string(), value))
+    }).collect()
+}
+
+fn save_queues(storage: &Option<Storage>, state: &NetworkState) {
+    let Some(storage) = storage else {
+        return;
+    };
+    if let Err(e) = storage.set_item(QUEUES_KEY, &serde_json::to_string(state).unwrap()) {
+        web_sys::console::warn_2(&JsValue::from_str("Network queues not saved:"), &e);
+    }
+}
+
+// A share link's payload, before encoding. Links are never rewritten, so every shape one has
+// had must keep decoding; schema.rs holds one of each
 #[derive(Serialize, Deserialize)]
 struct CompactFractal {
+    #[serde(default, rename = "v")]
+    version: u32, // FROZEN_VERSION of the build that made the link; 0 for links from before versioning
     seed: u32,
-    fractal_type: u8,
+    #[serde(serialize_with = "registry::serialize_id")]
+    fractal_type: FractalType,
     complexity: u16,
     interactions: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    params: Option<FractalParams>,
+    #[serde(default)]
+    derivation: u32, // Links from before versioning decode as derivation 0
+    #[serde(default)]
+    math_version: u32,
+    // The transform as a packed pose in base64, left out for the identity; links from
+    // before it open untransformed
+    #[serde(default, rename = "t", skip_serializing_if = "Option::is_none",
+            serialize_with = "serialize_pose", deserialize_with = "deserialize_pose")]
+    transform: Option<Pose>,
+}
+
+fn serialize_pose<S: Serializer>(pose: &Option<Pose>, serializer: S) -> Result<S::Ok, S::Error> {
+    match pose {
+        Some(pose) => serializer.serialize_str(&codec::base64_encode(&pose.to_packed())),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_pose<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Pose>, D::Error> {
+    let packed = String::deserialize(deserializer)?;
+    codec::base64_decode(&packed).ok()
+        .and_then(|bytes| Pose::from_packed(&bytes))
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("Invalid transform: {}", packed)))
+}
+
+// The compact JSON a share link carries
+pub fn encode_compact(fractal: &FrozenFractal) -> String {
+    let compact = CompactFractal {
+        version: FROZEN_VERSION,
+        seed: fractal.seed,
+        fractal_type: fractal.fractal_type(),
+        complexity: (fractal.complexity_score * 100.0) as u16,
+        interactions: fractal.interaction_count.min(255) as u8,
+        params: fractal.params,
+        derivation: fractal.derivation,
+        math_version: fractal.math_version,
+        transform: Some(Pose::from_matrix(&UserState::from_matrix_data(&fractal.transform_matrix)))
+            .filter(|pose| *pose != Pose::default()),
+    };
+    serde_json::to_string(&compact).unwrap()
+}
+
+// Reads a share link's JSON back into a frozen fractal opened at `timestamp`. Links
+// carry no styling or curation; those start from their defaults
+pub fn decode_compact(json: &str, timestamp: u64) -> Result<FrozenFractal, serde_json::Error> {
+    let compact: CompactFractal = serde_json::from_str(json)?;
+    // Params past what the renderer bounds, as only a hand-made link could carry, are
+    // refused rather than opened
+    if let Some(params) = &compact.params {
+        params.validate().map_err(serde::de::Error::custom)?;
+    }
+    // Every link version so far carries the same fields, later ones defaulting in older
+    // links, so there's nothing else to migrate
+    Ok(FrozenFractal {
+        version: FROZEN_VERSION,
+        seed: compact.seed,
+        fractal_type: Some(compact.fractal_type),
+        transform_matrix: UserState::matrix_data(&compact.transform.unwrap_or_default().to_matrix()),
+        complexity_score: compact.complexity as f32 / 100.0,
+        timestamp,
+        interaction_count: compact.interactions as u32,
+        coloring_mode: Default::default(),
+        palette: None,
+        params: compact.params,
+        derivation: compact.derivation,
+        math_version: compact.math_version,
+        lineage: Lineage::default(),
+        curation: Curation::default(),
+    })
 }
 
 #[derive(Serialize, Deserialize)]
@@ -211,17 +1155,92 @@ struct ShareToken {
     creator: String,
 }
 
-// Simple base64 encoding/decoding for URL safety
-fn base64_encode(data: &str) -> String {
-    // Simplified base64 implementation for demonstration
-    // In production, use a proper base64 library or browser API
-    js_sys::encode_uri_component(data).as_string().unwrap()
+// Reads a link's or token's payload, as codec::encode writes it. Those made before the
+// codec were the JSON through encodeURIComponent, so they start with "{" or, still
+// escaped, "%7B"; neither can begin a base64 one
+fn payload_decode(encoded: &str) -> Result<String, ()> {
+    if encoded.starts_with('{') || encoded.starts_with('%') {
+        return match js_sys::decode_uri_component(encoded) {
+            Ok(decoded) => Ok(decoded.as_string().unwrap()),
+            Err(_) => Err(()),
+        };
+    }
+    codec::decode(encoded)
 }
 
-fn base64_decode(encoded: &str) -> Result<String, ()> {
-    // Simplified decode - in production use proper base64
-    match js_sys::decode_uri_component(encoded) {
-        Ok(decoded) => Ok(decoded.as_string().unwrap()),
-        Err(_) => Err(()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resonance::Resonance;
+
+    fn message(sender_id: &str) -> FractalMessage {
+        FractalMessage {
+            sender_id: sender_id.to_string(),
+            fractal_data: serde_json::from_str(r#"{"seed":42,"fractal_type":"Mandelbulb","transform_matrix":[],"complexity_score":1.5,"timestamp":0,"interaction_count":0}"#).unwrap(),
+            transform_echo: None,
+            timestamp: 0,
+            message_type: MessageType::Morning,
+            recipients: Vec::new(),
+            battle: None,
+            epoch: None,
+        }
+    }
+
+    fn presence(user_id: &str, status: PresenceStatus) -> Presence {
+        Presence { user_id: user_id.to_string(), status, idle: Some(30), recipients: Vec::new() }
     }
-}
\ No newline at end of file
+
+    // Only friends are listed, and a stranger's Left removes nobody
+    #[test]
+    fn presence_kept_only_from_friends() {
+        let is_friend = |id: &str| id == "friend";
+        let mut friends = BTreeMap::new();
+        receive_presence(&mut friends, presence("friend", PresenceStatus::Joined), 1_000_000, &is_friend);
+        receive_presence(&mut friends, presence("stranger", PresenceStatus::Joined), 1_000_000, &is_friend);
+        assert_eq!(friends.keys().collect::<Vec<_>>(), ["friend"]);
+        assert_eq!(friends["friend"].last_active, Some(970_000));
+
+        receive_presence(&mut friends, presence("stranger", PresenceStatus::Left), 1_000_100, &is_friend);
+        assert!(friends.contains_key("friend"));
+
+        receive_presence(&mut friends, presence("friend", PresenceStatus::Left), 1_000_200, &is_friend);
+        assert!(friends.is_empty());
+    }
+
+    // Two strangers' messages alongside the user's gesture make no resonance of three;
+    // a second friend's does
+    #[test]
+    fn resonance_only_among_friends() {
+        let is_friend = |id: &str| id.starts_with("friend");
+        let mut state = NetworkState::default();
+        for (id, sender) in ["friend_a", "stranger_a", "stranger_b"].into_iter().enumerate() {
+            state.receive(id as u64, message(sender), 1_000_000 + id as u64);
+        }
+        let mut resonance = Resonance::default();
+        let detect = |resonance: &mut Resonance, state: &NetworkState| {
+            resonance.detect(1_000_100, "user", Some(1_000_050), &state.recent_messages(0, &is_friend), 0, 7)
+        };
+        assert_eq!(detect(&mut resonance, &state), None);
+
+        state.receive(3, message("friend_b"), 1_000_090);
+        let event = detect(&mut resonance, &state).expect("two friends and the user resonate");
+        assert_eq!(event.participants, ["friend_a", "friend_b"]);
+    }
+
+    // A message addressed to a friend whose key no link has carried can't be sealed, and
+    // so isn't sent at all; one to everyone connected needs no keys
+    #[test]
+    fn addressed_messages_need_every_key() {
+        let key = crypto::parse_public_key(&codec::base64_encode(&[9; 32])).unwrap();
+        let key_of = |id: &str| (id == "friend_a").then_some(key);
+        let mut message = message("user");
+        assert_eq!(recipient_keys(&message, &key_of).map(|keys| keys.len()), Ok(0));
+
+        message.recipients = vec!["friend_a".to_string()];
+        assert_eq!(recipient_keys(&message, &key_of).map(|keys| keys.len()), Ok(1));
+
+        message.recipients.push("friend_b".to_string());
+        let error = recipient_keys(&message, &key_of).unwrap_err();
+        assert!(error.contains("friend_b"), "{}", error);
+    }
+}
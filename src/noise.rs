@@ -0,0 +1,54 @@
+use nalgebra::Vector3;
+
+// 3D gradient (Perlin-style) noise. Everything is multiplies, adds, floor and fract, so
+// gradient_noise() in FRAGMENT_SHADER computes the same field to within f32 rounding.
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+// Hash without sine (Dave Hoskins): three pseudo-random values in 0..1 per lattice point
+fn hash33(p: &Vector3<f32>) -> Vector3<f32> {
+    let mut q = Vector3::new(fract(p.x * 0.1031), fract(p.y * 0.1030), fract(p.z * 0.0973));
+    let d = q.dot(&Vector3::new(q.y + 33.33, q.x + 33.33, q.z + 33.33));
+    q.add_scalar_mut(d);
+    Vector3::new(
+        fract((q.x + q.y) * q.z),
+        fract((q.x + q.x) * q.y),
+        fract((q.y + q.x) * q.x),
+    )
+}
+
+// Quintic fade, so the noise has continuous second derivatives across cells
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Smooth noise in roughly -1..1, zero at every lattice point; its gradient stays
+// below NOISE_SLOPE
+pub fn gradient_noise(p: &Vector3<f32>) -> f32 {
+    let cell = p.map(f32::floor);
+    let f = p - cell;
+
+    let corner = |x: f32, y: f32, z: f32| {
+        let offset = Vector3::new(x, y, z);
+        let gradient = hash33(&(cell + offset)) * 2.0 - Vector3::repeat(1.0);
+        gradient.dot(&(f - offset))
+    };
+
+    let (u, v, w) = (fade(f.x), fade(f.y), fade(f.z));
+    lerp(
+        lerp(lerp(corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), u),
+             lerp(corner(0.0, 1.0, 0.0), corner(1.0, 1.0, 0.0), u), v),
+        lerp(lerp(corner(0.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), u),
+             lerp(corner(0.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), u), v),
+        w,
+    )
+}
+
+// Upper bound on |grad gradient_noise|, for keeping displaced distance estimates safe
+pub const NOISE_SLOPE: f32 = 3.0;
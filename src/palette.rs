@@ -0,0 +1,135 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram};
+use nalgebra::Vector3;
+use serde::{Serialize, Deserialize};
+use crate::environment::GradientStop;
+use crate::fractals::hsv_to_rgb;
+
+// Must match the array sizes in FRAGMENT_SHADER
+pub const MAX_PALETTE_STOPS: usize = 8;
+
+// Color ramp sampled by the fractal colorers; positions wrap, so the first and last
+// stops should usually share a color
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Palette {
+    pub stops: Vec<GradientStop>,
+}
+
+impl Palette {
+    // Accepts a built-in palette name or a JSON palette ({"stops":[{"position":0.0,"color":[r,g,b]}, ...]})
+    pub fn parse(name_or_json: &str) -> Result<Self, JsValue> {
+        if let Some(palette) = Self::named(name_or_json) {
+            return Ok(palette);
+        }
+
+        let mut palette: Palette = serde_json::from_str(name_or_json)
+            .map_err(|_| JsValue::from_str(&format!("Unknown palette: {}", name_or_json)))?;
+
+        palette.validate().map_err(|e| JsValue::from_str(&e))?;
+
+        palette.stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(palette)
+    }
+
+    // Between 1 and MAX_PALETTE_STOPS stops, every position and color finite
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stops.is_empty() || self.stops.len() > MAX_PALETTE_STOPS {
+            return Err(format!("Palette needs between 1 and {} stops", MAX_PALETTE_STOPS));
+        }
+        if self.stops.iter().any(|stop| !stop.position.is_finite() || stop.color.iter().any(|c| !c.is_finite())) {
+            return Err("Palette stops must be finite".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        let colors: &[[f32; 3]] = match name {
+            // The classic hue wheel every fractal used before palettes existed
+            "spectrum" => &[
+                [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, 1.0],
+                [0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 0.0, 0.0],
+            ],
+            "fire" => &[[0.15, 0.0, 0.0], [0.9, 0.2, 0.0], [1.0, 0.8, 0.2], [0.15, 0.0, 0.0]],
+            "ocean" => &[[0.0, 0.1, 0.3], [0.0, 0.5, 0.7], [0.6, 0.9, 1.0], [0.0, 0.1, 0.3]],
+            "aurora" => &[[0.1, 0.0, 0.3], [0.0, 0.8, 0.5], [0.5, 1.0, 0.8], [0.6, 0.2, 0.8], [0.1, 0.0, 0.3]],
+            "mono" => &[[0.1, 0.1, 0.1], [0.95, 0.95, 0.95], [0.1, 0.1, 0.1]],
+            _ => return None,
+        };
+
+        Some(Self::evenly_spaced(colors))
+    }
+
+    // Deterministic palette for a daily seed: a base hue plus an analogous and a complementary accent
+    pub fn from_seed(seed: u32) -> Self {
+        let base_hue = (seed % 360) as f32 / 360.0;
+        let saturation = 0.6 + ((seed / 360) % 5) as f32 * 0.08;
+
+        let colors: Vec<[f32; 3]> = [0.0, 0.08, 0.5, 0.58, 0.0]
+            .iter()
+            .enumerate()
+            .map(|(i, offset)| {
+                let value = if i % 2 == 0 { 0.9 } else { 0.6 };
+                let rgb = hsv_to_rgb((base_hue + offset).fract(), saturation, value);
+                [rgb.x, rgb.y, rgb.z]
+            })
+            .collect();
+
+        Self::evenly_spaced(&colors)
+    }
+
+    fn evenly_spaced(colors: &[[f32; 3]]) -> Self {
+        let last = (colors.len().max(2) - 1) as f32;
+        Palette {
+            stops: colors.iter().enumerate()
+                .map(|(i, color)| GradientStop { position: i as f32 / last, color: *color })
+                .collect(),
+        }
+    }
+
+    pub fn sample(&self, t: f32) -> Vector3<f32> {
+        let t = t.rem_euclid(1.0);
+        // Mid grey for a palette with no stops, which validate refuses
+        let Some(first) = self.stops.first() else {
+            return Vector3::repeat(0.5);
+        };
+        let mut color = Vector3::from(first.color);
+
+        for pair in self.stops.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if t >= a.position {
+                let span = (b.position - a.position).max(0.0001);
+                let k = ((t - a.position) / span).max(0.0).min(1.0);
+                color = Vector3::from(a.color).lerp(&Vector3::from(b.color), k);
+            }
+        }
+
+        color
+    }
+
+    // Palette equivalent of hsv_to_rgb: t picks the color, saturation blends toward its
+    // luminance and value scales brightness. Mirrored by palette_shade() in glsl::HELPERS
+    pub fn shade(&self, t: f32, saturation: f32, value: f32) -> Vector3<f32> {
+        let color = self.sample(t);
+        let luminance = color.dot(&Vector3::new(0.299, 0.587, 0.114));
+        Vector3::repeat(luminance).lerp(&color, saturation.max(0.0).min(1.0)) * value
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram) {
+        let mut colors = [0.0f32; MAX_PALETTE_STOPS * 3];
+        let mut positions = [0.0f32; MAX_PALETTE_STOPS];
+        for (i, stop) in self.stops.iter().take(MAX_PALETTE_STOPS).enumerate() {
+            colors[i * 3..i * 3 + 3].copy_from_slice(&stop.color);
+            positions[i] = stop.position;
+        }
+
+        if let Some(loc) = gl.get_uniform_location(program, "u_palette_count") {
+            gl.uniform1i(Some(&loc), self.stops.len().min(MAX_PALETTE_STOPS) as i32);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_palette_colors") {
+            gl.uniform3fv_with_f32_array(Some(&loc), &colors);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_palette_positions") {
+            gl.uniform1fv_with_f32_array(Some(&loc), &positions);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use nalgebra::{Matrix3, Matrix4, Quaternion, Rotation3, Translation3, UnitQuaternion, Vector3, Vector4};
+
+// Limits on the accumulated transform, so a long day of pinching can neither blow the
+// fractal up past the view nor shrink it to a point
+const MIN_SCALE: f32 = 0.1;
+const MAX_SCALE: f32 = 10.0;
+// Largest ratio between the longest and shortest axis; beyond it the fractal flattens
+// toward a plane and the complexity score falls apart
+const MAX_ANISOTROPY: f32 = 4.0;
+const MAX_TRANSLATION: f32 = 5.0;
+
+// Iterations Rotation3::from_matrix_eps takes to find the nearest rotation
+const ORTHONORMALIZE_ITERATIONS: usize = 16;
+
+// Bytes in a packed pose: the rotation's four quaternion components, then the log of
+// each scale, then the translation, each as a little-endian i16 spread over its range
+pub const PACKED_LEN: usize = 20;
+
+// The day's transform as rotation, scale and translation, held apart so each stays
+// well formed however many gestures are stacked up: multiplying raw matrices lets
+// rounding creep in until the rotation shears and the scale runs away. A gesture's
+// matrix is applied to the recomposed transform and the result split up again, which
+// leaves out whatever isn't rotation, scale or translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub translation: Vector3<f32>,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Pose { rotation: UnitQuaternion::identity(), scale: Vector3::repeat(1.0), translation: Vector3::zeros() }
+    }
+}
+
+impl Pose {
+    // The nearest pose to an affine matrix, held within the limits; a degenerate part
+    // (zero or non-finite) falls back to the identity or the nearest limit
+    pub fn from_matrix(matrix: &Matrix4<f32>) -> Self {
+        let linear: Matrix3<f32> = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+        let translation: Vector3<f32> = matrix.fixed_view::<3, 1>(0, 3).into_owned();
+
+        // Column lengths are the scale, and what's left nearest a rotation the rotation; a
+        // mirror image comes back unmirrored
+        let scale = Vector3::new(linear.column(0).norm(), linear.column(1).norm(), linear.column(2).norm());
+        let rotation = if scale.iter().all(|s| s.is_finite() && s.abs() > f32::EPSILON) {
+            let unscaled = Matrix3::from_columns(&[linear.column(0) / scale.x, linear.column(1) / scale.y, linear.column(2) / scale.z]);
+            let nearest = Rotation3::from_matrix_eps(&unscaled, f32::EPSILON, ORTHONORMALIZE_ITERATIONS, Rotation3::identity());
+            UnitQuaternion::from_rotation_matrix(&nearest)
+        } else {
+            UnitQuaternion::identity()
+        };
+
+        let mut pose = Pose { rotation, scale, translation };
+        pose.clamp();
+        pose
+    }
+
+    // Translation, then rotation, then scale, as a 4x4 matrix
+    pub fn to_matrix(self) -> Matrix4<f32> {
+        Translation3::from(self.translation).to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    // Quantized for a share link; about 1e-4 off in each part, well below what a
+    // gesture can move
+    pub fn to_packed(self) -> [u8; PACKED_LEN] {
+        // q and -q are the same rotation; keeping w non-negative keeps it one packing
+        let rotation = if self.rotation.w < 0.0 { -self.rotation.coords } else { self.rotation.coords };
+        let (low_scale, high_scale) = (MIN_SCALE.ln(), MAX_SCALE.ln());
+        let values = rotation.iter().map(|&c| quantize(c, -1.0, 1.0))
+            .chain(self.scale.iter().map(|&s| quantize(s.ln(), low_scale, high_scale)))
+            .chain(self.translation.iter().map(|&t| quantize(t, -MAX_TRANSLATION, MAX_TRANSLATION)));
+
+        let mut packed = [0; PACKED_LEN];
+        for (bytes, value) in packed.chunks_exact_mut(2).zip(values) {
+            bytes.copy_from_slice(&value.to_le_bytes());
+        }
+        packed
+    }
+
+    // None unless `packed` is PACKED_LEN bytes
+    pub fn from_packed(packed: &[u8]) -> Option<Self> {
+        if packed.len() != PACKED_LEN {
+            return None;
+        }
+        let values: Vec<i16> = packed.chunks_exact(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+        let (low_scale, high_scale) = (MIN_SCALE.ln(), MAX_SCALE.ln());
+        let part = |i: usize, low: f32, high: f32| dequantize(values[i], low, high);
+
+        let coords = Vector4::new(part(0, -1.0, 1.0), part(1, -1.0, 1.0), part(2, -1.0, 1.0), part(3, -1.0, 1.0));
+        let mut pose = Pose {
+            rotation: UnitQuaternion::new_normalize(Quaternion::from(coords)),
+            scale: Vector3::new(part(4, low_scale, high_scale).exp(), part(5, low_scale, high_scale).exp(),
+                                part(6, low_scale, high_scale).exp()),
+            translation: Vector3::new(part(7, -MAX_TRANSLATION, MAX_TRANSLATION), part(8, -MAX_TRANSLATION, MAX_TRANSLATION),
+                                      part(9, -MAX_TRANSLATION, MAX_TRANSLATION)),
+        };
+        pose.clamp();
+        Some(pose)
+    }
+
+    // Within the limits, and whole: a part that isn't finite falls back to the identity's
+    pub fn clamp(&mut self) {
+        if !self.rotation.coords.iter().all(|c| c.is_finite()) {
+            self.rotation = UnitQuaternion::identity();
+        }
+        self.rotation.renormalize();
+
+        if !self.scale.iter().all(|s| s.is_finite()) {
+            self.scale = Vector3::repeat(1.0);
+        }
+        let longest = self.scale.max().max(MIN_SCALE).min(MAX_SCALE);
+        let shortest = (longest / MAX_ANISOTROPY).max(MIN_SCALE);
+        self.scale = self.scale.map(|s| s.max(shortest).min(longest));
+
+        if !self.translation.iter().all(|t| t.is_finite()) {
+            self.translation = Vector3::zeros();
+        }
+        let distance = self.translation.norm();
+        if distance > MAX_TRANSLATION {
+            self.translation *= MAX_TRANSLATION / distance;
+        }
+    }
+}
+
+// `value` in low..=high as an i16 from -32767 to 32767
+fn quantize(value: f32, low: f32, high: f32) -> i16 {
+    let unit = ((value - low) / (high - low)).clamp(0.0, 1.0);
+    (unit * 65534.0 - 32767.0).round() as i16
+}
+
+fn dequantize(value: i16, low: f32, high: f32) -> f32 {
+    low + (value as f32 + 32767.0) / 65534.0 * (high - low)
+}
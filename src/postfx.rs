@@ -0,0 +1,200 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram, WebGlFramebuffer, WebGlTexture};
+
+// Raymarch distance beyond which the scene shader gives up (must match FRAGMENT_SHADER)
+pub const MAX_RAY_DISTANCE: f32 = 15.0;
+
+// Offscreen color target for passes that don't draw straight to the canvas
+pub struct RenderTarget {
+    framebuffer: WebGlFramebuffer,
+    color_texture: WebGlTexture,
+    width: i32,
+    height: i32,
+}
+
+impl RenderTarget {
+    pub fn new(gl: &GL) -> Result<Self, JsValue> {
+        let framebuffer = gl.create_framebuffer().ok_or("Failed to create framebuffer")?;
+        let color_texture = gl.create_texture().ok_or("Failed to create texture")?;
+
+        Ok(RenderTarget {
+            framebuffer,
+            color_texture,
+            width: 0,
+            height: 0,
+        })
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn texture(&self) -> &WebGlTexture {
+        &self.color_texture
+    }
+
+    // Bind for drawing, (re)allocating storage when the size changed
+    pub fn bind(&mut self, gl: &GL, width: i32, height: i32) -> Result<(), JsValue> {
+        if width != self.width || height != self.height {
+            self.allocate(gl, width, height)?;
+        }
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.viewport(0, 0, width, height);
+        Ok(())
+    }
+
+    // Copy the target's contents out as RGBA rows, top row first
+    pub fn read_rgba(&self, gl: &GL) -> Result<Vec<u8>, JsValue> {
+        let row_len = self.width as usize * 4;
+        let mut pixels = vec![0u8; row_len * self.height as usize];
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.read_pixels_with_opt_u8_array(
+            0, 0, self.width, self.height, GL::RGBA, GL::UNSIGNED_BYTE, Some(&mut pixels),
+        )?;
+
+        // GL rows start at the bottom
+        let flipped = pixels.chunks(row_len).rev().flatten().copied().collect();
+        Ok(flipped)
+    }
+
+    fn allocate(&mut self, gl: &GL, width: i32, height: i32) -> Result<(), JsValue> {
+        gl.bind_texture(GL::TEXTURE_2D, Some(&self.color_texture));
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D, 0, GL::RGBA as i32, width, height, 0,
+            GL::RGBA, GL::UNSIGNED_BYTE, None,
+        )?;
+
+        // WebGL1 requires clamp + no mipmaps for non-power-of-two textures
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0, GL::TEXTURE_2D, Some(&self.color_texture), 0,
+        );
+
+        let status = gl.check_framebuffer_status(GL::FRAMEBUFFER);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        if status != GL::FRAMEBUFFER_COMPLETE {
+            return Err(JsValue::from_str(&format!("Offscreen framebuffer incomplete: {}", status)));
+        }
+
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+}
+
+// Depth-of-field post effect: the scene is rendered into an offscreen texture with the
+// normalized raymarch distance in alpha, then blurred by circle of confusion
+pub struct DepthOfField {
+    program: WebGlProgram,
+    target: RenderTarget,
+    pub focus_distance: f32,
+    pub aperture: f32,
+}
+
+impl DepthOfField {
+    pub fn new(gl: &GL, program: WebGlProgram) -> Result<Self, JsValue> {
+        Ok(DepthOfField {
+            program,
+            target: RenderTarget::new(gl)?,
+            focus_distance: 4.0,
+            aperture: 0.0, // Disabled until the host asks for it
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.aperture > 0.0
+    }
+
+    // Redirect scene rendering into the offscreen target
+    pub fn begin(&mut self, gl: &GL) -> Result<(), JsValue> {
+        self.target.bind(gl, gl.drawing_buffer_width(), gl.drawing_buffer_height())
+    }
+
+    // Resolve the offscreen scene onto the canvas with the blur applied
+    pub fn end(&self, gl: &GL) {
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        gl.viewport(0, 0, self.target.width(), self.target.height());
+        gl.use_program(Some(&self.program));
+
+        gl.active_texture(GL::TEXTURE0);
+        gl.bind_texture(GL::TEXTURE_2D, Some(self.target.texture()));
+
+        if let Some(loc) = gl.get_uniform_location(&self.program, "u_scene") {
+            gl.uniform1i(Some(&loc), 0);
+        }
+        if let Some(loc) = gl.get_uniform_location(&self.program, "u_resolution") {
+            gl.uniform2f(Some(&loc), self.target.width() as f32, self.target.height() as f32);
+        }
+        if let Some(loc) = gl.get_uniform_location(&self.program, "u_focus_distance") {
+            gl.uniform1f(Some(&loc), self.focus_distance);
+        }
+        if let Some(loc) = gl.get_uniform_location(&self.program, "u_aperture") {
+            gl.uniform1f(Some(&loc), self.aperture);
+        }
+        if let Some(loc) = gl.get_uniform_location(&self.program, "u_max_distance") {
+            gl.uniform1f(Some(&loc), MAX_RAY_DISTANCE);
+        }
+
+        gl.draw_arrays(GL::TRIANGLE_STRIP, 0, 4);
+    }
+}
+
+pub const DOF_FRAGMENT_SHADER: &str = r#"
+precision mediump float;
+
+uniform sampler2D u_scene;
+uniform vec2 u_resolution;
+uniform float u_focus_distance;
+uniform float u_aperture;
+uniform float u_max_distance;
+
+const int TAPS = 24;
+const float MAX_COC = 12.0; // pixels
+const float GOLDEN_ANGLE = 2.39996323;
+
+float circle_of_confusion(float depth) {
+    float dist = depth * u_max_distance;
+    return clamp(u_aperture * abs(dist - u_focus_distance) / max(dist, 0.001), 0.0, 1.0) * MAX_COC;
+}
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / u_resolution;
+    vec4 center = texture2D(u_scene, uv);
+    float center_coc = circle_of_confusion(center.a);
+
+    vec3 sum = center.rgb;
+    float weight = 1.0;
+
+    // Golden-angle spiral over the largest possible blur disc, so blurry foreground
+    // can spill over in-focus pixels behind it
+    for(int i = 1; i < TAPS; i++) {
+        float r = sqrt(float(i) / float(TAPS)) * MAX_COC;
+        float theta = float(i) * GOLDEN_ANGLE;
+        vec2 offset = vec2(cos(theta), sin(theta)) * r / u_resolution;
+
+        vec4 tap = texture2D(u_scene, uv + offset);
+
+        // A tap only contributes if its own blur disc reaches this pixel; taps behind
+        // the center are limited to the center's blur so sharp edges don't smear backwards
+        float tap_coc = circle_of_confusion(tap.a);
+        float reach = tap.a > center.a ? min(tap_coc, center_coc) : tap_coc;
+        float w = clamp(reach - r + 1.0, 0.0, 1.0);
+
+        sum += tap.rgb * w;
+        weight += w;
+    }
+
+    gl_FragColor = vec4(sum / weight, 1.0);
+}
+"#;
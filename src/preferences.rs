@@ -0,0 +1,158 @@
+use wasm_bindgen::prelude::*;
+use web_sys::Storage;
+use serde::{Serialize, Deserialize};
+use crate::day::DayBoundary;
+use crate::palette::Palette;
+
+const PREFERENCES_KEY: &str = "resonant_preferences";
+
+// Where each preference was kept, one key apiece, before there was a preferences record
+const LEGACY_HAPTICS_KEY: &str = "resonant_haptics";
+const LEGACY_DAY_BOUNDARY_KEY: &str = "resonant_day_boundary";
+const LEGACY_FROZEN_CAPACITY_KEY: &str = "resonant_frozen_capacity";
+
+// Every key preferences have been kept under, for erasing them
+pub const STORAGE_KEYS: [&str; 4] = [PREFERENCES_KEY, LEGACY_HAPTICS_KEY, LEGACY_DAY_BOUNDARY_KEY, LEGACY_FROZEN_CAPACITY_KEY];
+
+// Frozen fractals kept unless the user says otherwise, the most complex first;
+// favorites are kept beyond it
+const DEFAULT_FROZEN_CAPACITY: usize = 10;
+const MAX_FROZEN_CAPACITY: usize = 1000;
+
+// How hard the renderer works: at full detail always, or trading detail for frame rate
+// to hold 60 fps, or 30 to save battery (see QualityController)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityMode {
+    #[default]
+    Full,
+    Adaptive,
+    Saver,
+}
+
+impl QualityMode {
+    pub fn target_fps(self) -> f32 {
+        match self {
+            QualityMode::Full => 0.0,
+            QualityMode::Adaptive => 60.0,
+            QualityMode::Saver => 30.0,
+        }
+    }
+}
+
+// Everything the user has chosen, kept together in localStorage (it is needed before the
+// first frame) and carried by export_state
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Preferences {
+    pub volume: f32, // 0..1 of the full master level
+    pub quality: QualityMode,
+    pub palette: String, // As set_palette takes it; "seed" follows the daily seed
+    pub haptics_enabled: bool,
+    pub reduced_motion: bool, // Gentler gestures, for the motion-sensitive
+    pub share_usage: bool, // Whether usage insights may ever leave the device
+    pub share_presence: bool, // Whether friends connected to the relay are told the user is here
+    pub day_boundary: DayBoundary,
+    pub frozen_capacity: usize,
+    // Days of history kept, today's included; older ones are evicted even with room to
+    // spare. 0 keeps them until storage runs short
+    pub history_days: u32,
+    pub export_notes: bool, // Whether export_state carries the day notes
+    pub updated_at: u64, // ms since the epoch; the later of two devices' preferences wins a sync
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            volume: 1.0,
+            quality: QualityMode::Full,
+            palette: "seed".to_string(),
+            haptics_enabled: true,
+            reduced_motion: false,
+            share_usage: false,
+            share_presence: true,
+            day_boundary: DayBoundary::Local,
+            frozen_capacity: DEFAULT_FROZEN_CAPACITY,
+            history_days: 0,
+            export_notes: true,
+            updated_at: 0,
+        }
+    }
+}
+
+impl Preferences {
+    // The saved preferences, or the defaults with whatever was saved the old way. Reduced
+    // motion starts out as the system's own setting
+    pub fn load(storage: &Storage) -> Result<Self, JsValue> {
+        if let Some(data) = storage.get_item(PREFERENCES_KEY)? {
+            let mut preferences: Preferences = serde_json::from_str(&data).unwrap_or_default();
+            preferences.sanitize();
+            return Ok(preferences);
+        }
+
+        let mut preferences = Preferences {
+            reduced_motion: prefers_reduced_motion(),
+            ..Preferences::default()
+        };
+        if let Some(haptics) = storage.get_item(LEGACY_HAPTICS_KEY)? {
+            preferences.haptics_enabled = haptics != "off";
+        }
+        if let Some(boundary) = storage.get_item(LEGACY_DAY_BOUNDARY_KEY)? {
+            preferences.day_boundary = DayBoundary::parse(&boundary).unwrap_or_default();
+        }
+        if let Some(capacity) = storage.get_item(LEGACY_FROZEN_CAPACITY_KEY)? {
+            preferences.frozen_capacity = capacity.parse().unwrap_or(DEFAULT_FROZEN_CAPACITY);
+        }
+        preferences.sanitize();
+        preferences.save(storage)?;
+        for key in [LEGACY_HAPTICS_KEY, LEGACY_DAY_BOUNDARY_KEY, LEGACY_FROZEN_CAPACITY_KEY] {
+            storage.remove_item(key)?;
+        }
+        Ok(preferences)
+    }
+
+    pub fn save(&self, storage: &Storage) -> Result<(), JsValue> {
+        storage.set_item(PREFERENCES_KEY, &serde_json::to_string(self).unwrap())
+    }
+
+    // Sets one by its field name from text: a number for "volume", "frozen_capacity" and "history_days",
+    // true or false for "haptics_enabled", "reduced_motion", "share_usage", "share_presence" and "export_notes", a mode's name for "quality"
+    // (full, adaptive or saver) and "day_boundary" (local or utc), and a palette name or
+    // JSON for "palette"
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), JsValue> {
+        let invalid = |e: &dyn std::fmt::Display| JsValue::from_str(&format!("Invalid {}: {}", key, e));
+        match key {
+            "volume" => self.volume = value.parse().map_err(|e| invalid(&e))?,
+            "quality" => self.quality = serde_json::from_value(serde_json::Value::from(value)).map_err(|e| invalid(&e))?,
+            "palette" => {
+                if value != "seed" {
+                    Palette::parse(value)?;
+                }
+                self.palette = value.to_string();
+            }
+            "haptics_enabled" => self.haptics_enabled = value.parse().map_err(|e| invalid(&e))?,
+            "reduced_motion" => self.reduced_motion = value.parse().map_err(|e| invalid(&e))?,
+            "share_usage" => self.share_usage = value.parse().map_err(|e| invalid(&e))?,
+            "share_presence" => self.share_presence = value.parse().map_err(|e| invalid(&e))?,
+            "day_boundary" => self.day_boundary = DayBoundary::parse(value)?,
+            "frozen_capacity" => self.frozen_capacity = value.parse().map_err(|e| invalid(&e))?,
+            "history_days" => self.history_days = value.parse().map_err(|e| invalid(&e))?,
+            "export_notes" => self.export_notes = value.parse().map_err(|e| invalid(&e))?,
+            _ => return Err(JsValue::from_str(&format!("Unknown preference: {}", key))),
+        }
+        self.updated_at = js_sys::Date::now() as u64;
+        self.sanitize();
+        Ok(())
+    }
+
+    pub fn sanitize(&mut self) {
+        self.volume = if self.volume.is_finite() { self.volume.max(0.0).min(1.0) } else { 1.0 };
+        self.frozen_capacity = self.frozen_capacity.max(1).min(MAX_FROZEN_CAPACITY);
+    }
+}
+
+fn prefers_reduced_motion() -> bool {
+    web_sys::window()
+        .and_then(|window| window.match_media("(prefers-reduced-motion: reduce)").ok().flatten())
+        .is_some_and(|query| query.matches())
+}
@@ -0,0 +1,88 @@
+use crate::fractals::{FractalParams, HybridFormula, HybridSpec};
+
+// A hand-picked fractal with the built-in palette it looks best in. Names are stable:
+// share links carry them, so rename or remove one only with a redirect in by_name.
+pub struct Preset {
+    pub name: &'static str,
+    pub params: FractalParams,
+    pub palette: &'static str,
+}
+
+pub const PRESETS: &[Preset] = &[
+    // Mandelbulb
+    Preset { name: "classic-bulb", params: FractalParams::Mandelbulb { power: 8.0, iterations: 10 }, palette: "spectrum" },
+    Preset { name: "molten-bulb", params: FractalParams::Mandelbulb { power: 6.0, iterations: 12 }, palette: "fire" },
+    Preset { name: "coral-bulb", params: FractalParams::Mandelbulb { power: 11.5, iterations: 9 }, palette: "ocean" },
+    Preset { name: "thorn-bulb", params: FractalParams::Mandelbulb { power: 13.0, iterations: 8 }, palette: "mono" },
+
+    // Julia4D, constants near the boundary of the quaternion Mandelbrot set
+    Preset { name: "julia-filigree", params: FractalParams::Julia4D { c: [-0.2, 0.6, 0.2, 0.2], iterations: 12 }, palette: "aurora" },
+    Preset { name: "julia-dragon", params: FractalParams::Julia4D { c: [-0.45, 0.45, 0.0, 0.0], iterations: 14 }, palette: "fire" },
+    Preset { name: "julia-seahorse", params: FractalParams::Julia4D { c: [-0.75, 0.11, 0.0, 0.05], iterations: 13 }, palette: "ocean" },
+    Preset { name: "julia-cloud", params: FractalParams::Julia4D { c: [-0.08, 0.0, -0.83, -0.03], iterations: 11 }, palette: "aurora" },
+    Preset { name: "julia-rabbit", params: FractalParams::Julia4D { c: [-0.123, 0.745, 0.0, 0.0], iterations: 12 }, palette: "spectrum" },
+    Preset { name: "julia-twist", params: FractalParams::Julia4D { c: [-0.291, -0.399, 0.339, 0.437], iterations: 10 }, palette: "mono" },
+
+    // KaleidoIFS
+    Preset { name: "kaleido-star", params: FractalParams::KaleidoIFS { fold_count: 6, scale: 2.1 }, palette: "spectrum" },
+    Preset { name: "kaleido-lace", params: FractalParams::KaleidoIFS { fold_count: 10, scale: 1.8 }, palette: "aurora" },
+    Preset { name: "kaleido-crystal", params: FractalParams::KaleidoIFS { fold_count: 8, scale: 3.0 }, palette: "ocean" },
+    Preset { name: "kaleido-ember", params: FractalParams::KaleidoIFS { fold_count: 5, scale: 2.6 }, palette: "fire" },
+
+    // Mandelbox; negative scales give the spikier variant
+    Preset { name: "box-classic", params: FractalParams::Mandelbox { scale: 2.0, min_radius: 0.5, iterations: 12 }, palette: "mono" },
+    Preset { name: "box-spikes", params: FractalParams::Mandelbox { scale: -1.5, min_radius: 0.5, iterations: 12 }, palette: "fire" },
+    Preset { name: "box-cathedral", params: FractalParams::Mandelbox { scale: -2.5, min_radius: 0.25, iterations: 13 }, palette: "aurora" },
+    Preset { name: "box-reef", params: FractalParams::Mandelbox { scale: 2.7, min_radius: 0.35, iterations: 11 }, palette: "ocean" },
+    Preset { name: "box-citadel", params: FractalParams::Mandelbox { scale: -2.0, min_radius: 0.4, iterations: 14 }, palette: "spectrum" },
+
+    // MengerSponge
+    Preset { name: "menger-classic", params: FractalParams::MengerSponge { fold_count: 4, hole_scale: 3.0 }, palette: "mono" },
+    Preset { name: "menger-lattice", params: FractalParams::MengerSponge { fold_count: 5, hole_scale: 2.8 }, palette: "ocean" },
+    Preset { name: "menger-ruins", params: FractalParams::MengerSponge { fold_count: 3, hole_scale: 3.3 }, palette: "fire" },
+
+    // Apollonian
+    Preset { name: "apollonian-foam", params: FractalParams::Apollonian { inversion: 1.2, iterations: 8 }, palette: "aurora" },
+    Preset { name: "apollonian-pearls", params: FractalParams::Apollonian { inversion: 1.05, iterations: 9 }, palette: "ocean" },
+    Preset { name: "apollonian-caverns", params: FractalParams::Apollonian { inversion: 1.4, iterations: 7 }, palette: "fire" },
+
+    // Hybrid
+    Preset {
+        name: "hybrid-bulbbox",
+        params: FractalParams::Hybrid(HybridSpec {
+            first: HybridFormula::Mandelbulb { power: 8.0 },
+            second: HybridFormula::Mandelbox { scale: -2.0, min_radius: 0.5 },
+            first_steps: 1,
+            second_steps: 2,
+        }),
+        palette: "aurora",
+    },
+    Preset {
+        name: "hybrid-forge",
+        params: FractalParams::Hybrid(HybridSpec {
+            first: HybridFormula::Mandelbox { scale: 2.2, min_radius: 0.4 },
+            second: HybridFormula::Mandelbulb { power: 6.0 },
+            first_steps: 2,
+            second_steps: 1,
+        }),
+        palette: "fire",
+    },
+
+    // Plane fractals
+    Preset { name: "mandelbrot", params: FractalParams::Mandelbrot2D { julia: None, iterations: 192 }, palette: "spectrum" },
+    Preset { name: "douady-rabbit", params: FractalParams::Mandelbrot2D { julia: Some([-0.123, 0.745]), iterations: 160 }, palette: "aurora" },
+    Preset { name: "siegel-disk", params: FractalParams::Mandelbrot2D { julia: Some([-0.391, -0.587]), iterations: 224 }, palette: "ocean" },
+    Preset { name: "dendrite", params: FractalParams::Mandelbrot2D { julia: Some([0.0, 1.0]), iterations: 128 }, palette: "mono" },
+
+    // LSystem
+    Preset { name: "fern", params: FractalParams::LSystem { rule_seed: 0, iterations: 4, angle: 22.5 }, palette: "aurora" },
+    Preset { name: "bramble", params: FractalParams::LSystem { rule_seed: 3, iterations: 4, angle: 30.0 }, palette: "fire" },
+];
+
+pub fn by_name(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|preset| preset.name).collect()
+}
@@ -0,0 +1,52 @@
+// Weight of the newest frame in the smoothed frame time (as in RenderStats)
+const SMOOTHING: f32 = 0.1;
+
+// Lowest share of the full iteration budget the controller will drop to
+const MIN_DETAIL: f32 = 0.5;
+
+// Detail change per frame while outside the tolerance band
+const DETAIL_STEP: f32 = 0.02;
+
+// Frames within this fraction of the target leave the detail alone, so it doesn't hunt
+const TOLERANCE: f32 = 0.15;
+
+// Frame-time driven level of detail: scales the fractal's iteration budget down while
+// frames run slower than the target and back up once there is headroom
+pub struct QualityController {
+    pub target_frame_ms: f32, // 0 disables adaptation
+    pub detail: f32,          // MIN_DETAIL..1, share of the full budget (see fractals::with_detail)
+    smoothed_ms: f32,
+}
+
+impl Default for QualityController {
+    fn default() -> Self {
+        QualityController { target_frame_ms: 0.0, detail: 1.0, smoothed_ms: 0.0 }
+    }
+}
+
+impl QualityController {
+    // 0 fps turns adaptation off and restores full detail
+    pub fn set_target_fps(&mut self, fps: f32) {
+        self.target_frame_ms = if fps > 0.0 { 1000.0 / fps } else { 0.0 };
+        self.detail = 1.0;
+        self.smoothed_ms = 0.0;
+    }
+
+    pub fn record_frame(&mut self, delta_ms: f32) {
+        if self.target_frame_ms <= 0.0 || delta_ms <= 0.0 {
+            return;
+        }
+
+        self.smoothed_ms = if self.smoothed_ms == 0.0 {
+            delta_ms
+        } else {
+            self.smoothed_ms + (delta_ms - self.smoothed_ms) * SMOOTHING
+        };
+
+        if self.smoothed_ms > self.target_frame_ms * (1.0 + TOLERANCE) {
+            self.detail = (self.detail - DETAIL_STEP).max(MIN_DETAIL);
+        } else if self.smoothed_ms < self.target_frame_ms * (1.0 - TOLERANCE) {
+            self.detail = (self.detail + DETAIL_STEP).min(1.0);
+        }
+    }
+}
@@ -0,0 +1,530 @@
+use std::fmt;
+use std::str::FromStr;
+use nalgebra::Vector4;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use crate::fractals::*;
+use crate::lsystem::LSystem;
+use crate::palette::Palette;
+use crate::rng::SeededRng;
+
+pub type FractalBuilder = fn(&FractalParams, f32, ColoringMode, Palette) -> Box<dyn FractalGenerator>;
+
+// Which kind of fractal something is. Serialized by name ("Mandelbulb"), as the strings
+// stored before this type existed; deserializes from either the name or the registry id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FractalType {
+    Mandelbulb,
+    Julia4D,
+    KaleidoIFS,
+    Mandelbox,
+    MengerSponge,
+    Apollonian,
+    Hybrid,
+    Mandelbrot2D,
+    LSystem,
+}
+
+impl FractalType {
+    // As the type is named in saved fractals and shown to the user
+    pub fn name(&self) -> &'static str {
+        match self {
+            FractalType::Mandelbulb => "Mandelbulb",
+            FractalType::Julia4D => "Julia4D",
+            FractalType::KaleidoIFS => "KaleidoIFS",
+            FractalType::Mandelbox => "Mandelbox",
+            FractalType::MengerSponge => "MengerSponge",
+            FractalType::Apollonian => "Apollonian",
+            FractalType::Hybrid => "Hybrid",
+            FractalType::Mandelbrot2D => "Mandelbrot2D",
+            FractalType::LSystem => "LSystem",
+        }
+    }
+
+    // This type's registry entry
+    pub fn info(&self) -> FractalTypeInfo {
+        get(*self).expect("every FractalType is registered")
+    }
+
+    pub fn id(&self) -> u8 {
+        self.info().id
+    }
+
+    // u_fractal_type in FRAGMENT_SHADER
+    pub fn shader_id(&self) -> i32 {
+        self.info().shader_id
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        by_id(id).map(|info| info.kind)
+    }
+}
+
+impl fmt::Display for FractalType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FractalType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        all().into_iter()
+            .map(|info| info.kind)
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| format!("Unknown fractal type: {}", name))
+    }
+}
+
+// How a type has been written down: by name in FrozenFractal, by id in CompactFractal
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredType {
+    Name(String),
+    Id(u8),
+}
+
+impl Serialize for FractalType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for FractalType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match StoredType::deserialize(deserializer)? {
+            StoredType::Name(name) => name.parse().map_err(serde::de::Error::custom),
+            StoredType::Id(id) => FractalType::from_id(id)
+                .ok_or_else(|| serde::de::Error::custom(format!("Unknown fractal type id: {}", id))),
+        }
+    }
+}
+
+// serialize_with for share links, which keep the numeric id to stay short
+pub fn serialize_id<S: Serializer>(kind: &FractalType, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u8(kind.id())
+}
+
+// deserialize_with for saved fractals: older builds stored placeholders ("Unknown",
+// "Current") where they had no type to hand, which come back as None
+pub fn deserialize_legacy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<FractalType>, D::Error> {
+    Ok(match Option::<StoredType>::deserialize(deserializer)? {
+        Some(StoredType::Name(name)) => name.parse().ok(),
+        Some(StoredType::Id(id)) => FractalType::from_id(id),
+        None => None,
+    })
+}
+
+// Everything the app needs to know about one kind of fractal. Adding a type means one
+// FractalType and FractalParams variant plus one entry in FractalRegistry::builtin().
+#[derive(Clone, Copy)]
+pub struct FractalTypeInfo {
+    pub id: u8,              // Stable id stored in share links (CompactFractal); never reuse one
+    pub kind: FractalType,
+    pub shader_id: i32,      // u_fractal_type in FRAGMENT_SHADER
+    pub example: FractalParams, // Typical settings, for instantiating the type without a seed (e.g. for to_glsl)
+    // Legacy modulo derivation (derivation 0), kept so old frozen fractals rebuild; new
+    // types must leave it None or every later v0 seed would shift
+    pub from_seed_v0: Option<fn(u32) -> FractalParams>,
+    pub from_rng: Option<fn(&mut SeededRng) -> FractalParams>, // Derivation 1; Some for types in the daily rotation
+    // Time-of-day drift toward a more complex variant, for intensity 0..1; see EvolutionCurve
+    pub evolve: Option<fn(&FractalParams, f32) -> FractalParams>,
+    pub build: FractalBuilder,
+}
+
+pub struct FractalRegistry {
+    types: Vec<FractalTypeInfo>,
+}
+
+impl FractalRegistry {
+    pub fn new() -> Self {
+        FractalRegistry { types: Vec::new() }
+    }
+
+    // Each derivation's rotation takes its seeded types in id order; ids must never move
+    pub fn builtin() -> Self {
+        let mut registry = FractalRegistry::new();
+        let entries = [
+            FractalTypeInfo {
+                id: 0,
+                kind: FractalType::Mandelbulb,
+                shader_id: 0,
+                example: FractalParams::Mandelbulb { power: 8.0, iterations: 10 },
+                from_seed_v0: Some(mandelbulb_from_seed),
+                from_rng: Some(mandelbulb_from_rng),
+                evolve: Some(evolve_mandelbulb),
+                build: build_mandelbulb,
+            },
+            FractalTypeInfo {
+                id: 1,
+                kind: FractalType::Julia4D,
+                shader_id: 1,
+                example: FractalParams::Julia4D { c: [-0.2, 0.6, 0.2, 0.2], iterations: 12 },
+                from_seed_v0: Some(julia_from_seed),
+                from_rng: Some(julia_from_rng),
+                evolve: Some(evolve_julia),
+                build: build_julia,
+            },
+            FractalTypeInfo {
+                id: 2,
+                kind: FractalType::KaleidoIFS,
+                shader_id: 2,
+                example: FractalParams::KaleidoIFS { fold_count: 6, scale: 2.1 },
+                from_seed_v0: Some(kaleido_from_seed),
+                from_rng: Some(kaleido_from_rng),
+                evolve: Some(evolve_kaleido),
+                build: build_kaleido,
+            },
+            FractalTypeInfo {
+                id: 3,
+                kind: FractalType::Mandelbox,
+                shader_id: 3,
+                example: FractalParams::Mandelbox { scale: 2.0, min_radius: 0.5, iterations: 12 },
+                from_seed_v0: Some(mandelbox_from_seed),
+                from_rng: Some(mandelbox_from_rng),
+                evolve: Some(evolve_mandelbox),
+                build: build_mandelbox,
+            },
+            FractalTypeInfo {
+                id: 4,
+                kind: FractalType::MengerSponge,
+                shader_id: 4,
+                example: FractalParams::MengerSponge { fold_count: 4, hole_scale: 3.0 },
+                from_seed_v0: Some(menger_from_seed),
+                from_rng: Some(menger_from_rng),
+                evolve: Some(evolve_menger),
+                build: build_menger,
+            },
+            FractalTypeInfo {
+                id: 5,
+                kind: FractalType::Apollonian,
+                shader_id: 5,
+                example: FractalParams::Apollonian { inversion: 1.2, iterations: 8 },
+                from_seed_v0: Some(apollonian_from_seed),
+                from_rng: Some(apollonian_from_rng),
+                evolve: Some(evolve_apollonian),
+                build: build_apollonian,
+            },
+            FractalTypeInfo {
+                id: 6,
+                kind: FractalType::Hybrid,
+                shader_id: 6,
+                example: FractalParams::Hybrid(HybridSpec::from_seed(0)),
+                from_seed_v0: None,
+                from_rng: None,
+                evolve: None,
+                build: build_hybrid,
+            },
+            FractalTypeInfo {
+                id: 7,
+                kind: FractalType::Mandelbrot2D,
+                shader_id: 7,
+                example: FractalParams::Mandelbrot2D { julia: None, iterations: 192 },
+                from_seed_v0: None,
+                from_rng: None,
+                evolve: Some(evolve_mandelbrot2d),
+                build: build_mandelbrot2d,
+            },
+            FractalTypeInfo {
+                id: 8,
+                kind: FractalType::LSystem,
+                shader_id: 8,
+                example: FractalParams::LSystem { rule_seed: 0, iterations: 4, angle: 22.5 },
+                from_seed_v0: Some(lsystem_from_seed),
+                from_rng: Some(lsystem_from_rng),
+                evolve: Some(evolve_lsystem),
+                build: build_lsystem,
+            },
+        ];
+
+        for entry in entries {
+            registry.register(entry).expect("built-in fractal types are unique");
+        }
+        registry
+    }
+
+    pub fn register(&mut self, entry: FractalTypeInfo) -> Result<(), String> {
+        if self.types.iter().any(|t| t.id == entry.id || t.kind == entry.kind) {
+            return Err(format!("Fractal type {} ({}) is already registered", entry.kind, entry.id));
+        }
+
+        self.types.push(entry);
+        self.types.sort_by_key(|t| t.id);
+        Ok(())
+    }
+
+    pub fn types(&self) -> &[FractalTypeInfo] {
+        &self.types
+    }
+
+    pub fn by_id(&self, id: u8) -> Option<&FractalTypeInfo> {
+        self.types.iter().find(|t| t.id == id)
+    }
+
+    pub fn get(&self, kind: FractalType) -> Option<&FractalTypeInfo> {
+        self.types.iter().find(|t| t.kind == kind)
+    }
+
+    // Daily type of derivation 0: the seed modulo the rotation length
+    pub fn daily_v0(&self, seed: u32) -> Option<&FractalTypeInfo> {
+        let rotation: Vec<&FractalTypeInfo> = self.types.iter().filter(|t| t.from_seed_v0.is_some()).collect();
+        if rotation.is_empty() {
+            return None;
+        }
+        Some(rotation[(seed % rotation.len() as u32) as usize])
+    }
+
+    // Daily type of derivation 1, drawn from the rng before the type's own settings
+    pub fn daily_v1(&self, rng: &mut SeededRng) -> Option<&FractalTypeInfo> {
+        let rotation: Vec<&FractalTypeInfo> = self.types.iter().filter(|t| t.from_rng.is_some()).collect();
+        if rotation.is_empty() {
+            return None;
+        }
+        Some(rotation[rng.below(rotation.len() as u32) as usize])
+    }
+}
+
+thread_local! {
+    static REGISTRY: FractalRegistry = FractalRegistry::builtin();
+}
+
+// Lookups in the app-wide registry; entries are small, so they are handed out by value
+pub fn all() -> Vec<FractalTypeInfo> {
+    REGISTRY.with(|registry| registry.types().to_vec())
+}
+
+pub fn by_id(id: u8) -> Option<FractalTypeInfo> {
+    REGISTRY.with(|registry| registry.by_id(id).copied())
+}
+
+pub fn get(kind: FractalType) -> Option<FractalTypeInfo> {
+    REGISTRY.with(|registry| registry.get(kind).copied())
+}
+
+pub fn daily_v0(seed: u32) -> Option<FractalTypeInfo> {
+    REGISTRY.with(|registry| registry.daily_v0(seed).copied())
+}
+
+pub fn daily_v1(rng: &mut SeededRng) -> Option<FractalTypeInfo> {
+    REGISTRY.with(|registry| registry.daily_v1(rng).copied())
+}
+
+// Derivation 0 (legacy): modulo arithmetic on the seed. Frozen; see from_seed_v0.
+
+fn mandelbulb_from_seed(seed: u32) -> FractalParams {
+    FractalParams::Mandelbulb {
+        power: 6.0 + ((seed / 3) % 8) as f32,
+        iterations: 8 + ((seed / 24) % 4) as i32,
+    }
+}
+
+fn julia_from_seed(seed: u32) -> FractalParams {
+    let c_seed = seed / 100;
+    FractalParams::Julia4D {
+        c: [
+            ((c_seed % 1000) as f32 / 1000.0 - 0.5) * 2.0,
+            (((c_seed / 1000) % 1000) as f32 / 1000.0 - 0.5) * 2.0,
+            (((c_seed / 1000000) % 1000) as f32 / 1000.0 - 0.5) * 2.0,
+            (((c_seed / 1000000000) % 1000) as f32 / 1000.0 - 0.5) * 2.0,
+        ],
+        iterations: 8 + ((seed / 13) % 6) as i32,
+    }
+}
+
+fn kaleido_from_seed(seed: u32) -> FractalParams {
+    FractalParams::KaleidoIFS {
+        fold_count: 4 + ((seed / 7) % 8) as i32,
+        scale: 1.5 + ((seed / 17) % 10) as f32 * 0.3,
+    }
+}
+
+fn mandelbox_from_seed(seed: u32) -> FractalParams {
+    // |scale| in 2.0..2.875, negative for half the seeds (the spikier variant)
+    let magnitude = 2.0 + ((seed / 4) % 8) as f32 * 0.125;
+    FractalParams::Mandelbox {
+        scale: if (seed / 32) % 2 == 1 { -magnitude } else { magnitude },
+        min_radius: 0.25 + ((seed / 64) % 6) as f32 * 0.05,
+        iterations: 10 + ((seed / 384) % 4) as i32,
+    }
+}
+
+fn menger_from_seed(seed: u32) -> FractalParams {
+    FractalParams::MengerSponge {
+        fold_count: 3 + ((seed / 5) % 3) as i32,
+        hole_scale: 2.8 + ((seed / 15) % 6) as f32 * 0.1,
+    }
+}
+
+fn apollonian_from_seed(seed: u32) -> FractalParams {
+    FractalParams::Apollonian {
+        inversion: 1.0 + ((seed / 6) % 10) as f32 * 0.05,
+        iterations: 6 + ((seed / 60) % 4) as i32,
+    }
+}
+
+fn lsystem_from_seed(seed: u32) -> FractalParams {
+    FractalParams::LSystem {
+        rule_seed: seed / 7,
+        iterations: 3 + ((seed / 28) % 2) as i32,
+        angle: 18.0 + ((seed / 56) % 8) as f32 * 2.5,
+    }
+}
+
+// Derivation 1: the same ranges as derivation 0, drawn from SeededRng. Frozen as well;
+// changes go into a new derivation.
+
+fn mandelbulb_from_rng(rng: &mut SeededRng) -> FractalParams {
+    FractalParams::Mandelbulb {
+        power: rng.range_f32(6.0, 13.0),
+        iterations: rng.range_i32(8, 11),
+    }
+}
+
+fn julia_from_rng(rng: &mut SeededRng) -> FractalParams {
+    let mut c = [0.0; 4];
+    for value in c.iter_mut() {
+        *value = rng.range_f32(-1.0, 1.0);
+    }
+    FractalParams::Julia4D { c, iterations: rng.range_i32(8, 13) }
+}
+
+fn kaleido_from_rng(rng: &mut SeededRng) -> FractalParams {
+    FractalParams::KaleidoIFS {
+        fold_count: rng.range_i32(4, 11),
+        scale: rng.range_f32(1.5, 4.2),
+    }
+}
+
+fn mandelbox_from_rng(rng: &mut SeededRng) -> FractalParams {
+    let magnitude = rng.range_f32(2.0, 2.875);
+    FractalParams::Mandelbox {
+        scale: if rng.chance(0.5) { -magnitude } else { magnitude },
+        min_radius: rng.range_f32(0.25, 0.5),
+        iterations: rng.range_i32(10, 13),
+    }
+}
+
+fn menger_from_rng(rng: &mut SeededRng) -> FractalParams {
+    FractalParams::MengerSponge {
+        fold_count: rng.range_i32(3, 5),
+        hole_scale: rng.range_f32(2.8, 3.3),
+    }
+}
+
+fn apollonian_from_rng(rng: &mut SeededRng) -> FractalParams {
+    FractalParams::Apollonian {
+        inversion: rng.range_f32(1.0, 1.45),
+        iterations: rng.range_i32(6, 9),
+    }
+}
+
+fn lsystem_from_rng(rng: &mut SeededRng) -> FractalParams {
+    FractalParams::LSystem {
+        rule_seed: rng.next_u32(),
+        iterations: rng.range_i32(3, 4),
+        angle: rng.range_f32(18.0, 35.5),
+    }
+}
+
+// Evolution: how far each type's settings drift at full intensity. Iteration and fold
+// counts stay within the shader loop limits.
+
+fn drift(count: i32, by: f32, max: i32) -> i32 {
+    (count + by.round() as i32).min(max)
+}
+
+fn evolve_mandelbulb(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::Mandelbulb { power, iterations } = *params else { unreachable!() };
+    FractalParams::Mandelbulb {
+        power: power + 2.0 * intensity,
+        iterations: drift(iterations, 2.0 * intensity, 14),
+    }
+}
+
+fn evolve_julia(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::Julia4D { c, iterations } = *params else { unreachable!() };
+    FractalParams::Julia4D { c, iterations: drift(iterations, 3.0 * intensity, 16) }
+}
+
+fn evolve_kaleido(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::KaleidoIFS { fold_count, scale } = *params else { unreachable!() };
+    FractalParams::KaleidoIFS { fold_count: drift(fold_count, 3.0 * intensity, 14), scale }
+}
+
+fn evolve_mandelbox(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::Mandelbox { scale, min_radius, iterations } = *params else { unreachable!() };
+    FractalParams::Mandelbox {
+        scale,
+        min_radius: min_radius * (1.0 - 0.3 * intensity),
+        iterations: drift(iterations, 2.0 * intensity, 16),
+    }
+}
+
+fn evolve_menger(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::MengerSponge { fold_count, hole_scale } = *params else { unreachable!() };
+    FractalParams::MengerSponge { fold_count: drift(fold_count, intensity, 6), hole_scale }
+}
+
+fn evolve_apollonian(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::Apollonian { inversion, iterations } = *params else { unreachable!() };
+    FractalParams::Apollonian { inversion, iterations: drift(iterations, 3.0 * intensity, 12) }
+}
+
+fn evolve_mandelbrot2d(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::Mandelbrot2D { julia, iterations } = *params else { unreachable!() };
+    FractalParams::Mandelbrot2D { julia, iterations: drift(iterations, 64.0 * intensity, 256) }
+}
+
+// Symbol count grows too fast to add generations; the branches spread instead
+fn evolve_lsystem(params: &FractalParams, intensity: f32) -> FractalParams {
+    let FractalParams::LSystem { rule_seed, iterations, angle } = *params else { unreachable!() };
+    FractalParams::LSystem { rule_seed, iterations, angle: angle + 6.0 * intensity }
+}
+
+// Builders. The registry looks a builder up by FractalParams::fractal_type(), so each one only
+// ever sees its own variant.
+
+fn build_mandelbulb(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::Mandelbulb { power, iterations } = *params else { unreachable!() };
+    Box::new(Mandelbulb { power, iterations, time, coloring, palette })
+}
+
+fn build_julia(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::Julia4D { c, iterations } = *params else { unreachable!() };
+    Box::new(Julia4D { c: Vector4::from(c), iterations, time, coloring, palette })
+}
+
+fn build_kaleido(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::KaleidoIFS { fold_count, scale } = *params else { unreachable!() };
+    Box::new(KaleidoIFS { fold_count, scale, time, coloring, palette })
+}
+
+fn build_mandelbox(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::Mandelbox { scale, min_radius, iterations } = *params else { unreachable!() };
+    Box::new(Mandelbox { scale, min_radius, iterations, time, coloring, palette })
+}
+
+fn build_menger(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::MengerSponge { fold_count, hole_scale } = *params else { unreachable!() };
+    Box::new(MengerSponge { fold_count, hole_scale, time, coloring, palette })
+}
+
+fn build_apollonian(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::Apollonian { inversion, iterations } = *params else { unreachable!() };
+    Box::new(Apollonian { inversion, iterations, time, coloring, palette })
+}
+
+fn build_hybrid(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::Hybrid(spec) = *params else { unreachable!() };
+    Box::new(HybridFractal { spec, time, coloring, palette })
+}
+
+fn build_mandelbrot2d(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::Mandelbrot2D { julia, iterations } = *params else { unreachable!() };
+    Box::new(Mandelbrot2D { julia, iterations, time, coloring, palette })
+}
+
+fn build_lsystem(params: &FractalParams, time: f32, coloring: ColoringMode, palette: Palette) -> Box<dyn FractalGenerator> {
+    let FractalParams::LSystem { rule_seed, iterations, angle } = *params else { unreachable!() };
+    Box::new(LSystem::new(rule_seed, iterations, angle, time, coloring, palette))
+}
@@ -0,0 +1,64 @@
+use nalgebra::Matrix4;
+use serde::{Serialize, Deserialize};
+
+// Longest pause between two steps of a replay, in seconds at speed 1; a day's gestures
+// are often hours apart
+const MAX_REPLAY_GAP: f64 = 2.0;
+
+// Seconds the untouched fractal shows before the first step, at speed 1
+const START_PAUSE: f64 = 1.0;
+
+// One step of the day's transform: a gesture, or an undo, redo or reset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GestureRecord {
+    pub kind: String, // The gesture type, or "undo", "redo" or "reset"
+    pub intensity: f32,
+    pub direction: f32,
+    pub timestamp: u64, // ms since the epoch
+    // The day's transform once the step was taken, row by row. Replaying sets these
+    // rather than recomputing the gestures, which depend on the animation time, so it
+    // ends exactly where the day did
+    pub transform: Vec<f32>,
+}
+
+// A day's steps played back over time from the untouched fractal, `speed` times as fast
+// as they happened
+pub struct Replay {
+    steps: Vec<GestureRecord>,
+    next: usize,
+    speed: f64,
+    until_next: f64, // Seconds of replay to the next step
+    transform: Matrix4<f32>,
+}
+
+impl Replay {
+    pub fn new(steps: Vec<GestureRecord>, speed: f64) -> Self {
+        let speed = speed.max(0.01);
+        Replay { steps, next: 0, speed, until_next: START_PAUSE / speed, transform: Matrix4::identity() }
+    }
+
+    // The transform the replay has reached
+    pub fn transform(&self) -> Matrix4<f32> {
+        self.transform
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.steps.len()
+    }
+
+    pub fn advance(&mut self, seconds: f64) {
+        self.until_next -= seconds;
+        while self.until_next <= 0.0 && !self.is_finished() {
+            let step = &self.steps[self.next];
+            if step.transform.len() == 16 {
+                self.transform = Matrix4::from_row_slice(&step.transform);
+            }
+            self.next += 1;
+
+            if let Some(following) = self.steps.get(self.next) {
+                let gap = following.timestamp.saturating_sub(step.timestamp) as f64 / 1000.0;
+                self.until_next += gap.min(MAX_REPLAY_GAP) / self.speed;
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+
+// Events the history keeps; past it the oldest go
+const MAX_HISTORY: usize = 100;
+
+// Seconds the resonance mode takes to swell as it starts and to fade as it ends
+pub const MODE_FADE: f32 = 3.0;
+
+// Activity stamped this far, in ms, past now is from a clock that's wrong, and ignored
+const CLOCK_SLACK: u64 = 60 * 1000;
+
+// When a resonance happens: everyone active within `window` seconds of now, the user among
+// them, numbering at least `participants`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ResonanceSettings {
+    pub window: u32,       // Seconds
+    pub participants: u32, // Counting the user
+    pub duration: f32,     // Seconds the resonance mode lasts
+}
+
+impl Default for ResonanceSettings {
+    fn default() -> Self {
+        ResonanceSettings { window: 300, participants: 3, duration: 30.0 }
+    }
+}
+
+impl ResonanceSettings {
+    pub fn parse(json: &str) -> Result<Self, JsValue> {
+        let settings: ResonanceSettings = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid resonance settings: {}", e)))?;
+        if !(10..=3600).contains(&settings.window) {
+            return Err(JsValue::from_str("The window is 10 to 3600 seconds"));
+        }
+        if !(2..=50).contains(&settings.participants) {
+            return Err(JsValue::from_str("Resonance takes 2 to 50 participants"));
+        }
+        if !(2.0 * MODE_FADE..=300.0).contains(&settings.duration) {
+            return Err(JsValue::from_str(&format!("The duration is {} to 300 seconds", 2.0 * MODE_FADE)));
+        }
+        Ok(settings)
+    }
+}
+
+// A moment the user and others were active together
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ResonanceEvent {
+    pub started_at: u64,           // ms since the epoch
+    pub participants: Vec<String>, // The others' user ids, sorted
+    pub seed: u32,                 // The user's fractal at the time
+    pub strength: f32,             // 1 with just enough participants, up to 2 with twice as many
+    pub duration: f32,             // Seconds the resonance mode lasted
+}
+
+impl ResonanceEvent {
+    pub fn ends_at(&self) -> u64 {
+        self.started_at + (self.duration * 1000.0) as u64
+    }
+}
+
+// Every resonance the user has been part of, oldest first; kept with the rest of the
+// state, and synced and exported with it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ResonanceHistory {
+    pub events: Vec<ResonanceEvent>,
+}
+
+impl ResonanceHistory {
+    pub fn record(&mut self, event: ResonanceEvent) {
+        self.events.push(event);
+        self.trim();
+    }
+
+    pub fn last_at(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.started_at)
+    }
+
+    // Both sides' events, one of each start time
+    pub fn merge(&mut self, other: ResonanceHistory) {
+        for event in other.events {
+            if !self.events.iter().any(|kept| kept.started_at == event.started_at) {
+                self.events.push(event);
+            }
+        }
+        self.events.sort_by_key(|event| event.started_at);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.events.len().saturating_sub(MAX_HISTORY);
+        self.events.drain(..excess);
+    }
+}
+
+// Watches for resonances and plays out the resonance mode that follows one. Activity
+// only counts toward one resonance: what came before the last one started is passed over
+#[derive(Default)]
+pub struct Resonance {
+    pub settings: ResonanceSettings,
+    active: Option<ResonanceEvent>,
+}
+
+impl Resonance {
+    // A new resonance if the user's latest gesture (`user_active_at`) and others' activity
+    // (sender and when it arrived, all ms since the epoch) now make one
+    pub fn detect(&mut self, now: u64, user_id: &str, user_active_at: Option<u64>,
+                  activity: &[(&str, u64)], last_at: u64, seed: u32) -> Option<ResonanceEvent> {
+        if self.active.as_ref().is_some_and(|event| now < event.ends_at()) {
+            return None;
+        }
+        self.active = None;
+
+        let since = now.saturating_sub(self.settings.window as u64 * 1000).max(last_at + 1);
+        let counts = |at: u64| at >= since && at <= now + CLOCK_SLACK;
+        if !user_active_at.is_some_and(counts) {
+            return None;
+        }
+        let others: BTreeSet<&str> = activity.iter()
+            .filter(|&&(sender, at)| sender != user_id && counts(at))
+            .map(|&(sender, _)| sender)
+            .collect();
+        let required = self.settings.participants as usize - 1;
+        if others.len() < required {
+            return None;
+        }
+
+        let strength = (others.len() as f32 + 1.0) / self.settings.participants as f32;
+        let event = ResonanceEvent {
+            started_at: now,
+            participants: others.into_iter().map(str::to_string).collect(),
+            seed,
+            strength: strength.min(2.0),
+            duration: self.settings.duration,
+        };
+        self.active = Some(event.clone());
+        Some(event)
+    }
+
+    // How strongly the resonance mode shows at `now`, 0..1: full through the event, easing
+    // in and out over MODE_FADE at either end
+    pub fn level(&self, now: u64) -> f32 {
+        let Some(event) = &self.active else {
+            return 0.0;
+        };
+        if now >= event.ends_at() {
+            return 0.0;
+        }
+        let elapsed = now.saturating_sub(event.started_at) as f32 / 1000.0;
+        let edge = (elapsed.min(event.duration - elapsed) / MODE_FADE).min(1.0);
+        edge * edge * (3.0 - 2.0 * edge)
+    }
+}
@@ -0,0 +1,68 @@
+// PCG32 (XSH RR) generator for deriving fractals from seeds. Everything here is integer
+// math plus exact int-to-float conversions, so a seed gives the same sequence on every
+// platform and build. Changing any constant or method here changes historical fractals;
+// add a new derivation version instead.
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+#[derive(Clone, Debug)]
+pub struct SeededRng {
+    state: u64,
+    increment: u64,
+}
+
+impl SeededRng {
+    // `stream` picks one of 2^63 independent sequences, so different uses of the same
+    // seed don't share numbers
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = SeededRng { state: 0, increment: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rotation = (old >> 59) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    // Uniform in 0..bound (0 when bound is 0)
+    pub fn below(&mut self, bound: u32) -> u32 {
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+
+    // Uniform in [0, 1), on a 2^-24 grid so every value is an exact f32
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // Inclusive of both ends
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        min + self.below((max - min + 1).max(0) as u32) as i32
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The reference PCG32's first outputs for seed 42 on stream 54 (pcg32-demo)
+    #[test]
+    fn matches_the_reference_sequence() {
+        let mut rng = SeededRng::new(42, 54);
+        let outputs: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+        assert_eq!(outputs, [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]);
+    }
+}
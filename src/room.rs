@@ -0,0 +1,327 @@
+// Rooms, where a group sculpts one fractal together: everyone in a room has its seed in
+// the sandbox, and a gesture anyone makes moves it for all. The shared state is the seed
+// and each part of the pose (rotation, scale, translation), each a register the latest
+// write wins, latest as a Lamport clock and then the writer's user id order them. An
+// update carries the whole state, and merging keeps the later of each part, so however
+// updates are ordered, lost or repeated on the way, everyone ends up with the same. Of
+// two gestures at once, each keeps the parts only it moved, and the later the part both did
+use std::collections::BTreeMap;
+use nalgebra::{Quaternion, UnitQuaternion, Vector3, Vector4};
+use serde::{Serialize, Deserialize};
+use crate::pose::Pose;
+
+// Updates go out at most this often, in ms; gestures between gather into the next
+const SEND_INTERVAL: f64 = 50.0;
+
+// With nothing new, the state still goes out this often, in ms, making up for any update
+// lost; a member not heard from in MEMBER_TIMEOUT has gone
+const RESEND_INTERVAL: f64 = 5.0 * 1000.0;
+const MEMBER_TIMEOUT: u64 = 15 * 1000;
+
+const MAX_ROOM_ID: usize = 64;
+
+// How far past the local clock an update's stamps may be. Far more writes than a room
+// ever makes, yet an update stamped near u64::MAX, which would leave the user's own
+// writes nowhere to go, is refused
+const MAX_CLOCK_LEAD: u64 = 1 << 32;
+
+// When a part was written, and by whom; the later is the greater
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Stamp {
+    pub clock: u64, // 0 for a part nobody has written yet, which any write beats
+    pub user_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Register<T> {
+    pub value: T,
+    pub stamp: Stamp,
+}
+
+impl<T> Register<T> {
+    // Takes `other` if it was written later
+    fn merge(&mut self, other: Register<T>) {
+        if other.stamp > self.stamp {
+            *self = other;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoomState {
+    pub seed: Register<u32>,
+    pub rotation: Register<[f32; 4]>, // The quaternion's i, j, k and w
+    pub scale: Register<[f32; 3]>,
+    pub translation: Register<[f32; 3]>,
+}
+
+impl RoomState {
+    fn new(seed: u32, pose: &Pose, stamp: Stamp) -> Self {
+        RoomState {
+            seed: Register { value: seed, stamp: stamp.clone() },
+            rotation: Register { value: pose.rotation.coords.into(), stamp: stamp.clone() },
+            scale: Register { value: pose.scale.into(), stamp: stamp.clone() },
+            translation: Register { value: pose.translation.into(), stamp },
+        }
+    }
+
+    // The latest clock of any part, for the next write to go past
+    fn clock(&self) -> u64 {
+        [&self.seed.stamp, &self.rotation.stamp, &self.scale.stamp, &self.translation.stamp]
+            .iter().map(|stamp| stamp.clock).max().unwrap_or(0)
+    }
+
+    // As it was written: a pose the view showed, or one held within the limits on arrival
+    pub fn pose(&self) -> Pose {
+        Pose {
+            rotation: UnitQuaternion::new_unchecked(Quaternion::from(Vector4::from(self.rotation.value))),
+            scale: Vector3::from(self.scale.value),
+            translation: Vector3::from(self.translation.value),
+        }
+    }
+
+    fn merge(&mut self, other: RoomState) {
+        self.seed.merge(other.seed);
+        self.rotation.merge(other.rotation);
+        self.scale.merge(other.scale);
+        self.translation.merge(other.translation);
+    }
+
+    // From someone else: a pose that isn't one (non-finite, or past the limits) is
+    // brought back within them
+    fn sanitized(mut self) -> Self {
+        let mut pose = self.pose();
+        // A zero quaternion is no rotation at all; clamp sees to whatever isn't finite
+        if pose.rotation.norm() <= f32::EPSILON {
+            pose.rotation = UnitQuaternion::identity();
+        }
+        pose.clamp();
+        self.rotation.value = pose.rotation.coords.into();
+        self.scale.value = pose.scale.into();
+        self.translation.value = pose.translation.into();
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomStatus {
+    Joined, // Just joined; the others answer with the state, so they catch up
+    Update,
+    Left,
+}
+
+// What goes to a room's members: who it's from, and the state as they have it. The relay
+// keeps whoever has joined a room, until they leave or disconnect, and passes each update
+// to the rest of it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoomUpdate {
+    pub room_id: String,
+    pub user_id: String,
+    pub status: RoomStatus,
+    pub state: RoomState,
+}
+
+// What poll_room found
+pub struct RoomPoll {
+    pub shared: Option<(u32, Pose)>, // The seed and pose to show, if the view has fallen behind
+    pub members_changed: bool,      // Someone joined or left
+}
+
+// The room the user is in, as this end knows it. Anyone who knows its id can join
+pub struct Room {
+    pub id: String,
+    state: RoomState,
+    shown: Option<(u32, Pose)>,     // What the view showed at the last update
+    members: BTreeMap<String, u64>, // The others, and when each was last heard from (ms since the epoch)
+    members_changed: bool,
+    announced: bool, // Whether the room has heard the user joined, on this connection
+    changed: bool,   // The state has moved on since it last went out
+    last_sent: f64,  // ms since the epoch
+}
+
+impl Room {
+    // Joins with the fractal in view, `seed` posed as `pose`. Claiming the room makes
+    // that its fractal; otherwise it's only shown until the room's arrives, or if nobody
+    // there has sculpted or claimed one
+    pub fn new(id: &str, user_id: &str, seed: u32, pose: &Pose, claim: bool) -> Result<Self, String> {
+        if id.is_empty() || id.len() > MAX_ROOM_ID {
+            return Err(format!("A room id is 1 to {} bytes", MAX_ROOM_ID));
+        }
+        Ok(Room {
+            id: id.to_string(),
+            state: RoomState::new(seed, pose, Stamp { clock: claim as u64, user_id: user_id.to_string() }),
+            shown: None,
+            members: BTreeMap::new(),
+            members_changed: false,
+            announced: false,
+            changed: claim,
+            last_sent: 0.0,
+        })
+    }
+
+    // Whatever the view has moved since the last update is the user's own write, stamped
+    // past every part seen so far; returns the room's state if the view isn't showing it
+    pub fn update(&mut self, user_id: &str, seed: u32, pose: &Pose) -> RoomPoll {
+        if let Some((shown_seed, shown)) = self.shown {
+            let stamp = Stamp { clock: self.state.clock().saturating_add(1), user_id: user_id.to_string() };
+            // Parts the user left alone keep whatever arrived since, however old the view's
+            let state = &mut self.state;
+            if seed != shown_seed {
+                state.seed = Register { value: seed, stamp: stamp.clone() };
+            }
+            if pose.rotation != shown.rotation {
+                state.rotation = Register { value: pose.rotation.coords.into(), stamp: stamp.clone() };
+            }
+            if pose.scale != shown.scale {
+                state.scale = Register { value: pose.scale.into(), stamp: stamp.clone() };
+            }
+            if pose.translation != shown.translation {
+                state.translation = Register { value: pose.translation.into(), stamp };
+            }
+            self.changed |= (seed, *pose) != (shown_seed, shown);
+        }
+        let shared = (self.state.seed.value, self.state.pose());
+        self.shown = Some(shared);
+        RoomPoll {
+            shared: (shared != (seed, *pose)).then_some(shared),
+            members_changed: std::mem::take(&mut self.members_changed),
+        }
+    }
+
+    // Takes in an update from another member; one for another room, or stamped past
+    // MAX_CLOCK_LEAD, is dropped
+    pub fn receive(&mut self, update: RoomUpdate, now: u64) {
+        if update.room_id != self.id || update.state.clock() > self.state.clock().saturating_add(MAX_CLOCK_LEAD) {
+            return;
+        }
+        match update.status {
+            RoomStatus::Left => self.members_changed |= self.members.remove(&update.user_id).is_some(),
+            status => {
+                let joined = self.members.insert(update.user_id, now).is_none();
+                self.members_changed |= joined;
+                // Answered soon, so they needn't wait out RESEND_INTERVAL to catch up
+                self.changed |= joined || status == RoomStatus::Joined;
+            }
+        }
+        self.state.merge(update.state.sanitized());
+    }
+
+    // The update to send now, if one is due: the user's joining, a change, or the state
+    // again after RESEND_INTERVAL
+    pub fn outgoing(&mut self, user_id: &str, now: f64) -> Option<RoomUpdate> {
+        let status = if !self.announced {
+            RoomStatus::Joined
+        } else if (self.changed && now >= self.last_sent + SEND_INTERVAL) || now >= self.last_sent + RESEND_INTERVAL {
+            RoomStatus::Update
+        } else {
+            return None;
+        };
+        self.announced = true;
+        self.changed = false;
+        self.last_sent = now;
+        Some(self.message(user_id, status))
+    }
+
+    pub fn message(&self, user_id: &str, status: RoomStatus) -> RoomUpdate {
+        RoomUpdate { room_id: self.id.clone(), user_id: user_id.to_string(), status, state: self.state.clone() }
+    }
+
+    // Members not heard from in MEMBER_TIMEOUT have gone
+    pub fn expire(&mut self, now: u64) {
+        let before = self.members.len();
+        self.members.retain(|_, heard_at| now.saturating_sub(*heard_at) <= MEMBER_TIMEOUT);
+        self.members_changed |= self.members.len() != before;
+    }
+
+    // The connection dropped: the next one joins again, and hears who is there afresh
+    pub fn disconnected(&mut self) {
+        self.announced = false;
+        self.members_changed |= !self.members.is_empty();
+        self.members.clear();
+    }
+
+    pub fn is_announced(&self) -> bool {
+        self.announced
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.state.seed.value
+    }
+
+    // The others' user ids
+    pub fn members(&self) -> impl Iterator<Item = &String> {
+        self.members.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(clock: u64, user_id: &str) -> Stamp {
+        Stamp { clock, user_id: user_id.to_string() }
+    }
+
+    // Three members' updates, taken in every order, with one repeated and a stale one among
+    // them: each order ends with the same state, the latest of each part
+    #[test]
+    fn updates_merge_the_same_in_any_order() {
+        let base = RoomState::new(7, &Pose::default(), stamp(0, "a"));
+        let mut turned = base.clone();
+        turned.rotation = Register { value: [0.0, 0.6, 0.0, 0.8], stamp: stamp(3, "b") };
+        let mut scaled = base.clone();
+        scaled.scale = Register { value: [2.0, 2.0, 2.0], stamp: stamp(2, "c") };
+        scaled.rotation = Register { value: [0.6, 0.0, 0.0, 0.8], stamp: stamp(2, "c") };
+        let mut reseeded = base.clone();
+        reseeded.seed = Register { value: 11, stamp: stamp(3, "a") };
+        reseeded.rotation = Register { value: [0.0, 0.0, 0.6, 0.8], stamp: stamp(3, "a") };
+
+        let mut expected = base.clone();
+        expected.seed = reseeded.seed.clone();
+        expected.rotation = turned.rotation.clone(); // At the same clock, "b" goes after "a"
+        expected.scale = scaled.scale.clone();
+
+        let updates = [turned, scaled, reseeded];
+        let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+        for order in orders {
+            let mut state = base.clone();
+            for &i in order.iter().chain(order.first()) {
+                state.merge(updates[i].clone().sanitized());
+            }
+            state.merge(base.clone());
+            assert_eq!(state, expected, "merged in the order {:?}", order);
+        }
+    }
+
+    #[test]
+    fn updates_past_the_limits_are_held_within_them() {
+        let mut wild = RoomState::new(7, &Pose::default(), stamp(0, "a"));
+        wild.scale = Register { value: [f32::NAN, 1e9, 0.0], stamp: stamp(1, "d") };
+        wild.translation = Register { value: [f32::INFINITY, 0.0, 0.0], stamp: stamp(1, "d") };
+        let wild = wild.sanitized().pose();
+        for part in wild.scale.iter().chain(wild.translation.iter()) {
+            assert!(part.is_finite() && part.abs() <= 10.0, "{} left past the limits", part);
+        }
+    }
+
+    // A member stamping u64::MAX neither overflows the clock nor outlasts the user's writes
+    #[test]
+    fn updates_stamped_far_ahead_are_refused() {
+        let pose = Pose::default();
+        let mut room = Room::new("r", "a", 7, &pose, true).expect("a valid room id");
+        room.update("a", 7, &pose);
+        let mut hostile = room.message("m", RoomStatus::Update);
+        hostile.state.seed = Register { value: 99, stamp: stamp(u64::MAX, "m") };
+        room.receive(hostile, 0);
+        assert_eq!(room.state.seed.value, 7);
+
+        room.update("a", 8, &pose);
+        assert_eq!(room.state.seed.value, 8);
+
+        // And a clock already at the end stays there
+        room.state.seed.stamp = stamp(u64::MAX, "m");
+        room.update("a", 9, &pose);
+        assert_eq!(room.state.seed.stamp, stamp(u64::MAX, "a"));
+    }
+}
@@ -0,0 +1,201 @@
+use nalgebra::{Matrix4, Translation3, UnitQuaternion, Vector3};
+use serde_json::Value;
+use crate::fractals::FractalParams;
+use crate::codec;
+use crate::network;
+use crate::pose::Pose;
+use crate::registry::FractalType;
+use crate::user::{FrozenFractal, UserState, FROZEN_VERSION};
+
+const IDENTITY: &str = "[1.0,0.0,0.0,0.0,0.0,1.0,0.0,0.0,0.0,0.0,1.0,0.0,0.0,0.0,0.0,1.0]";
+
+// Furthest any entry of a transform may move going through a share link; the largest
+// entries are the scale's, up to ten, so this is about 1e-4 of them
+const MAX_LINK_TRANSFORM_ERROR: f32 = 2e-3;
+
+// Characters a share link's payload may take with a transform and params, short enough
+// to paste into a message without it being cut
+const MAX_LINK_PAYLOAD: usize = 256;
+
+// One shape a saved fractal has had, as the build that wrote it wrote it
+struct Fixture {
+    name: &'static str,
+    json: String,
+    fractal_type: Option<FractalType>, // None for whatever the seed derives
+}
+
+// Frozen fractals as every build so far has saved them, in the order the fields arrived
+fn frozen_fixtures() -> Vec<Fixture> {
+    let base = format!(
+        r#""seed":20240611,"transform_matrix":{},"complexity_score":1.5,"timestamp":1718064000000,"interaction_count":12"#,
+        IDENTITY
+    );
+    let fixture = |name, extra: &str, fractal_type| Fixture {
+        name,
+        json: format!("{{{},{}}}", base, extra),
+        fractal_type,
+    };
+    let params = r#""params":{"type":"Julia4D","c":[0.25,-0.5,0.125,0.0],"iterations":10}"#;
+    let palette = r#""palette":{"stops":[{"position":0.0,"color":[0.0,0.0,0.25]},{"position":1.0,"color":[1.0,0.5,0.0]}]}"#;
+
+    vec![
+        fixture("v0 first release", r#""fractal_type":"Mandelbulb""#, Some(FractalType::Mandelbulb)),
+        fixture("v0 placeholder type", r#""fractal_type":"Current""#, None),
+        fixture("v0 unknown type", r#""fractal_type":"Unknown""#, None),
+        fixture("v0 coloring mode", r#""fractal_type":"Mandelbox","coloring_mode":"PointTrap""#, Some(FractalType::Mandelbox)),
+        fixture("v0 palette", &format!(r#""fractal_type":"Mandelbulb","coloring_mode":"Standard",{}"#, palette),
+                Some(FractalType::Mandelbulb)),
+        fixture("v0 params", &format!(r#""fractal_type":"Julia4D","coloring_mode":"LineTrap","palette":null,{}"#, params),
+                Some(FractalType::Julia4D)),
+        fixture("v0 derivation", r#""fractal_type":"Current","coloring_mode":"Standard","palette":null,"params":null,"derivation":1"#, None),
+        fixture("v0 registry id", &format!(r#""fractal_type":1,{},"derivation":1"#, params), Some(FractalType::Julia4D)),
+        fixture("v0 math version", &format!(r#""fractal_type":"Julia4D",{},"derivation":1,"math_version":1"#, params),
+                Some(FractalType::Julia4D)),
+        fixture("v0 curation",
+                &format!(r#""fractal_type":"Julia4D",{},{},"derivation":1,"math_version":1,"collection":"Spirals","tags":["blue","calm"],"favorite":true"#,
+                         params, palette),
+                Some(FractalType::Julia4D)),
+        fixture("v1",
+                &format!(r#""version":1,"fractal_type":"Julia4D","coloring_mode":"PlaneTrap",{},{},"derivation":1,"math_version":1,"collection":null,"tags":[],"favorite":false"#,
+                         palette, params),
+                Some(FractalType::Julia4D)),
+        fixture("v1 lineage",
+                &format!(r#""version":1,"fractal_type":"Julia4D",{},"derivation":1,"math_version":1,"lineage":{{"generation":2,"parents":[{{"seed":7,"timestamp":1717977600000,"generation":1}},{{"seed":9,"timestamp":1717977600001,"generation":0}}]}}"#,
+                         params),
+                Some(FractalType::Julia4D)),
+    ]
+}
+
+// Share links' JSON, before encoding, as every build so far has written it
+fn link_fixtures() -> Vec<Fixture> {
+    let fixture = |name, json: &str, fractal_type| Fixture { name, json: json.to_string(), fractal_type: Some(fractal_type) };
+    vec![
+        fixture("link v0 first release", r#"{"seed":42,"fractal_type":0,"complexity":150,"interactions":12}"#, FractalType::Mandelbulb),
+        fixture("link v0 params",
+                r#"{"seed":42,"fractal_type":3,"complexity":150,"interactions":12,"params":{"type":"Mandelbox","scale":-1.5,"min_radius":0.5,"iterations":12}}"#,
+                FractalType::Mandelbox),
+        fixture("link v0 derivation", r#"{"seed":42,"fractal_type":1,"complexity":150,"interactions":255,"derivation":1,"math_version":1}"#,
+                FractalType::Julia4D),
+        fixture("link v1", r#"{"v":1,"seed":42,"fractal_type":0,"complexity":150,"interactions":12,"derivation":1,"math_version":1}"#,
+                FractalType::Mandelbulb),
+        fixture("link v1 transform",
+                r#"{"v":1,"seed":42,"fractal_type":0,"complexity":150,"interactions":12,"derivation":1,"math_version":1,"t":"cRN5FLDYfnaKFooWihbNDGfmZgY"}"#,
+                FractalType::Mandelbulb),
+    ]
+}
+
+// Reads every historical shape, checks it migrated to FROZEN_VERSION with nothing it said
+// lost, then writes it and reads it back, which must give the same thing again
+#[test]
+fn frozen_shapes_migrate_and_round_trip() {
+    for fixture in frozen_fixtures() {
+        let frozen: FrozenFractal = serde_json::from_str(&fixture.json)
+            .unwrap_or_else(|e| panic!("{}: unreadable: {}", fixture.name, e));
+        assert_migrated(&fixture, &frozen);
+
+        // Every field the fixture gave comes back as it was, bar the type, which older
+        // builds wrote as a placeholder or id
+        let written = serde_json::to_value(&frozen).unwrap();
+        let original: Value = serde_json::from_str(&fixture.json).unwrap();
+        for (key, value) in original.as_object().into_iter().flatten() {
+            if key != "fractal_type" && key != "version" {
+                assert_eq!(written.get(key), Some(value), "{}: {} changed", fixture.name, key);
+            }
+        }
+
+        assert_round_trips(fixture.name, &serde_json::to_string(&frozen).unwrap(),
+                           |json| serde_json::from_str(json).unwrap());
+    }
+}
+
+#[test]
+fn link_shapes_migrate_and_round_trip() {
+    for fixture in link_fixtures() {
+        // The codec gives back exactly what it was given, and never makes a link longer
+        // than base64 of the plain JSON would
+        let encoded = codec::encode(&fixture.json);
+        assert_eq!(codec::decode(&encoded).as_deref(), Ok(fixture.json.as_str()), "{}", fixture.name);
+        assert!(encoded.len() <= (fixture.json.len() + 1).div_ceil(3) * 4,
+                "{}: encoded {} bytes of JSON in {}", fixture.name, fixture.json.len(), encoded.len());
+
+        let frozen = network::decode_compact(&fixture.json, 0)
+            .unwrap_or_else(|e| panic!("{}: unreadable: {}", fixture.name, e));
+        assert_migrated(&fixture, &frozen);
+        assert_round_trips(fixture.name, &serde_json::to_string(&frozen).unwrap(), |json| {
+            let frozen: FrozenFractal = serde_json::from_str(json).unwrap();
+            network::decode_compact(&network::encode_compact(&frozen), 0).unwrap()
+        });
+    }
+}
+
+// A link is opened only with params the renderer can draw; one carrying more iterations
+// than it bounds, as only a hand-made link could, is refused
+#[test]
+fn links_past_the_bounds_are_refused() {
+    let link = |iterations: u32| format!(
+        r#"{{"seed":42,"fractal_type":3,"complexity":150,"interactions":12,"params":{{"type":"Mandelbox","scale":-1.5,"min_radius":0.5,"iterations":{}}}}}"#,
+        iterations
+    );
+    assert!(network::decode_compact(&link(12), 0).is_ok());
+    assert!(network::decode_compact(&link(i32::MAX as u32), 0).is_err());
+}
+
+// Poses across the range gestures can reach, each shared and opened again
+#[test]
+fn link_transforms_keep_their_precision() {
+    let mut fractal: FrozenFractal = serde_json::from_str(&frozen_fixtures()[5].json).unwrap();
+    let poses = [
+        (UnitQuaternion::identity(), Vector3::new(0.5, 0.5, 0.5), Vector3::zeros()),
+        (UnitQuaternion::from_euler_angles(0.3, -1.2, 2.9), Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.25, -1.0, 4.0)),
+        (UnitQuaternion::from_euler_angles(3.1, 0.0, -3.1), Vector3::new(10.0, 10.0, 2.5), Vector3::new(-3.0, 3.0, -2.0)),
+        (UnitQuaternion::from_euler_angles(-0.7, 1.5, 0.01), Vector3::new(0.1, 0.4, 0.2), Vector3::new(5.0, 0.0, 0.0)),
+    ];
+    for (rotation, scale, translation) in poses {
+        let transform = Translation3::from(translation).to_homogeneous() * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&scale);
+        fractal.transform_matrix = UserState::matrix_data(&transform);
+        let opened = network::decode_compact(&network::encode_compact(&fractal), 0).unwrap();
+        let error = fractal.transform_matrix.iter().zip(&opened.transform_matrix)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error.is_finite() && error <= MAX_LINK_TRANSFORM_ERROR,
+                "Transform {:?} came back {} off", fractal.transform_matrix, error);
+    }
+}
+
+// A fractal with everything a link can carry, its payload as the URL will hold it
+#[test]
+fn links_stay_short() {
+    let mut fractal: FrozenFractal = serde_json::from_str(&frozen_fixtures()[5].json).unwrap();
+    let pose = Pose::from_matrix(&(Translation3::new(1.5, -2.25, 0.75).to_homogeneous()
+        * UnitQuaternion::from_euler_angles(0.4, 1.1, -2.0).to_homogeneous()
+        * Matrix4::new_nonuniform_scaling(&Vector3::new(1.8, 2.4, 1.2))));
+    fractal.transform_matrix = UserState::matrix_data(&pose.to_matrix());
+    let payload = codec::encode(&network::encode_compact(&fractal));
+    assert!(payload.len() <= MAX_LINK_PAYLOAD, "{} characters, at most {}: {}", payload.len(), MAX_LINK_PAYLOAD, payload);
+}
+
+// Transforms are saved row by row, so a translation is the last of each of the first
+// three rows, and read back as they were written
+#[test]
+fn transforms_are_saved_row_by_row() {
+    let transform = Translation3::new(1.5, -2.25, 0.75).to_homogeneous()
+        * UnitQuaternion::from_euler_angles(0.4, 1.1, -2.0).to_homogeneous();
+    let saved = UserState::matrix_data(&transform);
+    assert_eq!([saved[3], saved[7], saved[11]], [1.5, -2.25, 0.75]);
+    assert_eq!(&saved[12..], &[0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(UserState::from_matrix_data(&saved), transform);
+}
+
+fn assert_migrated(fixture: &Fixture, frozen: &FrozenFractal) {
+    assert_eq!(frozen.version, FROZEN_VERSION, "{}", fixture.name);
+    let expected = fixture.fractal_type
+        .unwrap_or_else(|| FractalParams::from_seed_with(frozen.seed, frozen.derivation).fractal_type());
+    assert_eq!(frozen.fractal_type, Some(expected), "{}", fixture.name);
+    assert_eq!(frozen.transform_matrix.len(), 16, "{}", fixture.name);
+}
+
+// Reading `json` again with `read` and writing the result must reproduce it exactly
+fn assert_round_trips(name: &str, json: &str, read: impl Fn(&str) -> FrozenFractal) {
+    assert_eq!(serde_json::to_string(&read(json)).unwrap(), json, "{}: round trip changed it", name);
+}
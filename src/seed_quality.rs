@@ -0,0 +1,106 @@
+use nalgebra::Vector4;
+use serde::Serialize;
+use crate::fractals::*;
+use crate::palette::Palette;
+use crate::rng::SeededRng;
+
+// SeededRng stream for alternate seeds
+const ALTERNATE_STREAM: u64 = 0xA17E_0001;
+
+// Alternates tried after a degenerate daily seed
+const MAX_ALTERNATES: u32 = 8;
+
+// Evaluation grid: GRID samples per axis over a cube of half-size EXTENT, which holds
+// everything the camera normally frames
+const GRID: usize = 10;
+const EXTENT: f32 = 2.5;
+
+// Distance estimates at or below this count as inside the set; escape-time formulas
+// report zero (or less) wherever the orbit stays bounded
+const INSIDE_EPSILON: f32 = 1e-3;
+
+// Fewer samples outside than this reads as a solid blob; fewer inside or within half a
+// cell of the surface than MIN_COVERAGE as an empty view. Thin line drawings (LSystem)
+// come near only a handful of samples, so the latter is kept low
+const MIN_ESCAPE_RATIO: f32 = 0.05;
+const MIN_COVERAGE: f32 = 0.002;
+
+// Below this the distance field is flat (e.g. NaN or zero nearly everywhere)
+const MIN_DE_VARIANCE: f32 = 1e-4;
+
+// How the day's fractal looks from a coarse sweep of its distance field, and which seed
+// it ended up with
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct SeedQuality {
+    pub seed: u32,          // Seed in use; differs from original_seed when an alternate was picked
+    pub original_seed: u32,
+    pub alternates_tried: u32,
+    pub escape_ratio: f32,  // Share of samples outside the set
+    pub coverage: f32,      // Share of samples inside the set or within half a cell of it
+    pub de_variance: f32,   // Of the distance estimates, capped at EXTENT
+    pub score: f32,         // 0..1; highest with the set near about half the samples
+    pub degenerate: bool,   // Only true when every alternate was degenerate as well
+}
+
+impl SeedQuality {
+    pub fn evaluate(seed: u32) -> Self {
+        let params = FractalParams::from_seed(seed);
+        let fractal = create_fractal_from_params(&params, 0.0, ColoringMode::Standard, Palette::from_seed(seed));
+        let spacing = 2.0 * EXTENT / GRID as f32;
+
+        let mut distances = Vec::with_capacity(GRID * GRID * GRID);
+        for i in 0..GRID * GRID * GRID {
+            let coord = |n: usize| -EXTENT + (n as f32 + 0.5) * spacing;
+            let point = Vector4::new(coord(i % GRID), coord(i / GRID % GRID), coord(i / (GRID * GRID)), 0.0);
+            let distance = fractal.distance_estimator(&point);
+            if distance.is_finite() {
+                distances.push(distance.max(0.0).min(EXTENT));
+            }
+        }
+
+        if distances.is_empty() {
+            return Self::scored(seed, 0.0, 0.0, 0.0);
+        }
+
+        let share = |count: usize| count as f32 / distances.len() as f32;
+        let outside = distances.iter().filter(|&&d| d > INSIDE_EPSILON).count();
+        let covered = distances.iter().filter(|&&d| d <= spacing * 0.5).count();
+        let mean = distances.iter().sum::<f32>() / distances.len() as f32;
+        let variance = distances.iter().map(|d| (d - mean) * (d - mean)).sum::<f32>() / distances.len() as f32;
+        Self::scored(seed, share(outside), share(covered), variance)
+    }
+
+    // The daily seed if it is usable, otherwise the first usable one of a fixed sequence
+    // of alternates derived from it (or the best of them if none are), so every device
+    // lands on the same fractal
+    pub fn choose(seed: u32) -> Self {
+        let mut best = Self::evaluate(seed);
+        let mut rng = SeededRng::new(seed as u64, ALTERNATE_STREAM);
+        let mut tried = 0;
+
+        while best.degenerate && tried < MAX_ALTERNATES {
+            tried += 1;
+            let alternate = Self::evaluate(rng.next_u32());
+            if !alternate.degenerate || alternate.score > best.score {
+                best = alternate;
+            }
+        }
+
+        SeedQuality { original_seed: seed, alternates_tried: tried, ..best }
+    }
+
+    fn scored(seed: u32, escape_ratio: f32, coverage: f32, de_variance: f32) -> Self {
+        let degenerate = escape_ratio < MIN_ESCAPE_RATIO || coverage < MIN_COVERAGE || de_variance < MIN_DE_VARIANCE;
+        let balance = 4.0 * coverage * (1.0 - coverage);
+        SeedQuality {
+            seed,
+            original_seed: seed,
+            alternates_tried: 0,
+            escape_ratio,
+            coverage,
+            de_variance,
+            score: if degenerate { 0.0 } else { balance * de_variance / (de_variance + 0.01) },
+            degenerate,
+        }
+    }
+}
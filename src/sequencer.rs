@@ -0,0 +1,203 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioNode};
+use nalgebra::Vector4;
+use serde::Serialize;
+use crate::fractals::{FractalGenerator, OrbitStats};
+use crate::clock::Clock;
+
+// Steps in one bar of the pattern
+pub const STEPS: usize = 16;
+
+// How far ahead of the audio clock steps are scheduled; frames arrive every ~16ms but
+// can stall, and anything scheduled is played on time regardless
+const LOOKAHEAD: f64 = 0.12;
+
+// Iterations per sampled orbit, as for the audio analysis
+const RHYTHM_ITERATIONS: i32 = 31;
+
+// Radius of the ring the steps are sampled around, through the busy edge of most sets
+const RING_RADIUS: f32 = 1.2;
+
+// Heartbeat range: calm fractals rest near the bottom, busy ones race toward the top
+const MIN_TEMPO: f32 = 56.0;
+const MAX_TEMPO: f32 = 96.0;
+
+// Length of each percussive hit, attack and decay included
+const HIT_LENGTH: f64 = 0.3;
+
+// Seconds for a hit's visual pulse to fall to 1/e; shorter than the thump's tail, which
+// the eye would read as a blur
+const PULSE_DECAY: f64 = 0.08;
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct Step {
+    pub trigger: bool,
+    pub accent: f32, // 0..1, how hard the hit lands
+    pub pitch: f32,  // Hz the hit settles to after its initial drop
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Pattern {
+    pub tempo: f32, // Beats per minute, four steps to the beat
+    pub steps: [Step; STEPS],
+}
+
+impl Pattern {
+    // Orbits sampled around a ring become the bar: how many of them escape sets the number
+    // of hits, spread evenly (a Euclidean rhythm) and rotated so the liveliest orbit lands
+    // on a hit; the longer an orbit lingers the harder and lower its hit, and the longer
+    // the orbits linger overall the faster the tempo
+    pub fn from_fractal(fractal: &dyn FractalGenerator) -> Self {
+        let stats: Vec<OrbitStats> = (0..STEPS)
+            .map(|i| {
+                let angle = i as f32 / STEPS as f32 * std::f32::consts::TAU;
+                let point = Vector4::new(
+                    RING_RADIUS * angle.cos(),
+                    RING_RADIUS * angle.sin(),
+                    0.3 * (2.0 * angle).sin(),
+                    0.0,
+                );
+                fractal.orbit_stats(&point, RHYTHM_ITERATIONS)
+            })
+            .collect();
+
+        // How long each orbit lingers, 0..1: escape-time orbits by their escape time,
+        // bounded ones and the IFS formulas (which never bail out) by how far in they reach
+        let depth = |s: &OrbitStats| {
+            let depth = if s.escaped {
+                s.smooth_iteration / RHYTHM_ITERATIONS as f32
+            } else {
+                1.0 - s.min_distance / RING_RADIUS
+            };
+            depth.max(0.0).min(1.0)
+        };
+        let depths: Vec<f32> = stats.iter().map(depth).collect();
+
+        // Accents spread over the bar's own range, so even a fractal whose orbits all
+        // behave alike gets light and heavy hits
+        let lowest = depths.iter().cloned().fold(f32::MAX, f32::min);
+        let highest = depths.iter().cloned().fold(f32::MIN, f32::max);
+        let activity: Vec<f32> = depths.iter().map(|d| (d - lowest) / (highest - lowest).max(1e-3)).collect();
+
+        let escaped = stats.iter().filter(|s| s.escaped).count();
+        let hits = 3 + escaped * 6 / STEPS;
+        let liveliest = activity.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+
+        let mut steps = [Step::default(); STEPS];
+        for (i, step) in steps.iter_mut().enumerate() {
+            // Bresenham-style Euclidean spacing, with the liveliest step always a hit
+            let shifted = (i + STEPS - liveliest) % STEPS;
+            step.trigger = shifted * hits % STEPS < hits;
+            step.accent = 0.35 + 0.65 * activity[i];
+            step.pitch = 55.0 + 55.0 * (1.0 - activity[i]);
+        }
+
+        let mean_depth = depths.iter().sum::<f32>() / STEPS as f32;
+        Pattern {
+            tempo: MIN_TEMPO + (MAX_TEMPO - MIN_TEMPO) * mean_depth.sqrt(),
+            steps,
+        }
+    }
+
+    fn step_length(&self) -> f64 {
+        60.0 / self.tempo as f64 / 4.0
+    }
+}
+
+// Plays a Pattern on the shared Clock: each frame schedules the steps due within the
+// lookahead window, so timing doesn't depend on the frame rate, and remembers them so the
+// visuals can pulse on the same beat
+pub struct Sequencer {
+    pattern: Option<Pattern>,
+    next_step: usize,
+    next_step_time: f64, // Clock time
+    hits: Vec<(f64, f32)>, // Clock time and accent of the hits scheduled, until they fade
+    pub enabled: bool,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer {
+            pattern: None,
+            next_step: 0,
+            next_step_time: 0.0,
+            hits: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    pub fn pattern(&self) -> Option<&Pattern> {
+        self.pattern.as_ref()
+    }
+
+    // The bar keeps its place, so a new pattern takes over without a stumble
+    pub fn set_pattern(&mut self, pattern: Pattern) {
+        self.pattern = Some(pattern);
+    }
+
+    // How strongly the beat is landing at `time` on the clock, 0..1: the accent of the
+    // latest hit, decaying from the moment it sounds
+    pub fn pulse(&self, time: f64) -> f32 {
+        self.hits.iter()
+            .filter(|&&(when, _)| when <= time)
+            .map(|&(when, accent)| accent * (-(time - when) / PULSE_DECAY).exp() as f32)
+            .fold(0.0, f32::max)
+    }
+
+    pub fn schedule(&mut self, context: &AudioContext, output: &AudioNode, clock: &Clock) -> Result<(), JsValue> {
+        let Some(pattern) = &self.pattern else {
+            return Ok(());
+        };
+        if !self.enabled {
+            return Ok(());
+        }
+
+        // After a stall (e.g. a background tab) pick up from now rather than replay the gap
+        let now = clock.now();
+        self.hits.retain(|&(when, _)| when > now - HIT_LENGTH);
+        if self.next_step_time < now {
+            self.next_step_time = now;
+        }
+
+        while self.next_step_time < now + LOOKAHEAD {
+            // Only while the clock is following the audio can a step be placed on it
+            let Some(when) = clock.to_audio(self.next_step_time) else {
+                return Ok(());
+            };
+            let step = pattern.steps[self.next_step];
+            if step.trigger {
+                Self::hit(context, output, &step, when)?;
+                self.hits.push((self.next_step_time, step.accent));
+            }
+            self.next_step_time += pattern.step_length();
+            self.next_step = (self.next_step + 1) % STEPS;
+        }
+        Ok(())
+    }
+
+    // A short thump: a sine dropping an octave onto the step's pitch under a fast
+    // attack and exponential decay
+    fn hit(context: &AudioContext, output: &AudioNode, step: &Step, when: f64) -> Result<(), JsValue> {
+        let oscillator = context.create_oscillator()?;
+        let gain = context.create_gain()?;
+
+        let frequency = oscillator.frequency();
+        frequency.set_value_at_time(step.pitch * 2.0, when)?;
+        frequency.exponential_ramp_to_value_at_time(step.pitch, when + 0.05)?;
+
+        let envelope = gain.gain();
+        envelope.set_value_at_time(0.0, when)?;
+        envelope.linear_ramp_to_value_at_time(0.3 * step.accent, when + 0.005)?;
+        envelope.exponential_ramp_to_value_at_time(0.001, when + HIT_LENGTH)?;
+
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(output)?;
+        oscillator.start_with_when(when)?;
+        oscillator.stop_with_when(when + HIT_LENGTH)?;
+        Ok(())
+    }
+}
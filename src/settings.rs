@@ -0,0 +1,53 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram};
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum RenderMode {
+    #[default]
+    #[serde(rename = "3d")]
+    Raymarched,
+    // Escape-time plane fractal; a fraction of the raymarcher's cost, for very old phones
+    #[serde(rename = "2d")]
+    Plane,
+}
+
+// Host-tunable renderer knobs; missing JSON fields keep their defaults
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RenderSettings {
+    // 0.0 = uniform quality, 1.0 = strongest step/precision falloff toward the screen edges
+    pub foveation: f32,
+    // Switch to the slower dual-float shader path once zoomed past f32 precision
+    pub deep_zoom: bool,
+    pub mode: RenderMode,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            foveation: 0.0,
+            deep_zoom: false,
+            mode: RenderMode::Raymarched,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn from_json(json: &str) -> Result<Self, JsValue> {
+        let mut settings: RenderSettings = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid render settings: {}", e)))?;
+        settings.foveation = settings.foveation.max(0.0).min(1.0);
+        Ok(settings)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram) {
+        if let Some(loc) = gl.get_uniform_location(program, "u_foveation") {
+            gl.uniform1f(Some(&loc), self.foveation);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram};
+use nalgebra::Vector3;
+
+// Half-space cut through the fractal: everything on the normal's side of the plane is
+// removed so the internal structure shows. The offset can sweep back and forth over time.
+pub struct SlicePlane {
+    pub normal: Vector3<f32>,
+    pub offset: f32,
+    pub enabled: bool,
+    pub sweep_speed: f32,
+    pub sweep_amplitude: f32,
+}
+
+impl Default for SlicePlane {
+    fn default() -> Self {
+        SlicePlane {
+            normal: Vector3::new(0.0, 0.0, -1.0), // Facing the camera
+            offset: 0.0,
+            enabled: false,
+            sweep_speed: 0.0,
+            sweep_amplitude: 0.0,
+        }
+    }
+}
+
+impl SlicePlane {
+    pub fn set(&mut self, normal: &[f32], offset: f32, enabled: bool) -> Result<(), JsValue> {
+        if normal.len() != 3 {
+            return Err(JsValue::from_str("Slice normal needs exactly 3 components"));
+        }
+
+        let normal = Vector3::new(normal[0], normal[1], normal[2]);
+        if normal.norm() < 1e-6 {
+            return Err(JsValue::from_str("Slice normal must not be zero"));
+        }
+
+        self.normal = normal.normalize();
+        self.offset = offset;
+        self.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn set_sweep(&mut self, speed: f32, amplitude: f32) {
+        self.sweep_speed = speed;
+        self.sweep_amplitude = amplitude.abs();
+    }
+
+    pub fn offset_at(&self, time: f32) -> f32 {
+        self.offset + (time * self.sweep_speed).sin() * self.sweep_amplitude
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram, time: f32) {
+        if let Some(loc) = gl.get_uniform_location(program, "u_slice_enabled") {
+            gl.uniform1i(Some(&loc), self.enabled as i32);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_slice_normal") {
+            gl.uniform3f(Some(&loc), self.normal.x, self.normal.y, self.normal.z);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_slice_offset") {
+            gl.uniform1f(Some(&loc), self.offset_at(time));
+        }
+    }
+}
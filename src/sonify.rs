@@ -0,0 +1,139 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, AudioNode, PanningModelType};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use crate::fractals::FractalGenerator;
+use crate::zoom::ZoomState;
+
+// The grid the view is read in: each row is one beat of the sweep, each column one voice
+// placed left to right
+pub const SCAN_ROWS: usize = 16;
+pub const SCAN_COLUMNS: usize = 12;
+
+// Same camera as FRAGMENT_SHADER's main() and render_fractal_cpu
+const VIEW_SCALE: f32 = 2.5;
+const CAMERA_DISTANCE: f32 = 4.0;
+const MAX_STEPS: i32 = 64;
+const HIT_EPSILON: f32 = 0.002;
+const MAX_DISTANCE: f32 = 15.0;
+
+// Ray lengths mapped from nearest (closeness 1) to farthest (0); the fractals sit within
+// a couple of units of the origin, four in front of the camera
+const NEAR: f32 = 2.0;
+const FAR: f32 = 6.0;
+
+// Closeness 0 plays the low note and 1 three octaves up
+const LOW_NOTE: f32 = 220.0;
+const OCTAVES: f32 = 3.0;
+
+// Seconds each row's notes take to fade in and out, so the rows sound as separate beats
+const EDGE: f64 = 0.02;
+
+// How near the geometry is in each cell of a coarse grid over the view, top row first:
+// 1 for a surface right at the front (or inside a plane set), toward 0 for the far side,
+// None where the view shows nothing
+pub struct ViewScan {
+    pub rows: Vec<[Option<f32>; SCAN_COLUMNS]>,
+}
+
+impl ViewScan {
+    // `width` and `height` are the canvas's, for the view's aspect
+    pub fn read(fractal: &dyn FractalGenerator, zoom: &ZoomState, transform: &Matrix4<f32>, width: i32, height: i32) -> Self {
+        let min_side = width.min(height).max(1) as f32;
+        let half_width = 0.5 * width.max(1) as f32 / min_side;
+        let half_height = 0.5 * height.max(1) as f32 / min_side;
+        let center = zoom.center.cast::<f32>();
+        let scale = zoom.zoom as f32;
+        let planar = fractal.get_params().is_planar();
+        // A cell's width in fractal space, how close a plane set must pass to be heard
+        let cell = 2.0 * half_width * VIEW_SCALE / scale / SCAN_COLUMNS as f32;
+
+        let rows = (0..SCAN_ROWS).map(|row| {
+            let mut cells = [None; SCAN_COLUMNS];
+            let uv_y = half_height - (row as f32 + 0.5) / SCAN_ROWS as f32 * 2.0 * half_height;
+            for (column, closeness) in cells.iter_mut().enumerate() {
+                let uv_x = (column as f32 + 0.5) / SCAN_COLUMNS as f32 * 2.0 * half_width - half_width;
+                *closeness = if planar {
+                    // As FRAGMENT_SHADER_2D places the plane
+                    let p = center.xy() + Vector2::new(uv_x, uv_y) * VIEW_SCALE / scale;
+                    let p = transform * Vector4::new(p.x, p.y, 0.0, 1.0);
+                    let distance = fractal.distance_estimator(&Vector4::new(p.x, p.y, 0.0, 0.0));
+                    (distance < cell).then(|| 1.0 - distance.max(0.0) / cell)
+                } else {
+                    march(fractal, &center, scale, uv_x, uv_y).map(|t| 1.0 - ((t - NEAR) / (FAR - NEAR)).max(0.0).min(1.0))
+                };
+            }
+            cells
+        }).collect();
+
+        ViewScan { rows }
+    }
+
+    // Plays the scan from `start` over `seconds`, a row at a time from the top: each
+    // column is a tone panned to its place across the view, higher and louder the nearer
+    // its geometry, and silent where there is none
+    pub fn play(&self, context: &AudioContext, output: &AudioNode, start: f64, seconds: f64) -> Result<(), JsValue> {
+        let row_length = seconds / SCAN_ROWS as f64;
+        let end = start + seconds;
+
+        for column in 0..SCAN_COLUMNS {
+            let oscillator = context.create_oscillator()?;
+            let gain = context.create_gain()?;
+            let panner = context.create_panner()?;
+
+            // Equal-power panning around the listener, hard left to hard right
+            let angle = ((column as f32 + 0.5) / SCAN_COLUMNS as f32 - 0.5) * std::f32::consts::PI;
+            panner.set_panning_model(PanningModelType::Equalpower);
+            panner.position_x().set_value(angle.sin());
+            panner.position_z().set_value(-angle.cos());
+
+            let frequency = oscillator.frequency();
+            let level = gain.gain();
+            level.set_value_at_time(0.0, start)?;
+            for (row, cells) in self.rows.iter().enumerate() {
+                let Some(closeness) = cells[column] else {
+                    continue;
+                };
+                let row_start = start + row as f64 * row_length;
+                let row_end = row_start + row_length;
+                frequency.set_value_at_time(LOW_NOTE * 2f32.powf(closeness * OCTAVES), row_start)?;
+                level.set_value_at_time(0.0, row_start)?;
+                level.linear_ramp_to_value_at_time(loudness(closeness), row_start + EDGE)?;
+                level.set_value_at_time(loudness(closeness), row_end - 2.0 * EDGE)?;
+                level.linear_ramp_to_value_at_time(0.0, row_end - EDGE)?;
+            }
+
+            oscillator.connect_with_audio_node(&gain)?;
+            gain.connect_with_audio_node(&panner)?;
+            panner.connect_with_audio_node(output)?;
+            oscillator.start_with_when(start)?;
+            oscillator.stop_with_when(end)?;
+        }
+        Ok(())
+    }
+}
+
+// Shared between the columns, so a row full of geometry is no louder than one voice
+fn loudness(closeness: f32) -> f32 {
+    0.6 / SCAN_COLUMNS as f32 * (0.4 + 0.6 * closeness)
+}
+
+// Length of the view ray through (uv_x, uv_y) to the surface, marched as FRAGMENT_SHADER
+// does: in view space, with the zoom shrinking the fractal's distances
+fn march(fractal: &dyn FractalGenerator, center: &Vector3<f32>, zoom: f32, uv_x: f32, uv_y: f32) -> Option<f32> {
+    let origin = Vector3::new(uv_x * VIEW_SCALE, uv_y * VIEW_SCALE, -CAMERA_DISTANCE);
+    let dir = Vector3::new(uv_x * 0.6, uv_y * 0.6, 1.0).normalize();
+    let mut t = 0.0f32;
+
+    for _ in 0..MAX_STEPS {
+        let pos = center + (origin + dir * t) / zoom;
+        let dist = fractal.distance_estimator(&Vector4::new(pos.x, pos.y, pos.z, 0.0));
+        if dist < HIT_EPSILON {
+            return Some(t);
+        }
+        t += dist * 0.9;
+        if !t.is_finite() || t > MAX_DISTANCE {
+            break;
+        }
+    }
+    None
+}
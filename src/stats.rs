@@ -0,0 +1,125 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, HtmlCanvasElement};
+use serde::Serialize;
+
+// Loop bound and foveation curve from FRAGMENT_SHADER
+const MAX_RAYMARCH_STEPS: f32 = 80.0;
+const FOVEA_RADIUS: f32 = 0.7;
+const MIN_FOVEATED_STEPS: f32 = 0.35;
+
+// Rays that hit usually stop well before the loop bound; rough average from profiling
+const AVERAGE_STEP_FRACTION: f32 = 0.5;
+
+// Weight of the newest frame in the smoothed frame time
+const SMOOTHING: f32 = 0.1;
+
+// Refresh the overlay text every N frames rather than thrashing the DOM
+const OVERLAY_REFRESH_FRAMES: u64 = 15;
+
+#[derive(Serialize, Clone, Default)]
+pub struct RenderStats {
+    pub frame_time_ms: f32, // Smoothed CPU-side frame interval
+    pub fps: f32,
+    pub estimated_raymarch_steps: u64,
+    pub draw_calls: u32,
+    pub resolution_scale: f32, // Drawing buffer pixels per physical screen pixel
+    pub width: i32,
+    pub height: i32,
+    pub frames: u64,
+}
+
+impl RenderStats {
+    pub fn record_frame(&mut self, delta_ms: f32, width: i32, height: i32, draw_calls: u32,
+                        foveation: f32, resolution_scale: f32) {
+        if delta_ms > 0.0 {
+            self.frame_time_ms = if self.frames == 0 {
+                delta_ms
+            } else {
+                self.frame_time_ms + (delta_ms - self.frame_time_ms) * SMOOTHING
+            };
+            self.fps = 1000.0 / self.frame_time_ms;
+        }
+
+        let pixels = (width.max(0) as u64) * (height.max(0) as u64);
+        let steps_per_pixel = MAX_RAYMARCH_STEPS * AVERAGE_STEP_FRACTION * foveated_step_fraction(foveation, width, height);
+        self.estimated_raymarch_steps = (pixels as f32 * steps_per_pixel) as u64;
+
+        self.draw_calls = draw_calls;
+        self.resolution_scale = resolution_scale;
+        self.width = width;
+        self.height = height;
+        self.frames += 1;
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+// Mean step budget relative to unfoveated rendering, sampled on a coarse screen grid
+fn foveated_step_fraction(foveation: f32, width: i32, height: i32) -> f32 {
+    if foveation <= 0.0 || width <= 0 || height <= 0 {
+        return 1.0;
+    }
+
+    const GRID: i32 = 16;
+    let min_side = width.min(height) as f32;
+    let mut total = 0.0;
+
+    for gy in 0..GRID {
+        for gx in 0..GRID {
+            let x = (gx as f32 + 0.5) / GRID as f32 * width as f32 - 0.5 * width as f32;
+            let y = (gy as f32 + 0.5) / GRID as f32 * height as f32 - 0.5 * height as f32;
+            let eccentricity = ((x * x + y * y).sqrt() / min_side / FOVEA_RADIUS).min(1.0);
+            let quality = 1.0 - foveation * eccentricity * eccentricity;
+            total += MIN_FOVEATED_STEPS + (1.0 - MIN_FOVEATED_STEPS) * quality;
+        }
+    }
+
+    total / (GRID * GRID) as f32
+}
+
+// Small text panel layered over the canvas
+pub struct StatsOverlay {
+    element: Element,
+}
+
+impl StatsOverlay {
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<Self, JsValue> {
+        let document = canvas.owner_document().ok_or("Canvas is not attached to a document")?;
+        let element = document.create_element("div")?;
+        element.set_attribute("style", concat!(
+            "position:absolute;top:8px;left:8px;z-index:1000;pointer-events:none;",
+            "padding:4px 6px;background:rgba(0,0,0,0.6);color:#7f7;",
+            "font:11px monospace;white-space:pre;"
+        ))?;
+
+        let parent = canvas.parent_element().ok_or("Canvas has no parent element")?;
+        parent.append_child(&element)?;
+
+        Ok(StatsOverlay { element })
+    }
+
+    pub fn update(&self, stats: &RenderStats) {
+        if stats.frames % OVERLAY_REFRESH_FRAMES != 1 {
+            return;
+        }
+
+        self.element.set_text_content(Some(&format!(
+            "{:.1} fps  {:.2} ms\n{}x{} @ {:.2}x\n~{:.1}M steps  {} draws",
+            stats.fps,
+            stats.frame_time_ms,
+            stats.width,
+            stats.height,
+            stats.resolution_scale,
+            stats.estimated_raymarch_steps as f32 / 1.0e6,
+            stats.draw_calls,
+        )));
+    }
+}
+
+impl Drop for StatsOverlay {
+    fn drop(&mut self) {
+        self.element.remove();
+    }
+}
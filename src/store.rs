@@ -0,0 +1,412 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use serde::Serialize;
+use web_sys::{DomException, Event, IdbDatabase, IdbOpenDbRequest, IdbTransaction, IdbTransactionMode, IdbVersionChangeEvent, Storage, StorageEstimate};
+
+const DB_NAME: &str = "resonant";
+const RECORDS: &str = "records";
+
+// Schema version; raising it runs upgrade() from whatever version the browser has
+const DB_VERSION: u32 = 1;
+
+// localStorage keys that grow with use, moved into the database the first time it opens.
+// The user id and the preferences stay behind: the daily seed is needed before the first
+// frame, sooner than the database can answer
+const MIGRATED_PREFIXES: [&str; 10] = [
+    "resonant_transform_", "resonant_history_", "resonant_interactions_", "resonant_day_", "resonant_gestures_",
+    "resonant_frozen_fractals", "resonant_achievements", "resonant_usage", "resonant_ladder", "resonant_golden",
+];
+
+// Share of the origin's quota (navigator.storage.estimate) the records may fill before
+// the oldest days are evicted; the browser counts more than the text itself
+const QUOTA_SHARE: f64 = 0.5;
+// Budget until the estimate comes in, or in browsers that give none
+const DEFAULT_BUDGET: f64 = 50.0 * 1024.0 * 1024.0;
+// Budget in localStorage, which most browsers cap at five million characters, two bytes
+// each, shared with the user id and preferences
+const LOCAL_STORAGE_BUDGET: f64 = 8.0 * 1024.0 * 1024.0;
+
+type EventClosure = Closure<dyn FnMut(Event)>;
+
+// A write that failed, for the host to hear about; nothing is retried, but the next save
+// of the same state writes it again
+#[derive(Serialize, Clone, Debug)]
+pub struct StorageError {
+    pub kind: String,        // "quota" when the browser ran out of room, "write" otherwise
+    pub key: Option<String>, // The record that wasn't saved, where it's known
+    pub message: String,
+}
+
+impl StorageError {
+    pub fn new(key: Option<&str>, error: &JsValue) -> Self {
+        let exception = error.dyn_ref::<DomException>();
+        let name = exception.map(|e| e.name()).unwrap_or_default();
+        // Firefox named it differently before QuotaExceededError was standard
+        let quota = name == "QuotaExceededError" || name == "NS_ERROR_DOM_QUOTA_REACHED";
+        StorageError {
+            kind: if quota { "quota" } else { "write" }.to_string(),
+            key: key.map(str::to_string),
+            message: exception.map(|e| e.message()).or_else(|| error.as_string()).unwrap_or_else(|| format!("{:?}", error)),
+        }
+    }
+
+    pub fn is_quota(&self) -> bool {
+        self.kind == "quota"
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    db: Option<IdbDatabase>,
+    // Every record as last written or read, for export
+    records: HashMap<String, String>,
+    // Every record, read once the database opens, until taken
+    loaded: Option<HashMap<String, String>>,
+    ready: bool, // The records have been read, whether or not they've been taken
+    // Writes made before the database opened, applied once it does
+    pending: Vec<(String, String)>,
+    // localStorage keys copied in by the upgrade, removed once it has committed
+    migrated: Vec<String>,
+    // No IndexedDB here (private browsing in some browsers), or it wouldn't open; writes
+    // go to localStorage as before
+    failed: bool,
+    used: f64,          // Estimated bytes the records take up, as UTF-16 like the browser holds them
+    quota: Option<f64>, // The origin's quota in bytes, once the browser has said
+    // What had been used when a write last ran out of room: the real limit, wherever the
+    // budget put it
+    filled: Option<f64>,
+    errors: Vec<StorageError>, // Failed writes, until taken
+    on_abort: Option<EventClosure>, // Shared by every write's transaction
+}
+
+impl Shared {
+    fn insert(&mut self, key: String, value: String) {
+        if let Some(old) = self.records.get(&key) {
+            self.used -= record_size(&key, old);
+        }
+        self.used += record_size(&key, &value);
+        self.records.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.records.remove(key) {
+            self.used -= record_size(key, &old);
+        }
+    }
+
+    fn report(&mut self, error: StorageError) {
+        if error.is_quota() {
+            self.filled = Some(self.used);
+        }
+        web_sys::console::warn_2(&JsValue::from_str("Storage write failed:"), &JsValue::from_str(&error.message));
+        self.errors.push(error);
+    }
+}
+
+// Key-value records of JSON strings in IndexedDB, which has room for far more frozen
+// fractals and history than localStorage's few megabytes. Opening and loading are
+// asynchronous: writes made meanwhile are queued, and take_loaded() hands over the
+// records once they are in
+pub struct Store {
+    shared: Rc<RefCell<Shared>>,
+    fallback: Storage,
+}
+
+impl Store {
+    pub fn open(fallback: Storage) -> Self {
+        let shared = Rc::new(RefCell::new(Shared::default()));
+        estimate_quota(shared.clone());
+
+        // A transaction that aborts takes its write with it, most often for want of room
+        let slot = shared.clone();
+        let on_abort: EventClosure = Closure::new(move |event: Event| {
+            let Some(transaction) = event.target().and_then(|target| target.dyn_into::<IdbTransaction>().ok()) else {
+                return;
+            };
+            let error = transaction.error().map_or_else(|| JsValue::from_str("Transaction aborted"), JsValue::from);
+            slot.borrow_mut().report(StorageError::new(None, &error));
+        });
+        shared.borrow_mut().on_abort = Some(on_abort);
+
+        let factory = web_sys::window().and_then(|window| window.indexed_db().ok().flatten());
+        let request = factory.and_then(|factory| factory.open_with_u32(DB_NAME, DB_VERSION).ok());
+        let Some(request) = request else {
+            fall_back(&mut shared.borrow_mut(), &fallback);
+            return Store { shared, fallback };
+        };
+
+        let (slot, opening, storage) = (shared.clone(), request.clone(), fallback.clone());
+        let on_upgrade: EventClosure = Closure::once(move |event: Event| {
+            let Some(event) = event.dyn_ref::<IdbVersionChangeEvent>() else {
+                return;
+            };
+            if let Err(e) = upgrade(&opening, event.old_version() as u32, &storage, &mut slot.borrow_mut()) {
+                web_sys::console::warn_2(&JsValue::from_str("Storage upgrade failed:"), &e);
+            }
+        });
+
+        let (slot, opening, storage) = (shared.clone(), request.clone(), fallback.clone());
+        let on_open: EventClosure = Closure::once(move |_: Event| {
+            let Ok(db) = opening.result().and_then(|db| db.dyn_into::<IdbDatabase>()) else {
+                return;
+            };
+            let mut shared = slot.borrow_mut();
+            for key in shared.migrated.drain(..) {
+                let _ = storage.remove_item(&key);
+            }
+            if let Err(e) = load(&db, slot.clone()) {
+                web_sys::console::warn_2(&JsValue::from_str("Storage load failed:"), &e);
+            }
+            shared.db = Some(db);
+            for (key, value) in std::mem::take(&mut shared.pending) {
+                put(&mut shared, &key, &value);
+            }
+        });
+
+        let (slot, storage) = (shared.clone(), fallback.clone());
+        let on_error: EventClosure = Closure::once(move |_: Event| {
+            web_sys::console::warn_1(&JsValue::from_str("IndexedDB unavailable; saving to localStorage"));
+            let mut shared = slot.borrow_mut();
+            for (key, value) in std::mem::take(&mut shared.pending) {
+                if let Err(e) = storage.set_item(&key, &value) {
+                    shared.report(StorageError::new(Some(&key), &e));
+                }
+            }
+            fall_back(&mut shared, &storage);
+        });
+
+        request.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+        request.set_onsuccess(Some(on_open.as_ref().unchecked_ref()));
+        request.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        // Each fires at most once, possibly after this Store is gone
+        on_upgrade.forget();
+        on_open.forget();
+        on_error.forget();
+
+        Store { shared, fallback }
+    }
+
+    // Whether the records have been read (or there is no database to read them from)
+    pub fn is_ready(&self) -> bool {
+        let shared = self.shared.borrow();
+        shared.ready || shared.failed
+    }
+
+    // The records as they were when the database opened; Some once only
+    pub fn take_loaded(&self) -> Option<HashMap<String, String>> {
+        self.shared.borrow_mut().loaded.take()
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.shared.borrow().records.get(key).cloned()
+    }
+
+    // Every record whose key starts with `prefix`, including writes still on their way
+    pub fn entries(&self, prefix: &str) -> Vec<(String, String)> {
+        self.shared.borrow().records.iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    // Every key that starts with `prefix`
+    pub fn keys(&self, prefix: &str) -> Vec<String> {
+        self.shared.borrow().records.keys().filter(|key| key.starts_with(prefix)).cloned().collect()
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        let mut shared = self.shared.borrow_mut();
+        shared.insert(key.to_string(), value.to_string());
+        if shared.db.is_some() {
+            put(&mut shared, key, value);
+        } else if shared.failed {
+            if let Err(e) = self.fallback.set_item(key, value) {
+                shared.report(StorageError::new(Some(key), &e));
+            }
+        } else {
+            shared.pending.retain(|(pending, _)| pending != key);
+            shared.pending.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        let mut shared = self.shared.borrow_mut();
+        shared.remove(key);
+        shared.pending.retain(|(pending, _)| pending != key);
+        if let Some(db) = &shared.db {
+            let removed = db.transaction_with_str_and_mode(RECORDS, IdbTransactionMode::Readwrite)
+                .and_then(|transaction| transaction.object_store(RECORDS))
+                .and_then(|records| records.delete(&JsValue::from_str(key)));
+            if let Err(e) = removed {
+                web_sys::console::warn_2(&JsValue::from_str("Storage delete failed:"), &e);
+            }
+        } else if shared.failed {
+            let _ = self.fallback.remove_item(key);
+        }
+    }
+
+    // Removes every record, queued writes included, and whatever of them is still in
+    // localStorage (all of them where there's no database); returns their keys, in order
+    pub fn clear(&self) -> Vec<String> {
+        let mut shared = self.shared.borrow_mut();
+        let mut keys: Vec<String> = shared.records.keys().cloned().collect();
+        shared.records.clear();
+        shared.pending.clear();
+        shared.loaded = None;
+        shared.used = 0.0;
+        shared.filled = None;
+
+        if let Some(db) = &shared.db {
+            let cleared = db.transaction_with_str_and_mode(RECORDS, IdbTransactionMode::Readwrite)
+                .and_then(|transaction| transaction.object_store(RECORDS))
+                .and_then(|records| records.clear());
+            if let Err(e) = cleared {
+                shared.report(StorageError::new(None, &e));
+            }
+        }
+        for key in migrated_keys(&self.fallback) {
+            let _ = self.fallback.remove_item(&key);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    // Estimated bytes the records take up
+    pub fn usage(&self) -> f64 {
+        self.shared.borrow().used.max(0.0)
+    }
+
+    // Bytes the records may take up before the oldest days are evicted: a share of the
+    // quota, or of localStorage's few megabytes, and never more than fit when a write last
+    // ran out of room
+    pub fn budget(&self) -> f64 {
+        let shared = self.shared.borrow();
+        let budget = if shared.failed {
+            LOCAL_STORAGE_BUDGET
+        } else {
+            shared.quota.map_or(DEFAULT_BUDGET, |quota| quota * QUOTA_SHARE)
+        };
+        shared.filled.map_or(budget, |filled| budget.min(filled))
+    }
+
+    // A failed write of the caller's own, to the localStorage this store falls back on
+    pub fn report(&self, key: &str, error: &JsValue) {
+        self.shared.borrow_mut().report(StorageError::new(Some(key), error));
+    }
+
+    // Writes that have failed since the last call
+    pub fn take_errors(&self) -> Vec<StorageError> {
+        std::mem::take(&mut self.shared.borrow_mut().errors)
+    }
+}
+
+// Both are UTF-16 in the browser, two bytes a character; the records are nearly all ASCII
+// JSON, so their length in bytes stands in for the count
+fn record_size(key: &str, value: &str) -> f64 {
+    ((key.len() + value.len()) * 2) as f64
+}
+
+// Asks the browser for the origin's quota; the default budget holds until it answers
+fn estimate_quota(shared: Rc<RefCell<Shared>>) {
+    let estimate = web_sys::window().and_then(|window| window.navigator().storage().estimate().ok());
+    let Some(estimate) = estimate else {
+        return;
+    };
+    let on_estimate: Closure<dyn FnMut(JsValue)> = Closure::once(move |estimate: JsValue| {
+        shared.borrow_mut().quota = estimate.unchecked_into::<StorageEstimate>().get_quota();
+    });
+    let _ = estimate.then(&on_estimate);
+    on_estimate.forget();
+}
+
+// Brings the database from `old_version` (0 when it's new) up to DB_VERSION, inside the
+// open request's version-change transaction
+fn upgrade(request: &IdbOpenDbRequest, old_version: u32, storage: &Storage, shared: &mut Shared) -> Result<(), JsValue> {
+    let db: IdbDatabase = request.result()?.dyn_into()?;
+    let transaction = request.transaction().ok_or("No upgrade transaction")?;
+
+    if old_version < 1 {
+        db.create_object_store(RECORDS)?;
+        let records = transaction.object_store(RECORDS)?;
+        for key in migrated_keys(storage) {
+            if let Some(value) = storage.get_item(&key)? {
+                records.put_with_key(&JsValue::from_str(&value), &JsValue::from_str(&key))?;
+                shared.migrated.push(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The records stay in localStorage, where they are already
+fn fall_back(shared: &mut Shared, storage: &Storage) {
+    shared.failed = true;
+    for key in migrated_keys(storage) {
+        if let Ok(Some(value)) = storage.get_item(&key) {
+            if !shared.records.contains_key(&key) {
+                shared.insert(key, value);
+            }
+        }
+    }
+}
+
+fn migrated_keys(storage: &Storage) -> Vec<String> {
+    (0..storage.length().unwrap_or(0))
+        .filter_map(|i| storage.key(i).ok().flatten())
+        .filter(|key| MIGRATED_PREFIXES.iter().any(|prefix| key.starts_with(prefix)))
+        .collect()
+}
+
+// Reads every record into `shared.loaded`
+fn load(db: &IdbDatabase, shared: Rc<RefCell<Shared>>) -> Result<(), JsValue> {
+    let records = db.transaction_with_str(RECORDS)?.object_store(RECORDS)?;
+    let keys = records.get_all_keys()?;
+    let values = records.get_all()?;
+
+    // Requests in one transaction complete in order, so the keys are in by now
+    let reading = values.clone();
+    let on_read: EventClosure = Closure::once(move |_: Event| {
+        let (Ok(keys), Ok(values)) = (keys.result(), reading.result()) else {
+            return;
+        };
+        let keys = js_sys::Array::from(&keys);
+        let values = js_sys::Array::from(&values);
+        let loaded: HashMap<String, String> = keys.iter().zip(values.iter())
+            .filter_map(|(key, value)| Some((key.as_string()?, value.as_string()?)))
+            .collect();
+        let mut shared = shared.borrow_mut();
+        // Anything written since the read began is newer
+        for (key, value) in &loaded {
+            if !shared.records.contains_key(key) {
+                shared.insert(key.clone(), value.clone());
+            }
+        }
+        shared.loaded = Some(loaded);
+        shared.ready = true;
+    });
+    values.set_onsuccess(Some(on_read.as_ref().unchecked_ref()));
+    on_read.forget();
+    Ok(())
+}
+
+// Only once the database is open; a quota error comes later, when the transaction aborts
+fn put(shared: &mut Shared, key: &str, value: &str) {
+    let Some(db) = &shared.db else {
+        return;
+    };
+    let written = db.transaction_with_str_and_mode(RECORDS, IdbTransactionMode::Readwrite)
+        .and_then(|transaction| {
+            transaction.set_onabort(shared.on_abort.as_ref().map(|on_abort| on_abort.as_ref().unchecked_ref()));
+            transaction.object_store(RECORDS)
+        })
+        .and_then(|records| records.put_with_key(&JsValue::from_str(value), &JsValue::from_str(key)));
+    if let Err(e) = written {
+        shared.report(StorageError::new(Some(key), &e));
+    }
+}
@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use serde::{Serialize, Deserialize};
+use web_sys::{Headers, Request, RequestInit, Response};
+use crate::user::UserState;
+
+// How often, in ms, a configured backend is synced with
+const SYNC_INTERVAL: f64 = 5.0 * 60.0 * 1000.0;
+
+// How soon, in ms, a sync that failed is tried again
+const RETRY_INTERVAL: f64 = 30.0 * 1000.0;
+
+// Pushes that find another device got there first, each merged before trying again,
+// before the sync gives up until the next one
+const MAX_PUSH_ATTEMPTS: u32 = 3;
+
+// The user's state as a backend keeps it: export_state's JSON, and the revision it was
+// stored at, one more with each push
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncDocument {
+    pub revision: u64,
+    pub updated_at: u64, // ms since the epoch
+    pub state: serde_json::Value,
+}
+
+pub enum PushOutcome {
+    Stored(u64),            // The revision it was stored at
+    Conflict(SyncDocument), // Another device pushed first; this is what it stored
+}
+
+// Filled in once a backend's request completes; until then None
+pub type Reply<T> = Rc<RefCell<Option<Result<T, JsValue>>>>;
+
+// Somewhere the state is kept off the device, so it survives cleared browser storage and
+// follows the user to another one. Both calls answer asynchronously, through the Reply
+// they return
+pub trait SyncBackend {
+    // The stored document, or None if nothing has been pushed yet
+    fn pull(&self) -> Reply<Option<SyncDocument>>;
+
+    // Stores `document` only if the stored one is still at `base_revision` (0 for none),
+    // and otherwise hands back the stored one to merge with
+    fn push(&self, document: &SyncDocument, base_revision: u64) -> Reply<PushOutcome>;
+}
+
+// A backend at an HTTP endpoint the host runs: GET returns the document (404 for none
+// yet), and PUT stores one, with the revision it replaces in If-Match, answering 409 or
+// 412 with the stored document when that isn't the one stored. The token, if any, goes
+// in a Bearer Authorization header
+pub struct RestBackend {
+    endpoint: String,
+    token: Option<String>,
+}
+
+impl RestBackend {
+    pub fn new(endpoint: &str, token: Option<String>) -> Result<Self, JsValue> {
+        if endpoint.is_empty() {
+            return Err(JsValue::from_str("No sync endpoint"));
+        }
+        Ok(RestBackend { endpoint: endpoint.to_string(), token: token.filter(|token| !token.is_empty()) })
+    }
+
+    fn send<T: 'static>(&self, method: &str, body: Option<&str>, base_revision: Option<u64>,
+                        parse: impl FnOnce(u16, String) -> Result<T, JsValue> + 'static) -> Reply<T> {
+        let headers = base_revision.map(|revision| ("If-Match", format!("\"{}\"", revision)));
+        send(request(&self.endpoint, method, self.token.as_deref(), body, headers.as_slice()), parse)
+    }
+}
+
+// A request to `url` accepting JSON, with the token if any as a Bearer Authorization
+// header and `body` if any as JSON
+pub fn request(url: &str, method: &str, token: Option<&str>, body: Option<&str>,
+               extra_headers: &[(&str, String)]) -> Result<Request, JsValue> {
+    let headers = Headers::new()?;
+    headers.set("Accept", "application/json")?;
+    if let Some(token) = token {
+        headers.set("Authorization", &format!("Bearer {}", token))?;
+    }
+    for (name, value) in extra_headers {
+        headers.set(name, value)?;
+    }
+
+    let init = RequestInit::new();
+    init.set_method(method);
+    if let Some(body) = body {
+        headers.set("Content-Type", "application/json")?;
+        init.set_body(&JsValue::from_str(body));
+    }
+    init.set_headers(&headers);
+    Request::new_with_str_and_init(url, &init)
+}
+
+// Sends the request, and reads the response's status and text into the reply with
+// `parse`
+pub fn send<T: 'static>(request: Result<Request, JsValue>,
+                        parse: impl FnOnce(u16, String) -> Result<T, JsValue> + 'static) -> Reply<T> {
+    let reply: Reply<T> = Rc::new(RefCell::new(None));
+    let fetched = request.and_then(|request| {
+        let window = web_sys::window().ok_or("No window available")?;
+        Ok(window.fetch_with_request(&request))
+    });
+    let promise = match fetched {
+        Ok(promise) => promise,
+        Err(e) => {
+            *reply.borrow_mut() = Some(Err(e));
+            return reply;
+        }
+    };
+
+    let (slot, failed) = (reply.clone(), reply.clone());
+    let on_response: Closure<dyn FnMut(JsValue)> = Closure::once(move |response: JsValue| {
+        let response: Response = response.unchecked_into();
+        let status = response.status();
+        let text = match response.text() {
+            Ok(text) => text,
+            Err(e) => {
+                *slot.borrow_mut() = Some(Err(e));
+                return;
+            }
+        };
+        let unread = slot.clone();
+        let on_text: Closure<dyn FnMut(JsValue)> = Closure::once(move |text: JsValue| {
+            *slot.borrow_mut() = Some(parse(status, text.as_string().unwrap_or_default()));
+        });
+        let on_unread: Closure<dyn FnMut(JsValue)> = Closure::once(move |error: JsValue| {
+            *unread.borrow_mut() = Some(Err(error));
+        });
+        let _ = text.then2(&on_text, &on_unread);
+        on_text.forget();
+        on_unread.forget();
+    });
+    let on_failed: Closure<dyn FnMut(JsValue)> = Closure::once(move |error: JsValue| {
+        *failed.borrow_mut() = Some(Err(error));
+    });
+    let _ = promise.then2(&on_response, &on_failed);
+    // Each fires at most once, possibly after whatever asked has moved on
+    on_response.forget();
+    on_failed.forget();
+    reply
+}
+
+impl SyncBackend for RestBackend {
+    fn pull(&self) -> Reply<Option<SyncDocument>> {
+        self.send("GET", None, None, |status, text| match status {
+            404 => Ok(None),
+            200..=299 => parse_document(&text).map(Some),
+            _ => Err(http_error(status, &text)),
+        })
+    }
+
+    fn push(&self, document: &SyncDocument, base_revision: u64) -> Reply<PushOutcome> {
+        let revision = document.revision;
+        let body = serde_json::to_string(document).unwrap();
+        self.send("PUT", Some(&body), Some(base_revision), move |status, text| match status {
+            200..=299 => Ok(PushOutcome::Stored(revision)),
+            409 | 412 => parse_document(&text).map(PushOutcome::Conflict),
+            _ => Err(http_error(status, &text)),
+        })
+    }
+}
+
+fn parse_document(text: &str) -> Result<SyncDocument, JsValue> {
+    serde_json::from_str(text).map_err(|e| JsValue::from_str(&format!("Invalid sync document: {}", e)))
+}
+
+fn http_error(status: u16, text: &str) -> JsValue {
+    JsValue::from_str(&format!("Sync failed ({}): {}", status, text))
+}
+
+// What the host hears after each sync
+#[derive(Serialize)]
+pub struct SyncStatus {
+    pub synced: bool,
+    pub revision: u64,
+    pub error: Option<String>,
+}
+
+enum Phase {
+    Idle,
+    Pulling(Reply<Option<SyncDocument>>),
+    Pushing(Reply<PushOutcome>, u32), // And the attempts so far
+}
+
+// Keeps the user's state in step with a backend: every SYNC_INTERVAL it pulls the stored
+// document, merges it in (UserState::merge_state: the newer of each day, the union of
+// frozen fractals, the better achievements), and pushes the result back. A push another
+// device beat is merged and tried again. Call poll once per frame
+pub struct CloudSync {
+    backend: Box<dyn SyncBackend>,
+    revision: u64, // Of the document last pulled or pushed
+    phase: Phase,
+    next_sync: f64, // ms since the epoch
+}
+
+impl CloudSync {
+    // Syncs as soon as the local state has loaded
+    pub fn new(backend: Box<dyn SyncBackend>) -> Self {
+        CloudSync { backend, revision: 0, phase: Phase::Idle, next_sync: 0.0 }
+    }
+
+    pub fn sync_now(&mut self) {
+        self.next_sync = 0.0;
+    }
+
+    // Moves the sync along; Some once one has finished, or failed
+    pub fn poll(&mut self, user_state: &mut UserState) -> Option<SyncStatus> {
+        let now = js_sys::Date::now();
+        match std::mem::replace(&mut self.phase, Phase::Idle) {
+            Phase::Idle => {
+                // Pushing before the local state has loaded would overwrite the stored one
+                // with an empty one, and merging would end a sandbox session
+                if now >= self.next_sync && user_state.is_store_ready() && !user_state.in_sandbox() {
+                    self.phase = Phase::Pulling(self.backend.pull());
+                }
+                None
+            }
+            Phase::Pulling(reply) => {
+                let pulled = reply.borrow_mut().take();
+                let Some(pulled) = pulled else {
+                    self.phase = Phase::Pulling(reply);
+                    return None;
+                };
+                let merged = pulled.and_then(|document| {
+                    if let Some(document) = document {
+                        user_state.merge_state(&document.state.to_string())?;
+                        self.revision = document.revision;
+                    }
+                    Ok(())
+                });
+                match merged {
+                    Ok(()) => {
+                        self.push(user_state, 1);
+                        None
+                    }
+                    Err(e) => Some(self.fail(now, e)),
+                }
+            }
+            Phase::Pushing(reply, attempts) => {
+                let pushed = reply.borrow_mut().take();
+                let Some(pushed) = pushed else {
+                    self.phase = Phase::Pushing(reply, attempts);
+                    return None;
+                };
+                match pushed {
+                    Ok(PushOutcome::Stored(revision)) => {
+                        self.revision = revision;
+                        self.next_sync = now + SYNC_INTERVAL;
+                        Some(SyncStatus { synced: true, revision, error: None })
+                    }
+                    Ok(PushOutcome::Conflict(document)) if attempts < MAX_PUSH_ATTEMPTS => {
+                        if let Err(e) = user_state.merge_state(&document.state.to_string()) {
+                            return Some(self.fail(now, e));
+                        }
+                        self.revision = document.revision;
+                        self.push(user_state, attempts + 1);
+                        None
+                    }
+                    Ok(PushOutcome::Conflict(_)) => Some(self.fail(now, JsValue::from_str("Sync kept conflicting"))),
+                    Err(e) => Some(self.fail(now, e)),
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, user_state: &UserState, attempts: u32) {
+        let state = serde_json::from_str(&user_state.export(true, false)).unwrap_or_default();
+        let document = SyncDocument { revision: self.revision + 1, updated_at: js_sys::Date::now() as u64, state };
+        self.phase = Phase::Pushing(self.backend.push(&document, self.revision), attempts);
+    }
+
+    fn fail(&mut self, now: f64, error: JsValue) -> SyncStatus {
+        web_sys::console::warn_2(&JsValue::from_str("Sync failed:"), &error);
+        self.next_sync = now + RETRY_INTERVAL;
+        SyncStatus {
+            synced: false,
+            revision: self.revision,
+            error: Some(error.as_string().unwrap_or_else(|| format!("{:?}", error))),
+        }
+    }
+}
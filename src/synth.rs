@@ -0,0 +1,145 @@
+use wasm_bindgen::prelude::*;
+use crate::audio::{AudioEngine, CROSSFADE_TIME};
+use crate::complexity;
+use crate::fractals::{create_fractal_from_params, FractalAudioAnalyzer};
+use crate::harmony::Progression;
+use crate::palette::Palette;
+use crate::sequencer::Pattern;
+use crate::user::FrozenFractal;
+
+// A frozen fractal's soundscape with no canvas or WebGL context, for hosts that only
+// want the sound (a lock-screen widget, say): the live AudioEngine, fed by the same
+// analysis Resonant::update_audio runs each frame. Like Resonant's, the audio starts with
+// the first gesture on the page
+#[wasm_bindgen]
+pub struct FractalSynth {
+    engine: AudioEngine,
+    frozen: FrozenFractal,
+    time: f32,
+    harmony_enabled: bool,
+    transition: Option<f32>, // Seconds of crossfade the next update should make
+    fitted: bool,            // Whether the effects and rhythm follow the current fractal yet
+}
+
+#[wasm_bindgen]
+impl FractalSynth {
+    // `frozen_json` as from Resonant::freeze_fractal or a share link
+    #[wasm_bindgen(constructor)]
+    pub fn new(frozen_json: &str) -> Result<FractalSynth, JsValue> {
+        Ok(FractalSynth {
+            engine: AudioEngine::new()?,
+            frozen: Self::parse(frozen_json)?,
+            time: 0.0,
+            harmony_enabled: true,
+            transition: None,
+            fitted: false,
+        })
+    }
+
+    // Crossfades to another frozen fractal's sound
+    pub fn load(&mut self, frozen_json: &str) -> Result<(), JsValue> {
+        self.frozen = Self::parse(frozen_json)?;
+        self.transition = Some(CROSSFADE_TIME);
+        self.fitted = false;
+        Ok(())
+    }
+
+    // Call regularly, at least ten times a second for the rhythm to keep time. Use a timer
+    // rather than requestAnimationFrame, which stops while the page is hidden
+    pub fn update(&mut self) {
+        self.time += self.engine.tick_clock() as f32;
+
+        let palette = self.frozen.palette.clone().unwrap_or_else(|| Palette::from_seed(self.frozen.seed));
+        let params = self.frozen.fractal_params();
+        let fractal = create_fractal_from_params(&params, self.time, self.frozen.coloring_mode, palette);
+
+        let mut frequencies = FractalAudioAnalyzer::extract_frequencies(&*fractal, &FractalAudioAnalyzer::sample_points(self.time));
+        // The chord the fractal was frozen on
+        if self.harmony_enabled {
+            let chord = *Progression::from_seed(self.frozen.seed).chord_at(self.frozen.interaction_count);
+            for frequency in &mut frequencies {
+                *frequency = chord.constrain(*frequency);
+            }
+        }
+        match self.transition.take() {
+            Some(seconds) => self.engine.transition_to(&frequencies, seconds),
+            None => self.engine.update_frequencies(&frequencies),
+        }
+        self.engine.update_ambience(self.frozen.seed);
+        self.engine.update_modulation(self.frozen.seed);
+
+        // A frozen fractal doesn't change, so its effects and rhythm are fitted once
+        if !self.fitted {
+            self.fitted = true;
+            if self.engine.follows_fractal() {
+                let _ = self.engine.follow_fractal(&complexity::analyze(&params));
+            }
+            self.engine.set_rhythm(Pattern::from_fractal(&*fractal));
+        }
+        let _ = self.engine.schedule_rhythm();
+        self.engine.update_levels();
+    }
+
+    // Starts or resumes the sound; call from a gesture handler
+    pub fn resume(&mut self) {
+        self.engine.resume();
+    }
+
+    pub fn suspend(&self) {
+        self.engine.suspend();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.engine.is_running()
+    }
+
+    // Whether the sound carries on while the page is hidden (off by default)
+    pub fn set_background_playback(&self, enabled: bool) {
+        self.engine.set_background_playback(enabled);
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.engine.set_master_volume(volume);
+    }
+
+    pub fn set_harmony_enabled(&mut self, enabled: bool) {
+        self.harmony_enabled = enabled;
+    }
+
+    pub fn set_ambience_level(&mut self, level: f32) {
+        self.engine.set_ambience_level(level);
+    }
+
+    pub fn set_rhythm_enabled(&mut self, enabled: bool) {
+        self.engine.set_rhythm_enabled(enabled);
+    }
+
+    pub fn set_max_voices(&mut self, count: u32) {
+        self.engine.set_max_voices(count as usize);
+    }
+
+    // See Resonant::set_audio_effect
+    pub fn set_audio_effect(&mut self, name: &str, params_json: &str) -> Result<(), JsValue> {
+        self.engine.set_effect(name, params_json)?;
+        self.fitted = false;
+        Ok(())
+    }
+
+    // See Resonant::set_modulation
+    pub fn set_modulation(&mut self, routes_json: &str) -> Result<(), JsValue> {
+        self.engine.set_modulation(routes_json)
+    }
+
+    pub fn get_modulation(&self) -> String {
+        self.engine.get_modulation(self.frozen.seed)
+    }
+
+    // The frequencies the voices are playing, in Hz
+    pub fn get_frequencies(&self) -> Vec<f32> {
+        self.engine.get_current_frequencies()
+    }
+
+    fn parse(frozen_json: &str) -> Result<FrozenFractal, JsValue> {
+        serde_json::from_str(frozen_json).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
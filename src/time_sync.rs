@@ -0,0 +1,139 @@
+// The clock friends share: the relay's, as each client estimates it, the way NTP does. The
+// client sends a probe stamped with its own time; the relay stamps when it took it and
+// when it answered, by its clock. Of the four times, the round trip less the relay's
+// holding time is the delay, and the offset is the relay's clock's lead, taking the way
+// there and back to be equally long. Of the latest samples, the one with the least delay
+// is trusted, as queueing only ever lengthens a trip, and lengthens it unevenly
+use std::collections::VecDeque;
+
+// Probes go every PROBE_INTERVAL, in ms, until SETTLED samples are in, and every
+// SETTLED_INTERVAL after, as clocks drift only slowly
+const PROBE_INTERVAL: f64 = 2.0 * 1000.0;
+const SETTLED_INTERVAL: f64 = 60.0 * 1000.0;
+const SETTLED: usize = 4;
+
+// Samples kept; the oldest go first
+const MAX_SAMPLES: usize = 8;
+
+// An answer to a probe sent longer ago than this, in ms, is no use, if it is one at all
+const MAX_ROUND_TRIP: u64 = 10 * 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    offset: f64, // The relay's clock less this device's, ms
+    delay: f64,  // Round trip, less the relay's holding time, ms
+}
+
+#[derive(Default)]
+pub struct SharedClock {
+    samples: VecDeque<Sample>,
+    next_probe: f64, // ms since the epoch, by this device's clock
+}
+
+impl SharedClock {
+    // The time to stamp a probe with, if one is due
+    pub fn probe(&mut self, now: f64) -> Option<u64> {
+        if now < self.next_probe {
+            return None;
+        }
+        self.next_probe = now + if self.samples.len() < SETTLED { PROBE_INTERVAL } else { SETTLED_INTERVAL };
+        Some(now as u64)
+    }
+
+    // Takes in an answer: the probe's time `sent`, the relay's `received` and `replied`,
+    // and now, as it arrived. One that can't be the answer to a probe of ours is dropped
+    pub fn receive(&mut self, sent: u64, received: u64, replied: u64, now: u64) {
+        if sent > now || now - sent > MAX_ROUND_TRIP || replied < received {
+            return;
+        }
+        let (sent, received, replied, now) = (sent as f64, received as f64, replied as f64, now as f64);
+        let sample = Sample {
+            offset: ((received - sent) + (replied - now)) / 2.0,
+            delay: ((now - sent) - (replied - received)).max(0.0),
+        };
+        self.samples.push_back(sample);
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    // Starts again, for another relay, whose clock may be anything
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.next_probe = 0.0;
+    }
+
+    fn best(&self) -> Option<&Sample> {
+        self.samples.iter().min_by(|a, b| a.delay.total_cmp(&b.delay))
+    }
+
+    // The relay's clock's lead over this device's, ms, once a sample is in
+    pub fn offset(&self) -> Option<f64> {
+        self.best().map(|sample| sample.offset)
+    }
+
+    // How far off the offset may be, ms: half the trip it was measured over
+    pub fn uncertainty(&self) -> Option<f64> {
+        self.best().map(|sample| sample.delay / 2.0)
+    }
+
+    // `local` (ms since the epoch by this device's clock) on the shared clock; before any
+    // sample, the device's own clock stands in
+    pub fn to_shared(&self, local: f64) -> f64 {
+        local + self.offset().unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEAD: u64 = 5000; // How far the relay's clock runs ahead, in ms
+
+    // Probes answered by the relay over trips of uneven length, returning when the last
+    // answer arrived
+    fn probed(clock: &mut SharedClock) -> u64 {
+        // Out, relay's holding time, back, in ms
+        let trips: [(u64, u64, u64); 5] = [(40, 2, 90), (12, 1, 14), (300, 5, 20), (25, 0, 25), (7, 3, 60)];
+        let mut now = 1_718_064_000_000u64;
+        for (out, held, back) in trips {
+            let sent = clock.probe(now as f64).unwrap_or(now);
+            let received = sent + out + LEAD;
+            let replied = received + held;
+            now = sent + out + held + back;
+            clock.receive(sent, received, replied, now);
+            now += PROBE_INTERVAL as u64;
+        }
+        now
+    }
+
+    #[test]
+    fn device_clock_until_answered() {
+        let clock = SharedClock::default();
+        assert_eq!(clock.offset(), None);
+        assert_eq!(clock.to_shared(1000.0), 1000.0);
+    }
+
+    // The offset comes from the quickest trip, within its uncertainty
+    #[test]
+    fn offset_from_the_quickest_trip() {
+        let mut clock = SharedClock::default();
+        probed(&mut clock);
+        let uncertainty = clock.uncertainty().unwrap();
+        assert_eq!(uncertainty, 13.0);
+        assert!((clock.offset().unwrap() - LEAD as f64).abs() <= uncertainty);
+        assert_eq!(clock.to_shared(1234.5), 1234.5 + clock.offset().unwrap());
+    }
+
+    // Answers from the future, too slow, or replied before they were received
+    #[test]
+    fn stray_answers_passed_over() {
+        let mut clock = SharedClock::default();
+        let now = probed(&mut clock);
+        let before = (clock.offset(), clock.uncertainty());
+        clock.receive(now + 10, now + 10 + LEAD, now + 10 + LEAD, now);
+        clock.receive(now - MAX_ROUND_TRIP - 1, now, now, now);
+        clock.receive(now - 10, now, now - 1, now);
+        assert_eq!((clock.offset(), clock.uncertainty()), before);
+    }
+}
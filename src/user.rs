@@ -1,17 +1,255 @@
 use wasm_bindgen::prelude::*;
 use web_sys::{Storage, Window};
 use nalgebra::Matrix4;
+use std::collections::BTreeMap;
 use serde::{Serialize, Deserialize};
 use crate::fractals::*;
+use crate::palette::Palette;
+use crate::registry::{self, FractalType};
+use crate::complexity::{self, ComplexityReport};
+use crate::math;
+use crate::seed_quality::SeedQuality;
+use crate::store::{Store, StorageError};
+use crate::achievements::{Achievements, AchievementInfo, Progress};
+use crate::day::{self, DayBoundary, ROLLOVER_CHECK_INTERVAL};
+use crate::preferences::{self, Preferences};
+use crate::pose::Pose;
+use crate::analytics::{Insights, Usage};
+use crate::ladder::{Battle, Ladder, DEFAULT_RATING};
+use crate::replay::GestureRecord;
+use crate::golden::{self, GoldenStats, GoldenWindow, GOLDEN_MULTIPLIER};
+use crate::evolution;
+use crate::lineage::{self, Lineage, LineageNode};
+use crate::effort::{self, RateLimiter};
+use crate::wake::{ActivityLog, ACTIVITY_INTERVAL};
+use crate::contacts::{self, Contact, Contacts};
+use crate::resonance::{ResonanceEvent, ResonanceHistory};
+use crate::battle::{self, Challenge};
+use crate::crypto::{self, Identity};
+
+const USER_ID_KEY: &str = "resonant_user_id";
+const IDENTITY_KEY: &str = "resonant_identity_key";
+const ACTIVITY_KEY: &str = "resonant_activity";
+const GESTURE_RATE_KEY: &str = "resonant_gesture_rate";
+const FROZEN_KEY: &str = "resonant_frozen_fractals";
+const ACHIEVEMENTS_KEY: &str = "resonant_achievements";
+const USAGE_KEY: &str = "resonant_usage";
+const LADDER_KEY: &str = "resonant_ladder";
+const GOLDEN_KEY: &str = "resonant_golden";
+const CONTACTS_KEY: &str = "resonant_contacts";
+const RESONANCES_KEY: &str = "resonant_resonances";
+const TRANSFORM_PREFIX: &str = "resonant_transform_";
+const HISTORY_PREFIX: &str = "resonant_history_";
+const DAY_PREFIX: &str = "resonant_day_";
+const GESTURES_PREFIX: &str = "resonant_gestures_";
+const INTERACTIONS_PREFIX: &str = "resonant_interactions_";
+const GESTURE_FEEDBACK_KEY: &str = "last_gesture_feedback";
+
+// Steps undo() can go back through each day
+const MAX_HISTORY: usize = 50;
+
+// Steps recorded for replay each day; later ones go unrecorded
+const MAX_GESTURES: usize = 1000;
+
+// Days never evicted, today's included, however short storage runs
+const MIN_KEPT_DAYS: usize = 7;
+
+// Eviction for room goes on until the records are down to this share of the budget, so
+// it isn't needed again on the very next save
+const EVICTION_TARGET: f64 = 0.9;
+
+// Longest day note, in characters; a mood, a line or two, not a diary
+const MAX_NOTE_CHARS: usize = 280;
+
+// Format of export_state's blob; import_state reads this version and every one before it
+const EXPORT_VERSION: u32 = 1;
+
+// Format of a saved FrozenFractal, written with each one; StoredFrozen reads this version
+// and every one before it. Bump it with each change older builds would misread, and add
+// the new shape to schema.rs
+pub const FROZEN_VERSION: u32 = 1;
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "StoredFrozen")]
 pub struct FrozenFractal {
+    pub version: u32, // Always FROZEN_VERSION once read; older ones are migrated
     pub seed: u32,
-    pub fractal_type: String,
+    // None for fractals saved with a placeholder instead of a type; see fractal_type()
+    pub fractal_type: Option<FractalType>,
     pub transform_matrix: Vec<f32>, // 4x4 matrix flattened
     pub complexity_score: f32,
     pub timestamp: u64,
     pub interaction_count: u32,
+    pub coloring_mode: ColoringMode,
+    pub palette: Option<Palette>, // None means derived from the seed
+    pub params: Option<FractalParams>, // None means derived from the seed
+    pub derivation: u32, // Seed derivation `params: None` falls back to; 0 for fractals saved before versioning
+    // math::MATH_VERSION the complexity score was computed with: 0 is platform math (and
+    // everything saved before versioning), higher versions reproduce bit for bit anywhere
+    pub math_version: u32,
+    pub lineage: Lineage,
+    #[serde(flatten)]
+    pub curation: Curation,
+}
+
+// A FrozenFractal as any build has saved it. Fields added since the first release
+// default, so every older shape reads; a missing version is version 0, everything saved
+// before versioning
+#[derive(Deserialize)]
+struct StoredFrozen {
+    #[serde(default)]
+    version: u32,
+    seed: u32,
+    #[serde(default, deserialize_with = "registry::deserialize_legacy")]
+    fractal_type: Option<FractalType>,
+    transform_matrix: Vec<f32>,
+    complexity_score: f32,
+    timestamp: u64,
+    interaction_count: u32,
+    #[serde(default)]
+    coloring_mode: ColoringMode,
+    #[serde(default)]
+    palette: Option<Palette>,
+    #[serde(default)]
+    params: Option<FractalParams>,
+    #[serde(default)]
+    derivation: u32,
+    #[serde(default)]
+    math_version: u32,
+    #[serde(default)]
+    lineage: Lineage,
+    #[serde(default, flatten)]
+    curation: Curation,
+}
+
+impl From<StoredFrozen> for FrozenFractal {
+    fn from(stored: StoredFrozen) -> Self {
+        let mut frozen = FrozenFractal {
+            version: stored.version,
+            seed: stored.seed,
+            fractal_type: stored.fractal_type,
+            transform_matrix: stored.transform_matrix,
+            complexity_score: stored.complexity_score,
+            timestamp: stored.timestamp,
+            interaction_count: stored.interaction_count,
+            coloring_mode: stored.coloring_mode,
+            palette: stored.palette,
+            params: stored.params,
+            derivation: stored.derivation,
+            math_version: stored.math_version,
+            lineage: stored.lineage,
+            curation: stored.curation,
+        };
+        if frozen.version < 1 {
+            // Version 0 stored placeholders ("Current", "Unknown") for the type where it had
+            // none to hand; the seed, params and derivation it was saved with name it
+            if frozen.fractal_type.is_none() {
+                frozen.fractal_type = Some(frozen.fractal_type());
+            }
+            // And fractals opened from share links were given a matrix of ones, meant as
+            // the identity but collapsing the fractal onto a line
+            if frozen.transform_matrix.len() != 16 || frozen.transform_matrix.iter().all(|&m| m == 1.0) {
+                frozen.transform_matrix = UserState::matrix_data(&Matrix4::identity());
+            }
+        }
+        // Params past FractalParams::validate's bounds, from a file or another device, are
+        // never built; the fractal is its seed's instead
+        if frozen.params.is_some_and(|params| params.validate().is_err()) {
+            frozen.params = None;
+            frozen.fractal_type = Some(frozen.fractal_params().fractal_type());
+        }
+        // As is a palette validate refuses, for the seed's own
+        if frozen.palette.as_ref().is_some_and(|palette| palette.validate().is_err()) {
+            frozen.palette = None;
+        }
+        // A newer build's fields this one doesn't know are dropped, so it's saved again
+        // as what it now is
+        frozen.version = FROZEN_VERSION;
+        frozen
+    }
+}
+
+// How the user has filed a frozen fractal
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Curation {
+    pub collection: Option<String>, // None for none in particular
+    pub tags: Vec<String>,
+    pub favorite: bool, // Never dropped to make room
+}
+
+// Which frozen fractals list_frozen returns; every field given must match, and the
+// tags must all be there
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct FrozenFilter {
+    pub collection: Option<String>,
+    pub tags: Vec<String>,
+    pub favorite: Option<bool>,
+}
+
+impl FrozenFilter {
+    fn matches(&self, frozen: &FrozenFractal) -> bool {
+        let curation = &frozen.curation;
+        self.collection.as_ref().is_none_or(|collection| curation.collection.as_ref() == Some(collection))
+            && self.tags.iter().all(|tag| curation.tags.contains(tag))
+            && self.favorite.is_none_or(|favorite| curation.favorite == favorite)
+    }
+}
+
+// Changes to a frozen fractal's curation; fields left out stay as they are, and an
+// empty collection takes it out of its collection
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CurationChange {
+    pub collection: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub favorite: Option<bool>,
+}
+
+impl FrozenFractal {
+    pub fn fractal_params(&self) -> FractalParams {
+        self.params.unwrap_or_else(|| FractalParams::from_seed_with(self.seed, self.derivation))
+    }
+
+    pub fn fractal_type(&self) -> FractalType {
+        self.fractal_type.unwrap_or_else(|| self.fractal_params().fractal_type())
+    }
+
+    // Frozen fractals are told apart by their seed and when they were frozen
+    pub fn is(&self, seed: u32, timestamp: u64) -> bool {
+        self.seed == seed && self.timestamp == timestamp
+    }
+}
+
+// What a day came to, enough to draw its fractal again: with the seed and derivation the
+// fractal itself, with the seed's saved transform the shape the user left it in
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DayRecord {
+    pub date: String, // YYYY-MM-DD
+    pub seed: u32,
+    #[serde(default)]
+    pub derivation: u32,
+    pub complexity: f32,
+    pub interactions: u32,
+    // FNV-1a over the seed, derivation and transform, the inputs the day's thumbnail is
+    // drawn from, so a cached thumbnail can be checked against it
+    pub thumbnail_hash: String,
+    pub timestamp: u64,
+    #[serde(default)]
+    pub note: Option<String>, // The user's mood or journal line for the day, emoji and all
+    #[serde(skip)]
+    geometry_score: Option<f32>, // The fractal's own complexity, computed once per session
+}
+
+// What erase_all_data removed
+#[derive(Serialize, Default)]
+pub struct ErasureReport {
+    pub removed: Vec<String>, // Every key, in order
+    // How many keys of each kind: "transforms", "undo_histories", "interaction_counts",
+    // "days", "gesture_logs", "frozen_fractals", "achievements", "usage", "battles",
+    // "golden_hours", "preferences" and "profile" (the user id, session log and the like)
+    pub counts: BTreeMap<String, u32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -20,15 +258,93 @@ pub struct BattleResult {
     pub score_self: f32,
     pub score_opponent: f32,
     pub resonance_factor: f32,
+    #[serde(default)]
+    pub rating: f32, // The user's rating after the battle
+    #[serde(default)]
+    pub rating_change: f32,
+}
+
+// What a challenger's JSON may say about its owner besides the fractal itself
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Challenger {
+    user_id: Option<String>,
+    rating: Option<f32>,
+}
+
+// Everything that makes a user theirs, for moving to another device
+#[derive(Serialize, Deserialize)]
+pub struct ExportedState {
+    pub version: u32,
+    pub user_id: String,
+    #[serde(default)]
+    pub frozen_fractals: Vec<FrozenFractal>,
+    #[serde(default)]
+    pub transforms: BTreeMap<u32, Vec<f32>>, // Each day's seed's accumulated transform
+    #[serde(default)]
+    pub interactions: BTreeMap<String, u32>, // Gestures by date, YYYY-MM-DD
+    #[serde(default)]
+    pub preferences: Preferences,
+    #[serde(default)]
+    pub achievements: Progress,
+    #[serde(default)]
+    pub days: Vec<DayRecord>,
+    #[serde(default)]
+    pub ladder: Ladder,
+    #[serde(default)]
+    pub contacts: Contacts,
+    #[serde(default)]
+    pub resonances: ResonanceHistory,
+    #[serde(default)]
+    pub identity_key: Option<String>, // The secret key messages are sealed with, so it moves with the user id; never synced
+    #[serde(default)]
+    pub exported_at: u64,
+}
+
+// The day's transforms before the current one (undo) and after it (redo), most recent
+// last, flattened as the transform itself is saved
+#[derive(Serialize, Deserialize, Default)]
+struct TransformHistory {
+    undo: Vec<Vec<f32>>,
+    redo: Vec<Vec<f32>>,
+}
+
+// The day's seed, transform and undo history, set aside while another seed is explored
+// in the sandbox
+struct Parked {
+    seed_quality: SeedQuality,
+    pose: Pose,
+    history: TransformHistory,
 }
 
 pub struct UserState {
     user_id: String,
+    identity: Identity, // The key pair messages to and from friends are sealed with
+    day: String, // YYYY-MM-DD the seed and the day's state belong to
+    next_rollover_check: f64, // ms since the epoch
     current_seed: u32,
-    current_transform: Matrix4<f32>,
+    seed_quality: SeedQuality,
+    pose: Pose, // The day's transform; see get_current_transform
+    history: TransformHistory,
+    gestures: Vec<GestureRecord>, // The day's steps, for replay
     daily_interactions: u32,
-    storage: Storage,
+    storage: Storage, // The user id and preferences
+    store: Store,     // Everything else (see Store)
     frozen_fractals: Vec<FrozenFractal>,
+    today: Option<DayRecord>, // Kept up to date as the day's state is saved
+    achievements: Achievements,
+    usage: Usage, // Never leaves the device unless the user opts in
+    ladder: Ladder,
+    golden_windows: Vec<GoldenWindow>, // Today's, from the seed
+    golden: GoldenStats,
+    contacts: Contacts,
+    resonances: ResonanceHistory,
+    gesture_rate: RateLimiter, // Of the gestures counted toward the day's bonus
+    activity: ActivityLog,
+    next_activity_touch: f64, // ms since the epoch
+    preferences: Preferences,
+    sandbox: Option<Parked>, // The day's own state while in the sandbox
+    changed: bool, // Whether the day's state has moved on since the page loaded
 }
 
 impl UserState {
@@ -38,129 +354,512 @@ impl UserState {
 
         // Get or create user ID
         let user_id = Self::get_or_create_user_id(&storage)?;
+        let identity = Self::get_or_create_identity(&storage)?;
+        let preferences = Preferences::load(&storage)?;
+        let day = preferences.day_boundary.today();
 
-        // Generate today's seed
-        let current_seed = Self::generate_daily_seed(&user_id);
-
-        // Load or initialize transform
-        let current_transform = Self::load_transform(&storage, current_seed)?;
+        // When the user was last here decides whether this is a wake-up, so the log is
+        // read before anything else that could count as activity
+        let now = js_sys::Date::now();
+        let mut activity: ActivityLog = storage.get_item(ACTIVITY_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        activity.touch(now);
+        storage.set_item(ACTIVITY_KEY, &serde_json::to_string(&activity).unwrap())?;
 
-        // Load interaction count
-        let daily_interactions = Self::load_daily_interactions(&storage)?;
+        // Generate today's seed, swapping in an alternate if its fractal is degenerate
+        let seed_quality = SeedQuality::choose(Self::generate_daily_seed(&user_id, &day));
+        let current_seed = seed_quality.seed;
 
-        // Load frozen fractals
-        let frozen_fractals = Self::load_frozen_fractals(&storage)?;
+        // Load or initialize transform, interaction count and frozen fractals. These are
+        // only in localStorage until the store has moved them into IndexedDB; from then on
+        // they arrive a moment later, through poll_store
+        let pose = storage.get_item(&Self::transform_key(current_seed))?
+            .map_or_else(Pose::default, |data| Pose::from_matrix(&Self::parse_transform(&data)));
+        let history = storage.get_item(&Self::history_key(current_seed))?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let gestures = storage.get_item(&Self::gestures_key(&day))?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let daily_interactions = storage.get_item(&Self::interactions_key(&day))?
+            .map_or(0, |count| count.parse().unwrap_or(0));
+        let frozen_fractals = storage.get_item(FROZEN_KEY)?
+            .map(|data| serde_json::from_str(&data).unwrap_or_default())
+            .unwrap_or_default();
+        let progress = storage.get_item(ACHIEVEMENTS_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let usage = storage.get_item(USAGE_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let ladder = storage.get_item(LADDER_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let golden = storage.get_item(GOLDEN_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let contacts = storage.get_item(CONTACTS_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let gesture_rate = storage.get_item(GESTURE_RATE_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let resonances = storage.get_item(RESONANCES_KEY)?
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        let store = Store::open(storage.clone());
 
         Ok(UserState {
             user_id,
+            identity,
+            day,
+            next_rollover_check: now + ROLLOVER_CHECK_INTERVAL,
             current_seed,
-            current_transform,
+            seed_quality,
+            pose,
+            history,
+            gestures,
             daily_interactions,
             storage,
+            store,
             frozen_fractals,
+            today: None,
+            achievements: Achievements::new(progress),
+            usage,
+            ladder,
+            golden_windows: golden::windows(current_seed),
+            golden,
+            contacts,
+            resonances,
+            gesture_rate,
+            activity,
+            next_activity_touch: now + ACTIVITY_INTERVAL,
+            preferences,
+            sandbox: None,
+            changed: false,
         })
     }
 
+    // Ids saved before UUIDs ("user_" and a number) are kept as they are, so existing
+    // users keep their daily seeds
     fn get_or_create_user_id(storage: &Storage) -> Result<String, JsValue> {
-        match storage.get_item("resonant_user_id")? {
+        match storage.get_item(USER_ID_KEY)? {
             Some(id) => Ok(id),
             None => {
-                // Generate unique user ID
-                let id = format!("user_{}", js_sys::Math::random() * 1000000.0);
-                storage.set_item("resonant_user_id", &id)?;
+                let id = Self::generate_user_id()?;
+                storage.set_item(USER_ID_KEY, &id)?;
                 Ok(id)
             }
         }
     }
 
-    fn generate_daily_seed(user_id: &str) -> u32 {
-        let date = js_sys::Date::new_0();
-        let day_code = (date.get_full_year() * 10000 + date.get_month() * 100 + date.get_date()) as u32;
+    // One saved that won't decode is replaced, as there's no reading messages with it anyway
+    fn get_or_create_identity(storage: &Storage) -> Result<Identity, JsValue> {
+        if let Some(identity) = storage.get_item(IDENTITY_KEY)?.and_then(|key| Identity::decode(&key).ok()) {
+            return Ok(identity);
+        }
+        let identity = Identity::generate()?;
+        storage.set_item(IDENTITY_KEY, &identity.encode())?;
+        Ok(identity)
+    }
+
+    // A random (version 4) UUID from crypto.getRandomValues; none without it, as the
+    // daily seeds hang on the id not being guessable
+    fn generate_user_id() -> Result<String, JsValue> {
+        let mut bytes = [0u8; 16];
+        crypto::fill_random(&mut bytes)?;
+        bytes[6] = (bytes[6] & 0x0f) | 0x40; // Version 4
+        bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+    }
 
+    // `date` is YYYY-MM-DD
+    fn generate_daily_seed(user_id: &str, date: &str) -> u32 {
         // Simple hash of user_id + date
         let mut hash = 0u32;
-        for byte in user_id.bytes().chain(day_code.to_string().bytes()) {
+        for byte in user_id.bytes().chain(day::day_code(date).to_string().bytes()) {
             hash = hash.wrapping_mul(31).wrapping_add(byte as u32);
         }
         hash
     }
 
-    fn load_transform(storage: &Storage, seed: u32) -> Result<Matrix4<f32>, JsValue> {
-        let key = format!("resonant_transform_{}", seed);
-        match storage.get_item(&key)? {
-            Some(data) => {
-                let matrix_data: Vec<f32> = serde_json::from_str(&data).unwrap_or_default();
-                if matrix_data.len() == 16 {
-                    Ok(Matrix4::from_row_slice(&matrix_data))
-                } else {
-                    Ok(Matrix4::identity())
-                }
+    fn transform_key(seed: u32) -> String {
+        format!("{}{}", TRANSFORM_PREFIX, seed)
+    }
+
+    fn history_key(seed: u32) -> String {
+        format!("{}{}", HISTORY_PREFIX, seed)
+    }
+
+    fn day_key(date: &str) -> String {
+        format!("{}{}", DAY_PREFIX, date)
+    }
+
+    fn gestures_key(date: &str) -> String {
+        format!("{}{}", GESTURES_PREFIX, date)
+    }
+
+    fn interactions_key(date: &str) -> String {
+        format!("{}{}", INTERACTIONS_PREFIX, date)
+    }
+
+    fn parse_transform(data: &str) -> Matrix4<f32> {
+        let matrix_data: Vec<f32> = serde_json::from_str(data).unwrap_or_default();
+        Self::from_matrix_data(&matrix_data)
+    }
+
+    // Row by row, as from_matrix_data reads it back. Builds before the undo history wrote
+    // column by column, which from_matrix_data has always read transposed; those entries
+    // still read back as they did, and are row by row from their next save
+    pub fn matrix_data(matrix: &Matrix4<f32>) -> Vec<f32> {
+        matrix.transpose().as_slice().to_vec()
+    }
+
+    pub fn from_matrix_data(matrix_data: &[f32]) -> Matrix4<f32> {
+        if matrix_data.len() == 16 {
+            Matrix4::from_row_slice(matrix_data)
+        } else {
+            Matrix4::identity()
+        }
+    }
+
+    // Takes in what the store has read, once it has; call once per frame. Today's
+    // transform and count replace the ones loaded at startup unless the day has moved
+    // on from those already, and the frozen fractals saved either way are merged
+    pub fn poll_store(&mut self) {
+        let Some(records) = self.store.take_loaded() else {
+            return;
+        };
+
+        if !self.changed {
+            if let Some(data) = records.get(&Self::transform_key(self.current_seed)) {
+                self.pose = Pose::from_matrix(&Self::parse_transform(data));
+            }
+            if let Some(history) = records.get(&Self::history_key(self.current_seed)).and_then(|data| serde_json::from_str(data).ok()) {
+                self.history = history;
+            }
+            if let Some(gestures) = records.get(&Self::gestures_key(&self.day)).and_then(|data| serde_json::from_str(data).ok()) {
+                self.gestures = gestures;
+            }
+            if let Some(count) = records.get(&Self::interactions_key(&self.day)) {
+                self.daily_interactions = count.parse().unwrap_or(self.daily_interactions);
             }
-            None => Ok(Matrix4::identity()),
+        }
+
+        let stored = records.get(FROZEN_KEY)
+            .and_then(|data| serde_json::from_str(data).ok())
+            .unwrap_or_default();
+        self.merge_frozen(stored);
+        if let Some(progress) = records.get(ACHIEVEMENTS_KEY).and_then(|data| serde_json::from_str(data).ok()) {
+            self.achievements.merge(progress);
+        }
+        if let Some(usage) = records.get(USAGE_KEY).and_then(|data| serde_json::from_str(data).ok()) {
+            self.usage.merge(usage);
+        }
+        if let Some(ladder) = records.get(LADDER_KEY).and_then(|data| serde_json::from_str(data).ok()) {
+            self.ladder.merge(ladder);
+        }
+        if let Some(golden) = records.get(GOLDEN_KEY).and_then(|data| serde_json::from_str(data).ok()) {
+            self.golden.merge(golden);
+        }
+        if let Some(contacts) = records.get(CONTACTS_KEY).and_then(|data| serde_json::from_str(data).ok()) {
+            self.contacts.merge(contacts);
+        }
+        if let Some(resonances) = records.get(RESONANCES_KEY).and_then(|data| serde_json::from_str(data).ok()) {
+            self.resonances.merge(resonances);
         }
     }
 
-    fn load_daily_interactions(storage: &Storage) -> Result<u32, JsValue> {
-        let today = Self::get_date_string();
-        let key = format!("resonant_interactions_{}", today);
-        match storage.get_item(&key)? {
-            Some(count_str) => Ok(count_str.parse().unwrap_or(0)),
-            None => Ok(0),
+    // Adds the ones not already here, then keeps the best
+    fn merge_frozen(&mut self, fractals: Vec<FrozenFractal>) {
+        for frozen in fractals {
+            let known = self.frozen_fractals.iter().any(|kept| kept.is(frozen.seed, frozen.timestamp));
+            if !known {
+                self.frozen_fractals.push(frozen);
+            }
         }
+        self.keep_best_frozen();
     }
 
-    fn load_frozen_fractals(storage: &Storage) -> Result<Vec<FrozenFractal>, JsValue> {
-        match storage.get_item("resonant_frozen_fractals")? {
-            Some(data) => Ok(serde_json::from_str(&data).unwrap_or_default()),
-            None => Ok(Vec::new()),
+    pub fn is_store_ready(&self) -> bool {
+        self.store.is_ready()
+    }
+
+    // Day notes go along unless the export_notes preference is off
+    pub fn export_state(&self) -> String {
+        self.export(self.preferences.export_notes, true)
+    }
+
+    // export_state's blob, with or without the day notes and the secret key. A sync
+    // always carries the notes, as a newer day without its note would wipe the note on
+    // the next device, and never the key, which would let the sync backend open every
+    // sealed message; only the user's own export file holds it
+    pub fn export(&self, include_notes: bool, include_key: bool) -> String {
+        let mut days = self.get_history("", "");
+        if !include_notes {
+            for day in &mut days {
+                day.note = None;
+            }
         }
+        let transforms = self.store.entries(TRANSFORM_PREFIX).into_iter()
+            .filter_map(|(key, data)| Some((key[TRANSFORM_PREFIX.len()..].parse().ok()?, serde_json::from_str(&data).ok()?)))
+            .collect();
+        let interactions = self.store.entries(INTERACTIONS_PREFIX).into_iter()
+            .filter_map(|(key, count)| Some((key[INTERACTIONS_PREFIX.len()..].to_string(), count.parse().ok()?)))
+            .collect();
+
+        serde_json::to_string(&ExportedState {
+            version: EXPORT_VERSION,
+            user_id: self.user_id.clone(),
+            frozen_fractals: self.frozen_fractals.clone(),
+            transforms,
+            interactions,
+            preferences: self.preferences.clone(),
+            achievements: self.achievements.progress.clone(),
+            days,
+            ladder: self.ladder.clone(),
+            contacts: self.contacts.clone(),
+            resonances: self.resonances.clone(),
+            identity_key: include_key.then(|| self.identity.encode()),
+            exported_at: js_sys::Date::now() as u64,
+        }).unwrap()
     }
 
-    fn get_date_string() -> String {
-        let date = js_sys::Date::new_0();
-        format!("{}-{:02}-{:02}",
-            date.get_full_year(),
-            date.get_month() + 1,
-            date.get_date()
-        )
+    // Takes on the identity in an export_state blob: its user id, and so its daily seeds,
+    // replaces this one, as do its transforms, counts and preferences; its frozen
+    // fractals join the ones here
+    pub fn import_state(&mut self, state_json: &str) -> Result<(), JsValue> {
+        let state = Self::parse_exported(state_json)?;
+        self.exit_sandbox();
+
+        self.storage.set_item(USER_ID_KEY, &state.user_id)?;
+        self.take_identity(state.identity_key.as_deref(), state.user_id != self.user_id)?;
+        self.preferences = state.preferences;
+        self.preferences.sanitize();
+        self.preferences.save(&self.storage)?;
+        self.user_id = state.user_id;
+        self.day = self.preferences.day_boundary.today();
+        self.seed_quality = SeedQuality::choose(Self::generate_daily_seed(&self.user_id, &self.day));
+        self.current_seed = self.seed_quality.seed;
+
+        for (seed, matrix_data) in &state.transforms {
+            self.store.set(&Self::transform_key(*seed), &serde_json::to_string(matrix_data).unwrap());
+        }
+        for (date, count) in &state.interactions {
+            self.store.set(&format!("{}{}", INTERACTIONS_PREFIX, date), &count.to_string());
+        }
+        for day in &state.days {
+            self.store.set(&Self::day_key(&day.date), &serde_json::to_string(day).unwrap());
+        }
+        self.today = None;
+        self.pose = state.transforms.get(&self.current_seed)
+            .map_or_else(Pose::default, |matrix_data| Pose::from_matrix(&Self::from_matrix_data(matrix_data)));
+        self.history = TransformHistory::default();
+        self.gestures = Vec::new();
+        self.daily_interactions = state.interactions.get(&self.day).copied().unwrap_or(0);
+        self.changed = true;
+        self.merge_frozen(state.frozen_fractals);
+        self.achievements.merge(state.achievements);
+        self.ladder.merge(state.ladder);
+        self.contacts.merge(state.contacts);
+        self.resonances.merge(state.resonances);
+        self.save_state();
+        Ok(())
     }
 
-    pub fn get_current_fractal(&self, time: f32) -> Box<dyn FractalGenerator> {
-        create_fractal_from_seed(self.current_seed, time)
+    // Folds in a state kept elsewhere (see CloudSync): each day's record, transform and
+    // count where that day was saved there later than here, the preferences likewise,
+    // and the frozen fractals, achievements and ladder merged as poll_store merges them.
+    // Its user id is taken on if it differs, as import_state does: the synced state is
+    // the account's, and a device whose storage was cleared gets its identity back. A
+    // synced state carries no key, so the device then has a new one, and friends need a
+    // new link from it to seal to it
+    pub fn merge_state(&mut self, state_json: &str) -> Result<(), JsValue> {
+        let state = Self::parse_exported(state_json)?;
+        self.exit_sandbox();
+
+        let local_days: BTreeMap<String, u64> = self.get_history("", "").into_iter()
+            .map(|day| (day.date, day.timestamp))
+            .collect();
+        for day in &state.days {
+            if local_days.get(&day.date).is_some_and(|&saved| saved >= day.timestamp) {
+                continue;
+            }
+            self.store.set(&Self::day_key(&day.date), &serde_json::to_string(day).unwrap());
+            if let Some(matrix_data) = state.transforms.get(&day.seed) {
+                self.store.set(&Self::transform_key(day.seed), &serde_json::to_string(matrix_data).unwrap());
+            }
+            if let Some(count) = state.interactions.get(&day.date) {
+                self.store.set(&Self::interactions_key(&day.date), &count.to_string());
+            }
+        }
+
+        if state.preferences.updated_at > self.preferences.updated_at {
+            self.preferences = state.preferences;
+            self.preferences.sanitize();
+            self.preferences.save(&self.storage)?;
+        }
+        self.take_identity(state.identity_key.as_deref(), state.user_id != self.user_id)?;
+        if state.user_id != self.user_id {
+            self.storage.set_item(USER_ID_KEY, &state.user_id)?;
+            self.user_id = state.user_id;
+        }
+        self.merge_frozen(state.frozen_fractals);
+        self.achievements.merge(state.achievements);
+        self.ladder.merge(state.ladder);
+        self.contacts.merge(state.contacts);
+        self.resonances.merge(state.resonances);
+
+        // Today's state, and even its seed, may have changed underneath
+        self.day = self.preferences.day_boundary.today();
+        self.today = None;
+        self.reset_daily_state()
+    }
+
+    // Takes on an imported or synced key, so friends' messages open on every device; an
+    // identity taken on without one (exported before keys) gets a fresh key
+    fn take_identity(&mut self, encoded: Option<&str>, new_user: bool) -> Result<(), JsValue> {
+        let identity = match encoded {
+            Some(encoded) => Identity::decode(encoded)?,
+            None if new_user => Identity::generate()?,
+            None => return Ok(()),
+        };
+        self.storage.set_item(IDENTITY_KEY, &identity.encode())?;
+        self.identity = identity;
+        Ok(())
+    }
+
+    fn parse_exported(state_json: &str) -> Result<ExportedState, JsValue> {
+        let state: ExportedState = serde_json::from_str(state_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid state: {}", e)))?;
+        if state.version > EXPORT_VERSION {
+            return Err(JsValue::from_str("State was exported by a newer version"));
+        }
+        if state.user_id.is_empty() {
+            return Err(JsValue::from_str("State has no user id"));
+        }
+        Ok(state)
+    }
+
+    pub fn get_current_fractal(&self, time: f32, coloring: ColoringMode, palette: &Palette,
+                               params: &FractalParams) -> Box<dyn FractalGenerator> {
+        create_fractal_from_params(params, time, coloring, palette.clone())
     }
 
     pub fn get_seed(&self) -> u32 {
         self.current_seed
     }
 
+    pub fn get_seed_quality(&self) -> SeedQuality {
+        self.seed_quality
+    }
+
     pub fn get_user_id(&self) -> &str {
         &self.user_id
     }
 
+    pub fn get_identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    // Recomposed from the pose each time it's asked for
     pub fn get_current_transform(&self) -> Matrix4<f32> {
-        self.current_transform
+        self.pose.to_matrix()
+    }
+
+    // The transform as its parts, as a room shares them
+    pub fn get_pose(&self) -> Pose {
+        self.pose
     }
 
-    pub fn get_complexity_score(&self) -> f32 {
-        // Calculate complexity based on transform matrix and interactions
-        let matrix_complexity = self.current_transform.determinant().abs().ln().max(0.0);
-        let interaction_bonus = (self.daily_interactions as f32).sqrt() * 0.1;
-        matrix_complexity + interaction_bonus
+    pub fn get_complexity_score(&self, params: &FractalParams) -> f32 {
+        self.complexity_score(&complexity::analyze(params))
+    }
+
+    // Geometric richness of the fractal itself, plus a little for today's engagement
+    pub fn complexity_score(&self, geometry: &ComplexityReport) -> f32 {
+        geometry.score + self.interaction_bonus()
+    }
+
+    // The day's gestures on effort::bonus's diminishing curve, those made in a golden hour
+    // counting GOLDEN_MULTIPLIER times; they earn a sandbox seed nothing
+    fn interaction_bonus(&self) -> f32 {
+        if self.in_sandbox() {
+            return 0.0;
+        }
+        let golden = self.golden.interactions_on(&self.day).min(self.daily_interactions);
+        let weighted = self.daily_interactions + golden * (GOLDEN_MULTIPLIER - 1);
+        effort::bonus(weighted)
     }
 
     pub fn get_interaction_count(&self) -> u32 {
         self.daily_interactions
     }
 
+    pub fn get_preferences(&self) -> &Preferences {
+        &self.preferences
+    }
+
+    // Sets and saves one preference (see Preferences::set), and brings the collection and
+    // the day into line with it
+    pub fn set_preference(&mut self, key: &str, value: &str) -> Result<(), JsValue> {
+        self.preferences.set(key, value)?;
+        self.preferences.save(&self.storage)?;
+        match key {
+            "frozen_capacity" => {
+                self.keep_best_frozen();
+                self.save_state();
+            }
+            // The next check_rollover moves to the day it now makes it
+            "day_boundary" => self.next_rollover_check = 0.0,
+            "history_days" => self.evict_old_days(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn get_haptics_enabled(&self) -> bool {
+        self.preferences.haptics_enabled
+    }
+
+    pub fn set_haptics_enabled(&mut self, enabled: bool) -> Result<(), JsValue> {
+        self.preferences.haptics_enabled = enabled;
+        self.preferences.updated_at = js_sys::Date::now() as u64;
+        self.preferences.save(&self.storage)
+    }
+
     pub fn apply_transform(&mut self, transform: Matrix4<f32>) {
-        // Accumulate transform
-        self.current_transform = self.current_transform * transform;
+        // Accumulate transform, remembering the one before for undo
+        self.push_undo();
+        self.history.redo.clear();
+        self.pose = Pose::from_matrix(&(self.get_current_transform() * transform));
+        if self.in_sandbox() {
+            return;
+        }
+        self.changed = true;
 
-        // Increment interaction count
+        // Past the rate limit a gesture still moves the fractal, but counts for nothing
+        let now = js_sys::Date::now();
+        if !self.gesture_rate.admit(now) {
+            self.save_state();
+            return;
+        }
+        if let Err(e) = self.storage.set_item(GESTURE_RATE_KEY, &serde_json::to_string(&self.gesture_rate).unwrap()) {
+            self.store.report(GESTURE_RATE_KEY, &e);
+        }
+
+        // Increment interaction count, and the streak on the first of the day
         self.daily_interactions += 1;
+        self.achievements.record_gesture(&self.day, &day::previous(&self.day), now as u64);
+        if let Some(window) = golden::active(&self.golden_windows, self.preferences.day_boundary.minute_of_day()) {
+            self.golden.record(&self.day, window.start);
+        }
 
         // Save to storage
-        let _ = self.save_state();
+        self.save_state();
     }
 
     pub fn apply_transform_animated(&mut self, transform: Matrix4<f32>, intensity: f32) -> Result<(), JsValue> {
@@ -176,115 +875,864 @@ impl UserState {
         });
 
         // Store in localStorage for potential visual feedback systems
-        if let Some(window) = web_sys::window() {
-            if let Ok(Some(storage)) = window.local_storage() {
-                let _ = storage.set_item("last_gesture_feedback", &feedback_data.to_string());
-            }
+        if let Err(e) = self.storage.set_item(GESTURE_FEEDBACK_KEY, &feedback_data.to_string()) {
+            self.store.report(GESTURE_FEEDBACK_KEY, &e);
         }
 
         Ok(())
     }
 
-    fn save_state(&self) -> Result<(), JsValue> {
-        // Save transform
-        let transform_key = format!("resonant_transform_{}", self.current_seed);
-        let matrix_data: Vec<f32> = self.current_transform.as_slice().to_vec();
-        let transform_json = serde_json::to_string(&matrix_data).unwrap();
-        self.storage.set_item(&transform_key, &transform_json)?;
+    // Marks the app as still in use; call once per frame. The log is only written every
+    // ACTIVITY_INTERVAL, and frames stop while the page is hidden, so coming back to it
+    // after four hours counts as waking as much as reopening it does
+    pub fn note_activity(&mut self) {
+        let now = js_sys::Date::now();
+        if now < self.next_activity_touch {
+            return;
+        }
+        self.next_activity_touch = now + ACTIVITY_INTERVAL;
+        self.activity.touch(now);
+        if let Err(e) = self.storage.set_item(ACTIVITY_KEY, &serde_json::to_string(&self.activity).unwrap()) {
+            self.store.report(ACTIVITY_KEY, &e);
+        }
+    }
+
+    // Start of the user's waking day (see ActivityLog), ms since the epoch
+    pub fn get_wake_time(&self) -> f64 {
+        self.activity.wake_time().unwrap_or_else(js_sys::Date::now)
+    }
 
-        // Save interaction count
-        let today = Self::get_date_string();
-        let interactions_key = format!("resonant_interactions_{}", today);
-        self.storage.set_item(&interactions_key, &self.daily_interactions.to_string())?;
+    pub fn is_wake_session(&self) -> bool {
+        self.activity.is_wake_session()
+    }
 
-        // Save frozen fractals
-        let frozen_json = serde_json::to_string(&self.frozen_fractals).unwrap();
-        self.storage.set_item("resonant_frozen_fractals", &frozen_json)?;
+    // Records a gesture just applied, for replay_today
+    pub fn record_gesture(&mut self, kind: &str, intensity: f32, direction: f32) {
+        self.record_step(kind, intensity, direction);
+    }
+
+    // The day's steps so far, oldest first
+    pub fn get_today_gestures(&self) -> &[GestureRecord] {
+        &self.gestures
+    }
+
+    fn record_step(&mut self, kind: &str, intensity: f32, direction: f32) {
+        if self.gestures.len() >= MAX_GESTURES || self.in_sandbox() {
+            return;
+        }
+        self.gestures.push(GestureRecord {
+            kind: kind.to_string(),
+            intensity,
+            direction,
+            timestamp: js_sys::Date::now() as u64,
+            transform: Self::matrix_data(&self.get_current_transform()),
+        });
+        self.store.set(&Self::gestures_key(&self.day), &serde_json::to_string(&self.gestures).unwrap());
+    }
+
+    // Counts a gesture of `kind` toward the usage insights
+    pub fn record_usage(&mut self, kind: &str) {
+        if self.in_sandbox() {
+            return;
+        }
+        self.usage.record_gesture(kind, js_sys::Date::now(), evolution::local_hour() as u32);
+        self.store.set(USAGE_KEY, &serde_json::to_string(&self.usage).unwrap());
+    }
+
+    // What the usage so far says about the user: favorite gesture, busiest hour, how long
+    // they stay
+    pub fn get_insights(&self) -> Insights {
+        self.usage.insights()
+    }
+
+    // Steps back to the transform before the last gesture (or redo or reset); false if
+    // there is none
+    pub fn undo(&mut self) -> bool {
+        let Some(previous) = self.history.undo.pop() else {
+            return false;
+        };
+        self.history.redo.push(Self::matrix_data(&self.get_current_transform()));
+        self.pose = Pose::from_matrix(&Self::from_matrix_data(&previous));
+        self.changed = true;
+        self.record_step("undo", 0.0, 0.0);
+        self.save_state();
+        true
+    }
+
+    // Steps forward again after undo; false if there is nothing undone
+    pub fn redo(&mut self) -> bool {
+        let Some(next) = self.history.redo.pop() else {
+            return false;
+        };
+        self.push_undo();
+        self.pose = Pose::from_matrix(&Self::from_matrix_data(&next));
+        self.changed = true;
+        self.record_step("redo", 0.0, 0.0);
+        self.save_state();
+        true
+    }
+
+    // Back to the day's untouched fractal, as a step undo() can take back
+    pub fn reset_transform(&mut self) {
+        self.push_undo();
+        self.history.redo.clear();
+        self.pose = Pose::default();
+        self.changed = true;
+        self.record_step("reset", 0.0, 0.0);
+        self.save_state();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    // Only the latest MAX_HISTORY steps are kept
+    fn push_undo(&mut self) {
+        self.history.undo.push(Self::matrix_data(&self.get_current_transform()));
+        if self.history.undo.len() > MAX_HISTORY {
+            self.history.undo.remove(0);
+        }
+    }
+
+    // The recorded days from `from` to `to` (YYYY-MM-DD, both included; empty for no
+    // limit), oldest first
+    pub fn get_history(&self, from: &str, to: &str) -> Vec<DayRecord> {
+        let mut days: Vec<DayRecord> = self.store.entries(DAY_PREFIX).into_iter()
+            .filter_map(|(_, data)| serde_json::from_str(&data).ok())
+            .filter(|day: &DayRecord| day.date.as_str() >= from && (to.is_empty() || day.date.as_str() <= to))
+            .collect();
+        days.sort_by(|a, b| a.date.cmp(&b.date));
+        days
+    }
+
+    // The fractal of a recorded day as it was left, as a FrozenFractal to render
+    pub fn past_day(&self, date: &str) -> Option<FrozenFractal> {
+        let day: DayRecord = serde_json::from_str(&self.store.get(&Self::day_key(date))?).ok()?;
+        let params = FractalParams::from_seed_with(day.seed, day.derivation);
+        let transform = self.store.get(&Self::transform_key(day.seed))
+            .map_or_else(Matrix4::identity, |data| Self::parse_transform(&data));
+
+        Some(FrozenFractal {
+            version: FROZEN_VERSION,
+            seed: day.seed,
+            fractal_type: Some(params.fractal_type()),
+            transform_matrix: Self::matrix_data(&transform),
+            complexity_score: day.complexity,
+            timestamp: day.timestamp,
+            interaction_count: day.interactions,
+            coloring_mode: ColoringMode::Standard,
+            palette: None,
+            params: Some(params),
+            derivation: day.derivation,
+            math_version: math::MATH_VERSION,
+            lineage: Lineage::default(),
+            curation: Curation::default(),
+        })
+    }
+
+    // Today's note, if the user has written one
+    pub fn get_day_note(&self) -> Option<String> {
+        match &self.today {
+            Some(day) if day.date == self.day => day.note.clone(),
+            _ => self.saved_note(&self.day),
+        }
+    }
 
+    // Attaches a note to today's fractal, kept with the day's record (and so in the
+    // history, syncs and, unless the export_notes preference is off, exports); an empty
+    // one removes it
+    pub fn set_day_note(&mut self, text: &str) -> Result<(), JsValue> {
+        if self.in_sandbox() {
+            return Err(JsValue::from_str("Notes are kept with the day's fractal; leave the sandbox first"));
+        }
+        let text = text.trim();
+        if text.chars().count() > MAX_NOTE_CHARS {
+            return Err(JsValue::from_str(&format!("Notes are at most {} characters", MAX_NOTE_CHARS)));
+        }
+        let mut day = self.update_today().clone();
+        day.note = (!text.is_empty()).then(|| text.to_string());
+        self.store.set(&Self::day_key(&day.date), &serde_json::to_string(&day).unwrap());
+        self.today = Some(day);
         Ok(())
     }
 
-    pub fn freeze_current_fractal(&mut self, fractal_type: String) -> Result<FrozenFractal, JsValue> {
-        let frozen = FrozenFractal {
+    fn saved_note(&self, date: &str) -> Option<String> {
+        let day: DayRecord = serde_json::from_str(&self.store.get(&Self::day_key(date))?).ok()?;
+        day.note
+    }
+
+    // Brings today's record up to date with the state about to be saved
+    fn update_today(&mut self) -> &DayRecord {
+        let date = self.day.clone();
+        let previous = self.today.take().filter(|day| day.date == date);
+        // A note outlives a change of seed (regenerate), and one written before a reload
+        // is still in the saved record
+        let note = match &previous {
+            Some(day) => day.note.clone(),
+            None => self.saved_note(&date),
+        };
+        let current = previous.filter(|day| day.seed == self.current_seed);
+        let mut day = current.unwrap_or_else(|| DayRecord {
+            date,
             seed: self.current_seed,
-            fractal_type,
-            transform_matrix: self.current_transform.as_slice().to_vec(),
-            complexity_score: self.get_complexity_score(),
-            timestamp: js_sys::Date::now() as u64,
-            interaction_count: self.daily_interactions,
+            derivation: CURRENT_DERIVATION,
+            complexity: 0.0,
+            interactions: 0,
+            thumbnail_hash: String::new(),
+            timestamp: 0,
+            note,
+            geometry_score: None,
+        });
+
+        let geometry_score = *day.geometry_score.get_or_insert_with(|| {
+            complexity::analyze(&FractalParams::from_seed_with(day.seed, day.derivation)).score
+        });
+        day.complexity = geometry_score + self.interaction_bonus();
+        day.interactions = self.daily_interactions;
+        day.timestamp = js_sys::Date::now() as u64;
+
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let bytes = day.seed.to_le_bytes().into_iter()
+            .chain(day.derivation.to_le_bytes())
+            .chain(Self::matrix_data(&self.get_current_transform()).into_iter().flat_map(f32::to_le_bytes));
+        for byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        day.thumbnail_hash = format!("{:016x}", hash);
+
+        self.today.insert(day)
+    }
+
+    // Written in the background; see Store. Writes that fail are reported through
+    // take_storage_errors rather than here
+    fn save_state(&mut self) {
+        // Nothing of the sandbox is kept
+        if !self.in_sandbox() {
+            // Save the day's record
+            let day = serde_json::to_string(self.update_today()).unwrap();
+            self.store.set(&Self::day_key(&self.day), &day);
+
+            // Save transform and its history
+            let matrix_data = Self::matrix_data(&self.get_current_transform());
+            let transform_json = serde_json::to_string(&matrix_data).unwrap();
+            self.store.set(&Self::transform_key(self.current_seed), &transform_json);
+            self.store.set(&Self::history_key(self.current_seed), &serde_json::to_string(&self.history).unwrap());
+
+            // Save interaction count
+            self.store.set(&Self::interactions_key(&self.day), &self.daily_interactions.to_string());
+        }
+
+        // Save frozen fractals and achievements
+        let frozen_json = serde_json::to_string(&self.frozen_fractals).unwrap();
+        self.store.set(FROZEN_KEY, &frozen_json);
+        self.store.set(ACHIEVEMENTS_KEY, &serde_json::to_string(&self.achievements.progress).unwrap());
+        self.store.set(LADDER_KEY, &serde_json::to_string(&self.ladder).unwrap());
+        self.store.set(GOLDEN_KEY, &serde_json::to_string(&self.golden).unwrap());
+        self.store.set(CONTACTS_KEY, &serde_json::to_string(&self.contacts).unwrap());
+        self.store.set(RESONANCES_KEY, &serde_json::to_string(&self.resonances).unwrap());
+
+        self.evict_old_days();
+    }
+
+    // Drops whole days, oldest first, while there are more than the history_days
+    // preference keeps or the records are over the store's budget; the last
+    // MIN_KEPT_DAYS always stay. Frozen fractals, achievements and the like are never
+    // evicted, and neither is anything before the store has loaded
+    fn evict_old_days(&mut self) {
+        if !self.store.is_ready() {
+            return;
+        }
+        let history_days = match self.preferences.history_days {
+            0 => usize::MAX,
+            days => (days as usize).max(MIN_KEPT_DAYS),
         };
+        let day_count = self.store.keys(DAY_PREFIX).len();
+        let over_budget = self.store.usage() > self.store.budget();
+        if day_count <= history_days && !over_budget {
+            return;
+        }
 
-        // Only keep the best 10 frozen fractals
-        self.frozen_fractals.push(frozen.clone());
+        let kept_from = (1..MIN_KEPT_DAYS).fold(self.day.clone(), |date, _| day::previous(&date));
+        let mut excess = day_count.saturating_sub(history_days);
+        for (date, keys) in self.stored_days() {
+            let wanted = excess > 0 || (over_budget && self.store.usage() > self.store.budget() * EVICTION_TARGET);
+            if !wanted || date >= kept_from {
+                break;
+            }
+            for key in keys {
+                self.store.remove(&key);
+            }
+            if !date.is_empty() {
+                excess = excess.saturating_sub(1);
+            }
+        }
+    }
+
+    // Every stored day's keys by date, oldest first. Transforms and undo histories are
+    // kept by seed, and go with the day recorded for it; those saved before days were
+    // recorded come first, under an empty date
+    fn stored_days(&self) -> BTreeMap<String, Vec<String>> {
+        let mut days: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for prefix in [DAY_PREFIX, INTERACTIONS_PREFIX, GESTURES_PREFIX] {
+            for key in self.store.keys(prefix) {
+                days.entry(key[prefix.len()..].to_string()).or_default().push(key);
+            }
+        }
+
+        // The latest day wins a seed two days happen to share
+        let seed_dates: BTreeMap<u32, String> = self.get_history("", "").into_iter()
+            .map(|day| (day.seed, day.date))
+            .collect();
+        for prefix in [TRANSFORM_PREFIX, HISTORY_PREFIX] {
+            for key in self.store.keys(prefix) {
+                let Ok(seed) = key[prefix.len()..].parse::<u32>() else {
+                    continue;
+                };
+                if seed == self.current_seed {
+                    continue;
+                }
+                let date = seed_dates.get(&seed).cloned().unwrap_or_default();
+                days.entry(date).or_default().push(key);
+            }
+        }
+        days
+    }
+
+    // Writes that have failed since the last call, most often for want of room
+    pub fn take_storage_errors(&mut self) -> Vec<StorageError> {
+        self.store.take_errors()
+    }
+
+    // Estimated bytes in use, and how many there may be before old days are evicted
+    pub fn get_storage_usage(&self) -> (f64, f64) {
+        (self.store.usage(), self.store.budget())
+    }
+
+    // Drops the least complex that aren't favorites until there are no more than the
+    // capacity, or only favorites left
+    fn keep_best_frozen(&mut self) {
         self.frozen_fractals.sort_by(|a, b| b.complexity_score.partial_cmp(&a.complexity_score).unwrap());
-        if self.frozen_fractals.len() > 10 {
-            self.frozen_fractals.truncate(10);
+        let mut excess = self.frozen_fractals.len().saturating_sub(self.preferences.frozen_capacity);
+        let mut kept = Vec::with_capacity(self.frozen_fractals.len());
+        for frozen in std::mem::take(&mut self.frozen_fractals).into_iter().rev() {
+            if excess > 0 && !frozen.curation.favorite {
+                excess -= 1;
+            } else {
+                kept.push(frozen);
+            }
         }
+        kept.reverse();
+        self.frozen_fractals = kept;
+    }
+
+    // The frozen fractals `filter` lets through, the most complex first
+    pub fn list_frozen(&self, filter: &FrozenFilter) -> Vec<FrozenFractal> {
+        self.frozen_fractals.iter().filter(|frozen| filter.matches(frozen)).cloned().collect()
+    }
 
-        self.save_state()?;
+    // Every collection in use, in order
+    pub fn get_collections(&self) -> Vec<String> {
+        let collections: std::collections::BTreeSet<&String> = self.frozen_fractals.iter()
+            .filter_map(|frozen| frozen.curation.collection.as_ref())
+            .collect();
+        collections.into_iter().cloned().collect()
+    }
+
+    pub fn update_frozen(&mut self, seed: u32, timestamp: u64, change: CurationChange) -> Result<FrozenFractal, JsValue> {
+        let frozen = self.frozen_fractals.iter_mut().find(|frozen| frozen.is(seed, timestamp))
+            .ok_or("No such frozen fractal")?;
+        let curation = &mut frozen.curation;
+        if let Some(collection) = change.collection {
+            curation.collection = (!collection.is_empty()).then_some(collection);
+        }
+        if let Some(tags) = change.tags {
+            curation.tags.clear();
+            for tag in tags {
+                if !tag.is_empty() && !curation.tags.contains(&tag) {
+                    curation.tags.push(tag);
+                }
+            }
+        }
+        if let Some(favorite) = change.favorite {
+            curation.favorite = favorite;
+        }
+        let updated = frozen.clone();
+
+        // Unmarking a favorite can leave more than there is room for
+        self.keep_best_frozen();
+        self.save_state();
+        Ok(updated)
+    }
+
+    // False if there was no such fractal
+    pub fn delete_frozen(&mut self, seed: u32, timestamp: u64) -> Result<bool, JsValue> {
+        let count = self.frozen_fractals.len();
+        self.frozen_fractals.retain(|frozen| !frozen.is(seed, timestamp));
+        if self.frozen_fractals.len() == count {
+            return Ok(false);
+        }
+        self.save_state();
+        Ok(true)
+    }
+
+    // Adds a frozen fractal made elsewhere (a bred child, an echo) to the collection, its
+    // lineage and all, subject to the capacity as freezes are
+    pub fn keep_frozen(&mut self, frozen: FrozenFractal) -> FrozenFractal {
+        self.merge_frozen(vec![frozen.clone()]);
+        self.save_state();
+        frozen
+    }
+
+    // The frozen fractal with this seed and timestamp and its ancestors, as far back as
+    // the collection knows them
+    pub fn get_lineage(&self, seed: u32, timestamp: u64) -> Result<LineageNode, JsValue> {
+        let frozen = self.frozen_fractals.iter().find(|frozen| frozen.is(seed, timestamp))
+            .ok_or("No such frozen fractal")?;
+        Ok(lineage::tree(frozen, &self.frozen_fractals))
+    }
+
+    // Lowering it drops the least complex non-favorites at once
+    pub fn set_frozen_capacity(&mut self, capacity: usize) -> Result<(), JsValue> {
+        self.set_preference("frozen_capacity", &capacity.to_string())
+    }
+
+    // Today's fractal as it currently stands, without saving it
+    pub fn snapshot_current_fractal(&self, coloring_mode: ColoringMode, palette: Palette,
+                                    params: FractalParams) -> FrozenFractal {
+        FrozenFractal {
+            version: FROZEN_VERSION,
+            seed: self.current_seed,
+            fractal_type: Some(params.fractal_type()),
+            transform_matrix: Self::matrix_data(&self.get_current_transform()),
+            complexity_score: self.get_complexity_score(&params),
+            timestamp: js_sys::Date::now() as u64,
+            interaction_count: if self.in_sandbox() { 0 } else { self.daily_interactions },
+            coloring_mode,
+            palette: Some(palette),
+            params: Some(params),
+            derivation: CURRENT_DERIVATION,
+            math_version: math::MATH_VERSION,
+            lineage: Lineage::default(),
+            curation: Curation::default(),
+        }
+    }
+
+    pub fn freeze_current_fractal(&mut self, coloring_mode: ColoringMode, palette: Palette,
+                                  params: FractalParams) -> Result<FrozenFractal, JsValue> {
+        let frozen = self.snapshot_current_fractal(coloring_mode, palette, params);
+
+        // Only keep the best, up to the capacity
+        self.frozen_fractals.push(frozen.clone());
+        self.keep_best_frozen();
+        self.achievements.record_freeze(js_sys::Date::now() as u64);
+
+        self.save_state();
         Ok(frozen)
     }
 
-    pub fn battle_against_fractal(&self, opponent_json: &str) -> Result<BattleResult, JsValue> {
+    // How strongly the golden-hour aura shows now, 0..1
+    pub fn golden_aura(&self) -> f32 {
+        golden::aura(&self.golden_windows, self.preferences.day_boundary.minute_of_day())
+    }
+
+    // The golden hour under way, or else the next: today's if one is still to come,
+    // otherwise tomorrow's first, as its start and end in ms since the epoch. Tomorrow's
+    // is reckoned from today's clock, so a DST change overnight can put it an hour out
+    pub fn next_golden_hour(&self) -> Option<(f64, f64)> {
+        let now = js_sys::Date::now();
+        let minute = self.preferences.day_boundary.minute_of_day();
+        let at = |minutes_ahead: f64| now + minutes_ahead * 60.0 * 1000.0;
+
+        if let Some(window) = self.golden_windows.iter().find(|window| window.end() as f64 > minute) {
+            return Some((at(window.start as f64 - minute), at(window.end() as f64 - minute)));
+        }
+        let tomorrow_seed = SeedQuality::choose(Self::generate_daily_seed(&self.user_id, &day::next(&self.day))).seed;
+        let window = golden::windows(tomorrow_seed).into_iter().next()?;
+        let midnight = 24.0 * 60.0 - minute;
+        Some((at(midnight + window.start as f64), at(midnight + window.end() as f64)))
+    }
+
+    pub fn is_golden_hour(&self) -> bool {
+        golden::active(&self.golden_windows, self.preferences.day_boundary.minute_of_day()).is_some()
+    }
+
+    // Windows hit and gestures made in them, all time and today, as JSON
+    pub fn get_golden_stats(&self) -> String {
+        serde_json::json!({
+            "windows_hit": self.golden.windows_hit,
+            "interactions": self.golden.interactions,
+            "today_interactions": self.golden.interactions_on(&self.day),
+            "today_windows_hit": if self.golden.day == self.day { self.golden.day_windows.len() } else { 0 },
+            "windows": self.golden_windows,
+        }).to_string()
+    }
+
+    // Befriends whoever shared `url` (get_share_url's or a received message's link), by
+    // `name` if given, taking their public key if the link carries one
+    pub fn add_friend_from_share_url(&mut self, url: &str, name: Option<&str>) -> Result<Contact, JsValue> {
+        let (id, share_key) = contacts::from_share_url(url)?;
+        if id == self.user_id {
+            return Err(JsValue::from_str("That's your own link"));
+        }
+        let friend = self.contacts.add(&id, name, share_key, js_sys::Date::now() as u64)?.clone();
+        self.save_contacts();
+        Ok(friend)
+    }
+
+    pub fn get_contacts(&self) -> &Contacts {
+        &self.contacts
+    }
+
+    pub fn remove_friend(&mut self, id: &str) -> bool {
+        let removed = self.contacts.remove(id);
+        if removed {
+            self.save_contacts();
+        }
+        removed
+    }
+
+    pub fn rename_friend(&mut self, id: &str, name: &str) -> Result<(), JsValue> {
+        self.contacts.rename(id, name, js_sys::Date::now() as u64)?;
+        self.save_contacts();
+        Ok(())
+    }
+
+    // Notes a message from `id`, if they're a friend
+    pub fn friend_seen(&mut self, id: &str, at: u64) {
+        if self.contacts.seen(id, at) {
+            self.save_contacts();
+        }
+    }
+
+    fn save_contacts(&self) {
+        self.store.set(CONTACTS_KEY, &serde_json::to_string(&self.contacts).unwrap());
+    }
+
+    // Rating, wins and losses, and the recent battles, as JSON
+    pub fn get_battle_record(&self) -> String {
+        serde_json::to_string(&self.ladder).unwrap()
+    }
+
+    pub fn get_achievements(&self) -> String {
+        serde_json::to_string(&self.achievements.report()).unwrap()
+    }
+
+    // Adds a resonance to the history, and counts it toward the achievements
+    pub fn record_resonance(&mut self, event: ResonanceEvent) {
+        self.achievements.record_resonance_event(event.started_at);
+        self.resonances.record(event);
+        self.store.set(RESONANCES_KEY, &serde_json::to_string(&self.resonances).unwrap());
+        self.store.set(ACHIEVEMENTS_KEY, &serde_json::to_string(&self.achievements.progress).unwrap());
+    }
+
+    pub fn get_resonances(&self) -> &ResonanceHistory {
+        &self.resonances
+    }
+
+    // When the latest of the day's gestures was made, ms since the epoch
+    pub fn last_gesture_at(&self) -> Option<u64> {
+        self.gestures.last().map(|gesture| gesture.timestamp)
+    }
+
+    // Achievements unlocked since the last call, to announce
+    pub fn take_unlocked_achievements(&mut self) -> Vec<AchievementInfo> {
+        self.achievements.take_unlocked()
+    }
+
+    // Battles today's fractal against a frozen one and records the outcome: the rating
+    // moves, the battle joins the history and counts toward the achievements. The
+    // opponent's JSON may carry its owner's "user_id" and "rating" alongside the fractal;
+    // an unrated opponent is taken to be at the starting rating
+    pub fn battle_against_fractal(&mut self, opponent_json: &str, current_params: FractalParams) -> Result<BattleResult, JsValue> {
+        let current = self.battle_snapshot(current_params)?;
         let opponent: FrozenFractal = serde_json::from_str(opponent_json)
             .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let challenger: Challenger = serde_json::from_str(opponent_json).unwrap_or_default();
+
+        let scores = battle::scores(&current, &opponent)
+            .ok_or("The opponent's complexity doesn't check out against its fractal")?;
+        let opponent_id = challenger.user_id.unwrap_or_else(|| format!("seed_{}", opponent.seed));
+        let opponent_rating = challenger.rating.unwrap_or(DEFAULT_RATING);
+        Ok(self.record_battle(current, opponent, opponent_id, opponent_rating,
+                              (scores.challenger, scores.opponent, scores.resonance)))
+    }
 
-        // Create current fractal for battle
-        let current = FrozenFractal {
+    // Records a networked battle once both ends have scored it alike (see battle.rs);
+    // None for one that hasn't been
+    pub fn record_challenge(&mut self, challenge: &Challenge) -> Option<BattleResult> {
+        let scores = challenge.own_scores()?;
+        let (own, theirs) = (challenge.own.clone()?, challenge.theirs.clone()?);
+        Some(self.record_battle(own, theirs, challenge.opponent_id.clone(), challenge.their_rating, scores))
+    }
+
+    // Today's fractal as it goes into battle; not from the sandbox
+    pub fn battle_snapshot(&self, current_params: FractalParams) -> Result<FrozenFractal, JsValue> {
+        if self.in_sandbox() {
+            return Err(JsValue::from_str("Battles are fought with the day's fractal; leave the sandbox first"));
+        }
+        Ok(FrozenFractal {
+            version: FROZEN_VERSION,
             seed: self.current_seed,
-            fractal_type: "Current".to_string(),
-            transform_matrix: self.current_transform.as_slice().to_vec(),
-            complexity_score: self.get_complexity_score(),
+            fractal_type: Some(current_params.fractal_type()),
+            transform_matrix: Self::matrix_data(&self.get_current_transform()),
+            complexity_score: self.get_complexity_score(&current_params),
             timestamp: js_sys::Date::now() as u64,
             interaction_count: self.daily_interactions,
-        };
+            coloring_mode: ColoringMode::Standard,
+            palette: None,
+            params: Some(current_params),
+            derivation: CURRENT_DERIVATION,
+            math_version: math::MATH_VERSION,
+            lineage: Lineage::default(),
+            curation: Curation::default(),
+        })
+    }
 
-        // Battle algorithm: complexity + resonance
-        let self_score = current.complexity_score + self.calculate_resonance(&current, &opponent);
-        let opponent_score = opponent.complexity_score + self.calculate_resonance(&opponent, &current);
+    // The rating moves, the battle joins the history and counts toward the achievements.
+    // `scores` are the user's, the opponent's and the resonance between them
+    fn record_battle(&mut self, current: FrozenFractal, opponent: FrozenFractal, opponent_id: String,
+                     opponent_rating: f32, scores: (f32, f32, f32)) -> BattleResult {
+        let (self_score, opponent_score, resonance_factor) = scores;
+        let won = self_score > opponent_score;
 
-        let resonance_factor = self.calculate_resonance(&current, &opponent);
+        let now = js_sys::Date::now() as u64;
+        let battle = self.ladder.record(Battle {
+            opponent_id,
+            opponent_seed: opponent.seed,
+            opponent_rating: Some(opponent_rating).filter(|rating| rating.is_finite()).unwrap_or(DEFAULT_RATING),
+            score_self: self_score,
+            score_opponent: opponent_score,
+            resonance: resonance_factor,
+            won,
+            rating_change: 0.0,
+            timestamp: now,
+        });
+        self.achievements.record_battle(won, resonance_factor, now);
+        self.save_state();
 
-        let winner = if self_score > opponent_score {
+        let winner = if won {
             current
         } else {
             opponent
         };
 
-        Ok(BattleResult {
+        BattleResult {
             winner,
             score_self: self_score,
             score_opponent: opponent_score,
             resonance_factor,
-        })
+            rating: self.ladder.rating,
+            rating_change: battle.rating_change,
+        }
     }
 
-    fn calculate_resonance(&self, fractal_a: &FrozenFractal, fractal_b: &FrozenFractal) -> f32 {
-        // Calculate mathematical resonance between two fractals
-        let matrix_a = Matrix4::from_row_slice(&fractal_a.transform_matrix);
-        let matrix_b = Matrix4::from_row_slice(&fractal_b.transform_matrix);
+    pub fn get_rating(&self) -> f32 {
+        self.ladder.rating
+    }
 
-        // Calculate trace similarity (simplified resonance measure)
-        let trace_a = matrix_a.trace();
-        let trace_b = matrix_b.trace();
-        let trace_similarity = 1.0 / (1.0 + (trace_a - trace_b).abs());
+    pub fn get_best_frozen_fractal(&self) -> Option<&FrozenFractal> {
+        self.frozen_fractals.first()
+    }
 
-        // Seed harmony (how well seeds work together)
-        let seed_diff = (fractal_a.seed as f32 - fractal_b.seed as f32).abs();
-        let seed_harmony = 1.0 / (1.0 + seed_diff / 1000.0);
+    // Starts over as a new, unrelated user: a fresh id and key, and so fresh daily seeds,
+    // from today's on. Nothing saved under the old id is shared from here; the frozen
+    // fractals and achievements stay
+    pub fn regenerate_identity(&mut self) -> Result<(), JsValue> {
+        self.exit_sandbox();
+        self.user_id = Self::generate_user_id()?;
+        self.storage.set_item(USER_ID_KEY, &self.user_id)?;
+        self.take_identity(None, true)?;
+        self.reset_daily_state()
+    }
 
-        (trace_similarity + seed_harmony) * 0.5
+    // Explores `seed` from its untouched fractal, with a transform and undo history of
+    // its own, none of it saved: gestures count for nothing and the day's state is left
+    // as it was, to come back to with exit_sandbox. In the sandbox already, moves on to
+    // `seed` afresh. Waits for the saved state to load, so it can't be set aside half read
+    pub fn enter_sandbox(&mut self, seed: u32) -> Result<(), JsValue> {
+        self.poll_store();
+        if !self.store.is_ready() {
+            return Err(JsValue::from_str("Saved state is still loading"));
+        }
+        if self.sandbox.is_none() {
+            self.sandbox = Some(Parked {
+                seed_quality: self.seed_quality,
+                pose: self.pose,
+                history: std::mem::take(&mut self.history),
+            });
+        }
+        self.seed_quality = SeedQuality::evaluate(seed);
+        self.current_seed = seed;
+        self.pose = Pose::default();
+        self.history = TransformHistory::default();
+        Ok(())
     }
 
-    pub fn get_best_frozen_fractal(&self) -> Option<&FrozenFractal> {
-        self.frozen_fractals.first()
+    // Back to the day's own seed and transform, as they were left; false if not in the
+    // sandbox
+    pub fn exit_sandbox(&mut self) -> bool {
+        let Some(parked) = self.sandbox.take() else {
+            return false;
+        };
+        self.seed_quality = parked.seed_quality;
+        self.current_seed = parked.seed_quality.seed;
+        self.pose = parked.pose;
+        self.history = parked.history;
+        // The day may have changed meanwhile
+        self.next_rollover_check = 0.0;
+        true
     }
 
-    pub fn reset_daily_state(&mut self) -> Result<(), JsValue> {
-        // Reset for new day
-        self.current_seed = Self::generate_daily_seed(&self.user_id);
-        self.current_transform = Matrix4::identity();
+    pub fn in_sandbox(&self) -> bool {
+        self.sandbox.is_some()
+    }
+
+    // Poses the sandbox's fractal as `transform` has it, with nothing to undo; not outside
+    // the sandbox, where the pose is the day's own
+    pub fn set_sandbox_transform(&mut self, transform: &Matrix4<f32>) -> Result<(), JsValue> {
+        if !self.in_sandbox() {
+            return Err(JsValue::from_str("Not in the sandbox"));
+        }
+        self.pose = Pose::from_matrix(transform);
+        self.history = TransformHistory::default();
+        Ok(())
+    }
+
+    // Poses the sandbox's fractal as someone else's gesture in a room left it; the user's
+    // own steps can still be undone
+    pub fn set_sandbox_pose(&mut self, pose: Pose) -> Result<(), JsValue> {
+        if !self.in_sandbox() {
+            return Err(JsValue::from_str("Not in the sandbox"));
+        }
+        self.pose = pose;
+        Ok(())
+    }
+
+    // Deletes everything this crate has saved on the device, in IndexedDB and in
+    // localStorage, under every key it has ever used, and reports what went. What is in
+    // memory starts over as a new user whose id isn't saved; reload straight after, as
+    // anything done meanwhile would be saved again without it. A sync backend's copy
+    // isn't touched. Waits for the saved state to load, so none of it is missed
+    pub fn erase_all_data(&mut self) -> Result<ErasureReport, JsValue> {
+        self.poll_store();
+        if !self.store.is_ready() {
+            return Err(JsValue::from_str("Saved state is still loading"));
+        }
+
+        let mut removed = self.store.clear();
+        let keys: Vec<String> = (0..self.storage.length()?)
+            .filter_map(|i| self.storage.key(i).ok().flatten())
+            .filter(|key| key.starts_with("resonant_") || key == GESTURE_FEEDBACK_KEY)
+            .collect();
+        for key in keys {
+            self.storage.remove_item(&key)?;
+            if !removed.contains(&key) {
+                removed.push(key);
+            }
+        }
+        removed.sort();
+
+        let mut report = ErasureReport::default();
+        for key in &removed {
+            *report.counts.entry(Self::key_kind(key).to_string()).or_insert(0) += 1;
+        }
+        report.removed = removed;
+
+        self.sandbox = None;
+        self.user_id = Self::generate_user_id()?;
+        self.identity = Identity::generate()?;
+        self.preferences = Preferences::default();
+        self.day = self.preferences.day_boundary.today();
+        self.seed_quality = SeedQuality::choose(Self::generate_daily_seed(&self.user_id, &self.day));
+        self.current_seed = self.seed_quality.seed;
+        self.golden_windows = golden::windows(self.current_seed);
+        self.pose = Pose::default();
+        self.history = TransformHistory::default();
+        self.gestures.clear();
         self.daily_interactions = 0;
-        self.save_state()
+        self.frozen_fractals.clear();
+        self.today = None;
+        self.achievements = Achievements::new(Progress::default());
+        self.usage = Usage::default();
+        self.ladder = Ladder::default();
+        self.golden = GoldenStats::default();
+        self.contacts = Contacts::default();
+        self.resonances = ResonanceHistory::default();
+        self.activity = ActivityLog::default();
+        self.gesture_rate = RateLimiter::default();
+        self.changed = true;
+        Ok(report)
+    }
+
+    fn key_kind(key: &str) -> &'static str {
+        // Before the prefixes: the legacy "resonant_day_boundary" starts like a day
+        if preferences::STORAGE_KEYS.contains(&key) {
+            return "preferences";
+        }
+        let kinds = [
+            (TRANSFORM_PREFIX, "transforms"), (HISTORY_PREFIX, "undo_histories"),
+            (INTERACTIONS_PREFIX, "interaction_counts"), (DAY_PREFIX, "days"), (GESTURES_PREFIX, "gesture_logs"),
+            (FROZEN_KEY, "frozen_fractals"), (ACHIEVEMENTS_KEY, "achievements"), (USAGE_KEY, "usage"),
+            (LADDER_KEY, "battles"), (GOLDEN_KEY, "golden_hours"), (CONTACTS_KEY, "contacts"),
+            (RESONANCES_KEY, "resonances"),
+        ];
+        kinds.iter().find(|(prefix, _)| key.starts_with(prefix)).map_or("profile", |&(_, kind)| kind)
+    }
+
+    // The date (YYYY-MM-DD) the current seed is for
+    pub fn get_day(&self) -> &str {
+        &self.day
+    }
+
+    // Saved for next time; if it puts today on another date, rolls over to it at once
+    pub fn set_day_boundary(&mut self, boundary: DayBoundary) -> Result<Option<u32>, JsValue> {
+        self.set_preference("day_boundary", boundary.name())?;
+        self.check_rollover()
+    }
+
+    // Moves on to the new day's seed once the date changes, at midnight or when the
+    // device's timezone carries it into another date, returning the seed it moved on
+    // from. Call once per frame; the clock is only read every ROLLOVER_CHECK_INTERVAL
+    pub fn check_rollover(&mut self) -> Result<Option<u32>, JsValue> {
+        // The sandbox isn't interrupted; exit_sandbox checks straight after
+        let now = js_sys::Date::now();
+        if now < self.next_rollover_check || self.in_sandbox() {
+            return Ok(None);
+        }
+        self.next_rollover_check = now + ROLLOVER_CHECK_INTERVAL;
+
+        let today = self.preferences.day_boundary.today();
+        if today == self.day {
+            return Ok(None);
+        }
+        let old_seed = self.current_seed;
+        self.day = today;
+        self.reset_daily_state()?;
+        Ok(Some(old_seed))
+    }
+
+    // Rederives the seed for the day, and picks up anything already saved for it
+    // (by another tab, or before travelling back across the date line)
+    pub fn reset_daily_state(&mut self) -> Result<(), JsValue> {
+        self.seed_quality = SeedQuality::choose(Self::generate_daily_seed(&self.user_id, &self.day));
+        self.current_seed = self.seed_quality.seed;
+        self.golden_windows = golden::windows(self.current_seed);
+        self.pose = self.store.get(&Self::transform_key(self.current_seed))
+            .map_or_else(Pose::default, |data| Pose::from_matrix(&Self::parse_transform(&data)));
+        self.history = self.store.get(&Self::history_key(self.current_seed))
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        self.gestures = self.store.get(&Self::gestures_key(&self.day))
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        self.daily_interactions = self.store.get(&Self::interactions_key(&self.day))
+            .map_or(0, |count| count.parse().unwrap_or(0));
+        self.changed = true;
+        self.save_state();
+        Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+// Voices AudioEngine plays at most until set_max_voices says otherwise; the fractal asks
+// for up to 12, and the quietest of those are the first to go
+pub const DEFAULT_MAX_VOICES: usize = 8;
+pub const MAX_VOICES: usize = 32;
+
+// What to do with one oscillator slot this update
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotAction {
+    // Still playing the same voice, which glides to its new frequency
+    Keep { slot: usize, voice: usize },
+    // Takes over a voice: a new slot starts it, an existing one releases whatever it was
+    // playing first
+    Assign { slot: usize, voice: usize },
+    // Nothing left to play; fades out
+    Release { slot: usize },
+}
+
+// Maps the voices the fractal asks for (by index into its frequency list) onto a capped
+// set of oscillator slots. The loudest voices win; a voice keeps its slot for as long as
+// it stays in, so it glides rather than restarts, and a newcomer takes a silent slot, then
+// a new one while under the cap, and only then steals the quietest voice that dropped out
+pub struct VoiceAllocator {
+    max_voices: usize,
+    slots: Vec<Option<usize>>,
+}
+
+impl VoiceAllocator {
+    pub fn new(max_voices: usize) -> Self {
+        VoiceAllocator { max_voices: max_voices.clamp(1, MAX_VOICES), slots: Vec::new() }
+    }
+
+    // The voice each slot is playing
+    pub fn slots(&self) -> &[Option<usize>] {
+        &self.slots
+    }
+
+    // `amplitudes` has one entry per voice asked for
+    pub fn allocate(&mut self, amplitudes: &[f32]) -> Vec<SlotAction> {
+        let mut by_priority: Vec<usize> = (0..amplitudes.len()).collect();
+        by_priority.sort_by(|&a, &b| amplitudes[b].partial_cmp(&amplitudes[a]).unwrap_or(std::cmp::Ordering::Equal));
+        by_priority.truncate(self.max_voices);
+
+        let mut actions = Vec::new();
+        let mut unplaced = Vec::new();
+        for &voice in &by_priority {
+            match self.slots.iter().position(|&playing| playing == Some(voice)) {
+                Some(slot) => actions.push(SlotAction::Keep { slot, voice }),
+                None => unplaced.push(voice),
+            }
+        }
+
+        // Slots free for newcomers, quietest first: empty ones, then voices that dropped out
+        let level = |playing: Option<usize>| playing.and_then(|voice| amplitudes.get(voice).copied()).unwrap_or(0.0);
+        let mut free: Vec<usize> = (0..self.slots.len())
+            .filter(|&slot| !matches!(self.slots[slot], Some(voice) if by_priority.contains(&voice)))
+            .collect();
+        free.sort_by(|&a, &b| {
+            (self.slots[a].is_some(), level(self.slots[a]))
+                .partial_cmp(&(self.slots[b].is_some(), level(self.slots[b])))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut free = free.into_iter().peekable();
+
+        for voice in unplaced {
+            // Under the cap a fresh slot beats cutting a voice short
+            let silent = free.peek().is_some_and(|&slot| self.slots[slot].is_none());
+            let slot = if silent || self.slots.len() >= self.max_voices {
+                match free.next() {
+                    Some(slot) => slot,
+                    None => break,
+                }
+            } else {
+                self.slots.push(None);
+                self.slots.len() - 1
+            };
+            self.slots[slot] = Some(voice);
+            actions.push(SlotAction::Assign { slot, voice });
+        }
+
+        for slot in free {
+            if self.slots[slot].take().is_some() {
+                actions.push(SlotAction::Release { slot });
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assigned(actions: &[SlotAction]) -> Vec<(usize, usize)> {
+        actions.iter().filter_map(|action| match *action {
+            SlotAction::Assign { slot, voice } => Some((slot, voice)),
+            _ => None,
+        }).collect()
+    }
+
+    // A voice still asked for keeps its slot however the order of loudness changes
+    #[test]
+    fn surviving_voices_keep_their_slots() {
+        let mut allocator = VoiceAllocator::new(4);
+        allocator.allocate(&[1.0, 0.5]);
+        let actions = allocator.allocate(&[0.2, 0.9]);
+        assert!(assigned(&actions).is_empty(), "{:?}", actions);
+        assert!(actions.contains(&SlotAction::Keep { slot: 0, voice: 0 }));
+        assert!(actions.contains(&SlotAction::Keep { slot: 1, voice: 1 }));
+        assert_eq!(allocator.slots(), [Some(0), Some(1)]);
+    }
+
+    // A newcomer takes the slot a released voice left silent before the bank grows
+    #[test]
+    fn silent_slots_fill_before_the_bank_grows() {
+        let mut allocator = VoiceAllocator::new(4);
+        allocator.allocate(&[1.0, 0.5]);
+        assert_eq!(allocator.allocate(&[1.0]), [SlotAction::Keep { slot: 0, voice: 0 }, SlotAction::Release { slot: 1 }]);
+        let actions = allocator.allocate(&[1.0, 0.0, 0.7]);
+        assert_eq!(assigned(&actions), [(1, 2), (2, 1)]);
+        assert_eq!(allocator.slots(), [Some(0), Some(2), Some(1)]);
+    }
+
+    // At the cap, newcomers steal from the voices that dropped out, quietest first
+    #[test]
+    fn the_quietest_dropped_voice_is_stolen_at_the_cap() {
+        let mut allocator = VoiceAllocator::new(3);
+        allocator.allocate(&[1.0, 0.6, 0.3]);
+        let actions = allocator.allocate(&[1.0, 0.2, 0.3, 0.9, 0.8]);
+        assert_eq!(assigned(&actions), [(1, 3), (2, 4)]);
+        assert_eq!(allocator.slots(), [Some(0), Some(3), Some(4)]);
+    }
+
+    // Under a cap lowered past the slots there are, only the loudest keep sounding and
+    // no slot is added
+    #[test]
+    fn a_lowered_cap_releases_the_quietest() {
+        let mut allocator = VoiceAllocator::new(4);
+        allocator.allocate(&[1.0, 0.8, 0.6, 0.4]);
+        allocator.max_voices = 2;
+        let actions = allocator.allocate(&[1.0, 0.8, 0.6, 0.4, 0.9]);
+        assert_eq!(actions, [
+            SlotAction::Keep { slot: 0, voice: 0 },
+            SlotAction::Assign { slot: 3, voice: 4 },
+            SlotAction::Release { slot: 2 },
+            SlotAction::Release { slot: 1 },
+        ]);
+        assert_eq!(allocator.slots(), [Some(0), None, None, Some(4)]);
+    }
+}
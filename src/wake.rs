@@ -0,0 +1,65 @@
+use serde::{Serialize, Deserialize};
+
+// Away this long, in ms, and coming back counts as waking up
+const WAKE_GAP: f64 = 4.0 * 3600.0 * 1000.0;
+
+// Away this long and coming back starts a new session, short of a wake-up
+const SESSION_GAP: f64 = 30.0 * 60.0 * 1000.0;
+
+// Sessions kept in the log, most recent last
+const MAX_SESSIONS: usize = 50;
+
+// How often, in ms, a session that's still open is marked as seen
+pub const ACTIVITY_INTERVAL: f64 = 60.0 * 1000.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Session {
+    pub start: f64, // ms since the epoch
+    pub end: f64,   // Last seen
+    pub wake: bool, // Began after at least WAKE_GAP away
+}
+
+// A rolling log of when the app was in use, from which the wake time is read: the start
+// of the first session after four hours or more away, which is most often the morning,
+// but follows the user's own day rather than the clock
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ActivityLog {
+    pub sessions: Vec<Session>,
+}
+
+impl ActivityLog {
+    // Marks the app as in use at `now`, starting a session (and perhaps a waking day)
+    // after a gap; true when this starts a new one
+    pub fn touch(&mut self, now: f64) -> bool {
+        let away = self.sessions.last().map(|last| now - last.end);
+        match away {
+            Some(away) if away <= SESSION_GAP => {
+                let last = self.sessions.last_mut().unwrap();
+                last.end = last.end.max(now);
+                false
+            }
+            _ => {
+                // The first session ever counts as a wake-up
+                let wake = away.is_none_or(|away| away >= WAKE_GAP);
+                self.sessions.push(Session { start: now, end: now, wake });
+                if self.sessions.len() > MAX_SESSIONS {
+                    self.sessions.remove(0);
+                }
+                true
+            }
+        }
+    }
+
+    // Start of the latest waking period, ms since the epoch; None before any activity
+    pub fn wake_time(&self) -> Option<f64> {
+        self.sessions.iter().rev().find(|session| session.wake)
+            .or(self.sessions.first())
+            .map(|session| session.start)
+    }
+
+    // Whether the session under way is the first since waking
+    pub fn is_wake_session(&self) -> bool {
+        self.sessions.last().is_some_and(|session| session.wake)
+    }
+}
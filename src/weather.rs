@@ -0,0 +1,230 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram};
+use nalgebra::{Vector3, Vector4};
+use serde::{Serialize, Deserialize};
+use crate::fractals::*;
+use crate::glsl::GlslSnippet;
+use crate::math;
+use crate::noise::{self, NOISE_SLOPE};
+use crate::palette::Palette;
+use crate::rng::SeededRng;
+
+// SeededRng stream for the daily modifier
+const WEATHER_STREAM: u64 = 0x3EA7_0001;
+
+// Shape of the three effects at strength 1; mirrored by weather_warp() and
+// weather_surface() in FRAGMENT_SHADER
+const WARP_AMPLITUDE: f32 = 0.08;
+const WARP_FREQUENCY: f32 = 3.0;
+const EROSION_DEPTH: f32 = 0.02;
+const EROSION_FREQUENCY: f32 = 17.0;
+const BLOOM_RADIUS: f32 = 0.03;
+
+// Seed-derived strengths stay below this so the daily fractal is still recognisable
+const MAX_DAILY_STRENGTH: f32 = 0.5;
+
+// Noise displacement from the day's interactions; mirrored by roughen() in FRAGMENT_SHADER
+const ROUGHNESS_FREQUENCY: f32 = 9.0;
+const ROUGHNESS_PER_INTERACTION: f32 = 0.0015;
+const MAX_ROUGHNESS: f32 = 0.03;
+
+// Host-provided conditions, e.g. from a weather API; every field is optional and a
+// missing one leaves that effect at its seed-derived strength
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct WeatherConditions {
+    pub wind_speed: Option<f32>,    // m/s
+    pub humidity: Option<f32>,      // 0..1
+    pub precipitation: Option<f32>, // mm/h
+    pub temperature: Option<f32>,   // °C
+}
+
+// Where the modifier strengths come from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeatherSource {
+    Off,
+    Seed,
+    Conditions(WeatherConditions),
+}
+
+// Perturbation layered over any base fractal's distance field: turbulence warps space
+// before the formula runs, erosion pits the surface and bloom inflates it (strengths 0..1),
+// and roughness displaces it by gradient noise.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifier {
+    pub turbulence: f32,
+    pub erosion: f32,
+    pub bloom: f32,
+    pub phase: [f32; 3], // Offsets of the warp and erosion waves, so every day's pattern differs
+    pub roughness: f32,  // Noise amplitude in world units, see with_interactions
+}
+
+impl Modifier {
+    // `day` is days since the Unix epoch; the daily seed already changes with the date,
+    // but mixing it in keeps fixed seeds (shared links, frozen fractals) changing too
+    pub fn from_seed(seed: u32, day: u32) -> Self {
+        let mut rng = SeededRng::new(((seed as u64) << 32) | day as u64, WEATHER_STREAM);
+        Modifier {
+            turbulence: rng.range_f32(0.0, MAX_DAILY_STRENGTH),
+            erosion: rng.range_f32(0.0, MAX_DAILY_STRENGTH),
+            bloom: rng.range_f32(0.0, MAX_DAILY_STRENGTH),
+            phase: [
+                rng.range_f32(0.0, std::f32::consts::TAU),
+                rng.range_f32(0.0, std::f32::consts::TAU),
+                rng.range_f32(0.0, std::f32::consts::TAU),
+            ],
+            roughness: 0.0,
+        }
+    }
+
+    // Wind stirs, wet weather erodes and heat blooms; the phase stays seed-derived
+    pub fn from_conditions(seed: u32, day: u32, conditions: &WeatherConditions) -> Self {
+        let daily = Self::from_seed(seed, day);
+        let clamp = |v: f32| v.max(0.0).min(1.0);
+
+        let erosion = match (conditions.precipitation, conditions.humidity) {
+            (None, None) => daily.erosion,
+            (rain, humidity) => clamp(rain.unwrap_or(0.0) / 5.0 * 0.6 + humidity.unwrap_or(0.0) * 0.4),
+        };
+
+        Modifier {
+            turbulence: conditions.wind_speed.map(|wind| clamp(wind / 20.0)).unwrap_or(daily.turbulence),
+            erosion,
+            bloom: conditions.temperature.map(|t| clamp((t - 5.0) / 30.0)).unwrap_or(daily.bloom),
+            phase: daily.phase,
+            roughness: 0.0,
+        }
+    }
+
+    // Heavily-interacted fractals roughen over the day, up to MAX_ROUGHNESS
+    pub fn with_interactions(self, interactions: u32) -> Self {
+        Modifier {
+            roughness: (interactions as f32 * ROUGHNESS_PER_INTERACTION).min(MAX_ROUGHNESS),
+            ..self
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.turbulence > 0.0 || self.erosion > 0.0 || self.bloom > 0.0 || self.roughness > 0.0
+    }
+
+    pub fn warp(&self, p: &Vector3<f32>) -> Vector3<f32> {
+        let wave = Vector3::new(
+            math::sin(WARP_FREQUENCY * p.y + self.phase[0]),
+            math::sin(WARP_FREQUENCY * p.z + self.phase[1]),
+            math::sin(WARP_FREQUENCY * p.x + self.phase[2]),
+        );
+        p + wave * self.turbulence * WARP_AMPLITUDE
+    }
+
+    // `dist` is the base estimate at warp(p). The warp stretches space by up to
+    // 1 + turbulence * WARP_AMPLITUDE * WARP_FREQUENCY, so the estimate is scaled down
+    // by that to stay a safe raymarch step.
+    pub fn surface(&self, dist: f32, p: &Vector3<f32>) -> f32 {
+        let pits = 0.5 + 0.5
+            * math::sin(EROSION_FREQUENCY * p.x + self.phase[2])
+            * math::sin(EROSION_FREQUENCY * p.y + self.phase[0])
+            * math::sin(EROSION_FREQUENCY * p.z + self.phase[1]);
+        let dist = dist / (1.0 + self.turbulence * WARP_AMPLITUDE * WARP_FREQUENCY)
+            + self.erosion * EROSION_DEPTH * pits
+            - self.bloom * BLOOM_RADIUS;
+        self.roughen(dist, p)
+    }
+
+    // Noise displacement; the displacement's slope is divided out so the estimate still
+    // never overshoots the surface
+    fn roughen(&self, dist: f32, p: &Vector3<f32>) -> f32 {
+        if self.roughness <= 0.0 {
+            return dist;
+        }
+        let displacement = self.roughness * noise::gradient_noise(&(p * ROUGHNESS_FREQUENCY));
+        (dist + displacement) / (1.0 + self.roughness * ROUGHNESS_FREQUENCY * NOISE_SLOPE)
+    }
+
+    // The base fractal seen through this modifier, for the CPU paths
+    pub fn wrap(self, base: Box<dyn FractalGenerator>) -> Box<dyn FractalGenerator> {
+        if !self.is_active() {
+            return base;
+        }
+        Box::new(Weathered { base, modifier: self })
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram) {
+        if let Some(loc) = gl.get_uniform_location(program, "u_weather") {
+            gl.uniform3f(Some(&loc), self.turbulence, self.erosion, self.bloom);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_weather_phase") {
+            gl.uniform3f(Some(&loc), self.phase[0], self.phase[1], self.phase[2]);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_roughness") {
+            gl.uniform1f(Some(&loc), self.roughness);
+        }
+    }
+}
+
+impl WeatherSource {
+    // "off", "seed", or WeatherConditions JSON
+    pub fn parse(source: &str) -> Result<Self, JsValue> {
+        match source {
+            "off" => Ok(WeatherSource::Off),
+            "seed" => Ok(WeatherSource::Seed),
+            json => serde_json::from_str(json)
+                .map(WeatherSource::Conditions)
+                .map_err(|e| JsValue::from_str(&format!("Invalid weather conditions: {}", e))),
+        }
+    }
+
+    pub fn modifier(&self, seed: u32) -> Modifier {
+        let day = (js_sys::Date::now() / 86_400_000.0) as u32;
+        match self {
+            WeatherSource::Off => Modifier::default(),
+            WeatherSource::Seed => Modifier::from_seed(seed, day),
+            WeatherSource::Conditions(conditions) => Modifier::from_conditions(seed, day, conditions),
+        }
+    }
+}
+
+// A base fractal with a Modifier applied to its distance field; everything else is the base's
+pub struct Weathered {
+    pub base: Box<dyn FractalGenerator>,
+    pub modifier: Modifier,
+}
+
+impl FractalGenerator for Weathered {
+    fn distance_estimator(&self, pos: &Vector4<f32>) -> f32 {
+        let dist = self.base.distance_estimator(&self.warped(pos));
+        self.modifier.surface(dist, &pos.xyz())
+    }
+
+    fn get_color(&self, iterations: f32, distance: f32, pos: &Vector4<f32>) -> Vector3<f32> {
+        self.base.get_color(iterations, distance, pos)
+    }
+
+    fn to_glsl(&self) -> Option<GlslSnippet> { self.base.to_glsl() }
+    fn get_coloring_mode(&self) -> ColoringMode { self.base.get_coloring_mode() }
+    fn get_palette(&self) -> &Palette { self.base.get_palette() }
+
+    fn with_iterations(&self, iterations: i32) -> Box<dyn FractalGenerator> {
+        self.modifier.wrap(self.base.with_iterations(iterations))
+    }
+
+    fn orbit_trap(&self, pos: &Vector4<f32>) -> f32 {
+        self.base.orbit_trap(&self.warped(pos))
+    }
+
+    fn get_params(&self) -> FractalParams {
+        self.base.get_params()
+    }
+
+    fn orbit_stats(&self, pos: &Vector4<f32>, max_iter: i32) -> OrbitStats {
+        self.base.orbit_stats(&self.warped(pos), max_iter)
+    }
+}
+
+impl Weathered {
+    // The orbits run on the warped point, as in the shader
+    fn warped(&self, pos: &Vector4<f32>) -> Vector4<f32> {
+        let p = self.modifier.warp(&pos.xyz());
+        Vector4::new(p.x, p.y, p.z, pos.w)
+    }
+}
@@ -0,0 +1,951 @@
+// The relay's binary frames: a version byte, the frame's kind, then its fields in a fixed
+// order. Integers are LEB128 varints (zigzagged where they may be negative), floats their
+// four little-endian bytes, strings and lists a varint length and then their contents,
+// and an optional field a 0 or 1 byte before it. Share links keep the JSON; this is only
+// for the socket, where every message is a whole frozen fractal
+use crate::battle::{BattleScores, BattleSignal, BattleStage};
+use crate::crypto::Envelope;
+use crate::echo::{EchoDelta, EchoStep};
+use crate::environment::GradientStop;
+use crate::fractals::{ColoringMode, FractalParams, HybridFormula, HybridSpec};
+use crate::lineage::{Lineage, ParentRef};
+use crate::network::{Frame, FractalMessage, MessageType, Presence, PresenceStatus};
+use crate::palette::Palette;
+use crate::registry::FractalType;
+use crate::room::{Register, RoomState, RoomStatus, RoomUpdate, Stamp};
+use crate::user::{Curation, FrozenFractal};
+
+// First byte of every frame. A layout change bumps it, and decode keeps reading every
+// version before; a frame with a later one is refused rather than misread. Version 2
+// added messages' recipients, version 3 their battle challenge steps, version 4 sealed
+// messages, version 5 presence, version 6 rooms, version 7 live echoes, and version 8
+// clock probes and messages' epochs
+pub const WIRE_VERSION: u8 = 8;
+
+// Versions that added a message's trailing fields, each read only from frames at least
+// that new
+const RECIPIENTS_VERSION: u8 = 2;
+const BATTLE_VERSION: u8 = 3;
+const EPOCH_VERSION: u8 = 8;
+
+// Longest string or list decode will allocate for; a frame claiming more is corrupt
+const MAX_LENGTH: u64 = 64 * 1024;
+
+const FRAME_HELLO: u8 = 0;
+const FRAME_MESSAGE: u8 = 1;
+const FRAME_ACK: u8 = 2;
+const FRAME_PEERS: u8 = 3;
+const FRAME_SEALED: u8 = 4;
+const FRAME_PRESENCE: u8 = 5;
+const FRAME_ROOM: u8 = 6;
+const FRAME_ECHO: u8 = 7;
+const FRAME_TIME: u8 = 8;
+
+pub fn encode(frame: &Frame) -> Vec<u8> {
+    let mut writer = Writer(vec![WIRE_VERSION]);
+    match frame {
+        Frame::Hello { user_id } => {
+            writer.u8(FRAME_HELLO);
+            writer.string(user_id);
+        }
+        Frame::Message { id, message } => {
+            writer.u8(FRAME_MESSAGE);
+            writer.varint(*id);
+            writer.message(message);
+        }
+        Frame::Ack { id } => {
+            writer.u8(FRAME_ACK);
+            writer.varint(*id);
+        }
+        Frame::Peers { peers } => {
+            writer.u8(FRAME_PEERS);
+            writer.varint(peers.len() as u64);
+            for peer in peers {
+                writer.string(peer);
+            }
+        }
+        Frame::Sealed { id, envelope } => {
+            writer.u8(FRAME_SEALED);
+            writer.varint(*id);
+            writer.envelope(envelope);
+        }
+        Frame::Presence { presence } => {
+            writer.u8(FRAME_PRESENCE);
+            writer.string(&presence.user_id);
+            writer.u8(match presence.status {
+                PresenceStatus::Joined => 0,
+                PresenceStatus::Active => 1,
+                PresenceStatus::Left => 2,
+            });
+            writer.option(&presence.idle, |writer, &idle| writer.varint(idle as u64));
+            writer.varint(presence.recipients.len() as u64);
+            for recipient in &presence.recipients {
+                writer.string(recipient);
+            }
+        }
+        Frame::Room { update } => {
+            writer.u8(FRAME_ROOM);
+            writer.room(update);
+        }
+        Frame::Echo { step } => {
+            writer.u8(FRAME_ECHO);
+            writer.echo(step);
+        }
+        Frame::Time { sent, received, replied } => {
+            writer.u8(FRAME_TIME);
+            writer.varint(*sent);
+            writer.option(received, |writer, &received| writer.varint(received));
+            writer.option(replied, |writer, &replied| writer.varint(replied));
+        }
+    }
+    writer.0
+}
+
+// A message alone, as a sealed one carries it: the version byte, then the message as a
+// frame holds it
+pub fn encode_message(message: &FractalMessage) -> Vec<u8> {
+    let mut writer = Writer(vec![WIRE_VERSION]);
+    writer.message(message);
+    writer.0
+}
+
+pub fn decode_message(bytes: &[u8]) -> Result<FractalMessage, String> {
+    let mut reader = Reader { bytes, at: 0, version: 0 };
+    reader.version = reader.u8()?;
+    if reader.version == 0 || reader.version > WIRE_VERSION {
+        return Err(format!("Wire version {}, expected {} or before", reader.version, WIRE_VERSION));
+    }
+    let message = reader.message()?;
+    if reader.at != bytes.len() {
+        return Err(format!("{} bytes left over", bytes.len() - reader.at));
+    }
+    Ok(message)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Frame, String> {
+    let mut reader = Reader { bytes, at: 0, version: 0 };
+    reader.version = reader.u8()?;
+    if reader.version == 0 || reader.version > WIRE_VERSION {
+        return Err(format!("Wire version {}, expected {} or before", reader.version, WIRE_VERSION));
+    }
+    let frame = match reader.u8()? {
+        FRAME_HELLO => Frame::Hello { user_id: reader.string()? },
+        FRAME_MESSAGE => Frame::Message { id: reader.varint()?, message: reader.message()? },
+        FRAME_ACK => Frame::Ack { id: reader.varint()? },
+        FRAME_PEERS => Frame::Peers { peers: reader.list(Reader::string)? },
+        FRAME_SEALED => Frame::Sealed { id: reader.varint()?, envelope: reader.envelope()? },
+        FRAME_PRESENCE => Frame::Presence { presence: reader.presence()? },
+        FRAME_ROOM => Frame::Room { update: reader.room()? },
+        FRAME_ECHO => Frame::Echo { step: reader.echo()? },
+        FRAME_TIME => Frame::Time {
+            sent: reader.varint()?,
+            received: reader.option(Reader::varint)?,
+            replied: reader.option(Reader::varint)?,
+        },
+        kind => return Err(format!("Unknown frame kind {}", kind)),
+    };
+    if reader.at != bytes.len() {
+        return Err(format!("{} bytes left over", bytes.len() - reader.at));
+    }
+    Ok(frame)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.varint(((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn option<T>(&mut self, value: &Option<T>, write: impl FnOnce(&mut Self, &T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn floats(&mut self, values: &[f32]) {
+        self.varint(values.len() as u64);
+        for &value in values {
+            self.f32(value);
+        }
+    }
+
+    fn message(&mut self, message: &FractalMessage) {
+        self.string(&message.sender_id);
+        self.u8(match message.message_type {
+            MessageType::Morning => 0,
+            MessageType::Echo => 1,
+            MessageType::Battle => 2,
+            MessageType::Resonance => 3,
+        });
+        self.varint(message.timestamp);
+        self.option(&message.transform_echo, |writer, matrix| writer.floats(matrix));
+        self.fractal(&message.fractal_data);
+        self.varint(message.recipients.len() as u64);
+        for recipient in &message.recipients {
+            self.string(recipient);
+        }
+        self.option(&message.battle, Writer::battle);
+        self.option(&message.epoch, |writer, &epoch| writer.varint(epoch));
+    }
+
+    // The fixed-length keys and nonce as they are, the rest with their lengths
+    fn envelope(&mut self, envelope: &Envelope) {
+        self.string(&envelope.sender_id);
+        self.0.extend_from_slice(&envelope.sender_key);
+        self.varint(envelope.recipients.len() as u64);
+        for recipient in &envelope.recipients {
+            self.string(recipient);
+        }
+        self.varint(envelope.keys.len() as u64);
+        for key in &envelope.keys {
+            self.bytes(key);
+        }
+        self.0.extend_from_slice(&envelope.nonce);
+        self.bytes(&envelope.ciphertext);
+    }
+
+    // Each part's value, then its stamp
+    fn room(&mut self, update: &RoomUpdate) {
+        self.string(&update.room_id);
+        self.string(&update.user_id);
+        self.u8(match update.status {
+            RoomStatus::Joined => 0,
+            RoomStatus::Update => 1,
+            RoomStatus::Left => 2,
+        });
+        let state = &update.state;
+        self.varint(state.seed.value as u64);
+        self.stamp(&state.seed.stamp);
+        for (values, stamp) in [(state.rotation.value.as_slice(), &state.rotation.stamp),
+                                (state.scale.value.as_slice(), &state.scale.stamp),
+                                (state.translation.value.as_slice(), &state.translation.stamp)] {
+            for &value in values {
+                self.f32(value);
+            }
+            self.stamp(stamp);
+        }
+    }
+
+    fn stamp(&mut self, stamp: &Stamp) {
+        self.varint(stamp.clock);
+        self.string(&stamp.user_id);
+    }
+
+    // A keyframe's packed pose as it is; a delta's parts zigzagged, so small ones take a byte
+    fn echo(&mut self, step: &EchoStep) {
+        self.string(&step.sender_id);
+        self.varint(step.stream);
+        self.varint(step.sequence as u64);
+        self.varint(step.recipients.len() as u64);
+        for recipient in &step.recipients {
+            self.string(recipient);
+        }
+        match &step.delta {
+            EchoDelta::Key { pose } => {
+                self.u8(0);
+                self.0.extend_from_slice(pose);
+            }
+            EchoDelta::Delta { base, rotation, scale, translation } => {
+                self.u8(1);
+                self.varint(*base as u64);
+                for &part in rotation.iter().chain(scale).chain(translation) {
+                    self.i32(part);
+                }
+            }
+            EchoDelta::End => self.u8(2),
+        }
+    }
+
+    fn battle(&mut self, signal: &BattleSignal) {
+        self.string(&signal.challenge_id);
+        match signal.stage {
+            BattleStage::Challenge { rating } => {
+                self.u8(0);
+                self.f32(rating);
+            }
+            BattleStage::Accept { rating, scores } => {
+                self.u8(1);
+                self.f32(rating);
+                self.scores(&scores);
+            }
+            BattleStage::Decline => self.u8(2),
+            BattleStage::Result { scores } => {
+                self.u8(3);
+                self.scores(&scores);
+            }
+        }
+    }
+
+    fn scores(&mut self, scores: &BattleScores) {
+        self.f32(scores.challenger);
+        self.f32(scores.opponent);
+        self.f32(scores.resonance);
+    }
+
+    fn fractal(&mut self, fractal: &FrozenFractal) {
+        self.varint(fractal.version as u64);
+        self.varint(fractal.seed as u64);
+        self.option(&fractal.fractal_type, |writer, kind| writer.u8(kind.id()));
+        self.floats(&fractal.transform_matrix);
+        self.f32(fractal.complexity_score);
+        self.varint(fractal.timestamp);
+        self.varint(fractal.interaction_count as u64);
+        self.u8(fractal.coloring_mode.shader_id() as u8);
+        self.option(&fractal.palette, |writer, palette| {
+            writer.varint(palette.stops.len() as u64);
+            for stop in &palette.stops {
+                writer.f32(stop.position);
+                writer.floats(&stop.color);
+            }
+        });
+        self.option(&fractal.params, |writer, params| writer.params(params));
+        self.varint(fractal.derivation as u64);
+        self.varint(fractal.math_version as u64);
+
+        self.varint(fractal.lineage.generation as u64);
+        self.varint(fractal.lineage.parents.len() as u64);
+        for parent in &fractal.lineage.parents {
+            self.varint(parent.seed as u64);
+            self.varint(parent.timestamp);
+            self.varint(parent.generation as u64);
+        }
+
+        self.option(&fractal.curation.collection, |writer, collection| writer.string(collection));
+        self.varint(fractal.curation.tags.len() as u64);
+        for tag in &fractal.curation.tags {
+            self.string(tag);
+        }
+        self.bool(fractal.curation.favorite);
+    }
+
+    // The registry id, then the variant's fields in declaration order
+    fn params(&mut self, params: &FractalParams) {
+        self.u8(params.fractal_type().id());
+        match *params {
+            FractalParams::Mandelbulb { power, iterations } => {
+                self.f32(power);
+                self.i32(iterations);
+            }
+            FractalParams::Julia4D { c, iterations } => {
+                self.floats(&c);
+                self.i32(iterations);
+            }
+            FractalParams::KaleidoIFS { fold_count, scale } => {
+                self.i32(fold_count);
+                self.f32(scale);
+            }
+            FractalParams::Mandelbox { scale, min_radius, iterations } => {
+                self.f32(scale);
+                self.f32(min_radius);
+                self.i32(iterations);
+            }
+            FractalParams::MengerSponge { fold_count, hole_scale } => {
+                self.i32(fold_count);
+                self.f32(hole_scale);
+            }
+            FractalParams::Apollonian { inversion, iterations } => {
+                self.f32(inversion);
+                self.i32(iterations);
+            }
+            FractalParams::Hybrid(spec) => {
+                self.formula(&spec.first);
+                self.formula(&spec.second);
+                self.varint(spec.first_steps as u64);
+                self.varint(spec.second_steps as u64);
+            }
+            FractalParams::Mandelbrot2D { julia, iterations } => {
+                self.option(&julia, |writer, julia| writer.floats(julia));
+                self.i32(iterations);
+            }
+            FractalParams::LSystem { rule_seed, iterations, angle } => {
+                self.varint(rule_seed as u64);
+                self.i32(iterations);
+                self.f32(angle);
+            }
+        }
+    }
+
+    fn formula(&mut self, formula: &HybridFormula) {
+        match *formula {
+            HybridFormula::Mandelbulb { power } => {
+                self.u8(0);
+                self.f32(power);
+            }
+            HybridFormula::Mandelbox { scale, min_radius } => {
+                self.u8(1);
+                self.f32(scale);
+                self.f32(min_radius);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    version: u8, // Of the frame being read
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let end = self.at.checked_add(count).filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| format!("Frame ends at {} of {} bytes", self.bytes.len(), self.at.saturating_add(count)))?;
+        let taken = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(format!("{} is not a flag", other)),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Varint longer than 64 bits".to_string())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let value = self.varint()?;
+        u32::try_from(value).map_err(|_| format!("{} is out of range", value))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let zigzag = self.u32()?;
+        Ok((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        let bytes = self.take(4)?;
+        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn length(&mut self) -> Result<usize, String> {
+        let length = self.varint()?;
+        if length > MAX_LENGTH {
+            return Err(format!("Length {} is too long", length));
+        }
+        Ok(length as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.length()?;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.length()?;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn list<T>(&mut self, read: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let length = self.length()?;
+        (0..length).map(|_| read(self)).collect()
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<Option<T>, String> {
+        if self.bool()? { read(self).map(Some) } else { Ok(None) }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[f32; N], String> {
+        let values = self.list(Reader::f32)?;
+        values.try_into().map_err(|values: Vec<f32>| format!("{} values, expected {}", values.len(), N))
+    }
+
+    fn fractal_type(&mut self) -> Result<FractalType, String> {
+        let id = self.u8()?;
+        FractalType::from_id(id).ok_or_else(|| format!("Unknown fractal type id {}", id))
+    }
+
+    fn message(&mut self) -> Result<FractalMessage, String> {
+        let sender_id = self.string()?;
+        let message_type = match self.u8()? {
+            0 => MessageType::Morning,
+            1 => MessageType::Echo,
+            2 => MessageType::Battle,
+            3 => MessageType::Resonance,
+            other => return Err(format!("Unknown message type {}", other)),
+        };
+        Ok(FractalMessage {
+            sender_id,
+            message_type,
+            timestamp: self.varint()?,
+            transform_echo: self.option(|reader| reader.list(Reader::f32))?,
+            fractal_data: self.fractal()?,
+            recipients: if self.version >= RECIPIENTS_VERSION { self.list(Reader::string)? } else { Vec::new() },
+            battle: if self.version >= BATTLE_VERSION { self.option(Reader::battle)? } else { None },
+            epoch: if self.version >= EPOCH_VERSION { self.option(Reader::varint)? } else { None },
+        })
+    }
+
+    fn envelope(&mut self) -> Result<Envelope, String> {
+        Ok(Envelope {
+            sender_id: self.string()?,
+            sender_key: self.fixed()?,
+            recipients: self.list(Reader::string)?,
+            keys: self.list(Reader::bytes)?,
+            nonce: self.fixed()?,
+            ciphertext: self.bytes()?,
+        })
+    }
+
+    fn presence(&mut self) -> Result<Presence, String> {
+        Ok(Presence {
+            user_id: self.string()?,
+            status: match self.u8()? {
+                0 => PresenceStatus::Joined,
+                1 => PresenceStatus::Active,
+                2 => PresenceStatus::Left,
+                other => return Err(format!("Unknown presence status {}", other)),
+            },
+            idle: self.option(Reader::u32)?,
+            recipients: self.list(Reader::string)?,
+        })
+    }
+
+    fn room(&mut self) -> Result<RoomUpdate, String> {
+        Ok(RoomUpdate {
+            room_id: self.string()?,
+            user_id: self.string()?,
+            status: match self.u8()? {
+                0 => RoomStatus::Joined,
+                1 => RoomStatus::Update,
+                2 => RoomStatus::Left,
+                other => return Err(format!("Unknown room status {}", other)),
+            },
+            state: RoomState {
+                seed: Register { value: self.u32()?, stamp: self.stamp()? },
+                rotation: Register { value: [self.f32()?, self.f32()?, self.f32()?, self.f32()?], stamp: self.stamp()? },
+                scale: Register { value: [self.f32()?, self.f32()?, self.f32()?], stamp: self.stamp()? },
+                translation: Register { value: [self.f32()?, self.f32()?, self.f32()?], stamp: self.stamp()? },
+            },
+        })
+    }
+
+    fn stamp(&mut self) -> Result<Stamp, String> {
+        Ok(Stamp { clock: self.varint()?, user_id: self.string()? })
+    }
+
+    fn echo(&mut self) -> Result<EchoStep, String> {
+        Ok(EchoStep {
+            sender_id: self.string()?,
+            stream: self.varint()?,
+            sequence: self.u32()?,
+            recipients: self.list(Reader::string)?,
+            delta: match self.u8()? {
+                0 => EchoDelta::Key { pose: self.fixed()? },
+                1 => EchoDelta::Delta {
+                    base: self.u32()?,
+                    rotation: [self.i32()?, self.i32()?, self.i32()?],
+                    scale: [self.i32()?, self.i32()?, self.i32()?],
+                    translation: [self.i32()?, self.i32()?, self.i32()?],
+                },
+                2 => EchoDelta::End,
+                other => return Err(format!("Unknown echo step {}", other)),
+            },
+        })
+    }
+
+    fn battle(&mut self) -> Result<BattleSignal, String> {
+        let challenge_id = self.string()?;
+        let stage = match self.u8()? {
+            0 => BattleStage::Challenge { rating: self.f32()? },
+            1 => BattleStage::Accept { rating: self.f32()?, scores: self.scores()? },
+            2 => BattleStage::Decline,
+            3 => BattleStage::Result { scores: self.scores()? },
+            other => return Err(format!("Unknown battle stage {}", other)),
+        };
+        Ok(BattleSignal { challenge_id, stage })
+    }
+
+    fn scores(&mut self) -> Result<BattleScores, String> {
+        Ok(BattleScores { challenger: self.f32()?, opponent: self.f32()?, resonance: self.f32()? })
+    }
+
+    fn fractal(&mut self) -> Result<FrozenFractal, String> {
+        Ok(FrozenFractal {
+            version: self.u32()?,
+            seed: self.u32()?,
+            fractal_type: self.option(Reader::fractal_type)?,
+            transform_matrix: self.list(Reader::f32)?,
+            complexity_score: self.f32()?,
+            timestamp: self.varint()?,
+            interaction_count: self.u32()?,
+            coloring_mode: {
+                let id = self.u8()?;
+                ColoringMode::from_shader_id(id as i32).ok_or_else(|| format!("Unknown coloring mode {}", id))?
+            },
+            // Refused past Palette::validate's bounds, as params are below
+            palette: self.option(|reader| {
+                let stops = reader.list(|reader| Ok(GradientStop { position: reader.f32()?, color: reader.array()? }))?;
+                let palette = Palette { stops };
+                palette.validate()?;
+                Ok(palette)
+            })?,
+            params: self.option(Reader::params)?,
+            derivation: self.u32()?,
+            math_version: self.u32()?,
+            lineage: Lineage {
+                generation: self.u32()?,
+                parents: self.list(|reader| Ok(ParentRef {
+                    seed: reader.u32()?,
+                    timestamp: reader.varint()?,
+                    generation: reader.u32()?,
+                }))?,
+            },
+            curation: Curation {
+                collection: self.option(Reader::string)?,
+                tags: self.list(Reader::string)?,
+                favorite: self.bool()?,
+            },
+        })
+    }
+
+    // Refused past FractalParams::validate's bounds, as a peer may send anything
+    fn params(&mut self) -> Result<FractalParams, String> {
+        let params = match self.fractal_type()? {
+            FractalType::Mandelbulb => FractalParams::Mandelbulb { power: self.f32()?, iterations: self.i32()? },
+            FractalType::Julia4D => FractalParams::Julia4D { c: self.array()?, iterations: self.i32()? },
+            FractalType::KaleidoIFS => FractalParams::KaleidoIFS { fold_count: self.i32()?, scale: self.f32()? },
+            FractalType::Mandelbox => FractalParams::Mandelbox {
+                scale: self.f32()?,
+                min_radius: self.f32()?,
+                iterations: self.i32()?,
+            },
+            FractalType::MengerSponge => FractalParams::MengerSponge { fold_count: self.i32()?, hole_scale: self.f32()? },
+            FractalType::Apollonian => FractalParams::Apollonian { inversion: self.f32()?, iterations: self.i32()? },
+            FractalType::Hybrid => FractalParams::Hybrid(HybridSpec {
+                first: self.formula()?,
+                second: self.formula()?,
+                first_steps: self.u32()?,
+                second_steps: self.u32()?,
+            }),
+            FractalType::Mandelbrot2D => FractalParams::Mandelbrot2D {
+                julia: self.option(Reader::array)?,
+                iterations: self.i32()?,
+            },
+            FractalType::LSystem => FractalParams::LSystem {
+                rule_seed: self.u32()?,
+                iterations: self.i32()?,
+                angle: self.f32()?,
+            },
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    fn formula(&mut self) -> Result<HybridFormula, String> {
+        match self.u8()? {
+            0 => Ok(HybridFormula::Mandelbulb { power: self.f32()? }),
+            1 => Ok(HybridFormula::Mandelbox { scale: self.f32()?, min_radius: self.f32()? }),
+            other => Err(format!("Unknown hybrid formula {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractals::HYBRID_ITERATIONS;
+    use crate::palette::MAX_PALETTE_STOPS;
+
+    // One of everything a frame can hold: each frame kind and message type, each params
+    // variant and its optional parts, palettes, lineage and curation
+    fn sample_frames() -> Vec<(String, Frame)> {
+        let params = [
+            FractalParams::Mandelbulb { power: 8.0, iterations: 12 },
+            FractalParams::Julia4D { c: [0.25, -0.5, 0.125, 0.0], iterations: 10 },
+            FractalParams::KaleidoIFS { fold_count: 3, scale: -1.75 },
+            FractalParams::Mandelbox { scale: -1.5, min_radius: 0.5, iterations: 12 },
+            FractalParams::MengerSponge { fold_count: 4, hole_scale: 3.0 },
+            FractalParams::Apollonian { inversion: 1.2, iterations: 1 },
+            FractalParams::Hybrid(HybridSpec::from_seed(20240611)),
+            FractalParams::Hybrid(HybridSpec {
+                first: HybridFormula::Mandelbox { scale: 2.0, min_radius: 0.25 },
+                second: HybridFormula::Mandelbulb { power: 3.0 },
+                first_steps: HYBRID_ITERATIONS as u32,
+                second_steps: 1,
+            }),
+            FractalParams::Mandelbrot2D { julia: None, iterations: 256 },
+            FractalParams::Mandelbrot2D { julia: Some([-0.8, 0.156]), iterations: 1 },
+            FractalParams::LSystem { rule_seed: 7, iterations: 4, angle: 25.7 },
+        ];
+        let coloring_modes = [ColoringMode::Standard, ColoringMode::PointTrap, ColoringMode::LineTrap,
+                              ColoringMode::PlaneTrap, ColoringMode::Equalized];
+        let message_types = [MessageType::Morning, MessageType::Echo, MessageType::Battle, MessageType::Resonance];
+
+        let fractal = |i: usize, params: Option<FractalParams>| FrozenFractal {
+            version: 1,
+            seed: 20240611 + i as u32,
+            fractal_type: params.map(|params| params.fractal_type()),
+            transform_matrix: (0..16).map(|j| (i * 16 + j) as f32 * -0.375).collect(),
+            complexity_score: 1.5 + i as f32,
+            timestamp: 1_718_064_000_000 + i as u64,
+            interaction_count: i as u32 * 1000,
+            coloring_mode: coloring_modes[i % coloring_modes.len()],
+            palette: (i % 2 == 1).then(|| Palette::named("fire").expect("fire is a built-in palette")),
+            params,
+            derivation: (i % 2) as u32,
+            math_version: 1,
+            lineage: if i.is_multiple_of(3) {
+                Lineage::default()
+            } else {
+                Lineage { generation: 2, parents: vec![ParentRef { seed: 7, timestamp: u64::MAX, generation: 1 }] }
+            },
+            curation: Curation {
+                collection: i.is_multiple_of(2).then(|| "Spirals ✨".to_string()),
+                tags: (0..i % 3).map(|tag| format!("tag{}", tag)).collect(),
+                favorite: i.is_multiple_of(2),
+            },
+        };
+
+        let mut frames = vec![
+            ("hello".to_string(), Frame::Hello { user_id: "user_abc".to_string() }),
+            ("hello, no id".to_string(), Frame::Hello { user_id: String::new() }),
+            ("ack".to_string(), Frame::Ack { id: 1_718_064_000_123 }),
+            ("ack, largest id".to_string(), Frame::Ack { id: u64::MAX }),
+            ("peers".to_string(), Frame::Peers { peers: vec!["a".to_string(), "b".to_string()] }),
+            ("no peers".to_string(), Frame::Peers { peers: Vec::new() }),
+        ];
+        let all_params = params.iter().map(|&params| Some(params)).chain([None]);
+        for (i, params) in all_params.enumerate() {
+            let message_type = message_types[i % message_types.len()].clone();
+            let name = format!("message {}, {}", i, params.map_or("seed params", |params| params.name()));
+            let message = FractalMessage {
+                sender_id: format!("user_{}", i),
+                fractal_data: fractal(i, params),
+                transform_echo: (i % 2 == 1).then(|| (0..16).map(|j| j as f32 / 7.0).collect()),
+                timestamp: 1_718_064_000_000 + i as u64,
+                message_type,
+                recipients: (0..i % 3).map(|recipient| format!("friend_{}", recipient)).collect(),
+                battle: None,
+                epoch: (i % 4 == 3).then(|| 1_718_063_000_000 + i as u64),
+            };
+            frames.push((name, Frame::Message { id: i as u64, message }));
+        }
+
+        let scores = BattleScores { challenger: 12.625, opponent: -0.1, resonance: 0.75 };
+        let stages = [
+            BattleStage::Challenge { rating: 1234.5 },
+            BattleStage::Accept { rating: f32::MAX, scores },
+            BattleStage::Decline,
+            BattleStage::Result { scores },
+        ];
+        for (i, stage) in stages.into_iter().enumerate() {
+            let message = FractalMessage {
+                sender_id: "user_abc".to_string(),
+                fractal_data: fractal(i, params.get(i).copied()),
+                transform_echo: None,
+                timestamp: 1_718_064_000_000,
+                message_type: MessageType::Battle,
+                recipients: vec!["user_def".to_string()],
+                battle: Some(BattleSignal { challenge_id: format!("user_abc:{}", 1_718_064_000_000 + i), stage }),
+                epoch: None,
+            };
+            frames.push((format!("battle step {}", i), Frame::Message { id: 100 + i as u64, message }));
+        }
+
+        // Made up rather than sealed: only the layout is checked here
+        let envelope = |recipients: usize| Envelope {
+            sender_id: "user_abc".to_string(),
+            sender_key: [0xa5; 32],
+            recipients: (0..recipients).map(|recipient| format!("friend_{}", recipient)).collect(),
+            keys: (0..recipients).map(|recipient| vec![recipient as u8; 72]).collect(),
+            nonce: [0x5a; 24],
+            ciphertext: (0..=255).collect(),
+        };
+        frames.push(("sealed".to_string(), Frame::Sealed { id: 200, envelope: envelope(2) }));
+        frames.push(("sealed, one recipient".to_string(), Frame::Sealed { id: u64::MAX, envelope: envelope(1) }));
+
+        let statuses = [(PresenceStatus::Joined, Some(0)), (PresenceStatus::Active, Some(u32::MAX)),
+                        (PresenceStatus::Active, None), (PresenceStatus::Left, None)];
+        for (i, (status, idle)) in statuses.into_iter().enumerate() {
+            let presence = Presence {
+                user_id: "user_abc".to_string(),
+                status,
+                idle,
+                recipients: (0..i).map(|recipient| format!("friend_{}", recipient)).collect(),
+            };
+            frames.push((format!("presence {}", i), Frame::Presence { presence }));
+        }
+
+        let stamp = |clock, user_id: &str| Stamp { clock, user_id: user_id.to_string() };
+        let state = RoomState {
+            seed: Register { value: u32::MAX, stamp: stamp(1, "user_abc") },
+            rotation: Register { value: [0.0, 0.6, 0.0, -0.8], stamp: stamp(u64::MAX, "user_def") },
+            scale: Register { value: [0.5, 2.0, 1.25], stamp: stamp(0, "") },
+            translation: Register { value: [-5.0, 0.0, 0.125], stamp: stamp(42, "user_abc") },
+        };
+        for (i, status) in [RoomStatus::Joined, RoomStatus::Update, RoomStatus::Left].into_iter().enumerate() {
+            let update = RoomUpdate {
+                room_id: format!("room {} ✨", i),
+                user_id: "user_abc".to_string(),
+                status,
+                state: state.clone(),
+            };
+            frames.push((format!("room {}", i), Frame::Room { update }));
+        }
+
+        let deltas = [
+            EchoDelta::Key { pose: [0x7f; crate::pose::PACKED_LEN] },
+            EchoDelta::Delta { base: 0, rotation: [1, -1, 0], scale: [8192, -8192, 0], translation: [i32::MAX, i32::MIN, 63] },
+            EchoDelta::Delta { base: u32::MAX, rotation: [0; 3], scale: [0; 3], translation: [0; 3] },
+            EchoDelta::End,
+        ];
+        for (i, delta) in deltas.into_iter().enumerate() {
+            let step = EchoStep {
+                sender_id: "user_abc".to_string(),
+                stream: 1_718_064_000_000,
+                sequence: i as u32 * 1000,
+                recipients: (0..i % 3).map(|recipient| format!("friend_{}", recipient)).collect(),
+                delta,
+            };
+            frames.push((format!("echo step {}", i), Frame::Echo { step }));
+        }
+
+        frames.push(("time probe".to_string(), Frame::Time { sent: 1_718_064_000_000, received: None, replied: None }));
+        frames.push(("time answer".to_string(), Frame::Time {
+            sent: 1_718_064_000_000,
+            received: Some(1_718_064_005_012),
+            replied: Some(u64::MAX),
+        }));
+        frames
+    }
+
+    // Every sample frame decodes to exactly what was encoded, is smaller than its JSON, and
+    // cut short anywhere, or given a later version, is refused rather than misread
+    #[test]
+    fn frames_round_trip() {
+        for (name, frame) in sample_frames() {
+            let bytes = encode(&frame);
+            assert_decodes(&name, &frame, &bytes);
+            let json = serde_json::to_string(&frame).unwrap();
+            assert!(bytes.len() < json.len(), "{}: {} bytes, no smaller than its {} bytes of JSON", name, bytes.len(), json.len());
+            if let Some(length) = (0..bytes.len()).find(|&length| decode(&bytes[..length]).is_ok()) {
+                panic!("{}: read when cut to {} of {} bytes", name, length, bytes.len());
+            }
+            let mut later_version = bytes.clone();
+            later_version[0] = WIRE_VERSION + 1;
+            assert!(decode(&later_version).is_err(), "{}: read with a later version", name);
+        }
+    }
+
+    // Each message as every version it fits in wrote it: the same, but for the version and
+    // none of the trailing fields added since
+    #[test]
+    fn earlier_versions_still_read() {
+        let added = [RECIPIENTS_VERSION, BATTLE_VERSION, EPOCH_VERSION];
+        for (name, frame) in sample_frames() {
+            let Frame::Message { message, .. } = &frame else {
+                continue;
+            };
+            // The first version that could carry everything the message holds
+            let needed = [
+                (!message.recipients.is_empty()).then_some(RECIPIENTS_VERSION),
+                message.battle.is_some().then_some(BATTLE_VERSION),
+                message.epoch.is_some().then_some(EPOCH_VERSION),
+            ];
+            let oldest = needed.into_iter().flatten().max().unwrap_or(1);
+            for version in oldest..WIRE_VERSION {
+                let mut bytes = encode(&frame);
+                bytes[0] = version;
+                bytes.truncate(bytes.len() - added.iter().filter(|&&added| version < added).count());
+                assert_decodes(&format!("{}, version {}", name, version), &frame, &bytes);
+            }
+        }
+    }
+
+    // A message alone, as sealed messages carry it, reads back as it was written
+    #[test]
+    fn messages_alone_round_trip() {
+        for (name, frame) in sample_frames() {
+            if let Frame::Message { message, .. } = frame {
+                let decoded = decode_message(&encode_message(&message)).unwrap_or_else(|e| panic!("{}: {}", name, e));
+                assert_eq!(serde_json::to_string(&decoded).unwrap(), serde_json::to_string(&message).unwrap(), "{}", name);
+            }
+        }
+    }
+
+    // A message whose params would keep the estimators going for billions of steps is refused
+    #[test]
+    fn params_past_the_bounds_are_refused() {
+        let Some(Frame::Message { id, mut message }) = sample_frames().into_iter()
+            .map(|(_, frame)| frame)
+            .find(|frame| matches!(frame, Frame::Message { .. })) else {
+            panic!("No message among the samples");
+        };
+        message.fractal_data.params = Some(FractalParams::MengerSponge { fold_count: i32::MAX, hole_scale: 3.0 });
+        assert!(decode(&encode(&Frame::Message { id, message })).is_err(), "Read a fold count of i32::MAX");
+    }
+
+    // As is one whose palette has no stops to sample, or stops that aren't numbers
+    #[test]
+    fn palettes_past_the_bounds_are_refused() {
+        let Some(Frame::Message { id, message }) = sample_frames().into_iter()
+            .map(|(_, frame)| frame)
+            .find(|frame| matches!(frame, Frame::Message { .. })) else {
+            panic!("No message among the samples");
+        };
+        let stop = GradientStop { position: f32::NAN, color: [1.0, 0.0, 0.0] };
+        for stops in [Vec::new(), vec![stop.clone()], vec![GradientStop { position: 0.0, ..stop }; MAX_PALETTE_STOPS + 1]] {
+            let mut message = message.clone();
+            message.fractal_data.palette = Some(Palette { stops: stops.clone() });
+            assert!(decode(&encode(&Frame::Message { id, message })).is_err(), "Read a palette of {:?}", stops);
+        }
+    }
+
+    fn assert_decodes(name: &str, frame: &Frame, bytes: &[u8]) {
+        let decoded = decode(bytes).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), serde_json::to_string(frame).unwrap(), "{}", name);
+    }
+}
@@ -0,0 +1,85 @@
+use nalgebra::Vector3;
+use web_sys::{WebGlRenderingContext as GL, WebGlProgram};
+
+// Camera half-width in view units at zoom 1.0 (matches `uv * 2.5` in FRAGMENT_SHADER)
+const VIEW_SCALE: f64 = 2.5;
+
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 1.0e12;
+
+// Beyond this magnification f32 positions can no longer separate neighbouring pixels
+pub const DEEP_ZOOM_THRESHOLD: f64 = 1.0e4;
+
+// Zoom center tracked in f64 on the CPU and handed to the shader as hi/lo f32 pairs,
+// so the dual-float path can reconstruct it with ~48 bits of mantissa
+#[derive(Clone, Copy)]
+pub struct ZoomState {
+    pub center: Vector3<f64>,
+    pub zoom: f64,
+}
+
+impl Default for ZoomState {
+    fn default() -> Self {
+        ZoomState {
+            center: Vector3::zeros(),
+            zoom: 1.0,
+        }
+    }
+}
+
+impl ZoomState {
+    // Zoom by `factor` keeping the point under (uv_x, uv_y) fixed on screen.
+    // uv is the shader's centered coordinate: (pixel - 0.5 * resolution) / min(resolution)
+    pub fn zoom_at(&mut self, uv_x: f64, uv_y: f64, factor: f64) {
+        let anchor = self.center + self.view_offset(uv_x, uv_y);
+        self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+        self.center = anchor - self.view_offset(uv_x, uv_y);
+    }
+
+    pub fn pan(&mut self, uv_dx: f64, uv_dy: f64) {
+        self.center -= self.view_offset(uv_dx, uv_dy);
+    }
+
+    pub fn needs_dual_float(&self) -> bool {
+        self.zoom > DEEP_ZOOM_THRESHOLD
+    }
+
+    fn view_offset(&self, uv_x: f64, uv_y: f64) -> Vector3<f64> {
+        Vector3::new(uv_x * VIEW_SCALE / self.zoom, uv_y * VIEW_SCALE / self.zoom, 0.0)
+    }
+
+    pub fn apply_uniforms(&self, gl: &GL, program: &WebGlProgram, deep_zoom_allowed: bool) {
+        let (hi, lo) = split_vector(&self.center);
+
+        if let Some(loc) = gl.get_uniform_location(program, "u_zoom") {
+            gl.uniform1f(Some(&loc), self.zoom as f32);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_center_hi") {
+            gl.uniform3f(Some(&loc), hi[0], hi[1], hi[2]);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_center_lo") {
+            gl.uniform3f(Some(&loc), lo[0], lo[1], lo[2]);
+        }
+        if let Some(loc) = gl.get_uniform_location(program, "u_dual_float") {
+            gl.uniform1i(Some(&loc), (deep_zoom_allowed && self.needs_dual_float()) as i32);
+        }
+        // Opaque 1.0 that stops shader compilers from folding away the error terms
+        if let Some(loc) = gl.get_uniform_location(program, "u_df_one") {
+            gl.uniform1f(Some(&loc), 1.0);
+        }
+    }
+}
+
+// f64 -> (hi, lo) with hi + lo == value to roughly double f32 precision
+pub fn split_f64(value: f64) -> (f32, f32) {
+    let hi = value as f32;
+    let lo = (value - hi as f64) as f32;
+    (hi, lo)
+}
+
+fn split_vector(v: &Vector3<f64>) -> ([f32; 3], [f32; 3]) {
+    let (x_hi, x_lo) = split_f64(v.x);
+    let (y_hi, y_lo) = split_f64(v.y);
+    let (z_hi, z_lo) = split_f64(v.z);
+    ([x_hi, y_hi, z_hi], [x_lo, y_lo, z_lo])
+}